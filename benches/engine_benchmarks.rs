@@ -1,6 +1,8 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use tokio::runtime::Runtime;
+use transactions_engine::account::ClientId;
 use transactions_engine::engine::Engine;
+use transactions_engine::transaction::TxId;
 
 fn engine_benchmark(c: &mut Criterion) {
     let rt = Runtime::new().unwrap(); // single-threaded Tokio runtime
@@ -18,8 +20,8 @@ fn engine_benchmark(c: &mut Criterion) {
     group.bench_function("deposit_random", |b| {
         b.iter(|| {
             rt.block_on(async {
-                let acc = fastrand::u16(..);
-                let tx = fastrand::u32(..);
+                let acc = fastrand::u16(..) as ClientId;
+                let tx = fastrand::u32(..) as TxId;
                 let amount = fastrand::u32(1..10);
                 black_box(Engine::default().deposit(acc, tx, amount.into()).await)
             })
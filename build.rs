@@ -0,0 +1,24 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=build.rs");
+    // NOTE: the schema is compiled with protox, so the `grpc` feature does not need protoc installed
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto");
+        let file_descriptors = protox::compile(["proto/transactions_engine.proto"], ["proto"])?;
+        tonic_build::configure().compile_fds(file_descriptors)?;
+    }
+    // NOTE: the header is generated from the `ffi` module only, a copy is checked in to include/ for the C integrators
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let config = cbindgen::Config::from_file("cbindgen.toml")?;
+        let out_dir = std::env::var("OUT_DIR")?;
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/ffi.rs")
+            .generate()?
+            .write_to_file(format!("{}/transactions_engine.h", out_dir));
+    }
+    Ok(())
+}
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use transactions_engine::fuzzing::{run_scenario, Scenario};
+
+fuzz_target!(|scenario: Scenario| {
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    if let Err(violation) = rt.block_on(run_scenario(&scenario)) {
+        panic!("{}", violation);
+    }
+});
@@ -0,0 +1,115 @@
+#ifndef TRANSACTIONS_ENGINE_H
+#define TRANSACTIONS_ENGINE_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The operation succeeded. The other codes are the `EngineError` codes (positive) or the FFI errors (negative).
+ */
+#define TE_OK 0
+
+/**
+ * A null pointer, an id out of range of the build or an unknown operation type.
+ */
+#define TE_INVALID_ARGUMENT -1
+
+/**
+ * The engine panicked, the handle should not be used anymore.
+ */
+#define TE_PANIC -2
+
+#define TE_DEPOSIT 0
+
+#define TE_WITHDRAWAL 1
+
+#define TE_DISPUTE 2
+
+#define TE_RESOLVE 3
+
+#define TE_CHARGEBACK 4
+
+/**
+ * An engine with the runtime its calls are blocking on, opaque to C.
+ */
+typedef struct TeEngine TeEngine;
+
+/**
+ * The state of an account. The amounts are in minor units (1/10000), e.g. `1.5` is `15000`.
+ * The status is 0 (active), 1 (locked), 2 (frozen) or 3 (closed).
+ */
+typedef struct {
+  uint64_t client;
+  int64_t available;
+  int64_t held;
+  int64_t total;
+  bool locked;
+  uint8_t status;
+} TeAccount;
+
+/**
+ * Opens an engine over a storage: `memory`, `file:<path>` or `sqlite:<url>` (with the `sqlite` feature).
+ * Returns null on failure, see `te_last_error`. The engine is freed with `te_engine_free`.
+ *
+ * # Safety
+ *
+ * `storage` must be a valid NUL-terminated string.
+ */
+TeEngine *te_engine_new(const char *storage);
+
+/**
+ * Frees an engine of `te_engine_new` and shuts its runtime down. A null engine is ignored.
+ *
+ * # Safety
+ *
+ * `engine` must be null or an engine of `te_engine_new` that is not used afterwards.
+ */
+void te_engine_free(TeEngine *engine);
+
+/**
+ * Executes an operation (`TE_DEPOSIT`, ...), `amount` in minor units is ignored by the operations without an amount.
+ * Returns `TE_OK`, the code of the `EngineError` rejecting the operation or an FFI error.
+ * The engine can be called from several threads at once.
+ *
+ * # Safety
+ *
+ * `engine` must be an engine of `te_engine_new`.
+ */
+int32_t te_engine_execute(const TeEngine *engine,
+                          uint8_t op_type,
+                          uint64_t client,
+                          uint64_t tx,
+                          int64_t amount);
+
+/**
+ * Writes the state of an account to `account`. Returns `TE_OK`, 101 (`AccountNotFound`), 111 (`AmountOverflow`)
+ * when a balance doesn't fit into the minor units, the code of another `EngineError` or an FFI error.
+ *
+ * # Safety
+ *
+ * `engine` must be an engine of `te_engine_new` and `account` must point to a writable `TeAccount`.
+ */
+int32_t te_engine_get_account(const TeEngine *engine,
+                              uint64_t client,
+                              TeAccount *account);
+
+/**
+ * The message of the last error on the calling thread, or null when no call failed yet.
+ * The message is freed with `te_string_free`.
+ */
+char *te_last_error(void);
+
+/**
+ * Frees a string returned by the library. A null string is ignored.
+ *
+ * # Safety
+ *
+ * `string` must be null or a string of this library that is not used afterwards.
+ */
+void te_string_free(char *string);
+
+#endif  /* TRANSACTIONS_ENGINE_H */
@@ -1,29 +1,176 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::decimal::Decimal4;
+use crate::decimal::{AmountOverflowError, Decimal4};
+
+/// The id of a client account: `u16` by default, `u64` with the `wide-ids` feature.
+#[cfg(not(feature = "wide-ids"))]
+pub type ClientId = u16;
+/// The id of a client account: `u16` by default, `u64` with the `wide-ids` feature.
+#[cfg(feature = "wide-ids")]
+pub type ClientId = u64;
+
+/// What an account can still do:
+/// - `Active` accepts every operation,
+/// - `Locked` (by a chargeback or an admin) rejects the deposits and the withdrawals,
+/// - `Frozen` rejects the withdrawals only, so the funds can come in but not leave,
+/// - `Closed` rejects every operation and is final, only an account without funds can be closed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    #[default]
+    Active = 0,
+    Locked = 1,
+    Frozen = 2,
+    Closed = 3,
+}
+
+impl AccountStatus {
+    pub fn check_deposit(self) -> Result<(), AccountUpdateError> {
+        match self {
+            AccountStatus::Active | AccountStatus::Frozen => Ok(()),
+            AccountStatus::Locked => Err(AccountUpdateError::AccountLocked),
+            AccountStatus::Closed => Err(AccountUpdateError::AccountClosed),
+        }
+    }
+
+    pub fn check_withdrawal(self) -> Result<(), AccountUpdateError> {
+        match self {
+            AccountStatus::Active => Ok(()),
+            AccountStatus::Locked => Err(AccountUpdateError::AccountLocked),
+            AccountStatus::Frozen => Err(AccountUpdateError::AccountFrozen),
+            AccountStatus::Closed => Err(AccountUpdateError::AccountClosed),
+        }
+    }
+
+    /// Disputes, resolves and chargebacks are accepted unless the account is closed.
+    pub fn check_dispute(self) -> Result<(), AccountUpdateError> {
+        match self {
+            AccountStatus::Closed => Err(AccountUpdateError::AccountClosed),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The internal accounts of the engine, stored apart from the client accounts (see `Storage::get_system_account`),
+/// so every client id stays available to the clients. They keep the total money in the system balanced: the withdrawal
+/// fees are moved to `Fees`, and the negative balance left by a chargeback is written off to `ChargebackLosses`
+/// (see `EnginePolicy::write_off_chargeback_losses`), whose balance is then negative.
+///
+/// Their balances are kept in an [`Account`] whose id is not a client id and is left at 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemAccount {
+    Fees,
+    ChargebackLosses,
+}
+
+impl SystemAccount {
+    pub const ALL: [SystemAccount; 2] = [SystemAccount::Fees, SystemAccount::ChargebackLosses];
+
+    /// The name of the account, as in `GET /system-accounts/{name}` and in the storage keys.
+    pub fn name(self) -> &'static str {
+        match self {
+            SystemAccount::Fees => "fees",
+            SystemAccount::ChargebackLosses => "chargeback_losses",
+        }
+    }
+}
+
+/// A client account or a system account, for the outputs listing both: serialized as the client id or as the name
+/// of the system account, e.g. `1` or `"fees"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AccountRef {
+    Client(ClientId),
+    System(SystemAccount),
+}
+
+impl From<ClientId> for AccountRef {
+    fn from(value: ClientId) -> Self {
+        AccountRef::Client(value)
+    }
+}
+
+impl From<SystemAccount> for AccountRef {
+    fn from(value: SystemAccount) -> Self {
+        AccountRef::System(value)
+    }
+}
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Account {
-    id: u16,
+    id: ClientId,
     available: Decimal4,
     held: Decimal4,
-    locked: bool,
+    status: AccountStatus,
     version: u16, // concurrency token
+    #[serde(default)]
+    metadata: AccountMetadata,
+    #[serde(default)]
+    created_at: u64,
+    #[serde(default)]
+    updated_at: u64,
+    /// The funds parked in named escrow buckets, see [`Account::escrow`]. Empty buckets are removed.
+    #[serde(default)]
+    escrow: BTreeMap<String, Decimal4>,
+    /// The number of operations applied to the account, see [`Account::seq`].
+    #[serde(default)]
+    seq: u64,
+}
+
+/// Descriptive fields of an account, they never affect the balances.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountMetadata {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The id of the account in an external system, e.g. a CRM.
+    #[serde(default)]
+    pub external_ref: Option<String>,
 }
 
 impl Account {
-    pub fn new(id: u16) -> Self {
+    pub fn new(id: ClientId) -> Self {
         Self {
             id,
             available: Decimal4::zero(),
             held: Decimal4::zero(),
-            locked: false,
+            status: AccountStatus::Active,
             version: 0,
+            metadata: AccountMetadata::default(),
+            created_at: 0,
+            updated_at: 0,
+            escrow: BTreeMap::new(),
+            seq: 0,
         }
     }
 
-    pub fn id(&self) -> u16 {
+    /// Restores an account previously persisted by a storage backend.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn from_parts(id: ClientId, available: Decimal4, held: Decimal4, status: AccountStatus, version: u16) -> Self {
+        Self { id, available, held, status, version, ..Self::new(id) }
+    }
+
+    /// Restores the fields of an account that are not balances, see [`Account::from_parts`].
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn with_details(mut self, metadata: AccountMetadata, created_at: u64, updated_at: u64, seq: u64) -> Self {
+        self.metadata = metadata;
+        self.created_at = created_at;
+        self.updated_at = updated_at;
+        self.seq = seq;
+        self
+    }
+
+    /// Restores the escrow buckets, see [`Account::from_parts`].
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn with_escrow(mut self, escrow: BTreeMap<String, Decimal4>) -> Self {
+        self.escrow = escrow;
+        self
+    }
+
+    pub fn id(&self) -> ClientId {
         self.id
     }
 
@@ -35,26 +182,124 @@ impl Account {
         self.held
     }
 
-    pub fn total(&self) -> Decimal4 {
-        self.available + self.held
+    /// The sum of the escrow buckets, or an error when it does not fit into a `Decimal4`.
+    pub fn escrowed(&self) -> Result<Decimal4, AmountOverflowError> {
+        self.escrow.values().try_fold(Decimal4::zero(), |total, x| total.checked_add(*x))
+    }
+
+    /// The funds in the escrow `bucket`, zero for an unknown bucket.
+    pub fn escrowed_in(&self, bucket: &str) -> Decimal4 {
+        self.escrow.get(bucket).copied().unwrap_or_default()
+    }
+
+    pub fn escrow_buckets(&self) -> &BTreeMap<String, Decimal4> {
+        &self.escrow
+    }
+
+    /// The available, held and escrowed funds, or an error when they do not fit into a `Decimal4`. The deposits keep it
+    /// representable, see [`Account::deposit`].
+    pub fn total(&self) -> Result<Decimal4, AmountOverflowError> {
+        self.available.checked_add(self.held)?.checked_add(self.escrowed()?)
+    }
+
+    pub fn status(&self) -> AccountStatus {
+        self.status
     }
 
+    /// Whether the status is `Locked`, the `locked` column of the account summary.
     pub fn locked(&self) -> bool {
-        self.locked
+        self.status == AccountStatus::Locked
     }
 
     pub fn version(&self) -> u16 {
         self.version
     }
 
+    pub fn metadata(&self) -> &AccountMetadata {
+        &self.metadata
+    }
+
+    /// Milliseconds since the epoch of the first change of the account, 0 if unknown.
+    pub fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    /// Milliseconds since the epoch of the last change of the account (balances, status or metadata), 0 if unknown.
+    pub fn updated_at(&self) -> u64 {
+        self.updated_at
+    }
+
+    /// The sequence number of the last operation applied to the account: 1 for the first one, then increasing by one with
+    /// every applied operation, so a consumer of the outcomes can detect a missing or reordered one (see [`AccountSeqVerifier`]).
+    /// 0 for an account without operations, or whose operations were all applied before the sequence numbers were recorded.
+    /// Only the journaled operations count: the status and metadata changes and the postings to a [`SystemAccount`] don't.
+    ///
+    /// [`AccountSeqVerifier`]: crate::journal::AccountSeqVerifier
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Counts an applied operation, see [`Account::seq`].
+    pub fn advance_seq(&mut self) {
+        self.seq += 1;
+    }
+
+    /// Records a change made at `timestamp`, the first one is also the creation time.
+    pub fn touch(&mut self, timestamp: u64) {
+        if self.created_at == 0 {
+            self.created_at = timestamp;
+        }
+        self.updated_at = timestamp;
+    }
+
+    pub fn set_metadata(&mut self, metadata: AccountMetadata, timestamp: u64) {
+        self.metadata = metadata;
+        self.touch(timestamp);
+        self.version += 1;
+    }
+
+    /// Moves the account to `status`: a closed account stays closed, and only an account without funds can be closed.
+    pub fn set_status(&mut self, status: AccountStatus, timestamp: u64) -> Result<(), AccountUpdateError> {
+        match (self.status, status) {
+            (from, to) if from == to || from == AccountStatus::Closed => return Err(AccountUpdateError::ForbiddenStatusTransition { from, to }),
+            (_, AccountStatus::Closed) if !self.available.is_zero() || !self.held.is_zero() || !self.escrow.is_empty() => return Err(AccountUpdateError::AccountNotEmpty),
+            _ => {}
+        }
+        self.status = status;
+        self.touch(timestamp);
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Whether the balances (including the escrow buckets) are the same, ignoring the status (which the admins can change), the metadata,
+    /// the timestamps and the version.
+    pub fn same_balances(&self, other: &Account) -> bool {
+        self.id == other.id && self.available == other.available && self.held == other.held && self.escrow == other.escrow
+    }
+
+    /// Takes the status of `other` as it was when the operation was applied, see [`Account::copy_details_from`].
+    pub(crate) fn copy_status_from(&mut self, other: &Account) {
+        self.status = other.status;
+    }
+
+    /// Takes the metadata, the timestamps, the version and the sequence number of `other`, which are not derived from the operations
+    /// (the sequence numbers are checked on their own, see [`AccountSeqVerifier`](crate::journal::AccountSeqVerifier)).
+    pub(crate) fn copy_details_from(&mut self, other: &Account) {
+        self.metadata = other.metadata.clone();
+        self.created_at = other.created_at;
+        self.updated_at = other.updated_at;
+        self.version = other.version;
+        self.seq = other.seq;
+    }
+
     pub fn deposit(&mut self, amount: Decimal4) -> Result<(), AccountUpdateError> {
         if !amount.is_positive() {
             return Err(AccountUpdateError::AmountIsNotPositive);
         }
-        if self.locked {
-            return Err(AccountUpdateError::AccountLocked);
-        }
-        self.available += amount;
+        self.status.check_deposit()?;
+        let available = self.available.checked_add(amount)?;
+        available.checked_add(self.held)?.checked_add(self.escrowed()?)?; // NOTE: keeps the total representable
+        self.available = available;
         self.version += 1;
         Ok(())
     }
@@ -63,44 +308,193 @@ impl Account {
         if !amount.is_positive() {
             return Err(AccountUpdateError::AmountIsNotPositive);
         }
-        if self.locked {
-            return Err(AccountUpdateError::AccountLocked);
-        }
+        self.status.check_withdrawal()?;
         if amount > self.available {
             return Err(AccountUpdateError::InsufficientFunds);
         }
-        self.available -= amount;
+        self.available = self.available.checked_sub(amount)?;
         self.version += 1;
         Ok(())
     }
 
     pub fn dispute(&mut self, amount: Decimal4) -> Result<(), AccountUpdateError> {
+        self.dispute_with_shortfall(amount, Decimal4::zero())
+    }
+
+    /// The part of a disputed `amount` that is no longer in the available funds (the client already spent it),
+    /// zero if the whole amount can be held. See `NegativeAvailablePolicy::Clamp`.
+    pub fn dispute_shortfall(&self, amount: Decimal4) -> Decimal4 {
+        let holdable = amount.min(self.available.max(Decimal4::zero()));
+        amount - holdable
+    }
+
+    /// Holds the disputed `amount` except its `shortfall`, which stays out of the available funds so they don't go negative.
+    /// The resolve and the chargeback of the dispute must pass the same shortfall.
+    pub fn dispute_with_shortfall(&mut self, amount: Decimal4, shortfall: Decimal4) -> Result<(), AccountUpdateError> {
         if !amount.is_positive() {
             return Err(AccountUpdateError::AmountIsNotPositive);
         }
-        self.available -= amount;
-        self.held += amount;
+        self.status.check_dispute()?;
+        let held_amount = amount.checked_sub(shortfall)?;
+        let available = self.available.checked_sub(held_amount)?;
+        let held = self.held.checked_add(held_amount)?;
+        self.available = available;
+        self.held = held;
         self.version += 1;
         Ok(())
     }
 
     pub fn resolve(&mut self, amount: Decimal4) -> Result<(), AccountUpdateError> {
+        self.resolve_with_shortfall(amount, Decimal4::zero())
+    }
+
+    /// Releases the held part of a dispute with a shortfall, see [`Account::dispute_with_shortfall`].
+    pub fn resolve_with_shortfall(&mut self, amount: Decimal4, shortfall: Decimal4) -> Result<(), AccountUpdateError> {
+        if !amount.is_positive() {
+            return Err(AccountUpdateError::AmountIsNotPositive);
+        }
+        self.status.check_dispute()?;
+        let held_amount = amount.checked_sub(shortfall)?;
+        if held_amount > self.held {
+            return Err(AccountUpdateError::HeldUnderflow);
+        }
+        let held = self.held.checked_sub(held_amount)?;
+        let available = self.available.checked_add(held_amount)?;
+        self.held = held;
+        self.available = available;
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Moves available funds into the escrow `bucket`, where they can't be withdrawn until released.
+    /// The funds are on their way out of the account, so it is checked like a withdrawal.
+    pub fn escrow(&mut self, bucket: &str, amount: Decimal4) -> Result<(), AccountUpdateError> {
         if !amount.is_positive() {
             return Err(AccountUpdateError::AmountIsNotPositive);
         }
-        self.held -= amount;
-        self.available += amount;
+        self.status.check_withdrawal()?;
+        if amount > self.available {
+            return Err(AccountUpdateError::InsufficientFunds);
+        }
+        let escrowed = self.escrowed_in(bucket).checked_add(amount)?;
+        self.available = self.available.checked_sub(amount)?;
+        self.escrow.insert(bucket.to_string(), escrowed);
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Moves funds of the escrow `bucket` back to the available funds, checked like a deposit.
+    pub fn release_escrow(&mut self, bucket: &str, amount: Decimal4) -> Result<(), AccountUpdateError> {
+        if !amount.is_positive() {
+            return Err(AccountUpdateError::AmountIsNotPositive);
+        }
+        self.status.check_deposit()?;
+        let escrowed = self.escrowed_in(bucket);
+        if amount > escrowed {
+            return Err(AccountUpdateError::InsufficientEscrow);
+        }
+        self.available = self.available.checked_add(amount)?;
+        match escrowed - amount {
+            x if x.is_zero() => self.escrow.remove(bucket),
+            x => self.escrow.insert(bucket.to_string(), x),
+        };
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Holds available funds for an authorization until it is captured or expires. The funds are on their way out of
+    /// the account, so it is checked like a withdrawal.
+    pub fn authorize(&mut self, amount: Decimal4) -> Result<(), AccountUpdateError> {
+        if !amount.is_positive() {
+            return Err(AccountUpdateError::AmountIsNotPositive);
+        }
+        self.status.check_withdrawal()?;
+        if amount > self.available {
+            return Err(AccountUpdateError::InsufficientFunds);
+        }
+        self.available = self.available.checked_sub(amount)?;
+        self.held = self.held.checked_add(amount)?;
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Takes the held funds of a captured authorization from the account. The funds were checked when they were held,
+    /// so only a closed account rejects it, like a chargeback.
+    pub fn capture(&mut self, amount: Decimal4) -> Result<(), AccountUpdateError> {
+        if !amount.is_positive() {
+            return Err(AccountUpdateError::AmountIsNotPositive);
+        }
+        self.status.check_dispute()?;
+        if amount > self.held {
+            return Err(AccountUpdateError::HeldUnderflow);
+        }
+        self.held = self.held.checked_sub(amount)?;
+        self.version += 1;
+        Ok(())
+    }
+
+    /// Checks what must hold for every stored account whatever the operation: the held funds are never negative.
+    pub fn check_invariants(&self) -> Result<(), AccountUpdateError> {
+        if self.held.is_negative() {
+            return Err(AccountUpdateError::HeldUnderflow);
+        }
+        Ok(())
+    }
+
+    /// Fails when the total balance is over `max_balance`, see `EnginePolicy::max_balance`.
+    pub fn check_balance_limit(&self, max_balance: Option<Decimal4>) -> Result<(), AccountUpdateError> {
+        match max_balance {
+            Some(max_balance) if self.total()? > max_balance => Err(AccountUpdateError::BalanceLimitExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    /// Moves the negative available funds left by a chargeback off the account and returns the written-off amount,
+    /// zero if the account is not in debt.
+    pub fn write_off(&mut self) -> Decimal4 {
+        if !self.available.is_negative() {
+            return Decimal4::zero();
+        }
+        let written_off = Decimal4::zero() - self.available;
+        self.available = Decimal4::zero();
+        self.version += 1;
+        written_off
+    }
+
+    /// Adds a (possibly negative) amount to the available funds of a [`SystemAccount`], whatever its status.
+    pub fn post(&mut self, amount: Decimal4) -> Result<(), AccountUpdateError> {
+        self.available = self.available.checked_add(amount)?;
         self.version += 1;
         Ok(())
     }
 
     pub fn chargeback(&mut self, amount: Decimal4) -> Result<(), AccountUpdateError> {
+        self.chargeback_with_shortfall(amount, Decimal4::zero(), true)
+    }
+
+    /// Removes the held funds of a chargeback but leaves the account open, see `EnginePolicy::lock_on_chargeback`.
+    pub fn chargeback_without_lock(&mut self, amount: Decimal4) -> Result<(), AccountUpdateError> {
+        self.chargeback_with_shortfall(amount, Decimal4::zero(), false)
+    }
+
+    /// Charges back a dispute with a shortfall (see [`Account::dispute_with_shortfall`]): the held part is removed and the
+    /// shortfall is taken from the available funds, so the account loses the whole amount like after any other dispute.
+    pub fn chargeback_with_shortfall(&mut self, amount: Decimal4, shortfall: Decimal4, lock: bool) -> Result<(), AccountUpdateError> {
         if !amount.is_positive() {
             return Err(AccountUpdateError::AmountIsNotPositive);
         }
-        self.held -= amount;
-        self.locked = true;
+        self.status.check_dispute()?;
+        let held_amount = amount.checked_sub(shortfall)?;
+        if held_amount > self.held {
+            return Err(AccountUpdateError::HeldUnderflow);
+        }
+        let available = self.available.checked_sub(shortfall)?;
+        self.held = self.held.checked_sub(held_amount)?;
+        self.available = available;
         self.version += 1;
+        if lock {
+            self.status = AccountStatus::Locked;
+        }
         Ok(())
     }
 }
@@ -110,11 +504,41 @@ pub enum AccountUpdateError {
     #[error("account locked")]
     AccountLocked,
 
+    #[error("account frozen")]
+    AccountFrozen,
+
+    #[error("account closed")]
+    AccountClosed,
+
+    #[error("account still has funds")]
+    AccountNotEmpty,
+
+    #[error("forbidden status transition: {from:?} -> {to:?}")]
+    ForbiddenStatusTransition { from: AccountStatus, to: AccountStatus },
+
     #[error("insufficient funds")]
     InsufficientFunds,
 
     #[error("amount is not positive")]
     AmountIsNotPositive,
+
+    #[error("amount overflow")]
+    AmountOverflow,
+
+    #[error("balance limit exceeded")]
+    BalanceLimitExceeded,
+
+    #[error("held funds would become negative")]
+    HeldUnderflow,
+
+    #[error("insufficient funds in escrow")]
+    InsufficientEscrow,
+}
+
+impl From<AmountOverflowError> for AccountUpdateError {
+    fn from(_: AmountOverflowError) -> Self {
+        AccountUpdateError::AmountOverflow
+    }
 }
 
 #[cfg(test)]
@@ -139,6 +563,23 @@ mod account_tests {
         assert_eq!(acc.withdraw(1.into()), Err(AccountUpdateError::AccountLocked));
     }
 
+    #[test]
+    fn account_status_rules() {
+        let mut acc = Account::new(1);
+        acc.deposit(4.into()).unwrap();
+        assert_eq!(acc.set_status(AccountStatus::Frozen, 10), Ok(()));
+        assert_eq!(acc.withdraw(1.into()), Err(AccountUpdateError::AccountFrozen));
+        assert_eq!(acc.deposit(1.into()), Ok(()));
+        assert_eq!(acc.set_status(AccountStatus::Frozen, 20), Err(AccountUpdateError::ForbiddenStatusTransition { from: AccountStatus::Frozen, to: AccountStatus::Frozen }));
+        assert_eq!(acc.set_status(AccountStatus::Closed, 20), Err(AccountUpdateError::AccountNotEmpty));
+        assert_eq!(acc.set_status(AccountStatus::Active, 30), Ok(()));
+        acc.withdraw(5.into()).unwrap();
+        assert_eq!(acc.set_status(AccountStatus::Closed, 40), Ok(()));
+        assert_eq!(acc.deposit(1.into()), Err(AccountUpdateError::AccountClosed));
+        assert_eq!(acc.set_status(AccountStatus::Active, 50), Err(AccountUpdateError::ForbiddenStatusTransition { from: AccountStatus::Closed, to: AccountStatus::Active }));
+        assert_eq!((acc.status(), acc.updated_at()), (AccountStatus::Closed, 40));
+    }
+
     #[test]
     fn account_withdraw_on_insufficient_funds_err() {
         let mut acc = Account::new(1);
@@ -188,7 +629,7 @@ mod account_tests {
         acc.chargeback(2.into()).unwrap();
         assert_eq!(acc.available(), 3.into());
         assert_eq!(acc.held(), 0.into());
-        assert_eq!(acc.locked(), true);
+        assert!(acc.locked());
     }
 
     #[test]
@@ -256,6 +697,107 @@ mod account_tests {
         assert_eq!(acc.resolve(Decimal4::zero()), Err(AccountUpdateError::AmountIsNotPositive));
     }
 
+    #[test]
+    fn account_deposit_overflow_err() {
+        let mut acc = Account::new(1);
+        let max = Decimal4::from(rust_decimal::Decimal::MAX);
+        acc.deposit(max).unwrap();
+        assert_eq!(acc.deposit(1.into()), Err(AccountUpdateError::AmountOverflow));
+        assert_eq!(acc.available(), max);
+        assert_eq!(acc.version(), 1);
+    }
+
+    #[test]
+    fn account_deposit_overflow_with_escrow_err() {
+        let mut acc = Account::new(1);
+        let max = Decimal4::from(rust_decimal::Decimal::MAX);
+        acc.deposit(max).unwrap();
+        acc.escrow("orders", max).unwrap();
+        assert_eq!(acc.deposit(1.into()), Err(AccountUpdateError::AmountOverflow));
+        assert_eq!((acc.available(), acc.total()), (Decimal4::zero(), Ok(max)));
+    }
+
+    #[test]
+    fn account_balance_limit() {
+        let mut acc = Account::new(1);
+        acc.deposit(5.into()).unwrap();
+        acc.dispute(5.into()).unwrap();
+        assert_eq!(acc.check_balance_limit(Some(5.into())), Ok(()));
+        assert_eq!(acc.check_balance_limit(Some(4.into())), Err(AccountUpdateError::BalanceLimitExceeded));
+        assert_eq!(acc.check_balance_limit(None), Ok(()));
+    }
+
+    #[test]
+    fn account_held_underflow_err() {
+        let mut acc = Account::new(1);
+        acc.deposit(5.into()).unwrap();
+        acc.dispute(2.into()).unwrap();
+        assert_eq!(acc.resolve(3.into()), Err(AccountUpdateError::HeldUnderflow));
+        assert_eq!(acc.chargeback(3.into()), Err(AccountUpdateError::HeldUnderflow));
+        assert_eq!((acc.held(), acc.locked(), acc.version()), (2.into(), false, 2));
+        assert_eq!(acc.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn account_metadata_and_timestamps() {
+        let mut acc = Account::new(1);
+        acc.deposit(5.into()).unwrap();
+        acc.touch(100);
+        let before = acc.clone();
+        let metadata = AccountMetadata { name: Some("Alice".to_string()), external_ref: None };
+        acc.set_metadata(metadata.clone(), 200);
+        assert_eq!((acc.metadata(), acc.created_at(), acc.updated_at(), acc.version()), (&metadata, 100, 200, 2));
+        assert!(acc.same_balances(&before));
+        assert_ne!(acc, before);
+    }
+
+    #[test]
+    fn account_escrow_and_release() {
+        let mut acc = Account::new(1);
+        acc.deposit(10.into()).unwrap();
+        acc.escrow("orders", 4.into()).unwrap();
+        acc.escrow("orders", 2.into()).unwrap();
+        assert_eq!(acc.escrow("orders", 5.into()), Err(AccountUpdateError::InsufficientFunds));
+        assert_eq!((acc.available(), acc.escrowed_in("orders"), acc.total().unwrap()), (4.into(), 6.into(), 10.into()));
+        assert_eq!(acc.withdraw(5.into()), Err(AccountUpdateError::InsufficientFunds));
+        assert_eq!(acc.release_escrow("orders", 7.into()), Err(AccountUpdateError::InsufficientEscrow));
+        assert_eq!(acc.release_escrow("returns", 1.into()), Err(AccountUpdateError::InsufficientEscrow));
+        acc.release_escrow("orders", 6.into()).unwrap();
+        assert_eq!((acc.available(), acc.escrowed().unwrap(), acc.version()), (10.into(), Decimal4::zero(), 4));
+        assert!(acc.escrow_buckets().is_empty());
+    }
+
+    #[test]
+    fn account_dispute_with_shortfall() {
+        let mut acc = Account::new(1);
+        acc.deposit(100.into()).unwrap();
+        acc.withdraw(70.into()).unwrap();
+        assert_eq!(acc.dispute_shortfall(20.into()), Decimal4::zero());
+        let shortfall = acc.dispute_shortfall(100.into());
+        assert_eq!(shortfall, 70.into());
+        acc.dispute_with_shortfall(100.into(), shortfall).unwrap();
+        assert_eq!((acc.available(), acc.held(), acc.total().unwrap()), (Decimal4::zero(), 30.into(), 30.into()));
+        assert_eq!(acc.dispute_shortfall(5.into()), 5.into());
+
+        let mut resolved = acc.clone();
+        resolved.resolve_with_shortfall(100.into(), shortfall).unwrap();
+        assert_eq!((resolved.available(), resolved.held()), (30.into(), Decimal4::zero()));
+        acc.chargeback_with_shortfall(100.into(), shortfall, true).unwrap();
+        assert_eq!((acc.available(), acc.held(), acc.locked()), (Decimal4::from(-70), Decimal4::zero(), true));
+    }
+
+    #[test]
+    fn account_authorize_and_capture() {
+        let mut acc = Account::new(1);
+        acc.deposit(10.into()).unwrap();
+        assert_eq!(acc.authorize(11.into()), Err(AccountUpdateError::InsufficientFunds));
+        acc.authorize(6.into()).unwrap();
+        assert_eq!((acc.available(), acc.held(), acc.total().unwrap()), (4.into(), 6.into(), 10.into()));
+        assert_eq!(acc.capture(7.into()), Err(AccountUpdateError::HeldUnderflow));
+        acc.capture(6.into()).unwrap();
+        assert_eq!((acc.available(), acc.held(), acc.total().unwrap(), acc.version()), (4.into(), Decimal4::zero(), 4.into(), 3));
+    }
+
     #[test]
     fn account_chargeback_amount_not_positive_err() {
         let mut acc = Account::new(1);
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{SinkExt, Stream, StreamExt};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::account::ClientId;
+use crate::engine::{Engine, EngineError, Operation};
+use crate::journal::Journal;
+use crate::storage::Storage;
+
+/// Operations buffered in the mailbox of an account, the senders wait when it is full.
+const DEFAULT_MAILBOX_SIZE: usize = 64;
+
+/// An actor with an empty mailbox stops after this long, the next operation of its account starts a new one.
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An operation in the mailbox of an account, with the channel of its outcome.
+type Mail = (Operation, oneshot::Sender<Result<(), EngineError>>);
+
+type Mailboxes = Arc<Mutex<HashMap<ClientId, mpsc::Sender<Mail>>>>;
+
+/// An alternative to calling the [`Engine`] directly: every active account gets a dedicated task (the actor of the account)
+/// consuming a mailbox of its operations one at a time.
+///
+/// The operations of an account are applied in the order they were submitted and never run concurrently, so they don't
+/// fail with `EngineError::ConcurrentOperationDetected` and need no retries, while the operations of different accounts
+/// run in parallel. Two accounts can still conflict on the same transaction id (e.g. a deposit id reused by another client),
+/// which is reported like by the engine.
+///
+/// An actor is spawned on the first operation of its account and stops after an idle timeout, so only the active accounts
+/// hold a task. The clones share the actors. The actors run on the Tokio runtime of the caller.
+pub struct ActorEngine<TStorage: Storage> {
+    engine: Engine<TStorage>,
+    mailbox_size: usize,
+    idle_timeout: Duration,
+    mailboxes: Mailboxes,
+}
+
+impl<TStorage: Storage> Clone for ActorEngine<TStorage> {
+    fn clone(&self) -> Self {
+        Self {
+            engine: self.engine.clone(),
+            mailbox_size: self.mailbox_size,
+            idle_timeout: self.idle_timeout,
+            mailboxes: self.mailboxes.clone(),
+        }
+    }
+}
+
+impl<TStorage> ActorEngine<TStorage>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    pub fn new(engine: Engine<TStorage>) -> Self {
+        Self {
+            engine,
+            mailbox_size: DEFAULT_MAILBOX_SIZE,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            mailboxes: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Sets the number of operations buffered per account (at least 1), 64 by default.
+    pub fn with_mailbox_size(mut self, mailbox_size: usize) -> Self {
+        self.mailbox_size = mailbox_size.max(1);
+        self
+    }
+
+    /// Sets how long an actor waits for the next operation of its account before stopping, 30 seconds by default.
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// The wrapped engine, e.g. for the queries.
+    pub fn engine(&self) -> &Engine<TStorage> {
+        &self.engine
+    }
+
+    /// The number of running actors, i.e. of the recently active accounts.
+    pub fn active_actors(&self) -> usize {
+        self.mailboxes.lock().unwrap().len()
+    }
+
+    /// Executes the operation on the actor of its account and waits for the outcome.
+    pub async fn execute_operation(&self, operation: Operation) -> Result<(), EngineError> {
+        self.submit(operation).await.await.unwrap_or(Err(EngineError::ActorStopped))
+    }
+
+    /// Submits the operations of the input stream one by one and yields the outcome of each operation in the input order.
+    /// The operations of different accounts are executed in parallel, at most `buffer_size` outcomes are awaited at once:
+    /// when the output stream is not polled, the input stream is not polled either (backpressure).
+    pub fn process_stream<S>(&self, operations: S, buffer_size: usize) -> impl Stream<Item = (Operation, Result<(), EngineError>)>
+        where S: Stream<Item = Operation> + Send + 'static
+    {
+        let (mut sender, receiver) = futures::channel::mpsc::channel(buffer_size);
+        let engine = self.clone();
+        tokio::spawn(async move {
+            let mut operations = std::pin::pin!(operations);
+            while let Some(operation) = operations.next().await {
+                let outcome = engine.submit(operation.clone()).await;
+                if sender.send((operation, outcome)).await.is_err() {
+                    break; // the output stream was dropped, the submitted operations are still executed
+                }
+            }
+        });
+        receiver.then(|(operation, outcome)| async move { (operation, outcome.await.unwrap_or(Err(EngineError::ActorStopped))) })
+    }
+
+    /// Puts the operation into the mailbox of its account, waiting while the mailbox is full, and returns the receiver of the outcome.
+    async fn submit(&self, operation: Operation) -> oneshot::Receiver<Result<(), EngineError>> {
+        let acc_id = operation.acc_id();
+        let (reply, outcome) = oneshot::channel();
+        let mut mail = (operation, reply);
+        loop {
+            let mailbox = self.mailbox(acc_id);
+            match mailbox.send(mail).await {
+                Ok(()) => return outcome,
+                Err(mpsc::error::SendError(returned)) => {
+                    // NOTE: the actor panicked, the next attempt starts a new one
+                    mail = returned;
+                    let mut mailboxes = self.mailboxes.lock().unwrap();
+                    if mailboxes.get(&acc_id).is_some_and(|x| x.same_channel(&mailbox)) {
+                        mailboxes.remove(&acc_id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the mailbox of the account, spawning its actor if it is not running.
+    fn mailbox(&self, acc_id: ClientId) -> mpsc::Sender<Mail> {
+        let mut mailboxes = self.mailboxes.lock().unwrap();
+        if let Some(mailbox) = mailboxes.get(&acc_id) {
+            return mailbox.clone();
+        }
+        let (mailbox, receiver) = mpsc::channel(self.mailbox_size);
+        tokio::spawn(run_actor(self.engine.clone(), acc_id, receiver, self.mailboxes.clone(), self.idle_timeout));
+        mailboxes.insert(acc_id, mailbox.clone());
+        mailbox
+    }
+}
+
+/// Executes the operations of the account one by one until the mailbox stays empty for `idle_timeout`.
+async fn run_actor<TStorage>(engine: Engine<TStorage>, acc_id: ClientId, mut receiver: mpsc::Receiver<Mail>, mailboxes: Mailboxes, idle_timeout: Duration)
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    loop {
+        match tokio::time::timeout(idle_timeout, receiver.recv()).await {
+            Ok(Some((operation, reply))) => {
+                let _ = reply.send(engine.execute_operation(operation).await); // NOTE: the caller may be gone
+            }
+            Ok(None) => return,
+            Err(_) => {
+                // NOTE: the senders are cloned under the lock, so when the registered one is the only one left and the mailbox
+                // is empty, no operation is on its way and the account can not get a new one before the actor is unregistered
+                let mut mailboxes = mailboxes.lock().unwrap();
+                if receiver.is_empty() && mailboxes.get(&acc_id).is_some_and(|x| x.strong_count() == 1) {
+                    mailboxes.remove(&acc_id);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod actor_tests {
+    use rust_decimal::Decimal;
+
+    use crate::decimal::Decimal4;
+    use crate::storage::EchoDbStorage;
+    use crate::transaction::TxId;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn serializes_operations_per_account() {
+        let engine = ActorEngine::new(Engine::new(EchoDbStorage::new()));
+        let tasks: Vec<_> = (1..=200 as TxId)
+            .map(|tx_id| {
+                let engine = engine.clone();
+                let acc_id = (tx_id % 4 + 1) as ClientId;
+                tokio::spawn(async move { engine.execute_operation(Operation::Deposit { acc_id, tx_id, amount: Decimal::from(1) }).await })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), Ok(()));
+        }
+        for acc_id in 1..=4 {
+            assert_eq!(engine.engine().get_account(acc_id).await.unwrap().unwrap().available(), Decimal4::from(50));
+        }
+        assert_eq!(engine.active_actors(), 4);
+        assert_eq!(engine.engine().verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn idle_actors_stop() {
+        let engine = ActorEngine::new(Engine::new(EchoDbStorage::new())).with_idle_timeout(Duration::from_millis(10));
+        assert_eq!(engine.execute_operation(Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) }).await, Ok(()));
+        assert_eq!(engine.execute_operation(Operation::Withdraw { acc_id: 2, tx_id: 2, amount: Decimal::from(10) }).await, Err(EngineError::AccountNotFound));
+        assert_eq!(engine.active_actors(), 2);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(engine.active_actors(), 0);
+        assert_eq!(engine.execute_operation(Operation::Dispute { acc_id: 1, tx_id: 1 }).await, Ok(()));
+        assert_eq!(engine.active_actors(), 1);
+    }
+
+    #[tokio::test]
+    async fn stream_outcomes_in_input_order() {
+        let engine = ActorEngine::new(Engine::new(EchoDbStorage::new())).with_mailbox_size(1);
+        let operations = vec![
+            Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) },
+            Operation::Deposit { acc_id: 2, tx_id: 2, amount: Decimal::from(5) },
+            Operation::Withdraw { acc_id: 1, tx_id: 3, amount: Decimal::from(20) },
+            Operation::Dispute { acc_id: 1, tx_id: 1 },
+            Operation::Withdraw { acc_id: 2, tx_id: 4, amount: Decimal::from(5) },
+        ];
+        let outcomes: Vec<_> = engine.process_stream(futures::stream::iter(operations.clone()), 2).collect().await;
+        assert_eq!(outcomes.iter().map(|x| x.0.clone()).collect::<Vec<_>>(), operations);
+        assert_eq!(outcomes.into_iter().map(|x| x.1).collect::<Vec<_>>(), vec![Ok(()), Ok(()), Err(EngineError::InsufficientFunds), Ok(()), Ok(())]);
+    }
+}
@@ -0,0 +1,220 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use futures::StreamExt;
+use lapin::options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions};
+use lapin::types::FieldTable;
+use lapin::message::Delivery;
+use lapin::{Connection, ConnectionProperties};
+
+use crate::dead_letter::{DeadLetter, DeadLetterStore};
+use crate::engine::Engine;
+use crate::journal::{Journal, OperationSource, Provenance};
+use crate::queue::{execute_message, MessageOutcome, RetryPolicy, RetryQueue};
+use crate::runtime;
+use crate::storage::Storage;
+
+/// What is done with a delivery once the engine is done with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Acknowledgement {
+    Ack,
+    /// `basic.nack` with requeue, the delivery goes back to the queue.
+    Requeue,
+    /// `basic.nack` without requeue, the broker routes the delivery to the dead-letter exchange of the queue (or drops it without one).
+    DeadLetter,
+}
+
+impl FromStr for Acknowledgement {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ack" => Ok(Acknowledgement::Ack),
+            "requeue" => Ok(Acknowledgement::Requeue),
+            "dead-letter" => Ok(Acknowledgement::DeadLetter),
+            _ => bail!("unknown acknowledgement: {} (expected ack, requeue or dead-letter)", s),
+        }
+    }
+}
+
+/// The acknowledgement of the deliveries that are not applied, by the class of the failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailurePolicy {
+    /// Payloads that are not an operation.
+    pub invalid: Acknowledgement,
+    /// Operations the engine rejects (insufficient funds, locked account, etc.), redelivering them gives the same error.
+    pub rejected: Acknowledgement,
+    /// Concurrent operations and storage failures, after the retries in place and in the retry queue.
+    pub transient: Acknowledgement,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        Self { invalid: Acknowledgement::DeadLetter, rejected: Acknowledgement::DeadLetter, transient: Acknowledgement::Requeue }
+    }
+}
+
+impl FailurePolicy {
+    pub fn acknowledgement(&self, outcome: &MessageOutcome) -> Acknowledgement {
+        match outcome {
+            MessageOutcome::Applied(_) => Acknowledgement::Ack,
+            MessageOutcome::Invalid(_) => self.invalid,
+            MessageOutcome::Rejected(..) => self.rejected,
+            MessageOutcome::Transient(..) => self.transient,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AmqpConfig {
+    pub url: String,
+    pub queue: String,
+    pub consumer_tag: String,
+    /// Deliveries sent by the broker ahead of the acknowledgements (`basic.qos`).
+    pub prefetch: u16,
+    pub retry: RetryPolicy,
+    /// The retries of the transient failures left after the retries in place, see [`RetryQueue`].
+    pub retry_queue: RetryPolicy,
+    pub failure_policy: FailurePolicy,
+}
+
+impl AmqpConfig {
+    pub fn new(url: impl Into<String>, queue: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            queue: queue.into(),
+            consumer_tag: "transactions-engine".to_string(),
+            prefetch: 100,
+            retry: RetryPolicy::default(),
+            retry_queue: RetryPolicy { attempts: 3, initial_backoff: Duration::from_secs(1) },
+            failure_policy: FailurePolicy::default(),
+        }
+    }
+
+    pub fn with_prefetch(mut self, prefetch: u16) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    pub fn with_failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
+    pub fn with_retry_queue(mut self, retry_queue: RetryPolicy) -> Self {
+        self.retry_queue = retry_queue;
+        self
+    }
+}
+
+/// Consumes the operations from an AMQP queue (JSON payloads, see [`crate::queue::decode_operation`]) one by one,
+/// until `shutdown` completes or the connection fails. `on_message` is called with the outcome of every delivery; the delivery
+/// in flight is finished on shutdown.
+///
+/// Every delivery is acknowledged after the engine is done with it: acked once the operation is committed, otherwise
+/// requeued or dead-lettered according to the [`FailurePolicy`]. The rejected operations are also put in `dead_letters`,
+/// whatever their acknowledgement. Transient failures are retried in place, then in the [`RetryQueue`] while the next
+/// deliveries are consumed (they count in the prefetch meanwhile). A redelivery of a committed operation (e.g. after
+/// a lost ack) is acked again without effect, since the engine is idempotent.
+pub async fn consume_queue<TStorage: Storage + Journal, D: DeadLetterStore + Sync>(
+    engine: &Engine<TStorage>,
+    config: &AmqpConfig,
+    dead_letters: &D,
+    mut on_message: impl FnMut(&MessageOutcome),
+    shutdown: impl std::future::Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let connection = Connection::connect(&config.url, ConnectionProperties::default()).await.with_context(|| format!("error connecting to {}", config.url))?;
+    let channel = connection.create_channel().await.context("error opening a channel")?;
+    channel.basic_qos(config.prefetch, BasicQosOptions::default()).await.context("error setting the prefetch")?;
+    let mut consumer = channel
+        .basic_consume(config.queue.as_str().into(), config.consumer_tag.as_str().into(), BasicConsumeOptions::default(), FieldTable::default())
+        .await
+        .with_context(|| format!("error consuming queue {}", config.queue))?;
+
+    let mut retries = RetryQueue::new(config.retry_queue).with_clock(engine.clock().clone());
+    tokio::pin!(shutdown);
+    loop {
+        let next_due_in = retries.next_due_in();
+        let delivery = tokio::select! {
+            biased;
+            _ = &mut shutdown => break,
+            _ = runtime::sleep(next_due_in.unwrap_or_default()), if next_due_in.is_some() => {
+                for (delivery, outcome, provenance) in retries.retry_due(engine).await {
+                    finish_delivery(&delivery, outcome, provenance, config, dead_letters, &mut on_message).await?;
+                }
+                continue;
+            }
+            delivery = consumer.next() => match delivery {
+                Some(delivery) => delivery,
+                None => break,
+            },
+        };
+        let delivery = delivery.context("error receiving a delivery")?;
+        let provenance = Provenance::new(OperationSource::Queue { name: config.queue.clone(), sequence: delivery.delivery_tag })
+            .with_correlation_id(delivery.properties.correlation_id().as_ref().map(|x| x.to_string()));
+        let outcome = execute_message(engine, &delivery.data, Some(&provenance), config.retry).await;
+        if let Err((delivery, outcome)) = retries.push(delivery, outcome, Some(&provenance)) {
+            finish_delivery(&delivery, outcome, Some(provenance), config, dead_letters, &mut on_message).await?;
+        }
+    }
+    // NOTE: the deliveries not processed yet (prefetched or in the retry queue) are requeued by the broker when the connection is closed
+    connection.close(200, "shutdown".into()).await.context("error closing the connection")?;
+    Ok(())
+}
+
+/// Dead-letters the rejected operation of a delivery, then acknowledges the delivery and reports its outcome.
+async fn finish_delivery<D: DeadLetterStore + Sync>(
+    delivery: &Delivery,
+    outcome: MessageOutcome,
+    provenance: Option<Provenance>,
+    config: &AmqpConfig,
+    dead_letters: &D,
+    on_message: &mut impl FnMut(&MessageOutcome),
+) -> anyhow::Result<()> {
+    if let MessageOutcome::Rejected(operation, err) = &outcome {
+        dead_letters.add(DeadLetter::new(operation.clone(), err, provenance)).await.context("error storing a dead letter")?;
+    }
+    match config.failure_policy.acknowledgement(&outcome) {
+        Acknowledgement::Ack => delivery.ack(BasicAckOptions::default()).await,
+        Acknowledgement::Requeue => delivery.nack(BasicNackOptions { multiple: false, requeue: true }).await,
+        Acknowledgement::DeadLetter => delivery.nack(BasicNackOptions { multiple: false, requeue: false }).await,
+    }
+    .context("error acknowledging a delivery")?;
+    on_message(&outcome);
+    Ok(())
+}
+
+#[cfg(test)]
+mod amqp_tests {
+    use rust_decimal::Decimal;
+
+    use crate::engine::{EngineError, Operation};
+
+    use super::*;
+
+    #[test]
+    fn failure_policy() {
+        let deposit = Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(1) };
+        let outcomes = [
+            MessageOutcome::Applied(deposit.clone()),
+            MessageOutcome::Invalid("invalid json".to_string()),
+            MessageOutcome::Rejected(deposit.clone(), EngineError::InsufficientFunds),
+            MessageOutcome::Transient(deposit, EngineError::DatabaseError("timeout".to_string())),
+        ];
+        let acknowledgements = |policy: FailurePolicy| outcomes.iter().map(|x| policy.acknowledgement(x)).collect::<Vec<_>>();
+        assert_eq!(acknowledgements(FailurePolicy::default()), vec![
+            Acknowledgement::Ack,
+            Acknowledgement::DeadLetter,
+            Acknowledgement::DeadLetter,
+            Acknowledgement::Requeue,
+        ]);
+        let policy = FailurePolicy { rejected: Acknowledgement::Ack, transient: Acknowledgement::DeadLetter, ..Default::default() };
+        assert_eq!(acknowledgements(policy), vec![
+            Acknowledgement::Ack,
+            Acknowledgement::DeadLetter,
+            Acknowledgement::Ack,
+            Acknowledgement::DeadLetter,
+        ]);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The roles of the API clients, each one is allowed everything the previous ones are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Reads the accounts and transactions.
+    Reader,
+    /// Also submits operations.
+    Operator,
+    /// Also calls the administrative endpoints (unlocks, adjustments, etc.).
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Reader => write!(f, "reader"),
+            Role::Operator => write!(f, "operator"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    #[error("missing credentials")]
+    MissingCredentials,
+    #[error("invalid credentials")]
+    InvalidCredentials,
+    #[error("the {actual} role is not allowed to do this, {required} is required")]
+    Forbidden { actual: Role, required: Role },
+    #[error("invalid auth config: {0}")]
+    InvalidConfig(String),
+}
+
+/// The `auth` section of the config file, e.g.
+/// `{"api_keys": [{"key": "s3cr3t", "role": "operator"}], "jwt": {"secret": "...", "issuer": "https://auth.example.com"}}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    /// Accepts the HS256 JWTs signed with this secret, with a `role` claim and an expiration.
+    #[serde(default)]
+    pub jwt: Option<JwtConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub key: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JwtConfig {
+    pub secret: String,
+    /// The required `iss` claim, any issuer when missing.
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// The required `aud` claim, the audience is not checked when missing.
+    #[serde(default)]
+    pub audience: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    role: Role,
+}
+
+/// Resolves the role of an API client from its credentials: an API key or a JWT, sent by the HTTP and gRPC clients
+/// as `Authorization: Bearer <credentials>` or `x-api-key: <credentials>`.
+pub struct Authenticator {
+    api_keys: HashMap<String, Role>,
+    jwt: Option<(DecodingKey, Validation)>,
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // NOTE: the keys and the secret are not printed
+        f.debug_struct("Authenticator").field("api_keys", &self.api_keys.len()).field("jwt", &self.jwt.is_some()).finish()
+    }
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Self {
+        let api_keys = config.api_keys.iter().map(|x| (x.key.clone(), x.role)).collect();
+        let jwt = config.jwt.as_ref().map(|jwt| {
+            let mut validation = Validation::new(Algorithm::HS256);
+            match &jwt.audience {
+                Some(audience) => validation.set_audience(&[audience]),
+                None => validation.validate_aud = false,
+            }
+            if let Some(issuer) = &jwt.issuer {
+                validation.set_issuer(&[issuer]);
+            }
+            (DecodingKey::from_secret(jwt.secret.as_bytes()), validation)
+        });
+        Self { api_keys, jwt }
+    }
+
+    /// Reads the [`AuthConfig`] from a JSON file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AuthError> {
+        let path = path.as_ref();
+        let content = std::fs::read(path).map_err(|e| AuthError::InvalidConfig(format!("error reading {}: {}", path.display(), e)))?;
+        let config: AuthConfig = serde_json::from_slice(&content).map_err(|e| AuthError::InvalidConfig(format!("error parsing {}: {}", path.display(), e)))?;
+        if config.api_keys.is_empty() && config.jwt.is_none() {
+            return Err(AuthError::InvalidConfig(format!("{} has neither API keys nor a JWT secret", path.display())));
+        }
+        Ok(Self::new(&config))
+    }
+
+    pub fn authenticate(&self, credentials: &str) -> Result<Role, AuthError> {
+        if let Some(role) = self.api_keys.get(credentials) {
+            return Ok(*role);
+        }
+        let Some((key, validation)) = &self.jwt else {
+            return Err(AuthError::InvalidCredentials);
+        };
+        jsonwebtoken::decode::<Claims>(credentials, key, validation)
+            .map(|x| x.claims.role)
+            .map_err(|_| AuthError::InvalidCredentials)
+    }
+
+    /// Checks that the credentials (if any) belong to a client allowed to act as `required`.
+    pub fn authorize(&self, credentials: Option<&str>, required: Role) -> Result<Role, AuthError> {
+        let actual = self.authenticate(credentials.ok_or(AuthError::MissingCredentials)?)?;
+        if actual < required {
+            return Err(AuthError::Forbidden { actual, required });
+        }
+        Ok(actual)
+    }
+}
+
+/// The credentials of the `Authorization: Bearer` or `x-api-key` header values.
+pub fn credentials<'a>(authorization: Option<&'a str>, api_key: Option<&'a str>) -> Option<&'a str> {
+    authorization.and_then(|x| x.strip_prefix("Bearer ")).or(api_key).map(str::trim)
+}
+
+#[cfg(test)]
+mod auth_tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use jsonwebtoken::{EncodingKey, Header};
+
+    use super::*;
+
+    fn token(secret: &str, role: &str, expires_in: i64) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let claims = serde_json::json!({"sub": "tests", "role": role, "iss": "tests", "exp": now + expires_in});
+        jsonwebtoken::encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn api_keys_and_jwts() {
+        let config: AuthConfig = serde_json::from_str(r#"{
+            "api_keys": [{"key": "reader-key", "role": "reader"}, {"key": "operator-key", "role": "operator"}],
+            "jwt": {"secret": "secret", "issuer": "tests"}
+        }"#).unwrap();
+        let authenticator = Authenticator::new(&config);
+
+        assert_eq!(authenticator.authorize(Some("operator-key"), Role::Reader), Ok(Role::Operator));
+        assert_eq!(authenticator.authorize(Some("reader-key"), Role::Operator), Err(AuthError::Forbidden { actual: Role::Reader, required: Role::Operator }));
+        assert_eq!(authenticator.authorize(None, Role::Reader), Err(AuthError::MissingCredentials));
+        assert_eq!(authenticator.authorize(Some("unknown-key"), Role::Reader), Err(AuthError::InvalidCredentials));
+
+        assert_eq!(authenticator.authorize(Some(&token("secret", "admin", 60)), Role::Admin), Ok(Role::Admin));
+        assert_eq!(authenticator.authorize(Some(&token("other-secret", "admin", 60)), Role::Reader), Err(AuthError::InvalidCredentials));
+        assert_eq!(authenticator.authorize(Some(&token("secret", "admin", -3600)), Role::Reader), Err(AuthError::InvalidCredentials));
+        assert_eq!(authenticator.authorize(Some(&token("secret", "root", 60)), Role::Reader), Err(AuthError::InvalidCredentials));
+
+        assert_eq!(credentials(Some("Bearer operator-key"), None), Some("operator-key"));
+        assert_eq!(credentials(Some("Basic dXNlcg=="), Some("reader-key")), Some("reader-key"));
+        assert_eq!(credentials(None, None), None);
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use lru::LruCache;
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, Savepoint, Savepoints, Storage, TxOptions};
+use crate::transaction::{Transaction, TxId};
+
+/// Storage decorator that keeps the hot accounts and the recent transactions decoded in memory,
+/// so a client showing up in thousands of consecutive rows is read from the wrapped storage only once.
+///
+/// Writes go to the wrapped storage right away and reach the cache only after the db transaction is committed,
+/// so a rolled back transaction never leaks into the cache. The cache assumes that every write goes through it:
+/// a record changed behind its back is served stale until it is evicted or a write to it fails with a conflict.
+pub struct CachedStorage<S> {
+    inner: S,
+    accounts: Mutex<LruCache<ClientId, Account>>,
+    txs: Mutex<LruCache<TxId, Transaction>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Db transaction of the `CachedStorage`, holds the records written so far until the commit.
+pub struct CachedDbTx<T> {
+    inner: T,
+    accounts: HashMap<ClientId, Account>,
+    txs: HashMap<TxId, Transaction>,
+    deleted_txs: HashSet<TxId>,
+    savepoints: Savepoints<PendingWrites>,
+}
+
+/// The accounts, the transactions and the deleted transactions written by a `CachedDbTx` at a savepoint.
+type PendingWrites = (HashMap<ClientId, Account>, HashMap<TxId, Transaction>, HashSet<TxId>);
+
+impl<S> CachedStorage<S> {
+    /// Caches up to `accounts_capacity` accounts and `txs_capacity` transactions, the least recently used ones are evicted first.
+    pub fn new(inner: S, accounts_capacity: NonZeroUsize, txs_capacity: NonZeroUsize) -> Self {
+        Self {
+            inner,
+            accounts: Mutex::new(LruCache::new(accounts_capacity)),
+            txs: Mutex::new(LruCache::new(txs_capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Number of reads served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of reads that had to go to the wrapped storage.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn lookup<K: Hash + Eq, V: Clone>(&self, cache: &Mutex<LruCache<K, V>>, key: &K) -> Option<V> {
+        let value = cache.lock().ok().and_then(|mut cache| cache.get(key).cloned());
+        match value {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        value
+    }
+
+    /// Fills the cache after a read, keeping the cached record if a concurrent commit already put a newer one.
+    fn remember<K: Hash + Eq, V>(cache: &Mutex<LruCache<K, V>>, key: K, value: V, version: impl Fn(&V) -> u16) {
+        if let Ok(mut cache) = cache.lock() {
+            Self::put_newer(&mut cache, key, value, version);
+        }
+    }
+
+    /// Puts the record unless the cached one has a newer version.
+    fn put_newer<K: Hash + Eq, V>(cache: &mut LruCache<K, V>, key: K, value: V, version: impl Fn(&V) -> u16) {
+        if cache.peek(&key).is_none_or(|cached| version(cached) <= version(&value)) {
+            cache.put(key, value);
+        }
+    }
+
+    fn forget<K: Hash + Eq, V>(cache: &Mutex<LruCache<K, V>>, key: &K) {
+        if let Ok(mut cache) = cache.lock() {
+            cache.pop(key);
+        }
+    }
+
+    fn get_cached_account(&self, db_tx: &CachedDbTx<impl Sized>, acc_id: ClientId) -> Option<Account> {
+        db_tx.accounts.get(&acc_id).cloned().or_else(|| self.lookup(&self.accounts, &acc_id))
+    }
+
+    fn get_cached_tx(&self, db_tx: &CachedDbTx<impl Sized>, tx_id: TxId) -> Option<Transaction> {
+        if db_tx.deleted_txs.contains(&tx_id) {
+            return None;
+        }
+        db_tx.txs.get(&tx_id).cloned().or_else(|| self.lookup(&self.txs, &tx_id))
+    }
+
+    /// Drops the cached record when the wrapped storage reports that it has changed in the meantime.
+    fn on_write_error<K: Hash + Eq, V>(cache: &Mutex<LruCache<K, V>>, key: &K, err: DbError) -> DbError {
+        if err == DbError::ConcurrentModification {
+            Self::forget(cache, key);
+        }
+        err
+    }
+}
+
+impl<S> Storage for CachedStorage<S>
+where
+    S: Storage + Sync,
+    S::DbTx: Send,
+{
+    type DbTx = CachedDbTx<S::DbTx>;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        if let Some(tx) = self.get_cached_tx(db_tx, tx_id) {
+            return Ok(Some(tx));
+        }
+        let tx = self.inner.get_tx(&mut db_tx.inner, tx_id).await?;
+        if let Some(tx) = &tx {
+            Self::remember(&self.txs, tx_id, tx.clone(), Transaction::version);
+        }
+        Ok(tx)
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        self.inner.insert_tx(&mut db_tx.inner, tx).await?;
+        db_tx.deleted_txs.remove(&tx.id());
+        db_tx.txs.insert(tx.id(), tx.clone());
+        Ok(())
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        self.inner.update_tx(&mut db_tx.inner, old_tx, new_tx).await
+            .map_err(|err| Self::on_write_error(&self.txs, &old_tx.id(), err))?;
+        db_tx.txs.insert(new_tx.id(), new_tx.clone());
+        Ok(())
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        self.inner.get_all_txs(&mut db_tx.inner).await
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        let mut txs: Vec<Option<Transaction>> = tx_ids.iter().map(|tx_id| self.get_cached_tx(db_tx, *tx_id)).collect();
+        let missing: Vec<TxId> = tx_ids.iter().zip(&txs).filter(|(_, tx)| tx.is_none()).map(|(tx_id, _)| *tx_id).collect();
+        if missing.is_empty() {
+            return Ok(txs);
+        }
+        let mut fetched = self.inner.get_txs(&mut db_tx.inner, &missing).await?.into_iter();
+        for tx in txs.iter_mut().filter(|tx| tx.is_none()) {
+            *tx = fetched.next().flatten();
+            if let Some(tx) = tx {
+                Self::remember(&self.txs, tx.id(), tx.clone(), Transaction::version);
+            }
+        }
+        Ok(txs)
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        self.inner.insert_txs(&mut db_tx.inner, txs).await?;
+        for tx in txs {
+            db_tx.deleted_txs.remove(&tx.id());
+            db_tx.txs.insert(tx.id(), tx.clone());
+        }
+        Ok(())
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        let deleted = self.inner.delete_txs(&mut db_tx.inner, tx_ids).await?;
+        for tx_id in tx_ids {
+            db_tx.txs.remove(tx_id);
+            db_tx.deleted_txs.insert(*tx_id);
+        }
+        Ok(deleted)
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        self.inner.get_txs_by_account(&mut db_tx.inner, acc_id, cursor, limit).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        self.inner.get_tx_by_external_id(&mut db_tx.inner, external_id).await
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        self.inner.get_expired_authorizations(&mut db_tx.inner, now).await
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        if let Some(acc) = self.get_cached_account(db_tx, acc_id) {
+            return Ok(Some(acc));
+        }
+        let acc = self.inner.get_account(&mut db_tx.inner, acc_id).await?;
+        if let Some(acc) = &acc {
+            Self::remember(&self.accounts, acc_id, acc.clone(), Account::version);
+        }
+        Ok(acc)
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        self.inner.get_all_accounts(&mut db_tx.inner).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        let mut accs: Vec<Option<Account>> = acc_ids.iter().map(|acc_id| self.get_cached_account(db_tx, *acc_id)).collect();
+        let missing: Vec<ClientId> = acc_ids.iter().zip(&accs).filter(|(_, acc)| acc.is_none()).map(|(acc_id, _)| *acc_id).collect();
+        if missing.is_empty() {
+            return Ok(accs);
+        }
+        let mut fetched = self.inner.get_accounts(&mut db_tx.inner, &missing).await?.into_iter();
+        for acc in accs.iter_mut().filter(|acc| acc.is_none()) {
+            *acc = fetched.next().flatten();
+            if let Some(acc) = acc {
+                Self::remember(&self.accounts, acc.id(), acc.clone(), Account::version);
+            }
+        }
+        Ok(accs)
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        self.inner.insert_accounts(&mut db_tx.inner, accs).await?;
+        db_tx.accounts.extend(accs.iter().map(|acc| (acc.id(), acc.clone())));
+        Ok(())
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        self.inner.list_accounts(&mut db_tx.inner, cursor, limit).await
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        self.inner.insert_account(&mut db_tx.inner, acc).await?;
+        db_tx.accounts.insert(acc.id(), acc.clone());
+        Ok(())
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        self.inner.update_account(&mut db_tx.inner, old_acc, new_acc).await
+            .map_err(|err| Self::on_write_error(&self.accounts, &old_acc.id(), err))?;
+        db_tx.accounts.insert(new_acc.id(), new_acc.clone());
+        Ok(())
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        self.inner.get_system_account(&mut db_tx.inner, account).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        self.inner.set_system_account(&mut db_tx.inner, account, acc).await
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        self.inner.is_operation_processed(&mut db_tx.inner, op_hash).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        self.inner.insert_operation(&mut db_tx.inner, op_hash, timestamp).await
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        self.inner.get_all_operations(&mut db_tx.inner).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        self.inner.prune_operations(&mut db_tx.inner, older_than).await
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        self.inner.get_operation_rejection(&mut db_tx.inner, op_hash).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        self.inner.insert_operation_rejection(&mut db_tx.inner, op_hash, code, timestamp).await
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        self.inner.get_checkpoint(&mut db_tx.inner, source).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        self.inner.set_checkpoint(&mut db_tx.inner, source, rows).await
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        let inner = self.inner.start_db_tx(options).await?;
+        Ok(CachedDbTx { inner, accounts: HashMap::new(), txs: HashMap::new(), deleted_txs: HashSet::new(), savepoints: Savepoints::default() })
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        self.inner.commit_db_tx(db_tx.inner).await?;
+        if let Ok(mut cache) = self.accounts.lock() {
+            // NOTE: the commits finish in any order, a later one may already have cached a newer version
+            for (acc_id, acc) in db_tx.accounts {
+                Self::put_newer(&mut cache, acc_id, acc, Account::version);
+            }
+        }
+        if let Ok(mut cache) = self.txs.lock() {
+            for tx_id in &db_tx.deleted_txs {
+                cache.pop(tx_id);
+            }
+            for (tx_id, tx) in db_tx.txs {
+                Self::put_newer(&mut cache, tx_id, tx, Transaction::version);
+            }
+        }
+        Ok(())
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        let savepoint = self.inner.savepoint(&mut db_tx.inner).await?;
+        db_tx.savepoints.insert(savepoint, (db_tx.accounts.clone(), db_tx.txs.clone(), db_tx.deleted_txs.clone()));
+        Ok(savepoint)
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        self.inner.rollback_to(&mut db_tx.inner, savepoint).await?;
+        let (accounts, txs, deleted_txs) = db_tx.savepoints.rollback_to(savepoint)?;
+        db_tx.accounts = accounts.clone();
+        db_tx.txs = txs.clone();
+        db_tx.deleted_txs = deleted_txs.clone();
+        Ok(())
+    }
+}
+
+impl<S> Journal for CachedStorage<S>
+where
+    S: Journal + Sync,
+    S::DbTx: Send,
+{
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        self.inner.get_last_journal_seq(&mut db_tx.inner).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        self.inner.append_journal_entry(&mut db_tx.inner, entry).await
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        self.inner.get_journal_entries(&mut db_tx.inner, from_seq, limit).await
+    }
+}
+
+#[cfg(test)]
+mod cached_storage_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::{Engine, EngineError};
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    fn cached(capacity: usize) -> CachedStorage<EchoDbStorage> {
+        let capacity = NonZeroUsize::new(capacity).unwrap();
+        CachedStorage::new(EchoDbStorage::new(), capacity, capacity)
+    }
+
+    #[tokio::test]
+    async fn hot_account_is_served_from_cache() {
+        let engine = Engine::new(cached(16));
+        for tx_id in 1..=10 {
+            assert_eq!(engine.deposit(1, tx_id, Decimal4::from(10)).await, Ok(()));
+        }
+        assert_eq!(engine.withdraw(1, 11, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+
+        assert!(engine.storage().hits() >= 11);
+        let mut db_tx = engine.storage().inner().start_db_tx(TxOptions::default()).await.unwrap();
+        let acc = engine.storage().inner().get_account(&mut db_tx, 1).await.unwrap().unwrap();
+        drop(db_tx);
+        assert_eq!(acc, engine.get_account(1).await.unwrap().unwrap());
+        assert_eq!(acc.available(), Decimal4::from(60));
+        assert_eq!(acc.held(), Decimal4::from(10));
+    }
+
+    #[tokio::test]
+    async fn rolled_back_writes_are_not_cached() {
+        let storage = cached(16);
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        storage.insert_account(&mut db_tx, &Account::new(1)).await.unwrap();
+        assert_eq!(storage.get_account(&mut db_tx, 1).await, Ok(Some(Account::new(1))));
+        drop(db_tx);
+
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_account(&mut db_tx, 1).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn commit_keeps_newer_cached_records() {
+        let storage = cached(16);
+        let mut newer = Account::new(1);
+        newer.deposit(Decimal4::from(10)).unwrap();
+        CachedStorage::<EchoDbStorage>::remember(&storage.accounts, 1, newer.clone(), Account::version);
+
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        storage.insert_account(&mut db_tx, &Account::new(1)).await.unwrap();
+        storage.commit_db_tx(db_tx).await.unwrap();
+        assert_eq!(storage.accounts.lock().unwrap().peek(&1), Some(&newer));
+    }
+
+    #[tokio::test]
+    async fn evicted_records_are_reloaded() {
+        let engine = Engine::new(cached(1));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(1)).await, Err(EngineError::TransactionWithTheSameIdAlreadyExists));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().held(), Decimal4::from(100));
+        assert_eq!(engine.get_account(2).await.unwrap().unwrap().available(), Decimal4::from(50));
+    }
+}
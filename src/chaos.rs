@@ -0,0 +1,326 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, Savepoint, Storage, TxOptions};
+use crate::transaction::{Transaction, TxId};
+
+/// Storage decorator that randomly fails a share of the calls to the wrapped storage,
+/// so the retry and error-handling paths can be tested against the engine.
+///
+/// Rates are probabilities in the `[0, 1]` range. Failed reads, writes and commits return `DbError::DatabaseError`,
+/// injected conflicts make writes return `DbError::ConcurrentModification`. A failed write or commit leaves the wrapped
+/// storage untouched.
+pub struct ChaosStorage<S> {
+    inner: S,
+    read_failure_rate: f64,
+    write_failure_rate: f64,
+    commit_failure_rate: f64,
+    conflict_rate: f64,
+    rng: Mutex<fastrand::Rng>,
+    injected_failures: AtomicU64,
+}
+
+impl<S> ChaosStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            read_failure_rate: 0.0,
+            write_failure_rate: 0.0,
+            commit_failure_rate: 0.0,
+            conflict_rate: 0.0,
+            rng: Mutex::new(fastrand::Rng::new()),
+            injected_failures: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_read_failure_rate(mut self, rate: f64) -> Self {
+        self.read_failure_rate = rate;
+        self
+    }
+
+    pub fn with_write_failure_rate(mut self, rate: f64) -> Self {
+        self.write_failure_rate = rate;
+        self
+    }
+
+    pub fn with_commit_failure_rate(mut self, rate: f64) -> Self {
+        self.commit_failure_rate = rate;
+        self
+    }
+
+    pub fn with_conflict_rate(mut self, rate: f64) -> Self {
+        self.conflict_rate = rate;
+        self
+    }
+
+    /// Makes the injected failures reproducible.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { rng: Mutex::new(fastrand::Rng::with_seed(seed)), ..self }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn injected_failures(&self) -> u64 {
+        self.injected_failures.load(Ordering::Relaxed)
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        let hit = rate > 0.0 && self.rng.lock().map(|mut rng| rng.f64() < rate).unwrap_or(false);
+        if hit {
+            self.injected_failures.fetch_add(1, Ordering::Relaxed);
+        }
+        hit
+    }
+
+    fn maybe_fail_read(&self) -> Result<(), DbError> {
+        if self.roll(self.read_failure_rate) {
+            return Err(DbError::DatabaseError("Injected read failure".to_string()));
+        }
+        Ok(())
+    }
+
+    fn maybe_fail_write(&self) -> Result<(), DbError> {
+        if self.roll(self.write_failure_rate) {
+            return Err(DbError::DatabaseError("Injected write failure".to_string()));
+        }
+        if self.roll(self.conflict_rate) {
+            return Err(DbError::ConcurrentModification);
+        }
+        Ok(())
+    }
+}
+
+impl<S> Storage for ChaosStorage<S>
+where
+    S: Storage + Sync,
+    S::DbTx: Send,
+{
+    type DbTx = S::DbTx;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_tx(db_tx, tx_id).await
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.insert_tx(db_tx, tx).await
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.update_tx(db_tx, old_tx, new_tx).await
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_all_txs(db_tx).await
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_txs(db_tx, tx_ids).await
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.insert_txs(db_tx, txs).await
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        self.maybe_fail_write()?;
+        self.inner.delete_txs(db_tx, tx_ids).await
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_txs_by_account(db_tx, acc_id, cursor, limit).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_tx_by_external_id(db_tx, external_id).await
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_expired_authorizations(db_tx, now).await
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_account(db_tx, acc_id).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_all_accounts(db_tx).await
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.list_accounts(db_tx, cursor, limit).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_accounts(db_tx, acc_ids).await
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.insert_accounts(db_tx, accs).await
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.insert_account(db_tx, acc).await
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.update_account(db_tx, old_acc, new_acc).await
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_system_account(db_tx, account).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.set_system_account(db_tx, account, acc).await
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.is_operation_processed(db_tx, op_hash).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.insert_operation(db_tx, op_hash, timestamp).await
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_all_operations(db_tx).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        self.maybe_fail_write()?;
+        self.inner.prune_operations(db_tx, older_than).await
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_operation_rejection(db_tx, op_hash).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.insert_operation_rejection(db_tx, op_hash, code, timestamp).await
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_checkpoint(db_tx, source).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.set_checkpoint(db_tx, source, rows).await
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        self.inner.start_db_tx(options).await
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        if self.roll(self.commit_failure_rate) {
+            return Err(DbError::DatabaseError("Injected commit failure".to_string()));
+        }
+        self.inner.commit_db_tx(db_tx).await
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        self.inner.savepoint(db_tx).await
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        self.inner.rollback_to(db_tx, savepoint).await
+    }
+}
+
+impl<S> Journal for ChaosStorage<S>
+where
+    S: Journal + Sync,
+    S::DbTx: Send,
+{
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_last_journal_seq(db_tx).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        self.maybe_fail_write()?;
+        self.inner.append_journal_entry(db_tx, entry).await
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        self.maybe_fail_read()?;
+        self.inner.get_journal_entries(db_tx, from_seq, limit).await
+    }
+}
+
+#[cfg(test)]
+mod chaos_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::{Engine, EngineError};
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn no_failures_by_default() {
+        let engine = Engine::new(ChaosStorage::new(EchoDbStorage::new()));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(30)).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn injected_failures_leave_state_consistent() {
+        let storage = ChaosStorage::new(EchoDbStorage::new())
+            .with_read_failure_rate(0.05)
+            .with_write_failure_rate(0.05)
+            .with_commit_failure_rate(0.05)
+            .with_conflict_rate(0.05)
+            .with_seed(42);
+        let engine = Engine::new(storage);
+
+        let mut applied = Decimal4::zero();
+        for tx_id in 1..=200 {
+            match engine.deposit(1, tx_id, Decimal4::from(1)).await {
+                Ok(()) => applied += Decimal4::from(1),
+                Err(EngineError::DatabaseError(_)) | Err(EngineError::ConcurrentOperationDetected) => {}
+                Err(err) => panic!("unexpected error: {:?}", err),
+            }
+        }
+
+        let failures = engine.storage().injected_failures();
+        assert!(failures > 0);
+        let mut db_tx = engine.storage().inner().start_db_tx(TxOptions::default()).await.unwrap();
+        let acc = engine.storage().inner().get_account(&mut db_tx, 1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), applied);
+    }
+
+    #[tokio::test]
+    async fn injected_conflict() {
+        let engine = Engine::new(ChaosStorage::new(EchoDbStorage::new()).with_conflict_rate(1.0));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Err(EngineError::ConcurrentOperationDetected));
+    }
+}
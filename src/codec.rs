@@ -0,0 +1,113 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::storage::DbError;
+
+/// Binary encoding of the stored values, shared by the storage backends.
+pub trait Codec: Send + Sync {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, DbError>;
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DbError>;
+}
+
+/// Compact MessagePack encoding, the default one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePackCodec;
+
+impl Codec for MessagePackCodec {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, DbError> {
+        Ok(rmp_serde::to_vec(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DbError> {
+        Ok(rmp_serde::from_slice(data)?)
+    }
+}
+
+/// Human-readable JSON encoding, handy for debugging.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, DbError> {
+        serde_json::to_vec(value).map_err(|e| DbError::DatabaseError(format!("Can not encode data: {}", e)))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DbError> {
+        serde_json::from_slice(data).map_err(|e| DbError::DatabaseError(format!("Can not decode data: {}", e)))
+    }
+}
+
+/// The fastest encoding, but without any schema information in the data.
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl Codec for BincodeCodec {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, DbError> {
+        bincode::serialize(value).map_err(|e| DbError::DatabaseError(format!("Can not encode data: {}", e)))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DbError> {
+        bincode::deserialize(data).map_err(|e| DbError::DatabaseError(format!("Can not decode data: {}", e)))
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, DbError> {
+        let mut data = Vec::new();
+        ciborium::into_writer(value, &mut data).map_err(|e| DbError::DatabaseError(format!("Can not encode data: {}", e)))?;
+        Ok(data)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, DbError> {
+        ciborium::from_reader(data).map_err(|e| DbError::DatabaseError(format!("Can not decode data: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod codec_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    async fn roundtrip<C: Codec + 'static>(codec: C) {
+        let engine = Engine::new(EchoDbStorage::with_codec(codec));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(-30));
+        assert_eq!(acc.held(), Decimal4::from(100));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn message_pack_roundtrip() {
+        roundtrip(MessagePackCodec).await;
+    }
+
+    #[tokio::test]
+    async fn json_roundtrip() {
+        roundtrip(JsonCodec).await;
+    }
+
+    #[cfg(feature = "bincode")]
+    #[tokio::test]
+    async fn bincode_roundtrip() {
+        roundtrip(BincodeCodec).await;
+    }
+
+    #[cfg(feature = "cbor")]
+    #[tokio::test]
+    async fn cbor_roundtrip() {
+        roundtrip(CborCodec).await;
+    }
+}
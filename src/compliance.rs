@@ -0,0 +1,311 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::account::{Account, ClientId};
+use crate::decimal::Decimal4;
+use crate::engine::Operation;
+use crate::journal::JournalEntry;
+use crate::observer::EngineObserver;
+use crate::redact;
+use crate::transaction::{Transaction, TransactionType, TxId};
+
+/// The thresholds of the anti-money-laundering checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmlConfig {
+    /// Deposits and withdrawals of at least this amount are flagged.
+    pub threshold: Decimal4,
+    /// Deposits less than `structuring_margin` below the threshold count as just below it.
+    pub structuring_margin: Decimal4,
+    /// This many just-below-threshold deposits of an account within the window are flagged as structuring.
+    pub structuring_count: usize,
+    /// The window of the structuring check, in millis.
+    pub structuring_window_ms: u64,
+}
+
+impl Default for AmlConfig {
+    fn default() -> Self {
+        Self {
+            threshold: Decimal4::from(10_000),
+            structuring_margin: Decimal4::from(1_000),
+            structuring_count: 3,
+            structuring_window_ms: 24 * 60 * 60 * 1000,
+        }
+    }
+}
+
+impl AmlConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_threshold(mut self, threshold: Decimal4) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_structuring_margin(mut self, margin: Decimal4) -> Self {
+        self.structuring_margin = margin;
+        self
+    }
+
+    pub fn with_structuring_count(mut self, count: usize) -> Self {
+        self.structuring_count = count;
+        self
+    }
+
+    pub fn with_structuring_window_ms(mut self, window_ms: u64) -> Self {
+        self.structuring_window_ms = window_ms;
+        self
+    }
+
+    fn is_just_below_threshold(&self, amount: Decimal4) -> bool {
+        amount < self.threshold && amount >= self.threshold - self.structuring_margin
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmlFlagKind {
+    /// A deposit or a withdrawal of at least the threshold.
+    LargeAmount,
+    /// The last of several just-below-threshold deposits within the window, the others are in `related`.
+    Structuring,
+}
+
+/// A suspicious operation, reported with the transaction that triggered the check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AmlFlag {
+    pub kind: AmlFlagKind,
+    pub client: ClientId,
+    pub tx: TxId,
+    #[serde(rename = "type")]
+    pub op_type: &'static str,
+    pub amount: Decimal4,
+    pub timestamp: u64,
+    /// The earlier transactions of the pattern, ordered by time.
+    pub related: Vec<TxId>,
+}
+
+/// Applies the checks to the deposits and withdrawals in the order they were applied.
+/// The structuring check remembers the recent just-below-threshold deposits of every account.
+#[derive(Debug, Default)]
+pub struct AmlMonitor {
+    config: AmlConfig,
+    recent_deposits: HashMap<ClientId, VecDeque<(u64, TxId)>>,
+}
+
+impl AmlMonitor {
+    pub fn new(config: AmlConfig) -> Self {
+        Self { config, recent_deposits: HashMap::new() }
+    }
+
+    pub fn config(&self) -> &AmlConfig {
+        &self.config
+    }
+
+    /// Checks an applied transaction, `timestamp` is the time it was applied (unix millis).
+    pub fn check(&mut self, tx: &Transaction, timestamp: u64) -> Vec<AmlFlag> {
+        let flag = |kind, related| AmlFlag {
+            kind,
+            client: tx.account_id(),
+            tx: tx.id(),
+            op_type: match tx.tx_type() {
+                TransactionType::Deposit => "deposit",
+                TransactionType::Withdrawal => "withdrawal",
+                TransactionType::Escrow => "escrow",
+                TransactionType::EscrowRelease => "release",
+                TransactionType::Authorization => "authorize",
+            },
+            amount: tx.amount(),
+            timestamp,
+            related,
+        };
+        let mut flags = Vec::new();
+        if tx.amount() >= self.config.threshold {
+            flags.push(flag(AmlFlagKind::LargeAmount, vec![]));
+        }
+        if tx.tx_type() == TransactionType::Deposit && self.config.is_just_below_threshold(tx.amount()) {
+            let recent = self.recent_deposits.entry(tx.account_id()).or_default();
+            recent.retain(|(x, _)| timestamp.saturating_sub(*x) < self.config.structuring_window_ms);
+            if recent.len() + 1 >= self.config.structuring_count {
+                // NOTE: the pattern is reported once, the next deposits start a new one
+                let related = recent.drain(..).map(|(_, x)| x).collect();
+                flags.push(flag(AmlFlagKind::Structuring, related));
+            } else {
+                recent.push_back((timestamp, tx.id()));
+            }
+        }
+        flags
+    }
+
+    /// Checks the transaction of a journal entry, only the deposits and withdrawals are checked.
+    pub fn check_entry(&mut self, entry: &JournalEntry) -> Vec<AmlFlag> {
+        // NOTE: a dispute, resolve or chargeback is journaled with the disputed transaction, which was already checked
+        match entry.operation() {
+            Operation::Deposit { .. } | Operation::Withdraw { .. } => self.check(entry.transaction(), entry.timestamp()),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The flagged operations of a period, e.g. for filing with the regulator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SuspiciousActivityReport {
+    pub threshold: Decimal4,
+    pub flags: Vec<AmlFlag>,
+}
+
+#[derive(Debug, Serialize)]
+struct CsvAmlFlagRow<'a> {
+    kind: AmlFlagKind,
+    client: ClientId,
+    tx: TxId,
+    #[serde(rename = "type")]
+    op_type: &'static str,
+    amount: Decimal4,
+    timestamp: u64,
+    related: &'a str,
+}
+
+impl SuspiciousActivityReport {
+    /// Runs the checks over journal entries, which must be ordered by seq.
+    pub fn from_journal<'a>(config: AmlConfig, entries: impl IntoIterator<Item = &'a JournalEntry>) -> Self {
+        let mut monitor = AmlMonitor::new(config);
+        let flags = entries.into_iter().flat_map(|x| monitor.check_entry(x)).collect();
+        Self { threshold: config.threshold, flags }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report is always serializable")
+    }
+
+    /// Writes a row per flag, with the related transactions separated by spaces.
+    pub fn write_csv<W: Write>(&self, writer: W) -> csv::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for flag in self.flags.iter() {
+            let related = flag.related.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(" ");
+            writer.serialize(CsvAmlFlagRow {
+                kind: flag.kind,
+                client: flag.client,
+                tx: flag.tx,
+                op_type: flag.op_type,
+                amount: flag.amount,
+                timestamp: flag.timestamp,
+                related: &related,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// An observer running the checks on the applied operations as they happen. The flags are logged as warnings
+/// and collected for [`AmlObserver::report`]. The report is lost on restart, `Engine::get_aml_report` rebuilds it from the journal.
+#[derive(Debug, Default)]
+pub struct AmlObserver {
+    monitor: Mutex<AmlMonitor>,
+    flags: Mutex<Vec<AmlFlag>>,
+}
+
+impl AmlObserver {
+    pub fn new(config: AmlConfig) -> Self {
+        Self { monitor: Mutex::new(AmlMonitor::new(config)), flags: Mutex::new(Vec::new()) }
+    }
+
+    /// The report of the operations flagged so far.
+    pub fn report(&self) -> SuspiciousActivityReport {
+        let threshold = self.monitor.lock().unwrap().config().threshold;
+        SuspiciousActivityReport { threshold, flags: self.flags.lock().unwrap().clone() }
+    }
+
+    fn check(&self, transaction: &Transaction) {
+        let flags = self.monitor.lock().unwrap().check(transaction, transaction.created_at());
+        for flag in flags.iter() {
+            tracing::warn!(client = %redact::client(flag.client), tx = flag.tx, amount = %redact::amount(flag.amount), kind = ?flag.kind, "suspicious activity");
+        }
+        self.flags.lock().unwrap().extend(flags);
+    }
+}
+
+impl EngineObserver for AmlObserver {
+    fn on_deposit_applied(&self, _account: &Account, transaction: &Transaction) {
+        self.check(transaction);
+    }
+
+    fn on_withdrawal_applied(&self, _account: &Account, transaction: &Transaction) {
+        self.check(transaction);
+    }
+}
+
+#[cfg(test)]
+mod compliance_tests {
+    use std::sync::Arc;
+
+    use crate::engine::Engine;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    fn deposit(tx_id: TxId, amount: u32) -> Transaction {
+        Transaction::new(tx_id, 1, TransactionType::Deposit, Decimal4::from(amount))
+    }
+
+    #[test]
+    fn structuring_within_window() {
+        let config = AmlConfig::new().with_threshold(Decimal4::from(1000)).with_structuring_margin(Decimal4::from(100)).with_structuring_window_ms(1000);
+        let mut monitor = AmlMonitor::new(config);
+        assert_eq!(monitor.check(&deposit(1, 950), 0), vec![]);
+        assert_eq!(monitor.check(&deposit(2, 500), 100), vec![]);
+        assert_eq!(monitor.check(&deposit(3, 990), 200), vec![]);
+        let flags = monitor.check(&deposit(4, 999), 900);
+        assert_eq!(flags.len(), 1);
+        assert_eq!((flags[0].kind, flags[0].tx, flags[0].related.clone()), (AmlFlagKind::Structuring, 4, vec![1, 3]));
+
+        // the deposits of the flagged pattern don't count again, and the older deposits leave the window
+        assert_eq!(monitor.check(&deposit(5, 950), 1000), vec![]);
+        assert_eq!(monitor.check(&deposit(6, 950), 2500), vec![]);
+        assert_eq!(monitor.check(&deposit(7, 950), 2600), vec![]);
+        assert_eq!(monitor.check(&deposit(8, 950), 2700)[0].related, vec![6, 7]);
+    }
+
+    #[tokio::test]
+    async fn report_from_hooks_and_journal() {
+        let config = AmlConfig::new().with_threshold(Decimal4::from(1000)).with_structuring_count(2);
+        let observer = Arc::new(AmlObserver::new(config));
+        let engine = Engine::new(EchoDbStorage::new()).with_observer(observer.clone());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(5000)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(1000)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 3, Decimal4::from(950)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 4, Decimal4::from(950)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+
+        let report = observer.report();
+        let flags: Vec<_> = report.flags.iter().map(|x| (x.kind, x.tx)).collect();
+        assert_eq!(flags, vec![(AmlFlagKind::LargeAmount, 1), (AmlFlagKind::LargeAmount, 2), (AmlFlagKind::Structuring, 4)]);
+        assert_eq!(engine.get_aml_report(config).await, Ok(report.clone()));
+
+        let mut data = Vec::new();
+        report.write_csv(&mut data).unwrap();
+        let timestamp = report.flags[2].timestamp;
+        assert!(String::from_utf8(data).unwrap().ends_with(&format!("structuring,2,4,deposit,950.0000,{},3\n", timestamp)));
+    }
+
+    #[tokio::test]
+    async fn report_over_journal_pages() {
+        let config = AmlConfig::new().with_threshold(Decimal4::from(1000)).with_structuring_margin(Decimal4::from(100)).with_structuring_count(2);
+        let engine = Engine::new(EchoDbStorage::new());
+        for tx_id in 1..=999 {
+            assert_eq!(engine.deposit(1, tx_id, Decimal4::from(1)).await, Ok(()));
+        }
+        assert_eq!(engine.deposit(2, 1000, Decimal4::from(950)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 1001, Decimal4::from(950)).await, Ok(()));
+        assert_eq!(engine.deposit(3, 1003, Decimal4::from(1000)).await, Ok(()));
+
+        let report = engine.get_aml_report(config).await.unwrap();
+        let flags: Vec<_> = report.flags.iter().map(|x| (x.kind, x.tx, x.related.clone())).collect();
+        assert_eq!(flags, vec![(AmlFlagKind::Structuring, 1001, vec![1000]), (AmlFlagKind::LargeAmount, 1003, vec![])]);
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::csv_parser::OutputFormat;
+use crate::decimal::{Decimal4, Rounding, MAX_DECIMALS};
+use crate::engine::{EnginePolicy, NegativeAvailablePolicy};
+
+/// The prefix of the environment variables read by [`EngineConfig::apply_env`], e.g. `TRANSACTIONS_ENGINE_STORAGE`.
+pub const ENV_PREFIX: &str = "TRANSACTIONS_ENGINE_";
+
+/// The settings of the binary, layered from the lowest to the highest priority: the defaults, the TOML config file,
+/// the `TRANSACTIONS_ENGINE_*` environment variables and the command line flags (applied by the caller), e.g.
+///
+/// ```toml
+/// storage = "file:engine.log"
+/// redact_pii = true
+///
+/// [engine]
+/// dispute_window_secs = 7776000
+/// lock_on_chargeback = true
+/// decimals = 2
+/// rounding = "bankers"
+/// max_amount = "1000000"
+/// withdrawal_fee = "0.5"
+/// write_off_chargeback_losses = true
+/// negative_available = "clamp"
+/// auto_unlock = true
+/// authorization_expiry_secs = 604800
+/// cache_rejections = true
+///
+/// [output]
+/// path = "accounts.json"
+/// format = "json"
+/// sorted = true
+///
+/// [server]
+/// http_listen = "0.0.0.0:8080"
+///
+/// [tenants.acme]
+/// storage = "file:acme.log"
+///
+/// [tenants.acme.output]
+/// path = "acme/accounts.csv"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// The storage backend: `memory`, `file:<path>` or `sqlite:<url>`.
+    pub storage: String,
+    /// Mask the account ids and bucket the amounts in the logs and traces, see [`crate::redact`].
+    pub redact_pii: bool,
+    /// The tenant the engine works for, see `Engine::with_tenant`. `{tenant}` in `storage` and `output.path` is replaced by it.
+    pub tenant: Option<String>,
+    pub engine: PolicyConfig,
+    pub output: OutputConfig,
+    pub server: ServerConfig,
+    /// The overrides of the settings per tenant, by tenant id.
+    pub tenants: BTreeMap<String, TenantConfig>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            storage: "memory".to_string(),
+            redact_pii: false,
+            tenant: None,
+            engine: PolicyConfig::default(),
+            output: OutputConfig::default(),
+            server: ServerConfig::default(),
+            tenants: BTreeMap::new(),
+        }
+    }
+}
+
+/// The `[engine]` section, see [`EnginePolicy`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Deposits older than this number of seconds can no longer be disputed, no limit when missing.
+    pub dispute_window_secs: Option<u64>,
+    pub lock_on_chargeback: bool,
+    /// The decimal places of the amounts, at most 4.
+    pub decimals: u32,
+    /// `midpoint-toward-zero`, `midpoint-away-from-zero`, `bankers`, `toward-zero` or `away-from-zero`.
+    pub rounding: String,
+    /// The largest amount of a deposit or withdrawal, no limit when missing.
+    pub max_amount: Option<Decimal4>,
+    /// The largest total balance of an account, no limit when missing.
+    pub max_balance: Option<Decimal4>,
+    /// The flat fee of a withdrawal, no fee when missing.
+    pub withdrawal_fee: Option<Decimal4>,
+    pub write_off_chargeback_losses: bool,
+    /// What a dispute of already spent funds does: `allow` (the available funds go negative), `clamp` or `reject`.
+    pub negative_available: String,
+    pub auto_unlock: bool,
+    /// Authorizations not captured within this number of seconds expire, no expiry when missing.
+    pub authorization_expiry_secs: Option<u64>,
+    pub cache_rejections: bool,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        let policy = EnginePolicy::default();
+        Self {
+            dispute_window_secs: policy.dispute_window.map(|x| x.as_secs()),
+            lock_on_chargeback: policy.lock_on_chargeback,
+            decimals: policy.rounding.decimals,
+            rounding: "midpoint-toward-zero".to_string(),
+            max_amount: policy.max_amount,
+            max_balance: policy.max_balance,
+            withdrawal_fee: policy.withdrawal_fee,
+            write_off_chargeback_losses: policy.write_off_chargeback_losses,
+            negative_available: "allow".to_string(),
+            auto_unlock: policy.auto_unlock,
+            authorization_expiry_secs: policy.authorization_expiry.map(|x| x.as_secs()),
+            cache_rejections: policy.cache_rejections,
+        }
+    }
+}
+
+impl PolicyConfig {
+    pub fn policy(&self) -> anyhow::Result<EnginePolicy> {
+        if self.decimals > MAX_DECIMALS {
+            bail!("at most {} decimals are supported, got {}", MAX_DECIMALS, self.decimals);
+        }
+        if self.withdrawal_fee.is_some_and(|x| x.is_negative()) {
+            bail!("the withdrawal fee must not be negative");
+        }
+        Ok(EnginePolicy {
+            dispute_window: self.dispute_window_secs.map(Duration::from_secs),
+            lock_on_chargeback: self.lock_on_chargeback,
+            rounding: Rounding { decimals: self.decimals, strategy: Rounding::parse_strategy(&self.rounding)? },
+            max_amount: self.max_amount,
+            max_balance: self.max_balance,
+            withdrawal_fee: self.withdrawal_fee,
+            write_off_chargeback_losses: self.write_off_chargeback_losses,
+            negative_available: match self.negative_available.as_str() {
+                "allow" => NegativeAvailablePolicy::Allow,
+                "clamp" => NegativeAvailablePolicy::Clamp,
+                "reject" => NegativeAvailablePolicy::Reject,
+                other => bail!("unknown negative available policy: {}", other),
+            },
+            auto_unlock: self.auto_unlock,
+            authorization_expiry: self.authorization_expiry_secs.map(Duration::from_secs),
+            cache_rejections: self.cache_rejections,
+        })
+    }
+}
+
+/// The `[output]` section: where and how the account summary is written.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// The file of the account summary, stdout when missing.
+    pub path: Option<String>,
+    /// `csv`, `json` or `jsonl`.
+    pub format: String,
+    /// Whether the accounts are sorted by client id.
+    pub sorted: bool,
+    /// Whether the summary also lists the system accounts (fees and chargeback losses).
+    pub system_accounts: bool,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self { path: None, format: "csv".to_string(), sorted: true, system_accounts: false }
+    }
+}
+
+impl OutputConfig {
+    pub fn format(&self) -> anyhow::Result<OutputFormat> {
+        self.format.parse()
+    }
+}
+
+/// The `[server]` section: the addresses the server modes listen on.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub http_listen: String,
+    pub grpc_listen: String,
+    pub tcp_listen: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            http_listen: "127.0.0.1:8080".to_string(),
+            grpc_listen: "127.0.0.1:50051".to_string(),
+            tcp_listen: "127.0.0.1:7070".to_string(),
+        }
+    }
+}
+
+/// A `[tenants.<id>]` section, the settings replacing the top-level ones when the engine works for that tenant.
+/// The `engine` and `output` sections are replaced as a whole.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantConfig {
+    pub storage: Option<String>,
+    pub engine: Option<PolicyConfig>,
+    pub output: Option<OutputConfig>,
+}
+
+impl EngineConfig {
+    /// Parses a TOML config, the missing settings keep their defaults.
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        config.engine.policy()?;
+        for (tenant, overrides) in config.tenants.iter() {
+            validate_tenant(tenant)?;
+            if let Some(engine) = &overrides.engine {
+                engine.policy().with_context(|| format!("invalid settings of tenant {}", tenant))?;
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("error reading config file {}", path.display()))?;
+        Self::from_toml(&text).with_context(|| format!("error parsing config file {}", path.display()))
+    }
+
+    /// Loads the config file (if any), applies the overrides of the tenant and then the environment variables of the process on top of it.
+    /// The tenant is the given one, else `TRANSACTIONS_ENGINE_TENANT`, else the `tenant` of the file.
+    pub fn load(path: Option<&Path>, tenant: Option<&str>) -> anyhow::Result<Self> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        let tenant = tenant.map(str::to_string).or_else(|| std::env::var(format!("{}TENANT", ENV_PREFIX)).ok()).or(config.tenant.clone());
+        if let Some(tenant) = tenant {
+            config.apply_tenant(&tenant)?;
+        }
+        config.apply_env(std::env::vars())?;
+        Ok(config)
+    }
+
+    /// Selects the tenant: applies its `[tenants.<id>]` overrides (if any) and replaces `{tenant}` in `storage` and `output.path`.
+    pub fn apply_tenant(&mut self, tenant: &str) -> anyhow::Result<()> {
+        validate_tenant(tenant)?;
+        if let Some(overrides) = self.tenants.get(tenant).cloned() {
+            self.storage = overrides.storage.unwrap_or(self.storage.clone());
+            self.engine = overrides.engine.unwrap_or(self.engine.clone());
+            self.output = overrides.output.unwrap_or(self.output.clone());
+        }
+        self.storage = self.storage.replace("{tenant}", tenant);
+        self.output.path = self.output.path.as_ref().map(|x| x.replace("{tenant}", tenant));
+        self.tenant = Some(tenant.to_string());
+        Ok(())
+    }
+
+    /// Overrides the settings with the `TRANSACTIONS_ENGINE_*` variables among `vars`: `STORAGE`, `REDACT_PII`, `DISPUTE_WINDOW_SECS`,
+    /// `LOCK_ON_CHARGEBACK`, `DECIMALS`, `ROUNDING`, `MAX_AMOUNT`, `MAX_BALANCE`,
+    /// `WITHDRAWAL_FEE`, `WRITE_OFF_CHARGEBACK_LOSSES`, `OUTPUT`, `OUTPUT_FORMAT`, `OUTPUT_SORTED`, `OUTPUT_SYSTEM_ACCOUNTS`, `HTTP_LISTEN`, `GRPC_LISTEN` and `TCP_LISTEN`.
+    /// Other variables are ignored, so the whole environment can be passed.
+    pub fn apply_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> anyhow::Result<()> {
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            match key {
+                "STORAGE" => self.storage = value,
+                "REDACT_PII" => self.redact_pii = parse_env(&name, &value)?,
+                "DISPUTE_WINDOW_SECS" => self.engine.dispute_window_secs = Some(parse_env(&name, &value)?),
+                "LOCK_ON_CHARGEBACK" => self.engine.lock_on_chargeback = parse_env(&name, &value)?,
+                "DECIMALS" => self.engine.decimals = parse_env(&name, &value)?,
+                "ROUNDING" => self.engine.rounding = value,
+                "MAX_AMOUNT" => self.engine.max_amount = Some(parse_env(&name, &value)?),
+                "MAX_BALANCE" => self.engine.max_balance = Some(parse_env(&name, &value)?),
+                "WITHDRAWAL_FEE" => self.engine.withdrawal_fee = Some(parse_env(&name, &value)?),
+                "WRITE_OFF_CHARGEBACK_LOSSES" => self.engine.write_off_chargeback_losses = parse_env(&name, &value)?,
+                "NEGATIVE_AVAILABLE" => self.engine.negative_available = value,
+                "AUTO_UNLOCK" => self.engine.auto_unlock = parse_env(&name, &value)?,
+                "AUTHORIZATION_EXPIRY_SECS" => self.engine.authorization_expiry_secs = Some(parse_env(&name, &value)?),
+                "CACHE_REJECTIONS" => self.engine.cache_rejections = parse_env(&name, &value)?,
+                "OUTPUT" => self.output.path = Some(value),
+                "OUTPUT_FORMAT" => self.output.format = value,
+                "OUTPUT_SORTED" => self.output.sorted = parse_env(&name, &value)?,
+                "OUTPUT_SYSTEM_ACCOUNTS" => self.output.system_accounts = parse_env(&name, &value)?,
+                "HTTP_LISTEN" => self.server.http_listen = value,
+                "GRPC_LISTEN" => self.server.grpc_listen = value,
+                "TCP_LISTEN" => self.server.tcp_listen = value,
+                "CONFIG" => {} // NOTE: the path of the config file itself, read by the binary
+                "TENANT" => {} // NOTE: applied by `load` together with the overrides of the tenant
+                _ => bail!("unknown environment variable {}", name),
+            }
+        }
+        self.output.format()?;
+        self.engine.policy()?;
+        Ok(())
+    }
+}
+
+/// The tenant ids end up in the storage keys, see `TenantStorage`.
+fn validate_tenant(tenant: &str) -> anyhow::Result<()> {
+    if tenant.is_empty() || tenant.contains(':') {
+        bail!("invalid tenant id {:?}, it must be non-empty and must not contain ':'", tenant);
+    }
+    Ok(())
+}
+
+fn parse_env<T: std::str::FromStr>(name: &str, value: &str) -> anyhow::Result<T> {
+    match value.parse() {
+        Ok(value) => Ok(value),
+        Err(_) => bail!("invalid value of {}: {}", name, value),
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    #[test]
+    fn toml_sections() {
+        let config = EngineConfig::from_toml(r#"
+            storage = "file:engine.log"
+            redact_pii = true
+
+            [engine]
+            dispute_window_secs = 60
+            lock_on_chargeback = false
+            decimals = 2
+            rounding = "bankers"
+            max_amount = "1000"
+            withdrawal_fee = "0.5"
+            negative_available = "reject"
+            authorization_expiry_secs = 3600
+
+            [server]
+            tcp_listen = "0.0.0.0:7070"
+        "#).unwrap();
+        assert_eq!(config.storage, "file:engine.log");
+        assert!(config.redact_pii);
+        assert_eq!(config.engine.policy().unwrap(), EnginePolicy {
+            dispute_window: Some(Duration::from_secs(60)),
+            lock_on_chargeback: false,
+            rounding: Rounding::bankers(2),
+            max_amount: Some(Decimal4::from(1000)),
+            max_balance: None,
+            withdrawal_fee: Some("0.5".parse().unwrap()),
+            write_off_chargeback_losses: false,
+            negative_available: NegativeAvailablePolicy::Reject,
+            auto_unlock: false,
+            authorization_expiry: Some(Duration::from_secs(3600)),
+            cache_rejections: false,
+        });
+        assert_eq!(config.output, OutputConfig::default());
+        assert_eq!(config.server.tcp_listen, "0.0.0.0:7070");
+        assert_eq!(config.server.http_listen, "127.0.0.1:8080");
+
+        assert!(EngineConfig::from_toml("[engine]\ndispute_window = 60").is_err());
+        assert!(EngineConfig::from_toml("[engine]\ndecimals = 6").is_err());
+        assert!(EngineConfig::from_toml("[engine]\nrounding = \"up\"").is_err());
+        assert!(EngineConfig::from_toml("[engine]\nnegative_available = \"ignore\"").is_err());
+        assert_eq!(EngineConfig::from_toml("").unwrap(), EngineConfig::default());
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let mut config = EngineConfig::from_toml("storage = \"file:engine.log\"\n[output]\nformat = \"json\"").unwrap();
+        let vars = [
+            ("PATH", "/usr/bin"),
+            ("TRANSACTIONS_ENGINE_STORAGE", "memory"),
+            ("TRANSACTIONS_ENGINE_DISPUTE_WINDOW_SECS", "3600"),
+            ("TRANSACTIONS_ENGINE_OUTPUT_SORTED", "false"),
+        ];
+        config.apply_env(vars.map(|(k, v)| (k.to_string(), v.to_string()))).unwrap();
+        assert_eq!(config.storage, "memory");
+        assert_eq!(config.engine.dispute_window_secs, Some(3600));
+        assert_eq!(config.output, OutputConfig { path: None, format: "json".to_string(), sorted: false, system_accounts: false });
+
+        let invalid = [("TRANSACTIONS_ENGINE_LOCK_ON_CHARGEBACK".to_string(), "maybe".to_string())];
+        assert!(config.apply_env(invalid).is_err());
+        let unknown = [("TRANSACTIONS_ENGINE_STORGE".to_string(), "memory".to_string())];
+        assert!(config.apply_env(unknown).is_err());
+    }
+
+    #[test]
+    fn tenant_overrides() {
+        let text = r#"
+            storage = "file:{tenant}.log"
+
+            [engine]
+            decimals = 2
+
+            [output]
+            path = "out/{tenant}/accounts.csv"
+
+            [tenants.acme]
+            storage = "memory"
+
+            [tenants.acme.engine]
+            lock_on_chargeback = false
+        "#;
+        let mut config = EngineConfig::from_toml(text).unwrap();
+        config.apply_tenant("acme").unwrap();
+        assert_eq!((config.tenant.as_deref(), config.storage.as_str()), (Some("acme"), "memory"));
+        assert_eq!(config.engine, PolicyConfig { lock_on_chargeback: false, ..PolicyConfig::default() });
+        assert_eq!(config.output.path.as_deref(), Some("out/acme/accounts.csv"));
+
+        let mut config = EngineConfig::from_toml(text).unwrap();
+        config.apply_tenant("globex").unwrap();
+        assert_eq!((config.storage.as_str(), config.engine.decimals), ("file:globex.log", 2));
+        assert!(config.apply_tenant("a:b").is_err());
+        assert!(EngineConfig::from_toml("[tenants.acme.engine]\ndecimals = 6").is_err());
+    }
+}
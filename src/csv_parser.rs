@@ -1,76 +1,249 @@
-use std::io;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Context;
+use anyhow::{bail, Context};
+use flate2::read::MultiGzDecoder;
+use futures::channel::mpsc::{self, Receiver};
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::account::Account;
-use crate::decimal::Decimal4;
-use crate::engine::{Engine, Operation};
-use crate::storage::EchoDbStorage;
+use crate::account::{Account, AccountRef, ClientId, SystemAccount};
+use crate::clock::Instant;
+use crate::decimal::{unrounded, AmountFormat, AmountOverflowError, Rounding};
+use crate::engine::{Engine, EngineError, ExecuteOptions, Operation};
+use crate::journal::{Journal, OperationSource, Provenance};
+use crate::redact;
+use crate::runtime::{self, JoinHandle};
+use crate::storage::Storage;
+use crate::transaction::{Transaction, TransactionState, TransactionType, TxId};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CsvOperation {
     #[serde(rename = "type")]
     op_type: Option<String>,
-    client: Option<u16>,
-    tx: Option<u32>,
-    amount: Option<Decimal4>,
+    client: Option<ClientId>,
+    tx: Option<TxId>,
+    #[serde(default, with = "unrounded::option")]
+    amount: Option<Decimal>,
+    /// The optional external id of a deposit or withdrawal, or the reference to the disputed transaction when `tx` is empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    external_id: Option<String>,
+    /// The escrow bucket of an escrow or a release.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bucket: Option<String>,
 }
 
-impl TryInto<Operation> for CsvOperation {
-    type Error = CsvParseError;
+impl CsvOperation {
+    #[cfg(feature = "parquet")]
+    pub(crate) fn new(op_type: Option<String>, client: Option<ClientId>, tx: Option<TxId>, amount: Option<Decimal>) -> Self {
+        Self { op_type, client, tx, amount, external_id: None, bucket: None }
+    }
+}
 
-    fn try_into(self) -> Result<Operation, Self::Error> {
+impl CsvOperation {
+    /// Maps the row to an operation, the `type` value is looked up in `types`.
+    pub fn into_operation(self, types: &OperationTypes) -> Result<Operation, CsvParseError> {
         let op_type = self.op_type.ok_or(CsvParseError::MissingField("type".to_string()))?;
         let client = self.client.ok_or(CsvParseError::MissingField("client".to_string()))?;
         let tx = self.tx.ok_or(CsvParseError::MissingField("tx".to_string()))?;
         let maybe_amount = self.amount;
 
-        if (op_type == "deposit" || op_type == "withdraw") && maybe_amount.is_none() {
-            return Err(CsvParseError::MissingField("amount".to_string()));
-        }
+        let op_type = types.get(&op_type).ok_or(CsvParseError::InvalidType)?;
+        let amount = match (op_type.requires_amount(), maybe_amount) {
+            (true, None) => return Err(CsvParseError::MissingField("amount".to_string())),
+            (_, Some(amount)) if amount < Decimal::ZERO => return Err(CsvParseError::NegativeAmount),
+            (_, amount) => amount.unwrap_or_default(),
+        };
 
-        if let Some(amount) = maybe_amount {
-            if amount < Decimal4::zero() {
-                return Err(CsvParseError::NegativeAmount);
-            }
-        }
+        let operation = match op_type {
+            OperationType::Deposit => Operation::Deposit { acc_id: client, tx_id: tx, amount },
+            OperationType::Withdrawal => Operation::Withdraw { acc_id: client, tx_id: tx, amount },
+            OperationType::Dispute => Operation::Dispute { acc_id: client, tx_id: tx },
+            OperationType::Resolve => Operation::Resolve { acc_id: client, tx_id: tx },
+            OperationType::Chargeback => Operation::Chargeback { acc_id: client, tx_id: tx },
+            OperationType::Escrow => Operation::Escrow { acc_id: client, tx_id: tx, bucket: self.bucket.ok_or(CsvParseError::MissingField("bucket".to_string()))?, amount },
+            OperationType::Release => Operation::ReleaseEscrow { acc_id: client, tx_id: tx, bucket: self.bucket.ok_or(CsvParseError::MissingField("bucket".to_string()))?, amount },
+            OperationType::Authorize => Operation::Authorize { acc_id: client, tx_id: tx, amount },
+            OperationType::Capture => Operation::Capture { acc_id: client, tx_id: tx },
+            OperationType::Expire => Operation::Expire { acc_id: client, tx_id: tx },
+        };
 
-        let op_type = match op_type.as_str() {
-            "deposit" => Operation::Deposit { acc_id: client, tx_id: tx, amount: maybe_amount.unwrap() },
-            "withdrawal" => Operation::Withdraw { acc_id: client, tx_id: tx, amount: maybe_amount.unwrap() },
-            "dispute" => Operation::Dispute { acc_id: client, tx_id: tx },
-            "resolve" => Operation::Resolve { acc_id: client, tx_id: tx },
-            "chargeback" => Operation::Chargeback { acc_id: client, tx_id: tx },
-            _ => return Err(CsvParseError::InvalidType),
+        Ok(operation)
+    }
+}
+
+impl From<&Operation> for CsvOperation {
+    fn from(value: &Operation) -> Self {
+        let (client, tx, amount, bucket) = match value {
+            &Operation::Deposit { acc_id, tx_id, amount } | &Operation::Withdraw { acc_id, tx_id, amount } | &Operation::Authorize { acc_id, tx_id, amount } => (acc_id, tx_id, Some(amount), None),
+            &Operation::Dispute { acc_id, tx_id } | &Operation::Resolve { acc_id, tx_id } | &Operation::Chargeback { acc_id, tx_id } => (acc_id, tx_id, None, None),
+            Operation::Escrow { acc_id, tx_id, bucket, amount } | Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount } => (*acc_id, *tx_id, Some(*amount), Some(bucket.clone())),
+            &Operation::Capture { acc_id, tx_id } | &Operation::Expire { acc_id, tx_id } => (acc_id, tx_id, None, None),
         };
+        Self { op_type: Some(value.name().to_string()), client: Some(client), tx: Some(tx), amount, external_id: None, bucket }
+    }
+}
+
+impl TryInto<Operation> for CsvOperation {
+    type Error = CsvParseError;
+
+    fn try_into(self) -> Result<Operation, Self::Error> {
+        self.into_operation(&OperationTypes::default())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Escrow,
+    Release,
+    Authorize,
+    Capture,
+    Expire,
+}
+
+impl OperationType {
+    pub fn requires_amount(&self) -> bool {
+        matches!(self, OperationType::Deposit | OperationType::Withdrawal | OperationType::Escrow | OperationType::Release | OperationType::Authorize)
+    }
+}
+
+/// Parses the names of the default [`OperationTypes`] table, so both accept the same values.
+impl FromStr for OperationType {
+    type Err = CsvParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OperationTypes::default().get(s).ok_or(CsvParseError::InvalidType)
+    }
+}
+
+/// Table of the accepted `type` values. The lookup ignores the case and the surrounding whitespace.
+/// By default it has the canonical names and `withdraw` as an alias of `withdrawal`.
+#[derive(Debug, Clone)]
+pub struct OperationTypes {
+    names: HashMap<String, OperationType>,
+}
+
+impl Default for OperationTypes {
+    fn default() -> Self {
+        let names = [
+            ("deposit", OperationType::Deposit),
+            ("withdrawal", OperationType::Withdrawal),
+            ("withdraw", OperationType::Withdrawal),
+            ("dispute", OperationType::Dispute),
+            ("resolve", OperationType::Resolve),
+            ("chargeback", OperationType::Chargeback),
+            ("escrow", OperationType::Escrow),
+            ("release", OperationType::Release),
+            ("authorize", OperationType::Authorize),
+            ("capture", OperationType::Capture),
+            ("expire", OperationType::Expire),
+        ];
+        Self { names: names.into_iter().map(|(name, op_type)| (name.to_string(), op_type)).collect() }
+    }
+}
+
+impl OperationTypes {
+    pub fn with_alias(mut self, name: &str, op_type: OperationType) -> Self {
+        self.names.insert(name.trim().to_lowercase(), op_type);
+        self
+    }
 
-        Ok(op_type)
+    pub fn get(&self, name: &str) -> Option<OperationType> {
+        let name = name.trim();
+        self.names.get(name).or_else(|| self.names.get(&name.to_lowercase())).copied()
     }
 }
 
+/// An account of the summary, the amounts are formatted with the decimal places of the engine, see [`Rounding`].
 #[derive(Debug, Clone, Serialize)]
 pub struct CsvAccount {
-    client: u16,
-    available: Decimal4,
-    held: Decimal4,
-    total: Decimal4,
+    client: AccountRef,
+    available: String,
+    held: String,
+    total: String,
     locked: bool,
 }
 
-impl From<Account> for CsvAccount {
-    fn from(value: Account) -> Self {
-        Self {
-            client: value.id(),
-            available: value.available(),
-            held: value.held(),
-            total: value.total(),
+impl CsvAccount {
+    /// Fails when the total balance of the account does not fit into a `Decimal4`.
+    pub fn new(client: AccountRef, value: Account, rounding: Rounding) -> Result<Self, AmountOverflowError> {
+        Ok(Self {
+            client,
+            available: value.available().to_string_with_decimals(rounding.decimals),
+            held: value.held().to_string_with_decimals(rounding.decimals),
+            total: value.total()?.to_string_with_decimals(rounding.decimals),
             locked: value.locked(),
+        })
+    }
+}
+
+impl TryFrom<Account> for CsvAccount {
+    type Error = AmountOverflowError;
+
+    fn try_from(value: Account) -> Result<Self, Self::Error> {
+        Self::new(value.id().into(), value, Rounding::default())
+    }
+}
+
+/// A stored transaction with its final state, written by [`write_transactions`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CsvTransaction {
+    tx: TxId,
+    client: ClientId,
+    #[serde(rename = "type")]
+    tx_type: &'static str,
+    amount: String,
+    state: &'static str,
+    created_at: u64,
+}
+
+impl CsvTransaction {
+    /// The amount is formatted with the decimal places of `rounding`.
+    pub fn new(value: Transaction, rounding: Rounding) -> Self {
+        Self {
+            tx: value.id(),
+            client: value.account_id(),
+            tx_type: match value.tx_type() {
+                TransactionType::Deposit => "deposit",
+                TransactionType::Withdrawal => "withdrawal",
+                TransactionType::Escrow => "escrow",
+                TransactionType::EscrowRelease => "release",
+                TransactionType::Authorization => "authorize",
+            },
+            amount: value.amount().to_string_with_decimals(rounding.decimals),
+            state: match value.state() {
+                TransactionState::Posted => "posted",
+                TransactionState::Disputed => "disputed",
+                TransactionState::Chargeback => "chargeback",
+                TransactionState::Captured => "captured",
+                TransactionState::Expired => "expired",
+            },
+            created_at: value.created_at(),
         }
     }
 }
 
+impl From<Transaction> for CsvTransaction {
+    fn from(value: Transaction) -> Self {
+        Self::new(value, Rounding::default())
+    }
+}
+
 #[derive(Debug, Error, PartialEq)]
 pub enum CsvParseError {
     #[error("missing field: {0}")]
@@ -83,51 +256,967 @@ pub enum CsvParseError {
     NegativeAmount,
 }
 
-pub async fn read_csv(filepath: &String, engine: &mut Engine<EchoDbStorage>) -> anyhow::Result<u64> {
-    let mut csv_reader = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .from_path(filepath)
-        .context("error reading csv file")?;
+/// The code of a row that is not a valid CSV record at all (wrong number of fields, not a number, etc.).
+pub const INVALID_RECORD_CODE: u16 = 200;
 
-    let mut counter = 0;
+impl CsvParseError {
+    /// A stable numeric code of the error (2xx), in the same space as [`EngineError::code`](crate::engine::EngineError::code).
+    pub fn code(&self) -> u16 {
+        match self {
+            CsvParseError::MissingField(_) => 201,
+            CsvParseError::InvalidType => 202,
+            CsvParseError::NegativeAmount => 203,
+        }
+    }
+}
 
-    for deserialize_result in csv_reader.deserialize() {
-        if deserialize_result.is_err() {
-            // eprintln!("csv error: {:?}", deserialize_result.err());
-            continue;
+/// A row that could not be applied, reported by the strict mode and by [`CsvReader::validate`]. The `code` is the
+/// one of the [`CsvParseError`] or [`EngineError`](crate::engine::EngineError), or [`INVALID_RECORD_CODE`].
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[error("line {line}: [{code}] {reason} (row: `{raw}`)")]
+pub struct CsvRowError {
+    pub line: u64,
+    pub raw: String,
+    pub code: u16,
+    pub reason: String,
+}
+
+/// Statistics of an ingestion run, passed to the progress callback during the run and returned at the end.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    /// Rows read from the input (rows skipped by a resume are not counted).
+    pub processed: u64,
+    pub applied: u64,
+    /// Rows that could not be parsed or were rejected by the engine.
+    pub skipped: u64,
+    /// Applied rows per operation type.
+    pub by_operation: BTreeMap<&'static str, u64>,
+    /// Skipped rows per error kind.
+    pub by_error: BTreeMap<String, u64>,
+    pub elapsed: Duration,
+}
+
+impl IngestStats {
+    pub fn rows_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 { self.processed as f64 / secs } else { 0.0 }
+    }
+}
+
+impl Display for IngestStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "processed {} rows in {:.3}s ({:.0} rows/sec): {} applied, {} skipped",
+            self.processed, self.elapsed.as_secs_f64(), self.rows_per_sec(), self.applied, self.skipped,
+        )?;
+        for (op_type, count) in self.by_operation.iter() {
+            write!(f, "\n  {}: {}", op_type, count)?;
         }
-        let csv_operation: CsvOperation = deserialize_result.unwrap();
-        let parse_result: Result<Operation, CsvParseError> = csv_operation.try_into();
-        if parse_result.is_err() {
-            // eprintln!("parse error: {:?}", parse_result.err());
-            continue;
+        for (kind, count) in self.by_error.iter() {
+            write!(f, "\n  error `{}`: {}", kind, count)?;
+        }
+        Ok(())
+    }
+}
+
+type ProgressCallback = Arc<dyn Fn(&IngestStats) + Send + Sync>;
+
+/// Reader of the CSV input. By default the rows that can not be parsed or are rejected by the engine are skipped,
+/// in the strict mode the first such row aborts the reading with a [`CsvRowError`].
+#[derive(Clone, Default)]
+pub struct CsvReader {
+    strict: bool,
+    resume: bool,
+    progress: Option<(Duration, ProgressCallback)>,
+    dialect: Dialect,
+    types: OperationTypes,
+}
+
+/// How the upstream CSV is laid out.
+#[derive(Debug, Clone)]
+struct Dialect {
+    delimiter: u8,
+    /// Header name in the input -> one of the expected columns (`type`, `client`, `tx`, `amount`).
+    aliases: HashMap<String, String>,
+    amount_format: AmountFormat,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self { delimiter: b',', aliases: HashMap::new(), amount_format: AmountFormat::Plain }
+    }
+}
+
+impl CsvReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts `name` (in any case) as the `type` of the given operation, e.g. `with_type_alias("dep", OperationType::Deposit)`.
+    pub fn with_type_alias(mut self, name: &str, op_type: OperationType) -> Self {
+        self.types = self.types.with_alias(name, op_type);
+        self
+    }
+
+    /// Sets the notation of the amounts, e.g. [`AmountFormat::European`] for `1.234,56`.
+    pub fn with_amount_format(mut self, amount_format: AmountFormat) -> Self {
+        self.dialect.amount_format = amount_format;
+        self
+    }
+
+    /// Sets the field delimiter, e.g. `b';'` or `b'\t'` (`,` by default).
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.dialect.delimiter = delimiter;
+        self
+    }
+
+    /// Reads the input column `header` as one of the expected columns (`type`, `client`, `tx`, `amount`),
+    /// e.g. `with_column_alias("client_id", "client")`.
+    pub fn with_column_alias(mut self, header: impl Into<String>, column: impl Into<String>) -> Self {
+        self.dialect.aliases.insert(header.into(), column.into());
+        self
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Saves the number of applied rows of every file as a checkpoint in the storage, together with each operation,
+    /// and skips the rows already applied by a previous (e.g. crashed or interrupted) run of the same file.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Calls `callback` with the statistics so far at most once per `interval` during the run.
+    pub fn with_progress(mut self, interval: Duration, callback: impl Fn(&IngestStats) + Send + Sync + 'static) -> Self {
+        self.progress = Some((interval, Arc::new(callback)));
+        self
+    }
+
+    /// Reads the operations from the CSV file at `filepath`, or from stdin if the path is `-`.
+    pub async fn read<TStorage: Storage + Journal>(&self, filepath: impl AsRef<Path>, engine: &mut Engine<TStorage>) -> anyhow::Result<IngestStats> {
+        let mut tracker = Tracker::new(self);
+        self.read_path(filepath.as_ref(), &mut tracker, engine).await?;
+        Ok(tracker.finish())
+    }
+
+    /// Reads and executes the operations from the CSV data, which may be gzip or zstd compressed. The blocking reads,
+    /// the decompression and the parsing run on a separate blocking thread feeding a bounded channel,
+    /// so a huge input never blocks the async runtime.
+    pub async fn read_from<R: Read + Send + 'static, TStorage: Storage + Journal>(&self, reader: R, engine: &mut Engine<TStorage>) -> anyhow::Result<IngestStats> {
+        let mut tracker = Tracker::new(self);
+        self.read_source(reader, "-", None, 0, &mut tracker, engine).await?;
+        Ok(tracker.finish())
+    }
+
+    /// Reads and executes the operations from several CSV files as one logical stream, in the given order.
+    pub async fn read_files<TStorage: Storage + Journal>(&self, paths: &[PathBuf], merge_by: MergeBy, engine: &mut Engine<TStorage>) -> anyhow::Result<IngestStats> {
+        let mut tracker = Tracker::new(self);
+        if merge_by == MergeBy::File {
+            for path in paths {
+                self.read_path(path, &mut tracker, engine).await?;
+            }
+            return Ok(tracker.finish());
         }
 
-        let operation = parse_result.unwrap();
-        let execution_result = engine.execute_operation(operation).await;
-        if execution_result.is_err() {
-            // eprintln!("execution error: {:?}", execution_result.err());
+        let mut sources = Vec::with_capacity(paths.len());
+        let mut readers = Vec::with_capacity(paths.len());
+        for path in paths {
+            let (source, skip) = self.checkpoint(path, engine).await?;
+            sources.push(source);
+            readers.push(spawn_reader(open_input(path)?, skip, self.dialect.clone()));
+        }
+        let mut heads = Vec::with_capacity(readers.len());
+        for (receiver, _) in readers.iter_mut() {
+            heads.push(receiver.next().await);
+        }
+
+        // NOTE: a linear scan over the heads, the number of files is small
+        let tx_id = |row: &CsvRow| row.operation.as_ref().ok().and_then(|x| x.tx).unwrap_or(0);
+        while let Some(next) = (0..heads.len()).filter(|x| heads[*x].is_some()).min_by_key(|x| heads[*x].as_ref().map(tx_id)) {
+            let row = heads[next].take().expect("filtered by is_some");
+            heads[next] = readers[next].0.next().await;
+            let name = paths[next].display().to_string();
+            self.execute_row(engine, row, &name, sources[next].as_deref(), &mut tracker).await
+                .with_context(|| format!("error processing {}", paths[next].display()))?;
+        }
+
+        for (_, reader_task) in readers {
+            reader_task.await.context("error reading csv")?.context("error decompressing csv")?;
+        }
+        Ok(tracker.finish())
+    }
+
+    /// Reads the CSV file (or stdin for `-`) like [`CsvReader::read`], but returns every row that can not be parsed
+    /// or is rejected by the engine instead of skipping it or stopping at the first one. The rows are applied to `engine`,
+    /// so for a dry run it should be a scratch engine, e.g. over an in-memory copy of the real state.
+    pub async fn validate<TStorage: Storage + Journal>(&self, filepath: impl AsRef<Path>, engine: &mut Engine<TStorage>) -> anyhow::Result<Vec<CsvRowError>> {
+        let path = filepath.as_ref();
+        self.validate_from(open_input(path)?, engine).await.with_context(|| format!("error validating {}", path.display()))
+    }
+
+    /// Same as [`CsvReader::validate`] for CSV data, which may be gzip or zstd compressed.
+    pub async fn validate_from<R: Read + Send + 'static, TStorage: Storage + Journal>(&self, reader: R, engine: &mut Engine<TStorage>) -> anyhow::Result<Vec<CsvRowError>> {
+        let (mut receiver, reader_task) = spawn_reader(reader, 0, self.dialect.clone());
+        let mut errors = Vec::new();
+        while let Some(row) = receiver.next().await {
+            if let Err(failure) = apply_row(engine, row.operation, &self.types, ExecuteOptions::default()).await {
+                errors.push(CsvRowError { line: row.line, raw: raw_row(row.record.as_ref()), code: failure.code, reason: failure.reason });
+            }
+        }
+        reader_task.await.context("error reading csv")?.context("error decompressing csv")?;
+        Ok(errors)
+    }
+
+    async fn read_path<TStorage: Storage + Journal>(&self, path: &Path, tracker: &mut Tracker, engine: &mut Engine<TStorage>) -> anyhow::Result<()> {
+        let (source, skip) = self.checkpoint(path, engine).await?;
+        self.read_source(open_input(path)?, &path.display().to_string(), source, skip, tracker, engine).await.with_context(|| format!("error processing {}", path.display()))
+    }
+
+    async fn read_source<R: Read + Send + 'static, TStorage: Storage + Journal>(&self, reader: R, name: &str, source: Option<String>, skip: u64, tracker: &mut Tracker, engine: &mut Engine<TStorage>) -> anyhow::Result<()> {
+        let (mut receiver, reader_task) = spawn_reader(reader, skip, self.dialect.clone());
+        while let Some(row) = receiver.next().await {
+            self.execute_row(engine, row, name, source.as_deref(), tracker).await?;
+        }
+        reader_task.await.context("error reading csv")?.context("error decompressing csv")?;
+        Ok(())
+    }
+
+    /// Returns the checkpoint source of the file and the number of its rows to skip, when resuming is enabled.
+    async fn checkpoint<TStorage: Storage + Journal>(&self, path: &Path, engine: &Engine<TStorage>) -> anyhow::Result<(Option<String>, u64)> {
+        if !self.resume {
+            return Ok((None, 0));
+        }
+        let source = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()).display().to_string();
+        let skip = engine.get_checkpoint(&source).await.context("error reading checkpoint")?.unwrap_or(0);
+        Ok((Some(source), skip))
+    }
+
+    /// Executes a parsed row of the file `name` and records the outcome, in the strict mode a failed row is an error.
+    async fn execute_row<TStorage: Storage + Journal>(&self, engine: &mut Engine<TStorage>, row: CsvRow, name: &str, source: Option<&str>, tracker: &mut Tracker) -> Result<(), CsvRowError> {
+        let provenance = Provenance::new(OperationSource::File { name: name.to_string(), line: row.line });
+        let options = ExecuteOptions { checkpoint: source.map(|x| (x, row.index)), provenance: Some(&provenance), ..ExecuteOptions::default() };
+        let outcome = apply_row(engine, row.operation, &self.types, options).await;
+        tracker.record(&outcome);
+        let Err(failure) = outcome else {
+            return Ok(());
+        };
+        if !self.strict {
+            tracing::info!(file = name, line = row.line, code = failure.code, reason = %redact::text(&failure.reason), "skipped row");
+            return Ok(());
+        }
+        Err(CsvRowError { line: row.line, raw: raw_row(row.record.as_ref()), code: failure.code, reason: failure.reason })
+    }
+}
+
+impl Debug for CsvReader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CsvReader")
+            .field("strict", &self.strict)
+            .field("resume", &self.resume)
+            .field("dialect", &self.dialect)
+            .field("types", &self.types)
+            .finish()
+    }
+}
+
+/// Collects the statistics of a run and reports the progress.
+struct Tracker {
+    stats: IngestStats,
+    started: Instant,
+    last_report: Instant,
+    progress: Option<(Duration, ProgressCallback)>,
+}
+
+impl Tracker {
+    fn new(reader: &CsvReader) -> Self {
+        let now = Instant::now();
+        Self { stats: IngestStats::default(), started: now, last_report: now, progress: reader.progress.clone() }
+    }
+
+    fn record(&mut self, outcome: &Result<&'static str, RowFailure>) {
+        self.stats.processed += 1;
+        match outcome {
+            Ok(op_type) => {
+                self.stats.applied += 1;
+                *self.stats.by_operation.entry(op_type).or_default() += 1;
+            }
+            Err(failure) => {
+                self.stats.skipped += 1;
+                *self.stats.by_error.entry(failure.kind.clone()).or_default() += 1;
+            }
+        }
+
+        if let Some((interval, callback)) = self.progress.as_ref() {
+            let now = Instant::now();
+            if now.duration_since(self.last_report) >= *interval {
+                self.last_report = now;
+                self.stats.elapsed = now.duration_since(self.started);
+                callback(&self.stats);
+            }
+        }
+    }
+
+    fn finish(mut self) -> IngestStats {
+        self.stats.elapsed = self.started.elapsed();
+        self.stats
+    }
+}
+
+/// Reads the operations from the CSV file at `filepath`, or from stdin if the path is `-`.
+pub async fn read_csv<TStorage: Storage + Journal>(filepath: impl AsRef<Path>, engine: &mut Engine<TStorage>) -> anyhow::Result<IngestStats> {
+    CsvReader::new().read(filepath, engine).await
+}
+
+pub async fn read_csv_from<R: Read + Send + 'static, TStorage: Storage + Journal>(reader: R, engine: &mut Engine<TStorage>) -> anyhow::Result<IngestStats> {
+    CsvReader::new().read_from(reader, engine).await
+}
+
+pub async fn read_csv_files<TStorage: Storage + Journal>(paths: &[PathBuf], merge_by: MergeBy, engine: &mut Engine<TStorage>) -> anyhow::Result<IngestStats> {
+    CsvReader::new().read_files(paths, merge_by, engine).await
+}
+
+fn open_input(path: &Path) -> anyhow::Result<Box<dyn Read + Send>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(io::stdin()));
+    }
+    let file = File::open(path).with_context(|| format!("error reading csv file {}", path.display()))?;
+    Ok(Box::new(file))
+}
+
+/// How the rows of several input files are combined into one stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeBy {
+    /// The files are processed one after another.
+    #[default]
+    File,
+    /// The rows of all the files are merged by tx id. The rows of each file keep their relative order,
+    /// so the files are expected to be sorted by tx id (disputes and resolutions naturally follow their deposits).
+    Tx,
+}
+
+impl FromStr for MergeBy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "file" => Ok(MergeBy::File),
+            "tx" => Ok(MergeBy::Tx),
+            _ => bail!("unknown merge order: {} (expected `file` or `tx`)", value),
+        }
+    }
+}
+
+/// Expands the glob patterns among the arguments and sorts all the input files by file name,
+/// which defines the processing order of the daily batch files.
+pub fn resolve_input_paths(args: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for arg in args {
+        if !arg.contains(['*', '?', '[']) {
+            paths.push(PathBuf::from(arg));
             continue;
         }
+        let matched: Vec<PathBuf> = glob::glob(arg).context("invalid glob pattern")?.collect::<Result<_, _>>()?;
+        if matched.is_empty() {
+            bail!("no input files match {}", arg);
+        }
+        paths.extend(matched);
+    }
+    paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b)));
+    paths.dedup();
+    Ok(paths)
+}
+
+/// Parsed rows buffered between the reader thread and the engine, bounds the memory used for any input size.
+const READ_AHEAD_ROWS: usize = 1024;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// A parsed row with its position, the raw record is kept for the error reports.
+struct CsvRow {
+    /// 1-based number of the row among the data rows of the input.
+    index: u64,
+    line: u64,
+    record: Option<csv::StringRecord>,
+    operation: Result<CsvOperation, csv::Error>,
+}
+
+/// The row as it appears in the error reports, empty if it could not be read at all.
+fn raw_row(record: Option<&csv::StringRecord>) -> String {
+    record.map(|x| x.iter().collect::<Vec<_>>().join(",")).unwrap_or_default()
+}
 
-        counter += 1;
+/// Wraps the reader into a decoder if the data starts with the gzip or zstd magic bytes.
+fn decompress<R: BufRead + Send + 'static>(mut reader: R) -> io::Result<Box<dyn Read + Send>> {
+    let head = reader.fill_buf()?;
+    if head.starts_with(GZIP_MAGIC) {
+        return Ok(Box::new(MultiGzDecoder::new(reader))); // NOTE: concatenated gzip members are common in day files
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if head.starts_with(ZSTD_MAGIC) {
+        return Ok(Box::new(zstd::Decoder::with_buffer(reader)?));
+    }
+    #[cfg(target_arch = "wasm32")]
+    if head.starts_with(ZSTD_MAGIC) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "zstd compressed data is not supported on wasm32"));
     }
+    Ok(Box::new(reader))
+}
 
-    Ok(counter)
+/// Starts a blocking thread that decompresses and parses the CSV data into a bounded channel.
+/// The first `skip` rows are dropped, they were applied by a previous run.
+fn spawn_reader<R: Read + Send + 'static>(reader: R, skip: u64, dialect: Dialect) -> (Receiver<CsvRow>, JoinHandle<io::Result<()>>) {
+    let (mut sender, receiver) = mpsc::channel(READ_AHEAD_ROWS);
+    let reader_task = runtime::spawn_blocking(move || -> io::Result<()> {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .delimiter(dialect.delimiter)
+            .from_reader(decompress(BufReader::new(reader))?);
+        let headers: csv::StringRecord = csv_reader.headers().cloned().unwrap_or_default().iter()
+            .map(|x| dialect.aliases.get(x).map_or(x, |x| x.as_str()))
+            .collect();
+        let amount_column = headers.iter().position(|x| x == "amount").filter(|_| dialect.amount_format != AmountFormat::Plain);
+        for (index, record) in (1..).zip(csv_reader.records()) {
+            if index <= skip {
+                continue;
+            }
+            let row = match record {
+                Ok(record) => CsvRow {
+                    index,
+                    line: record.position().map_or(0, |x| x.line()),
+                    operation: match amount_column {
+                        Some(column) => normalize_amount(&record, column, dialect.amount_format).deserialize(Some(&headers)),
+                        None => record.deserialize(Some(&headers)),
+                    },
+                    record: Some(record),
+                },
+                Err(err) => CsvRow {
+                    index,
+                    line: err.position().map_or(0, |x| x.line()),
+                    record: None,
+                    operation: Err(err),
+                },
+            };
+            if futures::executor::block_on(sender.send(row)).is_err() {
+                break; // NOTE: the receiving side is gone, nobody needs the rest of the input
+            }
+        }
+        Ok(())
+    });
+    (receiver, reader_task)
 }
 
-pub async fn write_csv(engine: &mut Engine<EchoDbStorage>) -> anyhow::Result<()> {
-    let all_accounts = engine.get_all_accounts().await
-        .context("error getting all accounts")?;
+/// Why a row was not applied: `kind` groups the failures in the statistics, `reason` is the full description.
+struct RowFailure {
+    kind: String,
+    code: u16,
+    reason: String,
+}
 
-    let mut writer = csv::Writer::from_writer(io::stdout());
+impl RowFailure {
+    fn new(stage: &str, code: u16, kind: String) -> Self {
+        Self { reason: format!("{} error: {}", stage, kind), code, kind }
+    }
+}
 
-    for account in all_accounts {
-        let csv_account: CsvAccount = account.into();
-        writer.serialize(csv_account).context("error writing csv")?;
+/// Rewrites the amount into the plain notation, an invalid amount is left as it is to fail the deserialization.
+fn normalize_amount(record: &csv::StringRecord, column: usize, amount_format: AmountFormat) -> csv::StringRecord {
+    let mut normalized: csv::StringRecord = record.iter().enumerate()
+        .map(|(i, x)| match i == column && !x.is_empty() {
+            true => amount_format.parse(x).map_or_else(|_| x.to_string(), |amount| amount.to_string()),
+            false => x.to_string(),
+        })
+        .collect();
+    normalized.set_position(record.position().cloned());
+    normalized
+}
+
+/// Executes a parsed row with the `options` of its position and returns the type of the applied operation.
+async fn apply_row<TStorage: Storage + Journal>(engine: &mut Engine<TStorage>, deserialize_result: Result<CsvOperation, csv::Error>, types: &OperationTypes, options: ExecuteOptions<'_>) -> Result<&'static str, RowFailure> {
+    let execution_failure = |e: EngineError| RowFailure::new("execution", e.code(), e.to_string());
+    let mut csv_operation: CsvOperation = deserialize_result.map_err(|e| RowFailure { kind: "invalid csv row".to_string(), code: INVALID_RECORD_CODE, reason: format!("csv error: {}", e) })?;
+    let external_id = csv_operation.external_id.take();
+    if let (None, Some(external_id)) = (csv_operation.tx, external_id.as_deref()) {
+        let tx = engine.get_tx_by_external_id(external_id).await.map_err(execution_failure)?;
+        csv_operation.tx = Some(tx.ok_or(EngineError::TransactionNotFound).map_err(execution_failure)?.id());
     }
+    let operation = csv_operation.into_operation(types).map_err(|e| RowFailure::new("parse", e.code(), e.to_string()))?;
+    let op_type = operation.name();
+    let options = ExecuteOptions { external_id: external_id.as_deref(), ..options };
+    engine.execute_operation_with(operation, options).await.map(|_| op_type).map_err(execution_failure)
+}
+
+/// Format of the account summary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// A single JSON array of the accounts.
+    Json,
+    /// One JSON object per line.
+    Jsonl,
+}
 
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => bail!("unknown output format: {}", s),
+        }
+    }
+}
+
+/// Writer of the account summary. By default the accounts are sorted by client id, so the output of two runs
+/// over the same input is identical whatever the storage iteration order is, and the [`SystemAccount`]s are left out.
+/// When included, they follow the client accounts, with their names in the `client` column.
+#[derive(Debug, Clone)]
+pub struct AccountsWriter {
+    format: OutputFormat,
+    sorted: bool,
+    system_accounts: bool,
+}
+
+impl Default for AccountsWriter {
+    fn default() -> Self {
+        Self { format: OutputFormat::Csv, sorted: true, system_accounts: false }
+    }
+}
+
+impl AccountsWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Unsorted output is streamed page by page in the storage order, sorted output collects all the accounts first.
+    pub fn with_sorted(mut self, sorted: bool) -> Self {
+        self.sorted = sorted;
+        self
+    }
+
+    pub fn with_system_accounts(mut self, system_accounts: bool) -> Self {
+        self.system_accounts = system_accounts;
+        self
+    }
+
+    pub async fn write<W: Write, TStorage: Storage + Journal>(&self, engine: &mut Engine<TStorage>, writer: W) -> anyhow::Result<()> {
+        const PAGE_SIZE: usize = 1000;
+        let mut sink = AccountSink::new(writer, self.format, engine.policy().rounding)?;
+
+        if self.sorted {
+            let mut accounts: Vec<Account> = engine.stream_accounts(PAGE_SIZE).try_collect().await.context("error getting accounts")?;
+            accounts.sort_by_key(|x| x.id());
+            for account in accounts {
+                sink.write(account.id().into(), account)?;
+            }
+        } else {
+            let mut accounts = pin!(engine.stream_accounts(PAGE_SIZE));
+            while let Some(account) = accounts.try_next().await.context("error getting accounts")? {
+                sink.write(account.id().into(), account)?;
+            }
+        }
+        if self.system_accounts {
+            for account in SystemAccount::ALL {
+                sink.write(account.into(), engine.get_system_account(account).await.context("error getting system accounts")?)?;
+            }
+        }
+
+        sink.finish()
+    }
+}
+
+/// Writes the account summary as CSV to stdout.
+pub async fn write_csv<TStorage: Storage + Journal>(engine: &mut Engine<TStorage>) -> anyhow::Result<()> {
+    write_accounts(engine, io::stdout(), OutputFormat::Csv).await
+}
+
+/// Writes the account summary, sorted by client id, to `writer` in the given format.
+pub async fn write_accounts<W: Write, TStorage: Storage + Journal>(engine: &mut Engine<TStorage>, writer: W, format: OutputFormat) -> anyhow::Result<()> {
+    AccountsWriter::new().with_format(format).write(engine, writer).await
+}
+
+/// Writes every stored transaction with its type, amount and final state as CSV, ordered by client id and then by tx id.
+/// The transactions are loaded page by page.
+pub async fn write_transactions<W: Write, TStorage: Storage + Journal>(engine: &mut Engine<TStorage>, writer: W) -> anyhow::Result<()> {
+    const PAGE_SIZE: usize = 1000;
+    let mut acc_ids: Vec<ClientId> = engine.stream_accounts(PAGE_SIZE).map_ok(|x| x.id()).try_collect().await.context("error getting accounts")?;
+    acc_ids.sort_unstable();
+
+    let rounding = engine.policy().rounding;
+    let mut writer = csv::Writer::from_writer(writer);
+    for acc_id in acc_ids {
+        let mut cursor = None;
+        loop {
+            let txs = engine.get_txs_by_account(acc_id, cursor, PAGE_SIZE).await.context("error getting transactions")?;
+            let Some(last) = txs.last() else { break };
+            cursor = Some(last.id());
+            for tx in txs {
+                writer.serialize(CsvTransaction::new(tx, rounding)).context("error writing csv")?;
+            }
+        }
+    }
     writer.flush().context("error flushing csv")?;
 
     Ok(())
 }
+
+/// Writes the operations in the CSV input format (`type,client,tx,amount`), so they can be read back with [`read_csv_from`].
+pub fn write_operations<W: Write>(writer: W, operations: impl IntoIterator<Item = impl Borrow<Operation>>) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for operation in operations {
+        writer.serialize(CsvOperation::from(operation.borrow())).context("error writing csv")?;
+    }
+    writer.flush().context("error flushing csv")?;
+
+    Ok(())
+}
+
+struct AccountSink<W: Write> {
+    output: AccountOutput<W>,
+    rounding: Rounding,
+}
+
+enum AccountOutput<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Json { writer: BufWriter<W>, lines: bool, first: bool },
+}
+
+impl<W: Write> AccountSink<W> {
+    fn new(writer: W, format: OutputFormat, rounding: Rounding) -> anyhow::Result<Self> {
+        let output = match format {
+            OutputFormat::Csv => AccountOutput::Csv(Box::new(csv::Writer::from_writer(writer))),
+            OutputFormat::Json => {
+                let mut writer = BufWriter::new(writer);
+                writer.write_all(b"[").context("error writing json")?;
+                AccountOutput::Json { writer, lines: false, first: true }
+            }
+            OutputFormat::Jsonl => AccountOutput::Json { writer: BufWriter::new(writer), lines: true, first: true },
+        };
+        Ok(Self { output, rounding })
+    }
+
+    fn write(&mut self, client: AccountRef, account: Account) -> anyhow::Result<()> {
+        let csv_account = CsvAccount::new(client, account, self.rounding).context("error writing the account total")?;
+        match &mut self.output {
+            AccountOutput::Csv(writer) => writer.serialize(csv_account).context("error writing csv"),
+            AccountOutput::Json { writer, lines, first } => {
+                if !*first {
+                    writer.write_all(if *lines { b"\n" } else { b"," }).context("error writing json")?;
+                }
+                *first = false;
+                serde_json::to_writer(writer, &csv_account).context("error writing json")
+            }
+        }
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        match self.output {
+            AccountOutput::Csv(mut writer) => writer.flush().context("error flushing csv"),
+            AccountOutput::Json { mut writer, lines, first } => {
+                let end: &[u8] = match (lines, first) {
+                    (false, _) => b"]\n",
+                    (true, true) => b"",
+                    (true, false) => b"\n",
+                };
+                writer.write_all(end).context("error writing json")?;
+                writer.flush().context("error flushing json")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod csv_parser_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::EnginePolicy;
+    use crate::file_storage::FileStorage;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn read_csv_from_reader() {
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 10.5\nwithdrawal, 1, 2, 0.5\nunknown, 1, 3, 1\n";
+        let mut engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(read_csv_from(data.as_bytes(), &mut engine).await.unwrap().applied, 2);
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(10));
+    }
+
+    #[tokio::test]
+    async fn read_csv_id_range() {
+        let data = format!("type, client, tx, amount\ndeposit, {}, {}, 1\ndeposit, 1, 18446744073709551616, 1\n", ClientId::MAX, TxId::MAX);
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let stats = read_csv_from(std::io::Cursor::new(data), &mut engine).await.unwrap();
+        assert_eq!((stats.applied, stats.skipped), (1, 1));
+        assert_eq!(engine.get_tx(TxId::MAX).await.unwrap().map(|x| x.account_id()), Some(ClientId::MAX));
+    }
+
+    #[tokio::test]
+    async fn read_external_ids() {
+        let data = "type, client, tx, amount, external_id\ndeposit, 1, 1, 10, a1\ndeposit, 1, 2, 5, a1\ndispute, 1, , , a1\ndispute, 1, , , b2\n";
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let stats = read_csv_from(data.as_bytes(), &mut engine).await.unwrap();
+        assert_eq!((stats.applied, stats.skipped), (2, 2));
+        assert_eq!(engine.get_tx(1).await.unwrap().unwrap().state(), TransactionState::Disputed);
+        assert_eq!(engine.get_tx(2).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn read_provenance() {
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 10\nwithdrawal, 1, 2, 50\ndispute, 1, 1,\n";
+        let mut engine = Engine::new(EchoDbStorage::new());
+        read_csv_from(data.as_bytes(), &mut engine).await.unwrap();
+        let sources: Vec<_> = engine.get_journal_entries(1, 10).await.unwrap().iter().map(|x| x.provenance().unwrap().source.clone()).collect();
+        assert_eq!(sources, vec![
+            OperationSource::File { name: "-".to_string(), line: 2 },
+            OperationSource::File { name: "-".to_string(), line: 4 },
+        ]);
+    }
+
+    #[tokio::test]
+    async fn ingest_stats() {
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 10\ndeposit, 1, 2, 5\nwithdrawal, 1, 3, 1\ndispute, 1, 9,\nunknown, 1, 4, 1\nbogus\n";
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let reports = Arc::new(std::sync::Mutex::new(0));
+        let reader = CsvReader::new().with_progress(Duration::ZERO, {
+            let reports = reports.clone();
+            move |_| *reports.lock().unwrap() += 1
+        });
+
+        let stats = reader.read_from(data.as_bytes(), &mut engine).await.unwrap();
+        assert_eq!((stats.processed, stats.applied, stats.skipped), (6, 3, 3));
+        assert_eq!(stats.by_operation, BTreeMap::from([("deposit", 2), ("withdrawal", 1)]));
+        assert_eq!(stats.by_error, BTreeMap::from([
+            ("invalid csv row".to_string(), 1),
+            ("invalid operation type".to_string(), 1),
+            ("transaction not found".to_string(), 1),
+        ]));
+        assert_eq!(*reports.lock().unwrap(), 6);
+    }
+
+    #[tokio::test]
+    async fn strict_mode_fails_on_first_bad_row() {
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 10\ndispute, 1, 9,\ndeposit, 1, 2, 5\n";
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let err = CsvReader::new().with_strict(true).read_from(data.as_bytes(), &mut engine).await.unwrap_err();
+        let err = err.downcast::<CsvRowError>().unwrap();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.raw, "dispute,1,9,");
+        assert!(err.reason.starts_with("execution error"), "{}", err.reason);
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(10));
+
+        let data = "type, client, tx, amount\ndeposit, 1, x, 10\n";
+        let err = CsvReader::new().with_strict(true).read_from(data.as_bytes(), &mut engine).await.unwrap_err();
+        assert_eq!(err.downcast::<CsvRowError>().unwrap().line, 2);
+    }
+
+    #[tokio::test]
+    async fn resume_from_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("transactions_engine_resume_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (csv, log) = (dir.join("input.csv"), dir.join("engine.log"));
+        std::fs::write(&csv, "type, client, tx, amount\ndeposit, 1, 1, 10\ndispute, 1, 1,\nresolve, 1, 1,\nbogus\n").unwrap();
+        let reader = CsvReader::new().with_resume(true);
+
+        let mut engine = Engine::new(FileStorage::open(&log).await.unwrap());
+        assert_eq!(reader.read(&csv, &mut engine).await.unwrap().applied, 3);
+        let source = std::fs::canonicalize(&csv).unwrap().display().to_string();
+        assert_eq!(engine.get_checkpoint(&source).await.unwrap(), Some(3)); // NOTE: the failed last row is not a checkpoint
+        drop(engine);
+
+        std::fs::write(&csv, "type, client, tx, amount\ndeposit, 1, 1, 10\ndispute, 1, 1,\nresolve, 1, 1,\nbogus\ndispute, 1, 1,\n").unwrap();
+        let mut engine = Engine::new(FileStorage::open(&log).await.unwrap());
+        assert_eq!(reader.read(&csv, &mut engine).await.unwrap().applied, 1);
+        assert_eq!(engine.get_checkpoint(&source).await.unwrap(), Some(5));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().held(), Decimal4::from(10));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn validate_reports_every_bad_row() {
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 10\nwithdrawal, 1, 2, 50\nbogus\ndispute, 1, 1,\nrefund, 1, 3, 1\ndispute, 1, 1,\n";
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let errors = CsvReader::new().validate_from(data.as_bytes(), &mut engine).await.unwrap();
+        assert_eq!(errors.iter().map(|x| (x.line, x.raw.as_str())).collect::<Vec<_>>(), vec![
+            (3, "withdrawal,1,2,50"),
+            (4, ""), // NOTE: a row with the wrong number of fields can not be read at all
+            (6, "refund,1,3,1"),
+            (7, "dispute,1,1,"),
+        ]);
+        assert_eq!((errors[0].code, errors[0].reason.as_str()), (104, "execution error: insufficient funds"));
+        assert_eq!(errors.iter().map(|x| x.code).collect::<Vec<_>>(), vec![104, INVALID_RECORD_CODE, 202, 109]);
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().held(), Decimal4::from(10));
+    }
+
+    #[tokio::test]
+    async fn write_accounts_formats() {
+        let mut engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 2, "1.5".parse().unwrap()).await, Ok(()));
+
+        let expected = [
+            (OutputFormat::Csv, "client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false\n2,1.5000,0.0000,1.5000,false\n"),
+            (OutputFormat::Json, "[{\"client\":1,\"available\":\"10.0000\",\"held\":\"0.0000\",\"total\":\"10.0000\",\"locked\":false},\
+            {\"client\":2,\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\",\"locked\":false}]\n"),
+            (OutputFormat::Jsonl, "{\"client\":1,\"available\":\"10.0000\",\"held\":\"0.0000\",\"total\":\"10.0000\",\"locked\":false}\n\
+            {\"client\":2,\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\",\"locked\":false}\n"),
+        ];
+        for (format, expected) in expected {
+            let mut output = Vec::new();
+            write_accounts(&mut engine, &mut output, format).await.unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected, "{:?}", format);
+        }
+    }
+
+    #[tokio::test]
+    async fn amounts_rounded_once_with_policy() {
+        let policy = EnginePolicy { rounding: Rounding::bankers(2), ..Default::default() };
+        let mut engine = Engine::new(EchoDbStorage::new()).with_policy(policy);
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 2.35499\ndeposit, 2, 2, 2.355\n";
+        assert_eq!(read_csv_from(data.as_bytes(), &mut engine).await.unwrap().applied, 2);
+        assert_eq!(engine.get_tx(1).await.unwrap().unwrap().amount(), "2.35".parse().unwrap());
+
+        let mut output = Vec::new();
+        write_accounts(&mut engine, &mut output, OutputFormat::Csv).await.unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "client,available,held,total,locked\n1,2.35,0.00,2.35,false\n2,2.36,0.00,2.36,false\n");
+    }
+
+    #[tokio::test]
+    async fn write_accounts_sorted() {
+        let mut engine = Engine::new(EchoDbStorage::new());
+        for acc_id in [10, 2, 1] {
+            assert_eq!(engine.deposit(acc_id, acc_id as TxId, Decimal4::from(1)).await, Ok(()));
+        }
+
+        let mut output = Vec::new();
+        AccountsWriter::new().write(&mut engine, &mut output).await.unwrap();
+        let clients: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().skip(1).map(|x| x.split(',').next().unwrap()).collect();
+        assert_eq!(clients, vec!["1", "2", "10"]);
+
+        let mut output = Vec::new();
+        AccountsWriter::new().with_sorted(false).write(&mut engine, &mut output).await.unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap().lines().count(), 4);
+    }
+
+    #[tokio::test]
+    async fn write_transactions_dump() {
+        let mut engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(2, 1, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(5)).await, Ok(()));
+        assert_eq!(engine.withdraw(2, 3, Decimal4::from(1)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 2).await, Ok(()));
+
+        let mut output = Vec::new();
+        write_transactions(&mut engine, &mut output).await.unwrap();
+        let rows: Vec<String> = std::str::from_utf8(&output).unwrap().lines()
+            .map(|x| x.rsplit_once(',').unwrap().0.to_string()) // NOTE: drops the created_at column
+            .collect();
+        assert_eq!(rows, vec![
+            "tx,client,type,amount,state",
+            "2,1,deposit,5.0000,disputed",
+            "1,2,deposit,10.0000,posted",
+            "3,2,withdrawal,1.0000,posted",
+        ]);
+    }
+
+    #[test]
+    fn write_operations_roundtrip() {
+        let operations = vec![
+            Operation::Deposit { acc_id: 1, tx_id: 1, amount: "2.5".parse().unwrap() },
+            Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(1) },
+            Operation::Dispute { acc_id: 1, tx_id: 1 },
+        ];
+        let mut output = Vec::new();
+        write_operations(&mut output, &operations).unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "type,client,tx,amount\ndeposit,1,1,2.5000\nwithdrawal,1,2,1.0000\ndispute,1,1,\n");
+
+        let mut reader = csv::Reader::from_reader(output.as_slice());
+        let parsed: Vec<Operation> = reader.deserialize::<CsvOperation>().map(|x| x.unwrap().try_into().unwrap()).collect();
+        assert_eq!(parsed, operations);
+    }
+
+    #[tokio::test]
+    async fn read_with_aliases_and_delimiter() {
+        let data = "type;client_id;transaction_id;amount\ndeposit;1;1;10\ndeposit; 2 ;2;3\n";
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let reader = CsvReader::new()
+            .with_delimiter(b';')
+            .with_column_alias("client_id", "client")
+            .with_column_alias("transaction_id", "tx");
+        assert_eq!(reader.read_from(data.as_bytes(), &mut engine).await.unwrap().applied, 2);
+        assert_eq!(engine.get_account(2).await.unwrap().unwrap().available(), Decimal4::from(3));
+    }
+
+    #[tokio::test]
+    async fn read_type_aliases() {
+        let data = "type, client, tx, amount\nDeposit, 1, 1, 10\nwithdraw, 1, 2, 1\nWITHDRAWAL, 1, 3, 1\ndep, 1, 4, 2\nwithdraw, 1, 5,\n";
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let reader = CsvReader::new().with_type_alias("DEP", OperationType::Deposit);
+        let stats = reader.read_from(data.as_bytes(), &mut engine).await.unwrap();
+        assert_eq!(stats.by_operation, BTreeMap::from([("deposit", 2), ("withdrawal", 2)]));
+        assert_eq!(stats.by_error, BTreeMap::from([("missing field: amount".to_string(), 1)]));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(10));
+        assert_eq!(" Withdraw".parse(), Ok(OperationType::Withdrawal));
+        assert_eq!("dep".parse::<OperationType>(), Err(CsvParseError::InvalidType));
+    }
+
+    #[tokio::test]
+    async fn read_european_amounts() {
+        let data = "type;client;tx;amount\ndeposit;1;1;1.234,56\nwithdrawal;1;2;0,5\ndeposit;1;3;1,2,3\n";
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let reader = CsvReader::new().with_delimiter(b';').with_amount_format(AmountFormat::European);
+        assert_eq!(reader.read_from(data.as_bytes(), &mut engine).await.unwrap().applied, 2);
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), "1234.06".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn read_compressed_csv() {
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 10\ndeposit, 2, 2, 5\n";
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        io::Write::write_all(&mut gzip, data.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let zstd = zstd::encode_all(data.as_bytes(), 0).unwrap();
+
+        for compressed in [gzip, zstd] {
+            let mut engine = Engine::new(EchoDbStorage::new());
+            assert_eq!(read_csv_from(io::Cursor::new(compressed), &mut engine).await.unwrap().applied, 2);
+            assert_eq!(engine.get_all_accounts().await.unwrap().len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn read_files_merged_by_tx() {
+        let dir = std::env::temp_dir().join(format!("transactions_engine_merge_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("day2.csv"), "type, client, tx, amount\nwithdrawal, 1, 2, 60\ndeposit, 1, 4, 10\n").unwrap();
+        std::fs::write(dir.join("day1.csv"), "type, client, tx, amount\ndeposit, 1, 1, 50\ndeposit, 1, 3, 20\n").unwrap();
+
+        let paths = resolve_input_paths(&[dir.join("day*.csv").display().to_string()]).unwrap();
+        assert_eq!(paths, vec![dir.join("day1.csv"), dir.join("day2.csv")]);
+
+        let mut engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(read_csv_files(&paths, MergeBy::File, &mut engine).await.unwrap().applied, 4);
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(20));
+
+        let mut engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(read_csv_files(&paths, MergeBy::Tx, &mut engine).await.unwrap().applied, 3); // the withdrawal comes before tx 3 now
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(80));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::{File, OpenOptions};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::now_millis;
+use crate::engine::{Engine, EngineError, Operation};
+use crate::journal::{Journal, Provenance};
+use crate::queue::{execute_with_retry, MessageOutcome, RetryPolicy};
+use crate::storage::{DbError, Storage};
+
+/// An operation of a queue consumer that failed permanently (the engine rejected it), kept for an inspection and a replay.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// Assigned by the store.
+    pub id: u64,
+    pub operation: Operation,
+    pub code: u16,
+    pub error: String,
+    pub provenance: Option<Provenance>,
+    /// When the operation failed, unix millis.
+    pub failed_at: u64,
+}
+
+impl DeadLetter {
+    pub fn new(operation: Operation, err: &EngineError, provenance: Option<Provenance>) -> Self {
+        Self { id: 0, operation, code: err.code(), error: err.to_string(), provenance, failed_at: now_millis() }
+    }
+}
+
+/// Where the consumers put the operations they give up on, see [`replay_dead_letters`].
+#[trait_variant::make(Send)]
+pub trait DeadLetterStore {
+    /// Stores the letter under a new id (the id of the letter is ignored) and returns the id.
+    async fn add(&self, letter: DeadLetter) -> Result<u64, DbError>;
+    /// All the letters, ordered by id.
+    async fn list(&self) -> Result<Vec<DeadLetter>, DbError>;
+    /// Returns whether the letter was in the store.
+    async fn remove(&self, id: u64) -> Result<bool, DbError>;
+}
+
+/// A [`DeadLetterStore`] kept in memory, the letters are lost when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryDeadLetterStore {
+    letters: Mutex<BTreeMap<u64, DeadLetter>>,
+}
+
+impl MemoryDeadLetterStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DeadLetterStore for MemoryDeadLetterStore {
+    async fn add(&self, letter: DeadLetter) -> Result<u64, DbError> {
+        let mut letters = self.letters.lock().unwrap();
+        Ok(insert_letter(&mut letters, letter))
+    }
+
+    async fn list(&self) -> Result<Vec<DeadLetter>, DbError> {
+        Ok(self.letters.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn remove(&self, id: u64) -> Result<bool, DbError> {
+        Ok(self.letters.lock().unwrap().remove(&id).is_some())
+    }
+}
+
+/// A [`DeadLetterStore`] persisted as a file with a JSON letter per line. The letters are appended (and fsync'd) as they
+/// come, a removal rewrites the file.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileDeadLetterStore {
+    path: PathBuf,
+    letters: Mutex<BTreeMap<u64, DeadLetter>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileDeadLetterStore {
+    /// Opens the file at the given path, creating it if missing.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DbError> {
+        let file = OpenOptions::new().read(true).create(true).append(true).open(path.as_ref())?;
+        let mut letters = BTreeMap::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let letter: DeadLetter = serde_json::from_str(&line).map_err(|e| DbError::DatabaseError(format!("invalid dead letter: {}", e)))?;
+            letters.insert(letter.id, letter);
+        }
+        Ok(Self { path: path.as_ref().to_path_buf(), letters: Mutex::new(letters) })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DeadLetterStore for FileDeadLetterStore {
+    async fn add(&self, letter: DeadLetter) -> Result<u64, DbError> {
+        let mut letters = self.letters.lock().unwrap();
+        let id = insert_letter(&mut letters, letter);
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        if let Err(err) = write_letter(&mut file, &letters[&id]).and_then(|_| file.sync_all()) {
+            letters.remove(&id);
+            return Err(err.into());
+        }
+        Ok(id)
+    }
+
+    async fn list(&self) -> Result<Vec<DeadLetter>, DbError> {
+        Ok(self.letters.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn remove(&self, id: u64) -> Result<bool, DbError> {
+        let mut letters = self.letters.lock().unwrap();
+        if !letters.contains_key(&id) {
+            return Ok(false);
+        }
+        // NOTE: written next to the file and renamed over it, so a crash leaves either the old or the new letters
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        for letter in letters.values().filter(|x| x.id != id) {
+            write_letter(&mut file, letter)?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        letters.remove(&id);
+        Ok(true)
+    }
+}
+
+fn insert_letter(letters: &mut BTreeMap<u64, DeadLetter>, mut letter: DeadLetter) -> u64 {
+    letter.id = letters.last_key_value().map_or(1, |(id, _)| id + 1);
+    let id = letter.id;
+    letters.insert(id, letter);
+    id
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_letter(file: &mut File, letter: &DeadLetter) -> std::io::Result<()> {
+    let line = serde_json::to_string(letter).expect("dead letter is always serializable");
+    writeln!(file, "{}", line)
+}
+
+/// Executes the dead letters again (the ones with the given ids, all of them when `ids` is empty) in the id order,
+/// with their original provenance, and removes the ones that are applied. The letters that fail again are kept as they are.
+///
+/// Replaying is meant for the operations rejected because of the state of the engine at that time, e.g. a dispute
+/// of a deposit that was received later, or a withdrawal from an account that was unlocked since. With
+/// `EnginePolicy::cache_rejections`, the deposits, withdrawals and escrow moves fail again with their recorded error.
+pub async fn replay_dead_letters<TStorage, D>(engine: &Engine<TStorage>, store: &D, ids: &[u64], retry: RetryPolicy) -> Result<Vec<(u64, MessageOutcome)>, DbError>
+    where TStorage: Storage + Journal,
+          D: DeadLetterStore + Sync
+{
+    let mut outcomes = Vec::new();
+    for letter in store.list().await?.into_iter().filter(|x| ids.is_empty() || ids.contains(&x.id)) {
+        let outcome = execute_with_retry(engine, letter.operation, letter.provenance.as_ref(), retry).await;
+        if let MessageOutcome::Applied(_) = outcome {
+            store.remove(letter.id).await?;
+        }
+        outcomes.push((letter.id, outcome));
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod dead_letter_tests {
+    use rust_decimal::Decimal;
+
+    use crate::journal::OperationSource;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn file_store_persists_letters() {
+        let path = std::env::temp_dir().join(format!("dead-letters-{}.jsonl", fastrand::u64(..)));
+        let provenance = Provenance::new(OperationSource::Queue { name: "operations".to_string(), sequence: 7 });
+        let dispute = Operation::Dispute { acc_id: 1, tx_id: 2 };
+        let withdrawal = Operation::Withdraw { acc_id: 1, tx_id: 3, amount: Decimal::from(5) };
+        {
+            let store = FileDeadLetterStore::open(&path).unwrap();
+            assert_eq!(store.add(DeadLetter::new(dispute.clone(), &EngineError::TransactionNotFound, Some(provenance.clone()))).await.unwrap(), 1);
+            assert_eq!(store.add(DeadLetter::new(withdrawal.clone(), &EngineError::InsufficientFunds, None)).await.unwrap(), 2);
+            assert!(store.remove(1).await.unwrap());
+            assert!(!store.remove(1).await.unwrap());
+        }
+
+        let store = FileDeadLetterStore::open(&path).unwrap();
+        let letters = store.list().await.unwrap();
+        assert_eq!(letters.iter().map(|x| (x.id, x.operation.clone(), x.code)).collect::<Vec<_>>(), vec![(2, withdrawal, 104)]);
+        assert_eq!(store.add(DeadLetter::new(dispute, &EngineError::TransactionNotFound, Some(provenance))).await.unwrap(), 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replay_removes_applied_letters() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let store = MemoryDeadLetterStore::new();
+        let dispute = Operation::Dispute { acc_id: 1, tx_id: 1 };
+        let withdrawal = Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(50) };
+        store.add(DeadLetter::new(dispute.clone(), &EngineError::TransactionNotFound, None)).await.unwrap();
+        store.add(DeadLetter::new(withdrawal.clone(), &EngineError::AccountNotFound, None)).await.unwrap();
+
+        engine.execute_operation(Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) }).await.unwrap();
+        let outcomes = replay_dead_letters(&engine, &store, &[], RetryPolicy::default()).await.unwrap();
+        assert_eq!(outcomes, vec![
+            (1, MessageOutcome::Applied(dispute)),
+            (2, MessageOutcome::Rejected(withdrawal, EngineError::InsufficientFunds)),
+        ]);
+        assert_eq!(store.list().await.unwrap().iter().map(|x| x.id).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(replay_dead_letters(&engine, &store, &[1], RetryPolicy::default()).await.unwrap(), vec![]);
+    }
+}
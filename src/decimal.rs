@@ -1,18 +1,28 @@
 use std::fmt;
 use std::fmt::Display;
-use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
 use std::str::FromStr;
 
 use rust_decimal::{Decimal, RoundingStrategy};
 use rust_decimal::prelude::{FromPrimitive, Zero};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
 
 /// A wrapper around [`rust_decimal::Decimal`] that serializes and deserializes with four decimal places.
+///
+/// A `Decimal4` is always rounded to four decimal places. The input amounts of the engine are plain [`Decimal`]s instead:
+/// they are parsed with [`parse_unrounded`] and keep their precision until the engine rounds them once with its [`Rounding`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct Decimal4(Decimal);
 
 const ROUNDING_STRATEGY: RoundingStrategy = RoundingStrategy::MidpointTowardZero;
 
+/// The most decimal places a `Decimal4` keeps.
+pub const MAX_DECIMALS: u32 = 4;
+
+/// The number of minor units (1/10000) in a unit.
+const MINOR_UNITS: i64 = 10_000;
+
 impl Decimal4 {
     pub fn zero() -> Self {
         Decimal4(Decimal::zero())
@@ -29,23 +39,120 @@ impl Decimal4 {
     pub fn is_positive(&self) -> bool {
         self.0.is_sign_positive() && !self.0.is_zero()
     }
+
+    /// `self + other`, or an error when the result does not fit into the inner [`Decimal`].
+    pub fn checked_add(self, other: Self) -> Result<Self, AmountOverflowError> {
+        self.0.checked_add(other.0).map(Decimal4::from).ok_or(AmountOverflowError)
+    }
+
+    /// `self - other`, or an error when the result does not fit into the inner [`Decimal`].
+    pub fn checked_sub(self, other: Self) -> Result<Self, AmountOverflowError> {
+        self.0.checked_sub(other.0).map(Decimal4::from).ok_or(AmountOverflowError)
+    }
+
+    /// `self * other` rounded like [`Mul`], or an error when the result does not fit into the inner [`Decimal`].
+    pub fn checked_mul(self, other: Self) -> Result<Self, AmountOverflowError> {
+        self.0.checked_mul(other.0).map(Decimal4::from).ok_or(AmountOverflowError)
+    }
+
+    /// `self / other` rounded like [`Div`], or an error on a division by zero or when the result does not fit into the inner [`Decimal`].
+    pub fn checked_div(self, other: Self) -> Result<Self, DivisionError> {
+        if other.is_zero() {
+            return Err(DivisionError::DivisionByZero);
+        }
+        self.0.checked_div(other.0).map(Decimal4::from).ok_or(DivisionError::Overflow(AmountOverflowError))
+    }
+
+    /// The amount as an integer number of 1/10000 units, e.g. `1.5` is `15000`.
+    pub fn to_minor_units(self) -> Result<i64, AmountOverflowError> {
+        let units = self.0.checked_mul(Decimal::from(MINOR_UNITS)).ok_or(AmountOverflowError)?;
+        i64::try_from(units).map_err(|_| AmountOverflowError)
+    }
+
+    pub fn from_minor_units(units: i64) -> Self {
+        Decimal4(Decimal::new(units, 4))
+    }
+
+    /// The amount with exactly `decimals` decimal places (rounded, then padded with zeros), e.g. `1.50` for `1.5` and
+    /// 2 decimal places; the output of an engine uses the decimal places of its [`Rounding`].
+    pub fn to_string_with_decimals(self, decimals: u32) -> String {
+        pad_decimals(self.0.round_dp_with_strategy(decimals, ROUNDING_STRATEGY).to_string(), decimals)
+    }
+
+    /// Rounds to the precision of an engine, see [`Rounding`].
+    pub fn round(self, rounding: Rounding) -> Self {
+        rounding.round(self.0)
+    }
+
+    /// `self` percent of `amount`, e.g. a 2.5% fee is `Decimal4::from_str("2.5")?.percent_of(amount)`.
+    ///
+    /// Rounded twice, after the multiplication and after the division by 100, so the result is off from the exact
+    /// value by at most 0.0000505 (half a unit of the 4th place, plus the first error divided by 100). An error when the
+    /// product does not fit into the inner [`Decimal`].
+    pub fn percent_of(self, amount: Self) -> Result<Self, AmountOverflowError> {
+        // NOTE: the divisor is never zero and a division by 100 can not overflow
+        Ok(self.checked_mul(amount)? / Decimal4::from(100))
+    }
+}
+
+/// Rounded to four decimal places like every other `Decimal4`, so the product is off by at most 0.00005.
+/// Panics on overflow, use [`Decimal4::checked_mul`] for amounts coming from the input.
+impl Mul for Decimal4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self::Output {
+        Decimal4::from(self.0 * other.0)
+    }
+}
+
+/// Rounded to four decimal places like every other `Decimal4`, so the quotient is off by at most 0.00005
+/// (e.g. `1 / 3` is `0.3333`). Panics on a division by zero or an overflow, use [`Decimal4::checked_div`] for amounts coming from the input.
+impl Div for Decimal4 {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self::Output {
+        Decimal4::from(self.0 / other.0)
+    }
+}
+
+/// The result of an arithmetic operation on [`Decimal4`] is out of range.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("amount overflow")]
+pub struct AmountOverflowError;
+
+/// The error of [`Decimal4::checked_div`].
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum DivisionError {
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error(transparent)]
+    Overflow(#[from] AmountOverflowError),
 }
 
 impl Display for Decimal4 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:.4}", self.0)
+        write!(f, "{}", pad_decimals(self.0.to_string(), MAX_DECIMALS))
     }
 }
 
+/// Pads `digits` with zeros to at least `decimals` decimal places.
+fn pad_decimals(digits: String, decimals: u32) -> String {
+    // NOTE: `{:.4}` of rust_decimal panics on more than 27 integer digits, so the zeros are padded here instead
+    let decimals = decimals as usize;
+    let present = digits.split_once('.').map_or(0, |(_, x)| x.len());
+    let point = if present == 0 && decimals > 0 { "." } else { "" };
+    format!("{}{}{}", digits, point, "0".repeat(decimals.saturating_sub(present)))
+}
+
 impl From<Decimal> for Decimal4 {
     fn from(value: Decimal) -> Self {
         Decimal4(value.round_dp_with_strategy(4, ROUNDING_STRATEGY))
     }
 }
 
-impl Into<Decimal> for Decimal4 {
-    fn into(self) -> Decimal {
-        self.0
+impl From<Decimal4> for Decimal {
+    fn from(value: Decimal4) -> Self {
+        value.0
     }
 }
 
@@ -97,6 +204,7 @@ impl<'de> Deserialize<'de> for Decimal4 {
     }
 }
 
+/// Panics on overflow, use [`Decimal4::checked_add`] for amounts coming from the input.
 impl Add for Decimal4 {
     type Output = Self;
 
@@ -105,6 +213,7 @@ impl Add for Decimal4 {
     }
 }
 
+/// Panics on overflow, use [`Decimal4::checked_sub`] for amounts coming from the input.
 impl Sub for Decimal4 {
     type Output = Self;
 
@@ -113,15 +222,212 @@ impl Sub for Decimal4 {
     }
 }
 
+/// Panics on overflow like `+`, use [`Decimal4::checked_add`] for amounts coming from the input.
 impl AddAssign for Decimal4 {
     fn add_assign(&mut self, other: Self) {
-        self.0 += other.0;
+        *self = self.checked_add(other).expect("amount overflow");
     }
 }
 
+/// Panics on overflow like `-`, use [`Decimal4::checked_sub`] for amounts coming from the input.
 impl SubAssign for Decimal4 {
     fn sub_assign(&mut self, other: Self) {
-        self.0 -= other.0;
+        *self = self.checked_sub(other).expect("amount overflow");
+    }
+}
+
+/// The precision and the rounding of the amounts accepted by an engine, see `EnginePolicy::rounding`. The default is
+/// the precision of `Decimal4` itself (4 decimal places, midpoint toward zero), e.g. `Rounding::bankers(2)` for
+/// integrations that use 2 decimal places with the banker's rounding end to end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rounding {
+    /// At most [`MAX_DECIMALS`], fewer decimal places than that are kept as trailing zeros.
+    pub decimals: u32,
+    pub strategy: RoundingStrategy,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Self { decimals: MAX_DECIMALS, strategy: ROUNDING_STRATEGY }
+    }
+}
+
+impl Rounding {
+    /// Rounds the midpoint to the nearest even digit.
+    pub fn bankers(decimals: u32) -> Self {
+        Self { decimals, strategy: RoundingStrategy::MidpointNearestEven }
+    }
+
+    /// Rounds an input amount (see [`parse_unrounded`]) to the precision of an engine.
+    pub fn round(&self, amount: Decimal) -> Decimal4 {
+        Decimal4(amount.round_dp_with_strategy(self.decimals.min(MAX_DECIMALS), self.strategy))
+    }
+
+    /// Parses the name of a strategy: `midpoint-toward-zero`, `midpoint-away-from-zero`, `bankers` (midpoint to even),
+    /// `toward-zero` or `away-from-zero`.
+    pub fn parse_strategy(s: &str) -> Result<RoundingStrategy, rust_decimal::Error> {
+        match s {
+            "midpoint-toward-zero" => Ok(RoundingStrategy::MidpointTowardZero),
+            "midpoint-away-from-zero" => Ok(RoundingStrategy::MidpointAwayFromZero),
+            "bankers" => Ok(RoundingStrategy::MidpointNearestEven),
+            "toward-zero" => Ok(RoundingStrategy::ToZero),
+            "away-from-zero" => Ok(RoundingStrategy::AwayFromZero),
+            _ => Err(rust_decimal::Error::ErrorString(format!("unknown rounding strategy: {}", s))),
+        }
+    }
+}
+
+/// Serde of a `Decimal4` as an `i64` number of 1/10000 units instead of the default string, e.g. `15000` for `1.5`:
+/// compact in MessagePack and the usual representation of the APIs exchanging integer minor units. Opt-in per field:
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use transactions_engine::decimal::{minor_units, Decimal4};
+/// #[derive(Serialize, Deserialize)]
+/// struct Payment {
+///     #[serde(with = "minor_units")]
+///     amount: Decimal4,
+///     #[serde(with = "minor_units::option", default)]
+///     fee: Option<Decimal4>,
+/// }
+/// ```
+pub mod minor_units {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Decimal4;
+
+    pub fn serialize<S: Serializer>(value: &Decimal4, serializer: S) -> Result<S::Ok, S::Error> {
+        let units = value.to_minor_units().map_err(serde::ser::Error::custom)?;
+        serializer.serialize_i64(units)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal4, D::Error> {
+        i64::deserialize(deserializer).map(Decimal4::from_minor_units)
+    }
+
+    /// The same for an `Option<Decimal4>`, `None` is encoded as null.
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        use super::super::Decimal4;
+
+        pub fn serialize<S: Serializer>(value: &Option<Decimal4>, serializer: S) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => super::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Decimal4>, D::Error> {
+            Ok(Option::<i64>::deserialize(deserializer)?.map(Decimal4::from_minor_units))
+        }
+    }
+}
+
+/// Parses an input amount without rounding it, so the engine rounds it only once, with its [`Rounding`]:
+/// `2.35499` stays below the midpoint of `2.35` and `2.36` instead of being rounded to `2.3550` first.
+pub fn parse_unrounded(s: &str) -> Result<Decimal, rust_decimal::Error> {
+    Decimal::from_str(s)
+}
+
+/// Serde of an input amount as a plain [`Decimal`] without rounding it, see [`parse_unrounded`]. It is written like a
+/// `Decimal4` (with at least four decimal places). Used by the amounts of the operations, which the engine rounds with its [`Rounding`].
+pub mod unrounded {
+    use rust_decimal::Decimal;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{pad_decimals, parse_unrounded, MAX_DECIMALS};
+
+    pub fn serialize<S: Serializer>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&pad_decimals(value.to_string(), MAX_DECIMALS))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse_unrounded(&s).map_err(serde::de::Error::custom)
+    }
+
+    /// The same for an `Option<Decimal>`.
+    pub mod option {
+        use rust_decimal::Decimal;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(value: &Option<Decimal>, serializer: S) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => super::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Decimal>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|s| super::parse_unrounded(&s).map_err(serde::de::Error::custom))
+                .transpose()
+        }
+    }
+}
+
+/// Notation of the amounts in an input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AmountFormat {
+    /// `1234.56`, no thousands separators.
+    #[default]
+    Plain,
+    /// `1,234.56`, optional comma thousands separators.
+    English,
+    /// `1.234,56`, optional dot thousands separators and a decimal comma.
+    European,
+}
+
+impl AmountFormat {
+    /// Parses the amount without rounding it, like [`parse_unrounded`]. The thousands separators are optional,
+    /// but when used every group after the first one must have three digits; a space or `'` is accepted as a separator too.
+    pub fn parse(&self, s: &str) -> Result<Decimal, rust_decimal::Error> {
+        let (group, decimal) = match self {
+            AmountFormat::Plain => return parse_unrounded(s),
+            AmountFormat::English => (',', '.'),
+            AmountFormat::European => ('.', ','),
+        };
+        let invalid = || rust_decimal::Error::ErrorString(format!("invalid amount: {}", s));
+
+        let s = s.trim();
+        let (sign, s) = match s.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", s),
+        };
+        let (int_part, frac_part) = match s.split_once(decimal) {
+            Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+            None => (s, None),
+        };
+        let groups: Vec<&str> = int_part.split([group, ' ', '\'']).collect();
+        let valid_groups = groups.iter().enumerate().all(|(i, x)| {
+            let len_ok = if i == 0 { !x.is_empty() && (groups.len() == 1 || x.len() <= 3) } else { x.len() == 3 };
+            len_ok && x.bytes().all(|x| x.is_ascii_digit())
+        });
+        let valid_frac = frac_part.is_none_or(|x| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit()));
+        if !valid_groups || !valid_frac {
+            return Err(invalid());
+        }
+
+        let mut normalized = format!("{}{}", sign, groups.concat());
+        if let Some(frac_part) = frac_part {
+            normalized.push('.');
+            normalized.push_str(frac_part);
+        }
+        parse_unrounded(&normalized)
+    }
+}
+
+impl FromStr for AmountFormat {
+    type Err = rust_decimal::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(AmountFormat::Plain),
+            "en" => Ok(AmountFormat::English),
+            "eu" => Ok(AmountFormat::European),
+            _ => Err(rust_decimal::Error::ErrorString(format!("unknown amount format: {}", s))),
+        }
     }
 }
 
@@ -150,6 +456,19 @@ mod decimal4_tests {
         assert_eq!(Ok("1.2345".to_string()), Decimal4::from_str("1.23454321").map(|x| x.to_string()));
     }
 
+    #[test]
+    fn amount_formats() {
+        assert_eq!(AmountFormat::European.parse("1.234,56"), Decimal::from_str("1234.56"));
+        assert_eq!(AmountFormat::European.parse("1234,567891"), Decimal::from_str("1234.567891"));
+        assert_eq!(AmountFormat::European.parse("-12"), Decimal::from_str("-12"));
+        assert_eq!(AmountFormat::English.parse("1,234,567.5"), Decimal::from_str("1234567.5"));
+        assert_eq!(AmountFormat::English.parse("1 234.5"), Decimal::from_str("1234.5"));
+        assert!(AmountFormat::European.parse("1.23,4").is_err());
+        assert!(AmountFormat::English.parse("1,2,3").is_err());
+        assert!(AmountFormat::English.parse("12,").is_err());
+        assert!(AmountFormat::English.parse("1.2.3").is_err());
+    }
+
     #[test]
     fn decimal4_addition() {
         let a = Decimal4::from_str("1.2345").unwrap();
@@ -211,4 +530,108 @@ mod decimal4_tests {
         let a = Decimal4::from_str("-0.0001").unwrap();
         assert!(!a.is_positive());
     }
+
+    #[test]
+    fn decimal4_display_of_large_amounts() {
+        assert_eq!(Decimal4::from(Decimal::MAX).to_string(), "79228162514264337593543950335.0000");
+        assert_eq!(Decimal4::from_str("-0.5").unwrap().to_string(), "-0.5000");
+    }
+
+    #[test]
+    fn decimal4_mul_div_round_to_four_places() {
+        let d = |s: &str| Decimal4::from_str(s).unwrap();
+        assert_eq!(d("1.2345") * d("2"), d("2.469"));
+        assert_eq!(d("0.0001") * d("0.5"), Decimal4::zero()); // NOTE: the midpoint is rounded toward zero
+        assert_eq!(d("0.0003") * d("0.5"), d("0.0001"));
+        assert_eq!(d("1") / d("3"), d("0.3333"));
+        assert_eq!(d("2") / d("3"), d("0.6667"));
+        assert_eq!(d("-2") / d("3"), d("-0.6667"));
+    }
+
+    #[test]
+    fn decimal4_checked_mul_div() {
+        let d = |s: &str| Decimal4::from_str(s).unwrap();
+        let max = Decimal4::from(Decimal::MAX);
+        assert_eq!(d("1.2345").checked_mul(d("2")), Ok(d("2.469")));
+        assert_eq!(max.checked_mul(d("2")), Err(AmountOverflowError));
+        assert_eq!(d("1").checked_div(d("3")), Ok(d("0.3333")));
+        assert_eq!(d("1").checked_div(Decimal4::zero()), Err(DivisionError::DivisionByZero));
+        assert_eq!(max.checked_div(d("0.5")), Err(DivisionError::Overflow(AmountOverflowError)));
+    }
+
+    #[test]
+    fn decimal4_percent_of() {
+        let d = |s: &str| Decimal4::from_str(s).unwrap();
+        assert_eq!(d("2.5").percent_of(d("200")), Ok(d("5")));
+        assert_eq!(d("1").percent_of(d("0.0150")), Ok(d("0.0001")));
+        assert_eq!(d("0.5").percent_of(d("0.0100")), Ok(Decimal4::zero())); // 0.00005, the midpoint
+        assert_eq!(d("100").percent_of(d("12.3456")), Ok(d("12.3456")));
+        assert_eq!(d("200").percent_of(Decimal4::from(Decimal::MAX)), Err(AmountOverflowError));
+    }
+
+    #[test]
+    fn decimal4_minor_units() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Payment {
+            #[serde(with = "minor_units")]
+            amount: Decimal4,
+            #[serde(with = "minor_units::option", default)]
+            fee: Option<Decimal4>,
+        }
+
+        let payment = Payment { amount: Decimal4::from_str("-1.5").unwrap(), fee: Some(Decimal4::from_str("0.0001").unwrap()) };
+        let json = serde_json::to_string(&payment).unwrap();
+        assert_eq!(json, r#"{"amount":-15000,"fee":1}"#);
+        assert_eq!(serde_json::from_str::<Payment>(&json).unwrap(), payment);
+        assert_eq!(serde_json::from_str::<Payment>(r#"{"amount":20000}"#).unwrap(), Payment { amount: Decimal4::from(2), fee: None });
+
+        let packed = rmp_serde::to_vec(&payment).unwrap();
+        assert!(packed.len() < rmp_serde::to_vec(&(payment.amount, payment.fee)).unwrap().len());
+        assert_eq!(rmp_serde::from_slice::<Payment>(&packed).unwrap(), payment);
+
+        assert_eq!(Decimal4::from(Decimal::MAX).to_minor_units(), Err(AmountOverflowError));
+        assert!(serde_json::to_string(&Payment { amount: Decimal4::from(Decimal::MAX), fee: None }).is_err());
+    }
+
+    #[test]
+    fn decimal4_round() {
+        let d = |s: &str| Decimal4::from_str(s).unwrap();
+        assert_eq!(d("2.345").round(Rounding::bankers(2)), d("2.34"));
+        assert_eq!(d("2.355").round(Rounding::bankers(2)), d("2.36"));
+        assert_eq!(d("-2.345").round(Rounding::bankers(2)), d("-2.34"));
+        assert_eq!(d("2.345").round(Rounding { decimals: 2, strategy: ROUNDING_STRATEGY }), d("2.34"));
+        assert_eq!(d("2.3451").round(Rounding::default()), d("2.3451"));
+        assert_eq!(d("2.5").round(Rounding { decimals: 0, strategy: Rounding::parse_strategy("away-from-zero").unwrap() }), d("3"));
+        assert!(Rounding::parse_strategy("up").is_err());
+    }
+
+    #[test]
+    fn parse_unrounded_amount() {
+        let amount = parse_unrounded("2.35499").unwrap();
+        assert_eq!(amount.to_string(), "2.35499");
+        assert_eq!(Rounding::bankers(2).round(amount), Decimal4::from_str("2.35").unwrap());
+        assert_eq!(Decimal4::from(amount).to_string(), "2.3550");
+        assert_eq!(Decimal4::from_str("2.35499").unwrap().round(Rounding::bankers(2)), Decimal4::from_str("2.36").unwrap());
+    }
+
+    #[test]
+    fn decimal4_to_string_with_decimals() {
+        let d = |s: &str| Decimal4::from_str(s).unwrap();
+        assert_eq!(d("1.5").to_string_with_decimals(2), "1.50");
+        assert_eq!(d("10").to_string_with_decimals(2), "10.00");
+        assert_eq!(d("10").to_string_with_decimals(0), "10");
+        assert_eq!(d("2.345").to_string_with_decimals(2), "2.34");
+        assert_eq!(d("1.5").to_string_with_decimals(4), d("1.5").to_string());
+    }
+
+    #[test]
+    fn decimal4_checked_arithmetic() {
+        let max = Decimal4::from(Decimal::MAX);
+        let min = Decimal4::from(Decimal::MIN);
+        assert_eq!(Decimal4::from(2).checked_add(Decimal4::from(3)), Ok(Decimal4::from(5)));
+        assert_eq!(Decimal4::from(2).checked_sub(Decimal4::from(3)), Ok(Decimal4::from(-1)));
+        assert_eq!(max.checked_add(Decimal4::from(1)), Err(AmountOverflowError));
+        assert_eq!(min.checked_sub(Decimal4::from(1)), Err(AmountOverflowError));
+        assert_eq!(max.checked_sub(max), Ok(Decimal4::zero()));
+    }
 }
@@ -0,0 +1,214 @@
+use std::io::Write;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::account::ClientId;
+use crate::decimal::Decimal4;
+use crate::transaction::{Transaction, TransactionState, TransactionType, TxId};
+
+/// Which open disputes [`crate::engine::Engine::get_open_disputes`] returns, all of them by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisputeFilter {
+    /// Only the disputes of this account.
+    pub client: Option<ClientId>,
+    /// Only the disputes open for at least this long, in millis.
+    pub min_age_ms: Option<u64>,
+}
+
+impl DisputeFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_client(mut self, client: ClientId) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn with_min_age_ms(mut self, min_age_ms: u64) -> Self {
+        self.min_age_ms = Some(min_age_ms);
+        self
+    }
+}
+
+/// A disputed deposit waiting for a resolve or a chargeback.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OpenDispute {
+    pub client: ClientId,
+    pub tx: TxId,
+    pub amount: Decimal4,
+    /// The part of the amount the dispute could not hold, see `NegativeAvailablePolicy::Clamp`.
+    pub shortfall: Decimal4,
+    /// When the dispute was opened (unix millis), the creation of the deposit for the transactions stored without a history.
+    pub opened_at: u64,
+    /// How long the dispute has been open at the time of the report, in millis.
+    pub age_ms: u64,
+}
+
+impl OpenDispute {
+    /// `None` if the transaction is not disputed.
+    pub fn from_transaction(tx: &Transaction, now: u64) -> Option<Self> {
+        if tx.state() != TransactionState::Disputed {
+            return None;
+        }
+        let opened_at = tx.history().iter().rev()
+            .find(|x| x.to == TransactionState::Disputed)
+            .map_or(tx.created_at(), |x| x.timestamp);
+        Some(Self {
+            client: tx.account_id(),
+            tx: tx.id(),
+            amount: tx.amount(),
+            shortfall: tx.shortfall(),
+            opened_at,
+            age_ms: now.saturating_sub(opened_at),
+        })
+    }
+}
+
+/// The dispute queue: the open disputes matching a filter, the oldest first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OpenDisputesReport {
+    /// The time the ages are computed at, unix millis.
+    pub as_of: u64,
+    pub disputes: Vec<OpenDispute>,
+    /// The sum of the disputed amounts.
+    pub amount: Decimal4,
+}
+
+impl OpenDisputesReport {
+    pub fn new(as_of: u64, mut disputes: Vec<OpenDispute>) -> Self {
+        disputes.sort_by_key(|x| (x.opened_at, x.tx));
+        let amount = disputes.iter().fold(Decimal4::zero(), |amount, x| amount + x.amount);
+        Self { as_of, disputes, amount }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report is always serializable")
+    }
+
+    /// Writes a row per dispute.
+    pub fn write_csv<W: Write>(&self, writer: W) -> csv::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for dispute in self.disputes.iter() {
+            writer.serialize(dispute)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The deposits and the chargebacks of an account, for the risk monitoring.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChargebackStats {
+    pub client: ClientId,
+    /// The number of deposits, whatever their state.
+    pub deposits: u64,
+    /// The number of charged back deposits.
+    pub chargebacks: u64,
+    pub deposited: Decimal4,
+    pub charged_back: Decimal4,
+    /// `chargebacks / deposits`, zero for an account without deposits.
+    pub ratio: Decimal4,
+}
+
+impl ChargebackStats {
+    pub fn from_transactions<'a>(client: ClientId, transactions: impl IntoIterator<Item = &'a Transaction>) -> Self {
+        let zero = Decimal4::zero();
+        let mut stats = Self { client, deposits: 0, chargebacks: 0, deposited: zero, charged_back: zero, ratio: zero };
+        for tx in transactions.into_iter().filter(|x| x.tx_type() == TransactionType::Deposit) {
+            stats.deposits += 1;
+            stats.deposited += tx.amount();
+            if tx.state() == TransactionState::Chargeback {
+                stats.chargebacks += 1;
+                stats.charged_back += tx.amount();
+            }
+        }
+        // NOTE: an account without deposits has a zero ratio
+        stats.ratio = Decimal4::from(Decimal::from(stats.chargebacks)).checked_div(Decimal4::from(Decimal::from(stats.deposits))).unwrap_or(zero);
+        stats
+    }
+}
+
+/// The accounts whose chargeback ratio is above `max_ratio`, the highest ratio first. The accounts with fewer than
+/// `min_deposits` deposits are left out, a single chargeback would put them over any ratio.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChargebackRatioReport {
+    pub max_ratio: Decimal4,
+    pub min_deposits: u64,
+    pub accounts: Vec<ChargebackStats>,
+}
+
+impl ChargebackRatioReport {
+    pub fn new(max_ratio: Decimal4, min_deposits: u64, stats: impl IntoIterator<Item = ChargebackStats>) -> Self {
+        let mut accounts: Vec<_> = stats.into_iter()
+            .filter(|x| x.deposits >= min_deposits && x.ratio > max_ratio)
+            .collect();
+        accounts.sort_by(|a, b| b.ratio.cmp(&a.ratio).then(a.client.cmp(&b.client)));
+        Self { max_ratio, min_deposits, accounts }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report is always serializable")
+    }
+
+    /// Writes a row per account.
+    pub fn write_csv<W: Write>(&self, writer: W) -> csv::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for stats in self.accounts.iter() {
+            writer.serialize(stats)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod disputes_tests {
+    use crate::engine::Operation;
+
+    use super::*;
+
+    #[test]
+    fn report_ages_open_disputes() {
+        let mut old = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(100)).with_created_at(10);
+        old.transition(TransactionState::Disputed, Operation::Dispute { acc_id: 1, tx_id: 1 }, 100).unwrap();
+        let mut recent = Transaction::new(2, 2, TransactionType::Deposit, Decimal4::from(5)).with_created_at(20);
+        recent.transition(TransactionState::Disputed, Operation::Dispute { acc_id: 2, tx_id: 2 }, 400).unwrap();
+        let posted = Transaction::new(3, 1, TransactionType::Deposit, Decimal4::from(1));
+        assert_eq!(OpenDispute::from_transaction(&posted, 1000), None);
+
+        let disputes = [&recent, &old].into_iter().filter_map(|x| OpenDispute::from_transaction(x, 1000)).collect();
+        let report = OpenDisputesReport::new(1000, disputes);
+        assert_eq!(report.disputes.iter().map(|x| (x.tx, x.age_ms)).collect::<Vec<_>>(), vec![(1, 900), (2, 600)]);
+        assert_eq!(report.amount, Decimal4::from(105));
+
+        let mut data = Vec::new();
+        report.write_csv(&mut data).unwrap();
+        assert_eq!(String::from_utf8(data).unwrap(), "\
+client,tx,amount,shortfall,opened_at,age_ms
+1,1,100.0000,0.0000,100,900
+2,2,5.0000,0.0000,400,600
+");
+    }
+
+    #[test]
+    fn chargeback_ratio_over_threshold() {
+        let mut charged_back = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(30));
+        charged_back.set_state(TransactionState::Disputed).unwrap();
+        charged_back.set_state(TransactionState::Chargeback).unwrap();
+        let posted = Transaction::new(2, 1, TransactionType::Deposit, Decimal4::from(10));
+        let withdrawal = Transaction::new(3, 1, TransactionType::Withdrawal, Decimal4::from(5));
+        let other = Transaction::new(4, 1, TransactionType::Deposit, Decimal4::from(10));
+
+        let stats = ChargebackStats::from_transactions(1, [&charged_back, &posted, &withdrawal, &other]);
+        assert_eq!((stats.deposits, stats.chargebacks, stats.deposited, stats.charged_back), (3, 1, Decimal4::from(50), Decimal4::from(30)));
+        assert_eq!(stats.ratio.to_string(), "0.3333");
+        let clean = ChargebackStats::from_transactions(2, [&posted]);
+        assert_eq!(clean.ratio, Decimal4::zero());
+
+        let report = ChargebackRatioReport::new("0.1".parse().unwrap(), 1, [stats.clone(), clean.clone()]);
+        assert_eq!(report.accounts, vec![stats.clone()]);
+        assert_eq!(ChargebackRatioReport::new("0.1".parse().unwrap(), 5, [stats, clean]).accounts, vec![]);
+    }
+}
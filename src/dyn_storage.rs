@@ -0,0 +1,432 @@
+use std::any::Any;
+
+use async_trait::async_trait;
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, Savepoint, Storage, TxOptions};
+use crate::traced_storage::TracedStorage;
+use crate::transaction::{Transaction, TxId};
+
+/// A storage transaction with the concrete type erased. It can only be used with the storage that started it.
+pub struct DynDbTx(Box<dyn Any + Send>);
+
+/// Object-safe counterpart of `Storage + Journal`, implemented for every storage type.
+/// `Box<dyn DynStorage>` implements `Storage` and `Journal` itself, so the backend of an `Engine` can be chosen at runtime:
+///
+/// ```
+/// use transactions_engine::dyn_storage::DynStorage;
+/// use transactions_engine::engine::Engine;
+/// use transactions_engine::storage::EchoDbStorage;
+///
+/// let storage: Box<dyn DynStorage> = Box::new(EchoDbStorage::new());
+/// let engine = Engine::new(storage);
+/// ```
+#[async_trait]
+pub trait DynStorage: Send + Sync {
+    async fn get_tx(&self, db_tx: &mut DynDbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError>;
+    async fn insert_tx(&self, db_tx: &mut DynDbTx, tx: &Transaction) -> Result<(), DbError>;
+    async fn update_tx(&self, db_tx: &mut DynDbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError>;
+    async fn get_all_txs(&self, db_tx: &mut DynDbTx) -> Result<Vec<Transaction>, DbError>;
+    async fn get_txs(&self, db_tx: &mut DynDbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError>;
+    async fn insert_txs(&self, db_tx: &mut DynDbTx, txs: &[Transaction]) -> Result<(), DbError>;
+    async fn delete_txs(&self, db_tx: &mut DynDbTx, tx_ids: &[TxId]) -> Result<usize, DbError>;
+    async fn get_txs_by_account(&self, db_tx: &mut DynDbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError>;
+    async fn get_tx_by_external_id(&self, db_tx: &mut DynDbTx, external_id: &str) -> Result<Option<Transaction>, DbError>;
+    async fn get_expired_authorizations(&self, db_tx: &mut DynDbTx, now: u64) -> Result<Vec<Transaction>, DbError>;
+
+    async fn get_account(&self, db_tx: &mut DynDbTx, acc_id: ClientId) -> Result<Option<Account>, DbError>;
+    async fn get_all_accounts(&self, db_tx: &mut DynDbTx) -> Result<Vec<Account>, DbError>;
+    async fn get_accounts(&self, db_tx: &mut DynDbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError>;
+    async fn insert_accounts(&self, db_tx: &mut DynDbTx, accs: &[Account]) -> Result<(), DbError>;
+    async fn list_accounts(&self, db_tx: &mut DynDbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError>;
+    async fn insert_account(&self, db_tx: &mut DynDbTx, acc: &Account) -> Result<(), DbError>;
+    async fn update_account(&self, db_tx: &mut DynDbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError>;
+
+    async fn get_system_account(&self, db_tx: &mut DynDbTx, account: SystemAccount) -> Result<Option<Account>, DbError>;
+    async fn set_system_account(&self, db_tx: &mut DynDbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError>;
+
+    async fn is_operation_processed(&self, db_tx: &mut DynDbTx, op_hash: u64) -> Result<bool, DbError>;
+    async fn insert_operation(&self, db_tx: &mut DynDbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError>;
+    async fn get_all_operations(&self, db_tx: &mut DynDbTx) -> Result<Vec<u64>, DbError>;
+    async fn prune_operations(&self, db_tx: &mut DynDbTx, older_than: u64) -> Result<usize, DbError>;
+    async fn get_operation_rejection(&self, db_tx: &mut DynDbTx, op_hash: u64) -> Result<Option<u16>, DbError>;
+    async fn insert_operation_rejection(&self, db_tx: &mut DynDbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError>;
+
+    async fn get_checkpoint(&self, db_tx: &mut DynDbTx, source: &str) -> Result<Option<u64>, DbError>;
+    async fn set_checkpoint(&self, db_tx: &mut DynDbTx, source: &str, rows: u64) -> Result<(), DbError>;
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<DynDbTx, DbError>;
+    async fn commit_db_tx(&self, db_tx: DynDbTx) -> Result<(), DbError>;
+    async fn savepoint(&self, db_tx: &mut DynDbTx) -> Result<Savepoint, DbError>;
+    async fn rollback_to(&self, db_tx: &mut DynDbTx, savepoint: Savepoint) -> Result<(), DbError>;
+
+    async fn get_last_journal_seq(&self, db_tx: &mut DynDbTx) -> Result<u64, DbError>;
+    async fn append_journal_entry(&self, db_tx: &mut DynDbTx, entry: &JournalEntry) -> Result<(), DbError>;
+    async fn get_journal_entries(&self, db_tx: &mut DynDbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError>;
+}
+
+/// Opens the storage of a `--storage` value: `memory`, `file:<path>` or `sqlite:<url>` (with the `sqlite` feature),
+/// traced (see `TracedStorage`). On `wasm32` targets only `memory` is available, backed by a `MemoryStorage`.
+pub async fn open_storage(storage: &str) -> anyhow::Result<Box<dyn DynStorage>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if storage == "memory" {
+        return Ok(Box::new(TracedStorage::new(crate::storage::EchoDbStorage::new(), "memory")));
+    }
+    #[cfg(target_arch = "wasm32")]
+    if storage == "memory" {
+        return Ok(Box::new(TracedStorage::new(crate::memory_storage::MemoryStorage::new(), "memory")));
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = storage.strip_prefix("file:") {
+        return Ok(Box::new(TracedStorage::new(crate::file_storage::FileStorage::open(path).await?, "file")));
+    }
+    #[cfg(feature = "sqlite")]
+    if storage.starts_with("sqlite:") {
+        return Ok(Box::new(TracedStorage::new(crate::sqlite::SqliteStorage::connect(storage).await?, "sqlite")));
+    }
+    anyhow::bail!("unknown storage: {}", storage)
+}
+
+/// Opens the storage of a `--storage` value like [`open_storage`], scoped to the namespace of the tenant (see `TenantStorage`).
+/// The `sqlite` storage has no tenant namespaces, give every tenant its own database instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn open_tenant_storage(storage: &str, tenant: &str) -> anyhow::Result<Box<dyn DynStorage>> {
+    use crate::storage::TenantStorage;
+
+    if tenant.is_empty() || tenant.contains(':') {
+        anyhow::bail!("invalid tenant id {:?}, it must be non-empty and must not contain ':'", tenant);
+    }
+    if storage == "memory" {
+        return Ok(Box::new(TracedStorage::new(crate::storage::EchoDbStorage::new().for_tenant(tenant), "memory")));
+    }
+    if let Some(path) = storage.strip_prefix("file:") {
+        return Ok(Box::new(TracedStorage::new(crate::file_storage::FileStorage::open(path).await?.for_tenant(tenant), "file")));
+    }
+    if storage.starts_with("sqlite:") {
+        anyhow::bail!("the sqlite storage has no tenant namespaces, configure a database per tenant");
+    }
+    anyhow::bail!("unknown storage: {}", storage)
+}
+
+fn downcast<T: 'static>(db_tx: &mut DynDbTx) -> Result<&mut T, DbError> {
+    db_tx.0.downcast_mut::<T>().ok_or_else(|| DbError::DatabaseError("Transaction belongs to another storage".to_string()))
+}
+
+#[async_trait]
+impl<T> DynStorage for T
+where
+    T: Storage + Journal + Send + Sync,
+    T::DbTx: Send + 'static,
+{
+    async fn get_tx(&self, db_tx: &mut DynDbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        Storage::get_tx(self, downcast(db_tx)?, tx_id).await
+    }
+
+    async fn insert_tx(&self, db_tx: &mut DynDbTx, tx: &Transaction) -> Result<(), DbError> {
+        Storage::insert_tx(self, downcast(db_tx)?, tx).await
+    }
+
+    async fn update_tx(&self, db_tx: &mut DynDbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        Storage::update_tx(self, downcast(db_tx)?, old_tx, new_tx).await
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut DynDbTx) -> Result<Vec<Transaction>, DbError> {
+        Storage::get_all_txs(self, downcast(db_tx)?).await
+    }
+
+    async fn get_txs(&self, db_tx: &mut DynDbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        Storage::get_txs(self, downcast(db_tx)?, tx_ids).await
+    }
+
+    async fn insert_txs(&self, db_tx: &mut DynDbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        Storage::insert_txs(self, downcast(db_tx)?, txs).await
+    }
+
+    async fn delete_txs(&self, db_tx: &mut DynDbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        Storage::delete_txs(self, downcast(db_tx)?, tx_ids).await
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut DynDbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        Storage::get_txs_by_account(self, downcast(db_tx)?, acc_id, cursor, limit).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut DynDbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        Storage::get_tx_by_external_id(self, downcast(db_tx)?, external_id).await
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut DynDbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        Storage::get_expired_authorizations(self, downcast(db_tx)?, now).await
+    }
+
+    async fn get_account(&self, db_tx: &mut DynDbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        Storage::get_account(self, downcast(db_tx)?, acc_id).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut DynDbTx) -> Result<Vec<Account>, DbError> {
+        Storage::get_all_accounts(self, downcast(db_tx)?).await
+    }
+
+    async fn list_accounts(&self, db_tx: &mut DynDbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        Storage::list_accounts(self, downcast(db_tx)?, cursor, limit).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut DynDbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        Storage::get_accounts(self, downcast(db_tx)?, acc_ids).await
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut DynDbTx, accs: &[Account]) -> Result<(), DbError> {
+        Storage::insert_accounts(self, downcast(db_tx)?, accs).await
+    }
+
+    async fn insert_account(&self, db_tx: &mut DynDbTx, acc: &Account) -> Result<(), DbError> {
+        Storage::insert_account(self, downcast(db_tx)?, acc).await
+    }
+
+    async fn update_account(&self, db_tx: &mut DynDbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        Storage::update_account(self, downcast(db_tx)?, old_acc, new_acc).await
+    }
+
+    async fn get_system_account(&self, db_tx: &mut DynDbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        Storage::get_system_account(self, downcast(db_tx)?, account).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut DynDbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        Storage::set_system_account(self, downcast(db_tx)?, account, acc).await
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut DynDbTx, op_hash: u64) -> Result<bool, DbError> {
+        Storage::is_operation_processed(self, downcast(db_tx)?, op_hash).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut DynDbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        Storage::insert_operation(self, downcast(db_tx)?, op_hash, timestamp).await
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut DynDbTx) -> Result<Vec<u64>, DbError> {
+        Storage::get_all_operations(self, downcast(db_tx)?).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut DynDbTx, older_than: u64) -> Result<usize, DbError> {
+        Storage::prune_operations(self, downcast(db_tx)?, older_than).await
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut DynDbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        Storage::get_operation_rejection(self, downcast(db_tx)?, op_hash).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut DynDbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        Storage::insert_operation_rejection(self, downcast(db_tx)?, op_hash, code, timestamp).await
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut DynDbTx, source: &str) -> Result<Option<u64>, DbError> {
+        Storage::get_checkpoint(self, downcast(db_tx)?, source).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut DynDbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        Storage::set_checkpoint(self, downcast(db_tx)?, source, rows).await
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<DynDbTx, DbError> {
+        let db_tx = Storage::start_db_tx(self, options).await?;
+        Ok(DynDbTx(Box::new(db_tx)))
+    }
+
+    async fn commit_db_tx(&self, db_tx: DynDbTx) -> Result<(), DbError> {
+        let db_tx = db_tx.0.downcast::<T::DbTx>()
+            .map_err(|_| DbError::DatabaseError("Transaction belongs to another storage".to_string()))?;
+        Storage::commit_db_tx(self, *db_tx).await
+    }
+
+    async fn savepoint(&self, db_tx: &mut DynDbTx) -> Result<Savepoint, DbError> {
+        Storage::savepoint(self, downcast(db_tx)?).await
+    }
+
+    async fn rollback_to(&self, db_tx: &mut DynDbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        Storage::rollback_to(self, downcast(db_tx)?, savepoint).await
+    }
+
+    async fn get_last_journal_seq(&self, db_tx: &mut DynDbTx) -> Result<u64, DbError> {
+        Journal::get_last_journal_seq(self, downcast(db_tx)?).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut DynDbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        Journal::append_journal_entry(self, downcast(db_tx)?, entry).await
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut DynDbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        Journal::get_journal_entries(self, downcast(db_tx)?, from_seq, limit).await
+    }
+}
+
+impl Storage for Box<dyn DynStorage> {
+    type DbTx = DynDbTx;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        (**self).get_tx(db_tx, tx_id).await
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        (**self).insert_tx(db_tx, tx).await
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        (**self).update_tx(db_tx, old_tx, new_tx).await
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        (**self).get_all_txs(db_tx).await
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        (**self).get_txs(db_tx, tx_ids).await
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        (**self).insert_txs(db_tx, txs).await
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        (**self).delete_txs(db_tx, tx_ids).await
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        (**self).get_txs_by_account(db_tx, acc_id, cursor, limit).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        (**self).get_tx_by_external_id(db_tx, external_id).await
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        (**self).get_expired_authorizations(db_tx, now).await
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        (**self).get_account(db_tx, acc_id).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        (**self).get_all_accounts(db_tx).await
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        (**self).list_accounts(db_tx, cursor, limit).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        (**self).get_accounts(db_tx, acc_ids).await
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        (**self).insert_accounts(db_tx, accs).await
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        (**self).insert_account(db_tx, acc).await
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        (**self).update_account(db_tx, old_acc, new_acc).await
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        (**self).get_system_account(db_tx, account).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        (**self).set_system_account(db_tx, account, acc).await
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        (**self).is_operation_processed(db_tx, op_hash).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        (**self).insert_operation(db_tx, op_hash, timestamp).await
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        (**self).get_all_operations(db_tx).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        (**self).prune_operations(db_tx, older_than).await
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        (**self).get_operation_rejection(db_tx, op_hash).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        (**self).insert_operation_rejection(db_tx, op_hash, code, timestamp).await
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        (**self).get_checkpoint(db_tx, source).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        (**self).set_checkpoint(db_tx, source, rows).await
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        (**self).start_db_tx(options).await
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        (**self).commit_db_tx(db_tx).await
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        (**self).savepoint(db_tx).await
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        (**self).rollback_to(db_tx, savepoint).await
+    }
+}
+
+impl Journal for Box<dyn DynStorage> {
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        (**self).get_last_journal_seq(db_tx).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        (**self).append_journal_entry(db_tx, entry).await
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        (**self).get_journal_entries(db_tx, from_seq, limit).await
+    }
+}
+
+#[cfg(test)]
+mod dyn_storage_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+    use crate::file_storage::FileStorage;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn engine_with_boxed_storage() {
+        let storage: Box<dyn DynStorage> = Box::new(EchoDbStorage::new());
+        let engine = Engine::new(storage);
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(70));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn foreign_db_tx_err() {
+        let path = std::env::temp_dir().join(format!("transactions_engine_dyn_{}.log", std::process::id()));
+        let first: Box<dyn DynStorage> = Box::new(EchoDbStorage::new());
+        let second: Box<dyn DynStorage> = Box::new(FileStorage::open(&path).await.unwrap());
+        let mut db_tx = Storage::start_db_tx(&first, TxOptions::default()).await.unwrap();
+        assert!(matches!(Storage::get_account(&second, &mut db_tx, 1).await, Err(DbError::DatabaseError(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
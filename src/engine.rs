@@ -1,24 +1,109 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::future::Future;
 use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-
+use std::time::Duration;
+
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+use futures::channel::mpsc;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+use futures::{SinkExt, StreamExt};
+use futures::{Stream, TryStreamExt};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-
-use crate::account::{Account, AccountUpdateError};
-use crate::decimal::Decimal4;
-use crate::storage::{DbError, Storage};
-use crate::transaction::{Transaction, TransactionState, TransactionType, TxUpdateError};
-
-#[derive(Debug, PartialEq, Eq)]
+use tracing::Instrument;
+
+use crate::account::{Account, AccountMetadata, AccountStatus, AccountUpdateError, ClientId, SystemAccount};
+use crate::compliance::{AmlConfig, AmlMonitor, SuspiciousActivityReport};
+use crate::clock::{Clock, Instant, SystemClock};
+use crate::decimal::{unrounded, AmountOverflowError, Decimal4, Rounding};
+use crate::disputes::{ChargebackRatioReport, ChargebackStats, DisputeFilter, OpenDispute, OpenDisputesReport};
+use crate::journal::{AccountSeqVerifier, AccountSeqViolation, ChainReport, ChainVerifier, Digest, Journal, JournalEntry, JournalPages, Provenance};
+use crate::observer::{EngineEvent, EngineObserver};
+use crate::privacy::AccountDataExport;
+use crate::reconcile::{reconcile_with, ReconciliationReport};
+use crate::redact;
+use crate::replay::{Divergence, PointInTime, ReplayState};
+use crate::risk::{RiskAssessor, RiskDecision};
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+use crate::runtime::{self, JoinHandle};
+use crate::settlement::{Settlement, SettlementRecord};
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotWriter};
+use crate::statement::Statement;
+use crate::storage::{AccountTxPages, DbError, IsolationLevel, LockingStrategy, Storage, TenantStorage, TxOptions};
+use crate::transaction::{Transaction, TransactionState, TxId, TxUpdateError};
+use crate::validator::{ValidatedOperation, Validator};
+
+/// The amounts are plain [`Decimal`]s deserialized without rounding, the engine rounds them once with [`EnginePolicy::rounding`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Operation {
-    Deposit { acc_id: u16, tx_id: u32, amount: Decimal4 },
-    Withdraw { acc_id: u16, tx_id: u32, amount: Decimal4 },
-    Dispute { acc_id: u16, tx_id: u32 },
-    Resolve { acc_id: u16, tx_id: u32 },
-    Chargeback { acc_id: u16, tx_id: u32 },
+    Deposit { acc_id: ClientId, tx_id: TxId, #[serde(with = "unrounded")] amount: Decimal },
+    Withdraw { acc_id: ClientId, tx_id: TxId, #[serde(with = "unrounded")] amount: Decimal },
+    Dispute { acc_id: ClientId, tx_id: TxId },
+    Resolve { acc_id: ClientId, tx_id: TxId },
+    Chargeback { acc_id: ClientId, tx_id: TxId },
+    /// Moves available funds into the escrow `bucket` of the account, see [`Engine::escrow`].
+    Escrow { acc_id: ClientId, tx_id: TxId, bucket: String, #[serde(with = "unrounded")] amount: Decimal },
+    /// Moves funds of the escrow `bucket` back to the available funds, see [`Engine::release_escrow`].
+    ReleaseEscrow { acc_id: ClientId, tx_id: TxId, bucket: String, #[serde(with = "unrounded")] amount: Decimal },
+    /// Holds available funds of the account until the authorization is captured or expires, see [`Engine::authorize`].
+    Authorize { acc_id: ClientId, tx_id: TxId, #[serde(with = "unrounded")] amount: Decimal },
+    /// Takes the held funds of the authorization `tx_id` from the account, see [`Engine::capture`].
+    Capture { acc_id: ClientId, tx_id: TxId },
+    /// Releases the held funds of the expired authorization `tx_id`, see [`Engine::expire_holds`].
+    Expire { acc_id: ClientId, tx_id: TxId },
 }
 
 impl Operation {
+    /// The canonical name of the operation type, as in the `type` column of the CSV input.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Deposit { .. } => "deposit",
+            Operation::Withdraw { .. } => "withdrawal",
+            Operation::Dispute { .. } => "dispute",
+            Operation::Resolve { .. } => "resolve",
+            Operation::Chargeback { .. } => "chargeback",
+            Operation::Escrow { .. } => "escrow",
+            Operation::ReleaseEscrow { .. } => "release",
+            Operation::Authorize { .. } => "authorize",
+            Operation::Capture { .. } => "capture",
+            Operation::Expire { .. } => "expire",
+        }
+    }
+
+    pub fn acc_id(&self) -> ClientId {
+        match *self {
+            Operation::Deposit { acc_id, .. }
+            | Operation::Withdraw { acc_id, .. }
+            | Operation::Dispute { acc_id, .. }
+            | Operation::Resolve { acc_id, .. }
+            | Operation::Chargeback { acc_id, .. }
+            | Operation::Escrow { acc_id, .. }
+            | Operation::ReleaseEscrow { acc_id, .. }
+            | Operation::Authorize { acc_id, .. }
+            | Operation::Capture { acc_id, .. }
+            | Operation::Expire { acc_id, .. } => acc_id,
+        }
+    }
+
+    pub fn tx_id(&self) -> TxId {
+        match *self {
+            Operation::Deposit { tx_id, .. }
+            | Operation::Withdraw { tx_id, .. }
+            | Operation::Dispute { tx_id, .. }
+            | Operation::Resolve { tx_id, .. }
+            | Operation::Chargeback { tx_id, .. }
+            | Operation::Escrow { tx_id, .. }
+            | Operation::ReleaseEscrow { tx_id, .. }
+            | Operation::Authorize { tx_id, .. }
+            | Operation::Capture { tx_id, .. }
+            | Operation::Expire { tx_id, .. } => tx_id,
+        }
+    }
+
     pub fn get_hash_code(&self) -> u64 {
         let mut hasher = std::collections::hash_map::DefaultHasher::new();
         self.hash(&mut hasher);
@@ -34,6 +119,11 @@ impl Hash for Operation {
             Operation::Dispute { acc_id, tx_id } => ("dispute", acc_id, tx_id),
             Operation::Resolve { acc_id, tx_id } => ("resolve", acc_id, tx_id),
             Operation::Chargeback { acc_id, tx_id } => ("chargeback", acc_id, tx_id),
+            Operation::Escrow { acc_id, tx_id, .. } => ("escrow", acc_id, tx_id),
+            Operation::ReleaseEscrow { acc_id, tx_id, .. } => ("release", acc_id, tx_id),
+            Operation::Authorize { acc_id, tx_id, amount: _ } => ("authorize", acc_id, tx_id),
+            Operation::Capture { acc_id, tx_id } => ("capture", acc_id, tx_id),
+            Operation::Expire { acc_id, tx_id } => ("expire", acc_id, tx_id),
         };
         op_str.hash(state);
         acc_id.hash(state);
@@ -41,494 +131,2614 @@ impl Hash for Operation {
     }
 }
 
+/// The optional inputs of [`Engine::execute_operation_with`] that are not part of the operation itself.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExecuteOptions<'a> {
+    /// The input source and its row the operation was read from, saved as the checkpoint of the source.
+    pub checkpoint: Option<(&'a str, u64)>,
+    /// The external id of the created deposit or withdrawal, ignored for the other operations.
+    pub external_id: Option<&'a str>,
+    /// Where the operation came from, journaled with it and recorded in the tracing span of the operation.
+    pub provenance: Option<&'a Provenance>,
+}
+
+/// The business rules that differ between deployments. The default is the classic behaviour:
+/// any deposit can be disputed, and a chargeback locks the account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnginePolicy {
+    /// Deposits older than this can no longer be disputed, `None` for no limit.
+    pub dispute_window: Option<Duration>,
+    /// Whether a chargeback locks the account, so it can not deposit or withdraw anymore.
+    pub lock_on_chargeback: bool,
+    /// The amounts of the deposits and withdrawals are rounded to this precision before anything else,
+    /// so the balances never have more decimal places; the account summary is written with as many decimal places.
+    pub rounding: Rounding,
+    /// Deposits and withdrawals of a larger amount are rejected, `None` for no limit.
+    pub max_amount: Option<Decimal4>,
+    /// Deposits that would take the total balance of the account over this are rejected, `None` for no limit.
+    pub max_balance: Option<Decimal4>,
+    /// The flat fee of a withdrawal, taken from the account on top of the amount and moved to [`SystemAccount::Fees`],
+    /// `None` for no fee.
+    pub withdrawal_fee: Option<Decimal4>,
+    /// Whether the negative balance left by a chargeback (the client already spent the disputed funds) is written off
+    /// to [`SystemAccount::ChargebackLosses`], so the client account ends at zero.
+    pub write_off_chargeback_losses: bool,
+    /// What a dispute does when the client already spent the disputed funds, so holding them would take the available
+    /// funds below zero.
+    pub negative_available: NegativeAvailablePolicy,
+    /// Whether an account locked by a chargeback is unlocked once it is settled: none of its disputes is open anymore
+    /// and its available funds are not negative (the losses of the chargebacks were written off or clamped).
+    pub auto_unlock: bool,
+    /// Authorizations not captured within this are released by [`Engine::expire_holds`], `None` for no expiry.
+    pub authorization_expiry: Option<Duration>,
+    /// Whether the rejection of a deposit, a withdrawal, an escrow move or an authorization is recorded with its idempotency key, so the
+    /// retries of the same operation fail with the original error instead of being validated again against the current state.
+    /// Only the final rejections are recorded, not the transient errors (`EngineError::is_transient`).
+    pub cache_rejections: bool,
+}
+
+/// See [`EnginePolicy::negative_available`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NegativeAvailablePolicy {
+    /// The whole amount is held, the available funds go negative.
+    #[default]
+    Allow,
+    /// Only the available funds are held, the rest is recorded as the shortfall of the transaction (`Transaction::shortfall()`).
+    /// A chargeback still takes the whole amount from the account.
+    Clamp,
+    /// The dispute is rejected with [`EngineError::DisputeExceedsAvailable`].
+    Reject,
+}
+
+impl Default for EnginePolicy {
+    fn default() -> Self {
+        Self {
+            dispute_window: None,
+            lock_on_chargeback: true,
+            rounding: Rounding::default(),
+            max_amount: None,
+            max_balance: None,
+            withdrawal_fee: None,
+            write_off_chargeback_losses: false,
+            negative_available: NegativeAvailablePolicy::Allow,
+            auto_unlock: false,
+            authorization_expiry: None,
+            cache_rejections: false,
+        }
+    }
+}
+
 pub struct Engine<TStorage: Storage> {
     storage: Arc<TStorage>,
+    observers: Vec<Arc<dyn EngineObserver>>,
+    policy: EnginePolicy,
+    risk_assessor: Option<Arc<dyn RiskAssessor>>,
+    paused: Arc<AtomicBool>,
+    clock: Arc<dyn Clock>,
+    write_options: TxOptions,
 }
 
 impl<TStorage: Storage> Engine<TStorage> {
     pub fn new(storage: TStorage) -> Self {
         Self {
             storage: Arc::new(storage),
+            observers: Vec::new(),
+            policy: EnginePolicy::default(),
+            risk_assessor: None,
+            paused: Arc::new(AtomicBool::new(false)),
+            clock: Arc::new(SystemClock),
+            write_options: TxOptions::read_write(),
         }
     }
 
-    pub async fn execute_operation(&self, operation: Operation) -> Result<(), EngineError> {
-        match operation {
-            Operation::Deposit { acc_id, tx_id, amount } => self.deposit(acc_id, tx_id, amount).await,
-            Operation::Withdraw { acc_id, tx_id, amount } => self.withdraw(acc_id, tx_id, amount).await,
-            Operation::Dispute { acc_id, tx_id } => self.dispute(acc_id, tx_id).await,
-            Operation::Resolve { acc_id, tx_id } => self.resolve(acc_id, tx_id).await,
-            Operation::Chargeback { acc_id, tx_id } => self.chargeback(acc_id, tx_id).await,
-        }
+    pub fn storage(&self) -> &TStorage {
+        &self.storage
     }
 
-    pub async fn get_account(&self, acc_id: u16) -> Result<Option<Account>, EngineError> {
-        let mut db_tx = self.storage.start_db_tx().await?;
-        let account = self.storage.get_account(&mut db_tx, acc_id).await?;
-        self.storage.commit_db_tx(db_tx).await?;
-        Ok(account)
+    /// Returns the storage, `None` while a clone of the engine (e.g. in a spawned task) still uses it.
+    pub fn into_storage(self) -> Option<TStorage> {
+        Arc::into_inner(self.storage)
     }
 
-    pub async fn get_all_accounts(&self) -> Result<Vec<Account>, EngineError> {
-        let mut db_tx = self.storage.start_db_tx().await?;
-        let accounts = self.storage.get_all_accounts(&mut db_tx).await?;
-        self.storage.commit_db_tx(db_tx).await?;
-        Ok(accounts)
+    pub fn policy(&self) -> &EnginePolicy {
+        &self.policy
     }
 
-    pub async fn deposit(&self, acc_id: u16, tx_id: u32, amount: Decimal4) -> Result<(), EngineError> {
-        if !amount.is_positive() {
-            return Err(EngineError::AmountIsNotPositive);
-        }
+    pub fn with_policy(mut self, policy: EnginePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
 
-        let mut db_tx = self.storage.start_db_tx().await?;
+    /// Registers an observer that is notified after every committed or rejected operation.
+    pub fn with_observer(mut self, observer: Arc<dyn EngineObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
 
-        let operation = Operation::Deposit { acc_id, tx_id, amount };
-        let op_hash = operation.get_hash_code();
-        let operation_processed = self.storage.is_operation_processed(&mut db_tx, op_hash).await?;
-        if operation_processed {
-            return Ok(()); // idempotency
+    /// Sets the [`RiskAssessor`] consulted before every deposit and withdrawal is committed.
+    pub fn with_risk_assessor(mut self, risk_assessor: Arc<dyn RiskAssessor>) -> Self {
+        self.risk_assessor = Some(risk_assessor);
+        self
+    }
+
+    /// Sets the [`Clock`] of the timestamps, the system clock by default. A `ManualClock` makes the time-dependent rules
+    /// (e.g. the dispute window) deterministic in tests.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Sets the isolation level of the storage transactions of the operations and the other changes, the default one of
+    /// the backend by default. The queries run in read-only storage transactions.
+    pub fn with_isolation(mut self, isolation: IsolationLevel) -> Self {
+        self.write_options = self.write_options.with_isolation(isolation);
+        self
+    }
+
+    /// Sets the locking strategy of the storage transactions of the operations and the other changes, optimistic by default:
+    /// an operation that loses the race for an account fails with `EngineError::ConcurrentOperationDetected`. With the
+    /// pessimistic locking the backends that support it make it wait instead.
+    pub fn with_locking(mut self, locking: LockingStrategy) -> Self {
+        self.write_options = self.write_options.with_locking(locking);
+        self
+    }
+
+    /// The options of the storage transactions that change the state, see [`Engine::with_isolation`] and [`Engine::with_locking`].
+    pub fn write_options(&self) -> TxOptions {
+        self.write_options
+    }
+
+    /// The current time of the engine clock, unix millis.
+    fn now(&self) -> u64 {
+        self.clock.now_millis()
+    }
+
+    /// Puts the engine into maintenance mode: the operations and the other changes of the state are rejected with
+    /// the transient `EngineError::Paused` while the queries keep working, e.g. to take a snapshot or run a migration.
+    /// The operations already in flight still complete. The clones and the tenant views of the engine share the mode.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        tracing::info!("engine paused");
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        tracing::info!("engine resumed");
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    fn ensure_running(&self) -> Result<(), EngineError> {
+        if self.is_paused() {
+            return Err(EngineError::Paused);
         }
+        Ok(())
+    }
+}
 
-        let maybe_tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
-        let transaction_exists = maybe_tx.is_some();
-        if transaction_exists {
-            return Err(EngineError::TransactionWithTheSameIdAlreadyExists);
+impl<TStorage: TenantStorage> Engine<TStorage> {
+    /// Scopes the engine to the isolated namespace of the given tenant: every operation and query only sees the accounts,
+    /// transactions, journal and checkpoints of that tenant. Set the tenant's own policy with `with_policy` afterwards.
+    pub fn with_tenant(self, tenant: &str) -> Self {
+        Self {
+            storage: Arc::new(self.storage.for_tenant(tenant)),
+            ..self
         }
+    }
 
-        let tx = Transaction::new(tx_id, acc_id, TransactionType::Deposit, amount);
-        self.storage.insert_tx(&mut db_tx, &tx).await?;
+    /// Returns an engine working on the isolated namespace of the given tenant, with the same observers, policy and risk assessor.
+    pub fn for_tenant(&self, tenant: &str) -> Self {
+        self.clone().with_tenant(tenant)
+    }
+}
 
-        let maybe_account = self.storage.get_account(&mut db_tx, acc_id).await?;
-        if let Some(old_acc) = maybe_account {
-            let mut new_acc = old_acc.clone();
-            new_acc.deposit(amount)?;
-            self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
-        } else {
-            let mut new_acc = Account::new(acc_id);
-            new_acc.deposit(amount)?;
-            self.storage.insert_account(&mut db_tx, &new_acc).await?;
+impl<TStorage: Storage + Journal> Engine<TStorage> {
+    pub async fn execute_operation(&self, operation: Operation) -> Result<(), EngineError> {
+        match self.round_amount(operation) {
+            Operation::Deposit { acc_id, tx_id, amount } => self.deposit(acc_id, tx_id, Decimal4::from(amount)).await,
+            Operation::Withdraw { acc_id, tx_id, amount } => self.withdraw(acc_id, tx_id, Decimal4::from(amount)).await,
+            Operation::Dispute { acc_id, tx_id } => self.dispute(acc_id, tx_id).await,
+            Operation::Resolve { acc_id, tx_id } => self.resolve(acc_id, tx_id).await,
+            Operation::Chargeback { acc_id, tx_id } => self.chargeback(acc_id, tx_id).await,
+            Operation::Escrow { acc_id, tx_id, bucket, amount } => self.escrow(acc_id, tx_id, &bucket, Decimal4::from(amount)).await,
+            Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount } => self.release_escrow(acc_id, tx_id, &bucket, Decimal4::from(amount)).await,
+            Operation::Authorize { acc_id, tx_id, amount } => self.authorize(acc_id, tx_id, Decimal4::from(amount)).await,
+            Operation::Capture { acc_id, tx_id } => self.capture(acc_id, tx_id).await,
+            Operation::Expire { acc_id, tx_id } => self.expire_hold(acc_id, tx_id, self.now()).await,
         }
+    }
 
-        self.storage.insert_operation(&mut db_tx, op_hash).await?;
-        self.storage.commit_db_tx(db_tx).await?;
-        Ok(())
+    /// Executes the operation read from the row `rows` of the input `source` and records that row as the checkpoint
+    /// of the source in the same storage transaction, so a resumed run continues exactly after the last applied row.
+    /// Rejected (and already processed) operations change nothing, the checkpoint stays at the previous row.
+    pub async fn execute_operation_with_checkpoint(&self, operation: Operation, source: &str, rows: u64) -> Result<(), EngineError> {
+        self.execute_operation_with(operation, ExecuteOptions { checkpoint: Some((source, rows)), ..ExecuteOptions::default() }).await
+    }
+
+    /// Executes the operation with the given [`ExecuteOptions`], see [`Engine::execute_operation_with_checkpoint`].
+    pub async fn execute_operation_with(&self, operation: Operation, options: ExecuteOptions<'_>) -> Result<(), EngineError> {
+        let operation = self.round_amount(operation);
+        let apply = async {
+            match operation.clone() {
+                Operation::Deposit { acc_id, tx_id, amount } => self.apply_deposit(acc_id, tx_id, Decimal4::from(amount), options).await,
+                Operation::Withdraw { acc_id, tx_id, amount } => self.apply_withdraw(acc_id, tx_id, Decimal4::from(amount), options).await,
+                Operation::Dispute { acc_id, tx_id } => self.apply_dispute(acc_id, tx_id, options).await,
+                Operation::Resolve { acc_id, tx_id } => self.apply_resolve(acc_id, tx_id, options).await,
+                Operation::Chargeback { acc_id, tx_id } => self.apply_chargeback(acc_id, tx_id, options).await,
+                Operation::Escrow { acc_id, tx_id, bucket, amount } => self.apply_escrow(acc_id, tx_id, bucket, Decimal4::from(amount), false, options).await,
+                Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount } => self.apply_escrow(acc_id, tx_id, bucket, Decimal4::from(amount), true, options).await,
+                Operation::Authorize { acc_id, tx_id, amount } => self.apply_authorize(acc_id, tx_id, Decimal4::from(amount), options).await,
+                Operation::Capture { acc_id, tx_id } => self.apply_capture(acc_id, tx_id, false, self.now(), options).await,
+                Operation::Expire { acc_id, tx_id } => self.apply_capture(acc_id, tx_id, true, self.now(), options).await,
+            }
+        };
+        self.run(operation.clone(), options.provenance, apply).await
     }
 
-    pub async fn withdraw(&self, acc_id: u16, tx_id: u32, amount: Decimal4) -> Result<(), EngineError> {
-        if !amount.is_positive() {
-            return Err(EngineError::AmountIsNotPositive);
+    /// A dry run of the operation: validates it against the current state like [`Engine::execute_operation_with`] would
+    /// (including the uniqueness of the `external_id`), and returns the account as the operation would leave it, without
+    /// changing anything. An operation already applied is valid, the engine is idempotent. The risk assessor is not consulted.
+    pub async fn validate_operation(&self, operation: Operation, external_id: Option<&str>) -> Result<Account, EngineError> {
+        let operation = self.round_amount(operation);
+        let validator = self.validator();
+        validator.check_operation(&operation)?;
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let maybe_account = self.storage.get_account(&mut db_tx, operation.acc_id()).await?;
+        let creates_tx = matches!(operation, Operation::Deposit { .. } | Operation::Withdraw { .. } | Operation::Escrow { .. } | Operation::ReleaseEscrow { .. } | Operation::Authorize { .. });
+        if creates_tx && self.is_operation_processed(&mut db_tx, operation.get_hash_code()).await? {
+            return maybe_account.ok_or(EngineError::AccountNotFound);
         }
+        let maybe_tx = self.storage.get_tx(&mut db_tx, operation.tx_id()).await?;
+        let validated = validator.validate(&operation, maybe_account.as_ref(), maybe_tx.as_ref(), self.now())?;
+        if creates_tx {
+            self.check_external_id(&mut db_tx, external_id).await?;
+        }
+        Ok(validated.account)
+    }
 
-        let mut db_tx = self.storage.start_db_tx().await?;
+    /// The [`Validator`] of the policy of the engine.
+    pub fn validator(&self) -> Validator {
+        Validator::new(self.policy)
+    }
 
-        let operation = Operation::Withdraw { acc_id, tx_id, amount };
-        let op_hash = operation.get_hash_code();
-        let operation_processed = self.storage.is_operation_processed(&mut db_tx, op_hash).await?;
-        if operation_processed {
-            return Ok(()); // idempotency
+    /// Rounds the amount of the operation to the policy precision.
+    fn round_amount(&self, operation: Operation) -> Operation {
+        let round = |amount| Decimal::from(self.policy.rounding.round(amount));
+        match operation {
+            Operation::Deposit { acc_id, tx_id, amount } => Operation::Deposit { acc_id, tx_id, amount: round(amount) },
+            Operation::Withdraw { acc_id, tx_id, amount } => Operation::Withdraw { acc_id, tx_id, amount: round(amount) },
+            Operation::Escrow { acc_id, tx_id, bucket, amount } => Operation::Escrow { acc_id, tx_id, bucket, amount: round(amount) },
+            Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount } => Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount: round(amount) },
+            Operation::Authorize { acc_id, tx_id, amount } => Operation::Authorize { acc_id, tx_id, amount: round(amount) },
+            operation => operation,
         }
+    }
 
-        let maybe_tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
-        let transaction_exists = maybe_tx.is_some();
-        if transaction_exists {
-            return Err(EngineError::TransactionWithTheSameIdAlreadyExists);
-        }
+    /// Returns the number of rows of the input `source` already applied by `execute_operation_with_checkpoint`.
+    pub async fn get_checkpoint(&self, source: &str) -> Result<Option<u64>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let rows = self.storage.get_checkpoint(&mut db_tx, source).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(rows)
+    }
 
-        let maybe_account = self.storage.get_account(&mut db_tx, acc_id).await?;
-        let old_acc = maybe_account.ok_or(EngineError::AccountNotFound)?;
+    /// Replaces the metadata of an existing account and returns the updated account. The metadata is not journaled
+    /// (it never affects the balances) and notifies no observers.
+    pub async fn set_account_metadata(&self, acc_id: ClientId, metadata: AccountMetadata) -> Result<Account, EngineError> {
+        self.ensure_running()?;
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+        let old_acc = self.storage.get_account(&mut db_tx, acc_id).await?.ok_or(EngineError::AccountNotFound)?;
         let mut new_acc = old_acc.clone();
-        new_acc.withdraw(amount)?;
-
-        let tx = Transaction::new(tx_id, acc_id, TransactionType::Withdrawal, amount);
-        self.storage.insert_tx(&mut db_tx, &tx).await?;
+        new_acc.set_metadata(metadata, self.now());
         self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
-        self.storage.insert_operation(&mut db_tx, op_hash).await?;
         self.storage.commit_db_tx(db_tx).await?;
-        Ok(())
+        Ok(new_acc)
     }
 
-    pub async fn dispute(&self, acc_id: u16, tx_id: u32) -> Result<(), EngineError> {
-        let mut db_tx = self.storage.start_db_tx().await?;
+    /// Moves an existing account to another [`AccountStatus`] and returns the updated account, e.g. to unlock an account
+    /// locked by a chargeback or to close an account without funds. Like the metadata, the status changes are not journaled
+    /// and notify no observers.
+    pub async fn set_account_status(&self, acc_id: ClientId, status: AccountStatus) -> Result<Account, EngineError> {
+        self.ensure_running()?;
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+        let old_acc = self.storage.get_account(&mut db_tx, acc_id).await?.ok_or(EngineError::AccountNotFound)?;
+        let mut new_acc = old_acc.clone();
+        new_acc.set_status(status, self.now())?;
+        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(new_acc)
+    }
 
-        let maybe_tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
-        let old_tx = maybe_tx.ok_or(EngineError::TransactionNotFound)?;
-        if old_tx.account_id() != acc_id {
-            return Err(EngineError::TransactionIsBoundToAnotherAccount(old_tx.account_id()));
+    /// Collects all stored data of a client (the account, its transactions and its journal entries) in one storage
+    /// transaction, e.g. for a data access request. `None` if there is no such account.
+    pub async fn export_account_data(&self, acc_id: ClientId) -> Result<Option<AccountDataExport>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let Some(account) = self.storage.get_account(&mut db_tx, acc_id).await? else {
+            return Ok(None);
+        };
+        let transactions = self.read_account_txs(&mut db_tx, acc_id).await?;
+        let mut journal = Vec::new();
+        let mut pages = JournalPages::new(1);
+        while let Some(entries) = pages.next_page(&*self.storage, &mut db_tx).await? {
+            journal.extend(entries.into_iter().filter(|x| x.account().id() == acc_id));
         }
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(Some(AccountDataExport { exported_at: self.now(), account, transactions, journal }))
+    }
 
-        let maybe_account = self.storage.get_account(&mut db_tx, acc_id).await?;
-        let old_acc = maybe_account.ok_or(EngineError::AccountNotFound)?;
-
-        let mut new_tx = old_tx.clone();
-        new_tx.set_state(TransactionState::Disputed)?;
-
+    /// Anonymizes an account for an erasure request: clears its metadata and returns the updated account.
+    /// The balances, the transactions and the journal are kept, they are financial records. An account with open disputes
+    /// can not be erased (`EngineError::OpenDisputes`), the dispute still needs the data.
+    pub async fn erase_account(&self, acc_id: ClientId) -> Result<Account, EngineError> {
+        self.ensure_running()?;
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+        let old_acc = self.storage.get_account(&mut db_tx, acc_id).await?.ok_or(EngineError::AccountNotFound)?;
+        let mut pages = AccountTxPages::new(acc_id);
+        while let Some(page) = pages.next_page(&*self.storage, &mut db_tx).await? {
+            if page.iter().any(|x| x.state() == TransactionState::Disputed) {
+                return Err(EngineError::OpenDisputes);
+            }
+        }
         let mut new_acc = old_acc.clone();
-        new_acc.dispute(new_tx.amount())?;
-
-        self.storage.update_tx(&mut db_tx, &old_tx, &new_tx).await?;
+        new_acc.set_metadata(AccountMetadata::default(), self.now());
         self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
         self.storage.commit_db_tx(db_tx).await?;
-        Ok(())
+        Ok(new_acc)
     }
 
-    pub async fn resolve(&self, acc_id: u16, tx_id: u32) -> Result<(), EngineError> {
-        let mut db_tx = self.storage.start_db_tx().await?;
+    pub async fn get_account(&self, acc_id: ClientId) -> Result<Option<Account>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let account = self.storage.get_account(&mut db_tx, acc_id).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(account)
+    }
 
-        let maybe_tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
-        let old_tx = maybe_tx.ok_or(EngineError::TransactionNotFound)?;
-        if old_tx.account_id() != acc_id {
-            return Err(EngineError::TransactionIsBoundToAnotherAccount(old_tx.account_id()));
-        }
+    /// Returns the balances of a [`SystemAccount`], an empty account before its first posting.
+    pub async fn get_system_account(&self, account: SystemAccount) -> Result<Account, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let acc = self.storage.get_system_account(&mut db_tx, account).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(acc.unwrap_or_default())
+    }
 
-        let maybe_account = self.storage.get_account(&mut db_tx, acc_id).await?;
-        let old_acc = maybe_account.ok_or(EngineError::AccountNotFound)?;
+    pub async fn get_tx(&self, tx_id: TxId) -> Result<Option<Transaction>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(tx)
+    }
 
-        let mut new_tx = old_tx.clone();
-        new_tx.set_state(TransactionState::Posted)?;
+    /// Returns the transaction created with the given external id, see [`ExecuteOptions::external_id`].
+    pub async fn get_tx_by_external_id(&self, external_id: &str) -> Result<Option<Transaction>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let tx = self.storage.get_tx_by_external_id(&mut db_tx, external_id).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(tx)
+    }
 
-        let mut new_acc = old_acc.clone();
-        new_acc.resolve(new_tx.amount())?;
+    /// Returns up to `limit` transactions of the account ordered by id, starting after the `cursor` transaction id.
+    pub async fn get_txs_by_account(&self, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let txs = self.storage.get_txs_by_account(&mut db_tx, acc_id, cursor, limit).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(txs)
+    }
 
-        self.storage.update_tx(&mut db_tx, &old_tx, &new_tx).await?;
-        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+    /// Returns the open disputes matching the filter, the oldest first, with how long they have been open.
+    /// The disputes of one account are read through the transaction index, all the others with a scan of the transactions.
+    pub async fn get_open_disputes(&self, filter: DisputeFilter) -> Result<OpenDisputesReport, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let txs = match filter.client {
+            Some(acc_id) => self.read_account_txs(&mut db_tx, acc_id).await?,
+            None => self.storage.get_all_txs(&mut db_tx).await?,
+        };
         self.storage.commit_db_tx(db_tx).await?;
-        Ok(())
+        let now = self.now();
+        let disputes = txs.iter()
+            .filter_map(|x| OpenDispute::from_transaction(x, now))
+            .filter(|x| filter.min_age_ms.is_none_or(|min_age_ms| x.age_ms >= min_age_ms))
+            .collect();
+        Ok(OpenDisputesReport::new(now, disputes))
     }
 
-    pub async fn chargeback(&self, acc_id: u16, tx_id: u32) -> Result<(), EngineError> {
-        let mut db_tx = self.storage.start_db_tx().await?;
+    /// Returns the transactions flagged for a manual review by the [`RiskAssessor`], ordered by id.
+    pub async fn get_pending_reviews(&self) -> Result<Vec<Transaction>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let mut txs = self.storage.get_all_txs(&mut db_tx).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        txs.retain(Transaction::pending_review);
+        txs.sort_by_key(Transaction::id);
+        Ok(txs)
+    }
 
-        let maybe_tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
-        let old_tx = maybe_tx.ok_or(EngineError::TransactionNotFound)?;
-        if old_tx.account_id() != acc_id {
-            return Err(EngineError::TransactionIsBoundToAnotherAccount(old_tx.account_id()));
-        }
+    pub async fn get_all_accounts(&self) -> Result<Vec<Account>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let accounts = self.storage.get_all_accounts(&mut db_tx).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(accounts)
+    }
 
-        let maybe_account = self.storage.get_account(&mut db_tx, acc_id).await?;
-        let old_acc = maybe_account.ok_or(EngineError::AccountNotFound)?;
+    /// Returns the accounts with the given ids in the same order, `None` for the missing ones.
+    pub async fn get_accounts(&self, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let accounts = self.storage.get_accounts(&mut db_tx, acc_ids).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(accounts)
+    }
 
-        let mut new_tx = old_tx.clone();
-        new_tx.set_state(TransactionState::Chargeback)?;
+    /// Returns up to `limit` accounts in the storage order, starting after the `cursor` account id (the last id of the previous page).
+    pub async fn list_accounts(&self, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let accounts = self.storage.list_accounts(&mut db_tx, cursor, limit).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(accounts)
+    }
 
-        let mut new_acc = old_acc.clone();
-        new_acc.chargeback(new_tx.amount())?;
+    /// Streams all accounts, loading them page by page. Each page is read in its own storage transaction.
+    pub fn stream_accounts(&self, page_size: usize) -> impl Stream<Item = Result<Account, EngineError>> + '_ {
+        futures::stream::try_unfold(Some(None), move |cursor: Option<Option<ClientId>>| async move {
+            let Some(cursor) = cursor else {
+                return Ok::<_, EngineError>(None);
+            };
+            let page = self.list_accounts(cursor, page_size).await?;
+            let next_cursor = if page.len() < page_size { None } else { page.last().map(|x| Some(x.id())) };
+            Ok(Some((futures::stream::iter(page.into_iter().map(Ok)), next_cursor)))
+        })
+        .try_flatten()
+    }
 
-        self.storage.update_tx(&mut db_tx, &old_tx, &new_tx).await?;
-        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+    pub async fn get_journal_entries(&self, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let entries = self.storage.get_journal_entries(&mut db_tx, from_seq, limit).await?;
         self.storage.commit_db_tx(db_tx).await?;
-        Ok(())
+        Ok(entries)
     }
-}
 
-impl<TStorage: Storage> Debug for Engine<TStorage> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Engine").finish()
+    /// Removes the idempotency records of operations applied before the `older_than` timestamp (unix millis)
+    /// and returns their count. Replaying a pruned deposit or withdrawal is rejected instead of being ignored.
+    pub async fn prune_operations(&self, older_than: u64) -> Result<usize, EngineError> {
+        self.ensure_running()?;
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+        let pruned = self.storage.prune_operations(&mut db_tx, older_than).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(pruned)
     }
-}
 
-impl<TStorage: Storage> Clone for Engine<TStorage> {
-    fn clone(&self) -> Self {
-        Self {
-            storage: self.storage.clone(),
-        }
+    /// Rebuilds all accounts and transactions by re-applying every journaled operation from scratch.
+    pub async fn rebuild_from_journal(&self) -> Result<ReplayState, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let (state, _) = self.replay_journal(&mut db_tx, None).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(state)
     }
-}
 
-#[derive(Error, Debug, PartialEq, Eq)]
-pub enum EngineError {
-    #[error("account not found")]
-    AccountNotFound,
+    /// Replays the journal up to the given point (inclusive) and returns the account as it was at that moment,
+    /// or `None` if the account did not exist yet.
+    pub async fn balance_as_of(&self, acc_id: ClientId, point: PointInTime) -> Result<Option<Account>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let (state, _) = self.replay_journal(&mut db_tx, Some(point)).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(state.accounts().get(&acc_id).cloned())
+    }
 
-    #[error("transaction not found")]
-    TransactionNotFound,
+    /// Rebuilds the state from the journal and reports every difference from the current storage.
+    /// An empty result means that the storage is consistent with the journal.
+    pub async fn verify_journal(&self) -> Result<Vec<Divergence>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let (state, mut divergences) = self.replay_journal(&mut db_tx, None).await?;
+
+        for expected in state.accounts().values() {
+            let actual = self.storage.get_account(&mut db_tx, expected.id()).await?;
+            // NOTE: the metadata and status updates are not journaled, only the balances can be verified
+            if !actual.as_ref().is_some_and(|x| x.same_balances(expected)) {
+                divergences.push(Divergence::AccountMismatch { expected: expected.clone(), actual });
+            }
+        }
+        for actual in self.storage.get_all_accounts(&mut db_tx).await? {
+            if !state.accounts().contains_key(&actual.id()) {
+                divergences.push(Divergence::UnexpectedAccount(actual));
+            }
+        }
+        for account in SystemAccount::ALL {
+            let expected = state.system_accounts().get(&account).cloned().unwrap_or_default();
+            let actual = self.storage.get_system_account(&mut db_tx, account).await?;
+            if !actual.as_ref().unwrap_or(&Account::default()).same_balances(&expected) {
+                divergences.push(Divergence::SystemAccountMismatch { account, expected, actual });
+            }
+        }
+        for expected in state.transactions().values() {
+            let actual = self.storage.get_tx(&mut db_tx, expected.id()).await?;
+            if actual.as_ref() != Some(expected) {
+                divergences.push(Divergence::TransactionMismatch { expected: expected.clone(), actual });
+            }
+        }
 
-    #[error("account is locked")]
-    AccountLocked,
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(divergences)
+    }
 
-    #[error("insufficient funds")]
-    InsufficientFunds,
+    /// Checks the hash chain of the journal, see [`ChainVerifier`]. `expected_head` is the head of an earlier verification,
+    /// kept outside of the storage to also detect a truncated or rewritten journal.
+    pub async fn verify_journal_chain(&self, expected_head: Option<(u64, Digest)>) -> Result<ChainReport, EngineError> {
+        let mut verifier = ChainVerifier::new(expected_head);
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let mut pages = JournalPages::new(1);
+        while let Some(entries) = pages.next_page(&*self.storage, &mut db_tx).await? {
+            entries.iter().for_each(|x| verifier.verify(x));
+        }
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(verifier.finish())
+    }
 
-    #[error("amount is not positive")]
-    AmountIsNotPositive,
+    /// Checks the sequence numbers of the operations of every account in the journal, see [`AccountSeqVerifier`].
+    pub async fn verify_account_sequences(&self) -> Result<Vec<AccountSeqViolation>, EngineError> {
+        let mut verifier = AccountSeqVerifier::new();
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let mut pages = JournalPages::new(1);
+        while let Some(entries) = pages.next_page(&*self.storage, &mut db_tx).await? {
+            entries.iter().for_each(|x| verifier.verify(x));
+        }
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(verifier.finish())
+    }
 
-    #[error("transaction with the same id already exists")]
-    TransactionWithTheSameIdAlreadyExists,
+    async fn replay_journal(&self, db_tx: &mut TStorage::DbTx, until: Option<PointInTime>) -> Result<(ReplayState, Vec<Divergence>), EngineError> {
+        let mut state = ReplayState::new();
+        let mut divergences = Vec::new();
+        let mut pages = JournalPages::new(1);
+        while let Some(entries) = pages.next_page(&*self.storage, db_tx).await? {
+            for entry in entries.iter() {
+                if until.is_some_and(|x| !x.includes(entry)) {
+                    return Ok((state, divergences));
+                }
+                let matches = state.apply(entry).map_err(|_| EngineError::CorruptedJournal(entry.seq()))?;
+                if !matches {
+                    divergences.push(Divergence::JournalEntryMismatch { seq: entry.seq() });
+                }
+            }
+        }
+        Ok((state, divergences))
+    }
 
-    #[error("transaction is bound to another account")]
-    TransactionIsBoundToAnotherAccount(u16),
+    /// Returns the account activity in the `[from, to)` period (unix millis) with running balances, built from the journal.
+    /// The journal is read page by page, only the entries of the account are kept.
+    pub async fn get_statement(&self, acc_id: ClientId, from: u64, to: u64) -> Result<Statement, EngineError> {
+        let mut statement = Statement::new(acc_id, from, to);
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let mut pages = JournalPages::new(1);
+        while let Some(entries) = pages.next_page(&*self.storage, &mut db_tx).await? {
+            entries.iter().try_for_each(|x| statement.push_entry(x))?;
+        }
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(statement)
+    }
 
-    #[error("invalid transaction type: only deposits can be disputed/resolved/chargebacked")]
-    InvalidTxType,
+    /// Nets the activity of every account in the `[from, to)` period (unix millis) into a [`Settlement`], e.g. for the end of a day
+    /// with [`crate::settlement::day_range`]. The transactions are read account by account through the transaction index,
+    /// by their creation and chargeback timestamps.
+    pub async fn get_settlement(&self, from: u64, to: u64) -> Result<Settlement, EngineError> {
+        const PAGE_SIZE: usize = 1000;
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let mut records = Vec::new();
+        let mut acc_cursor = None;
+        loop {
+            let accounts = self.storage.list_accounts(&mut db_tx, acc_cursor, PAGE_SIZE).await?;
+            for account in accounts.iter() {
+                let transactions = self.read_account_txs(&mut db_tx, account.id()).await?;
+                records.extend(SettlementRecord::from_transactions(account.id(), from, to, transactions.iter()));
+            }
+            match accounts.last() {
+                Some(last) if accounts.len() == PAGE_SIZE => acc_cursor = Some(last.id()),
+                _ => break,
+            }
+        }
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(Settlement::new(from, to, records))
+    }
 
-    #[error("forbidden state transition from {from:?} to {to:?}")]
-    ForbiddenTxStateTransition { from: TransactionState, to: TransactionState },
+    /// Counts the deposits and the chargebacks of an account, read through the transaction index.
+    pub async fn get_chargeback_stats(&self, acc_id: ClientId) -> Result<ChargebackStats, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let transactions = self.read_account_txs(&mut db_tx, acc_id).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(ChargebackStats::from_transactions(acc_id, transactions.iter()))
+    }
 
-    #[error("concurrent operation detected for the same entities")]
-    ConcurrentOperationDetected,
+    /// Lists the accounts with at least `min_deposits` deposits whose chargeback ratio is above `max_ratio`, see [`ChargebackRatioReport`].
+    pub async fn get_chargeback_report(&self, max_ratio: Decimal4, min_deposits: u64) -> Result<ChargebackRatioReport, EngineError> {
+        const PAGE_SIZE: usize = 1000;
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let mut stats = Vec::new();
+        let mut acc_cursor = None;
+        loop {
+            let accounts = self.storage.list_accounts(&mut db_tx, acc_cursor, PAGE_SIZE).await?;
+            for account in accounts.iter() {
+                let transactions = self.read_account_txs(&mut db_tx, account.id()).await?;
+                stats.push(ChargebackStats::from_transactions(account.id(), transactions.iter()));
+            }
+            match accounts.last() {
+                Some(last) if accounts.len() == PAGE_SIZE => acc_cursor = Some(last.id()),
+                _ => break,
+            }
+        }
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(ChargebackRatioReport::new(max_ratio, min_deposits, stats))
+    }
 
-    #[error("database error: {0}")]
-    DatabaseError(String),
-}
+    /// Reads all the transactions of an account through the transaction index, page by page.
+    async fn read_account_txs(&self, db_tx: &mut TStorage::DbTx, acc_id: ClientId) -> Result<Vec<Transaction>, EngineError> {
+        let mut transactions = Vec::new();
+        let mut pages = AccountTxPages::new(acc_id);
+        while let Some(page) = pages.next_page(&*self.storage, db_tx).await? {
+            transactions.extend(page);
+        }
+        Ok(transactions)
+    }
 
-impl From<DbError> for EngineError {
-    fn from(err: DbError) -> Self {
-        match err {
-            DbError::EntityAlreadyExists => EngineError::ConcurrentOperationDetected,
-            DbError::ConcurrentModification => EngineError::ConcurrentOperationDetected,
-            DbError::DatabaseError(msg) => EngineError::DatabaseError(msg),
+    /// Runs the AML checks over the whole journal, so the report also covers the operations applied before a restart.
+    /// The journal is read page by page.
+    pub async fn get_aml_report(&self, config: AmlConfig) -> Result<SuspiciousActivityReport, EngineError> {
+        let mut monitor = AmlMonitor::new(config);
+        let mut flags = Vec::new();
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let mut pages = JournalPages::new(1);
+        while let Some(entries) = pages.next_page(&*self.storage, &mut db_tx).await? {
+            entries.iter().for_each(|x| flags.extend(monitor.check_entry(x)));
         }
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(SuspiciousActivityReport { threshold: config.threshold, flags })
     }
-}
 
-impl From<AccountUpdateError> for EngineError {
-    fn from(err: AccountUpdateError) -> Self {
-        match err {
-            AccountUpdateError::AccountLocked => EngineError::AccountLocked,
-            AccountUpdateError::InsufficientFunds => EngineError::InsufficientFunds,
-            AccountUpdateError::AmountIsNotPositive => EngineError::AmountIsNotPositive,
+    /// Recomputes every account's balances from its transaction history and reports the accounts that differ from storage.
+    pub async fn reconcile(&self) -> Result<ReconciliationReport, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let accounts = self.storage.get_all_accounts(&mut db_tx).await?;
+        let system_accounts = self.read_system_accounts(&mut db_tx).await?;
+        let transactions = self.storage.get_all_txs(&mut db_tx).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(reconcile_with(&accounts, &system_accounts, &transactions, self.policy.lock_on_chargeback))
+    }
+
+    /// Reads the [`SystemAccount`]s posted to so far.
+    async fn read_system_accounts(&self, db_tx: &mut TStorage::DbTx) -> Result<BTreeMap<SystemAccount, Account>, EngineError> {
+        let mut system_accounts = BTreeMap::new();
+        for account in SystemAccount::ALL {
+            if let Some(acc) = self.storage.get_system_account(db_tx, account).await? {
+                system_accounts.insert(account, acc);
+            }
         }
+        Ok(system_accounts)
     }
-}
 
-impl From<TxUpdateError> for EngineError {
-    fn from(err: TxUpdateError) -> Self {
-        match err {
-            TxUpdateError::InvalidTxType => EngineError::InvalidTxType,
-            TxUpdateError::ForbiddenTxStateTransition { from, to } => EngineError::ForbiddenTxStateTransition { from, to },
+    /// Writes all accounts (the system accounts too), transactions, idempotency records and journal entries as a versioned binary snapshot.
+    /// The journal is written page by page.
+    pub async fn export_snapshot<W: std::io::Write>(&self, writer: &mut W) -> Result<(), EngineError> {
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let accounts = self.storage.get_all_accounts(&mut db_tx).await?;
+        let system_accounts = self.read_system_accounts(&mut db_tx).await?;
+        let transactions = self.storage.get_all_txs(&mut db_tx).await?;
+        let operations = self.storage.get_all_operations(&mut db_tx).await?;
+        // NOTE: the journal is numbered from 1 without gaps
+        let journal_len = self.storage.get_last_journal_seq(&mut db_tx).await? as usize;
+        let mut snapshot_writer = SnapshotWriter::begin(writer, &accounts, &system_accounts, &transactions, &operations, journal_len)?;
+        let mut pages = JournalPages::new(1);
+        while let Some(entries) = pages.next_page(&*self.storage, &mut db_tx).await? {
+            snapshot_writer.write_journal(&entries)?;
+        }
+        self.storage.commit_db_tx(db_tx).await?;
+        snapshot_writer.finish()?;
+        Ok(())
+    }
+
+    /// Restores the state written by [`Engine::export_snapshot`]. The storage must be empty.
+    pub async fn import_snapshot<R: std::io::Read>(&self, reader: &mut R) -> Result<(), EngineError> {
+        self.ensure_running()?;
+        let snapshot = Snapshot::read(reader)?;
+
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+        let storage_is_empty = self.storage.get_all_accounts(&mut db_tx).await?.is_empty()
+            && self.storage.get_all_txs(&mut db_tx).await?.is_empty()
+            && self.storage.get_all_operations(&mut db_tx).await?.is_empty()
+            && self.storage.get_last_journal_seq(&mut db_tx).await? == 0;
+        if !storage_is_empty {
+            return Err(SnapshotError::StorageNotEmpty.into());
         }
+
+        self.storage.insert_accounts(&mut db_tx, &snapshot.accounts).await?;
+        for (account, acc) in snapshot.system_accounts.iter() {
+            self.storage.set_system_account(&mut db_tx, *account, acc).await?;
+        }
+        self.storage.insert_txs(&mut db_tx, &snapshot.transactions).await?;
+        let imported_at = self.now(); // NOTE: snapshots don't keep the record timestamps, so the retention starts over
+        for op_hash in snapshot.operations.iter() {
+            self.storage.insert_operation(&mut db_tx, *op_hash, imported_at).await?;
+        }
+        for entry in snapshot.journal.iter() {
+            self.storage.append_journal_entry(&mut db_tx, entry).await?;
+        }
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(())
+    }
+
+    pub async fn deposit(&self, acc_id: ClientId, tx_id: TxId, amount: Decimal4) -> Result<(), EngineError> {
+        let amount = amount.round(self.policy.rounding);
+        self.run(Operation::Deposit { acc_id, tx_id, amount: amount.into() }, None, self.apply_deposit(acc_id, tx_id, amount, ExecuteOptions::default())).await
+    }
+
+    pub async fn withdraw(&self, acc_id: ClientId, tx_id: TxId, amount: Decimal4) -> Result<(), EngineError> {
+        let amount = amount.round(self.policy.rounding);
+        self.run(Operation::Withdraw { acc_id, tx_id, amount: amount.into() }, None, self.apply_withdraw(acc_id, tx_id, amount, ExecuteOptions::default())).await
+    }
+
+    pub async fn dispute(&self, acc_id: ClientId, tx_id: TxId) -> Result<(), EngineError> {
+        self.run(Operation::Dispute { acc_id, tx_id }, None, self.apply_dispute(acc_id, tx_id, ExecuteOptions::default())).await
+    }
+
+    pub async fn resolve(&self, acc_id: ClientId, tx_id: TxId) -> Result<(), EngineError> {
+        self.run(Operation::Resolve { acc_id, tx_id }, None, self.apply_resolve(acc_id, tx_id, ExecuteOptions::default())).await
+    }
+
+    pub async fn chargeback(&self, acc_id: ClientId, tx_id: TxId) -> Result<(), EngineError> {
+        self.run(Operation::Chargeback { acc_id, tx_id }, None, self.apply_chargeback(acc_id, tx_id, ExecuteOptions::default())).await
+    }
+
+    /// Parks `amount` of the available funds in the escrow `bucket` of the account (e.g. the funds of a marketplace seller
+    /// pending the delivery confirmation), where they can't be withdrawn until released. The move is a transaction of its own.
+    pub async fn escrow(&self, acc_id: ClientId, tx_id: TxId, bucket: &str, amount: Decimal4) -> Result<(), EngineError> {
+        let amount = amount.round(self.policy.rounding);
+        let operation = Operation::Escrow { acc_id, tx_id, bucket: bucket.to_string(), amount: amount.into() };
+        self.run(operation, None, self.apply_escrow(acc_id, tx_id, bucket.to_string(), amount, false, ExecuteOptions::default())).await
+    }
+
+    /// Moves `amount` of the escrow `bucket` back to the available funds of the account, see [`Engine::escrow`].
+    pub async fn release_escrow(&self, acc_id: ClientId, tx_id: TxId, bucket: &str, amount: Decimal4) -> Result<(), EngineError> {
+        let amount = amount.round(self.policy.rounding);
+        let operation = Operation::ReleaseEscrow { acc_id, tx_id, bucket: bucket.to_string(), amount: amount.into() };
+        self.run(operation, None, self.apply_escrow(acc_id, tx_id, bucket.to_string(), amount, true, ExecuteOptions::default())).await
+    }
+
+    /// Holds `amount` of the available funds of the account for a later capture, e.g. a card payment authorized before the
+    /// goods ship. The authorization is a transaction of its own, which expires after [`EnginePolicy::authorization_expiry`].
+    pub async fn authorize(&self, acc_id: ClientId, tx_id: TxId, amount: Decimal4) -> Result<(), EngineError> {
+        let amount = amount.round(self.policy.rounding);
+        self.run(Operation::Authorize { acc_id, tx_id, amount: amount.into() }, None, self.apply_authorize(acc_id, tx_id, amount, ExecuteOptions::default())).await
+    }
+
+    /// Takes the held funds of the authorization `tx_id` from the account. An expired authorization can not be captured,
+    /// even before [`Engine::expire_holds`] released its funds.
+    pub async fn capture(&self, acc_id: ClientId, tx_id: TxId) -> Result<(), EngineError> {
+        self.run(Operation::Capture { acc_id, tx_id }, None, self.apply_capture(acc_id, tx_id, false, self.now(), ExecuteOptions::default())).await
+    }
+
+    /// Releases the held funds of every authorization not captured before its expiry at the `now` timestamp (unix millis),
+    /// each one in its own storage transaction journaled as an [`Operation::Expire`], and returns how many were released.
+    /// The authorizations are read through the expiry index of the storage, the earliest expiry first. The ones captured
+    /// or expired by another run since the scan are skipped; a transient failure stops the run, the next one picks up the
+    /// rest, and any other failure is logged and leaves the authorization to the next run.
+    pub async fn expire_holds(&self, now: u64) -> Result<usize, EngineError> {
+        self.ensure_running()?;
+        let mut db_tx = self.storage.start_db_tx(TxOptions::read_only()).await?;
+        let txs = self.storage.get_expired_authorizations(&mut db_tx, now).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        let mut expired = 0;
+        for tx in txs.iter() {
+            match self.expire_hold(tx.account_id(), tx.id(), now).await {
+                Ok(()) => expired += 1,
+                Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Captured | TransactionState::Expired, .. }) => {}
+                Err(err) if err.is_transient() => return Err(err),
+                Err(err) => tracing::warn!(acc_id = %redact::client(tx.account_id()), tx_id = tx.id(), error = %redact::error(&err), "failed to expire the authorization"),
+            }
+        }
+        Ok(expired)
+    }
+
+    async fn expire_hold(&self, acc_id: ClientId, tx_id: TxId, now: u64) -> Result<(), EngineError> {
+        self.run(Operation::Expire { acc_id, tx_id }, None, self.apply_capture(acc_id, tx_id, true, now, ExecuteOptions::default())).await
+    }
+
+    async fn apply_deposit(&self, acc_id: ClientId, tx_id: TxId, amount: Decimal4, options: ExecuteOptions<'_>) -> Result<Vec<EngineEvent>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+
+        let operation = Operation::Deposit { acc_id, tx_id, amount: amount.into() };
+        let op_hash = operation.get_hash_code();
+        let operation_processed = self.is_operation_processed(&mut db_tx, op_hash).await?;
+        if operation_processed {
+            return Ok(vec![]); // idempotency
+        }
+
+        let maybe_tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
+        let maybe_account = self.storage.get_account(&mut db_tx, acc_id).await?;
+        let validated = self.validator().validate(&operation, maybe_account.as_ref(), maybe_tx.as_ref(), self.now())?;
+
+        self.check_external_id(&mut db_tx, options.external_id).await?;
+        let pending_review = self.assess_risk(&operation, maybe_account.as_ref(), options.provenance).await?;
+
+        let tx = validated.tx
+            .with_external_id(options.external_id.map(str::to_string))
+            .with_pending_review(pending_review);
+        self.storage.insert_tx(&mut db_tx, &tx).await?;
+
+        let mut new_acc = validated.account;
+        new_acc.touch(tx.created_at());
+        new_acc.advance_seq();
+        match maybe_account {
+            Some(old_acc) => self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?,
+            None => self.storage.insert_account(&mut db_tx, &new_acc).await?,
+        }
+
+        self.storage.insert_operation(&mut db_tx, op_hash, tx.created_at()).await?;
+        self.append_journal_entry(&mut db_tx, tx.created_at(), operation, &new_acc, &tx, options.provenance).await?;
+        self.save_checkpoint(&mut db_tx, options.checkpoint).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(vec![EngineEvent::DepositApplied { account: new_acc, transaction: tx }])
+    }
+
+    async fn apply_withdraw(&self, acc_id: ClientId, tx_id: TxId, amount: Decimal4, options: ExecuteOptions<'_>) -> Result<Vec<EngineEvent>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+
+        let operation = Operation::Withdraw { acc_id, tx_id, amount: amount.into() };
+        let op_hash = operation.get_hash_code();
+        let operation_processed = self.is_operation_processed(&mut db_tx, op_hash).await?;
+        if operation_processed {
+            return Ok(vec![]); // idempotency
+        }
+
+        let maybe_tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
+        let maybe_account = self.storage.get_account(&mut db_tx, acc_id).await?;
+        let validated = self.validator().validate(&operation, maybe_account.as_ref(), maybe_tx.as_ref(), self.now())?;
+        let old_acc = maybe_account.ok_or(EngineError::AccountNotFound)?;
+
+        self.check_external_id(&mut db_tx, options.external_id).await?;
+        let pending_review = self.assess_risk(&operation, Some(&old_acc), options.provenance).await?;
+
+        let tx = validated.tx
+            .with_external_id(options.external_id.map(str::to_string))
+            .with_pending_review(pending_review);
+        let mut new_acc = validated.account;
+        new_acc.touch(tx.created_at());
+        new_acc.advance_seq();
+        self.storage.insert_tx(&mut db_tx, &tx).await?;
+        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+        self.post_to_system_account(&mut db_tx, SystemAccount::Fees, tx.fee(), tx.created_at()).await?;
+        self.storage.insert_operation(&mut db_tx, op_hash, tx.created_at()).await?;
+        self.append_journal_entry(&mut db_tx, tx.created_at(), operation, &new_acc, &tx, options.provenance).await?;
+        self.save_checkpoint(&mut db_tx, options.checkpoint).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(vec![EngineEvent::WithdrawalApplied { account: new_acc, transaction: tx }])
+    }
+
+    /// Moves the funds into the escrow `bucket`, or out of it when `release` is set. The total balance doesn't change,
+    /// so neither the amount nor the balance limits apply.
+    async fn apply_escrow(&self, acc_id: ClientId, tx_id: TxId, bucket: String, amount: Decimal4, release: bool, options: ExecuteOptions<'_>) -> Result<Vec<EngineEvent>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+
+        let operation = if release {
+            Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount: amount.into() }
+        } else {
+            Operation::Escrow { acc_id, tx_id, bucket, amount: amount.into() }
+        };
+        let op_hash = operation.get_hash_code();
+        let operation_processed = self.is_operation_processed(&mut db_tx, op_hash).await?;
+        if operation_processed {
+            return Ok(vec![]); // idempotency
+        }
+
+        let maybe_tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
+        let maybe_account = self.storage.get_account(&mut db_tx, acc_id).await?;
+        let validated = self.validator().validate(&operation, maybe_account.as_ref(), maybe_tx.as_ref(), self.now())?;
+        let old_acc = maybe_account.ok_or(EngineError::AccountNotFound)?;
+
+        let tx = validated.tx;
+        let mut new_acc = validated.account;
+        new_acc.touch(tx.created_at());
+        new_acc.advance_seq();
+        self.storage.insert_tx(&mut db_tx, &tx).await?;
+        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+        self.storage.insert_operation(&mut db_tx, op_hash, tx.created_at()).await?;
+        self.append_journal_entry(&mut db_tx, tx.created_at(), operation, &new_acc, &tx, options.provenance).await?;
+        self.save_checkpoint(&mut db_tx, options.checkpoint).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        if release {
+            Ok(vec![EngineEvent::EscrowReleased { account: new_acc, transaction: tx }])
+        } else {
+            Ok(vec![EngineEvent::EscrowMoved { account: new_acc, transaction: tx }])
+        }
+    }
+
+    async fn apply_authorize(&self, acc_id: ClientId, tx_id: TxId, amount: Decimal4, options: ExecuteOptions<'_>) -> Result<Vec<EngineEvent>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+
+        let operation = Operation::Authorize { acc_id, tx_id, amount: amount.into() };
+        let op_hash = operation.get_hash_code();
+        let operation_processed = self.is_operation_processed(&mut db_tx, op_hash).await?;
+        if operation_processed {
+            return Ok(vec![]); // idempotency
+        }
+
+        let maybe_tx = self.storage.get_tx(&mut db_tx, tx_id).await?;
+        let maybe_account = self.storage.get_account(&mut db_tx, acc_id).await?;
+        let validated = self.validator().validate(&operation, maybe_account.as_ref(), maybe_tx.as_ref(), self.now())?;
+        let old_acc = maybe_account.ok_or(EngineError::AccountNotFound)?;
+
+        let tx = validated.tx;
+        let mut new_acc = validated.account;
+        new_acc.touch(tx.created_at());
+        new_acc.advance_seq();
+        self.storage.insert_tx(&mut db_tx, &tx).await?;
+        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+        self.storage.insert_operation(&mut db_tx, op_hash, tx.created_at()).await?;
+        self.append_journal_entry(&mut db_tx, tx.created_at(), operation, &new_acc, &tx, options.provenance).await?;
+        self.save_checkpoint(&mut db_tx, options.checkpoint).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(vec![EngineEvent::AuthorizationHeld { account: new_acc, transaction: tx }])
+    }
+
+    /// Whether the operation was already applied, or the error it was rejected with, see [`EnginePolicy::cache_rejections`].
+    async fn is_operation_processed(&self, db_tx: &mut TStorage::DbTx, op_hash: u64) -> Result<bool, EngineError> {
+        if self.storage.is_operation_processed(db_tx, op_hash).await? {
+            return Ok(true);
+        }
+        if self.policy.cache_rejections {
+            if let Some(err) = self.storage.get_operation_rejection(db_tx, op_hash).await?.and_then(EngineError::from_code) {
+                return Err(err);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Records the rejection of a deposit, a withdrawal, an escrow move or an authorization in its own storage transaction, see
+    /// [`EnginePolicy::cache_rejections`]. A failure is only logged, the operation is rejected anyway.
+    async fn cache_rejection(&self, operation: &Operation, err: &EngineError) {
+        let creates_tx = matches!(operation, Operation::Deposit { .. } | Operation::Withdraw { .. } | Operation::Escrow { .. } | Operation::ReleaseEscrow { .. } | Operation::Authorize { .. });
+        // NOTE: the external id is not a part of the idempotency key, a retry with another one may pass
+        let final_rejection = err.code() < 150 && err != &EngineError::DuplicateExternalId && EngineError::from_code(err.code()).as_ref() == Some(err);
+        if !self.policy.cache_rejections || !creates_tx || !final_rejection {
+            return;
+        }
+        let op_hash = operation.get_hash_code();
+        let cache = async {
+            let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+            if self.storage.get_operation_rejection(&mut db_tx, op_hash).await? == Some(err.code()) {
+                return Ok(()); // NOTE: a retry rejected with the cached error
+            }
+            self.storage.insert_operation_rejection(&mut db_tx, op_hash, err.code(), self.now()).await?;
+            self.storage.commit_db_tx(db_tx).await
+        };
+        if let Err(cache_err) = cache.await {
+            tracing::warn!(error = %redact::error(&cache_err), "failed to record the rejection");
+        }
+    }
+
+    async fn check_external_id(&self, db_tx: &mut TStorage::DbTx, external_id: Option<&str>) -> Result<(), EngineError> {
+        let Some(external_id) = external_id else {
+            return Ok(());
+        };
+        match self.storage.get_tx_by_external_id(db_tx, external_id).await? {
+            Some(_) => Err(EngineError::DuplicateExternalId),
+            None => Ok(()),
+        }
+    }
+
+    /// Consults the risk assessor, if any: `Deny` rejects the operation, and `Review` returns `true` to flag its transaction.
+    async fn assess_risk(&self, operation: &Operation, account: Option<&Account>, provenance: Option<&Provenance>) -> Result<bool, EngineError> {
+        let Some(risk_assessor) = self.risk_assessor.as_ref() else {
+            return Ok(false);
+        };
+        match risk_assessor.assess(operation, account, provenance).await {
+            RiskDecision::Allow => Ok(false),
+            RiskDecision::Review => Ok(true),
+            RiskDecision::Deny => Err(EngineError::RiskDenied),
+        }
+    }
+
+    async fn apply_dispute(&self, acc_id: ClientId, tx_id: TxId, options: ExecuteOptions<'_>) -> Result<Vec<EngineEvent>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+
+        let operation = Operation::Dispute { acc_id, tx_id };
+        let disputed_at = self.now();
+        let (old_acc, old_tx, validated) = self.validate_in(&mut db_tx, &operation, disputed_at).await?;
+        let new_tx = validated.tx;
+        let mut new_acc = validated.account;
+        new_acc.touch(disputed_at);
+        new_acc.advance_seq();
+
+        self.storage.update_tx(&mut db_tx, &old_tx, &new_tx).await?;
+        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+        self.append_journal_entry(&mut db_tx, disputed_at, operation, &new_acc, &new_tx, options.provenance).await?;
+        self.save_checkpoint(&mut db_tx, options.checkpoint).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        Ok(vec![EngineEvent::DisputeOpened { account: new_acc, transaction: new_tx }])
+    }
+
+    async fn apply_resolve(&self, acc_id: ClientId, tx_id: TxId, options: ExecuteOptions<'_>) -> Result<Vec<EngineEvent>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+
+        let operation = Operation::Resolve { acc_id, tx_id };
+        let resolved_at = self.now();
+        let (old_acc, old_tx, validated) = self.validate_in(&mut db_tx, &operation, resolved_at).await?;
+        let new_tx = validated.tx;
+        let mut new_acc = validated.account;
+        new_acc.touch(resolved_at);
+        self.auto_unlock(&mut db_tx, &mut new_acc, &new_tx, resolved_at).await?;
+        new_acc.advance_seq();
+
+        self.storage.update_tx(&mut db_tx, &old_tx, &new_tx).await?;
+        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+        self.append_journal_entry(&mut db_tx, resolved_at, operation, &new_acc, &new_tx, options.provenance).await?;
+        self.save_checkpoint(&mut db_tx, options.checkpoint).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        let mut events = vec![EngineEvent::DisputeResolved { account: new_acc.clone(), transaction: new_tx }];
+        if old_acc.locked() && !new_acc.locked() {
+            events.push(EngineEvent::AccountUnlocked { account: new_acc });
+        }
+        Ok(events)
+    }
+
+    async fn apply_chargeback(&self, acc_id: ClientId, tx_id: TxId, options: ExecuteOptions<'_>) -> Result<Vec<EngineEvent>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+
+        let operation = Operation::Chargeback { acc_id, tx_id };
+        let charged_back_at = self.now();
+        let (old_acc, old_tx, validated) = self.validate_in(&mut db_tx, &operation, charged_back_at).await?;
+        let new_tx = validated.tx;
+        let mut new_acc = validated.account;
+        new_acc.touch(charged_back_at);
+        self.auto_unlock(&mut db_tx, &mut new_acc, &new_tx, charged_back_at).await?;
+        new_acc.advance_seq();
+
+        self.storage.update_tx(&mut db_tx, &old_tx, &new_tx).await?;
+        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+        self.post_to_system_account(&mut db_tx, SystemAccount::ChargebackLosses, Decimal4::zero() - new_tx.written_off(), charged_back_at).await?;
+        self.append_journal_entry(&mut db_tx, charged_back_at, operation, &new_acc, &new_tx, options.provenance).await?;
+        self.save_checkpoint(&mut db_tx, options.checkpoint).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        let mut events = vec![EngineEvent::ChargebackApplied { account: new_acc.clone(), transaction: new_tx }];
+        if !old_acc.locked() && new_acc.locked() {
+            events.push(EngineEvent::AccountLocked { account: new_acc });
+        } else if old_acc.locked() && !new_acc.locked() {
+            events.push(EngineEvent::AccountUnlocked { account: new_acc });
+        }
+        Ok(events)
+    }
+
+    /// Reads the account and the transaction a dispute, a resolve, a chargeback, a capture or an expiry refers to and validates
+    /// the operation against them.
+    async fn validate_in(&self, db_tx: &mut TStorage::DbTx, operation: &Operation, now: u64) -> Result<(Account, Transaction, ValidatedOperation), EngineError> {
+        let maybe_tx = self.storage.get_tx(db_tx, operation.tx_id()).await?;
+        let maybe_account = self.storage.get_account(db_tx, operation.acc_id()).await?;
+        let validated = self.validator().validate(operation, maybe_account.as_ref(), maybe_tx.as_ref(), now)?;
+        let (Some(old_acc), Some(old_tx)) = (maybe_account, maybe_tx) else {
+            unreachable!("the validation requires both");
+        };
+        Ok((old_acc, old_tx, validated))
+    }
+
+    /// Unlocks the account `acc` updated by the resolve or the chargeback of `tx` if it is settled, see [`EnginePolicy::auto_unlock`].
+    /// The other transactions of the account are read through the transaction index: none of them may be disputed, and one of them
+    /// (or `tx`) must be charged back, so the accounts locked by an admin without any chargeback stay locked.
+    async fn auto_unlock(&self, db_tx: &mut TStorage::DbTx, acc: &mut Account, tx: &Transaction, timestamp: u64) -> Result<(), EngineError> {
+        if !self.policy.auto_unlock || !acc.locked() || acc.available().is_negative() {
+            return Ok(());
+        }
+        let mut charged_back = tx.state() == TransactionState::Chargeback;
+        let mut pages = AccountTxPages::new(acc.id());
+        while let Some(page) = pages.next_page(&*self.storage, db_tx).await? {
+            for other in page.iter().filter(|x| x.id() != tx.id()) {
+                match other.state() {
+                    TransactionState::Disputed => return Ok(()),
+                    TransactionState::Chargeback => charged_back = true,
+                    TransactionState::Posted | TransactionState::Captured | TransactionState::Expired => {}
+                }
+            }
+        }
+        if charged_back {
+            acc.set_status(AccountStatus::Active, timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Captures the authorization at the `now` timestamp, or releases its held funds when `expire` is set.
+    async fn apply_capture(&self, acc_id: ClientId, tx_id: TxId, expire: bool, now: u64, options: ExecuteOptions<'_>) -> Result<Vec<EngineEvent>, EngineError> {
+        let mut db_tx = self.storage.start_db_tx(self.write_options).await?;
+
+        let operation = if expire {
+            Operation::Expire { acc_id, tx_id }
+        } else {
+            Operation::Capture { acc_id, tx_id }
+        };
+        let (old_acc, old_tx, validated) = self.validate_in(&mut db_tx, &operation, now).await?;
+        let new_tx = validated.tx;
+        let mut new_acc = validated.account;
+        new_acc.touch(now);
+        new_acc.advance_seq();
+
+        self.storage.update_tx(&mut db_tx, &old_tx, &new_tx).await?;
+        self.storage.update_account(&mut db_tx, &old_acc, &new_acc).await?;
+        self.append_journal_entry(&mut db_tx, now, operation, &new_acc, &new_tx, options.provenance).await?;
+        self.save_checkpoint(&mut db_tx, options.checkpoint).await?;
+        self.storage.commit_db_tx(db_tx).await?;
+        if expire {
+            Ok(vec![EngineEvent::AuthorizationExpired { account: new_acc, transaction: new_tx }])
+        } else {
+            Ok(vec![EngineEvent::AuthorizationCaptured { account: new_acc, transaction: new_tx }])
+        }
+    }
+
+    /// Every operation ends here with the updated account, so the account invariants are checked one last time before the commit.
+    async fn append_journal_entry(&self, db_tx: &mut TStorage::DbTx, timestamp: u64, operation: Operation, acc: &Account, tx: &Transaction, provenance: Option<&Provenance>) -> Result<(), EngineError> {
+        if let Err(err) = acc.check_invariants() {
+            tracing::error!(acc_id = %redact::client(acc.id()), held = %redact::amount(acc.held()), "account invariant violated: {}", err);
+            return Err(err.into());
+        }
+        let seq = self.storage.get_last_journal_seq(db_tx).await? + 1;
+        let prev_digest = match seq {
+            1 => Digest::default(),
+            _ => self.storage.get_journal_entries(db_tx, seq - 1, 1).await?.first().map(JournalEntry::digest).unwrap_or_default(),
+        };
+        let entry = JournalEntry::new(seq, timestamp, operation, acc.clone(), tx.clone())
+            .with_provenance(provenance.cloned())
+            .chained(prev_digest);
+        self.storage.append_journal_entry(db_tx, &entry).await?;
+        Ok(())
+    }
+
+    /// Posts `amount` to a system account in the same storage transaction as the operation, creating the account on its first posting.
+    /// NOTE: all the operations posting to the same system account update the same record, the storages serialize them
+    async fn post_to_system_account(&self, db_tx: &mut TStorage::DbTx, account: SystemAccount, amount: Decimal4, timestamp: u64) -> Result<(), EngineError> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        let mut acc = self.storage.get_system_account(db_tx, account).await?.unwrap_or_default();
+        acc.post(amount)?;
+        acc.touch(timestamp);
+        self.storage.set_system_account(db_tx, account, &acc).await?;
+        Ok(())
+    }
+
+    async fn save_checkpoint(&self, db_tx: &mut TStorage::DbTx, checkpoint: Option<(&str, u64)>) -> Result<(), EngineError> {
+        if let Some((source, rows)) = checkpoint {
+            self.storage.set_checkpoint(db_tx, source, rows).await?;
+        }
+        Ok(())
+    }
+
+    /// Runs `apply` in an `operation` tracing span (type, acc_id, tx_id, the provenance if any and the outcome), then notifies the observers.
+    async fn run(&self, operation: Operation, provenance: Option<&Provenance>, apply: impl Future<Output = Result<Vec<EngineEvent>, EngineError>>) -> Result<(), EngineError> {
+        let span = tracing::info_span!(
+            "operation",
+            r#type = operation.name(),
+            acc_id = tracing::field::display(redact::client(operation.acc_id())),
+            tx_id = operation.tx_id(),
+            source = provenance.map(|x| tracing::field::display(&x.source)),
+            correlation_id = provenance.and_then(|x| x.correlation_id.as_deref()),
+            outcome = tracing::field::Empty,
+        );
+        let started_at = Instant::now();
+        let result = match self.ensure_running().and_then(|_| self.validator().check_operation(&operation)) {
+            Ok(()) => {
+                let result = apply.instrument(span.clone()).await;
+                if let Err(err) = result.as_ref() {
+                    self.cache_rejection(&operation, err).instrument(span.clone()).await;
+                }
+                result
+            }
+            Err(err) => Err(err),
+        };
+        span.in_scope(|| match result.as_ref() {
+            Ok(_) => {
+                span.record("outcome", "applied");
+                tracing::debug!("operation applied");
+            }
+            Err(err) => {
+                span.record("outcome", "rejected");
+                tracing::debug!(error = %redact::error(err), "operation rejected");
+            }
+        });
+        self.notify_observers(operation, provenance, started_at, result)
+    }
+
+    fn notify_observers(&self, operation: Operation, provenance: Option<&Provenance>, started_at: Instant, result: Result<Vec<EngineEvent>, EngineError>) -> Result<(), EngineError> {
+        #[cfg(feature = "metrics")]
+        metrics::histogram!(crate::engine_metrics::ENGINE_DURATION_METRIC, "type" => operation.name()).record(started_at.elapsed().as_secs_f64());
+        #[cfg(not(feature = "metrics"))]
+        let _ = started_at;
+        match result {
+            Ok(events) => {
+                for event in events.iter() {
+                    self.observers.iter().for_each(|observer| event.dispatch(observer.as_ref()));
+                }
+                Ok(())
+            }
+            Err(error) => {
+                if !self.observers.is_empty() {
+                    let event = EngineEvent::OperationRejected { operation, error: error.clone(), provenance: provenance.cloned() };
+                    self.observers.iter().for_each(|observer| event.dispatch(observer.as_ref()));
+                }
+                Err(error)
+            }
+        }
+    }
+}
+
+/// The background tasks, spawned on the runtime of the `tokio` or `async-std` feature.
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+impl<TStorage> Engine<TStorage>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    /// Executes operations from the input stream one by one and yields the outcome of each operation in the input order.
+    /// At most `buffer_size` outcomes are buffered ahead of the consumer: when the output stream is not polled,
+    /// the input stream is not polled either (backpressure).
+    pub fn process_stream<S>(&self, operations: S, buffer_size: usize) -> impl Stream<Item = (Operation, Result<(), EngineError>)>
+        where S: Stream<Item = Operation> + Send + 'static
+    {
+        let (mut sender, receiver) = mpsc::channel(buffer_size);
+        let engine = self.clone();
+        runtime::spawn(async move {
+            let mut operations = std::pin::pin!(operations);
+            while let Some(operation) = operations.next().await {
+                let result = engine.execute_operation(operation.clone()).await;
+                if sender.send((operation, result)).await.is_err() {
+                    break; // the output stream was dropped
+                }
+            }
+        });
+        receiver
+    }
+
+    /// Spawns a maintenance task that releases the expired authorizations every `interval`, see [`Engine::expire_holds`].
+    /// Failed runs are logged and retried on the next tick. Abort the handle to stop the task.
+    pub fn spawn_hold_expirer(&self, interval: Duration) -> JoinHandle<()> {
+        let engine = self.clone();
+        runtime::spawn(async move {
+            loop {
+                if let Err(err) = engine.expire_holds(engine.now()).await {
+                    tracing::warn!(error = %redact::error(&err), "failed to expire the authorizations");
+                }
+                runtime::sleep(interval).await;
+            }
+        })
+    }
+
+    /// Spawns a maintenance task that prunes the idempotency records older than `retention` every `interval`.
+    /// Failed runs (e.g. because of concurrent operations) are logged and retried on the next tick. Abort the handle to stop the task.
+    pub fn spawn_operations_pruner(&self, retention: Duration, interval: Duration) -> JoinHandle<()> {
+        let engine = self.clone();
+        runtime::spawn(async move {
+            loop {
+                let older_than = engine.now().saturating_sub(retention.as_millis() as u64);
+                if let Err(err) = engine.prune_operations(older_than).await {
+                    tracing::warn!(error = %redact::error(&err), "failed to prune the idempotency records");
+                }
+                runtime::sleep(interval).await;
+            }
+        })
+    }
+}
+
+impl<TStorage: Storage> Debug for Engine<TStorage> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Engine").finish()
+    }
+}
+
+impl<TStorage: Storage> Clone for Engine<TStorage> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            observers: self.observers.clone(),
+            policy: self.policy,
+            risk_assessor: self.risk_assessor.clone(),
+            paused: self.paused.clone(),
+            clock: self.clock.clone(),
+            write_options: self.write_options,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    #[error("account not found")]
+    AccountNotFound,
+
+    #[error("transaction not found")]
+    TransactionNotFound,
+
+    #[error("account is locked")]
+    AccountLocked,
+
+    #[error("insufficient funds")]
+    InsufficientFunds,
+
+    #[error("amount is not positive")]
+    AmountIsNotPositive,
+
+    #[error("transaction with the same id already exists")]
+    TransactionWithTheSameIdAlreadyExists,
+
+    #[error("transaction is bound to another account")]
+    TransactionIsBoundToAnotherAccount(ClientId),
+
+    #[error("invalid transaction type: only deposits can be disputed/resolved/chargebacked and authorizations captured/expired")]
+    InvalidTxType,
+
+    #[error("forbidden state transition from {from:?} to {to:?}")]
+    ForbiddenTxStateTransition { from: TransactionState, to: TransactionState },
+
+    #[error("the transaction is too old to be disputed")]
+    DisputeWindowExpired,
+
+    #[error("amount overflow")]
+    AmountOverflow,
+
+    #[error("amount limit exceeded")]
+    AmountLimitExceeded,
+
+    #[error("balance limit exceeded")]
+    BalanceLimitExceeded,
+
+    #[error("transaction with the same external id already exists")]
+    DuplicateExternalId,
+
+    #[error("account is frozen")]
+    AccountFrozen,
+
+    #[error("account is closed")]
+    AccountClosed,
+
+    #[error("account still has funds")]
+    AccountNotEmpty,
+
+    #[error("forbidden account status transition from {from:?} to {to:?}")]
+    ForbiddenAccountStatusTransition { from: AccountStatus, to: AccountStatus },
+
+    #[error("the operation was denied by the risk assessment")]
+    RiskDenied,
+
+    #[error("account has open disputes")]
+    OpenDisputes,
+
+    #[error("the authorization has expired")]
+    AuthorizationExpired,
+
+    #[error("the authorization has not expired yet")]
+    AuthorizationNotExpired,
+
+    #[error("insufficient funds in escrow")]
+    InsufficientEscrow,
+
+    #[error("the disputed amount exceeds the available funds")]
+    DisputeExceedsAvailable,
+
+    #[error("concurrent operation detected for the same entities")]
+    ConcurrentOperationDetected,
+
+    #[error("the engine is paused")]
+    Paused,
+
+    #[error("held funds would become negative")]
+    HeldUnderflow,
+
+    #[error("journal is corrupted at entry {0}")]
+    CorruptedJournal(u64),
+
+    #[error("snapshot error: {0}")]
+    SnapshotError(SnapshotError),
+
+    #[error("database error: {0}")]
+    DatabaseError(String),
+
+    #[error("the actor of the account stopped")]
+    ActorStopped,
+}
+
+impl EngineError {
+    /// Whether retrying the same operation later can succeed: concurrent operations, a paused engine and storage failures.
+    /// Every other error is a final verdict on the operation (the engine state is deterministic).
+    pub fn is_transient(&self) -> bool {
+        matches!(self, EngineError::ConcurrentOperationDetected | EngineError::Paused | EngineError::DatabaseError(_))
+    }
+
+    /// The error with the given code (see [`EngineError::code`]), `None` for the unknown codes and the errors whose
+    /// details the code doesn't carry.
+    pub fn from_code(code: u16) -> Option<EngineError> {
+        let err = match code {
+            101 => EngineError::AccountNotFound,
+            102 => EngineError::TransactionNotFound,
+            103 => EngineError::AccountLocked,
+            104 => EngineError::InsufficientFunds,
+            105 => EngineError::AmountIsNotPositive,
+            106 => EngineError::TransactionWithTheSameIdAlreadyExists,
+            108 => EngineError::InvalidTxType,
+            110 => EngineError::DisputeWindowExpired,
+            111 => EngineError::AmountOverflow,
+            112 => EngineError::AmountLimitExceeded,
+            113 => EngineError::BalanceLimitExceeded,
+            114 => EngineError::DuplicateExternalId,
+            115 => EngineError::AccountFrozen,
+            116 => EngineError::AccountClosed,
+            117 => EngineError::AccountNotEmpty,
+            119 => EngineError::RiskDenied,
+            120 => EngineError::OpenDisputes,
+            122 => EngineError::AuthorizationExpired,
+            123 => EngineError::AuthorizationNotExpired,
+            124 => EngineError::InsufficientEscrow,
+            125 => EngineError::DisputeExceedsAvailable,
+            150 => EngineError::ConcurrentOperationDetected,
+            151 => EngineError::Paused,
+            193 => EngineError::HeldUnderflow,
+            194 => EngineError::ActorStopped,
+            _ => return None,
+        };
+        Some(err)
+    }
+
+    /// A stable numeric code of the error, for the integrators to branch on instead of the message:
+    /// 1xx for the operations the engine rejects, 150 for concurrent operations, 151 for a paused engine and 19x for internal failures.
+    /// The codes are never reused or changed, new variants get new codes.
+    pub fn code(&self) -> u16 {
+        match self {
+            EngineError::AccountNotFound => 101,
+            EngineError::TransactionNotFound => 102,
+            EngineError::AccountLocked => 103,
+            EngineError::InsufficientFunds => 104,
+            EngineError::AmountIsNotPositive => 105,
+            EngineError::TransactionWithTheSameIdAlreadyExists => 106,
+            EngineError::TransactionIsBoundToAnotherAccount(_) => 107,
+            EngineError::InvalidTxType => 108,
+            EngineError::ForbiddenTxStateTransition { .. } => 109,
+            EngineError::DisputeWindowExpired => 110,
+            EngineError::AmountOverflow => 111,
+            EngineError::AmountLimitExceeded => 112,
+            EngineError::BalanceLimitExceeded => 113,
+            EngineError::DuplicateExternalId => 114,
+            EngineError::AccountFrozen => 115,
+            EngineError::AccountClosed => 116,
+            EngineError::AccountNotEmpty => 117,
+            EngineError::ForbiddenAccountStatusTransition { .. } => 118,
+            EngineError::RiskDenied => 119,
+            EngineError::OpenDisputes => 120,
+            EngineError::AuthorizationExpired => 122,
+            EngineError::AuthorizationNotExpired => 123,
+            EngineError::InsufficientEscrow => 124,
+            EngineError::DisputeExceedsAvailable => 125,
+            EngineError::ConcurrentOperationDetected => 150,
+            EngineError::Paused => 151,
+            EngineError::CorruptedJournal(_) => 190,
+            EngineError::SnapshotError(_) => 191,
+            EngineError::DatabaseError(_) => 192,
+            EngineError::HeldUnderflow => 193,
+            EngineError::ActorStopped => 194,
+        }
+    }
+}
+
+impl From<DbError> for EngineError {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::EntityAlreadyExists => EngineError::ConcurrentOperationDetected,
+            DbError::ConcurrentModification => EngineError::ConcurrentOperationDetected,
+            DbError::DatabaseError(msg) => EngineError::DatabaseError(msg),
+        }
+    }
+}
+
+impl From<AccountUpdateError> for EngineError {
+    fn from(err: AccountUpdateError) -> Self {
+        match err {
+            AccountUpdateError::AccountLocked => EngineError::AccountLocked,
+            AccountUpdateError::AccountFrozen => EngineError::AccountFrozen,
+            AccountUpdateError::AccountClosed => EngineError::AccountClosed,
+            AccountUpdateError::AccountNotEmpty => EngineError::AccountNotEmpty,
+            AccountUpdateError::ForbiddenStatusTransition { from, to } => EngineError::ForbiddenAccountStatusTransition { from, to },
+            AccountUpdateError::InsufficientFunds => EngineError::InsufficientFunds,
+            AccountUpdateError::AmountIsNotPositive => EngineError::AmountIsNotPositive,
+            AccountUpdateError::AmountOverflow => EngineError::AmountOverflow,
+            AccountUpdateError::BalanceLimitExceeded => EngineError::BalanceLimitExceeded,
+            AccountUpdateError::HeldUnderflow => EngineError::HeldUnderflow,
+            AccountUpdateError::InsufficientEscrow => EngineError::InsufficientEscrow,
+        }
+    }
+}
+
+impl From<AmountOverflowError> for EngineError {
+    fn from(_: AmountOverflowError) -> Self {
+        EngineError::AmountOverflow
+    }
+}
+
+impl From<SnapshotError> for EngineError {
+    fn from(err: SnapshotError) -> Self {
+        EngineError::SnapshotError(err)
+    }
+}
+
+impl From<TxUpdateError> for EngineError {
+    fn from(err: TxUpdateError) -> Self {
+        match err {
+            TxUpdateError::InvalidTxType => EngineError::InvalidTxType,
+            TxUpdateError::ForbiddenTxStateTransition { from, to } => EngineError::ForbiddenTxStateTransition { from, to },
+        }
+    }
+}
+
+#[cfg(test)]
+mod engine_tests {
+    use crate::clock::{now_millis, ManualClock};
+    use crate::journal::{ChainViolation, OperationSource};
+    use crate::storage::EchoDbStorage;
+    use crate::transaction::TransactionType;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn deposit_ok() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(200)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 3, Decimal4::from(300)).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn withdraw_ok() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(50)).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn dispute_ok() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn resolve_ok() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.resolve(1, 1).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn chargeback_ok() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn deposit_idempotency() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        let mut db_tx = engine.storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(100));
+    }
+
+    #[tokio::test]
+    async fn withdraw_idempotency() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(50)).await, Ok(()));
+        let mut db_tx = engine.storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(50));
+    }
+
+    #[tokio::test]
+    async fn dispute_no_idempotency() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Disputed, to: TransactionState::Disputed }));
+        let mut db_tx = engine.storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(0));
+        assert_eq!(acc.held(), Decimal4::from(100));
+    }
+
+    #[tokio::test]
+    async fn resolve_no_idempotency() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.resolve(1, 1).await, Ok(()));
+        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Posted, to: TransactionState::Posted }));
+        let mut db_tx = engine.storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(100));
+        assert_eq!(acc.held(), Decimal4::from(0));
+    }
+
+    #[tokio::test]
+    async fn chargeback_no_idempotency() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Chargeback, to: TransactionState::Chargeback }));
+        let mut db_tx = engine.storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(0));
+        assert_eq!(acc.held(), Decimal4::from(0));
+        assert!(acc.locked());
+    }
+
+    #[tokio::test]
+    async fn withdraw_insufficient_funds_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(200)).await, Err(EngineError::InsufficientFunds));
+    }
+
+    #[tokio::test]
+    async fn deposit_on_locked_account_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(100)).await, Err(EngineError::AccountLocked));
+    }
+
+    #[tokio::test]
+    async fn withdraw_on_locked_account_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(50)).await, Err(EngineError::AccountLocked));
+    }
+
+    #[tokio::test]
+    async fn deposit_on_nonexistent_account_ok() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn withdraw_on_nonexistent_account_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.withdraw(1, 1, Decimal4::from(100)).await, Err(EngineError::AccountNotFound));
+    }
+
+    #[tokio::test]
+    async fn dispute_on_nonexistent_tx_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.dispute(1, 1).await, Err(EngineError::TransactionNotFound));
+    }
+
+    #[tokio::test]
+    async fn resolve_on_nonexistent_tx_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::TransactionNotFound));
+    }
+
+    #[tokio::test]
+    async fn chargeback_on_nonexistent_tx_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.chargeback(1, 1).await, Err(EngineError::TransactionNotFound));
+    }
+
+    #[tokio::test]
+    async fn dispute_on_nonexistent_transaction_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.dispute(1, 1).await, Err(EngineError::TransactionNotFound));
+    }
+
+    #[tokio::test]
+    async fn resolve_on_nonexistent_transaction_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::TransactionNotFound));
     }
-}
 
-#[cfg(test)]
-mod engine_tests {
-    use crate::storage::EchoDbStorage;
+    #[tokio::test]
+    async fn chargeback_on_nonexistent_transaction_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.chargeback(1, 1).await, Err(EngineError::TransactionNotFound));
+    }
 
-    use super::*;
+    #[tokio::test]
+    async fn dispute_on_transaction_bound_to_another_account_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.dispute(2, 1).await, Err(EngineError::TransactionIsBoundToAnotherAccount(1)));
+    }
 
     #[tokio::test]
-    async fn deposit_ok() {
+    async fn resolve_on_transaction_bound_to_another_account_err() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.deposit(1, 2, Decimal4::from(200)).await, Ok(()));
-        assert_eq!(engine.deposit(2, 3, Decimal4::from(300)).await, Ok(()));
+        assert_eq!(engine.resolve(2, 1).await, Err(EngineError::TransactionIsBoundToAnotherAccount(1)));
     }
 
     #[tokio::test]
-    async fn withdraw_ok() {
+    async fn chargeback_on_transaction_bound_to_another_account_err() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.withdraw(1, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.chargeback(2, 1).await, Err(EngineError::TransactionIsBoundToAnotherAccount(1)));
     }
 
     #[tokio::test]
-    async fn dispute_ok() {
+    async fn resolve_after_chargeback_err() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
         assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Chargeback, to: TransactionState::Posted }));
     }
 
     #[tokio::test]
-    async fn resolve_ok() {
+    async fn resolve_after_posted_err() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.dispute(1, 1).await, Ok(()));
-        assert_eq!(engine.resolve(1, 1).await, Ok(()));
+        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Posted, to: TransactionState::Posted }));
     }
 
     #[tokio::test]
-    async fn chargeback_ok() {
+    async fn chargeback_after_posted_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Posted, to: TransactionState::Chargeback }));
+    }
+
+    #[tokio::test]
+    async fn chargeback_after_resolve_and_second_dispute_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
         assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.resolve(1, 1).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
         assert_eq!(engine.chargeback(1, 1).await, Ok(()));
     }
 
     #[tokio::test]
-    async fn deposit_idempotency() {
+    async fn journal_records_applied_operations() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        let mut db_tx = engine.storage.start_db_tx().await.unwrap();
-        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
-        assert_eq!(acc.available(), Decimal4::from(100));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        let entries = engine.get_journal_entries(0, usize::MAX).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].seq(), 1);
+        assert_eq!(entries[0].operation(), &Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) });
+        assert_eq!(entries[0].account().available(), Decimal4::from(100));
+        assert_eq!(entries[1].seq(), 2);
+        assert_eq!(entries[1].operation(), &Operation::Dispute { acc_id: 1, tx_id: 1 });
+        assert_eq!(entries[1].account().held(), Decimal4::from(100));
+        assert_eq!(entries[1].transaction().state(), TransactionState::Disputed);
     }
 
     #[tokio::test]
-    async fn withdraw_idempotency() {
+    async fn journal_skips_rejected_and_replayed_operations() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.withdraw(1, 2, Decimal4::from(50)).await, Ok(()));
-        assert_eq!(engine.withdraw(1, 2, Decimal4::from(50)).await, Ok(()));
-        let mut db_tx = engine.storage.start_db_tx().await.unwrap();
-        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
-        assert_eq!(acc.available(), Decimal4::from(50));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(200)).await, Err(EngineError::InsufficientFunds));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(50)).await, Ok(()));
+        let entries = engine.get_journal_entries(0, usize::MAX).await.unwrap();
+        assert_eq!(entries.iter().map(|x| x.seq()).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(engine.get_journal_entries(2, usize::MAX).await.unwrap().len(), 1);
     }
 
     #[tokio::test]
-    async fn dispute_no_idempotency() {
+    async fn rebuild_from_journal_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.dispute(1, 1).await, Ok(()));
-        assert_eq!(engine.dispute(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Disputed, to: TransactionState::Disputed }));
-        let mut db_tx = engine.storage.start_db_tx().await.unwrap();
-        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
-        assert_eq!(acc.available(), Decimal4::from(0));
-        assert_eq!(acc.held(), Decimal4::from(100));
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.dispute(2, 2).await, Ok(()));
+        assert_eq!(engine.chargeback(2, 2).await, Ok(()));
+        let state = engine.rebuild_from_journal().await.unwrap();
+        assert_eq!(state.last_seq(), 5);
+        assert_eq!(state.accounts().values().cloned().collect::<Vec<_>>(), engine.get_all_accounts().await.unwrap());
+        assert_eq!(state.transactions().len(), 3);
+        assert_eq!(state.transactions()[&2].state(), TransactionState::Chargeback);
     }
 
     #[tokio::test]
-    async fn resolve_no_idempotency() {
+    async fn verify_journal_no_divergence() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
         assert_eq!(engine.dispute(1, 1).await, Ok(()));
         assert_eq!(engine.resolve(1, 1).await, Ok(()));
-        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Posted, to: TransactionState::Posted }));
-        let mut db_tx = engine.storage.start_db_tx().await.unwrap();
-        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
-        assert_eq!(acc.available(), Decimal4::from(100));
-        assert_eq!(acc.held(), Decimal4::from(0));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
     }
 
     #[tokio::test]
-    async fn chargeback_no_idempotency() {
+    async fn verify_journal_detects_divergence() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.dispute(1, 1).await, Ok(()));
-        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
-        assert_eq!(engine.chargeback(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Chargeback, to: TransactionState::Chargeback }));
-        let mut db_tx = engine.storage.start_db_tx().await.unwrap();
-        let acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
-        assert_eq!(acc.available(), Decimal4::from(0));
-        assert_eq!(acc.held(), Decimal4::from(0));
-        assert!(acc.locked());
+        let mut db_tx = engine.storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let old_acc = engine.storage.get_account(&mut db_tx, 1).await.unwrap().unwrap();
+        let mut new_acc = old_acc.clone();
+        new_acc.deposit(Decimal4::from(1)).unwrap();
+        engine.storage.update_account(&mut db_tx, &old_acc, &new_acc).await.unwrap();
+        engine.storage.insert_account(&mut db_tx, &Account::new(2)).await.unwrap();
+        engine.storage.commit_db_tx(db_tx).await.unwrap();
+        assert_eq!(engine.verify_journal().await, Ok(vec![
+            Divergence::AccountMismatch { expected: old_acc, actual: Some(new_acc) },
+            Divergence::UnexpectedAccount(Account::new(2)),
+        ]));
     }
 
     #[tokio::test]
-    async fn withdraw_insufficient_funds_err() {
+    async fn echodb_rollback_to_savepoint() {
+        let storage = EchoDbStorage::new();
+        let tx = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(10));
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        storage.insert_tx(&mut db_tx, &tx).await.unwrap();
+        let savepoint = storage.savepoint(&mut db_tx).await.unwrap();
+        assert_eq!(storage.delete_txs(&mut db_tx, &[1]).await, Ok(1));
+        storage.insert_account(&mut db_tx, &Account::new(1)).await.unwrap();
+        storage.rollback_to(&mut db_tx, savepoint).await.unwrap();
+        storage.commit_db_tx(db_tx).await.unwrap();
+
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_tx(&mut db_tx, 1).await, Ok(Some(tx)));
+        assert_eq!(storage.get_all_accounts(&mut db_tx).await, Ok(vec![]));
+        assert!(storage.rollback_to(&mut db_tx, savepoint).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn echodb_read_only_tx() {
+        let engine = Engine::new(EchoDbStorage::new()).with_locking(LockingStrategy::Pessimistic);
+        assert_eq!(engine.write_options(), TxOptions::read_write().with_locking(LockingStrategy::Pessimistic));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        let mut writer = engine.storage.start_db_tx(engine.write_options()).await.unwrap();
+        engine.storage.insert_account(&mut writer, &Account::new(2)).await.unwrap();
+
+        // NOTE: does not wait for the writer, and reads the committed state
+        let mut reader = engine.storage.start_db_tx(TxOptions::read_only()).await.unwrap();
+        assert_eq!(engine.storage.get_all_accounts(&mut reader).await.unwrap().len(), 1);
+        assert!(engine.storage.insert_account(&mut reader, &Account::new(3)).await.is_err());
+        engine.storage.commit_db_tx(reader).await.unwrap();
+        engine.storage.commit_db_tx(writer).await.unwrap();
+        assert_eq!(engine.get_all_accounts().await.unwrap().len(), 2);
+    }
+
+    async fn engine_with_journal(entries: &[JournalEntry]) -> Engine<EchoDbStorage> {
+        let storage = EchoDbStorage::new();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        for entry in entries.iter() {
+            storage.append_journal_entry(&mut db_tx, entry).await.unwrap();
+        }
+        storage.commit_db_tx(db_tx).await.unwrap();
+        Engine::new(storage)
+    }
+
+    #[tokio::test]
+    async fn verify_journal_chain_detects_tampering() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.withdraw(1, 2, Decimal4::from(200)).await, Err(EngineError::InsufficientFunds));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(40)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        let report = engine.verify_journal_chain(None).await.unwrap();
+        assert_eq!((report.head_seq, report.violations), (3, vec![]));
+        let head = Some((3, report.head_digest));
+        let entries = engine.get_journal_entries(1, 10).await.unwrap();
+        assert_eq!(entries[1].prev_digest(), entries[0].digest());
+
+        let mut modified = serde_json::to_value(&entries[1]).unwrap();
+        modified["timestamp"] = serde_json::json!(0);
+        let modified = [entries[0].clone(), serde_json::from_value(modified).unwrap(), entries[2].clone()];
+        let violations = engine_with_journal(&modified).await.verify_journal_chain(head).await.unwrap().violations;
+        assert_eq!(violations, vec![ChainViolation::DigestMismatch { seq: 2 }]);
+
+        let removed = [entries[0].clone(), entries[2].clone()];
+        let violations = engine_with_journal(&removed).await.verify_journal_chain(head).await.unwrap().violations;
+        assert_eq!(violations, vec![ChainViolation::SequenceGap { expected: 2, actual: 3 }, ChainViolation::BrokenLink { seq: 3 }]);
+
+        let truncated = engine_with_journal(&entries[..2]).await;
+        assert_eq!(truncated.verify_journal_chain(None).await.unwrap().violations, vec![]);
+        assert_eq!(truncated.verify_journal_chain(head).await.unwrap().violations, vec![ChainViolation::HeadMismatch { seq: 3 }]);
     }
 
     #[tokio::test]
-    async fn deposit_on_locked_account_err() {
+    async fn verify_journal_chain_accepts_unchained_prefix() {
+        let legacy = JournalEntry::new(1, 0, Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) }, Account::new(1), Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(100)));
+        let engine = engine_with_journal(&[legacy]).await;
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(100)).await, Ok(()));
+        let report = engine.verify_journal_chain(None).await.unwrap();
+        assert_eq!((report.head_seq, report.violations), (2, vec![]));
+
+        // NOTE: an unchained entry is only accepted before the first chained one
+        let mut db_tx = engine.storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let unchained = JournalEntry::new(3, 0, Operation::Deposit { acc_id: 1, tx_id: 3, amount: Decimal::from(1) }, Account::new(1), Transaction::new(3, 1, TransactionType::Deposit, Decimal4::from(1)));
+        engine.storage.append_journal_entry(&mut db_tx, &unchained).await.unwrap();
+        engine.storage.commit_db_tx(db_tx).await.unwrap();
+        let violations = engine.verify_journal_chain(None).await.unwrap().violations;
+        assert_eq!(violations, vec![ChainViolation::BrokenLink { seq: 3 }, ChainViolation::DigestMismatch { seq: 3 }]);
+    }
+
+    #[tokio::test]
+    async fn account_sequences_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(500)).await, Err(EngineError::InsufficientFunds));
         assert_eq!(engine.dispute(1, 1).await, Ok(()));
-        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
-        assert_eq!(engine.deposit(1, 2, Decimal4::from(100)).await, Err(EngineError::AccountLocked));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().seq(), 2);
+        assert_eq!(engine.get_account(2).await.unwrap().unwrap().seq(), 1);
+        let entries = engine.get_journal_entries(1, 10).await.unwrap();
+        assert_eq!(entries.iter().map(|x| x.account().seq()).collect::<Vec<_>>(), vec![1, 1, 2]);
+        assert_eq!(engine.verify_account_sequences().await, Ok(vec![]));
+
+        let violations = engine_with_journal(&entries[1..]).await.verify_account_sequences().await.unwrap();
+        assert_eq!(violations, vec![AccountSeqViolation::Gap { client: 1, expected: 1, actual: 2 }]);
     }
 
     #[tokio::test]
-    async fn withdraw_on_locked_account_err() {
+    async fn balance_as_of_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(40)).await, Ok(()));
         assert_eq!(engine.dispute(1, 1).await, Ok(()));
-        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
-        assert_eq!(engine.withdraw(1, 2, Decimal4::from(50)).await, Err(EngineError::AccountLocked));
+
+        assert_eq!(engine.balance_as_of(1, PointInTime::Seq(0)).await, Ok(None));
+        let acc = engine.balance_as_of(1, PointInTime::Seq(2)).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(100));
+        let acc = engine.balance_as_of(1, PointInTime::Seq(3)).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(60));
+        assert_eq!(acc.held(), Decimal4::zero());
+        let acc = engine.balance_as_of(1, PointInTime::Timestamp(u64::MAX)).await.unwrap();
+        assert_eq!(acc, engine.get_account(1).await.unwrap());
     }
 
     #[tokio::test]
-    async fn deposit_on_nonexistent_account_ok() {
+    async fn prune_operations_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.prune_operations(0).await, Ok(0));
+        assert_eq!(engine.prune_operations(now_millis() + 1).await, Ok(1));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Err(EngineError::TransactionWithTheSameIdAlreadyExists));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(100));
     }
 
     #[tokio::test]
-    async fn withdraw_on_nonexistent_account_err() {
+    async fn cached_rejections_replayed() {
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(EnginePolicy { cache_rejections: true, ..Default::default() });
+        assert_eq!(engine.withdraw(1, 1, Decimal4::from(10)).await, Err(EngineError::AccountNotFound));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 1, Decimal4::from(10)).await, Err(EngineError::AccountNotFound));
+        assert_eq!(engine.validate_operation(Operation::Withdraw { acc_id: 1, tx_id: 1, amount: Decimal::from(10) }, None).await, Err(EngineError::AccountNotFound));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(100)).await, Ok(()));
+
+        engine.pause();
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(10)).await, Err(EngineError::Paused));
+        engine.resume();
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(10)).await, Ok(())); // NOTE: the transient errors are not cached
+
+        assert_eq!(engine.prune_operations(now_millis() + 1).await, Ok(3));
+        assert_eq!(engine.withdraw(1, 1, Decimal4::from(10)).await, Ok(()));
+
         let engine = Engine::new(EchoDbStorage::new());
-        assert_eq!(engine.withdraw(1, 1, Decimal4::from(100)).await, Err(EngineError::AccountNotFound));
+        assert_eq!(engine.withdraw(1, 1, Decimal4::from(10)).await, Err(EngineError::AccountNotFound));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 1, Decimal4::from(10)).await, Ok(()));
     }
 
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
     #[tokio::test]
-    async fn dispute_on_nonexistent_tx_err() {
+    async fn operations_pruner_ok() {
         let engine = Engine::new(EchoDbStorage::new());
-        assert_eq!(engine.dispute(1, 1).await, Err(EngineError::TransactionNotFound));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        let pruner = engine.spawn_operations_pruner(Duration::ZERO, Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        pruner.abort();
+
+        let mut db_tx = engine.storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(engine.storage.get_all_operations(&mut db_tx).await, Ok(vec![]));
     }
 
     #[tokio::test]
-    async fn resolve_on_nonexistent_tx_err() {
+    async fn pause_and_resume() {
         let engine = Engine::new(EchoDbStorage::new());
-        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::TransactionNotFound));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        let clone = engine.clone();
+        clone.pause();
+        assert!(engine.is_paused());
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(100)).await, Err(EngineError::Paused));
+        assert_eq!(engine.set_account_status(1, AccountStatus::Frozen).await, Err(EngineError::Paused));
+        assert_eq!(engine.prune_operations(u64::MAX).await, Err(EngineError::Paused));
+        assert!(EngineError::Paused.is_transient());
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().total().unwrap(), Decimal4::from(100));
+        assert_eq!(engine.rebuild_from_journal().await.map(|x| x.last_seq()), Ok(1)); // NOTE: read-only, like the queries
+
+        engine.resume();
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().total().unwrap(), Decimal4::from(200));
     }
 
     #[tokio::test]
-    async fn chargeback_on_nonexistent_tx_err() {
+    async fn get_accounts_ok() {
         let engine = Engine::new(EchoDbStorage::new());
-        assert_eq!(engine.chargeback(1, 1).await, Err(EngineError::TransactionNotFound));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(3, 2, Decimal4::from(10)).await, Ok(()));
+        let accounts = engine.get_accounts(&[3, 2, 1]).await.unwrap();
+        assert_eq!(accounts.iter().map(|x| x.as_ref().map(|x| x.id())).collect::<Vec<_>>(), vec![Some(3), None, Some(1)]);
     }
 
     #[tokio::test]
-    async fn dispute_on_nonexistent_transaction_err() {
+    async fn stream_accounts_ok() {
         let engine = Engine::new(EchoDbStorage::new());
-        assert_eq!(engine.dispute(1, 1).await, Err(EngineError::TransactionNotFound));
+        for acc_id in 1..=7 {
+            assert_eq!(engine.deposit(acc_id, acc_id as TxId, Decimal4::from(10)).await, Ok(()));
+        }
+        let first_page = engine.list_accounts(None, 3).await.unwrap();
+        assert_eq!(first_page.len(), 3);
+        let second_page = engine.list_accounts(first_page.last().map(|x| x.id()), 3).await.unwrap();
+        assert_eq!(second_page.len(), 3);
+        assert!(second_page.iter().all(|x| !first_page.contains(x)));
+
+        let streamed: Vec<Account> = engine.stream_accounts(3).try_collect().await.unwrap();
+        assert_eq!(streamed, engine.get_all_accounts().await.unwrap());
+        let streamed: Vec<Account> = engine.stream_accounts(7).try_collect().await.unwrap();
+        assert_eq!(streamed.len(), 7);
     }
 
     #[tokio::test]
-    async fn resolve_on_nonexistent_transaction_err() {
+    async fn get_txs_by_account_ok() {
         let engine = Engine::new(EchoDbStorage::new());
-        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::TransactionNotFound));
+        for tx_id in [5, 1, 12, 3] {
+            assert_eq!(engine.deposit(1, tx_id, Decimal4::from(10)).await, Ok(()));
+        }
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(10)).await, Ok(()));
+
+        let page = engine.get_txs_by_account(1, None, 3).await.unwrap();
+        assert_eq!(page.iter().map(|x| x.id()).collect::<Vec<_>>(), vec![1, 3, 5]);
+        let page = engine.get_txs_by_account(1, Some(5), 3).await.unwrap();
+        assert_eq!(page.iter().map(|x| x.id()).collect::<Vec<_>>(), vec![12]);
+        assert_eq!(engine.get_txs_by_account(3, None, 10).await, Ok(vec![]));
+        assert_eq!(engine.get_all_accounts().await.unwrap().len(), 2);
     }
 
     #[tokio::test]
-    async fn chargeback_on_nonexistent_transaction_err() {
+    async fn get_open_disputes_ok() {
         let engine = Engine::new(EchoDbStorage::new());
-        assert_eq!(engine.chargeback(1, 1).await, Err(EngineError::TransactionNotFound));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(20)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 3, Decimal4::from(30)).await, Ok(()));
+        for tx_id in [1, 2] {
+            assert_eq!(engine.dispute(1, tx_id).await, Ok(()));
+        }
+        assert_eq!(engine.dispute(2, 3).await, Ok(()));
+        assert_eq!(engine.resolve(1, 1).await, Ok(()));
+
+        let report = engine.get_open_disputes(DisputeFilter::new()).await.unwrap();
+        assert_eq!(report.disputes.iter().map(|x| x.tx).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(report.amount, Decimal4::from(50));
+        let report = engine.get_open_disputes(DisputeFilter::new().with_client(2)).await.unwrap();
+        assert_eq!(report.disputes.iter().map(|x| (x.client, x.tx)).collect::<Vec<_>>(), vec![(2, 3)]);
+        let report = engine.get_open_disputes(DisputeFilter::new().with_min_age_ms(60_000)).await.unwrap();
+        assert_eq!(report.disputes, vec![]);
     }
 
     #[tokio::test]
-    async fn dispute_on_transaction_bound_to_another_account_err() {
+    async fn chargeback_ratio_ok() {
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(EnginePolicy { lock_on_chargeback: false, ..Default::default() });
+        for tx_id in 1..=4 {
+            assert_eq!(engine.deposit(1, tx_id, Decimal4::from(10)).await, Ok(()));
+        }
+        assert_eq!(engine.deposit(2, 5, Decimal4::from(10)).await, Ok(()));
+        for tx_id in [1, 2] {
+            assert_eq!(engine.dispute(1, tx_id).await, Ok(()));
+            assert_eq!(engine.chargeback(1, tx_id).await, Ok(()));
+        }
+
+        let stats = engine.get_chargeback_stats(1).await.unwrap();
+        assert_eq!((stats.deposits, stats.chargebacks, stats.ratio), (4, 2, "0.5".parse().unwrap()));
+        let report = engine.get_chargeback_report("0.01".parse().unwrap(), 1).await.unwrap();
+        assert_eq!(report.accounts, vec![stats]);
+        assert_eq!(engine.get_chargeback_report("0.5".parse().unwrap(), 1).await.unwrap().accounts, vec![]);
+    }
+
+    #[tokio::test]
+    async fn get_statement_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.dispute(2, 1).await, Err(EngineError::TransactionIsBoundToAnotherAccount(1)));
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(40)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        let statement = engine.get_statement(1, 0, u64::MAX).await.unwrap();
+        assert_eq!(statement.lines.iter().map(|x| x.op_type).collect::<Vec<_>>(), vec!["deposit", "withdrawal", "dispute"]);
+        assert_eq!(statement.opening.total, Decimal4::zero());
+        assert_eq!(statement.closing.available, Decimal4::from(-40));
+        assert_eq!(statement.closing.held, Decimal4::from(100));
+        assert!(statement.lines.iter().all(|x| x.timestamp > 0));
     }
 
     #[tokio::test]
-    async fn resolve_on_transaction_bound_to_another_account_err() {
+    async fn get_statement_over_journal_pages() {
+        let clock = ManualClock::new(1000);
+        let engine = Engine::new(EchoDbStorage::new()).with_clock(Arc::new(clock.clone()));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        for tx_id in 2..=1001 {
+            assert_eq!(engine.deposit(2, tx_id, Decimal4::from(1)).await, Ok(()));
+        }
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(engine.withdraw(1, 1002, Decimal4::from(4)).await, Ok(()));
+
+        let statement = engine.get_statement(1, 2000, u64::MAX).await.unwrap();
+        assert_eq!(statement.lines.iter().map(|x| (x.seq, x.tx)).collect::<Vec<_>>(), vec![(1002, 1002)]);
+        assert_eq!((statement.opening.available, statement.closing.available), (Decimal4::from(10), Decimal4::from(6)));
+    }
+
+    #[tokio::test]
+    async fn get_settlement_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.resolve(2, 1).await, Err(EngineError::TransactionIsBoundToAnotherAccount(1)));
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(40)).await, Ok(()));
+        assert_eq!(engine.dispute(2, 2).await, Ok(()));
+        assert_eq!(engine.chargeback(2, 2).await, Ok(()));
+        let settlement = engine.get_settlement(0, u64::MAX).await.unwrap();
+        let nets: Vec<_> = settlement.records.iter().map(|x| (x.client, x.net)).collect();
+        assert_eq!(nets, vec![(1, Decimal4::from(60)), (2, Decimal4::zero())]);
+        assert_eq!(settlement.net, Decimal4::from(60));
+        assert_eq!(engine.get_settlement(0, 1).await.unwrap().records, vec![]);
     }
 
     #[tokio::test]
-    async fn chargeback_on_transaction_bound_to_another_account_err() {
+    async fn tenants_are_isolated() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let first = engine.for_tenant("first");
+        let second = engine.for_tenant("second");
+        assert_eq!(first.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(second.deposit(1, 1, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(second.deposit(2, 2, Decimal4::from(5)).await, Ok(()));
+        assert_eq!(first.dispute(1, 1).await, Ok(()));
+
+        assert_eq!(first.get_all_accounts().await.unwrap().len(), 1);
+        assert_eq!(second.get_all_accounts().await.unwrap().len(), 2);
+        assert_eq!(engine.get_all_accounts().await, Ok(vec![]));
+        assert_eq!(first.get_account(1).await.unwrap().unwrap().held(), Decimal4::from(100));
+        assert_eq!(second.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(30));
+        assert_eq!(second.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn reconcile_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.chargeback(2, 1).await, Err(EngineError::TransactionIsBoundToAnotherAccount(1)));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(40)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 3, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.dispute(2, 3).await, Ok(()));
+        assert_eq!(engine.chargeback(2, 3).await, Ok(()));
+        let report = engine.reconcile().await.unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(report.accounts_checked, 2);
+        assert_eq!(report.transactions_checked, 3);
+    }
+
+    #[test]
+    fn error_codes_are_unique() {
+        let errors = [
+            EngineError::AccountNotFound,
+            EngineError::TransactionNotFound,
+            EngineError::AccountLocked,
+            EngineError::InsufficientFunds,
+            EngineError::AmountIsNotPositive,
+            EngineError::TransactionWithTheSameIdAlreadyExists,
+            EngineError::TransactionIsBoundToAnotherAccount(1),
+            EngineError::InvalidTxType,
+            EngineError::ForbiddenTxStateTransition { from: TransactionState::Posted, to: TransactionState::Chargeback },
+            EngineError::DisputeWindowExpired,
+            EngineError::AmountOverflow,
+            EngineError::AmountLimitExceeded,
+            EngineError::BalanceLimitExceeded,
+            EngineError::DuplicateExternalId,
+            EngineError::AccountFrozen,
+            EngineError::AccountClosed,
+            EngineError::AccountNotEmpty,
+            EngineError::ForbiddenAccountStatusTransition { from: AccountStatus::Closed, to: AccountStatus::Active },
+            EngineError::RiskDenied,
+            EngineError::OpenDisputes,
+            EngineError::AuthorizationExpired,
+            EngineError::AuthorizationNotExpired,
+            EngineError::InsufficientEscrow,
+            EngineError::DisputeExceedsAvailable,
+            EngineError::ConcurrentOperationDetected,
+            EngineError::Paused,
+            EngineError::CorruptedJournal(1),
+            EngineError::SnapshotError(SnapshotError::StorageNotEmpty),
+            EngineError::DatabaseError(String::new()),
+            EngineError::HeldUnderflow,
+            EngineError::ActorStopped,
+        ];
+        let codes: std::collections::HashSet<u16> = errors.iter().map(EngineError::code).collect();
+        assert_eq!(codes.len(), errors.len());
+        assert_eq!(EngineError::InsufficientFunds.code(), 104);
     }
 
     #[tokio::test]
-    async fn resolve_after_chargeback_err() {
+    async fn external_ids() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let options = |external_id| ExecuteOptions { external_id: Some(external_id), ..ExecuteOptions::default() };
+        assert_eq!(engine.execute_operation_with(Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) }, options("a1")).await, Ok(()));
+        assert_eq!(engine.execute_operation_with(Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) }, options("a1")).await, Ok(()));
+        assert_eq!(engine.execute_operation_with(Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(10) }, options("a1")).await, Err(EngineError::DuplicateExternalId));
+        assert_eq!(engine.execute_operation_with(Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(10) }, options("b2")).await, Ok(()));
+
+        let tx = engine.get_tx_by_external_id("a1").await.unwrap().unwrap();
+        assert_eq!((tx.id(), tx.external_id()), (1, Some("a1")));
+        assert_eq!(engine.get_tx_by_external_id("c3").await, Ok(None));
+        assert_eq!(engine.get_tx(2).await.unwrap().unwrap().external_id(), Some("b2"));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn provenance_journaled() {
         let engine = Engine::new(EchoDbStorage::new());
+        let provenance = Provenance::new(OperationSource::File { name: "day1.csv".to_string(), line: 2 }).with_correlation_id(Some("c1".to_string()));
+        let options = ExecuteOptions { provenance: Some(&provenance), ..ExecuteOptions::default() };
+        assert_eq!(engine.execute_operation_with(Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) }, options).await, Ok(()));
+        assert_eq!(engine.execute_operation_with(Operation::Dispute { acc_id: 1, tx_id: 1 }, options).await, Ok(()));
+        assert_eq!(engine.resolve(1, 1).await, Ok(()));
+
+        let entries = engine.get_journal_entries(1, 10).await.unwrap();
+        assert_eq!(entries.iter().map(JournalEntry::provenance).collect::<Vec<_>>(), vec![Some(&provenance), Some(&provenance), None]);
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn dispute_window_expired() {
+        let clock = ManualClock::new(1_000);
+        let engine = Engine::new(EchoDbStorage::new())
+            .with_policy(EnginePolicy { dispute_window: Some(Duration::from_secs(60)), ..Default::default() })
+            .with_clock(Arc::new(clock.clone()));
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(50)).await, Ok(()));
+        clock.advance(Duration::from_secs(60));
         assert_eq!(engine.dispute(1, 1).await, Ok(()));
-        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
-        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Chargeback, to: TransactionState::Posted }));
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(engine.dispute(1, 2).await, Err(EngineError::DisputeWindowExpired));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().held(), Decimal4::from(100));
+        let disputes = engine.get_open_disputes(DisputeFilter::new()).await.unwrap();
+        assert_eq!((disputes.as_of, disputes.disputes[0].opened_at), (61_001, 61_000));
     }
 
     #[tokio::test]
-    async fn resolve_after_posted_err() {
-        let engine = Engine::new(EchoDbStorage::new());
+    async fn authorization_expiry() {
+        let clock = ManualClock::new(1_000);
+        let engine = Engine::new(EchoDbStorage::new())
+            .with_policy(EnginePolicy { authorization_expiry: Some(Duration::from_secs(60)), ..Default::default() })
+            .with_clock(Arc::new(clock.clone()));
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Posted, to: TransactionState::Posted }));
+        assert_eq!(engine.authorize(1, 2, Decimal4::from(60)).await, Ok(()));
+        assert_eq!(engine.authorize(1, 3, Decimal4::from(50)).await, Err(EngineError::InsufficientFunds));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.held()), (Decimal4::from(40), Decimal4::from(60)));
+        assert_eq!(engine.get_tx(2).await.unwrap().unwrap().expires_at(), Some(61_000));
+
+        clock.advance(Duration::from_millis(59_999));
+        assert_eq!(engine.expire_holds(clock.now_millis()).await, Ok(0));
+        assert_eq!(engine.execute_operation(Operation::Expire { acc_id: 1, tx_id: 2 }).await, Err(EngineError::AuthorizationNotExpired));
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(engine.capture(1, 2).await, Err(EngineError::AuthorizationExpired));
+        assert_eq!(engine.expire_holds(clock.now_millis()).await, Ok(1));
+        assert_eq!(engine.expire_holds(clock.now_millis()).await, Ok(0));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.held()), (Decimal4::from(100), Decimal4::zero()));
+        assert_eq!(engine.get_tx(2).await.unwrap().unwrap().state(), TransactionState::Expired);
+        assert_eq!(engine.capture(1, 2).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Expired, to: TransactionState::Captured }));
+
+        assert_eq!(engine.authorize(1, 3, Decimal4::from(30)).await, Ok(()));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(engine.capture(1, 3).await, Ok(()));
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(engine.expire_holds(clock.now_millis()).await, Ok(0));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.held(), acc.total().unwrap()), (Decimal4::from(70), Decimal4::zero(), Decimal4::from(70)));
+
+        let entries = engine.get_journal_entries(1, 10).await.unwrap();
+        assert_eq!(entries.iter().map(|x| (x.operation().name(), x.timestamp())).collect::<Vec<_>>(),
+                   vec![("deposit", 1_000), ("authorize", 1_000), ("expire", 61_000), ("authorize", 61_000), ("capture", 62_000)]);
+        assert!(engine.reconcile().await.unwrap().is_consistent());
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
     }
 
     #[tokio::test]
-    async fn chargeback_after_posted_err() {
+    async fn amount_overflow_rejected() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let max = Decimal4::from(rust_decimal::Decimal::MAX);
+        assert_eq!(engine.deposit(1, 1, max).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(1)).await, Err(EngineError::AmountOverflow));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), max);
+        assert_eq!(engine.get_tx(2).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn amounts_rounded_to_policy_precision() {
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(EnginePolicy { rounding: Rounding::bankers(2), ..Default::default() });
+        assert_eq!(engine.deposit(1, 1, "10.125".parse().unwrap()).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, "0.135".parse().unwrap()).await, Ok(()));
+        assert_eq!(engine.deposit(1, 3, "0.004".parse().unwrap()).await, Err(EngineError::AmountIsNotPositive));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), "9.98".parse().unwrap());
+        assert_eq!(engine.get_tx(1).await.unwrap().unwrap().amount(), "10.12".parse().unwrap());
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn validate_operation_dry_run() {
+        let engine = Engine::new(EchoDbStorage::new());
+        engine.deposit(1, 1, Decimal4::from(10)).await.unwrap();
+        let withdrawal = |amount| Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(amount) };
+        assert_eq!(engine.validate_operation(withdrawal(4), None).await.unwrap().available(), Decimal4::from(6));
+        assert_eq!(engine.validate_operation(withdrawal(11), None).await, Err(EngineError::InsufficientFunds));
+        assert_eq!(engine.validate_operation(Operation::Dispute { acc_id: 1, tx_id: 1 }, None).await.unwrap().held(), Decimal4::from(10));
+        let deposit = Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) };
+        assert_eq!(engine.validate_operation(deposit, None).await.unwrap().available(), Decimal4::from(10)); // NOTE: already applied
+        assert_eq!(engine.validate_operation(Operation::Deposit { acc_id: 1, tx_id: 3, amount: Decimal::ZERO }, None).await, Err(EngineError::AmountIsNotPositive));
+
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(10));
+        assert_eq!(engine.get_tx(2).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn amount_and_balance_limits() {
+        let policy = EnginePolicy { max_amount: Some(Decimal4::from(1000)), max_balance: Some(Decimal4::from(1500)), ..Default::default() };
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(policy);
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(1000)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, "10000000000000000000000000".parse().unwrap()).await, Err(EngineError::AmountLimitExceeded));
+        assert_eq!(engine.deposit(1, 3, Decimal4::from(600)).await, Err(EngineError::BalanceLimitExceeded));
+        assert_eq!(engine.deposit(2, 4, Decimal4::from(1600)).await, Err(EngineError::AmountLimitExceeded));
+        assert_eq!(engine.deposit(1, 5, Decimal4::from(500)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 6, Decimal4::from(1001)).await, Err(EngineError::AmountLimitExceeded));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().total().unwrap(), Decimal4::from(1500));
+        assert_eq!(engine.get_account(2).await, Ok(None));
+        assert_eq!(engine.get_tx(3).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn held_underflow_rejected() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        // NOTE: a transaction marked as disputed without holding its funds, as a logic bug or a corrupted storage would leave it
+        let mut db_tx = engine.storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let old_tx = engine.storage.get_tx(&mut db_tx, 1).await.unwrap().unwrap();
+        let mut new_tx = old_tx.clone();
+        new_tx.set_state(TransactionState::Disputed).unwrap();
+        engine.storage.update_tx(&mut db_tx, &old_tx, &new_tx).await.unwrap();
+        engine.storage.commit_db_tx(db_tx).await.unwrap();
+
+        assert_eq!(engine.resolve(1, 1).await, Err(EngineError::HeldUnderflow));
+        assert_eq!(engine.chargeback(1, 1).await, Err(EngineError::HeldUnderflow));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.held(), acc.locked()), (Decimal4::zero(), false));
+    }
+
+    #[tokio::test]
+    async fn account_metadata_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
-        assert_eq!(engine.chargeback(1, 1).await, Err(EngineError::ForbiddenTxStateTransition { from: TransactionState::Posted, to: TransactionState::Chargeback }));
+        let created_at = engine.get_account(1).await.unwrap().unwrap().created_at();
+        assert!(created_at > 0);
+
+        let metadata = AccountMetadata { name: Some("Alice".to_string()), external_ref: Some("crm-42".to_string()) };
+        let acc = engine.set_account_metadata(1, metadata.clone()).await.unwrap();
+        assert_eq!((acc.metadata(), acc.created_at()), (&metadata, created_at));
+        assert!(acc.updated_at() >= created_at);
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().metadata(), &metadata);
+        assert_eq!(engine.set_account_metadata(2, metadata).await, Err(EngineError::AccountNotFound));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
     }
 
     #[tokio::test]
-    async fn chargeback_after_resolve_and_second_dispute_ok() {
+    async fn account_status_ok() {
         let engine = Engine::new(EchoDbStorage::new());
         assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(50)).await, Ok(()));
         assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        assert_eq!(engine.deposit(1, 3, Decimal4::from(10)).await, Err(EngineError::AccountLocked));
+
+        let acc = engine.set_account_status(1, AccountStatus::Frozen).await.unwrap();
+        assert_eq!((acc.status(), acc.locked()), (AccountStatus::Frozen, false));
+        assert_eq!(engine.deposit(1, 3, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 4, Decimal4::from(60)).await, Err(EngineError::AccountFrozen));
+        assert_eq!(engine.set_account_status(1, AccountStatus::Closed).await, Err(EngineError::AccountNotEmpty));
+        assert_eq!(engine.set_account_status(1, AccountStatus::Active).await.map(|x| x.status()), Ok(AccountStatus::Active));
+        assert_eq!(engine.withdraw(1, 4, Decimal4::from(60)).await, Ok(()));
+        assert_eq!(engine.set_account_status(1, AccountStatus::Closed).await.map(|x| x.status()), Ok(AccountStatus::Closed));
+        assert_eq!(engine.deposit(1, 5, Decimal4::from(10)).await, Err(EngineError::AccountClosed));
+        assert_eq!(engine.set_account_status(1, AccountStatus::Active).await, Err(EngineError::ForbiddenAccountStatusTransition { from: AccountStatus::Closed, to: AccountStatus::Active }));
+        assert_eq!(engine.set_account_status(2, AccountStatus::Frozen).await, Err(EngineError::AccountNotFound));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn chargeback_without_lock() {
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(EnginePolicy { lock_on_chargeback: false, ..Default::default() });
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(20)).await, Ok(()));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert!(!acc.locked());
+        assert_eq!(acc.total().unwrap(), Decimal4::from(30));
+        assert!(engine.reconcile().await.unwrap().is_consistent());
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn negative_available_policies() {
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(EnginePolicy { negative_available: NegativeAvailablePolicy::Reject, ..Default::default() });
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(80)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Err(EngineError::DisputeExceedsAvailable));
+        assert_eq!(engine.dispute(1, 2).await, Ok(()));
+
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(EnginePolicy { negative_available: NegativeAvailablePolicy::Clamp, ..Default::default() });
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(120)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.held()), (Decimal4::zero(), Decimal4::from(30)));
+        assert_eq!(engine.get_tx(1).await.unwrap().unwrap().shortfall(), Decimal4::from(70));
+        assert!(engine.reconcile().await.unwrap().is_consistent());
         assert_eq!(engine.resolve(1, 1).await, Ok(()));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(30));
+        assert_eq!(engine.get_tx(1).await.unwrap().unwrap().shortfall(), Decimal4::zero());
+
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.held()), (Decimal4::from(-70), Decimal4::zero()));
+        assert!(engine.reconcile().await.unwrap().is_consistent());
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn auto_unlock_when_settled() {
+        let policy = EnginePolicy { write_off_chargeback_losses: true, auto_unlock: true, ..Default::default() };
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(policy);
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(120)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.dispute(1, 2).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.locked(), acc.available()), (true, Decimal4::zero()));
+        assert_eq!(engine.get_tx(1).await.unwrap().unwrap().written_off(), Decimal4::from(120));
+
+        assert_eq!(engine.resolve(1, 2).await, Ok(()));
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.status(), acc.available()), (AccountStatus::Active, Decimal4::from(50)));
+        assert_eq!(engine.deposit(1, 4, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+
+        // NOTE: the lock of an account without chargebacks was set by an admin, it is kept
+        assert_eq!(engine.deposit(2, 5, Decimal4::from(10)).await, Ok(()));
+        assert!(engine.set_account_status(2, AccountStatus::Locked).await.is_ok());
+        assert_eq!(engine.dispute(2, 5).await, Ok(()));
+        assert_eq!(engine.resolve(2, 5).await, Ok(()));
+        assert!(engine.get_account(2).await.unwrap().unwrap().locked());
+    }
+
+    #[tokio::test]
+    async fn system_accounts() {
+        let policy = EnginePolicy { withdrawal_fee: Some(Decimal4::from(1)), write_off_chargeback_losses: true, ..Default::default() };
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(policy);
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(100)).await, Err(EngineError::InsufficientFunds));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(30)).await, Ok(()));
         assert_eq!(engine.dispute(1, 1).await, Ok(()));
         assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.held()), (Decimal4::zero(), Decimal4::zero()));
+        assert_eq!(engine.get_tx(1).await.unwrap().unwrap().written_off(), Decimal4::from(31));
+        assert_eq!(engine.get_system_account(SystemAccount::Fees).await.unwrap().available(), Decimal4::from(1));
+        assert_eq!(engine.get_system_account(SystemAccount::ChargebackLosses).await.unwrap().available(), Decimal4::from(-31));
+        assert!(engine.reconcile().await.unwrap().is_consistent());
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+
+        // NOTE: the system accounts are stored apart, the highest client ids are regular clients
+        assert_eq!(engine.deposit(ClientId::MAX, 3, Decimal4::from(10)).await, Ok(()));
+        assert!(engine.set_account_status(ClientId::MAX, AccountStatus::Locked).await.is_ok());
+        let acc = engine.get_account(ClientId::MAX).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.locked()), (Decimal4::from(10), true));
+        assert_eq!(engine.get_system_account(SystemAccount::Fees).await.unwrap().available(), Decimal4::from(1));
+        assert!(engine.reconcile().await.unwrap().is_consistent());
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn escrow_and_release() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.escrow(1, 2, "order-7", Decimal4::from(60)).await, Ok(()));
+        assert_eq!(engine.escrow(1, 2, "order-7", Decimal4::from(60)).await, Ok(())); // idempotency
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(50)).await, Err(EngineError::InsufficientFunds));
+        assert_eq!(engine.release_escrow(1, 3, "order-7", Decimal4::from(70)).await, Err(EngineError::InsufficientEscrow));
+        assert_eq!(engine.dispute(1, 2).await, Err(EngineError::InvalidTxType));
+
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.escrowed_in("order-7"), acc.total().unwrap()), (Decimal4::from(40), Decimal4::from(60), Decimal4::from(100)));
+        assert_eq!(engine.get_tx(2).await.unwrap().unwrap().escrow_bucket(), Some("order-7"));
+
+        assert_eq!(engine.release_escrow(1, 3, "order-7", Decimal4::from(60)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 4, Decimal4::from(50)).await, Ok(()));
+        assert!(engine.reconcile().await.unwrap().is_consistent());
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn snapshot_export_import_ok() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.dispute(2, 2).await, Ok(()));
+        let mut data = Vec::new();
+        engine.export_snapshot(&mut data).await.unwrap();
+
+        let restored = Engine::new(EchoDbStorage::new());
+        assert_eq!(restored.import_snapshot(&mut data.as_slice()).await, Ok(()));
+        assert_eq!(restored.get_all_accounts().await, engine.get_all_accounts().await);
+        assert_eq!(restored.get_journal_entries(0, usize::MAX).await, engine.get_journal_entries(0, usize::MAX).await);
+        assert_eq!(restored.verify_journal().await, Ok(vec![]));
+        assert_eq!(restored.deposit(1, 1, Decimal4::from(100)).await, Ok(())); // idempotency records are restored
+        assert_eq!(restored.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(100));
+        assert_eq!(restored.resolve(2, 2).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn snapshot_import_into_non_empty_storage_err() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        let mut data = Vec::new();
+        engine.export_snapshot(&mut data).await.unwrap();
+        assert_eq!(engine.import_snapshot(&mut data.as_slice()).await, Err(EngineError::SnapshotError(SnapshotError::StorageNotEmpty)));
+    }
+
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    #[tokio::test]
+    async fn process_stream_ok() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let operations = futures::stream::iter(vec![
+            Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) },
+            Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(200) },
+            Operation::Dispute { acc_id: 1, tx_id: 1 },
+        ]);
+        let results: Vec<_> = engine.process_stream(operations, 1).collect().await;
+        assert_eq!(results, vec![
+            (Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) }, Ok(())),
+            (Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(200) }, Err(EngineError::InsufficientFunds)),
+            (Operation::Dispute { acc_id: 1, tx_id: 1 }, Ok(())),
+        ]);
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!(acc.held(), Decimal4::from(100));
+    }
+
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    #[tokio::test]
+    async fn process_stream_stops_when_output_dropped() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let (done, stopped) = futures::channel::oneshot::channel::<()>();
+        let operations = futures::stream::iter((0..100).map(|i| Operation::Deposit { acc_id: 1, tx_id: i, amount: Decimal::from(1) }))
+            .map(move |x| { let _ = &done; x }); // NOTE: `done` is dropped with the input stream, when the task stops
+        let mut results = Box::pin(engine.process_stream(operations, 1));
+        assert_eq!(results.next().await.map(|(_, result)| result), Some(Ok(())));
+        drop(results);
+        assert!(stopped.await.is_err());
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert!(acc.available() < Decimal4::from(100));
     }
 
     #[tokio::test]
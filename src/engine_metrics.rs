@@ -0,0 +1,186 @@
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+use crate::account::Account;
+use crate::decimal::Decimal4;
+use crate::engine::{Engine, EngineError};
+use crate::journal::Journal;
+use crate::observer::{EngineEvent, EngineObserver};
+use crate::storage::Storage;
+use crate::transaction::Transaction;
+
+/// Latency histogram (seconds) of every operation executed by the engine, labeled with `type`. Always recorded with the `metrics` feature.
+pub const ENGINE_DURATION_METRIC: &str = "engine_operation_duration_seconds";
+/// Counter of operations, labeled with `type` and `outcome` (`applied` or the rejection reason, e.g. `insufficient_funds`).
+pub const ENGINE_OPERATIONS_METRIC: &str = "engine_operations_total";
+/// Gauge of the funds held by open disputes and authorizations, over all the accounts.
+pub const HELD_FUNDS_METRIC: &str = "engine_held_funds";
+/// Gauge of the accounts locked by a chargeback.
+pub const LOCKED_ACCOUNTS_METRIC: &str = "engine_locked_accounts";
+
+/// Observer that records the operation counters and keeps the held funds and locked accounts gauges up to date
+/// via the `metrics` facade. The gauges only follow the changes, call [`record_state_gauges`] once at startup
+/// to include the state already in the storage.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetricsObserver;
+
+impl EngineObserver for MetricsObserver {
+    fn on_dispute_opened(&self, _account: &Account, transaction: &Transaction) {
+        metrics::gauge!(HELD_FUNDS_METRIC).increment(to_f64(transaction.amount()));
+    }
+
+    fn on_dispute_resolved(&self, _account: &Account, transaction: &Transaction) {
+        metrics::gauge!(HELD_FUNDS_METRIC).decrement(to_f64(transaction.amount()));
+    }
+
+    fn on_chargeback_applied(&self, _account: &Account, transaction: &Transaction) {
+        metrics::gauge!(HELD_FUNDS_METRIC).decrement(to_f64(transaction.amount()));
+    }
+
+    fn on_authorization_held(&self, _account: &Account, transaction: &Transaction) {
+        metrics::gauge!(HELD_FUNDS_METRIC).increment(to_f64(transaction.amount()));
+    }
+
+    fn on_authorization_captured(&self, _account: &Account, transaction: &Transaction) {
+        metrics::gauge!(HELD_FUNDS_METRIC).decrement(to_f64(transaction.amount()));
+    }
+
+    fn on_authorization_expired(&self, _account: &Account, transaction: &Transaction) {
+        metrics::gauge!(HELD_FUNDS_METRIC).decrement(to_f64(transaction.amount()));
+    }
+
+    fn on_account_locked(&self, _account: &Account) {
+        metrics::gauge!(LOCKED_ACCOUNTS_METRIC).increment(1.0);
+    }
+
+    fn on_account_unlocked(&self, _account: &Account) {
+        metrics::gauge!(LOCKED_ACCOUNTS_METRIC).decrement(1.0);
+    }
+
+    fn on_event(&self, event: &EngineEvent) {
+        let (op_type, outcome) = match event {
+            EngineEvent::DepositApplied { .. } => ("deposit", "applied"),
+            EngineEvent::WithdrawalApplied { .. } => ("withdrawal", "applied"),
+            EngineEvent::DisputeOpened { .. } => ("dispute", "applied"),
+            EngineEvent::DisputeResolved { .. } => ("resolve", "applied"),
+            EngineEvent::ChargebackApplied { .. } => ("chargeback", "applied"),
+            EngineEvent::EscrowMoved { .. } => ("escrow", "applied"),
+            EngineEvent::EscrowReleased { .. } => ("release", "applied"),
+            EngineEvent::AuthorizationHeld { .. } => ("authorize", "applied"),
+            EngineEvent::AuthorizationCaptured { .. } => ("capture", "applied"),
+            EngineEvent::AuthorizationExpired { .. } => ("expire", "applied"),
+            EngineEvent::AccountLocked { .. } | EngineEvent::AccountUnlocked { .. } => return,
+            EngineEvent::OperationRejected { operation, error, .. } => (operation.name(), outcome(error)),
+        };
+        metrics::counter!(ENGINE_OPERATIONS_METRIC, "type" => op_type, "outcome" => outcome).increment(1);
+    }
+}
+
+/// Sets the held funds and locked accounts gauges from a full scan of the accounts.
+pub async fn record_state_gauges<TStorage: Storage + Journal>(engine: &Engine<TStorage>) -> Result<(), EngineError> {
+    const PAGE_SIZE: usize = 1000;
+    let (mut held, mut locked) = (Decimal4::zero(), 0u64);
+    let mut cursor = None;
+    loop {
+        let accounts = engine.list_accounts(cursor, PAGE_SIZE).await?;
+        let Some(last) = accounts.last() else { break };
+        cursor = Some(last.id());
+        for account in accounts.iter() {
+            held += account.held();
+            locked += account.locked() as u64;
+        }
+    }
+    metrics::gauge!(HELD_FUNDS_METRIC).set(to_f64(held));
+    metrics::gauge!(LOCKED_ACCOUNTS_METRIC).set(locked as f64);
+    Ok(())
+}
+
+fn to_f64(amount: Decimal4) -> f64 {
+    Decimal::from(amount).to_f64().unwrap_or(f64::NAN)
+}
+
+fn outcome(error: &EngineError) -> &'static str {
+    match error {
+        EngineError::AccountNotFound => "account_not_found",
+        EngineError::TransactionNotFound => "transaction_not_found",
+        EngineError::AccountLocked => "account_locked",
+        EngineError::InsufficientFunds => "insufficient_funds",
+        EngineError::AmountIsNotPositive => "amount_not_positive",
+        EngineError::TransactionWithTheSameIdAlreadyExists => "duplicate_transaction",
+        EngineError::TransactionIsBoundToAnotherAccount(_) => "transaction_of_another_account",
+        EngineError::InvalidTxType => "invalid_transaction_type",
+        EngineError::ForbiddenTxStateTransition { .. } => "forbidden_state_transition",
+        EngineError::DisputeWindowExpired => "dispute_window_expired",
+        EngineError::AmountOverflow => "amount_overflow",
+        EngineError::AmountLimitExceeded => "amount_limit_exceeded",
+        EngineError::BalanceLimitExceeded => "balance_limit_exceeded",
+        EngineError::DuplicateExternalId => "duplicate_external_id",
+        EngineError::AccountFrozen => "account_frozen",
+        EngineError::AccountClosed => "account_closed",
+        EngineError::AccountNotEmpty => "account_not_empty",
+        EngineError::ForbiddenAccountStatusTransition { .. } => "forbidden_status_transition",
+        EngineError::RiskDenied => "risk_denied",
+        EngineError::OpenDisputes => "open_disputes",
+        EngineError::InsufficientEscrow => "insufficient_escrow",
+        EngineError::DisputeExceedsAvailable => "dispute_exceeds_available",
+        EngineError::ConcurrentOperationDetected => "concurrent_operation",
+        EngineError::Paused => "paused",
+        EngineError::CorruptedJournal(_) => "corrupted_journal",
+        EngineError::SnapshotError(_) => "snapshot_error",
+        EngineError::DatabaseError(_) => "database_error",
+        EngineError::HeldUnderflow => "held_underflow",
+        EngineError::ActorStopped => "actor_stopped",
+        EngineError::AuthorizationExpired => "authorization_expired",
+        EngineError::AuthorizationNotExpired => "authorization_not_expired",
+    }
+}
+
+#[cfg(test)]
+mod engine_metrics_tests {
+    use std::sync::Arc;
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[test]
+    fn operation_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+                let unobserved = Engine::new(EchoDbStorage::new());
+                assert_eq!(unobserved.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+                assert_eq!(unobserved.dispute(1, 1).await, Ok(()));
+                record_state_gauges(&unobserved).await.unwrap();
+
+                let engine = unobserved.with_observer(Arc::new(MetricsObserver));
+                assert_eq!(engine.deposit(2, 2, Decimal4::from(5)).await, Ok(()));
+                assert_eq!(engine.dispute(2, 2).await, Ok(()));
+                assert_eq!(engine.chargeback(2, 2).await, Ok(()));
+                assert_eq!(engine.withdraw(2, 3, Decimal4::from(1)).await, Err(EngineError::AccountLocked));
+            });
+        });
+
+        let values: Vec<_> = snapshotter.snapshot().into_vec().into_iter()
+            .map(|(key, _, _, value)| {
+                let labels: Vec<String> = key.key().labels().map(|x| format!("{}={}", x.key(), x.value())).collect();
+                (key.kind(), key.key().name().to_string(), labels, value)
+            })
+            .collect();
+        let find = |kind: MetricKind, name: &str, labels: &[&str]| values.iter()
+            .find(|x| x.0 == kind && x.1 == name && x.2 == labels)
+            .map(|x| &x.3);
+        assert_eq!(find(MetricKind::Gauge, HELD_FUNDS_METRIC, &[]), Some(&DebugValue::Gauge(10.0.into())));
+        assert_eq!(find(MetricKind::Gauge, LOCKED_ACCOUNTS_METRIC, &[]), Some(&DebugValue::Gauge(1.0.into())));
+        assert_eq!(find(MetricKind::Counter, ENGINE_OPERATIONS_METRIC, &["type=deposit", "outcome=applied"]), Some(&DebugValue::Counter(1)));
+        assert_eq!(find(MetricKind::Counter, ENGINE_OPERATIONS_METRIC, &["type=withdrawal", "outcome=account_locked"]), Some(&DebugValue::Counter(1)));
+        let Some(DebugValue::Histogram(deposits)) = find(MetricKind::Histogram, ENGINE_DURATION_METRIC, &["type=deposit"]) else {
+            panic!("missing histogram");
+        };
+        assert_eq!(deposits.len(), 2);
+    }
+}
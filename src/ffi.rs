@@ -0,0 +1,251 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use rust_decimal::Decimal;
+
+use crate::account::ClientId;
+use crate::decimal::Decimal4;
+use crate::dyn_storage::{open_storage, DynStorage};
+use crate::engine::{Engine, EngineError, Operation};
+use crate::transaction::TxId;
+
+/// The operation succeeded. The other codes are the `EngineError` codes (positive) or the FFI errors (negative).
+pub const TE_OK: i32 = 0;
+
+/// A null pointer, an id out of range of the build or an unknown operation type.
+pub const TE_INVALID_ARGUMENT: i32 = -1;
+
+/// The engine panicked, the handle should not be used anymore.
+pub const TE_PANIC: i32 = -2;
+
+pub const TE_DEPOSIT: u8 = 0;
+pub const TE_WITHDRAWAL: u8 = 1;
+pub const TE_DISPUTE: u8 = 2;
+pub const TE_RESOLVE: u8 = 3;
+pub const TE_CHARGEBACK: u8 = 4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An engine with the runtime its calls are blocking on, opaque to C.
+pub struct TeEngine {
+    runtime: tokio::runtime::Runtime,
+    engine: Engine<Box<dyn DynStorage>>,
+}
+
+/// The state of an account. The amounts are in minor units (1/10000), e.g. `1.5` is `15000`.
+/// The status is 0 (active), 1 (locked), 2 (frozen) or 3 (closed).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TeAccount {
+    pub client: u64,
+    pub available: i64,
+    pub held: i64,
+    pub total: i64,
+    pub locked: bool,
+    pub status: u8,
+}
+
+/// Opens an engine over a storage: `memory`, `file:<path>` or `sqlite:<url>` (with the `sqlite` feature).
+/// Returns null on failure, see `te_last_error`. The engine is freed with `te_engine_free`.
+///
+/// # Safety
+///
+/// `storage` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn te_engine_new(storage: *const c_char) -> *mut TeEngine {
+    let result = catch_unwind(|| {
+        if storage.is_null() {
+            anyhow::bail!("the storage is null");
+        }
+        let storage = CStr::from_ptr(storage).to_str()?;
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        let engine = Engine::new(runtime.block_on(open_storage(storage))?);
+        Ok(TeEngine { runtime, engine })
+    });
+    match result {
+        Ok(Ok(engine)) => Box::into_raw(Box::new(engine)),
+        Ok(Err(err)) => {
+            set_last_error(format!("{:#}", err));
+            std::ptr::null_mut()
+        }
+        Err(_) => {
+            set_last_error("the engine panicked".to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Frees an engine of `te_engine_new` and shuts its runtime down. A null engine is ignored.
+///
+/// # Safety
+///
+/// `engine` must be null or an engine of `te_engine_new` that is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn te_engine_free(engine: *mut TeEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Executes an operation (`TE_DEPOSIT`, ...), `amount` in minor units is ignored by the operations without an amount.
+/// Returns `TE_OK`, the code of the `EngineError` rejecting the operation or an FFI error.
+/// The engine can be called from several threads at once.
+///
+/// # Safety
+///
+/// `engine` must be an engine of `te_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn te_engine_execute(engine: *const TeEngine, op_type: u8, client: u64, tx: u64, amount: i64) -> i32 {
+    let Some(engine) = engine.as_ref() else {
+        return invalid_argument("the engine is null");
+    };
+    let (Some(acc_id), Some(tx_id)) = (ClientId::try_from(client).ok(), TxId::try_from(tx).ok()) else {
+        return invalid_argument("the id is out of range");
+    };
+    let amount = Decimal::from(Decimal4::from_minor_units(amount));
+    let operation = match op_type {
+        TE_DEPOSIT => Operation::Deposit { acc_id, tx_id, amount },
+        TE_WITHDRAWAL => Operation::Withdraw { acc_id, tx_id, amount },
+        TE_DISPUTE => Operation::Dispute { acc_id, tx_id },
+        TE_RESOLVE => Operation::Resolve { acc_id, tx_id },
+        TE_CHARGEBACK => Operation::Chargeback { acc_id, tx_id },
+        x => return invalid_argument(&format!("unknown operation type: {}", x)),
+    };
+    guarded(|| match engine.runtime.block_on(engine.engine.execute_operation(operation)) {
+        Ok(()) => TE_OK,
+        Err(err) => error(err),
+    })
+}
+
+/// Writes the state of an account to `account`. Returns `TE_OK`, 101 (`AccountNotFound`), 111 (`AmountOverflow`)
+/// when a balance doesn't fit into the minor units, the code of another `EngineError` or an FFI error.
+///
+/// # Safety
+///
+/// `engine` must be an engine of `te_engine_new` and `account` must point to a writable `TeAccount`.
+#[no_mangle]
+pub unsafe extern "C" fn te_engine_get_account(engine: *const TeEngine, client: u64, account: *mut TeAccount) -> i32 {
+    let (Some(engine), Some(account)) = (engine.as_ref(), account.as_mut()) else {
+        return invalid_argument("the engine or the account is null");
+    };
+    let Some(acc_id) = ClientId::try_from(client).ok() else {
+        return invalid_argument("the id is out of range");
+    };
+    guarded(|| {
+        let acc = match engine.runtime.block_on(engine.engine.get_account(acc_id)) {
+            Ok(Some(acc)) => acc,
+            Ok(None) => return error(EngineError::AccountNotFound),
+            Err(err) => return error(err),
+        };
+        let (Ok(available), Ok(held), Ok(total)) = (acc.available().to_minor_units(), acc.held().to_minor_units(), acc.total().and_then(Decimal4::to_minor_units)) else {
+            return error(EngineError::AmountOverflow);
+        };
+        *account = TeAccount { client, available, held, total, locked: acc.locked(), status: acc.status() as u8 };
+        TE_OK
+    })
+}
+
+/// The message of the last error on the calling thread, or null when no call failed yet.
+/// The message is freed with `te_string_free`.
+#[no_mangle]
+pub extern "C" fn te_last_error() -> *mut c_char {
+    LAST_ERROR.with(|x| x.borrow().clone()).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Frees a string returned by the library. A null string is ignored.
+///
+/// # Safety
+///
+/// `string` must be null or a string of this library that is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn te_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+fn set_last_error(message: String) {
+    // NOTE: the messages never contain a NUL, the replacement only keeps the conversion infallible
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|x| *x.borrow_mut() = Some(message));
+}
+
+fn invalid_argument(message: &str) -> i32 {
+    set_last_error(message.to_string());
+    TE_INVALID_ARGUMENT
+}
+
+fn error(err: EngineError) -> i32 {
+    set_last_error(err.to_string());
+    i32::from(err.code())
+}
+
+/// Unwinding into C is undefined behavior, so a panic is reported as `TE_PANIC` instead.
+fn guarded(f: impl FnOnce() -> i32) -> i32 {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        set_last_error("the engine panicked".to_string());
+        TE_PANIC
+    })
+}
+
+#[cfg(test)]
+mod ffi_tests {
+    use super::*;
+
+    fn last_error() -> String {
+        let message = te_last_error();
+        let result = unsafe { CStr::from_ptr(message) }.to_str().unwrap().to_string();
+        unsafe { te_string_free(message) };
+        result
+    }
+
+    #[test]
+    fn engine_lifecycle() {
+        unsafe {
+            let engine = te_engine_new(c"memory".as_ptr());
+            assert!(!engine.is_null());
+            assert_eq!(te_engine_execute(engine, TE_DEPOSIT, 1, 1, 15_000), TE_OK);
+            assert_eq!(te_engine_execute(engine, TE_WITHDRAWAL, 1, 2, 20_000), 104);
+            assert_eq!(last_error(), EngineError::InsufficientFunds.to_string());
+            assert_eq!(te_engine_execute(engine, TE_DISPUTE, 1, 1, 0), TE_OK);
+
+            let mut account = TeAccount::default();
+            assert_eq!(te_engine_get_account(engine, 1, &mut account), TE_OK);
+            assert_eq!(account, TeAccount { client: 1, available: 0, held: 15_000, total: 15_000, locked: false, status: 0 });
+            assert_eq!(te_engine_execute(engine, TE_CHARGEBACK, 1, 1, 0), TE_OK);
+            assert_eq!(te_engine_get_account(engine, 1, &mut account), TE_OK);
+            assert_eq!((account.total, account.locked, account.status), (0, true, 1));
+            assert_eq!(te_engine_get_account(engine, 2, &mut account), 101);
+            te_engine_free(engine);
+        }
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        unsafe {
+            assert!(te_engine_new(c"postgres://localhost".as_ptr()).is_null());
+            assert_eq!(last_error(), "unknown storage: postgres://localhost");
+            assert!(te_engine_new(std::ptr::null()).is_null());
+
+            let engine = te_engine_new(c"memory".as_ptr());
+            assert_eq!(te_engine_execute(engine, 9, 1, 1, 0), TE_INVALID_ARGUMENT);
+            assert_eq!(last_error(), "unknown operation type: 9");
+            assert_eq!(te_engine_execute(std::ptr::null(), TE_DEPOSIT, 1, 1, 0), TE_INVALID_ARGUMENT);
+            assert_eq!(te_engine_get_account(engine, 1, std::ptr::null_mut()), TE_INVALID_ARGUMENT);
+            #[cfg(not(feature = "wide-ids"))]
+            assert_eq!(te_engine_execute(engine, TE_DEPOSIT, u64::MAX, 1, 10_000), TE_INVALID_ARGUMENT);
+            te_engine_free(engine);
+            te_engine_free(std::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn header_is_up_to_date() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/transactions_engine.h"));
+        let checked_in = include_str!("../include/transactions_engine.h");
+        assert_eq!(generated, checked_in, "copy the header generated into OUT_DIR to include/");
+    }
+}
@@ -0,0 +1,515 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::codec::{Codec, MessagePackCodec};
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, EchoDbStorage, Savepoint, Savepoints, Storage, TenantStorage, TxOptions};
+use crate::transaction::{Transaction, TxId};
+
+/// A single write made inside a storage transaction, also the unit of the replication batches of `ReplicatedStorage`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum LogRecord {
+    Account(Account),
+    Transaction(Transaction),
+    DeletedTransactions(Vec<TxId>),
+    Operation { op_hash: u64, timestamp: u64 },
+    PrunedOperations { older_than: u64 },
+    Checkpoint { source: String, rows: u64 },
+    JournalEntry(Box<JournalEntry>), // NOTE: boxed, the largest record
+    /// The tenant namespace of the following records of the frame, the first record of the frames of a tenant view.
+    Tenant(String),
+    OperationRejection { op_hash: u64, code: u16, timestamp: u64 },
+    SystemAccount { account: SystemAccount, acc: Account }, // NOTE: last, the older logs keep their variant indexes
+}
+
+/// Durable storage that keeps the state in memory (`EchoDbStorage`) and appends the writes of every committed
+/// storage transaction to a log file. The log is fsync'd before the in-memory commit, and replayed on startup.
+///
+/// Each storage transaction is written as one length-prefixed frame with a CRC32 of its payload, so a crash in the middle
+/// of a write leaves a torn frame at the end of the log. Such a frame is discarded (and truncated) during recovery, while
+/// a corrupted frame followed by other frames fails the recovery: the committed frames after it are never dropped.
+///
+/// Tenant views (`TenantStorage`) share the log with the root storage, their frames are tagged with the tenant.
+pub struct FileStorage {
+    memory: EchoDbStorage,
+    log: Arc<Mutex<File>>,
+    tenant: Option<String>,
+}
+
+pub struct FileDbTx {
+    inner: <EchoDbStorage as Storage>::DbTx,
+    records: Vec<LogRecord>,
+    savepoints: Savepoints<usize>, // NOTE: the number of records at each savepoint
+}
+
+impl FileStorage {
+    /// Opens the log at the given path (creating it if missing) and rebuilds the in-memory state from it.
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, DbError> {
+        let mut log = OpenOptions::new().read(true).create(true).append(true).open(path)?;
+        let (batches, valid_len) = read_batches(&mut log)?;
+        if valid_len < log.metadata()?.len() {
+            log.set_len(valid_len)?; // NOTE: drops the torn frame left by a crash
+            log.sync_all()?;
+        }
+
+        let memory = EchoDbStorage::new();
+        let mut db_tx = memory.start_db_tx(TxOptions::read_write()).await?;
+        for batch in batches {
+            let mut tenant_memory = None;
+            for record in batch {
+                match record {
+                    LogRecord::Tenant(tenant) => tenant_memory = Some(memory.for_tenant(&tenant)),
+                    record => restore(tenant_memory.as_ref().unwrap_or(&memory), &mut db_tx, record).await?,
+                }
+            }
+        }
+        memory.commit_db_tx(db_tx).await?;
+
+        Ok(Self { memory, log: Arc::new(Mutex::new(log)), tenant: None })
+    }
+}
+
+/// Set in the length prefix of the frames followed by the CRC32 of their payload, the older logs have frames without it.
+const CRC_FLAG: u32 = 1 << 31;
+
+fn write_frame(data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(8 + data.len());
+    frame.extend_from_slice(&(data.len() as u32 | CRC_FLAG).to_be_bytes());
+    frame.extend_from_slice(&crc32fast::hash(data).to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// Reads the frames of the log and returns their batches and the length of the log they span. Only the last frame may be
+/// incomplete or corrupted (torn by a crash), it is left out; a corrupted frame followed by others is an error.
+fn read_batches(log: &mut File) -> Result<(Vec<Vec<LogRecord>>, u64), DbError> {
+    let mut data = Vec::new();
+    log.read_to_end(&mut data)?;
+
+    let mut batches = Vec::new();
+    let mut offset = 0;
+    while let Some(len_bytes) = data.get(offset..offset + 4) {
+        let header = u32::from_be_bytes(len_bytes.try_into().expect("slice has 4 bytes"));
+        let (len, crc_len) = ((header & !CRC_FLAG) as usize, if header & CRC_FLAG != 0 { 4 } else { 0 });
+        let Some(frame) = data.get(offset + 4..offset + 4 + crc_len + len) else {
+            break; // NOTE: an incomplete last frame
+        };
+        let (crc, payload) = frame.split_at(crc_len);
+        let crc_matches = crc.is_empty() || crc == crc32fast::hash(payload).to_be_bytes();
+        let batch = crc_matches.then(|| MessagePackCodec.decode(payload).ok()).flatten();
+        let end = offset + 4 + frame.len();
+        match batch {
+            Some(batch) => batches.push(batch),
+            None if end == data.len() => break, // NOTE: a complete but torn last frame
+            None => return Err(DbError::DatabaseError(format!("Corrupted log frame at offset {}", offset))),
+        }
+        offset = end;
+    }
+    Ok((batches, offset as u64))
+}
+
+/// Repeats a write on another storage.
+pub(crate) async fn restore<S: Storage + Journal + Sync>(storage: &S, db_tx: &mut S::DbTx, record: LogRecord) -> Result<(), DbError>
+where
+    S::DbTx: Send,
+{
+    match record {
+        LogRecord::Account(acc) => match storage.get_account(db_tx, acc.id()).await? {
+            Some(old_acc) => storage.update_account(db_tx, &old_acc, &acc).await,
+            None => storage.insert_account(db_tx, &acc).await,
+        },
+        LogRecord::Transaction(tx) => match storage.get_tx(db_tx, tx.id()).await? {
+            Some(old_tx) => storage.update_tx(db_tx, &old_tx, &tx).await,
+            None => storage.insert_tx(db_tx, &tx).await,
+        },
+        LogRecord::DeletedTransactions(tx_ids) => storage.delete_txs(db_tx, &tx_ids).await.map(|_| ()),
+        LogRecord::Operation { op_hash, timestamp } => storage.insert_operation(db_tx, op_hash, timestamp).await,
+        LogRecord::PrunedOperations { older_than } => storage.prune_operations(db_tx, older_than).await.map(|_| ()),
+        LogRecord::Checkpoint { source, rows } => storage.set_checkpoint(db_tx, &source, rows).await,
+        LogRecord::JournalEntry(entry) => storage.append_journal_entry(db_tx, &entry).await,
+        LogRecord::Tenant(_) => Ok(()), // NOTE: switched by the caller
+        LogRecord::OperationRejection { op_hash, code, timestamp } => storage.insert_operation_rejection(db_tx, op_hash, code, timestamp).await,
+        LogRecord::SystemAccount { account, acc } => storage.set_system_account(db_tx, account, &acc).await,
+    }
+}
+
+impl Storage for FileStorage {
+    type DbTx = FileDbTx;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        self.memory.get_tx(&mut db_tx.inner, tx_id).await
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        self.memory.insert_tx(&mut db_tx.inner, tx).await?;
+        db_tx.records.push(LogRecord::Transaction(tx.clone()));
+        Ok(())
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        self.memory.update_tx(&mut db_tx.inner, old_tx, new_tx).await?;
+        db_tx.records.push(LogRecord::Transaction(new_tx.clone()));
+        Ok(())
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        self.memory.get_all_txs(&mut db_tx.inner).await
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        self.memory.get_txs(&mut db_tx.inner, tx_ids).await
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        self.memory.insert_txs(&mut db_tx.inner, txs).await?;
+        db_tx.records.extend(txs.iter().cloned().map(LogRecord::Transaction));
+        Ok(())
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        let deleted = self.memory.delete_txs(&mut db_tx.inner, tx_ids).await?;
+        if deleted > 0 {
+            db_tx.records.push(LogRecord::DeletedTransactions(tx_ids.to_vec()));
+        }
+        Ok(deleted)
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        self.memory.get_txs_by_account(&mut db_tx.inner, acc_id, cursor, limit).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        self.memory.get_tx_by_external_id(&mut db_tx.inner, external_id).await
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        self.memory.get_expired_authorizations(&mut db_tx.inner, now).await
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        self.memory.get_account(&mut db_tx.inner, acc_id).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        self.memory.get_all_accounts(&mut db_tx.inner).await
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        self.memory.list_accounts(&mut db_tx.inner, cursor, limit).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        self.memory.get_accounts(&mut db_tx.inner, acc_ids).await
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        self.memory.insert_accounts(&mut db_tx.inner, accs).await?;
+        db_tx.records.extend(accs.iter().cloned().map(LogRecord::Account));
+        Ok(())
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        self.memory.insert_account(&mut db_tx.inner, acc).await?;
+        db_tx.records.push(LogRecord::Account(acc.clone()));
+        Ok(())
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        self.memory.update_account(&mut db_tx.inner, old_acc, new_acc).await?;
+        db_tx.records.push(LogRecord::Account(new_acc.clone()));
+        Ok(())
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        self.memory.get_system_account(&mut db_tx.inner, account).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        self.memory.set_system_account(&mut db_tx.inner, account, acc).await?;
+        db_tx.records.push(LogRecord::SystemAccount { account, acc: acc.clone() });
+        Ok(())
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        self.memory.is_operation_processed(&mut db_tx.inner, op_hash).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        self.memory.insert_operation(&mut db_tx.inner, op_hash, timestamp).await?;
+        db_tx.records.push(LogRecord::Operation { op_hash, timestamp });
+        Ok(())
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        self.memory.get_all_operations(&mut db_tx.inner).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        let pruned = self.memory.prune_operations(&mut db_tx.inner, older_than).await?;
+        if pruned > 0 {
+            db_tx.records.push(LogRecord::PrunedOperations { older_than });
+        }
+        Ok(pruned)
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        self.memory.get_operation_rejection(&mut db_tx.inner, op_hash).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        self.memory.insert_operation_rejection(&mut db_tx.inner, op_hash, code, timestamp).await?;
+        db_tx.records.push(LogRecord::OperationRejection { op_hash, code, timestamp });
+        Ok(())
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        self.memory.get_checkpoint(&mut db_tx.inner, source).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        self.memory.set_checkpoint(&mut db_tx.inner, source, rows).await?;
+        db_tx.records.push(LogRecord::Checkpoint { source: source.to_string(), rows });
+        Ok(())
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        let inner = self.memory.start_db_tx(options).await?;
+        Ok(FileDbTx { inner, records: Vec::new(), savepoints: Savepoints::default() })
+    }
+
+    async fn commit_db_tx(&self, mut db_tx: Self::DbTx) -> Result<(), DbError> {
+        if !db_tx.records.is_empty() {
+            if let Some(tenant) = &self.tenant {
+                db_tx.records.insert(0, LogRecord::Tenant(tenant.clone()));
+            }
+            let frame = write_frame(&MessagePackCodec.encode(&db_tx.records)?);
+
+            let mut log = self.log.lock().map_err(|_| DbError::DatabaseError("Log file lock is poisoned".to_string()))?;
+            let len = log.metadata()?.len();
+            if let Err(err) = log.write_all(&frame).and_then(|_| log.sync_data()) {
+                let _ = log.set_len(len); // NOTE: a partially written frame would hide all the frames appended after it
+                return Err(err.into());
+            }
+        }
+        self.memory.commit_db_tx(db_tx.inner).await
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        let savepoint = self.memory.savepoint(&mut db_tx.inner).await?;
+        db_tx.savepoints.insert(savepoint, db_tx.records.len());
+        Ok(savepoint)
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        self.memory.rollback_to(&mut db_tx.inner, savepoint).await?;
+        let records_len = *db_tx.savepoints.rollback_to(savepoint)?;
+        db_tx.records.truncate(records_len);
+        Ok(())
+    }
+}
+
+/// # Panics
+/// `for_tenant` panics if the tenant id contains `:`, like `EchoDbStorage`.
+impl TenantStorage for FileStorage {
+    fn for_tenant(&self, tenant: &str) -> Self {
+        Self {
+            memory: self.memory.for_tenant(tenant),
+            log: self.log.clone(),
+            tenant: Some(tenant.to_string()),
+        }
+    }
+}
+
+impl Journal for FileStorage {
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        self.memory.get_last_journal_seq(&mut db_tx.inner).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        self.memory.append_journal_entry(&mut db_tx.inner, entry).await?;
+        db_tx.records.push(LogRecord::JournalEntry(Box::new(entry.clone())));
+        Ok(())
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        self.memory.get_journal_entries(&mut db_tx.inner, from_seq, limit).await
+    }
+}
+
+#[cfg(test)]
+mod file_storage_tests {
+    use std::path::PathBuf;
+
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("transactions_engine_{}_{}.log", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn state_survives_restart() {
+        let path = temp_log("restart");
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        let accounts = engine.get_all_accounts().await.unwrap();
+        drop(engine);
+
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        assert_eq!(engine.get_all_accounts().await.unwrap(), accounts);
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(())); // idempotency survives too
+        assert_eq!(engine.resolve(1, 1).await, Ok(()));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pruned_operations_survive_restart() {
+        let path = temp_log("prune");
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.prune_operations(u64::MAX).await, Ok(1));
+        drop(engine);
+
+        let storage = FileStorage::open(&path).await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_all_operations(&mut db_tx).await, Ok(vec![]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejections_survive_restart() {
+        let path = temp_log("rejections");
+        let storage = FileStorage::open(&path).await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        storage.insert_operation_rejection(&mut db_tx, 42, 104, 10).await.unwrap();
+        storage.insert_operation_rejection(&mut db_tx, 43, 101, 20).await.unwrap();
+        storage.commit_db_tx(db_tx).await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.prune_operations(&mut db_tx, 15).await, Ok(1));
+        storage.commit_db_tx(db_tx).await.unwrap();
+        drop(storage);
+
+        let storage = FileStorage::open(&path).await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_operation_rejection(&mut db_tx, 42).await, Ok(None));
+        assert_eq!(storage.get_operation_rejection(&mut db_tx, 43).await, Ok(Some(101)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rolled_back_records_are_not_logged() {
+        let path = temp_log("savepoint");
+        let storage = FileStorage::open(&path).await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        storage.set_checkpoint(&mut db_tx, "a.csv", 10).await.unwrap();
+        let savepoint = storage.savepoint(&mut db_tx).await.unwrap();
+        storage.set_checkpoint(&mut db_tx, "a.csv", 12).await.unwrap();
+        storage.insert_operation(&mut db_tx, 42, 10).await.unwrap();
+        storage.rollback_to(&mut db_tx, savepoint).await.unwrap();
+        storage.commit_db_tx(db_tx).await.unwrap();
+        drop(storage);
+
+        let storage = FileStorage::open(&path).await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_checkpoint(&mut db_tx, "a.csv").await, Ok(Some(10)));
+        assert_eq!(storage.get_all_operations(&mut db_tx).await, Ok(vec![]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn torn_frame_is_discarded() {
+        let path = temp_log("torn");
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        drop(engine);
+        let valid_len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[0, 0, 1, 0, 42]).unwrap();
+
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), valid_len);
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(100));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(5)).await, Ok(()));
+        drop(engine);
+
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(105));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupted_frames_are_detected() {
+        let path = temp_log("corrupted");
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        let first_len = std::fs::metadata(&path).unwrap().len() as usize;
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(5)).await, Ok(()));
+        drop(engine);
+        let data = std::fs::read(&path).unwrap();
+
+        let mut torn = data.clone();
+        *torn.last_mut().unwrap() ^= 0xff;
+        std::fs::write(&path, &torn).unwrap();
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        assert_eq!(std::fs::metadata(&path).unwrap().len() as usize, first_len);
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(100));
+        drop(engine);
+
+        let mut corrupted = data.clone();
+        corrupted[first_len - 1] ^= 0xff;
+        std::fs::write(&path, &corrupted).unwrap();
+        assert_eq!(FileStorage::open(&path).await.err(), Some(DbError::DatabaseError("Corrupted log frame at offset 0".to_string())));
+        assert_eq!(std::fs::read(&path).unwrap(), corrupted);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn frames_without_crc_are_read() {
+        let path = temp_log("legacy");
+        let data = MessagePackCodec.encode(&vec![LogRecord::Checkpoint { source: "a.csv".to_string(), rows: 7 }]).unwrap();
+        let mut frame = (data.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&data);
+        std::fs::write(&path, &frame).unwrap();
+
+        let storage = FileStorage::open(&path).await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_checkpoint(&mut db_tx, "a.csv").await, Ok(Some(7)));
+        storage.set_checkpoint(&mut db_tx, "a.csv", 9).await.unwrap();
+        storage.commit_db_tx(db_tx).await.unwrap();
+        drop(storage);
+
+        let storage = FileStorage::open(&path).await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_checkpoint(&mut db_tx, "a.csv").await, Ok(Some(9)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tenants_survive_restart() {
+        let path = temp_log("tenants");
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        assert_eq!(engine.for_tenant("acme").deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.for_tenant("globex").deposit(1, 1, Decimal4::from(7)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(5)).await, Ok(()));
+        drop(engine);
+
+        let engine = Engine::new(FileStorage::open(&path).await.unwrap());
+        let available = |engine: Engine<FileStorage>| async move { engine.get_account(1).await.unwrap().unwrap().available() };
+        assert_eq!(available(engine.for_tenant("acme")).await, Decimal4::from(100));
+        assert_eq!(available(engine.for_tenant("globex")).await, Decimal4::from(7));
+        assert_eq!(available(engine.clone()).await, Decimal4::from(5));
+        assert_eq!(engine.for_tenant("acme").verify_journal().await, Ok(vec![]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
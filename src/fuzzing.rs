@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use arbitrary::{Arbitrary, Unstructured};
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::account::{Account, ClientId};
+use crate::decimal::Decimal4;
+use crate::engine::{Engine, Operation};
+use crate::storage::EchoDbStorage;
+use crate::transaction::{Transaction, TransactionState, TransactionType, TxId};
+
+// NOTE: small id ranges make collisions (replays, disputes of foreign txs, interleaved disputes) likely
+const ACCOUNTS: ClientId = 4;
+const TRANSACTIONS: TxId = 16;
+const ESCROW_BUCKET: &str = "fuzz";
+
+#[derive(Debug, Clone, Copy, Arbitrary)]
+pub enum StepKind {
+    Deposit,
+    Withdraw,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Escrow,
+    ReleaseEscrow,
+    Authorize,
+    Capture,
+}
+
+#[derive(Debug, Clone, Arbitrary)]
+pub struct Step {
+    kind: StepKind,
+    acc: u8,
+    tx: u8,
+    amount_cents: u16,
+}
+
+impl Step {
+    pub fn to_operation(&self) -> Operation {
+        let acc_id = self.acc as ClientId % ACCOUNTS;
+        let tx_id = self.tx as TxId % TRANSACTIONS;
+        let amount = Decimal::new(self.amount_cents as i64, 2);
+        match self.kind {
+            StepKind::Deposit => Operation::Deposit { acc_id, tx_id, amount },
+            StepKind::Withdraw => Operation::Withdraw { acc_id, tx_id, amount },
+            StepKind::Dispute => Operation::Dispute { acc_id, tx_id },
+            StepKind::Resolve => Operation::Resolve { acc_id, tx_id },
+            StepKind::Chargeback => Operation::Chargeback { acc_id, tx_id },
+            StepKind::Escrow => Operation::Escrow { acc_id, tx_id, bucket: ESCROW_BUCKET.to_string(), amount },
+            StepKind::ReleaseEscrow => Operation::ReleaseEscrow { acc_id, tx_id, bucket: ESCROW_BUCKET.to_string(), amount },
+            StepKind::Authorize => Operation::Authorize { acc_id, tx_id, amount },
+            StepKind::Capture => Operation::Capture { acc_id, tx_id },
+        }
+    }
+}
+
+/// A sequence of operations executed against a fresh in-memory engine.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub steps: Vec<Step>,
+}
+
+impl<'a> Arbitrary<'a> for Scenario {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        // NOTE: consume the whole input instead of an arbitrary length prefix, so every byte turns into steps
+        let mut steps = Vec::new();
+        while !u.is_empty() {
+            steps.push(Step::arbitrary(u)?);
+        }
+        Ok(Self { steps })
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invariant violated at step {step}: {reason}")]
+pub struct InvariantViolation {
+    pub step: usize,
+    pub reason: String,
+}
+
+/// Runs the scenario and checks the engine invariants after every step, plus the journal consistency at the end.
+/// The engine is checked against a simple model built only from the operations the engine accepted.
+pub async fn run_scenario(scenario: &Scenario) -> Result<(), InvariantViolation> {
+    let engine = Engine::new(EchoDbStorage::new());
+    let mut model: BTreeMap<TxId, Transaction> = BTreeMap::new();
+
+    for (step, operation) in scenario.steps.iter().map(|x| x.to_operation()).enumerate() {
+        let violation = |reason: String| InvariantViolation { step, reason };
+
+        let accounts_before = engine.get_all_accounts().await.map_err(|e| violation(e.to_string()))?;
+        let result = engine.execute_operation(operation.clone()).await;
+        let accounts_after = engine.get_all_accounts().await.map_err(|e| violation(e.to_string()))?;
+
+        if let Err(err) = result {
+            if accounts_before != accounts_after {
+                return Err(violation(format!("rejected {:?} ({}) changed the accounts", operation, err)));
+            }
+            continue;
+        }
+
+        match operation {
+            Operation::Deposit { acc_id, tx_id, amount } => {
+                model.entry(tx_id).or_insert(Transaction::new(tx_id, acc_id, TransactionType::Deposit, Decimal4::from(amount)));
+            }
+            Operation::Withdraw { acc_id, tx_id, amount } => {
+                model.entry(tx_id).or_insert(Transaction::new(tx_id, acc_id, TransactionType::Withdrawal, Decimal4::from(amount)));
+            }
+            Operation::Dispute { tx_id, .. } => apply_state(&mut model, tx_id, TransactionState::Disputed).map_err(violation)?,
+            Operation::Resolve { tx_id, .. } => apply_state(&mut model, tx_id, TransactionState::Posted).map_err(violation)?,
+            Operation::Chargeback { tx_id, .. } => apply_state(&mut model, tx_id, TransactionState::Chargeback).map_err(violation)?,
+            Operation::Escrow { acc_id, tx_id, amount, .. } => {
+                model.entry(tx_id).or_insert(Transaction::new(tx_id, acc_id, TransactionType::Escrow, Decimal4::from(amount)));
+            }
+            Operation::ReleaseEscrow { acc_id, tx_id, amount, .. } => {
+                model.entry(tx_id).or_insert(Transaction::new(tx_id, acc_id, TransactionType::EscrowRelease, Decimal4::from(amount)));
+            }
+            Operation::Authorize { acc_id, tx_id, amount } => {
+                model.entry(tx_id).or_insert(Transaction::new(tx_id, acc_id, TransactionType::Authorization, Decimal4::from(amount)));
+            }
+            Operation::Capture { tx_id, .. } => apply_state(&mut model, tx_id, TransactionState::Captured).map_err(violation)?,
+            Operation::Expire { tx_id, .. } => apply_state(&mut model, tx_id, TransactionState::Expired).map_err(violation)?,
+        }
+
+        for acc in accounts_after.iter() {
+            check_account(acc, &model).map_err(violation)?;
+        }
+    }
+
+    let divergences = engine.verify_journal().await
+        .map_err(|e| InvariantViolation { step: scenario.steps.len(), reason: e.to_string() })?;
+    if !divergences.is_empty() {
+        return Err(InvariantViolation { step: scenario.steps.len(), reason: format!("journal divergences: {:?}", divergences) });
+    }
+
+    Ok(())
+}
+
+fn apply_state(model: &mut BTreeMap<TxId, Transaction>, tx_id: TxId, state: TransactionState) -> Result<(), String> {
+    let tx = model.get_mut(&tx_id).ok_or(format!("engine accepted {:?} of unknown tx {}", state, tx_id))?;
+    tx.set_state(state).map_err(|e| format!("engine accepted {:?} of tx {}: {}", state, tx_id, e))
+}
+
+fn check_account(acc: &Account, model: &BTreeMap<TxId, Transaction>) -> Result<(), String> {
+    let mut held = Decimal4::zero();
+    let mut total = Decimal4::zero();
+    let mut escrowed = Decimal4::zero();
+    let mut charged_back = false;
+    for tx in model.values().filter(|x| x.account_id() == acc.id()) {
+        match (tx.tx_type(), tx.state()) {
+            (TransactionType::Withdrawal, _) => total -= tx.amount(),
+            (TransactionType::Deposit, TransactionState::Posted) => total += tx.amount(),
+            (TransactionType::Deposit, TransactionState::Disputed) => {
+                total += tx.amount();
+                held += tx.amount() - tx.shortfall();
+            }
+            (TransactionType::Deposit, TransactionState::Chargeback) => charged_back = true,
+            (TransactionType::Escrow, _) => escrowed += tx.amount(),
+            (TransactionType::EscrowRelease, _) => escrowed -= tx.amount(),
+            (TransactionType::Deposit, _) => {}
+            (TransactionType::Authorization, TransactionState::Posted) => held += tx.amount(),
+            (TransactionType::Authorization, TransactionState::Captured) => total -= tx.amount(),
+            (TransactionType::Authorization, _) => {}
+        }
+    }
+
+    if acc.held() != held {
+        return Err(format!("account {} held {} != {}", acc.id(), acc.held(), held));
+    }
+    if acc.escrowed() != Ok(escrowed) {
+        return Err(format!("account {} escrowed {:?} != {}", acc.id(), acc.escrowed(), escrowed));
+    }
+    if acc.total() != Ok(total) {
+        return Err(format!("account {} total {:?} != {}", acc.id(), acc.total(), total));
+    }
+    if acc.locked() != charged_back {
+        return Err(format!("account {} locked {} != {}", acc.id(), acc.locked(), charged_back));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod fuzzing_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn random_scenarios_keep_invariants() {
+        let mut rng = fastrand::Rng::with_seed(42);
+        for _ in 0..200 {
+            let bytes: Vec<u8> = (0..256).map(|_| rng.u8(..)).collect();
+            let scenario = Scenario::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            assert_eq!(run_scenario(&scenario).await, Ok(()));
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use crate::account::ClientId;
+use crate::decimal::Decimal4;
+use crate::engine::Operation;
+use crate::transaction::TxId;
+
+/// Generator of random but realistic workloads: deposits and withdrawals within the balance, disputes of earlier
+/// deposits that are later resolved or charged back, and a share of operations the engine rejects.
+///
+/// The generated stream is fully determined by the seed, so it can be used for reproducible load tests and fixtures.
+#[derive(Debug, Clone)]
+pub struct WorkloadGenerator {
+    accounts: u16,
+    dispute_ratio: f64,
+    error_rate: f64,
+    seed: u64,
+}
+
+impl Default for WorkloadGenerator {
+    fn default() -> Self {
+        Self { accounts: 100, dispute_ratio: 0.02, error_rate: 0.01, seed: 0 }
+    }
+}
+
+impl WorkloadGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of distinct client ids (`1..=accounts`).
+    ///
+    /// # Panics
+    /// Panics if `accounts` is zero.
+    pub fn with_accounts(mut self, accounts: u16) -> Self {
+        assert!(accounts > 0, "at least one account is required");
+        self.accounts = accounts;
+        self
+    }
+
+    /// Share of the operations that open a dispute (the resolves and chargebacks closing them come on top).
+    pub fn with_dispute_ratio(mut self, dispute_ratio: f64) -> Self {
+        self.dispute_ratio = dispute_ratio;
+        self
+    }
+
+    /// Share of the operations the engine rejects: overdrafts, resolves of undisputed deposits and disputes of unknown transactions.
+    pub fn with_error_rate(mut self, error_rate: f64) -> Self {
+        self.error_rate = error_rate;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Returns an endless stream of operations, take as many as needed.
+    pub fn operations(&self) -> Workload {
+        Workload {
+            config: self.clone(),
+            rng: fastrand::Rng::with_seed(self.seed),
+            next_tx_id: 1,
+            balances: HashMap::new(),
+            posted: Vec::new(),
+            disputed: Vec::new(),
+            locked: Vec::new(),
+        }
+    }
+}
+
+/// The state mirrored from the engine to keep the generated operations valid, see [`WorkloadGenerator::operations`].
+#[derive(Debug)]
+pub struct Workload {
+    config: WorkloadGenerator,
+    rng: fastrand::Rng,
+    next_tx_id: TxId,
+    balances: HashMap<ClientId, Decimal4>,
+    /// Deposits of the unlocked accounts that can be disputed: (acc_id, tx_id, amount).
+    posted: Vec<(ClientId, TxId, Decimal4)>,
+    disputed: Vec<(ClientId, TxId, Decimal4)>,
+    /// Accounts locked by a chargeback, they are not used anymore.
+    locked: Vec<ClientId>,
+}
+
+impl Workload {
+    fn random_account(&mut self) -> ClientId {
+        loop {
+            let acc_id = self.rng.u16(1..=self.config.accounts) as ClientId;
+            if !self.locked.contains(&acc_id) || self.locked.len() >= self.config.accounts as usize {
+                return acc_id;
+            }
+        }
+    }
+
+    fn random_amount(&mut self, max_cents: u64) -> Decimal4 {
+        let cents = self.rng.u64(1..=max_cents.max(1));
+        Decimal4::from(rust_decimal::Decimal::new(cents as i64, 2))
+    }
+
+    fn new_tx_id(&mut self) -> TxId {
+        let tx_id = self.next_tx_id;
+        self.next_tx_id += 1;
+        tx_id
+    }
+
+    fn invalid_operation(&mut self) -> Operation {
+        let acc_id = self.random_account();
+        match self.rng.u8(0..3) {
+            0 => {
+                let balance = self.balances.get(&acc_id).copied().unwrap_or_default();
+                let tx_id = self.new_tx_id();
+                Operation::Withdraw { acc_id, tx_id, amount: (balance + Decimal4::from(1)).into() }
+            }
+            1 if !self.posted.is_empty() => {
+                let (acc_id, tx_id, _) = self.posted[self.rng.usize(..self.posted.len())];
+                Operation::Resolve { acc_id, tx_id }
+            }
+            _ => Operation::Dispute { acc_id, tx_id: TxId::MAX }, // NOTE: never generated as a tx id
+        }
+    }
+
+    fn close_dispute(&mut self) -> Operation {
+        let (acc_id, tx_id, amount) = self.disputed.swap_remove(self.rng.usize(..self.disputed.len()));
+        if self.rng.u8(0..10) == 0 {
+            // NOTE: nothing is generated for a locked account anymore
+            self.locked.push(acc_id);
+            self.posted.retain(|x| x.0 != acc_id);
+            self.disputed.retain(|x| x.0 != acc_id);
+            Operation::Chargeback { acc_id, tx_id }
+        } else {
+            self.posted.push((acc_id, tx_id, amount));
+            Operation::Resolve { acc_id, tx_id }
+        }
+    }
+
+    fn open_dispute(&mut self) -> Option<Operation> {
+        if self.posted.is_empty() {
+            return None;
+        }
+        let (acc_id, tx_id, amount) = self.posted.swap_remove(self.rng.usize(..self.posted.len()));
+        self.disputed.push((acc_id, tx_id, amount));
+        Some(Operation::Dispute { acc_id, tx_id })
+    }
+}
+
+impl Iterator for Workload {
+    type Item = Operation;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rng.f64() < self.config.error_rate {
+            return Some(self.invalid_operation());
+        }
+        if !self.disputed.is_empty() && self.rng.f64() < self.config.dispute_ratio {
+            return Some(self.close_dispute());
+        }
+        if self.rng.f64() < self.config.dispute_ratio {
+            if let Some(operation) = self.open_dispute() {
+                return Some(operation);
+            }
+        }
+
+        let acc_id = self.random_account();
+        let tx_id = self.new_tx_id();
+        let balance = self.balances.get(&acc_id).copied().unwrap_or_default();
+        // NOTE: the available balance ignores the held funds, so withdrawals only use the funds without open disputes
+        let held: Decimal4 = self.disputed.iter().filter(|x| x.0 == acc_id).fold(Decimal4::zero(), |acc, x| acc + x.2);
+        let available_cents = rust_decimal::Decimal::from(balance - held) * rust_decimal::Decimal::from(100);
+        let available_cents: u64 = available_cents.trunc().try_into().unwrap_or(0);
+        if available_cents > 0 && self.rng.u8(0..10) < 3 {
+            let amount = self.random_amount(available_cents);
+            *self.balances.entry(acc_id).or_default() -= amount;
+            return Some(Operation::Withdraw { acc_id, tx_id, amount: amount.into() });
+        }
+
+        let amount = self.random_amount(100_000);
+        *self.balances.entry(acc_id).or_default() += amount;
+        self.posted.push((acc_id, tx_id, amount));
+        Some(Operation::Deposit { acc_id, tx_id, amount: amount.into() })
+    }
+}
+
+#[cfg(test)]
+mod generator_tests {
+    use crate::csv_parser::{read_csv_from, write_operations};
+    use crate::engine::Engine;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[test]
+    fn same_seed_same_workload() {
+        let generator = WorkloadGenerator::new().with_seed(7);
+        let first: Vec<Operation> = generator.operations().take(500).collect();
+        assert_eq!(first, generator.operations().take(500).collect::<Vec<_>>());
+        assert_ne!(first, generator.with_seed(8).operations().take(500).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn generated_workload_roundtrips_through_csv() {
+        let operations: Vec<Operation> = WorkloadGenerator::new()
+            .with_accounts(50)
+            .with_dispute_ratio(0.1)
+            .with_error_rate(0.0)
+            .with_seed(42)
+            .operations()
+            .take(2000)
+            .collect();
+        assert!(operations.iter().any(|x| matches!(x, Operation::Chargeback { .. })));
+
+        let mut data = Vec::new();
+        write_operations(&mut data, &operations).unwrap();
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let stats = read_csv_from(std::io::Cursor::new(data), &mut engine).await.unwrap();
+        assert_eq!(stats.by_error, Default::default());
+        assert_eq!(stats.applied, 2000);
+    }
+}
@@ -0,0 +1,291 @@
+use std::marker::PhantomData;
+
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Enum, Object, Schema};
+use axum::extract::State;
+use axum::response::{Html, IntoResponse};
+use axum::routing::get;
+use axum::{Json, Router};
+
+use crate::account::{Account, ClientId};
+use crate::engine::Engine;
+use crate::journal::Journal;
+use crate::storage::Storage;
+use crate::transaction::{StateTransition, Transaction, TransactionState, TransactionType, TxId};
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Nested selections allowed in a query, enough for `accounts { transactions { ... } }` with fragments.
+const MAX_DEPTH: usize = 10;
+
+pub type EngineSchema<TStorage> = Schema<QueryRoot<TStorage>, EmptyMutation, EmptySubscription>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(remote = "crate::transaction::TransactionType")]
+enum TxType {
+    Deposit,
+    Withdrawal,
+    Escrow,
+    EscrowRelease,
+    Authorization,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(remote = "crate::transaction::TransactionState")]
+enum TxState {
+    Posted,
+    Disputed,
+    Chargeback,
+    Captured,
+    Expired,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(name = "AccountStatus", remote = "crate::account::AccountStatus")]
+enum AccStatus {
+    Active,
+    Locked,
+    Frozen,
+    Closed,
+}
+
+/// The read-only query root: accounts and their transactions, filtered and paginated by id (`after` is exclusive,
+/// `first` defaults to 100 and is capped at 1000).
+pub struct QueryRoot<TStorage> {
+    _storage: PhantomData<fn() -> TStorage>,
+}
+
+#[Object(name = "Query")]
+impl<TStorage> QueryRoot<TStorage>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    async fn account(&self, ctx: &Context<'_>, client: ClientId) -> async_graphql::Result<Option<AccountNode<TStorage>>> {
+        let engine = ctx.data_unchecked::<Engine<TStorage>>();
+        Ok(engine.get_account(client).await?.map(AccountNode::new))
+    }
+
+    /// Accounts ordered by client id, optionally only the locked (or unlocked) ones.
+    async fn accounts(&self, ctx: &Context<'_>, after: Option<ClientId>, first: Option<usize>, locked: Option<bool>) -> async_graphql::Result<Vec<AccountNode<TStorage>>> {
+        let engine = ctx.data_unchecked::<Engine<TStorage>>();
+        let first = page_size(first);
+        let mut cursor = after;
+        let mut accounts = Vec::new();
+        while accounts.len() < first {
+            let page = engine.list_accounts(cursor, MAX_PAGE_SIZE).await?;
+            let Some(last) = page.last() else { break };
+            cursor = Some(last.id());
+            accounts.extend(page.into_iter().filter(|x| locked.is_none_or(|locked| x.locked() == locked)).map(AccountNode::new));
+        }
+        accounts.truncate(first);
+        Ok(accounts)
+    }
+}
+
+pub struct AccountNode<TStorage> {
+    account: Account,
+    _storage: PhantomData<fn() -> TStorage>,
+}
+
+impl<TStorage> AccountNode<TStorage> {
+    fn new(account: Account) -> Self {
+        Self { account, _storage: PhantomData }
+    }
+}
+
+#[Object(name = "Account")]
+impl<TStorage> AccountNode<TStorage>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    async fn client(&self) -> ClientId {
+        self.account.id()
+    }
+
+    async fn available(&self) -> String {
+        self.account.available().to_string()
+    }
+
+    async fn held(&self) -> String {
+        self.account.held().to_string()
+    }
+
+    /// The sum of the escrow buckets, included in the total.
+    async fn escrowed(&self) -> async_graphql::Result<String> {
+        Ok(self.account.escrowed()?.to_string())
+    }
+
+    async fn total(&self) -> async_graphql::Result<String> {
+        Ok(self.account.total()?.to_string())
+    }
+
+    async fn locked(&self) -> bool {
+        self.account.locked()
+    }
+
+    async fn status(&self) -> AccStatus {
+        self.account.status().into()
+    }
+
+    /// Transactions of the account ordered by tx id, optionally filtered by type and state (e.g. only the open disputes).
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<TxId>,
+        first: Option<usize>,
+        #[graphql(name = "type")] tx_type: Option<TxType>,
+        state: Option<TxState>,
+    ) -> async_graphql::Result<Vec<TransactionNode>> {
+        let engine = ctx.data_unchecked::<Engine<TStorage>>();
+        let first = page_size(first);
+        let tx_type: Option<TransactionType> = tx_type.map(Into::into);
+        let state: Option<TransactionState> = state.map(Into::into);
+        let mut cursor = after;
+        let mut txs = Vec::new();
+        while txs.len() < first {
+            let page = engine.get_txs_by_account(self.account.id(), cursor, MAX_PAGE_SIZE).await?;
+            let Some(last) = page.last() else { break };
+            cursor = Some(last.id());
+            txs.extend(page.into_iter()
+                .filter(|x| tx_type.is_none_or(|tx_type| x.tx_type() == tx_type) && state.is_none_or(|state| x.state() == state))
+                .map(TransactionNode));
+        }
+        txs.truncate(first);
+        Ok(txs)
+    }
+}
+
+pub struct TransactionNode(Transaction);
+
+#[Object(name = "Transaction")]
+impl TransactionNode {
+    async fn tx(&self) -> TxId {
+        self.0.id()
+    }
+
+    async fn client(&self) -> ClientId {
+        self.0.account_id()
+    }
+
+    #[graphql(name = "type")]
+    async fn tx_type(&self) -> TxType {
+        self.0.tx_type().into()
+    }
+
+    async fn amount(&self) -> String {
+        self.0.amount().to_string()
+    }
+
+    async fn state(&self) -> TxState {
+        self.0.state().into()
+    }
+
+    /// Milliseconds since the Unix epoch.
+    async fn created_at(&self) -> u64 {
+        self.0.created_at()
+    }
+
+    /// The state changes in the order they happened.
+    async fn history(&self) -> Vec<StateTransitionNode> {
+        self.0.history().iter().cloned().map(StateTransitionNode).collect()
+    }
+}
+
+pub struct StateTransitionNode(StateTransition);
+
+#[Object(name = "StateTransition")]
+impl StateTransitionNode {
+    async fn from(&self) -> TxState {
+        self.0.from.into()
+    }
+
+    async fn to(&self) -> TxState {
+        self.0.to.into()
+    }
+
+    /// The type of the triggering operation: `dispute`, `resolve` or `chargeback`.
+    async fn operation(&self) -> &'static str {
+        self.0.operation.name()
+    }
+
+    /// Milliseconds since the Unix epoch.
+    async fn timestamp(&self) -> u64 {
+        self.0.timestamp
+    }
+}
+
+fn page_size(first: Option<usize>) -> usize {
+    first.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+pub fn schema<TStorage>(engine: Engine<TStorage>) -> EngineSchema<TStorage>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    Schema::build(QueryRoot { _storage: PhantomData }, EmptyMutation, EmptySubscription)
+        .data(engine)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// `POST /graphql` executes a query, `GET /graphql` serves the GraphiQL explorer.
+pub fn router<TStorage>(engine: Engine<TStorage>) -> Router
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    Router::new()
+        .route("/graphql", get(graphiql).post(execute::<TStorage>))
+        .with_state(schema(engine))
+}
+
+async fn execute<TStorage>(State(schema): State<EngineSchema<TStorage>>, Json(request): Json<async_graphql::Request>) -> Json<async_graphql::Response>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    Json(schema.execute(request).await)
+}
+
+async fn graphiql() -> impl IntoResponse {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+#[cfg(test)]
+mod graphql_tests {
+    use crate::decimal::Decimal4;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn query_accounts_and_transactions() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(20)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(5)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 2).await, Ok(()));
+        assert_eq!(engine.deposit(2, 4, Decimal4::from(1)).await, Ok(()));
+        assert_eq!(engine.dispute(2, 4).await, Ok(()));
+        assert_eq!(engine.chargeback(2, 4).await, Ok(()));
+        let schema = schema(engine);
+
+        let response = schema.execute("{ account(client: 1) { available held transactions(state: DISPUTED) { tx amount state } } }").await;
+        assert_eq!(response.errors, vec![]);
+        assert_eq!(response.data.into_json().unwrap(), serde_json::json!({
+            "account": { "available": "5.0000", "held": "20.0000", "transactions": [{ "tx": 2, "amount": "20.0000", "state": "DISPUTED" }] },
+        }));
+
+        let response = schema.execute("{ account(client: 2) { transactions { history { from to operation } } } }").await;
+        assert_eq!(response.data.into_json().unwrap(), serde_json::json!({ "account": { "transactions": [{ "history": [
+            { "from": "POSTED", "to": "DISPUTED", "operation": "dispute" },
+            { "from": "DISPUTED", "to": "CHARGEBACK", "operation": "chargeback" },
+        ] }] } }));
+
+        let response = schema.execute("{ account(client: 1) { transactions(after: 1, first: 1) { tx type } } }").await;
+        assert_eq!(response.data.into_json().unwrap(), serde_json::json!({ "account": { "transactions": [{ "tx": 2, "type": "DEPOSIT" }] } }));
+        let response = schema.execute("{ account(client: 2) { locked status } }").await;
+        assert_eq!(response.data.into_json().unwrap(), serde_json::json!({ "account": { "locked": true, "status": "LOCKED" } }));
+        let response = schema.execute("{ accounts(locked: true) { client } all: accounts(after: 1) { client } missing: account(client: 9) { client } }").await;
+        assert_eq!(response.data.into_json().unwrap(), serde_json::json!({ "accounts": [{ "client": 2 }], "all": [{ "client": 2 }], "missing": null }));
+    }
+}
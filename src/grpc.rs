@@ -0,0 +1,373 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt};
+use rust_decimal::Decimal;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::account::Account;
+use crate::auth::{self, AuthError, Authenticator, Role};
+use crate::decimal::parse_unrounded;
+use crate::engine::{Engine, EngineError, ExecuteOptions, Operation};
+use crate::journal::{Journal, OperationSource, Provenance};
+use crate::storage::Storage;
+
+use self::proto::transactions_engine_server::{TransactionsEngine, TransactionsEngineServer};
+
+/// The types generated from `proto/transactions_engine.proto`, including the client for other services.
+pub mod proto {
+    tonic::include_proto!("transactions_engine");
+}
+
+const PAGE_SIZE: usize = 1000;
+
+/// Results buffered ahead of a slow client of the streaming calls.
+const STREAM_BUFFER: usize = 128;
+
+/// The metadata key carrying the stable code of an engine error, see [`EngineError::code`].
+pub const ERROR_CODE_KEY: &str = "x-error-code";
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send + 'static>>;
+
+impl TryFrom<proto::Operation> for Operation {
+    type Error = Status;
+
+    fn try_from(value: proto::Operation) -> Result<Self, Self::Error> {
+        let acc_id = parse_id("client", value.client).map_err(Status::invalid_argument)?;
+        let tx_id = parse_id("tx", value.tx).map_err(Status::invalid_argument)?;
+
+        match proto::OperationType::try_from(value.r#type) {
+            Ok(proto::OperationType::Deposit) => Ok(Operation::Deposit { acc_id, tx_id, amount: parse_amount(&value.amount).map_err(Status::invalid_argument)? }),
+            Ok(proto::OperationType::Withdrawal) => Ok(Operation::Withdraw { acc_id, tx_id, amount: parse_amount(&value.amount).map_err(Status::invalid_argument)? }),
+            Ok(proto::OperationType::Dispute) => Ok(Operation::Dispute { acc_id, tx_id }),
+            Ok(proto::OperationType::Resolve) => Ok(Operation::Resolve { acc_id, tx_id }),
+            Ok(proto::OperationType::Chargeback) => Ok(Operation::Chargeback { acc_id, tx_id }),
+            Ok(proto::OperationType::Escrow) => Ok(Operation::Escrow { acc_id, tx_id, bucket: parse_bucket(value.bucket).map_err(Status::invalid_argument)?, amount: parse_amount(&value.amount).map_err(Status::invalid_argument)? }),
+            Ok(proto::OperationType::Release) => Ok(Operation::ReleaseEscrow { acc_id, tx_id, bucket: parse_bucket(value.bucket).map_err(Status::invalid_argument)?, amount: parse_amount(&value.amount).map_err(Status::invalid_argument)? }),
+            Ok(proto::OperationType::Authorize) => Ok(Operation::Authorize { acc_id, tx_id, amount: parse_amount(&value.amount).map_err(Status::invalid_argument)? }),
+            Ok(proto::OperationType::Capture) => Ok(Operation::Capture { acc_id, tx_id }),
+            Ok(proto::OperationType::Expire) => Ok(Operation::Expire { acc_id, tx_id }),
+            Ok(proto::OperationType::Unspecified) | Err(_) => Err(Status::invalid_argument("invalid operation type")),
+        }
+    }
+}
+
+fn parse_id<T: TryFrom<u64>>(field: &str, value: u64) -> Result<T, String> {
+    T::try_from(value).map_err(|_| format!("invalid {}: {}", field, value))
+}
+
+fn wire_id(id: impl Into<u64>) -> u64 {
+    id.into()
+}
+
+fn parse_bucket(bucket: String) -> Result<String, String> {
+    match bucket.as_str() {
+        "" => Err("missing field: bucket".to_string()),
+        _ => Ok(bucket),
+    }
+}
+
+fn parse_amount(amount: &str) -> Result<Decimal, String> {
+    match amount {
+        "" => Err("missing field: amount".to_string()),
+        amount => parse_unrounded(amount).map_err(|_| format!("invalid amount: {}", amount)),
+    }
+}
+
+impl From<Operation> for proto::Operation {
+    fn from(value: Operation) -> Self {
+        let (op_type, client, tx, amount, bucket) = match value {
+            Operation::Deposit { acc_id, tx_id, amount } => (proto::OperationType::Deposit, acc_id, tx_id, amount.to_string(), String::new()),
+            Operation::Withdraw { acc_id, tx_id, amount } => (proto::OperationType::Withdrawal, acc_id, tx_id, amount.to_string(), String::new()),
+            Operation::Dispute { acc_id, tx_id } => (proto::OperationType::Dispute, acc_id, tx_id, String::new(), String::new()),
+            Operation::Resolve { acc_id, tx_id } => (proto::OperationType::Resolve, acc_id, tx_id, String::new(), String::new()),
+            Operation::Chargeback { acc_id, tx_id } => (proto::OperationType::Chargeback, acc_id, tx_id, String::new(), String::new()),
+            Operation::Escrow { acc_id, tx_id, bucket, amount } => (proto::OperationType::Escrow, acc_id, tx_id, amount.to_string(), bucket),
+            Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount } => (proto::OperationType::Release, acc_id, tx_id, amount.to_string(), bucket),
+            Operation::Authorize { acc_id, tx_id, amount } => (proto::OperationType::Authorize, acc_id, tx_id, amount.to_string(), String::new()),
+            Operation::Capture { acc_id, tx_id } => (proto::OperationType::Capture, acc_id, tx_id, String::new(), String::new()),
+            Operation::Expire { acc_id, tx_id } => (proto::OperationType::Expire, acc_id, tx_id, String::new(), String::new()),
+        };
+        Self { r#type: op_type.into(), client: wire_id(client), tx: wire_id(tx), amount, bucket }
+    }
+}
+
+impl TryFrom<Account> for proto::Account {
+    type Error = EngineError;
+
+    fn try_from(value: Account) -> Result<Self, Self::Error> {
+        Ok(Self {
+            client: wire_id(value.id()),
+            available: value.available().to_string(),
+            held: value.held().to_string(),
+            total: value.total()?.to_string(),
+            locked: value.locked(),
+            escrow: value.escrow_buckets().iter().map(|(bucket, amount)| (bucket.clone(), amount.to_string())).collect(),
+            seq: value.seq(),
+        })
+    }
+}
+
+impl From<EngineError> for Status {
+    fn from(value: EngineError) -> Self {
+        let message = value.to_string();
+        let code = value.code();
+        let mut status = match value {
+            EngineError::AccountNotFound | EngineError::TransactionNotFound => Status::not_found(message),
+            EngineError::ConcurrentOperationDetected => Status::aborted(message),
+            EngineError::Paused => Status::unavailable(message),
+            EngineError::CorruptedJournal(_) | EngineError::SnapshotError(_) | EngineError::DatabaseError(_) | EngineError::HeldUnderflow | EngineError::ActorStopped => Status::internal(message),
+            _ => Status::failed_precondition(message),
+        };
+        status.metadata_mut().insert(ERROR_CODE_KEY, code.into());
+        status
+    }
+}
+
+/// The engine error code attached to the status, 0 when it is not an engine error.
+pub fn error_code(status: &Status) -> u32 {
+    status.metadata().get(ERROR_CODE_KEY).and_then(|x| x.to_str().ok()).and_then(|x| x.parse().ok()).unwrap_or(0)
+}
+
+/// The envelope of the operations of a call, with its `x-request-id` and `x-correlation-id` metadata.
+fn provenance(metadata: &tonic::metadata::MetadataMap) -> Provenance {
+    let value = |key| metadata.get(key).and_then(|x| x.to_str().ok()).map(str::to_string);
+    Provenance::new(OperationSource::Api { request_id: value("x-request-id") }).with_correlation_id(value("x-correlation-id"))
+}
+
+/// The `TransactionsEngine` gRPC service over a shared engine.
+///
+/// Rejected operations fail with `FAILED_PRECONDITION` (`ABORTED` for concurrent operations, safe to retry),
+/// malformed ones with `INVALID_ARGUMENT`. The stable code of a rejection is sent in the `x-error-code` metadata. In `SubmitOperations` both are reported in the result of the operation instead.
+impl From<AuthError> for Status {
+    fn from(value: AuthError) -> Self {
+        match value {
+            AuthError::Forbidden { .. } => Status::permission_denied(value.to_string()),
+            _ => Status::unauthenticated(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcService<TStorage: Storage> {
+    engine: Engine<TStorage>,
+    authenticator: Option<Arc<Authenticator>>,
+}
+
+impl<TStorage: Storage> GrpcService<TStorage> {
+    pub fn new(engine: Engine<TStorage>) -> Self {
+        Self { engine, authenticator: None }
+    }
+
+    /// Requires the credentials of a reader for the queries and of an operator for the operations,
+    /// in the `authorization: Bearer <credentials>` or `x-api-key` metadata of the calls.
+    pub fn with_authenticator(mut self, authenticator: Arc<Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    fn authorize<T>(&self, request: &Request<T>, required: Role) -> Result<(), AuthError> {
+        let Some(authenticator) = &self.authenticator else {
+            return Ok(());
+        };
+        let metadata = |key| request.metadata().get(key).and_then(|x| x.to_str().ok());
+        authenticator.authorize(auth::credentials(metadata("authorization"), metadata("x-api-key")), required).map(|_| ())
+    }
+}
+
+impl<TStorage> GrpcService<TStorage>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    async fn execute(engine: &Engine<TStorage>, operation: proto::Operation, provenance: &Provenance) -> Result<proto::Account, Status> {
+        let operation = Operation::try_from(operation)?;
+        let acc_id = operation.acc_id();
+        engine.execute_operation_with(operation, ExecuteOptions { provenance: Some(provenance), ..ExecuteOptions::default() }).await?;
+        let account = engine.get_account(acc_id).await?.ok_or(EngineError::AccountNotFound)?;
+        Ok(account.try_into()?)
+    }
+}
+
+#[tonic::async_trait]
+impl<TStorage> TransactionsEngine for GrpcService<TStorage>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    type SubmitOperationsStream = ResponseStream<proto::OperationResult>;
+    type ListAccountsStream = ResponseStream<proto::Account>;
+
+    async fn execute_operation(&self, request: Request<proto::Operation>) -> Result<Response<proto::Account>, Status> {
+        self.authorize(&request, Role::Operator)?;
+        let provenance = provenance(request.metadata());
+        Self::execute(&self.engine, request.into_inner(), &provenance).await.map(Response::new)
+    }
+
+    async fn submit_operations(&self, request: Request<Streaming<proto::Operation>>) -> Result<Response<Self::SubmitOperationsStream>, Status> {
+        self.authorize(&request, Role::Operator)?;
+        let provenance = provenance(request.metadata());
+        let mut operations = request.into_inner();
+        let engine = self.engine.clone();
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            while let Some(operation) = operations.next().await {
+                let result = match operation {
+                    Ok(operation) => match Self::execute(&engine, operation.clone(), &provenance).await {
+                        Ok(account) => Ok(proto::OperationResult { operation: Some(operation), account: Some(account), error: String::new(), code: 0 }),
+                        Err(status) => Ok(proto::OperationResult { operation: Some(operation), account: None, error: status.message().to_string(), code: error_code(&status) }),
+                    },
+                    Err(status) => Err(status), // NOTE: the request stream is broken, nothing more can be read
+                };
+                let stop = result.is_err();
+                if sender.send(result).await.is_err() || stop {
+                    break; // the client is gone
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    async fn get_account(&self, request: Request<proto::GetAccountRequest>) -> Result<Response<proto::Account>, Status> {
+        self.authorize(&request, Role::Reader)?;
+        let client = request.into_inner().client;
+        let acc_id = parse_id("client", client).map_err(Status::invalid_argument)?;
+        let account = self.engine.get_account(acc_id).await?.ok_or(EngineError::AccountNotFound)?;
+        Ok(Response::new(account.try_into()?))
+    }
+
+    async fn list_accounts(&self, request: Request<proto::ListAccountsRequest>) -> Result<Response<Self::ListAccountsStream>, Status> {
+        self.authorize(&request, Role::Reader)?;
+        let mut cursor = match request.into_inner().cursor {
+            Some(cursor) => Some(parse_id("cursor", cursor).map_err(Status::invalid_argument)?),
+            None => None,
+        };
+        let engine = self.engine.clone();
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                let accounts = match engine.list_accounts(cursor, PAGE_SIZE).await {
+                    Ok(accounts) => accounts,
+                    Err(err) => {
+                        let _ = sender.send(Err(err.into())).await;
+                        return;
+                    }
+                };
+                let Some(last) = accounts.last() else { return };
+                cursor = Some(last.id());
+                for account in accounts {
+                    if sender.send(proto::Account::try_from(account).map_err(Status::from)).await.is_err() {
+                        return; // the client is gone
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+/// Returns the service to mount on a tonic server, together with other services.
+pub fn service<TStorage>(engine: Engine<TStorage>) -> TransactionsEngineServer<GrpcService<TStorage>>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    TransactionsEngineServer::new(GrpcService::new(engine))
+}
+
+/// Serves the [`service`] on `addr` until `shutdown` completes, then waits for the calls in flight.
+/// With an `authenticator`, the calls are authorized as described in [`GrpcService::with_authenticator`].
+pub async fn serve<TStorage>(
+    engine: Engine<TStorage>,
+    addr: SocketAddr,
+    authenticator: Option<Arc<Authenticator>>,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<(), tonic::transport::Error>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let service = match authenticator {
+        Some(authenticator) => GrpcService::new(engine).with_authenticator(authenticator),
+        None => GrpcService::new(engine),
+    };
+    tonic::transport::Server::builder().add_service(TransactionsEngineServer::new(service)).serve_with_shutdown(addr, shutdown).await
+}
+
+#[cfg(test)]
+mod grpc_tests {
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    use crate::storage::EchoDbStorage;
+
+    use super::proto::transactions_engine_client::TransactionsEngineClient;
+    use super::*;
+
+    fn operation(op_type: proto::OperationType, client: u64, tx: u64, amount: &str) -> proto::Operation {
+        proto::Operation { r#type: op_type.into(), client, tx, amount: amount.to_string(), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn grpc_service() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tonic::transport::Server::builder().add_service(service(engine)).serve_with_incoming(TcpListenerStream::new(listener));
+        tokio::spawn(server);
+        let mut client = TransactionsEngineClient::connect(format!("http://{}", addr)).await.unwrap();
+
+        let account = client.execute_operation(operation(proto::OperationType::Deposit, 2, 1, "10")).await.unwrap().into_inner();
+        assert_eq!(account, proto::Account { client: 2, available: "10.0000".into(), held: "0.0000".into(), total: "10.0000".into(), locked: false, seq: 1, ..Default::default() });
+        let status = client.execute_operation(operation(proto::OperationType::Withdrawal, 2, 2, "50")).await.unwrap_err();
+        assert_eq!((status.code(), status.message(), error_code(&status)), (tonic::Code::FailedPrecondition, "insufficient funds", 104));
+        let status = client.execute_operation(operation(proto::OperationType::Deposit, 2, 3, "")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let bulk = vec![
+            operation(proto::OperationType::Deposit, 1, 4, "5"),
+            operation(proto::OperationType::Dispute, 1, 99, ""),
+            operation(proto::OperationType::Dispute, 2, 1, ""),
+        ];
+        let results: Vec<proto::OperationResult> = client.submit_operations(futures::stream::iter(bulk)).await.unwrap()
+            .into_inner().map(|x| x.unwrap()).collect().await;
+        assert_eq!(results.iter().map(|x| x.error.as_str()).collect::<Vec<_>>(), vec!["", "transaction not found", ""]);
+        assert_eq!(results.iter().map(|x| x.code).collect::<Vec<_>>(), vec![0, 102, 0]);
+        assert_eq!(results[2].account.as_ref().unwrap().held, "10.0000");
+
+        let account = client.get_account(proto::GetAccountRequest { client: 1 }).await.unwrap().into_inner();
+        assert_eq!(account.available, "5.0000");
+        let status = client.get_account(proto::GetAccountRequest { client: 9 }).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let accounts: Vec<u64> = client.list_accounts(proto::ListAccountsRequest { cursor: None }).await.unwrap()
+            .into_inner().map(|x| x.unwrap().client).collect().await;
+        assert_eq!(accounts, vec![1, 2]);
+        let accounts: Vec<u64> = client.list_accounts(proto::ListAccountsRequest { cursor: Some(1) }).await.unwrap()
+            .into_inner().map(|x| x.unwrap().client).collect().await;
+        assert_eq!(accounts, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn authorized_calls() {
+        let config: crate::auth::AuthConfig = serde_json::from_str(r#"{"api_keys": [{"key": "reader-key", "role": "reader"}, {"key": "operator-key", "role": "operator"}]}"#).unwrap();
+        let service = GrpcService::new(Engine::new(EchoDbStorage::new())).with_authenticator(Arc::new(Authenticator::new(&config)));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tonic::transport::Server::builder().add_service(TransactionsEngineServer::new(service)).serve_with_incoming(TcpListenerStream::new(listener));
+        tokio::spawn(server);
+        let mut client = TransactionsEngineClient::connect(format!("http://{}", addr)).await.unwrap();
+        let request = |api_key: Option<&str>| {
+            let mut request = Request::new(operation(proto::OperationType::Deposit, 1, 1, "10"));
+            if let Some(api_key) = api_key {
+                request.metadata_mut().insert("x-api-key", api_key.parse().unwrap());
+            }
+            request
+        };
+
+        assert_eq!(client.execute_operation(request(None)).await.unwrap_err().code(), tonic::Code::Unauthenticated);
+        assert_eq!(client.execute_operation(request(Some("reader-key"))).await.unwrap_err().code(), tonic::Code::PermissionDenied);
+        assert!(client.execute_operation(request(Some("operator-key"))).await.is_ok());
+        let mut get_account = Request::new(proto::GetAccountRequest { client: 1 });
+        get_account.metadata_mut().insert("authorization", "Bearer reader-key".parse().unwrap());
+        assert_eq!(client.get_account(get_account).await.unwrap().into_inner().available, "10.0000");
+    }
+}
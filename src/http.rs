@@ -0,0 +1,721 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::net::ToSocketAddrs;
+use tokio::sync::broadcast;
+
+use crate::account::{Account, AccountMetadata, AccountStatus, ClientId, SystemAccount};
+use crate::auth::{self, AuthError, Authenticator, Role};
+use crate::csv_parser::CsvTransaction;
+use crate::decimal::{unrounded, Decimal4};
+use crate::engine::{Engine, EngineError, ExecuteOptions, Operation};
+use crate::journal::{Journal, OperationSource, Provenance};
+use crate::observer::{EngineEvent, EngineObserver};
+use crate::storage::Storage;
+use crate::transaction::TxId;
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Updates buffered for a slow WebSocket client before it is disconnected.
+const UPDATES_BUFFER: usize = 1024;
+
+/// The headers of `POST /operations` journaled in the [`Provenance`] of the operation.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+
+/// The body of `POST /operations`, with the same fields as a row of the CSV input, e.g.
+/// `{"type": "deposit", "client": 1, "tx": 1, "amount": "10.5"}` or `{"type": "dispute", "client": 1, "tx": 1}`.
+/// A deposit or withdrawal can carry a unique `external_id`, and a dispute, resolve or chargeback can reference
+/// the transaction by it instead of `tx`, e.g. `{"type": "dispute", "client": 1, "external_id": "a1b2"}`.
+/// An escrow or a release names its bucket, e.g. `{"type": "escrow", "client": 1, "tx": 2, "bucket": "order-7", "amount": "5"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OperationRequest {
+    Deposit {
+        client: ClientId,
+        tx: TxId,
+        #[serde(with = "unrounded")]
+        amount: Decimal,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        external_id: Option<String>,
+    },
+    Withdrawal {
+        client: ClientId,
+        tx: TxId,
+        #[serde(with = "unrounded")]
+        amount: Decimal,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        external_id: Option<String>,
+    },
+    Dispute { client: ClientId, #[serde(flatten)] tx: TxReference },
+    Resolve { client: ClientId, #[serde(flatten)] tx: TxReference },
+    Chargeback { client: ClientId, #[serde(flatten)] tx: TxReference },
+    Escrow { client: ClientId, tx: TxId, bucket: String, #[serde(with = "unrounded")] amount: Decimal },
+    Release { client: ClientId, tx: TxId, bucket: String, #[serde(with = "unrounded")] amount: Decimal },
+    Authorize { client: ClientId, tx: TxId, #[serde(with = "unrounded")] amount: Decimal },
+    Capture { client: ClientId, tx: TxId },
+    Expire { client: ClientId, tx: TxId },
+}
+
+/// The transaction a dispute, resolve or chargeback refers to: `{"tx": 1}` or `{"external_id": "a1b2"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TxReference {
+    Id { tx: TxId },
+    External { external_id: String },
+}
+
+impl From<Operation> for OperationRequest {
+    fn from(value: Operation) -> Self {
+        match value {
+            Operation::Deposit { acc_id, tx_id, amount } => OperationRequest::Deposit { client: acc_id, tx: tx_id, amount, external_id: None },
+            Operation::Withdraw { acc_id, tx_id, amount } => OperationRequest::Withdrawal { client: acc_id, tx: tx_id, amount, external_id: None },
+            Operation::Dispute { acc_id, tx_id } => OperationRequest::Dispute { client: acc_id, tx: TxReference::Id { tx: tx_id } },
+            Operation::Resolve { acc_id, tx_id } => OperationRequest::Resolve { client: acc_id, tx: TxReference::Id { tx: tx_id } },
+            Operation::Chargeback { acc_id, tx_id } => OperationRequest::Chargeback { client: acc_id, tx: TxReference::Id { tx: tx_id } },
+            Operation::Escrow { acc_id, tx_id, bucket, amount } => OperationRequest::Escrow { client: acc_id, tx: tx_id, bucket, amount },
+            Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount } => OperationRequest::Release { client: acc_id, tx: tx_id, bucket, amount },
+            Operation::Authorize { acc_id, tx_id, amount } => OperationRequest::Authorize { client: acc_id, tx: tx_id, amount },
+            Operation::Capture { acc_id, tx_id } => OperationRequest::Capture { client: acc_id, tx: tx_id },
+            Operation::Expire { acc_id, tx_id } => OperationRequest::Expire { client: acc_id, tx: tx_id },
+        }
+    }
+}
+
+impl OperationRequest {
+    /// The operation of the request and the external id of the transaction it creates, the referenced transactions are looked up.
+    async fn into_operation<TStorage: Storage + Journal>(self, engine: &Engine<TStorage>) -> Result<(Operation, Option<String>), EngineError> {
+        Ok(match self {
+            OperationRequest::Deposit { client, tx, amount, external_id } => (Operation::Deposit { acc_id: client, tx_id: tx, amount }, external_id),
+            OperationRequest::Withdrawal { client, tx, amount, external_id } => (Operation::Withdraw { acc_id: client, tx_id: tx, amount }, external_id),
+            OperationRequest::Dispute { client, tx } => (Operation::Dispute { acc_id: client, tx_id: tx.resolve(engine).await? }, None),
+            OperationRequest::Resolve { client, tx } => (Operation::Resolve { acc_id: client, tx_id: tx.resolve(engine).await? }, None),
+            OperationRequest::Chargeback { client, tx } => (Operation::Chargeback { acc_id: client, tx_id: tx.resolve(engine).await? }, None),
+            OperationRequest::Escrow { client, tx, bucket, amount } => (Operation::Escrow { acc_id: client, tx_id: tx, bucket, amount }, None),
+            OperationRequest::Release { client, tx, bucket, amount } => (Operation::ReleaseEscrow { acc_id: client, tx_id: tx, bucket, amount }, None),
+            OperationRequest::Authorize { client, tx, amount } => (Operation::Authorize { acc_id: client, tx_id: tx, amount }, None),
+            OperationRequest::Capture { client, tx } => (Operation::Capture { acc_id: client, tx_id: tx }, None),
+            OperationRequest::Expire { client, tx } => (Operation::Expire { acc_id: client, tx_id: tx }, None),
+        })
+    }
+}
+
+impl TxReference {
+    /// Looks up the id of the referenced transaction, [`EngineError::TransactionNotFound`] for an unknown external id.
+    async fn resolve<TStorage: Storage + Journal>(self, engine: &Engine<TStorage>) -> Result<TxId, EngineError> {
+        match self {
+            TxReference::Id { tx } => Ok(tx),
+            TxReference::External { external_id } => {
+                let tx = engine.get_tx_by_external_id(&external_id).await?;
+                tx.map(|x| x.id()).ok_or(EngineError::TransactionNotFound)
+            }
+        }
+    }
+}
+
+/// An account as returned by the API, with the same fields as the CSV account summary plus the status, the metadata
+/// (the fields that are set only), the non-empty escrow buckets and the creation and last change times in milliseconds since the epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountResponse {
+    pub client: ClientId,
+    pub available: Decimal4,
+    pub held: Decimal4,
+    pub total: Decimal4,
+    pub locked: bool,
+    pub status: AccountStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_ref: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub escrow: BTreeMap<String, Decimal4>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// The sequence number of the last operation applied to the account.
+    #[serde(default)]
+    pub seq: u64,
+}
+
+impl TryFrom<Account> for AccountResponse {
+    type Error = EngineError;
+
+    fn try_from(value: Account) -> Result<Self, Self::Error> {
+        Ok(Self {
+            client: value.id(),
+            available: value.available(),
+            held: value.held(),
+            total: value.total()?,
+            locked: value.locked(),
+            status: value.status(),
+            name: value.metadata().name.clone(),
+            external_ref: value.metadata().external_ref.clone(),
+            escrow: value.escrow_buckets().clone(),
+            created_at: value.created_at(),
+            updated_at: value.updated_at(),
+            seq: value.seq(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// The stable code of an engine error, see [`EngineError::code`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<u16>,
+}
+
+/// A balance change or transaction state transition pushed to the WebSocket clients, e.g.
+/// `{"event": "dispute_opened", "account": {...}, "transaction": {"tx": 1, "state": "disputed", ...}}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountUpdate {
+    pub event: &'static str,
+    pub account: AccountResponse,
+    pub transaction: Option<CsvTransaction>,
+}
+
+impl AccountUpdate {
+    pub fn from_engine_event(event: &EngineEvent) -> Option<Self> {
+        let (name, account, transaction) = match event {
+            EngineEvent::DepositApplied { account, transaction } => ("deposit_applied", account, Some(transaction)),
+            EngineEvent::WithdrawalApplied { account, transaction } => ("withdrawal_applied", account, Some(transaction)),
+            EngineEvent::DisputeOpened { account, transaction } => ("dispute_opened", account, Some(transaction)),
+            EngineEvent::DisputeResolved { account, transaction } => ("dispute_resolved", account, Some(transaction)),
+            EngineEvent::ChargebackApplied { account, transaction } => ("chargeback_applied", account, Some(transaction)),
+            EngineEvent::EscrowMoved { account, transaction } => ("escrow_moved", account, Some(transaction)),
+            EngineEvent::EscrowReleased { account, transaction } => ("escrow_released", account, Some(transaction)),
+            EngineEvent::AuthorizationHeld { account, transaction } => ("authorization_held", account, Some(transaction)),
+            EngineEvent::AuthorizationCaptured { account, transaction } => ("authorization_captured", account, Some(transaction)),
+            EngineEvent::AuthorizationExpired { account, transaction } => ("authorization_expired", account, Some(transaction)),
+            EngineEvent::AccountLocked { account } => ("account_locked", account, None),
+            EngineEvent::AccountUnlocked { account } => ("account_unlocked", account, None),
+            EngineEvent::OperationRejected { .. } => return None,
+        };
+        // NOTE: the deposits keep the total representable, an account whose total still overflows is not sent
+        Some(Self { event: name, account: account.clone().try_into().ok()?, transaction: transaction.cloned().map(CsvTransaction::from) })
+    }
+}
+
+/// An observer that fans out the engine events to the subscribers (the WebSocket connections).
+/// Sending never blocks the engine: a subscriber that falls behind by more than the buffer misses the older updates.
+#[derive(Debug, Clone)]
+pub struct UpdatesBroadcaster {
+    sender: broadcast::Sender<AccountUpdate>,
+}
+
+impl UpdatesBroadcaster {
+    pub fn new(capacity: usize) -> Self {
+        Self { sender: broadcast::channel(capacity).0 }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AccountUpdate> {
+        self.sender.subscribe()
+    }
+}
+
+impl EngineObserver for UpdatesBroadcaster {
+    fn on_event(&self, event: &EngineEvent) {
+        if let Some(update) = AccountUpdate::from_engine_event(event) {
+            let _ = self.sender.send(update); // NOTE: fails only when nobody is subscribed
+        }
+    }
+}
+
+/// The query of `GET /ws`: a comma-separated list of client ids to follow, all the accounts when missing.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdatesQuery {
+    pub clients: Option<String>,
+}
+
+/// The query of `GET /accounts`: the accounts with an id greater than `cursor`, ordered by id.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AccountsQuery {
+    pub cursor: Option<ClientId>,
+    pub limit: Option<usize>,
+}
+
+/// An [`EngineError`] mapped to the HTTP status: 404 for unknown accounts and transactions, 409 for concurrent
+/// operations (safe to retry), 503 for a paused engine (also safe to retry), 500 for storage failures and 422 for the operations the engine rejects.
+#[derive(Debug)]
+pub struct ApiError(pub EngineError);
+
+impl From<EngineError> for ApiError {
+    fn from(value: EngineError) -> Self {
+        ApiError(value)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0 {
+            EngineError::AccountNotFound | EngineError::TransactionNotFound => StatusCode::NOT_FOUND,
+            EngineError::ConcurrentOperationDetected => StatusCode::CONFLICT,
+            EngineError::Paused => StatusCode::SERVICE_UNAVAILABLE,
+            EngineError::CorruptedJournal(_) | EngineError::SnapshotError(_) | EngineError::DatabaseError(_) | EngineError::HeldUnderflow | EngineError::ActorStopped => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        (status, Json(ErrorResponse { error: self.0.to_string(), code: Some(self.0.code()) })).into_response()
+    }
+}
+
+/// The REST API over a shared engine:
+/// - `POST /operations` executes an operation and returns the updated account,
+/// - `POST /operations/validate` validates an operation against the current state without executing it (see
+///   [`Engine::validate_operation`]) and returns the account as the operation would leave it, or the error it would get,
+/// - `GET /accounts/{id}` returns an account,
+/// - `GET /accounts?cursor=&limit=` returns a page of accounts ordered by id (at most 1000, 100 by default),
+/// - `GET /system-accounts/{name}` returns the `fees` or the `chargeback_losses` [`SystemAccount`],
+/// - `GET /reviews` returns the transactions flagged for a manual review by the [`crate::risk::RiskAssessor`],
+/// - `PUT /accounts/{id}/metadata` replaces the [`AccountMetadata`] of an account and returns the updated account,
+/// - `PUT /accounts/{id}/status` with `{"status": "frozen"}` moves an account to another [`AccountStatus`] and returns
+///   the updated account,
+/// - `GET /maintenance` returns whether the engine is paused, `PUT /maintenance` with `{"paused": true}` pauses it
+///   (see [`Engine::pause`]) and `{"paused": false}` resumes it,
+/// - `GET /ws?clients=1,2` upgrades to a WebSocket streaming an [`AccountUpdate`] (as JSON text) for every operation
+///   applied through this router,
+/// - `POST /graphql` (with the `graphql` feature) runs the queries of [`crate::graphql::schema`],
+/// - `GET /metrics` (with the `prometheus` feature) serves the metrics, see [`crate::prometheus::install`].
+pub fn router<TStorage>(engine: Engine<TStorage>) -> Router
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let updates = UpdatesBroadcaster::new(UPDATES_BUFFER);
+    let engine = engine.with_observer(Arc::new(updates.clone()));
+    let router = Router::new()
+        .route("/operations", post(post_operation::<TStorage>))
+        .route("/operations/validate", post(validate_operation::<TStorage>))
+        .route("/accounts", get(list_accounts::<TStorage>))
+        .route("/accounts/:id", get(get_account::<TStorage>))
+        .route("/accounts/:id/metadata", put(put_account_metadata::<TStorage>))
+        .route("/accounts/:id/status", put(put_account_status::<TStorage>))
+        .route("/system-accounts/:name", get(get_system_account::<TStorage>))
+        .route("/reviews", get(list_reviews::<TStorage>))
+        .route("/maintenance", get(get_maintenance::<TStorage>).put(put_maintenance::<TStorage>))
+        .with_state(engine.clone())
+        .merge(Router::new().route("/ws", get(subscribe_updates)).with_state(updates));
+    #[cfg(feature = "graphql")]
+    let router = router.merge(crate::graphql::router(engine));
+    #[cfg(feature = "prometheus")]
+    let router = router.merge(crate::prometheus::router());
+    router
+}
+
+/// The role a request requires: the reads (including the GraphQL queries) are open to the readers, `POST /operations`
+/// (and its dry run) to the operators and the other endpoints (the administrative ones) to the admins.
+pub fn required_role(method: &Method, path: &str) -> Role {
+    match (method, path) {
+        (&Method::GET, _) | (&Method::POST, "/graphql") => Role::Reader,
+        (&Method::POST, "/operations" | "/operations/validate") => Role::Operator,
+        _ => Role::Admin,
+    }
+}
+
+/// Requires the credentials (`Authorization: Bearer <credentials>` or `x-api-key`) of a client with the [`required_role`]
+/// of every request, answering `401 Unauthorized` without valid credentials and `403 Forbidden` with a lesser role.
+pub fn with_auth(router: Router, authenticator: Arc<Authenticator>) -> Router {
+    router.layer(middleware::from_fn_with_state(authenticator, authorize))
+}
+
+async fn authorize(State(authenticator): State<Arc<Authenticator>>, request: Request, next: Next) -> Response {
+    let result = {
+        let header = |name| request.headers().get(name).and_then(|x| x.to_str().ok());
+        let credentials = auth::credentials(header(header::AUTHORIZATION.as_str()), header("x-api-key"));
+        authenticator.authorize(credentials, required_role(request.method(), request.uri().path()))
+    };
+    match result {
+        Ok(_) => next.run(request).await,
+        Err(err @ AuthError::Forbidden { .. }) => (StatusCode::FORBIDDEN, Json(ErrorResponse { error: err.to_string(), code: None })).into_response(),
+        Err(err) => (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], Json(ErrorResponse { error: err.to_string(), code: None })).into_response(),
+    }
+}
+
+/// Serves the [`router`] on `addr` until `shutdown` completes, then waits for the requests in flight.
+/// With an `authenticator`, the requests are authorized as described in [`with_auth`].
+pub async fn serve<TStorage>(
+    engine: Engine<TStorage>,
+    addr: impl ToSocketAddrs,
+    authenticator: Option<Arc<Authenticator>>,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let router = match authenticator {
+        Some(authenticator) => with_auth(router(engine), authenticator),
+        None => router(engine),
+    };
+    axum::serve(listener, router).with_graceful_shutdown(shutdown).await
+}
+
+async fn post_operation<TStorage>(State(engine): State<Engine<TStorage>>, headers: HeaderMap, Json(request): Json<OperationRequest>) -> Result<Json<AccountResponse>, ApiError>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let (operation, external_id) = request.into_operation(&engine).await?;
+    let acc_id = operation.acc_id();
+    let header = |name| headers.get(name).and_then(|x| x.to_str().ok()).map(str::to_string);
+    let provenance = Provenance::new(OperationSource::Api { request_id: header(REQUEST_ID_HEADER) }).with_correlation_id(header(CORRELATION_ID_HEADER));
+    let options = ExecuteOptions { external_id: external_id.as_deref(), provenance: Some(&provenance), ..ExecuteOptions::default() };
+    engine.execute_operation_with(operation, options).await?;
+    let account = engine.get_account(acc_id).await?.ok_or(EngineError::AccountNotFound)?;
+    Ok(Json(account.try_into()?))
+}
+
+async fn validate_operation<TStorage>(State(engine): State<Engine<TStorage>>, Json(request): Json<OperationRequest>) -> Result<Json<AccountResponse>, ApiError>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let (operation, external_id) = request.into_operation(&engine).await?;
+    let account = engine.validate_operation(operation, external_id.as_deref()).await?;
+    Ok(Json(account.try_into()?))
+}
+
+async fn get_account<TStorage>(State(engine): State<Engine<TStorage>>, Path(acc_id): Path<ClientId>) -> Result<Json<AccountResponse>, ApiError>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let account = engine.get_account(acc_id).await?.ok_or(EngineError::AccountNotFound)?;
+    Ok(Json(account.try_into()?))
+}
+
+async fn get_system_account<TStorage>(State(engine): State<Engine<TStorage>>, Path(account): Path<SystemAccount>) -> Result<Json<AccountResponse>, ApiError>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let account = engine.get_system_account(account).await?;
+    Ok(Json(account.try_into()?))
+}
+
+async fn put_account_metadata<TStorage>(State(engine): State<Engine<TStorage>>, Path(acc_id): Path<ClientId>, Json(metadata): Json<AccountMetadata>) -> Result<Json<AccountResponse>, ApiError>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let account = engine.set_account_metadata(acc_id, metadata).await?;
+    Ok(Json(account.try_into()?))
+}
+
+/// The body of `PUT /accounts/{id}/status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusRequest {
+    pub status: AccountStatus,
+}
+
+async fn put_account_status<TStorage>(State(engine): State<Engine<TStorage>>, Path(acc_id): Path<ClientId>, Json(request): Json<StatusRequest>) -> Result<Json<AccountResponse>, ApiError>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let account = engine.set_account_status(acc_id, request.status).await?;
+    Ok(Json(account.try_into()?))
+}
+
+async fn list_accounts<TStorage>(State(engine): State<Engine<TStorage>>, Query(query): Query<AccountsQuery>) -> Result<Json<Vec<AccountResponse>>, ApiError>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let accounts = engine.list_accounts(query.cursor, limit).await?;
+    Ok(Json(accounts.into_iter().map(AccountResponse::try_from).collect::<Result<_, _>>()?))
+}
+
+async fn list_reviews<TStorage>(State(engine): State<Engine<TStorage>>) -> Result<Json<Vec<CsvTransaction>>, ApiError>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let txs = engine.get_pending_reviews().await?;
+    let rounding = engine.policy().rounding;
+    Ok(Json(txs.into_iter().map(|x| CsvTransaction::new(x, rounding)).collect()))
+}
+
+/// The body of `PUT /maintenance` and the response of both maintenance endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceMode {
+    pub paused: bool,
+}
+
+async fn get_maintenance<TStorage>(State(engine): State<Engine<TStorage>>) -> Json<MaintenanceMode>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    Json(MaintenanceMode { paused: engine.is_paused() })
+}
+
+async fn put_maintenance<TStorage>(State(engine): State<Engine<TStorage>>, Json(request): Json<MaintenanceMode>) -> Json<MaintenanceMode>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    if request.paused {
+        engine.pause();
+    } else {
+        engine.resume();
+    }
+    Json(MaintenanceMode { paused: engine.is_paused() })
+}
+
+async fn subscribe_updates(State(updates): State<UpdatesBroadcaster>, Query(query): Query<UpdatesQuery>, ws: WebSocketUpgrade) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let clients = match query.clients {
+        Some(clients) => Some(clients.split(',').map(|x| x.trim().parse::<ClientId>()).collect::<Result<HashSet<_>, _>>()
+            .map_err(|_| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: format!("invalid clients: {}", clients), code: None })))?),
+        None => None,
+    };
+    let receiver = updates.subscribe();
+    Ok(ws.on_upgrade(move |socket| stream_updates(socket, receiver, clients)))
+}
+
+async fn stream_updates(mut socket: WebSocket, mut receiver: broadcast::Receiver<AccountUpdate>, clients: Option<HashSet<ClientId>>) {
+    loop {
+        tokio::select! {
+            update = receiver.recv() => match update {
+                Ok(update) if clients.as_ref().is_none_or(|x| x.contains(&update.account.client)) => {
+                    let text = serde_json::to_string(&update).expect("updates are always serializable");
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    // NOTE: the client has to reload the accounts anyway, so it is disconnected instead of skipping silently
+                    let reason = format!("lagged behind by {} updates", missed);
+                    let _ = socket.send(Message::Close(Some(CloseFrame { code: axum::extract::ws::close_code::AGAIN, reason: reason.into() }))).await;
+                    return;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {} // NOTE: pings are answered by axum, anything else from the client is ignored
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod http_tests {
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use crate::decimal::Rounding;
+    use crate::engine::EnginePolicy;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    async fn call(router: &Router, method: &str, uri: &str, body: Option<&str>) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body.map(|x| Body::from(x.to_string())).unwrap_or_default())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null))
+    }
+
+    #[tokio::test]
+    async fn operations_and_accounts() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let router = router(engine.clone());
+
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "deposit", "client": 2, "tx": 1, "amount": "10"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        let mut body = body;
+        let created_at = body.as_object_mut().unwrap().remove("created_at").unwrap();
+        assert_eq!(body.as_object_mut().unwrap().remove("updated_at"), Some(created_at));
+        assert_eq!(body, serde_json::json!({"client": 2, "available": "10.0000", "held": "0.0000", "total": "10.0000", "locked": false, "status": "active", "seq": 1}));
+        let (status, _) = call(&router, "POST", "/operations", Some(r#"{"type": "deposit", "client": 1, "tx": 2, "amount": "5"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "dispute", "client": 2, "tx": 1}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((body["held"].clone(), body["seq"].clone()), ("10.0000".into(), 2.into()));
+        let (status, _) = call(&router, "POST", "/operations", Some(r#"{"type": "deposit", "client": 2, "tx": 5, "amount": "1", "external_id": "a1"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "dispute", "client": 2, "external_id": "a1"}"#)).await;
+        assert_eq!((status, body["held"].clone()), (StatusCode::OK, "11.0000".into()));
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "resolve", "client": 2, "external_id": "b2"}"#)).await;
+        assert_eq!((status, body["code"].clone()), (StatusCode::NOT_FOUND, 102.into()));
+        let (status, _) = call(&router, "POST", "/operations", Some(r#"{"type": "resolve", "client": 2, "external_id": "a1"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "withdrawal", "client": 1, "tx": 3, "amount": "50"}"#)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body, serde_json::json!({"error": "insufficient funds", "code": 104}));
+        let (status, body) = call(&router, "POST", "/operations/validate", Some(r#"{"type": "withdrawal", "client": 1, "tx": 3, "amount": "50"}"#)).await;
+        assert_eq!((status, body["code"].clone()), (StatusCode::UNPROCESSABLE_ENTITY, 104.into()));
+        let (status, body) = call(&router, "POST", "/operations/validate", Some(r#"{"type": "withdrawal", "client": 1, "tx": 3, "amount": "2"}"#)).await;
+        assert_eq!((status, body["available"].clone()), (StatusCode::OK, "3.0000".into()));
+        let (status, _) = call(&router, "POST", "/operations", Some(r#"{"type": "refund", "client": 1, "tx": 4}"#)).await;
+        assert!(status.is_client_error());
+
+        let (status, body) = call(&router, "GET", "/accounts/1", None).await;
+        assert_eq!((status, body["available"].clone()), (StatusCode::OK, "5.0000".into()));
+        let (status, _) = call(&router, "GET", "/accounts/9", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = call(&router, "PUT", "/accounts/1/metadata", Some(r#"{"name": "Alice", "external_ref": "crm-42"}"#)).await;
+        assert_eq!((status, body["name"].clone(), body["external_ref"].clone()), (StatusCode::OK, "Alice".into(), "crm-42".into()));
+        let (_, body) = call(&router, "GET", "/accounts/1", None).await;
+        assert_eq!(body["name"], "Alice");
+        let (status, _) = call(&router, "PUT", "/accounts/9/metadata", Some(r#"{"name": "Bob"}"#)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = call(&router, "PUT", "/accounts/1/status", Some(r#"{"status": "frozen"}"#)).await;
+        assert_eq!((status, body["status"].clone(), body["locked"].clone()), (StatusCode::OK, "frozen".into(), false.into()));
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "withdrawal", "client": 1, "tx": 6, "amount": "1"}"#)).await;
+        assert_eq!((status, body["code"].clone()), (StatusCode::UNPROCESSABLE_ENTITY, 115.into()));
+        let (status, body) = call(&router, "PUT", "/accounts/1/status", Some(r#"{"status": "closed"}"#)).await;
+        assert_eq!((status, body["code"].clone()), (StatusCode::UNPROCESSABLE_ENTITY, 117.into()));
+        let (status, _) = call(&router, "PUT", "/accounts/1/status", Some(r#"{"status": "active"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (_, body) = call(&router, "GET", "/accounts", None).await;
+        assert_eq!(body.as_array().unwrap().iter().map(|x| x["client"].as_u64().unwrap()).collect::<Vec<_>>(), vec![1, 2]);
+        let (_, body) = call(&router, "GET", "/accounts?cursor=1&limit=10", None).await;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(engine.get_account(2).await.unwrap().unwrap().held(), Decimal4::from(10));
+    }
+
+    #[tokio::test]
+    async fn operation_amounts_rounded_once() {
+        let engine = Engine::new(EchoDbStorage::new()).with_policy(EnginePolicy { rounding: Rounding::bankers(2), ..Default::default() });
+        let router = router(engine.clone());
+
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.35499"}"#)).await;
+        assert_eq!((status, body["available"].clone()), (StatusCode::OK, "2.3500".into()));
+    }
+
+    #[tokio::test]
+    async fn operation_provenance() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let request = Request::builder()
+            .method("POST")
+            .uri("/operations")
+            .header("content-type", "application/json")
+            .header("x-request-id", "r1")
+            .header("x-correlation-id", "c1")
+            .body(Body::from(r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "10"}"#))
+            .unwrap();
+        assert_eq!(router(engine.clone()).oneshot(request).await.unwrap().status(), StatusCode::OK);
+
+        let entries = engine.get_journal_entries(1, 10).await.unwrap();
+        let provenance = entries[0].provenance().unwrap();
+        assert_eq!(provenance.source, OperationSource::Api { request_id: Some("r1".to_string()) });
+        assert_eq!(provenance.correlation_id.as_deref(), Some("c1"));
+    }
+
+    #[tokio::test]
+    async fn pending_reviews() {
+        struct ReviewAll;
+
+        #[async_trait::async_trait]
+        impl crate::risk::RiskAssessor for ReviewAll {
+            async fn assess(&self, _operation: &Operation, _account: Option<&Account>, _provenance: Option<&Provenance>) -> crate::risk::RiskDecision {
+                crate::risk::RiskDecision::Review
+            }
+        }
+
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        let router = router(engine.with_risk_assessor(Arc::new(ReviewAll)));
+        let (status, _) = call(&router, "POST", "/operations", Some(r#"{"type": "deposit", "client": 1, "tx": 2, "amount": "20"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = call(&router, "GET", "/reviews", None).await;
+        assert_eq!((status, body.as_array().unwrap().len(), body[0]["tx"].clone()), (StatusCode::OK, 1, 2.into()));
+    }
+
+    #[tokio::test]
+    async fn maintenance_mode() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let router = router(engine.clone());
+        let (status, body) = call(&router, "PUT", "/maintenance", Some(r#"{"paused": true}"#)).await;
+        assert_eq!((status, body), (StatusCode::OK, serde_json::json!({"paused": true})));
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "10"}"#)).await;
+        assert_eq!((status, body["code"].clone()), (StatusCode::SERVICE_UNAVAILABLE, 151.into()));
+        let (status, _) = call(&router, "GET", "/accounts", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (_, body) = call(&router, "PUT", "/maintenance", Some(r#"{"paused": false}"#)).await;
+        assert_eq!(body, serde_json::json!({"paused": false}));
+        let (status, _) = call(&router, "POST", "/operations", Some(r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "10"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!engine.is_paused());
+    }
+
+    #[tokio::test]
+    async fn escrow_operations() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        let router = router(engine);
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "escrow", "client": 1, "tx": 2, "bucket": "order-7", "amount": "4"}"#)).await;
+        assert_eq!((status, body["available"].clone(), body["escrow"].clone()), (StatusCode::OK, "6.0000".into(), serde_json::json!({"order-7": "4.0000"})));
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "release", "client": 1, "tx": 3, "bucket": "order-7", "amount": "5"}"#)).await;
+        assert_eq!((status, body["code"].clone()), (StatusCode::UNPROCESSABLE_ENTITY, 124.into()));
+        let (status, body) = call(&router, "POST", "/operations", Some(r#"{"type": "release", "client": 1, "tx": 3, "bucket": "order-7", "amount": "4"}"#)).await;
+        assert_eq!((status, body["available"].clone(), body.get("escrow")), (StatusCode::OK, "10.0000".into(), None));
+    }
+
+    #[tokio::test]
+    async fn websocket_updates() {
+        use futures::StreamExt;
+        use tokio_tungstenite::tungstenite;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = router(Engine::new(EchoDbStorage::new()));
+        let app = router.clone();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws?clients=2", addr)).await.unwrap();
+
+        call(&router, "POST", "/operations", Some(r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "5"}"#)).await;
+        call(&router, "POST", "/operations", Some(r#"{"type": "deposit", "client": 2, "tx": 2, "amount": "10"}"#)).await;
+        call(&router, "POST", "/operations", Some(r#"{"type": "dispute", "client": 2, "tx": 2}"#)).await;
+
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            if let tungstenite::Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                received.push(serde_json::from_str::<serde_json::Value>(&text).unwrap());
+            }
+        }
+        assert_eq!(received[0]["event"], "deposit_applied");
+        assert_eq!(received[0]["account"]["available"], "10.0000");
+        assert_eq!(received[1]["event"], "dispute_opened");
+        assert_eq!(received[1]["account"]["held"], "10.0000");
+        assert_eq!(received[1]["transaction"]["state"], "disputed");
+        socket.close(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn authorized_requests() {
+        let config: crate::auth::AuthConfig = serde_json::from_str(r#"{"api_keys": [{"key": "reader-key", "role": "reader"}, {"key": "operator-key", "role": "operator"}]}"#).unwrap();
+        let router = with_auth(router(Engine::new(EchoDbStorage::new())), Arc::new(Authenticator::new(&config)));
+        let send = |method: &str, uri: &str, api_key: Option<&str>| {
+            let mut request = Request::builder().method(method).uri(uri).header("content-type", "application/json");
+            if let Some(api_key) = api_key {
+                request = request.header("authorization", format!("Bearer {}", api_key));
+            }
+            let body = if method == "POST" { r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "10"}"# } else { "" };
+            router.clone().oneshot(request.body(Body::from(body)).unwrap())
+        };
+
+        let response = send("GET", "/accounts", None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+        assert_eq!(send("GET", "/accounts", Some("unknown-key")).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send("POST", "/operations", Some("reader-key")).await.unwrap().status(), StatusCode::FORBIDDEN);
+        assert_eq!(send("POST", "/operations", Some("operator-key")).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send("GET", "/accounts/1", Some("reader-key")).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(send("DELETE", "/accounts/1", Some("operator-key")).await.unwrap().status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, Savepoint, Savepoints, Storage, TxOptions};
+use crate::transaction::{Transaction, TxId};
+
+/// The set of the applied operations (their hashes), kept apart from the main storage, see [`SharedIdempotencyStorage`].
+/// Shared by several engine instances (e.g. a Redis set behind `SET NX` / `DEL`), it deduplicates the operations globally.
+#[trait_variant::make(Send)]
+pub trait IdempotencyStore {
+    async fn contains(&self, op_hash: u64) -> Result<bool, DbError>;
+    /// Records the operations as applied, with the timestamps (unix millis) they were applied at. All or nothing:
+    /// fails with `EntityAlreadyExists`, recording none of them, if one of them is already recorded.
+    async fn claim(&self, operations: &[(u64, u64)]) -> Result<(), DbError>;
+    /// Forgets the operations of a claim whose storage transaction failed to commit.
+    async fn release(&self, op_hashes: &[u64]) -> Result<(), DbError>;
+    async fn get_all(&self) -> Result<Vec<u64>, DbError>;
+    /// Removes the operations applied before the `older_than` timestamp (unix millis) and returns their count.
+    async fn prune(&self, older_than: u64) -> Result<usize, DbError>;
+}
+
+/// An in-process [`IdempotencyStore`], shared by the engines of one process through an `Arc`.
+#[derive(Debug, Default)]
+pub struct MemoryIdempotencyStore {
+    operations: Mutex<HashMap<u64, u64>>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn contains(&self, op_hash: u64) -> Result<bool, DbError> {
+        Ok(self.operations.lock().unwrap().contains_key(&op_hash))
+    }
+
+    async fn claim(&self, operations: &[(u64, u64)]) -> Result<(), DbError> {
+        let mut claimed = self.operations.lock().unwrap();
+        if operations.iter().any(|(op_hash, _)| claimed.contains_key(op_hash)) {
+            return Err(DbError::EntityAlreadyExists);
+        }
+        claimed.extend(operations.iter().copied());
+        Ok(())
+    }
+
+    async fn release(&self, op_hashes: &[u64]) -> Result<(), DbError> {
+        let mut claimed = self.operations.lock().unwrap();
+        for op_hash in op_hashes {
+            claimed.remove(op_hash);
+        }
+        Ok(())
+    }
+
+    async fn get_all(&self) -> Result<Vec<u64>, DbError> {
+        Ok(self.operations.lock().unwrap().keys().copied().collect())
+    }
+
+    async fn prune(&self, older_than: u64) -> Result<usize, DbError> {
+        let mut claimed = self.operations.lock().unwrap();
+        let before = claimed.len();
+        claimed.retain(|_, timestamp| *timestamp >= older_than);
+        Ok(before - claimed.len())
+    }
+}
+
+/// Storage decorator that keeps the idempotency records in an [`IdempotencyStore`] instead of the wrapped storage,
+/// so the engine instances sharing the store apply every operation once, whichever instance receives it.
+///
+/// The operations of a storage transaction are claimed in the store when it commits, before the wrapped storage commits:
+/// an operation already claimed by another instance fails the commit with `ConcurrentModification` (the operation is
+/// reported as a concurrent one, and its retry is ignored as already processed), and a failed commit releases the claim.
+/// A crash between the two commits leaves the operation claimed but not applied. Pruning goes to the store right away.
+/// The idempotency records already in the wrapped storage are not consulted. The rejections (see `EnginePolicy::cache_rejections`)
+/// stay in the wrapped storage, so they are not shared, and are pruned with the store.
+pub struct SharedIdempotencyStorage<S, I> {
+    inner: S,
+    store: Arc<I>,
+}
+
+/// Db transaction of the `SharedIdempotencyStorage`, holds the operations to claim at the commit.
+pub struct SharedIdempotencyDbTx<T> {
+    inner: T,
+    operations: Vec<(u64, u64)>,
+    savepoints: Savepoints<usize>, // NOTE: the number of operations at each savepoint
+}
+
+impl<S, I> SharedIdempotencyStorage<S, I> {
+    pub fn new(inner: S, store: Arc<I>) -> Self {
+        Self { inner, store }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn store(&self) -> &Arc<I> {
+        &self.store
+    }
+}
+
+impl<S, I> Storage for SharedIdempotencyStorage<S, I>
+where
+    S: Storage + Sync,
+    S::DbTx: Send,
+    I: IdempotencyStore + Sync + Send,
+{
+    type DbTx = SharedIdempotencyDbTx<S::DbTx>;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        self.inner.get_tx(&mut db_tx.inner, tx_id).await
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        self.inner.insert_tx(&mut db_tx.inner, tx).await
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        self.inner.update_tx(&mut db_tx.inner, old_tx, new_tx).await
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        self.inner.get_all_txs(&mut db_tx.inner).await
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        self.inner.get_txs(&mut db_tx.inner, tx_ids).await
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        self.inner.insert_txs(&mut db_tx.inner, txs).await
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        self.inner.delete_txs(&mut db_tx.inner, tx_ids).await
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        self.inner.get_txs_by_account(&mut db_tx.inner, acc_id, cursor, limit).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        self.inner.get_tx_by_external_id(&mut db_tx.inner, external_id).await
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        self.inner.get_expired_authorizations(&mut db_tx.inner, now).await
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        self.inner.get_account(&mut db_tx.inner, acc_id).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        self.inner.get_all_accounts(&mut db_tx.inner).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        self.inner.get_accounts(&mut db_tx.inner, acc_ids).await
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        self.inner.insert_accounts(&mut db_tx.inner, accs).await
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        self.inner.list_accounts(&mut db_tx.inner, cursor, limit).await
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        self.inner.insert_account(&mut db_tx.inner, acc).await
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        self.inner.update_account(&mut db_tx.inner, old_acc, new_acc).await
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        self.inner.get_system_account(&mut db_tx.inner, account).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        self.inner.set_system_account(&mut db_tx.inner, account, acc).await
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        if db_tx.operations.iter().any(|(x, _)| *x == op_hash) {
+            return Ok(true);
+        }
+        self.store.contains(op_hash).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        if self.is_operation_processed(db_tx, op_hash).await? {
+            return Err(DbError::EntityAlreadyExists);
+        }
+        db_tx.operations.push((op_hash, timestamp));
+        Ok(())
+    }
+
+    async fn get_all_operations(&self, _db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        self.store.get_all().await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        let pruned = self.inner.prune_operations(&mut db_tx.inner, older_than).await?;
+        Ok(pruned + self.store.prune(older_than).await?)
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        self.inner.get_operation_rejection(&mut db_tx.inner, op_hash).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        self.inner.insert_operation_rejection(&mut db_tx.inner, op_hash, code, timestamp).await
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        self.inner.get_checkpoint(&mut db_tx.inner, source).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        self.inner.set_checkpoint(&mut db_tx.inner, source, rows).await
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        let inner = self.inner.start_db_tx(options).await?;
+        Ok(SharedIdempotencyDbTx { inner, operations: Vec::new(), savepoints: Savepoints::default() })
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        if db_tx.operations.is_empty() {
+            return self.inner.commit_db_tx(db_tx.inner).await;
+        }
+        match self.store.claim(&db_tx.operations).await {
+            Err(DbError::EntityAlreadyExists) => return Err(DbError::ConcurrentModification), // NOTE: applied by another instance meanwhile
+            result => result?,
+        }
+        if let Err(err) = self.inner.commit_db_tx(db_tx.inner).await {
+            let op_hashes: Vec<u64> = db_tx.operations.iter().map(|(x, _)| *x).collect();
+            if let Err(release_err) = self.store.release(&op_hashes).await {
+                tracing::warn!(error = %release_err, "failed to release the claimed operations");
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        let savepoint = self.inner.savepoint(&mut db_tx.inner).await?;
+        db_tx.savepoints.insert(savepoint, db_tx.operations.len());
+        Ok(savepoint)
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        self.inner.rollback_to(&mut db_tx.inner, savepoint).await?;
+        let operations_len = *db_tx.savepoints.rollback_to(savepoint)?;
+        db_tx.operations.truncate(operations_len);
+        Ok(())
+    }
+}
+
+impl<S, I> Journal for SharedIdempotencyStorage<S, I>
+where
+    S: Journal + Sync,
+    S::DbTx: Send,
+    I: IdempotencyStore + Sync + Send,
+{
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        self.inner.get_last_journal_seq(&mut db_tx.inner).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        self.inner.append_journal_entry(&mut db_tx.inner, entry).await
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        self.inner.get_journal_entries(&mut db_tx.inner, from_seq, limit).await
+    }
+}
+
+#[cfg(test)]
+mod idempotency_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn instances_share_the_operations() {
+        let store = Arc::new(MemoryIdempotencyStore::new());
+        let first = Engine::new(SharedIdempotencyStorage::new(EchoDbStorage::new(), store.clone()));
+        let second = Engine::new(SharedIdempotencyStorage::new(EchoDbStorage::new(), store.clone()));
+        assert_eq!(first.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(second.deposit(1, 1, Decimal4::from(100)).await, Ok(())); // ignored, applied by the first instance
+        assert_eq!(second.deposit(2, 2, Decimal4::from(50)).await, Ok(()));
+
+        assert_eq!(first.get_all_accounts().await.unwrap().iter().map(Account::id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(second.get_all_accounts().await.unwrap().iter().map(Account::id).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(store.get_all().await.unwrap().len(), 2);
+        assert_eq!(first.prune_operations(u64::MAX).await, Ok(2));
+        assert_eq!(second.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(second.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(100));
+    }
+
+    #[tokio::test]
+    async fn claimed_operation_fails_the_commit() {
+        let store = Arc::new(MemoryIdempotencyStore::new());
+        let storage = SharedIdempotencyStorage::new(EchoDbStorage::new(), store.clone());
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        storage.insert_account(&mut db_tx, &Account::new(1)).await.unwrap();
+        storage.insert_operation(&mut db_tx, 42, 0).await.unwrap();
+        store.claim(&[(42, 0)]).await.unwrap();
+        assert_eq!(storage.commit_db_tx(db_tx).await, Err(DbError::ConcurrentModification));
+
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_all_accounts(&mut db_tx).await, Ok(vec![]));
+        assert_eq!(storage.is_operation_processed(&mut db_tx, 42).await, Ok(true));
+    }
+}
@@ -0,0 +1,136 @@
+use serde::Serialize;
+
+use crate::account::{Account, ClientId};
+use crate::decimal::AmountOverflowError;
+use crate::engine::{Engine, EngineError};
+use crate::journal::{Journal, JournalEntry, Provenance};
+use crate::statement::Balance;
+use crate::storage::Storage;
+use crate::transaction::{Transaction, TxId};
+
+const PAGE_SIZE: usize = 1000;
+
+/// A journaled operation that touched the inspected entity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HistoryEntry {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub tx: TxId,
+    pub client: ClientId,
+    #[serde(rename = "type")]
+    pub op_type: &'static str,
+    /// The balances of the account right after the operation.
+    pub balance: Balance,
+    /// Where the operation came from, missing for the operations executed without an envelope.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+impl TryFrom<&JournalEntry> for HistoryEntry {
+    type Error = AmountOverflowError;
+
+    fn try_from(entry: &JournalEntry) -> Result<Self, Self::Error> {
+        Ok(Self {
+            seq: entry.seq(),
+            timestamp: entry.timestamp(),
+            tx: entry.transaction().id(),
+            client: entry.account().id(),
+            op_type: entry.operation().name(),
+            balance: entry.account().try_into()?,
+            provenance: entry.provenance().cloned(),
+        })
+    }
+}
+
+/// The stored account with its transactions and its journal history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountReport {
+    pub account: Account,
+    pub balance: Balance,
+    pub transactions: Vec<Transaction>,
+    pub history: Vec<HistoryEntry>,
+}
+
+/// The stored transaction with its account and its journal history (the deposit or withdrawal and the disputes).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TransactionReport {
+    pub transaction: Transaction,
+    pub account: Option<Account>,
+    pub history: Vec<HistoryEntry>,
+}
+
+/// Collects everything stored about the account, `None` if there is no such account.
+pub async fn inspect_account<TStorage: Storage + Journal>(engine: &Engine<TStorage>, acc_id: ClientId) -> Result<Option<AccountReport>, EngineError> {
+    let Some(account) = engine.get_account(acc_id).await? else {
+        return Ok(None);
+    };
+    let mut transactions = Vec::new();
+    loop {
+        let page = engine.get_txs_by_account(acc_id, transactions.last().map(Transaction::id), PAGE_SIZE).await?;
+        let last_page = page.len() < PAGE_SIZE;
+        transactions.extend(page);
+        if last_page {
+            break;
+        }
+    }
+    let history = history(engine, |x| x.account().id() == acc_id).await?;
+    Ok(Some(AccountReport { balance: (&account).try_into()?, account, transactions, history }))
+}
+
+/// Collects everything stored about the transaction, `None` if there is no such transaction.
+pub async fn inspect_tx<TStorage: Storage + Journal>(engine: &Engine<TStorage>, tx_id: TxId) -> Result<Option<TransactionReport>, EngineError> {
+    let Some(transaction) = engine.get_tx(tx_id).await? else {
+        return Ok(None);
+    };
+    let account = engine.get_account(transaction.account_id()).await?;
+    let history = history(engine, |x| x.operation().tx_id() == tx_id).await?;
+    Ok(Some(TransactionReport { transaction, account, history }))
+}
+
+/// Scans the whole journal page by page, keeping the entries matching `filter`.
+async fn history<TStorage: Storage + Journal>(engine: &Engine<TStorage>, filter: impl Fn(&JournalEntry) -> bool) -> Result<Vec<HistoryEntry>, EngineError> {
+    let mut history = Vec::new();
+    let mut from_seq = 1;
+    loop {
+        let entries = engine.get_journal_entries(from_seq, PAGE_SIZE).await?;
+        for entry in entries.iter().filter(|x| filter(x)) {
+            history.push(HistoryEntry::try_from(entry)?);
+        }
+        match entries.last() {
+            Some(last) if entries.len() == PAGE_SIZE => from_seq = last.seq() + 1,
+            _ => return Ok(history),
+        }
+    }
+}
+
+#[cfg(test)]
+mod inspect_tests {
+    use crate::decimal::Decimal4;
+    use crate::storage::EchoDbStorage;
+    use crate::transaction::TransactionState;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn account_and_tx_reports() {
+        let engine = Engine::new(EchoDbStorage::new());
+        engine.deposit(1, 1, Decimal4::from(100)).await.unwrap();
+        engine.withdraw(1, 2, Decimal4::from(30)).await.unwrap();
+        engine.deposit(2, 3, Decimal4::from(5)).await.unwrap();
+        engine.dispute(1, 1).await.unwrap();
+
+        let report = inspect_account(&engine, 1).await.unwrap().unwrap();
+        assert_eq!(report.balance, Balance { available: Decimal4::from(-30), held: Decimal4::from(100), total: Decimal4::from(70) });
+        assert_eq!(report.transactions.iter().map(|x| x.id()).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(report.history.iter().map(|x| (x.seq, x.op_type)).collect::<Vec<_>>(), vec![(1, "deposit"), (2, "withdrawal"), (4, "dispute")]);
+
+        let report = inspect_tx(&engine, 1).await.unwrap().unwrap();
+        assert_eq!(report.transaction.state(), TransactionState::Disputed);
+        assert_eq!(report.transaction.history().iter().map(|x| (x.from, x.to, x.operation.name())).collect::<Vec<_>>(), vec![(TransactionState::Posted, TransactionState::Disputed, "dispute")]);
+        assert_eq!(report.account.map(|x| x.id()), Some(1));
+        assert_eq!(report.history.iter().map(|x| x.op_type).collect::<Vec<_>>(), vec!["deposit", "dispute"]);
+
+        assert_eq!(inspect_account(&engine, 3).await, Ok(None));
+        assert_eq!(inspect_tx(&engine, 4).await, Ok(None));
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use thiserror::Error;
+
+use crate::account::{Account, ClientId};
+use crate::clock::now_millis;
+use crate::engine::Operation;
+use crate::storage::{DbError, Storage};
+use crate::transaction::Transaction;
+
+/// Where an operation came from, see [`Provenance`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationSource {
+    /// A row of an input file, `-` for stdin.
+    File { name: String, line: u64 },
+    /// A request of the HTTP or gRPC API, with its `x-request-id` header when the client sent one.
+    Api { request_id: Option<String> },
+    /// A Kafka record.
+    Kafka { topic: String, partition: i32, offset: i64 },
+    /// A message of a NATS stream (with its stream sequence) or an AMQP queue (with its delivery tag).
+    Queue { name: String, sequence: u64 },
+}
+
+impl Display for OperationSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OperationSource::File { name, line } => write!(f, "file {}:{}", name, line),
+            OperationSource::Api { request_id: Some(request_id) } => write!(f, "api request {}", request_id),
+            OperationSource::Api { request_id: None } => write!(f, "api request"),
+            OperationSource::Kafka { topic, partition, offset } => write!(f, "kafka {}/{}@{}", topic, partition, offset),
+            OperationSource::Queue { name, sequence } => write!(f, "queue {}#{}", name, sequence),
+        }
+    }
+}
+
+/// The envelope of an operation: its source, when it was received and the correlation id of the caller, journaled
+/// together with the operation so every balance change can be traced back to its origin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub source: OperationSource,
+    pub received_at: u64, // unix millis
+    pub correlation_id: Option<String>,
+}
+
+impl Provenance {
+    /// The provenance of an operation received now, without a correlation id.
+    pub fn new(source: OperationSource) -> Self {
+        Self { source, received_at: now_millis(), correlation_id: None }
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: Option<String>) -> Self {
+        self.correlation_id = correlation_id;
+        self
+    }
+}
+
+/// The SHA-256 digest chaining the journal entries, shown as 64 hex digits.
+/// All zeros for the entries written before the journal was chained.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Digest([u8; 32]);
+
+impl Digest {
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 32]
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|x| write!(f, "{:02x}", x))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid digest, expected 64 hex digits")]
+pub struct InvalidDigest;
+
+impl FromStr for Digest {
+    type Err = InvalidDigest;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(InvalidDigest);
+        }
+        let mut digest = [0; 32];
+        for (i, x) in digest.iter_mut().enumerate() {
+            *x = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| InvalidDigest)?;
+        }
+        Ok(Self(digest))
+    }
+}
+
+/// A record of an applied operation together with the resulting state of the touched entities.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    seq: u64,
+    timestamp: u64, // unix millis
+    operation: Operation,
+    account: Account,
+    transaction: Transaction,
+    #[serde(default)]
+    provenance: Option<Box<Provenance>>, // NOTE: boxed, most entries have none
+    #[serde(default)]
+    prev_digest: Digest,
+    #[serde(default)]
+    digest: Digest,
+}
+
+impl JournalEntry {
+    pub fn new(seq: u64, timestamp: u64, operation: Operation, account: Account, transaction: Transaction) -> Self {
+        Self {
+            seq,
+            timestamp,
+            operation,
+            account,
+            transaction,
+            provenance: None,
+            prev_digest: Digest::default(),
+            digest: Digest::default(),
+        }
+    }
+
+    pub fn with_provenance(mut self, provenance: Option<Provenance>) -> Self {
+        self.provenance = provenance.map(Box::new);
+        self
+    }
+
+    /// Links the entry to the previous one and seals it with its digest, the last step before the entry is appended.
+    pub fn chained(mut self, prev_digest: Digest) -> Self {
+        self.prev_digest = prev_digest;
+        self.digest = self.compute_digest();
+        self
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn operation(&self) -> &Operation {
+        &self.operation
+    }
+
+    pub fn account(&self) -> &Account {
+        &self.account
+    }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+
+    /// The envelope the operation was received in, `None` for the operations executed without one.
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_deref()
+    }
+
+    /// The digest of the previous entry, all zeros for the first entry.
+    pub fn prev_digest(&self) -> Digest {
+        self.prev_digest
+    }
+
+    pub fn digest(&self) -> Digest {
+        self.digest
+    }
+
+    /// The SHA-256 of the previous digest and the MessagePack encoding of the other fields.
+    pub fn compute_digest(&self) -> Digest {
+        let content = (self.seq, self.timestamp, &self.operation, &self.account, &self.transaction, &self.provenance);
+        let encoded = rmp_serde::to_vec(&content).expect("journal entry is always serializable");
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev_digest.0);
+        hasher.update(encoded);
+        Digest(hasher.finalize().into())
+    }
+}
+
+/// A break of the hash chain of the journal, see [`ChainVerifier`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ChainViolation {
+    /// The entry doesn't follow the previous one, entries were removed.
+    SequenceGap { expected: u64, actual: u64 },
+    /// The entry doesn't refer to the digest of the previous entry, the previous entry was replaced.
+    BrokenLink { seq: u64 },
+    /// The content of the entry doesn't match its digest, the entry was modified.
+    DigestMismatch { seq: u64 },
+    /// The journal ends before the expected head, or the entry at the head has another digest:
+    /// the journal was truncated or rewritten since the head was recorded.
+    HeadMismatch { seq: u64 },
+}
+
+/// The result of a chain verification: the head to record for the next verification and the breaks found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChainReport {
+    pub head_seq: u64,
+    pub head_digest: Digest,
+    pub violations: Vec<ChainViolation>,
+}
+
+/// Checks that every entry follows the previous one and matches its digest. The entries must be passed in the seq order.
+///
+/// The chain alone can not reveal that the last entries were removed, or that the whole journal was rewritten with new digests,
+/// so the head (seq and digest) of an earlier verification should be kept outside of the storage and passed as the expected head.
+/// The unchained entries (written before the journal was chained) are only accepted at the start of the journal.
+#[derive(Debug, Default)]
+pub struct ChainVerifier {
+    expected_head: Option<(u64, Digest)>,
+    last_seq: u64,
+    last_digest: Digest,
+    chained: bool,
+    violations: Vec<ChainViolation>,
+}
+
+impl ChainVerifier {
+    pub fn new(expected_head: Option<(u64, Digest)>) -> Self {
+        Self { expected_head, ..Self::default() }
+    }
+
+    pub fn verify(&mut self, entry: &JournalEntry) {
+        if entry.seq() != self.last_seq + 1 {
+            self.violations.push(ChainViolation::SequenceGap { expected: self.last_seq + 1, actual: entry.seq() });
+        }
+        if self.chained || !entry.digest().is_zero() {
+            self.chained = true;
+            if entry.prev_digest() != self.last_digest {
+                self.violations.push(ChainViolation::BrokenLink { seq: entry.seq() });
+            }
+            if entry.compute_digest() != entry.digest() {
+                self.violations.push(ChainViolation::DigestMismatch { seq: entry.seq() });
+            }
+        }
+        if self.expected_head.is_some_and(|(seq, digest)| seq == entry.seq() && digest != entry.digest()) {
+            self.violations.push(ChainViolation::HeadMismatch { seq: entry.seq() });
+        }
+        self.last_seq = entry.seq();
+        self.last_digest = entry.digest();
+    }
+
+    pub fn finish(mut self) -> ChainReport {
+        if let Some((seq, _)) = self.expected_head.filter(|(seq, _)| *seq > self.last_seq) {
+            self.violations.push(ChainViolation::HeadMismatch { seq });
+        }
+        ChainReport { head_seq: self.last_seq, head_digest: self.last_digest, violations: self.violations }
+    }
+}
+
+/// A break of the sequence numbers of the operations of an account, see [`AccountSeqVerifier`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum AccountSeqViolation {
+    /// Operations of the account are missing before this one.
+    Gap { client: ClientId, expected: u64, actual: u64 },
+    /// The operation doesn't come after the previous ones of the account, the operations were reordered or duplicated.
+    Reordered { client: ClientId, expected: u64, actual: u64 },
+}
+
+/// Checks that the operations of every account come with consecutive sequence numbers (see [`Account::seq`]): the journal entries
+/// in the seq order, or the accounts of the events received from a queue. The accounts are numbered independently, so their
+/// operations can be interleaved in any way.
+///
+/// The operations applied before the accounts were numbered have the sequence number 0: they are skipped, and the first numbered
+/// operation of such an account is accepted whatever its number.
+#[derive(Debug, Default)]
+pub struct AccountSeqVerifier {
+    last_seqs: HashMap<ClientId, u64>,
+    unnumbered: HashSet<ClientId>,
+    violations: Vec<AccountSeqViolation>,
+}
+
+impl AccountSeqVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks the next operation of the account `client`, which got the sequence number `seq`.
+    pub fn check(&mut self, client: ClientId, seq: u64) {
+        if seq == 0 {
+            self.unnumbered.insert(client);
+            return;
+        }
+        let expected = match self.last_seqs.get(&client) {
+            Some(last) => last + 1,
+            None if self.unnumbered.contains(&client) => seq,
+            None => 1,
+        };
+        if seq > expected {
+            self.violations.push(AccountSeqViolation::Gap { client, expected, actual: seq });
+        } else if seq < expected {
+            self.violations.push(AccountSeqViolation::Reordered { client, expected, actual: seq });
+        }
+        // NOTE: a late operation doesn't rewind the account, otherwise all the following ones would be reported too
+        let last = self.last_seqs.entry(client).or_default();
+        *last = seq.max(*last);
+    }
+
+    pub fn verify(&mut self, entry: &JournalEntry) {
+        self.check(entry.account().id(), entry.account().seq());
+    }
+
+    pub fn finish(self) -> Vec<AccountSeqViolation> {
+        self.violations
+    }
+}
+
+/// Append-only log of applied operations, written in the same storage transaction as the mutation itself.
+/// Sequence numbers start at 1 and have no gaps.
+#[trait_variant::make(Send)]
+pub trait Journal: Storage {
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError>;
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError>;
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError>;
+}
+
+/// Reads the journal page by page within one storage transaction, from the `from_seq` entry to the end.
+pub(crate) struct JournalPages {
+    next_seq: Option<u64>,
+}
+
+impl JournalPages {
+    const PAGE_SIZE: usize = 1000;
+
+    pub(crate) fn new(from_seq: u64) -> Self {
+        Self { next_seq: Some(from_seq) }
+    }
+
+    /// Returns the next page of entries, `None` after the last page.
+    pub(crate) async fn next_page<S: Journal>(&mut self, storage: &S, db_tx: &mut S::DbTx) -> Result<Option<Vec<JournalEntry>>, DbError> {
+        let Some(from_seq) = self.next_seq else {
+            return Ok(None);
+        };
+        let entries = storage.get_journal_entries(db_tx, from_seq, Self::PAGE_SIZE).await?;
+        self.next_seq = entries.last().filter(|_| entries.len() == Self::PAGE_SIZE).map(|x| x.seq() + 1);
+        Ok(Some(entries))
+    }
+}
+
+#[cfg(test)]
+mod journal_tests {
+    use super::*;
+
+    #[test]
+    fn account_seq_gaps_and_reordering() {
+        let mut verifier = AccountSeqVerifier::new();
+        for (client, seq) in [(1, 1), (2, 1), (1, 2), (2, 3), (1, 4), (1, 3), (3, 0), (3, 7), (3, 8), (4, 2)] {
+            verifier.check(client, seq);
+        }
+        assert_eq!(verifier.finish(), vec![
+            AccountSeqViolation::Gap { client: 2, expected: 2, actual: 3 },
+            AccountSeqViolation::Gap { client: 1, expected: 3, actual: 4 },
+            AccountSeqViolation::Reordered { client: 1, expected: 5, actual: 3 },
+            AccountSeqViolation::Gap { client: 4, expected: 1, actual: 2 },
+        ]);
+    }
+}
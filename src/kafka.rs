@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use rdkafka::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::account::Account;
+use crate::observer::{EngineEvent, EngineObserver};
+use crate::transaction::Transaction;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    pub topic: String,
+    pub dead_letter_topic: String,
+    pub initial_backoff: Duration,
+}
+
+impl KafkaConfig {
+    pub fn new(topic: impl Into<String>, dead_letter_topic: impl Into<String>) -> Self {
+        Self {
+            topic: topic.into(),
+            dead_letter_topic: dead_letter_topic.into(),
+            initial_backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// The payload published for every applied operation, keyed by the client id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KafkaEvent {
+    pub event: &'static str,
+    pub account: Account,
+    pub transaction: Option<Transaction>,
+}
+
+impl KafkaEvent {
+    pub fn from_engine_event(event: &EngineEvent) -> Option<Self> {
+        let (name, account, transaction) = match event {
+            EngineEvent::DepositApplied { account, transaction } => ("deposit_applied", account, Some(transaction)),
+            EngineEvent::WithdrawalApplied { account, transaction } => ("withdrawal_applied", account, Some(transaction)),
+            EngineEvent::DisputeOpened { account, transaction } => ("dispute_opened", account, Some(transaction)),
+            EngineEvent::DisputeResolved { account, transaction } => ("dispute_resolved", account, Some(transaction)),
+            EngineEvent::ChargebackApplied { account, transaction } => ("chargeback_applied", account, Some(transaction)),
+            EngineEvent::EscrowMoved { account, transaction } => ("escrow_moved", account, Some(transaction)),
+            EngineEvent::EscrowReleased { account, transaction } => ("escrow_released", account, Some(transaction)),
+            EngineEvent::AuthorizationHeld { account, transaction } => ("authorization_held", account, Some(transaction)),
+            EngineEvent::AuthorizationCaptured { account, transaction } => ("authorization_captured", account, Some(transaction)),
+            EngineEvent::AuthorizationExpired { account, transaction } => ("authorization_expired", account, Some(transaction)),
+            EngineEvent::AccountLocked { account } => ("account_locked", account, None),
+            EngineEvent::AccountUnlocked { account } => ("account_unlocked", account, None),
+            EngineEvent::OperationRejected { .. } => return None,
+        };
+        Some(Self { event: name, account: account.clone(), transaction: transaction.cloned() })
+    }
+}
+
+#[trait_variant::make(Send)]
+pub trait MessageProducer {
+    async fn produce(&self, topic: &str, key: &str, payload: &[u8]) -> Result<(), String>;
+}
+
+/// Produces messages with `acks=all` and idempotence enabled.
+pub struct RdKafkaProducer {
+    producer: FutureProducer,
+}
+
+impl RdKafkaProducer {
+    pub fn new(bootstrap_servers: &str) -> Result<Self, String> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", bootstrap_servers)
+            .set("acks", "all")
+            .set("enable.idempotence", "true")
+            .create()
+            .map_err(|e| e.to_string())?;
+        Ok(Self { producer })
+    }
+}
+
+impl MessageProducer for RdKafkaProducer {
+    async fn produce(&self, topic: &str, key: &str, payload: &[u8]) -> Result<(), String> {
+        let record = FutureRecord::to(topic).key(key).payload(payload);
+        self.producer.send(record, Duration::from_secs(5)).await
+            .map(|_| ())
+            .map_err(|(err, _)| err.to_string())
+    }
+}
+
+/// Creates a connected observer/publisher pair.
+/// Register the observer in the engine and run the publisher in a background task: `tokio::spawn(publisher.run())`.
+pub fn kafka_sink<P: MessageProducer>(config: KafkaConfig, producer: P) -> (KafkaObserver, KafkaPublisher<P>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    (KafkaObserver { sender }, KafkaPublisher { config, producer, receiver })
+}
+
+/// Queues every committed engine event for the [`KafkaPublisher`].
+#[derive(Debug)]
+pub struct KafkaObserver {
+    sender: mpsc::UnboundedSender<EngineEvent>,
+}
+
+impl EngineObserver for KafkaObserver {
+    fn on_event(&self, event: &EngineEvent) {
+        if !matches!(event, EngineEvent::OperationRejected { .. }) {
+            let _ = self.sender.send(event.clone()); // NOTE: the publisher is gone, nothing to deliver to
+        }
+    }
+}
+
+/// Publishes queued events with at-least-once delivery: a failed send is retried until the broker accepts it.
+/// Events that can not be serialized are published to the dead-letter topic instead.
+pub struct KafkaPublisher<P: MessageProducer> {
+    config: KafkaConfig,
+    producer: P,
+    receiver: mpsc::UnboundedReceiver<EngineEvent>,
+}
+
+impl<P: MessageProducer> KafkaPublisher<P> {
+    /// Publishes events until the observer is dropped and the queue is drained.
+    pub async fn run(mut self) {
+        while let Some(event) = self.receiver.recv().await {
+            let Some(kafka_event) = KafkaEvent::from_engine_event(&event) else {
+                continue;
+            };
+            let key = kafka_event.account.id().to_string();
+            match serde_json::to_vec(&kafka_event) {
+                Ok(payload) => self.publish(&self.config.topic, &key, &payload).await,
+                Err(err) => {
+                    let payload = format!("{{\"error\":{:?},\"event\":{:?}}}", err.to_string(), format!("{:?}", event));
+                    self.publish(&self.config.dead_letter_topic, &key, payload.as_bytes()).await
+                }
+            }
+        }
+    }
+
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8]) {
+        let mut backoff = self.config.initial_backoff;
+        while self.producer.produce(topic, key, payload).await.is_err() {
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+#[cfg(test)]
+mod kafka_tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FlakyProducer {
+        failures_left: Mutex<u32>,
+        messages: Mutex<Vec<(String, String, String)>>,
+    }
+
+    impl MessageProducer for &FlakyProducer {
+        async fn produce(&self, topic: &str, key: &str, payload: &[u8]) -> Result<(), String> {
+            let mut failures_left = self.failures_left.lock().unwrap();
+            if *failures_left > 0 {
+                *failures_left -= 1;
+                return Err("broker not available".to_string());
+            }
+            self.messages.lock().unwrap().push((topic.to_string(), key.to_string(), String::from_utf8(payload.to_vec()).unwrap()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn publisher_retries_until_delivered() {
+        let mut config = KafkaConfig::new("engine-events", "engine-events-dlq");
+        config.initial_backoff = Duration::from_millis(1);
+        let producer = FlakyProducer { failures_left: Mutex::new(3), ..Default::default() };
+        let (observer, publisher) = kafka_sink(config, &producer);
+
+        let engine = Engine::default().with_observer(Arc::new(observer));
+        assert_eq!(engine.deposit(7, 1, Decimal4::from(100)).await, Ok(()));
+        assert!(engine.withdraw(7, 2, Decimal4::from(200)).await.is_err());
+        assert_eq!(engine.dispute(7, 1).await, Ok(()));
+        drop(engine);
+        publisher.run().await;
+
+        let messages = producer.messages.lock().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|(topic, key, _)| topic == "engine-events" && key == "7"));
+        assert!(messages[0].2.starts_with(r#"{"event":"deposit_applied","account":{"id":7"#));
+        assert!(messages[1].2.starts_with(r#"{"event":"dispute_opened""#));
+    }
+
+    #[test]
+    fn kafka_event_skips_rejections() {
+        let event = EngineEvent::OperationRejected {
+            operation: crate::engine::Operation::Dispute { acc_id: 1, tx_id: 1 },
+            error: crate::engine::EngineError::TransactionNotFound,
+            provenance: None,
+        };
+        assert_eq!(KafkaEvent::from_engine_event(&event), None);
+    }
+}
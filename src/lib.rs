@@ -1,6 +1,82 @@
+pub mod clock;
+pub mod codec;
+pub mod compliance;
+pub mod config;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub mod dead_letter;
 pub mod decimal;
+pub mod disputes;
 pub mod transaction;
 pub mod engine;
+pub mod generator;
+pub mod idempotency;
+pub mod inspect;
 pub mod storage;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod file_storage;
+pub mod dyn_storage;
+pub mod memory_storage;
+pub mod cached_storage;
+pub mod sharded_storage;
+pub mod tiered_storage;
+pub mod traced_storage;
 pub mod account;
+#[cfg(feature = "tokio")]
+pub mod actor;
 pub mod csv_parser;
+pub mod journal;
+pub mod migrate;
+pub mod observer;
+pub mod privacy;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub mod queue;
+pub mod reconcile;
+pub mod redact;
+#[cfg(all(not(target_arch = "wasm32"), any(feature = "tokio", feature = "async-std")))]
+pub mod replicated_storage;
+pub mod risk;
+pub mod replay;
+pub mod runtime;
+pub mod shutdown;
+pub mod settlement;
+pub mod snapshot;
+pub mod statement;
+pub mod validator;
+#[cfg(feature = "tokio")]
+pub mod tcp;
+#[cfg(feature = "tokio")]
+pub mod watch;
+#[cfg(feature = "amqp")]
+pub mod amqp;
+#[cfg(any(feature = "http", feature = "grpc"))]
+pub mod auth;
+#[cfg(feature = "webhooks")]
+pub mod webhook;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "parquet")]
+pub mod parquet_reader;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "tower")]
+pub mod service;
+#[cfg(feature = "test-utils")]
+pub mod chaos;
+#[cfg(feature = "metrics")]
+pub mod metered_storage;
+#[cfg(feature = "metrics")]
+pub mod engine_metrics;
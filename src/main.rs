@@ -1,27 +1,1076 @@
-use clap::{Arg, Command};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use transactions_engine::csv_parser::{read_csv, write_csv};
+use anyhow::{bail, Context};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use tracing_subscriber::EnvFilter;
+
+use transactions_engine::account::ClientId;
+use transactions_engine::compliance::AmlConfig;
+use transactions_engine::config::EngineConfig;
+use transactions_engine::csv_parser::{resolve_input_paths, write_csv, write_operations, write_transactions, AccountsWriter, CsvReader, IngestStats, MergeBy, OperationType, OutputFormat};
+use transactions_engine::dead_letter::{replay_dead_letters, DeadLetterStore, FileDeadLetterStore};
+use transactions_engine::decimal::{AmountFormat, Decimal4};
+use transactions_engine::disputes::DisputeFilter;
+use transactions_engine::dyn_storage::{open_storage, open_tenant_storage, DynStorage};
 use transactions_engine::engine::Engine;
+use transactions_engine::generator::WorkloadGenerator;
+use transactions_engine::inspect::{inspect_account, inspect_tx};
+use transactions_engine::journal::Digest;
+use transactions_engine::migrate::migrate;
+use transactions_engine::queue::{MessageOutcome, RetryPolicy};
+use transactions_engine::redact;
+use transactions_engine::runtime::JoinHandle;
+use transactions_engine::settlement;
+use transactions_engine::shutdown::{self, OperationCounter};
 use transactions_engine::storage::EchoDbStorage;
+use transactions_engine::tcp::LineServerConfig;
+use transactions_engine::transaction::TxId;
+use transactions_engine::watch::{DirectoryWatcher, WatchedFile};
+
+/// How often a server releases the expired authorizations.
+const HOLD_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let matches = Command::new("Transactions Engine")
+    let matches = cli().get_matches();
+    let Some((name, matches)) = matches.subcommand() else {
+        unreachable!("a subcommand is required");
+    };
+    init_logging(matches)?;
+    let config = load_config(matches)?;
+    redact::set_enabled(config.redact_pii);
+    match name {
+        "process" => process(matches, &config).await,
+        "validate" => validate(matches, &config).await,
+        "inspect" => inspect(matches, &config).await,
+        "aml-report" => aml_report(matches, &config).await,
+        "report" => report(matches, &config).await,
+        "settle" => settle(matches, &config).await,
+        "verify-journal" => verify_journal(matches, &config).await,
+        "dead-letters" => dead_letters(matches, &config).await,
+        "migrate" => migrate_storage(matches).await,
+        "generate" => generate(matches),
+        "watch" => watch(matches, &config).await,
+        "tcp" => serve_tcp(matches, &config).await,
+        #[cfg(feature = "http")]
+        "serve" => serve_http(matches, &config).await,
+        #[cfg(feature = "grpc")]
+        "grpc" => serve_grpc(matches, &config).await,
+        #[cfg(feature = "nats")]
+        "nats" => consume_nats(matches, &config).await,
+        #[cfg(feature = "amqp")]
+        "amqp" => consume_amqp(matches, &config).await,
+        _ => unreachable!("unknown subcommand {}", name),
+    }
+}
+
+/// The command line: a subcommand per mode, and the config, storage, policy and input format flags shared by all of them.
+fn cli() -> Command {
+    let command = Command::new("Transactions Engine")
         .version("0.1.0")
         .about("A simple transactions engine")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .help("The TOML config file, see `EngineConfig` (also `TRANSACTIONS_ENGINE_CONFIG`); the flags override it")
+                .value_parser(clap::value_parser!(PathBuf))
+                .global(true),
+        )
+        .arg(
+            Arg::new("storage")
+                .long("storage")
+                .help("The storage backend: `memory` (the default), `file:<path>` or `sqlite:<url>` (requires the `sqlite` feature)")
+                .global(true),
+        )
+        .arg(
+            Arg::new("dispute-window-secs")
+                .long("dispute-window-secs")
+                .help("Reject the disputes of deposits older than this, no limit by default")
+                .value_parser(clap::value_parser!(u64))
+                .global(true),
+        )
+        .arg(
+            Arg::new("authorization-expiry-secs")
+                .long("authorization-expiry-secs")
+                .help("Release the authorizations not captured within this, no expiry by default")
+                .value_parser(clap::value_parser!(u64))
+                .global(true),
+        )
+        .arg(
+            Arg::new("lock-on-chargeback")
+                .long("lock-on-chargeback")
+                .help("Whether a chargeback locks the account, `true` by default")
+                .value_parser(clap::value_parser!(bool))
+                .global(true),
+        )
+        .arg(
+            Arg::new("decimals")
+                .long("decimals")
+                .help("The decimal places the amounts are rounded to, at most 4 (the default)")
+                .value_parser(clap::value_parser!(u32))
+                .global(true),
+        )
+        .arg(
+            Arg::new("rounding")
+                .long("rounding")
+                .help("The rounding of the amounts: midpoint-toward-zero (the default), midpoint-away-from-zero, bankers, toward-zero or away-from-zero")
+                .global(true),
+        )
+        .arg(
+            Arg::new("max-amount")
+                .long("max-amount")
+                .help("Reject deposits and withdrawals of a larger amount, no limit by default")
+                .global(true),
+        )
         .arg(
-            Arg::new("filepath")
-                .help("The path to the CSV file to process")
-                .required(true)
-                .index(1),
+            Arg::new("max-balance")
+                .long("max-balance")
+                .help("Reject deposits that would take the total balance of an account over this, no limit by default")
+                .global(true),
         )
-        .get_matches();
+        .arg(
+            Arg::new("withdrawal-fee")
+                .long("withdrawal-fee")
+                .help("The flat fee of a withdrawal, moved to the fees system account, no fee by default")
+                .global(true),
+        )
+        .arg(
+            Arg::new("write-off-chargeback-losses")
+                .long("write-off-chargeback-losses")
+                .help("Whether the negative balance left by a chargeback is written off to the chargeback losses system account, `false` by default")
+                .value_parser(clap::value_parser!(bool))
+                .global(true),
+        )
+        .arg(
+            Arg::new("negative-available")
+                .long("negative-available")
+                .help("What a dispute of already spent funds does: allow (the default, the available funds go negative), clamp or reject")
+                .value_parser(["allow", "clamp", "reject"])
+                .global(true),
+        )
+        .arg(
+            Arg::new("auto-unlock")
+                .long("auto-unlock")
+                .help("Whether an account locked by a chargeback is unlocked once no dispute is open and its balance is not negative, `false` by default")
+                .value_parser(clap::value_parser!(bool))
+                .global(true),
+        )
+        .arg(
+            Arg::new("cache-rejections")
+                .long("cache-rejections")
+                .help("Whether a rejected deposit, withdrawal, escrow move or authorization is recorded, so its retries fail the same way, `false` by default")
+                .value_parser(clap::value_parser!(bool))
+                .global(true),
+        )
+        .arg(
+            Arg::new("quiet")
+                .long("quiet")
+                .help("Do not print the progress and the summary statistics to stderr")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .help("The format of the logs written to stderr: `text` for humans or `json` (one object per line)")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .global(true),
+        )
+        .arg(
+            Arg::new("log-level")
+                .long("log-level")
+                .help("The log filter, e.g. `info` or `transactions_engine=debug`; `RUST_LOG` or `warn` by default")
+                .global(true),
+        )
+        .arg(
+            Arg::new("tenant")
+                .long("tenant")
+                .help("Work in the isolated namespace of this tenant, with its `[tenants.<id>]` settings (also `TRANSACTIONS_ENGINE_TENANT`)")
+                .global(true),
+        )
+        .arg(
+            Arg::new("redact-pii")
+                .long("redact-pii")
+                .help("Mask the account ids and bucket the amounts in the logs")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Abort on the first row that can not be parsed or is rejected by the engine instead of skipping it")
+                .action(ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("delimiter")
+                .long("delimiter")
+                .help("The field delimiter of the input, a single character or `tab`")
+                .default_value(",")
+                .global(true),
+        )
+        .arg(
+            Arg::new("column-alias")
+                .long("column-alias")
+                .help("Reads an input column under another name, e.g. `client_id=client` (can be repeated)")
+                .action(ArgAction::Append)
+                .global(true),
+        )
+        .arg(
+            Arg::new("type-alias")
+                .long("type-alias")
+                .help("Accepts another (case-insensitive) name for an operation type, e.g. `dep=deposit` (can be repeated)")
+                .action(ArgAction::Append)
+                .global(true),
+        )
+        .arg(
+            Arg::new("amount-format")
+                .long("amount-format")
+                .help("The notation of the amounts: `plain` (1234.56), `en` (1,234.56) or `eu` (1.234,56)")
+                .value_parser(["plain", "en", "eu"])
+                .default_value("plain")
+                .global(true),
+        )
+        .subcommand(process_command())
+        .subcommand(
+            Command::new("validate")
+                .about("Checks CSV files against the schema and a dry run of the engine without changing the storage, reporting every bad row")
+                .arg(
+                    Arg::new("file")
+                        .help("The paths (or glob patterns) of the CSV files to check, `-` (the default) reads stdin")
+                        .num_args(1..)
+                        .default_value("-"),
+                ),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Prints a stored account or transaction with its linked records as JSON")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("account")
+                        .about("Prints the account with its transactions and journal history")
+                        .arg(Arg::new("id").help("The client id").value_parser(clap::value_parser!(ClientId)).required(true)),
+                )
+                .subcommand(
+                    Command::new("tx")
+                        .about("Prints the transaction with its account and journal history")
+                        .arg(Arg::new("id").help("The transaction id").value_parser(clap::value_parser!(TxId)).required(true)),
+                ),
+        )
+        .subcommand(
+            Command::new("aml-report")
+                .about("Runs the AML checks over the journal and prints the suspicious-activity report")
+                .arg(
+                    Arg::new("threshold")
+                        .long("threshold")
+                        .help("Deposits and withdrawals of at least this amount are flagged")
+                        .value_parser(clap::value_parser!(Decimal4))
+                        .default_value("10000"),
+                )
+                .arg(
+                    Arg::new("structuring-margin")
+                        .long("structuring-margin")
+                        .help("Deposits less than this below the threshold count as just below it")
+                        .value_parser(clap::value_parser!(Decimal4))
+                        .default_value("1000"),
+                )
+                .arg(
+                    Arg::new("structuring-count")
+                        .long("structuring-count")
+                        .help("This many just-below-threshold deposits of a client within the window are flagged as structuring")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("3"),
+                )
+                .arg(
+                    Arg::new("structuring-window-ms")
+                        .long("structuring-window-ms")
+                        .help("The window of the structuring check")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("86400000"),
+                )
+                .arg(Arg::new("output-format").long("output-format").help("The format of the report").value_parser(["json", "csv"]).default_value("json")),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Prints an operational report")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("disputes")
+                        .about("Lists the open disputes, the oldest first, with how long they have been open")
+                        .arg(Arg::new("client").long("client").help("Only the disputes of this client").value_parser(clap::value_parser!(ClientId)))
+                        .arg(Arg::new("min-age-secs").long("min-age-secs").help("Only the disputes open for at least this long").value_parser(clap::value_parser!(u64)))
+                        .arg(Arg::new("output-format").long("output-format").help("The format of the report").value_parser(["csv", "json"]).default_value("csv")),
+                )
+                .subcommand(
+                    Command::new("chargebacks")
+                        .about("Lists the clients whose chargeback ratio (charged back deposits / deposits) is above a threshold, the highest first")
+                        .arg(Arg::new("max-ratio").long("max-ratio").help("The highest acceptable ratio").value_parser(clap::value_parser!(Decimal4)).default_value("0.01"))
+                        .arg(Arg::new("min-deposits").long("min-deposits").help("The clients with fewer deposits are left out").value_parser(clap::value_parser!(u64)).default_value("1"))
+                        .arg(Arg::new("client").long("client").help("Print the deposit and chargeback counts of this client instead").value_parser(clap::value_parser!(ClientId)))
+                        .arg(Arg::new("output-format").long("output-format").help("The format of the report").value_parser(["csv", "json"]).default_value("csv")),
+                ),
+        )
+        .subcommand(
+            Command::new("settle")
+                .about("Nets the activity of every account on a UTC day into a settlement record and writes the settlement file")
+                .arg(Arg::new("date").long("date").help("The day to settle, `YYYY-MM-DD`").required(true))
+                .arg(Arg::new("output").long("output").help("The settlement file, `settlement-<date>.<format>` by default"))
+                .arg(Arg::new("output-format").long("output-format").help("The format of the settlement file").value_parser(["csv", "json"]).default_value("csv")),
+        )
+        .subcommand(
+            Command::new("verify-journal")
+                .about("Verifies the hash chain of the journal, the sequence numbers of the accounts and the stored state against it, printing the head of the journal")
+                .arg(Arg::new("head").long("head").help("The head printed by an earlier verification, `<seq>:<digest>`, to also detect a truncated or rewritten journal")),
+        )
+        .subcommand(
+            Command::new("dead-letters")
+                .about("Lists or replays the operations the queue consumers rejected")
+                .subcommand_required(true)
+                .arg(Arg::new("file").long("file").help("The dead-letter file of the consumers").default_value("dead-letters.jsonl"))
+                .subcommand(Command::new("list").about("Prints the dead letters as JSON"))
+                .subcommand(
+                    Command::new("replay")
+                        .about("Executes the dead letters again and removes the applied ones")
+                        .arg(Arg::new("id").long("id").help("Only replay this letter, can be repeated").value_parser(clap::value_parser!(u64)).action(ArgAction::Append)),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Copies the whole state from one storage backend to another and verifies the copy")
+                .arg(Arg::new("from").long("from").help("The source storage, in the `--storage` format").required(true))
+                .arg(Arg::new("to").long("to").help("The target storage, must be empty").required(true)),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Ingests the new CSV files appearing in a directory until interrupted, then writes the account summary")
+                .arg(Arg::new("dir").help("The directory to watch").required(true))
+                .arg(Arg::new("done-dir").long("done-dir").help("Where the processed files are moved, `<dir>/done` by default"))
+                .arg(Arg::new("failed-dir").long("failed-dir").help("Where the files that failed are moved, `<dir>/failed` by default"))
+                .arg(
+                    Arg::new("poll-interval-ms")
+                        .long("poll-interval-ms")
+                        .help("How often the directory is checked for new files")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("1000"),
+                ),
+        )
+        .subcommand(
+            Command::new("tcp")
+                .about("Accepts newline-delimited operations (CSV or JSON) over TCP until interrupted, then writes the account summary")
+                .arg(Arg::new("listen").long("listen").help("The address to listen on, `127.0.0.1:7070` by default"))
+                .arg(
+                    Arg::new("max-line-length")
+                        .long("max-line-length")
+                        .help("Longer lines are rejected and their connection is closed")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("4096"),
+                ),
+        )
+        .subcommand(
+            Command::new("generate")
+                .about("Writes a random workload in the CSV input format, for load tests and fixtures")
+                .arg(
+                    Arg::new("operations")
+                        .long("operations")
+                        .help("The number of rows to generate")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10000"),
+                )
+                .arg(
+                    Arg::new("accounts")
+                        .long("accounts")
+                        .help("The number of distinct clients")
+                        .value_parser(clap::value_parser!(u16).range(1..))
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::new("dispute-ratio")
+                        .long("dispute-ratio")
+                        .help("The share of the rows opening a dispute")
+                        .value_parser(clap::value_parser!(f64))
+                        .default_value("0.02"),
+                )
+                .arg(
+                    Arg::new("error-rate")
+                        .long("error-rate")
+                        .help("The share of the rows the engine rejects")
+                        .value_parser(clap::value_parser!(f64))
+                        .default_value("0.01"),
+                )
+                .arg(Arg::new("seed").long("seed").help("The random seed, the same seed gives the same workload").value_parser(clap::value_parser!(u64)).default_value("0"))
+                .arg(Arg::new("output").long("output").help("Write the rows to this file instead of stdout")),
+        );
+    #[cfg(feature = "http")]
+    let command = command.subcommand(
+        Command::new("serve")
+            .about("Runs the REST API over the engine until interrupted")
+            .arg(Arg::new("listen").long("listen").help("The address to listen on, `127.0.0.1:8080` by default"))
+            .arg(Arg::new("auth-config").long("auth-config").help("Require the API keys or JWTs of this JSON file, see `AuthConfig`")),
+    );
+    #[cfg(feature = "grpc")]
+    let command = command.subcommand(
+        Command::new("grpc")
+            .about("Runs the gRPC service over the engine until interrupted")
+            .arg(Arg::new("listen").long("listen").help("The address to listen on, `127.0.0.1:50051` by default"))
+            .arg(Arg::new("auth-config").long("auth-config").help("Require the API keys or JWTs of this JSON file, see `AuthConfig`")),
+    );
+    #[cfg(feature = "nats")]
+    let command = command.subcommand(
+        Command::new("nats")
+            .about("Consumes operations from a NATS JetStream consumer until interrupted, then writes the account summary")
+            .arg(Arg::new("url").long("url").help("The NATS server").default_value("nats://127.0.0.1:4222"))
+            .arg(Arg::new("stream").long("stream").help("The JetStream stream").required(true))
+            .arg(Arg::new("consumer").long("consumer").help("The durable pull consumer, created when missing").default_value("transactions-engine"))
+            .arg(Arg::new("subject").long("subject").help("Only consume the subjects matching this filter"))
+            .arg(Arg::new("dead-letter-file").long("dead-letter-file").help("Where the rejected operations are kept, see `dead-letters`").default_value("dead-letters.jsonl")),
+    );
+    #[cfg(feature = "amqp")]
+    let command = command.subcommand(
+        Command::new("amqp")
+            .about("Consumes operations from an AMQP queue until interrupted, then writes the account summary")
+            .arg(Arg::new("url").long("url").help("The AMQP broker").default_value("amqp://127.0.0.1:5672/%2f"))
+            .arg(Arg::new("queue").long("queue").help("The queue to consume").required(true))
+            .arg(
+                Arg::new("prefetch")
+                    .long("prefetch")
+                    .help("Deliveries sent by the broker ahead of the acknowledgements")
+                    .value_parser(clap::value_parser!(u16))
+                    .default_value("100"),
+            )
+            .arg(Arg::new("on-invalid").long("on-invalid").help("`ack`, `requeue` or `dead-letter` for the payloads that are not an operation").default_value("dead-letter"))
+            .arg(Arg::new("on-rejected").long("on-rejected").help("`ack`, `requeue` or `dead-letter` for the operations the engine rejects").default_value("dead-letter"))
+            .arg(Arg::new("on-transient").long("on-transient").help("`ack`, `requeue` or `dead-letter` for the concurrency and storage failures").default_value("requeue"))
+            .arg(Arg::new("dead-letter-file").long("dead-letter-file").help("Where the rejected operations are kept, see `dead-letters`").default_value("dead-letters.jsonl")),
+    );
+    command
+}
 
-    let filepath: &String = matches.get_one("filepath").unwrap();
+/// The batch mode: processes the input files and writes the account summary.
+fn process_command() -> Command {
+    let command = Command::new("process")
+        .about("Processes CSV (or Parquet) files of operations and writes the account summary")
+        .arg(
+            Arg::new("file")
+                .help("The paths (or glob patterns) of the CSV files to process, `-` (the default) reads stdin")
+                .num_args(1..)
+                .default_value("-"),
+        )
+        .arg(
+            Arg::new("merge-by")
+                .long("merge-by")
+                .help("How several files are combined: `file` processes them one by one in file name order, `tx` merges their rows by tx id")
+                .value_parser(["file", "tx"])
+                .default_value("file"),
+        )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .help("Checkpoint the applied rows of every file in the storage and skip the rows applied by a previous run")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .help("The file to write the account summary to, stdout by default"),
+        )
+        .arg(
+            Arg::new("output-format")
+                .long("output-format")
+                .help("The format of the account summary, `csv` by default")
+                .value_parser(["csv", "json", "jsonl"]),
+        )
+        .arg(
+            Arg::new("dump-transactions")
+                .long("dump-transactions")
+                .help("Also write every stored transaction with its type, amount and final state to this CSV file"),
+        )
+        .arg(
+            Arg::new("unsorted")
+                .long("unsorted")
+                .help("Write the accounts in the storage order instead of sorting them by client id")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("system-accounts")
+                .long("system-accounts")
+                .help("Also write the system accounts (fees and chargeback losses) to the account summary")
+                .action(ArgAction::SetTrue),
+        );
+    #[cfg(feature = "prometheus")]
+    let command = command.arg(
+        Arg::new("metrics-file")
+            .long("metrics-file")
+            .help("Write the engine and storage metrics in the Prometheus text format to this file once the input is processed"),
+    );
+    command
+}
 
-    let mut engine = Engine::new(EchoDbStorage::new());
-    read_csv(filepath, &mut engine).await?;
+async fn process(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let filepaths: Vec<String> = matches.get_many("file").unwrap().cloned().collect();
+    let merge_by: MergeBy = matches.get_one::<String>("merge-by").unwrap().parse()?;
+    let output = matches.get_one::<String>("output").or(config.output.path.as_ref());
+    let dump_transactions: Option<&String> = matches.get_one("dump-transactions");
+    let format = match matches.get_one::<String>("output-format") {
+        Some(format) => format.parse::<OutputFormat>()?,
+        None => config.output.format()?,
+    };
+    let writer = AccountsWriter::new()
+        .with_format(format)
+        .with_sorted(config.output.sorted && !matches.get_flag("unsorted"))
+        .with_system_accounts(config.output.system_accounts || matches.get_flag("system-accounts"));
+    let quiet = matches.get_flag("quiet");
+    let mut reader = csv_reader(matches)?.with_resume(matches.get_flag("resume"));
+    if !quiet {
+        reader = reader.with_progress(Duration::from_secs(1), |x| {
+            eprintln!("processed {} rows ({} skipped), {:.0} rows/sec", x.processed, x.skipped, x.rows_per_sec());
+        });
+    }
+
+    let mut engine = open_engine(config).await?;
+    let paths = resolve_input_paths(&filepaths)?;
+    match merge_by {
+        MergeBy::File => {
+            for path in paths.iter() {
+                let stats = read_input(&reader, path, &mut engine).await?;
+                match stats {
+                    Some(stats) if !quiet && paths.len() > 1 => eprintln!("{}: {}", path.display(), stats),
+                    Some(stats) if !quiet => eprintln!("{}", stats),
+                    _ => {}
+                }
+            }
+        }
+        MergeBy::Tx => {
+            let stats = reader.read_files(&paths, MergeBy::Tx, &mut engine).await?;
+            if !quiet {
+                eprintln!("{}", stats);
+            }
+        }
+    }
+    match output {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("error creating output file {}", path))?;
+            writer.write(&mut engine, file).await?;
+        }
+        None => writer.write(&mut engine, io::stdout()).await?,
+    }
+    if let Some(path) = dump_transactions {
+        let file = File::create(path).with_context(|| format!("error creating transactions dump {}", path))?;
+        write_transactions(&mut engine, file).await?;
+    }
+    #[cfg(feature = "prometheus")]
+    if let Some(path) = matches.get_one::<String>("metrics-file") {
+        std::fs::write(path, transactions_engine::prometheus::render()).with_context(|| format!("error writing metrics file {}", path))?;
+    }
+
+    Ok(())
+}
+
+/// Parses the files and runs them through an engine over an in-memory copy of the storage, so the checks see the real state.
+async fn validate(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let filepaths: Vec<String> = matches.get_many("file").unwrap().cloned().collect();
+    let paths = resolve_input_paths(&filepaths)?;
+    let reader = csv_reader(matches)?;
+    let scratch = EchoDbStorage::new();
+    migrate(&open_config_storage(config).await?, &scratch, |_| {}).await.context("error copying the storage")?;
+    let mut engine = Engine::new(scratch).with_policy(config.engine.policy()?);
+
+    let mut problems = 0;
+    for path in paths.iter() {
+        for error in reader.validate(path, &mut engine).await? {
+            println!("{}: {}", path.display(), error);
+            problems += 1;
+        }
+    }
+    if problems > 0 {
+        bail!("found {} invalid rows", problems);
+    }
+    if !matches.get_flag("quiet") {
+        eprintln!("no problems found");
+    }
+    Ok(())
+}
+
+async fn inspect(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let engine = Engine::new(open_config_storage(config).await?);
+    let json = match matches.subcommand() {
+        Some(("account", matches)) => {
+            let acc_id: ClientId = *matches.get_one("id").unwrap();
+            let Some(report) = inspect_account(&engine, acc_id).await? else {
+                bail!("account {} not found", acc_id);
+            };
+            serde_json::to_string_pretty(&report)?
+        }
+        Some(("tx", matches)) => {
+            let tx_id: TxId = *matches.get_one("id").unwrap();
+            let Some(report) = inspect_tx(&engine, tx_id).await? else {
+                bail!("transaction {} not found", tx_id);
+            };
+            serde_json::to_string_pretty(&report)?
+        }
+        _ => unreachable!("an entity is required"),
+    };
+    println!("{}", json);
+    Ok(())
+}
+
+async fn aml_report(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let engine = Engine::new(open_config_storage(config).await?);
+    let aml_config = AmlConfig::new()
+        .with_threshold(*matches.get_one("threshold").unwrap())
+        .with_structuring_margin(*matches.get_one("structuring-margin").unwrap())
+        .with_structuring_count(*matches.get_one("structuring-count").unwrap())
+        .with_structuring_window_ms(*matches.get_one("structuring-window-ms").unwrap());
+    let report = engine.get_aml_report(aml_config).await?;
+    match matches.get_one::<String>("output-format").unwrap().as_str() {
+        "csv" => report.write_csv(io::stdout())?,
+        _ => println!("{}", report.to_json()),
+    }
+    Ok(())
+}
+
+async fn report(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let engine = Engine::new(open_config_storage(config).await?);
+    match matches.subcommand() {
+        Some(("disputes", matches)) => {
+            let mut filter = DisputeFilter::new();
+            if let Some(client) = matches.get_one::<ClientId>("client") {
+                filter = filter.with_client(*client);
+            }
+            if let Some(secs) = matches.get_one::<u64>("min-age-secs") {
+                filter = filter.with_min_age_ms(secs.saturating_mul(1000));
+            }
+            let report = engine.get_open_disputes(filter).await?;
+            match matches.get_one::<String>("output-format").unwrap().as_str() {
+                "json" => println!("{}", report.to_json()),
+                _ => report.write_csv(io::stdout())?,
+            }
+            if !matches.get_flag("quiet") {
+                eprintln!("{} open disputes, {} disputed", report.disputes.len(), report.amount);
+            }
+        }
+        Some(("chargebacks", matches)) => {
+            if let Some(client) = matches.get_one::<ClientId>("client") {
+                let stats = engine.get_chargeback_stats(*client).await?;
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                return Ok(());
+            }
+            let report = engine.get_chargeback_report(*matches.get_one("max-ratio").unwrap(), *matches.get_one("min-deposits").unwrap()).await?;
+            match matches.get_one::<String>("output-format").unwrap().as_str() {
+                "json" => println!("{}", report.to_json()),
+                _ => report.write_csv(io::stdout())?,
+            }
+            if !matches.get_flag("quiet") {
+                eprintln!("{} clients over a chargeback ratio of {}", report.accounts.len(), report.max_ratio);
+            }
+        }
+        _ => unreachable!("a report is required"),
+    }
+    Ok(())
+}
+
+async fn settle(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let date = matches.get_one::<String>("date").unwrap();
+    let (from, to) = settlement::day_range(date).with_context(|| format!("invalid date {}, expected YYYY-MM-DD", date))?;
+    let format = matches.get_one::<String>("output-format").unwrap();
+    let path = matches.get_one::<String>("output").cloned().unwrap_or_else(|| format!("settlement-{}.{}", date, format));
+    let engine = Engine::new(open_config_storage(config).await?);
+    let settlement = engine.get_settlement(from, to).await?;
+    let mut file = File::create(&path).with_context(|| format!("error creating settlement file {}", path))?;
+    match format.as_str() {
+        "json" => writeln!(file, "{}", settlement.to_json())?,
+        _ => settlement.write_csv(file)?,
+    }
+    if !matches.get_flag("quiet") {
+        eprintln!("settled {} accounts into {}, net {}", settlement.records.len(), path, settlement.net);
+    }
+    Ok(())
+}
+
+async fn dead_letters(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let store = open_dead_letters(matches.get_one::<String>("file").unwrap())?;
+    match matches.subcommand() {
+        Some(("list", _)) => println!("{}", serde_json::to_string_pretty(&store.list().await?)?),
+        Some(("replay", matches)) => {
+            let ids: Vec<u64> = matches.get_many::<u64>("id").unwrap_or_default().copied().collect();
+            let engine = Engine::new(open_config_storage(config).await?).with_policy(config.engine.policy()?);
+            let outcomes = replay_dead_letters(&engine, &store, &ids, RetryPolicy::default()).await?;
+            if !matches.get_flag("quiet") {
+                for (id, outcome) in outcomes.iter() {
+                    if let MessageOutcome::Rejected(operation, err) | MessageOutcome::Transient(operation, err) = outcome {
+                        eprintln!("dead letter {}: {}: {}", id, redact::operation(operation), redact::error(err));
+                    }
+                }
+                let applied = outcomes.iter().filter(|(_, x)| matches!(x, MessageOutcome::Applied(_))).count();
+                eprintln!("replayed {} dead letters, {} applied", outcomes.len(), applied);
+            }
+        }
+        _ => unreachable!("an action is required"),
+    }
+    Ok(())
+}
+
+fn open_dead_letters(path: &str) -> anyhow::Result<FileDeadLetterStore> {
+    FileDeadLetterStore::open(path).with_context(|| format!("error opening dead-letter file {}", path))
+}
+
+async fn verify_journal(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let expected_head = match matches.get_one::<String>("head") {
+        Some(head) => {
+            let (seq, digest) = head.split_once(':').context("the head must be `<seq>:<digest>`")?;
+            Some((seq.parse::<u64>()?, digest.parse::<Digest>()?))
+        }
+        None => None,
+    };
+    let engine = Engine::new(open_config_storage(config).await?);
+    let report = engine.verify_journal_chain(expected_head).await?;
+    let seq_violations = engine.verify_account_sequences().await?;
+    let divergences = engine.verify_journal().await?;
+    println!("{}:{}", report.head_seq, report.head_digest);
+    for violation in report.violations.iter() {
+        eprintln!("{:?}", violation);
+    }
+    for violation in seq_violations.iter() {
+        eprintln!("{:?}", violation);
+    }
+    for divergence in divergences.iter() {
+        eprintln!("{:?}", divergence);
+    }
+    if !report.violations.is_empty() || !seq_violations.is_empty() || !divergences.is_empty() {
+        bail!(
+            "the journal failed the verification: {} chain violations, {} sequence violations, {} divergences",
+            report.violations.len(),
+            seq_violations.len(),
+            divergences.len()
+        );
+    }
+    Ok(())
+}
+
+async fn migrate_storage(matches: &ArgMatches) -> anyhow::Result<()> {
+    let source = open_storage(matches.get_one::<String>("from").unwrap()).await?;
+    let target = open_storage(matches.get_one::<String>("to").unwrap()).await?;
+    let report = migrate(&source, &target, |x| eprintln!("{:?}: {} records", x.stage, x.processed)).await?;
+    eprintln!(
+        "migrated {} accounts, {} transactions, {} idempotency records and {} journal entries",
+        report.accounts, report.transactions, report.operations, report.journal_entries,
+    );
+    Ok(())
+}
+
+fn generate(matches: &ArgMatches) -> anyhow::Result<()> {
+    let generator = WorkloadGenerator::new()
+        .with_accounts(*matches.get_one("accounts").unwrap())
+        .with_dispute_ratio(*matches.get_one("dispute-ratio").unwrap())
+        .with_error_rate(*matches.get_one("error-rate").unwrap())
+        .with_seed(*matches.get_one("seed").unwrap());
+    let operations = generator.operations().take(*matches.get_one("operations").unwrap());
+    match matches.get_one::<String>("output") {
+        Some(path) => write_operations(File::create(path).with_context(|| format!("error creating {}", path))?, operations)?,
+        None => write_operations(io::stdout().lock(), operations)?,
+    }
+    Ok(())
+}
+
+async fn watch(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let dir: &String = matches.get_one("dir").unwrap();
+    let mut watcher = DirectoryWatcher::new(dir)
+        .with_poll_interval(Duration::from_millis(*matches.get_one::<u64>("poll-interval-ms").unwrap()))
+        .with_reader(csv_reader(matches)?);
+    if let Some(done_dir) = matches.get_one::<String>("done-dir") {
+        watcher = watcher.with_done_dir(done_dir);
+    }
+    if let Some(failed_dir) = matches.get_one::<String>("failed-dir") {
+        watcher = watcher.with_failed_dir(failed_dir);
+    }
+
+    let quiet = matches.get_flag("quiet");
+    let counter = Arc::new(OperationCounter::new());
+    let mut engine = Engine::new(open_config_storage(config).await?).with_policy(config.engine.policy()?).with_observer(counter.clone());
+    let on_file = |file: &WatchedFile| match &file.result {
+        Ok(stats) if !quiet => eprintln!("{}: {}", file.path.display(), stats),
+        Err(err) => eprintln!("{}: {:#}", file.path.display(), err),
+        _ => {}
+    };
+    watcher.run(&mut engine, on_file, shutdown::signal()).await?;
+    report_shutdown(&counter, quiet);
+    write_csv(&mut engine).await?;
+    Ok(())
+}
+
+async fn serve_tcp(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let listen = matches.get_one::<String>("listen").unwrap_or(&config.server.tcp_listen);
+    let server_config = LineServerConfig { max_line_length: *matches.get_one("max-line-length").unwrap(), ..Default::default() };
+    let counter = Arc::new(OperationCounter::new());
+    let mut engine = open_engine(config).await?.with_observer(counter.clone());
+    let expirer = spawn_hold_expirer(&engine, config);
+    if !matches.get_flag("quiet") {
+        eprintln!("listening on {}", listen);
+    }
+    transactions_engine::tcp::serve(engine.clone(), listen.as_str(), server_config, shutdown::signal()).await
+        .with_context(|| format!("error serving on {}", listen))?;
+    if let Some(expirer) = expirer {
+        expirer.abort();
+    }
+    report_shutdown(&counter, matches.get_flag("quiet"));
     write_csv(&mut engine).await?;
+    Ok(())
+}
+
+#[cfg(feature = "http")]
+async fn serve_http(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let listen = matches.get_one::<String>("listen").unwrap_or(&config.server.http_listen);
+    let counter = Arc::new(OperationCounter::new());
+    let engine = open_engine(config).await?.with_observer(counter.clone());
+    let expirer = spawn_hold_expirer(&engine, config);
+    if !matches.get_flag("quiet") {
+        eprintln!("listening on {}", listen);
+    }
+    transactions_engine::http::serve(engine, listen.as_str(), authenticator(matches)?, shutdown::signal()).await
+        .with_context(|| format!("error serving on {}", listen))?;
+    if let Some(expirer) = expirer {
+        expirer.abort();
+    }
+    report_shutdown(&counter, matches.get_flag("quiet"));
+    Ok(())
+}
 
+#[cfg(feature = "grpc")]
+async fn serve_grpc(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    let listen = matches.get_one::<String>("listen").unwrap_or(&config.server.grpc_listen);
+    let listen: std::net::SocketAddr = listen.parse().with_context(|| format!("invalid listen address {}", listen))?;
+    let counter = Arc::new(OperationCounter::new());
+    let engine = Engine::new(open_config_storage(config).await?).with_policy(config.engine.policy()?).with_observer(counter.clone());
+    let expirer = spawn_hold_expirer(&engine, config);
+    if !matches.get_flag("quiet") {
+        eprintln!("listening on {}", listen);
+    }
+    transactions_engine::grpc::serve(engine, listen, authenticator(matches)?, shutdown::signal()).await
+        .with_context(|| format!("error serving on {}", listen))?;
+    if let Some(expirer) = expirer {
+        expirer.abort();
+    }
+    report_shutdown(&counter, matches.get_flag("quiet"));
     Ok(())
 }
+
+#[cfg(feature = "nats")]
+async fn consume_nats(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    use transactions_engine::nats::{consume_jetstream, NatsConfig};
+
+    let mut nats_config = NatsConfig::new(
+        matches.get_one::<String>("url").unwrap(),
+        matches.get_one::<String>("stream").unwrap(),
+        matches.get_one::<String>("consumer").unwrap(),
+    );
+    if let Some(subject) = matches.get_one::<String>("subject") {
+        nats_config = nats_config.with_filter_subject(subject);
+    }
+    let quiet = matches.get_flag("quiet");
+    let counter = Arc::new(OperationCounter::new());
+    let mut engine = Engine::new(open_config_storage(config).await?).with_policy(config.engine.policy()?).with_observer(counter.clone());
+    let dead_letters = open_dead_letters(matches.get_one::<String>("dead-letter-file").unwrap())?;
+    consume_jetstream(&engine, &nats_config, &dead_letters, |x| report_message(x, quiet), shutdown::signal()).await?;
+    report_shutdown(&counter, quiet);
+    write_csv(&mut engine).await?;
+    Ok(())
+}
+
+#[cfg(feature = "amqp")]
+async fn consume_amqp(matches: &ArgMatches, config: &EngineConfig) -> anyhow::Result<()> {
+    use transactions_engine::amqp::{consume_queue, AmqpConfig, FailurePolicy};
+
+    let policy = FailurePolicy {
+        invalid: matches.get_one::<String>("on-invalid").unwrap().parse()?,
+        rejected: matches.get_one::<String>("on-rejected").unwrap().parse()?,
+        transient: matches.get_one::<String>("on-transient").unwrap().parse()?,
+    };
+    let amqp_config = AmqpConfig::new(matches.get_one::<String>("url").unwrap(), matches.get_one::<String>("queue").unwrap())
+        .with_prefetch(*matches.get_one("prefetch").unwrap())
+        .with_failure_policy(policy);
+    let quiet = matches.get_flag("quiet");
+    let counter = Arc::new(OperationCounter::new());
+    let mut engine = Engine::new(open_config_storage(config).await?).with_policy(config.engine.policy()?).with_observer(counter.clone());
+    let dead_letters = open_dead_letters(matches.get_one::<String>("dead-letter-file").unwrap())?;
+    consume_queue(&engine, &amqp_config, &dead_letters, |x| report_message(x, quiet), shutdown::signal()).await?;
+    report_shutdown(&counter, quiet);
+    write_csv(&mut engine).await?;
+    Ok(())
+}
+
+/// Prints the operations executed since the start to stderr, once a long-running mode has finished the work in flight.
+fn report_shutdown(counter: &OperationCounter, quiet: bool) {
+    if !quiet {
+        eprintln!("shutting down, {}", counter.counts());
+    }
+}
+
+/// Prints the messages of a queue that were not applied to stderr.
+#[cfg(any(feature = "nats", feature = "amqp"))]
+fn report_message(outcome: &MessageOutcome, quiet: bool) {
+    match outcome {
+        MessageOutcome::Applied(_) => {}
+        MessageOutcome::Rejected(operation, err) | MessageOutcome::Transient(operation, err) if !quiet => eprintln!("{}: {}", redact::operation(operation), redact::error(err)),
+        MessageOutcome::Invalid(err) if !quiet => eprintln!("invalid message: {}", redact::text(err)),
+        _ => {}
+    }
+}
+
+/// Installs the global `tracing` subscriber writing the engine and storage spans and events to stderr.
+fn init_logging(matches: &ArgMatches) -> anyhow::Result<()> {
+    let filter = match matches.get_one::<String>("log-level") {
+        Some(level) => EnvFilter::try_new(level).with_context(|| format!("invalid log level {}", level))?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+    };
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(io::stderr);
+    let result = match matches.get_one::<String>("log-format").unwrap().as_str() {
+        "json" => builder.json().try_init(),
+        _ => builder.try_init(),
+    };
+    result.map_err(|e| anyhow::anyhow!("error installing the logger: {}", e))
+}
+
+/// Loads the config file and the environment variables, then applies the global flags on top of them.
+fn load_config(matches: &ArgMatches) -> anyhow::Result<EngineConfig> {
+    let path = matches.get_one::<PathBuf>("config").cloned().or_else(|| std::env::var_os("TRANSACTIONS_ENGINE_CONFIG").map(PathBuf::from));
+    let mut config = EngineConfig::load(path.as_deref(), matches.get_one::<String>("tenant").map(String::as_str))?;
+    if let Some(storage) = matches.get_one::<String>("storage") {
+        config.storage = storage.clone();
+    }
+    if let Some(secs) = matches.get_one::<u64>("dispute-window-secs") {
+        config.engine.dispute_window_secs = Some(*secs);
+    }
+    if let Some(secs) = matches.get_one::<u64>("authorization-expiry-secs") {
+        config.engine.authorization_expiry_secs = Some(*secs);
+    }
+    if let Some(lock) = matches.get_one::<bool>("lock-on-chargeback") {
+        config.engine.lock_on_chargeback = *lock;
+    }
+    if let Some(decimals) = matches.get_one::<u32>("decimals") {
+        config.engine.decimals = *decimals;
+    }
+    if let Some(rounding) = matches.get_one::<String>("rounding") {
+        config.engine.rounding = rounding.clone();
+    }
+    if let Some(max_amount) = matches.get_one::<String>("max-amount") {
+        config.engine.max_amount = Some(max_amount.parse().with_context(|| format!("invalid max amount {}", max_amount))?);
+    }
+    if let Some(max_balance) = matches.get_one::<String>("max-balance") {
+        config.engine.max_balance = Some(max_balance.parse().with_context(|| format!("invalid max balance {}", max_balance))?);
+    }
+    if let Some(fee) = matches.get_one::<String>("withdrawal-fee") {
+        config.engine.withdrawal_fee = Some(fee.parse().with_context(|| format!("invalid withdrawal fee {}", fee))?);
+    }
+    if let Some(write_off) = matches.get_one::<bool>("write-off-chargeback-losses") {
+        config.engine.write_off_chargeback_losses = *write_off;
+    }
+    if let Some(negative_available) = matches.get_one::<String>("negative-available") {
+        config.engine.negative_available = negative_available.clone();
+    }
+    if let Some(auto_unlock) = matches.get_one::<bool>("auto-unlock") {
+        config.engine.auto_unlock = *auto_unlock;
+    }
+    if let Some(cache_rejections) = matches.get_one::<bool>("cache-rejections") {
+        config.engine.cache_rejections = *cache_rejections;
+    }
+    if matches.get_flag("redact-pii") {
+        config.redact_pii = true;
+    }
+    config.engine.policy()?;
+    Ok(config)
+}
+
+/// Opens the storage of the config, scoped to the namespace of its tenant (if any).
+async fn open_config_storage(config: &EngineConfig) -> anyhow::Result<Box<dyn DynStorage>> {
+    match &config.tenant {
+        Some(tenant) => open_tenant_storage(&config.storage, tenant).await,
+        None => open_storage(&config.storage).await,
+    }
+}
+
+/// Spawns the task releasing the expired authorizations of a server, when the config sets an authorization expiry.
+fn spawn_hold_expirer(engine: &Engine<Box<dyn DynStorage>>, config: &EngineConfig) -> Option<JoinHandle<()>> {
+    config.engine.authorization_expiry_secs.map(|_| engine.spawn_hold_expirer(HOLD_EXPIRY_INTERVAL))
+}
+
+/// Opens the engine of `process`, `tcp` and `serve` with the storage and the policy of the config. With the `prometheus`
+/// feature, the storage calls and the operations are recorded, and the held funds and locked accounts gauges start from
+/// the state of the storage.
+async fn open_engine(config: &EngineConfig) -> anyhow::Result<Engine<Box<dyn DynStorage>>> {
+    #[cfg(feature = "prometheus")]
+    {
+        use transactions_engine::engine_metrics::{record_state_gauges, MetricsObserver};
+        use transactions_engine::metered_storage::MeteredStorage;
+
+        transactions_engine::prometheus::install()?;
+        let backend = match config.storage.as_str() {
+            "memory" => "memory",
+            x if x.starts_with("file:") => "file",
+            _ => "sqlite",
+        };
+        let metered: Box<dyn DynStorage> = Box::new(MeteredStorage::new(open_config_storage(config).await?, backend));
+        let engine = Engine::new(metered).with_policy(config.engine.policy()?).with_observer(Arc::new(MetricsObserver));
+        record_state_gauges(&engine).await?;
+        Ok(engine)
+    }
+    #[cfg(not(feature = "prometheus"))]
+    Ok(Engine::new(open_config_storage(config).await?).with_policy(config.engine.policy()?))
+}
+
+/// Builds the CSV reader from the input format options shared by all the commands.
+fn csv_reader(matches: &ArgMatches) -> anyhow::Result<CsvReader> {
+    let delimiter = match matches.get_one::<String>("delimiter").unwrap().as_str() {
+        "tab" | "\\t" => b'\t',
+        x if x.len() == 1 => x.as_bytes()[0],
+        x => bail!("the delimiter must be a single character: {}", x),
+    };
+    let mut reader = CsvReader::new()
+        .with_strict(matches.get_flag("strict"))
+        .with_delimiter(delimiter)
+        .with_amount_format(matches.get_one::<String>("amount-format").unwrap().parse::<AmountFormat>()?);
+    for alias in matches.get_many::<String>("column-alias").into_iter().flatten() {
+        let Some((header, column)) = alias.split_once('=') else {
+            bail!("the column alias must be in the `header=column` format: {}", alias);
+        };
+        reader = reader.with_column_alias(header, column);
+    }
+    for alias in matches.get_many::<String>("type-alias").into_iter().flatten() {
+        let Some((name, op_type)) = alias.split_once('=') else {
+            bail!("the type alias must be in the `name=type` format: {}", alias);
+        };
+        let op_type: OperationType = op_type.parse().with_context(|| format!("unknown operation type in {}", alias))?;
+        reader = reader.with_type_alias(name, op_type);
+    }
+    Ok(reader)
+}
+
+/// Reads a CSV or Parquet file, the statistics are only collected for CSV files.
+async fn read_input(reader: &CsvReader, path: &Path, engine: &mut Engine<Box<dyn DynStorage>>) -> anyhow::Result<Option<IngestStats>> {
+    #[cfg(feature = "parquet")]
+    if path.extension().is_some_and(|x| x == "parquet") {
+        let report = transactions_engine::parquet_reader::read_parquet(path, engine).await?;
+        for error in report.row_group_errors.iter() {
+            eprintln!("row group {}: {} rows skipped, first error: {}", error.row_group, error.failed_rows, error.first_error);
+        }
+        if reader.is_strict() && !report.row_group_errors.is_empty() {
+            bail!("error processing {}: {} row groups have invalid rows", path.display(), report.row_group_errors.len());
+        }
+        return Ok(None);
+    }
+    Ok(Some(reader.read(path, engine).await?))
+}
+
+/// The authenticator of `--auth-config`, if any.
+#[cfg(any(feature = "http", feature = "grpc"))]
+fn authenticator(matches: &ArgMatches) -> anyhow::Result<Option<Arc<transactions_engine::auth::Authenticator>>> {
+    let Some(path) = matches.get_one::<String>("auth-config") else {
+        return Ok(None);
+    };
+    Ok(Some(Arc::new(transactions_engine::auth::Authenticator::from_file(path)?)))
+}
@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use futures::lock::{Mutex, OwnedMutexGuard};
+
+use crate::account::{Account, ClientId, SystemAccount};
+#[cfg(target_arch = "wasm32")]
+use crate::engine::Engine;
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, Savepoint, Savepoints, Storage, TxOptions};
+use crate::transaction::{Transaction, TxId};
+
+#[derive(Default)]
+struct Tables {
+    txs: BTreeMap<TxId, Transaction>,
+    acc_txs: BTreeMap<(ClientId, TxId), ()>,
+    external_ids: BTreeMap<String, TxId>,
+    hold_expiries: BTreeMap<(u64, TxId), ()>,
+    accounts: BTreeMap<ClientId, Account>,
+    system_accounts: BTreeMap<SystemAccount, Account>,
+    operations: BTreeMap<u64, u64>,
+    rejections: BTreeMap<u64, (u16, u64)>,
+    checkpoints: BTreeMap<String, u64>,
+    journal: BTreeMap<u64, JournalEntry>,
+}
+
+type Table<K, V> = fn(&mut Tables) -> &mut BTreeMap<K, V>;
+
+type Undo = Box<dyn FnOnce(&mut Tables) + Send>;
+
+/// A plain in-memory storage without dependencies on a database or a runtime, so the core builds for
+/// `wasm32-unknown-unknown` (where `EchoDbStorage` is not available), e.g. for browser-based simulators.
+///
+/// The records are kept as values in ordered maps. Like in `EchoDbStorage`, the storage transactions are serialized by a
+/// lock held until the commit, and the writes of a transaction dropped without a commit are rolled back.
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    tables: Arc<Mutex<Tables>>,
+}
+
+/// A storage transaction of [`MemoryStorage`]: the locked tables and the undo log of the writes.
+pub struct MemoryDbTx {
+    tables: OwnedMutexGuard<Tables>,
+    undo: Vec<Undo>,
+    savepoints: Savepoints<usize>, // NOTE: the length of the undo log at each savepoint
+}
+
+// NOTE: `EchoDbStorage` is the default storage of the other targets
+#[cfg(target_arch = "wasm32")]
+impl Default for Engine<MemoryStorage> {
+    fn default() -> Self {
+        Self::new(MemoryStorage::default())
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MemoryDbTx {
+    /// Writes (`Some`) or removes (`None`) the record and logs how to restore the previous one.
+    fn write<K: Ord + Clone + Send + 'static, V: Send + 'static>(&mut self, table: Table<K, V>, key: K, value: Option<V>) {
+        let previous = match value {
+            Some(value) => table(&mut self.tables).insert(key.clone(), value),
+            None => table(&mut self.tables).remove(&key),
+        };
+        self.undo.push(Box::new(move |tables| {
+            match previous {
+                Some(previous) => table(tables).insert(key, previous),
+                None => table(tables).remove(&key),
+            };
+        }));
+    }
+}
+
+impl Drop for MemoryDbTx {
+    fn drop(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            undo(&mut self.tables);
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    type DbTx = MemoryDbTx;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        Ok(db_tx.tables.txs.get(&tx_id).cloned())
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        if db_tx.tables.txs.contains_key(&tx.id()) || tx.external_id().is_some_and(|x| db_tx.tables.external_ids.contains_key(x)) {
+            return Err(DbError::EntityAlreadyExists);
+        }
+        if let Some(external_id) = tx.external_id() {
+            db_tx.write(|x| &mut x.external_ids, external_id.to_string(), Some(tx.id()));
+        }
+        if let Some(expires_at) = tx.hold_expires_at() {
+            db_tx.write(|x| &mut x.hold_expiries, (expires_at, tx.id()), Some(()));
+        }
+        db_tx.write(|x| &mut x.acc_txs, (tx.account_id(), tx.id()), Some(()));
+        db_tx.write(|x| &mut x.txs, tx.id(), Some(tx.clone()));
+        Ok(())
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        if db_tx.tables.txs.get(&old_tx.id()) != Some(old_tx) {
+            return Err(DbError::ConcurrentModification);
+        }
+        if old_tx.hold_expires_at() != new_tx.hold_expires_at() {
+            if let Some(expires_at) = old_tx.hold_expires_at() {
+                db_tx.write(|x| &mut x.hold_expiries, (expires_at, old_tx.id()), None);
+            }
+            if let Some(expires_at) = new_tx.hold_expires_at() {
+                db_tx.write(|x| &mut x.hold_expiries, (expires_at, new_tx.id()), Some(()));
+            }
+        }
+        db_tx.write(|x| &mut x.txs, old_tx.id(), Some(new_tx.clone()));
+        Ok(())
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        Ok(db_tx.tables.txs.values().cloned().collect())
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        Ok(tx_ids.iter().map(|x| db_tx.tables.txs.get(x).cloned()).collect())
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        for tx in txs {
+            self.insert_tx(db_tx, tx).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        let mut deleted = 0;
+        for tx_id in tx_ids {
+            if let Some(tx) = db_tx.tables.txs.get(tx_id).cloned() {
+                db_tx.write(|x| &mut x.txs, tx.id(), None);
+                db_tx.write(|x| &mut x.acc_txs, (tx.account_id(), tx.id()), None);
+                if let Some(external_id) = tx.external_id() {
+                    db_tx.write(|x| &mut x.external_ids, external_id.to_string(), None);
+                }
+                if let Some(expires_at) = tx.hold_expires_at() {
+                    db_tx.write(|x| &mut x.hold_expiries, (expires_at, tx.id()), None);
+                }
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        let tables = &db_tx.tables;
+        let tx_ids = tables.acc_txs.range((acc_id, 0)..=(acc_id, TxId::MAX)).map(|((_, tx_id), _)| *tx_id);
+        tx_ids
+            .filter(|x| cursor.is_none_or(|cursor| *x > cursor))
+            .take(limit)
+            .map(|x| tables.txs.get(&x).cloned().ok_or_else(|| DbError::DatabaseError(format!("Dangling index entry: {}", x))))
+            .collect()
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        Ok(db_tx.tables.external_ids.get(external_id).and_then(|x| db_tx.tables.txs.get(x)).cloned())
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        let tables = &db_tx.tables;
+        tables.hold_expiries.range(..=(now, TxId::MAX))
+            .map(|((_, x), _)| tables.txs.get(x).cloned().ok_or_else(|| DbError::DatabaseError(format!("Dangling index entry: {}", x))))
+            .collect()
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        Ok(db_tx.tables.accounts.get(&acc_id).cloned())
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        Ok(db_tx.tables.accounts.values().cloned().collect())
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        Ok(acc_ids.iter().map(|x| db_tx.tables.accounts.get(x).cloned()).collect())
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        for acc in accs {
+            self.insert_account(db_tx, acc).await?;
+        }
+        Ok(())
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        let accounts = db_tx.tables.accounts.values().filter(|x| cursor.is_none_or(|cursor| x.id() > cursor));
+        Ok(accounts.take(limit).cloned().collect())
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        if db_tx.tables.accounts.contains_key(&acc.id()) {
+            return Err(DbError::EntityAlreadyExists);
+        }
+        db_tx.write(|x| &mut x.accounts, acc.id(), Some(acc.clone()));
+        Ok(())
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        if db_tx.tables.accounts.get(&old_acc.id()) != Some(old_acc) {
+            return Err(DbError::ConcurrentModification);
+        }
+        db_tx.write(|x| &mut x.accounts, old_acc.id(), Some(new_acc.clone()));
+        Ok(())
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        Ok(db_tx.tables.system_accounts.get(&account).cloned())
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        db_tx.write(|x| &mut x.system_accounts, account, Some(acc.clone()));
+        Ok(())
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        Ok(db_tx.tables.operations.contains_key(&op_hash))
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        if db_tx.tables.operations.contains_key(&op_hash) {
+            return Err(DbError::EntityAlreadyExists);
+        }
+        db_tx.write(|x| &mut x.operations, op_hash, Some(timestamp));
+        Ok(())
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        Ok(db_tx.tables.operations.keys().copied().collect())
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        let pruned: Vec<u64> = db_tx.tables.operations.iter().filter(|(_, timestamp)| **timestamp < older_than).map(|(op_hash, _)| *op_hash).collect();
+        for op_hash in pruned.iter() {
+            db_tx.write(|x| &mut x.operations, *op_hash, None);
+        }
+        let rejections: Vec<u64> = db_tx.tables.rejections.iter().filter(|(_, (_, timestamp))| *timestamp < older_than).map(|(op_hash, _)| *op_hash).collect();
+        for op_hash in rejections.iter() {
+            db_tx.write(|x| &mut x.rejections, *op_hash, None);
+        }
+        Ok(pruned.len() + rejections.len())
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        Ok(db_tx.tables.rejections.get(&op_hash).map(|(code, _)| *code))
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        db_tx.write(|x| &mut x.rejections, op_hash, Some((code, timestamp)));
+        Ok(())
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        Ok(db_tx.tables.checkpoints.get(source).copied())
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        db_tx.write(|x| &mut x.checkpoints, source.to_string(), Some(rows));
+        Ok(())
+    }
+
+    async fn start_db_tx(&self, _options: TxOptions) -> Result<Self::DbTx, DbError> {
+        // NOTE: the tables are locked whatever the options, the transactions are serialized
+        Ok(MemoryDbTx { tables: self.tables.clone().lock_owned().await, undo: Vec::new(), savepoints: Savepoints::default() })
+    }
+
+    async fn commit_db_tx(&self, mut db_tx: Self::DbTx) -> Result<(), DbError> {
+        db_tx.undo.clear();
+        Ok(())
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        let savepoint = db_tx.savepoints.next();
+        db_tx.savepoints.insert(savepoint, db_tx.undo.len());
+        Ok(savepoint)
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        let undo_len = *db_tx.savepoints.rollback_to(savepoint)?;
+        while db_tx.undo.len() > undo_len {
+            if let Some(undo) = db_tx.undo.pop() {
+                undo(&mut db_tx.tables);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Journal for MemoryStorage {
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        Ok(db_tx.tables.journal.last_key_value().map_or(0, |(seq, _)| *seq))
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        if db_tx.tables.journal.contains_key(&entry.seq()) {
+            return Err(DbError::EntityAlreadyExists);
+        }
+        db_tx.write(|x| &mut x.journal, entry.seq(), Some(entry.clone()));
+        Ok(())
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        Ok(db_tx.tables.journal.range(from_seq..).take(limit).map(|(_, entry)| entry.clone()).collect())
+    }
+}
+
+#[cfg(test)]
+mod memory_storage_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::{Engine, EngineError, Operation};
+    use crate::transaction::{TransactionState, TransactionType};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn dispute_lifecycle() {
+        let engine = Engine::new(MemoryStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(5)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(20)).await, Err(EngineError::InsufficientFunds));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.held(), acc.locked()), (Decimal4::from(5), Decimal4::zero(), true));
+        assert_eq!(engine.get_txs_by_account(1, None, 10).await.unwrap().len(), 2);
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn expired_authorizations_index() {
+        let storage = MemoryStorage::new();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let authorization = |tx_id, expires_at| Transaction::new(tx_id, 1, TransactionType::Authorization, Decimal4::from(1)).with_expires_at(expires_at);
+        let txs = [authorization(1, Some(300)), authorization(2, Some(100)), authorization(3, Some(200)), authorization(4, None)];
+        storage.insert_txs(&mut db_tx, &txs).await.unwrap();
+        storage.insert_tx(&mut db_tx, &Transaction::new(5, 1, TransactionType::Deposit, Decimal4::from(1))).await.unwrap();
+        let expired = |txs: Vec<Transaction>| txs.iter().map(Transaction::id).collect::<Vec<_>>();
+        assert_eq!(storage.get_expired_authorizations(&mut db_tx, 200).await.map(expired), Ok(vec![2, 3]));
+
+        let mut captured = txs[1].clone();
+        captured.transition(TransactionState::Captured, Operation::Capture { acc_id: 1, tx_id: 2 }, 150).unwrap();
+        storage.update_tx(&mut db_tx, &txs[1], &captured).await.unwrap();
+        assert_eq!(storage.delete_txs(&mut db_tx, &[3]).await, Ok(1));
+        assert_eq!(storage.get_expired_authorizations(&mut db_tx, 200).await.map(expired), Ok(vec![]));
+        assert_eq!(storage.get_expired_authorizations(&mut db_tx, u64::MAX).await.map(expired), Ok(vec![1]));
+    }
+
+    #[tokio::test]
+    async fn uncommitted_writes_are_rolled_back() {
+        let storage = MemoryStorage::new();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        storage.insert_account(&mut db_tx, &Account::new(1)).await.unwrap();
+        assert_eq!(storage.insert_account(&mut db_tx, &Account::new(1)).await, Err(DbError::EntityAlreadyExists));
+        drop(db_tx);
+
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_account(&mut db_tx, 1).await, Ok(None));
+        storage.insert_account(&mut db_tx, &Account::new(2)).await.unwrap();
+        storage.commit_db_tx(db_tx).await.unwrap();
+
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let mut stale = Account::new(2);
+        stale.deposit(Decimal4::from(1)).unwrap();
+        assert_eq!(storage.update_account(&mut db_tx, &stale, &Account::new(2)).await, Err(DbError::ConcurrentModification));
+        assert_eq!(storage.get_all_accounts(&mut db_tx).await, Ok(vec![Account::new(2)]));
+    }
+
+    #[tokio::test]
+    async fn rollback_to_savepoint() {
+        let storage = MemoryStorage::new();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        storage.insert_account(&mut db_tx, &Account::new(1)).await.unwrap();
+        let first = storage.savepoint(&mut db_tx).await.unwrap();
+        let mut updated = Account::new(1);
+        updated.deposit(Decimal4::from(10)).unwrap();
+        storage.update_account(&mut db_tx, &Account::new(1), &updated).await.unwrap();
+        let second = storage.savepoint(&mut db_tx).await.unwrap();
+        storage.insert_account(&mut db_tx, &Account::new(2)).await.unwrap();
+
+        assert_eq!(storage.rollback_to(&mut db_tx, first).await, Ok(()));
+        assert_eq!(storage.get_all_accounts(&mut db_tx).await, Ok(vec![Account::new(1)]));
+        assert_eq!(storage.rollback_to(&mut db_tx, second).await, Err(DbError::DatabaseError("Unknown savepoint: 1".to_string())));
+        storage.insert_account(&mut db_tx, &Account::new(3)).await.unwrap();
+        assert_eq!(storage.rollback_to(&mut db_tx, first).await, Ok(()));
+        storage.commit_db_tx(db_tx).await.unwrap();
+
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_all_accounts(&mut db_tx).await, Ok(vec![Account::new(1)]));
+    }
+}
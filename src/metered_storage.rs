@@ -0,0 +1,229 @@
+use std::future::Future;
+use std::time::Instant;
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, Savepoint, Storage, TxOptions};
+use crate::transaction::{Transaction, TxId};
+
+/// Latency histogram (seconds) of every storage call, labeled with `backend` and `operation`.
+pub const STORAGE_DURATION_METRIC: &str = "storage_operation_duration_seconds";
+/// Counter of storage calls, labeled with `backend`, `operation` and `result` (`ok` / `error`).
+pub const STORAGE_CALLS_METRIC: &str = "storage_operations_total";
+
+/// Storage decorator that records the latency and outcome of every call via the `metrics` facade.
+/// Install any `metrics` recorder (e.g. a Prometheus exporter) to collect the values.
+pub struct MeteredStorage<S> {
+    inner: S,
+    backend: &'static str,
+}
+
+impl<S> MeteredStorage<S> {
+    pub fn new(inner: S, backend: &'static str) -> Self {
+        Self { inner, backend }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    async fn measure<T>(&self, operation: &'static str, call: impl Future<Output = Result<T, DbError>>) -> Result<T, DbError> {
+        let started_at = Instant::now();
+        let result = call.await;
+        metrics::histogram!(STORAGE_DURATION_METRIC, "backend" => self.backend, "operation" => operation).record(started_at.elapsed().as_secs_f64());
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        metrics::counter!(STORAGE_CALLS_METRIC, "backend" => self.backend, "operation" => operation, "result" => outcome).increment(1);
+        result
+    }
+}
+
+impl<S> Storage for MeteredStorage<S>
+where
+    S: Storage + Sync,
+    S::DbTx: Send,
+{
+    type DbTx = S::DbTx;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        self.measure("get_tx", self.inner.get_tx(db_tx, tx_id)).await
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        self.measure("insert_tx", self.inner.insert_tx(db_tx, tx)).await
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        self.measure("update_tx", self.inner.update_tx(db_tx, old_tx, new_tx)).await
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        self.measure("get_all_txs", self.inner.get_all_txs(db_tx)).await
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        self.measure("get_txs", self.inner.get_txs(db_tx, tx_ids)).await
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        self.measure("insert_txs", self.inner.insert_txs(db_tx, txs)).await
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        self.measure("delete_txs", self.inner.delete_txs(db_tx, tx_ids)).await
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        self.measure("get_txs_by_account", self.inner.get_txs_by_account(db_tx, acc_id, cursor, limit)).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        self.measure("get_tx_by_external_id", self.inner.get_tx_by_external_id(db_tx, external_id)).await
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        self.measure("get_expired_authorizations", self.inner.get_expired_authorizations(db_tx, now)).await
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        self.measure("get_account", self.inner.get_account(db_tx, acc_id)).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        self.measure("get_all_accounts", self.inner.get_all_accounts(db_tx)).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        self.measure("get_accounts", self.inner.get_accounts(db_tx, acc_ids)).await
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        self.measure("insert_accounts", self.inner.insert_accounts(db_tx, accs)).await
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        self.measure("list_accounts", self.inner.list_accounts(db_tx, cursor, limit)).await
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        self.measure("insert_account", self.inner.insert_account(db_tx, acc)).await
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        self.measure("update_account", self.inner.update_account(db_tx, old_acc, new_acc)).await
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        self.measure("get_system_account", self.inner.get_system_account(db_tx, account)).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        self.measure("set_system_account", self.inner.set_system_account(db_tx, account, acc)).await
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        self.measure("is_operation_processed", self.inner.is_operation_processed(db_tx, op_hash)).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        self.measure("insert_operation", self.inner.insert_operation(db_tx, op_hash, timestamp)).await
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        self.measure("get_all_operations", self.inner.get_all_operations(db_tx)).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        self.measure("prune_operations", self.inner.prune_operations(db_tx, older_than)).await
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        self.measure("get_operation_rejection", self.inner.get_operation_rejection(db_tx, op_hash)).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        self.measure("insert_operation_rejection", self.inner.insert_operation_rejection(db_tx, op_hash, code, timestamp)).await
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        self.measure("get_checkpoint", self.inner.get_checkpoint(db_tx, source)).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        self.measure("set_checkpoint", self.inner.set_checkpoint(db_tx, source, rows)).await
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        self.measure("start_db_tx", self.inner.start_db_tx(options)).await
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        self.measure("commit_db_tx", self.inner.commit_db_tx(db_tx)).await
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        self.measure("savepoint", self.inner.savepoint(db_tx)).await
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        self.measure("rollback_to", self.inner.rollback_to(db_tx, savepoint)).await
+    }
+}
+
+impl<S> Journal for MeteredStorage<S>
+where
+    S: Journal + Sync,
+    S::DbTx: Send,
+{
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        self.measure("get_last_journal_seq", self.inner.get_last_journal_seq(db_tx)).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        self.measure("append_journal_entry", self.inner.append_journal_entry(db_tx, entry)).await
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        self.measure("get_journal_entries", self.inner.get_journal_entries(db_tx, from_seq, limit)).await
+    }
+}
+
+#[cfg(test)]
+mod metered_storage_tests {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    use crate::decimal::Decimal4;
+    use crate::engine::{Engine, EngineError};
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[test]
+    fn storage_calls_are_recorded() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+            runtime.block_on(async {
+                let engine = Engine::new(MeteredStorage::new(EchoDbStorage::new(), "echodb"));
+                assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+                assert_eq!(engine.deposit(2, 1, Decimal4::from(100)).await, Err(EngineError::TransactionWithTheSameIdAlreadyExists));
+            });
+        });
+
+        let metrics = snapshotter.snapshot().into_vec();
+        let counter = |operation: &str, result: &str| metrics.iter()
+            .filter(|(key, _, _, _)| key.kind() == MetricKind::Counter && key.key().name() == STORAGE_CALLS_METRIC)
+            .filter(|(key, _, _, _)| key.key().labels().any(|x| x.key() == "operation" && x.value() == operation))
+            .filter(|(key, _, _, _)| key.key().labels().any(|x| x.key() == "result" && x.value() == result))
+            .map(|(_, _, _, value)| match value {
+                DebugValue::Counter(x) => *x,
+                _ => 0,
+            })
+            .sum::<u64>();
+        assert_eq!(counter("get_tx", "ok"), 2);
+        assert_eq!(counter("insert_tx", "ok"), 1);
+        assert_eq!(counter("commit_db_tx", "ok"), 1);
+        assert!(metrics.iter().any(|(key, _, _, _)| key.kind() == MetricKind::Histogram && key.key().name() == STORAGE_DURATION_METRIC));
+    }
+}
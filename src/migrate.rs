@@ -0,0 +1,250 @@
+use std::collections::BTreeSet;
+
+use thiserror::Error;
+
+use crate::account::{ClientId, SystemAccount};
+use crate::clock::now_millis;
+use crate::journal::Journal;
+use crate::storage::{DbError, Storage, TxOptions};
+use crate::transaction::TxId;
+
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationStage {
+    Accounts,
+    Transactions,
+    Operations,
+    Journal,
+    Verification,
+}
+
+/// Reported after every copied (or verified) page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationProgress {
+    pub stage: MigrationStage,
+    /// Records processed so far in the current stage.
+    pub processed: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub accounts: usize,
+    pub transactions: usize,
+    pub operations: usize,
+    pub journal_entries: usize,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MigrationError {
+    #[error("target storage is not empty")]
+    TargetNotEmpty,
+
+    #[error("verification failed: {0} records differ between the source and the target")]
+    VerificationFailed(usize),
+
+    #[error(transparent)]
+    DbError(#[from] DbError),
+}
+
+/// Copies all the accounts (the system accounts too), transactions, idempotency records and journal entries from `source` into the empty `target`,
+/// page by page (every page is committed separately), then reads everything back to verify the copy.
+///
+/// Transactions are copied account by account, and the idempotency records are stamped with the migration time,
+/// so their retention starts over.
+pub async fn migrate<S, T>(source: &S, target: &T, mut progress: impl FnMut(MigrationProgress)) -> Result<MigrationReport, MigrationError>
+where
+    S: Storage + Journal,
+    T: Storage + Journal,
+{
+    let mut db_tx = target.start_db_tx(TxOptions::read_only()).await?;
+    let target_is_empty = target.list_accounts(&mut db_tx, None, 1).await?.is_empty()
+        && target.get_all_operations(&mut db_tx).await?.is_empty()
+        && target.get_last_journal_seq(&mut db_tx).await? == 0;
+    drop(db_tx);
+    if !target_is_empty {
+        return Err(MigrationError::TargetNotEmpty);
+    }
+
+    let mut report = MigrationReport::default();
+    let mut acc_ids = Vec::new();
+    let mut cursor = None;
+    loop {
+        let mut src_tx = source.start_db_tx(TxOptions::read_only()).await?;
+        let accounts = source.list_accounts(&mut src_tx, cursor, PAGE_SIZE).await?;
+        drop(src_tx);
+        let Some(last) = accounts.last() else { break };
+        cursor = Some(last.id());
+
+        let mut db_tx = target.start_db_tx(TxOptions::read_write()).await?;
+        target.insert_accounts(&mut db_tx, &accounts).await?;
+        target.commit_db_tx(db_tx).await?;
+        acc_ids.extend(accounts.iter().map(|x| x.id()));
+        report.accounts += accounts.len();
+        progress(MigrationProgress { stage: MigrationStage::Accounts, processed: report.accounts });
+    }
+    for account in SystemAccount::ALL {
+        let mut src_tx = source.start_db_tx(TxOptions::read_only()).await?;
+        let acc = source.get_system_account(&mut src_tx, account).await?;
+        drop(src_tx);
+        if let Some(acc) = acc {
+            let mut db_tx = target.start_db_tx(TxOptions::read_write()).await?;
+            target.set_system_account(&mut db_tx, account, &acc).await?;
+            target.commit_db_tx(db_tx).await?;
+        }
+    }
+
+    for acc_id in acc_ids.iter().copied() {
+        let mut cursor = None;
+        loop {
+            let mut src_tx = source.start_db_tx(TxOptions::read_only()).await?;
+            let txs = source.get_txs_by_account(&mut src_tx, acc_id, cursor, PAGE_SIZE).await?;
+            drop(src_tx);
+            let Some(last) = txs.last() else { break };
+            cursor = Some(last.id());
+
+            let mut db_tx = target.start_db_tx(TxOptions::read_write()).await?;
+            target.insert_txs(&mut db_tx, &txs).await?;
+            target.commit_db_tx(db_tx).await?;
+            report.transactions += txs.len();
+            progress(MigrationProgress { stage: MigrationStage::Transactions, processed: report.transactions });
+        }
+    }
+
+    let mut src_tx = source.start_db_tx(TxOptions::read_only()).await?;
+    let operations = source.get_all_operations(&mut src_tx).await?;
+    drop(src_tx);
+    let migrated_at = now_millis();
+    for chunk in operations.chunks(PAGE_SIZE) {
+        let mut db_tx = target.start_db_tx(TxOptions::read_write()).await?;
+        for op_hash in chunk {
+            target.insert_operation(&mut db_tx, *op_hash, migrated_at).await?;
+        }
+        target.commit_db_tx(db_tx).await?;
+        report.operations += chunk.len();
+        progress(MigrationProgress { stage: MigrationStage::Operations, processed: report.operations });
+    }
+
+    let mut from_seq = 0;
+    loop {
+        let mut src_tx = source.start_db_tx(TxOptions::read_only()).await?;
+        let entries = source.get_journal_entries(&mut src_tx, from_seq, PAGE_SIZE).await?;
+        drop(src_tx);
+        let Some(last) = entries.last() else { break };
+        from_seq = last.seq() + 1;
+
+        let mut db_tx = target.start_db_tx(TxOptions::read_write()).await?;
+        for entry in entries.iter() {
+            target.append_journal_entry(&mut db_tx, entry).await?;
+        }
+        target.commit_db_tx(db_tx).await?;
+        report.journal_entries += entries.len();
+        progress(MigrationProgress { stage: MigrationStage::Journal, processed: report.journal_entries });
+    }
+
+    let mismatches = verify(source, target, &acc_ids, &operations, &mut progress).await?;
+    if mismatches > 0 {
+        return Err(MigrationError::VerificationFailed(mismatches));
+    }
+    Ok(report)
+}
+
+/// Compares every copied record with the one in the target and returns the number of differences.
+async fn verify<S, T>(source: &S, target: &T, acc_ids: &[ClientId], operations: &[u64], progress: &mut impl FnMut(MigrationProgress)) -> Result<usize, DbError>
+where
+    S: Storage + Journal,
+    T: Storage + Journal,
+{
+    let mut mismatches = 0;
+    let mut processed = 0;
+    let mut tgt_tx = target.start_db_tx(TxOptions::read_only()).await?;
+
+    for chunk in acc_ids.chunks(PAGE_SIZE) {
+        let mut src_tx = source.start_db_tx(TxOptions::read_only()).await?;
+        let expected = source.get_accounts(&mut src_tx, chunk).await?;
+        drop(src_tx);
+        let actual = target.get_accounts(&mut tgt_tx, chunk).await?;
+        mismatches += expected.iter().zip(&actual).filter(|(x, y)| x != y).count();
+
+        for acc_id in chunk.iter().copied() {
+            let mut cursor = None;
+            loop {
+                let mut src_tx = source.start_db_tx(TxOptions::read_only()).await?;
+                let expected = source.get_txs_by_account(&mut src_tx, acc_id, cursor, PAGE_SIZE).await?;
+                drop(src_tx);
+                let Some(last) = expected.last() else { break };
+                cursor = Some(last.id());
+                let tx_ids: Vec<TxId> = expected.iter().map(|x| x.id()).collect();
+                let actual = target.get_txs(&mut tgt_tx, &tx_ids).await?;
+                mismatches += expected.iter().zip(&actual).filter(|(x, y)| Some(*x) != y.as_ref()).count();
+            }
+        }
+        processed += chunk.len();
+        progress(MigrationProgress { stage: MigrationStage::Verification, processed });
+    }
+
+    let copied: BTreeSet<u64> = target.get_all_operations(&mut tgt_tx).await?.into_iter().collect();
+    mismatches += operations.iter().filter(|x| !copied.contains(x)).count();
+
+    let mut from_seq = 0;
+    loop {
+        let mut src_tx = source.start_db_tx(TxOptions::read_only()).await?;
+        let expected = source.get_journal_entries(&mut src_tx, from_seq, PAGE_SIZE).await?;
+        drop(src_tx);
+        let Some(last) = expected.last() else { break };
+        from_seq = last.seq() + 1;
+        let actual = target.get_journal_entries(&mut tgt_tx, expected[0].seq(), expected.len()).await?;
+        mismatches += expected.iter().zip(&actual).filter(|(x, y)| x != y).count() + expected.len().saturating_sub(actual.len());
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod migrate_tests {
+    use std::path::PathBuf;
+
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+    use crate::file_storage::FileStorage;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("transactions_engine_migrate_{}_{}.log", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn migrate_ok() {
+        let source = Engine::new(EchoDbStorage::new());
+        assert_eq!(source.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(source.deposit(2, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(source.withdraw(1, 3, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(source.dispute(2, 2).await, Ok(()));
+
+        let path = temp_log("ok");
+        let target = FileStorage::open(&path).await.unwrap();
+        let mut stages = Vec::new();
+        let report = migrate(source.storage(), &target, |x| stages.push(x.stage)).await.unwrap();
+        assert_eq!(report, MigrationReport { accounts: 2, transactions: 3, operations: 3, journal_entries: 4 });
+        assert!(stages.contains(&MigrationStage::Verification));
+
+        let target = Engine::new(target);
+        assert_eq!(target.get_all_accounts().await, source.get_all_accounts().await);
+        assert_eq!(target.verify_journal().await, Ok(vec![]));
+        assert_eq!(target.resolve(2, 2).await, Ok(()));
+        assert_eq!(target.deposit(1, 1, Decimal4::from(100)).await, Ok(())); // idempotency records are copied too
+        assert_eq!(target.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(70));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn target_must_be_empty() {
+        let source = EchoDbStorage::new();
+        let target = Engine::new(EchoDbStorage::new());
+        assert_eq!(target.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(migrate(&source, target.storage(), |_| {}).await, Err(MigrationError::TargetNotEmpty));
+    }
+}
@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use async_nats::jetstream::consumer::{pull, AckPolicy, PullConsumer};
+use async_nats::jetstream::AckKind;
+use futures::StreamExt;
+
+use crate::dead_letter::{DeadLetter, DeadLetterStore};
+use crate::engine::Engine;
+use crate::journal::{Journal, OperationSource, Provenance};
+use crate::queue::{execute_message, MessageOutcome, RetryPolicy, RetryQueue};
+use crate::runtime;
+use crate::storage::Storage;
+
+#[derive(Debug, Clone)]
+pub struct NatsConfig {
+    pub url: String,
+    pub stream: String,
+    /// The durable pull consumer, created with explicit acks when it does not exist.
+    pub consumer: String,
+    /// Only the subjects matching this filter are consumed, all the subjects of the stream when empty.
+    pub filter_subject: String,
+    /// How long the server waits before redelivering a message given back on a transient error.
+    pub redelivery_delay: Duration,
+    pub retry: RetryPolicy,
+    /// The retries of the transient failures left after the retries in place, see [`RetryQueue`]. The whole backoff should
+    /// stay under the ack wait of the consumer (30s by default), or the server redelivers the message meanwhile.
+    pub retry_queue: RetryPolicy,
+}
+
+impl NatsConfig {
+    pub fn new(url: impl Into<String>, stream: impl Into<String>, consumer: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            stream: stream.into(),
+            consumer: consumer.into(),
+            filter_subject: String::new(),
+            redelivery_delay: Duration::from_secs(1),
+            retry: RetryPolicy::default(),
+            retry_queue: RetryPolicy { attempts: 3, initial_backoff: Duration::from_secs(1) },
+        }
+    }
+
+    pub fn with_filter_subject(mut self, filter_subject: impl Into<String>) -> Self {
+        self.filter_subject = filter_subject.into();
+        self
+    }
+
+    pub fn with_retry_queue(mut self, retry_queue: RetryPolicy) -> Self {
+        self.retry_queue = retry_queue;
+        self
+    }
+}
+
+/// How a message is acknowledged, see [`consume_jetstream`].
+pub fn ack_kind(outcome: &MessageOutcome, config: &NatsConfig) -> AckKind {
+    match outcome {
+        MessageOutcome::Applied(_) => AckKind::Ack,
+        MessageOutcome::Transient(..) => AckKind::Nak(Some(config.redelivery_delay)),
+        MessageOutcome::Rejected(..) | MessageOutcome::Invalid(_) => AckKind::Term,
+    }
+}
+
+/// Pulls the operations from a JetStream consumer (JSON payloads, see [`crate::queue::decode_operation`]) one by one,
+/// until `shutdown` completes or the connection fails. `on_message` is called with the outcome of every message; the message
+/// in flight is finished on shutdown, the unacked ones pulled ahead (or waiting in the retry queue) are redelivered later.
+///
+/// A message is acked (and the ack confirmed by the server) only after the operation is committed. Messages that can not
+/// be decoded or that the engine rejects are terminated, so they are not redelivered, and the rejected operations are put
+/// in `dead_letters` first. Transient failures are retried in place, then in the [`RetryQueue`] while the next messages
+/// are consumed, and given back with a nak when out of attempts. A message redelivered after a lost ack is acked again
+/// without effect, since the engine is idempotent.
+pub async fn consume_jetstream<TStorage: Storage + Journal, D: DeadLetterStore + Sync>(
+    engine: &Engine<TStorage>,
+    config: &NatsConfig,
+    dead_letters: &D,
+    mut on_message: impl FnMut(&MessageOutcome),
+    shutdown: impl std::future::Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let client = async_nats::connect(&config.url).await.with_context(|| format!("error connecting to {}", config.url))?;
+    let jetstream = async_nats::jetstream::new(client);
+    let stream = jetstream.get_stream(&config.stream).await.with_context(|| format!("error getting stream {}", config.stream))?;
+    let consumer: PullConsumer = stream
+        .get_or_create_consumer(&config.consumer, pull::Config {
+            durable_name: Some(config.consumer.clone()),
+            filter_subject: config.filter_subject.clone(),
+            ack_policy: AckPolicy::Explicit,
+            ..Default::default()
+        })
+        .await
+        .with_context(|| format!("error getting consumer {}", config.consumer))?;
+
+    let mut messages = consumer.messages().await.context("error pulling messages")?;
+    let mut retries = RetryQueue::new(config.retry_queue).with_clock(engine.clock().clone());
+    tokio::pin!(shutdown);
+    loop {
+        let next_due_in = retries.next_due_in();
+        let message = tokio::select! {
+            biased;
+            _ = &mut shutdown => break,
+            _ = runtime::sleep(next_due_in.unwrap_or_default()), if next_due_in.is_some() => {
+                for (message, outcome, provenance) in retries.retry_due(engine).await {
+                    finish_message(&message, outcome, provenance, config, dead_letters, &mut on_message).await?;
+                }
+                continue;
+            }
+            message = messages.next() => match message {
+                Some(message) => message,
+                None => break,
+            },
+        };
+        let message = message.context("error receiving a message")?;
+        let provenance = message.info().ok().map(|info| Provenance::new(OperationSource::Queue { name: info.stream.to_string(), sequence: info.stream_sequence }));
+        let outcome = execute_message(engine, &message.payload, provenance.as_ref(), config.retry).await;
+        if let Err((message, outcome)) = retries.push(message, outcome, provenance.as_ref()) {
+            finish_message(&message, outcome, provenance, config, dead_letters, &mut on_message).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Dead-letters the rejected operation of a message, then acknowledges the message and reports its outcome.
+async fn finish_message<D: DeadLetterStore + Sync>(
+    message: &async_nats::jetstream::Message,
+    outcome: MessageOutcome,
+    provenance: Option<Provenance>,
+    config: &NatsConfig,
+    dead_letters: &D,
+    on_message: &mut impl FnMut(&MessageOutcome),
+) -> anyhow::Result<()> {
+    if let MessageOutcome::Rejected(operation, err) = &outcome {
+        dead_letters.add(DeadLetter::new(operation.clone(), err, provenance)).await.context("error storing a dead letter")?;
+    }
+    match ack_kind(&outcome, config) {
+        AckKind::Ack => message.double_ack().await,
+        kind => message.ack_with(kind).await,
+    }
+    .map_err(|e| anyhow!("error acknowledging a message: {}", e))?;
+    on_message(&outcome);
+    Ok(())
+}
+
+#[cfg(test)]
+mod nats_tests {
+    use rust_decimal::Decimal;
+
+    use crate::engine::{EngineError, Operation};
+
+    use super::*;
+
+    #[test]
+    fn ack_kinds() {
+        let config = NatsConfig::new("nats://localhost:4222", "operations", "engine");
+        let deposit = Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(1) };
+        assert!(matches!(ack_kind(&MessageOutcome::Applied(deposit.clone()), &config), AckKind::Ack));
+        assert!(matches!(ack_kind(&MessageOutcome::Rejected(deposit.clone(), EngineError::AccountLocked), &config), AckKind::Term));
+        assert!(matches!(ack_kind(&MessageOutcome::Invalid("invalid json".to_string()), &config), AckKind::Term));
+        assert!(matches!(
+            ack_kind(&MessageOutcome::Transient(deposit, EngineError::ConcurrentOperationDetected), &config),
+            AckKind::Nak(Some(x)) if x == Duration::from_secs(1),
+        ));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::account::Account;
+use crate::engine::{EngineError, Operation};
+use crate::journal::Provenance;
+use crate::transaction::Transaction;
+
+/// An effect of an operation, emitted after the storage transaction is committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineEvent {
+    DepositApplied { account: Account, transaction: Transaction },
+    WithdrawalApplied { account: Account, transaction: Transaction },
+    DisputeOpened { account: Account, transaction: Transaction },
+    DisputeResolved { account: Account, transaction: Transaction },
+    ChargebackApplied { account: Account, transaction: Transaction },
+    EscrowMoved { account: Account, transaction: Transaction },
+    EscrowReleased { account: Account, transaction: Transaction },
+    AuthorizationHeld { account: Account, transaction: Transaction },
+    AuthorizationCaptured { account: Account, transaction: Transaction },
+    /// An authorization was not captured in time and its held funds were released, see `Engine::expire_holds`.
+    AuthorizationExpired { account: Account, transaction: Transaction },
+    AccountLocked { account: Account },
+    /// A resolve or a chargeback settled an account locked by a chargeback, see `EnginePolicy::auto_unlock`.
+    AccountUnlocked { account: Account },
+    /// The `provenance` is the envelope of the rejected operation, if it was executed with one.
+    OperationRejected { operation: Operation, error: EngineError, provenance: Option<Provenance> },
+}
+
+impl EngineEvent {
+    pub fn dispatch(&self, observer: &dyn EngineObserver) {
+        match self {
+            EngineEvent::DepositApplied { account, transaction } => observer.on_deposit_applied(account, transaction),
+            EngineEvent::WithdrawalApplied { account, transaction } => observer.on_withdrawal_applied(account, transaction),
+            EngineEvent::DisputeOpened { account, transaction } => observer.on_dispute_opened(account, transaction),
+            EngineEvent::DisputeResolved { account, transaction } => observer.on_dispute_resolved(account, transaction),
+            EngineEvent::ChargebackApplied { account, transaction } => observer.on_chargeback_applied(account, transaction),
+            EngineEvent::EscrowMoved { account, transaction } => observer.on_escrow_moved(account, transaction),
+            EngineEvent::EscrowReleased { account, transaction } => observer.on_escrow_released(account, transaction),
+            EngineEvent::AuthorizationHeld { account, transaction } => observer.on_authorization_held(account, transaction),
+            EngineEvent::AuthorizationCaptured { account, transaction } => observer.on_authorization_captured(account, transaction),
+            EngineEvent::AuthorizationExpired { account, transaction } => observer.on_authorization_expired(account, transaction),
+            EngineEvent::AccountLocked { account } => observer.on_account_locked(account),
+            EngineEvent::AccountUnlocked { account } => observer.on_account_unlocked(account),
+            EngineEvent::OperationRejected { operation, error, .. } => observer.on_operation_rejected(operation, error),
+        }
+        observer.on_event(self);
+    }
+}
+
+/// Callbacks invoked by the engine after an operation is committed or rejected.
+/// All methods have empty default implementations, so observers only implement what they need.
+///
+/// Callbacks are called synchronously on the task that executed the operation,
+/// so long-running work (network calls, etc.) should be moved to a separate task.
+pub trait EngineObserver: Send + Sync {
+    fn on_deposit_applied(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_withdrawal_applied(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_dispute_opened(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_dispute_resolved(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_chargeback_applied(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_escrow_moved(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_escrow_released(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_authorization_held(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_authorization_captured(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_authorization_expired(&self, _account: &Account, _transaction: &Transaction) {}
+    fn on_account_locked(&self, _account: &Account) {}
+    fn on_account_unlocked(&self, _account: &Account) {}
+    fn on_operation_rejected(&self, _operation: &Operation, _error: &EngineError) {}
+
+    /// Called for every event after the specific callback, useful for observers that forward all events.
+    fn on_event(&self, _event: &EngineEvent) {}
+}
+
+#[cfg(test)]
+mod observer_tests {
+    use std::sync::{Arc, Mutex};
+
+    use rust_decimal::Decimal;
+
+    use crate::account::ClientId;
+    use crate::decimal::Decimal4;
+    use crate::engine::{Engine, ExecuteOptions};
+    use crate::journal::OperationSource;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<EngineEvent>>,
+        locked_accounts: Mutex<Vec<ClientId>>,
+    }
+
+    impl EngineObserver for RecordingObserver {
+        fn on_account_locked(&self, account: &Account) {
+            self.locked_accounts.lock().unwrap().push(account.id());
+        }
+
+        fn on_event(&self, event: &EngineEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn observer_notified_after_commit() {
+        let observer = Arc::new(RecordingObserver::default());
+        let engine = Engine::new(EchoDbStorage::new()).with_observer(observer.clone());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(())); // idempotent replay, no event
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        let events = observer.events.lock().unwrap();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(events[0], EngineEvent::DepositApplied { .. }));
+        assert!(matches!(events[1], EngineEvent::DisputeOpened { .. }));
+        assert!(matches!(events[2], EngineEvent::ChargebackApplied { .. }));
+        assert!(matches!(&events[3], EngineEvent::AccountLocked { account } if account.locked()));
+        assert_eq!(*observer.locked_accounts.lock().unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn observer_notified_on_rejection() {
+        let observer = Arc::new(RecordingObserver::default());
+        let engine = Engine::new(EchoDbStorage::new()).with_observer(observer.clone());
+        assert_eq!(engine.withdraw(1, 1, Decimal4::from(100)).await, Err(EngineError::AccountNotFound));
+        assert_eq!(*observer.events.lock().unwrap(), vec![EngineEvent::OperationRejected {
+            operation: Operation::Withdraw { acc_id: 1, tx_id: 1, amount: Decimal::from(100) },
+            error: EngineError::AccountNotFound,
+            provenance: None,
+        }]);
+
+        let provenance = Provenance::new(OperationSource::Api { request_id: Some("r1".to_string()) });
+        let options = ExecuteOptions { provenance: Some(&provenance), ..ExecuteOptions::default() };
+        assert_eq!(engine.execute_operation_with(Operation::Dispute { acc_id: 1, tx_id: 1 }, options).await, Err(EngineError::TransactionNotFound));
+        assert!(matches!(&observer.events.lock().unwrap()[1], EngineEvent::OperationRejected { provenance: Some(x), .. } if *x == provenance));
+    }
+}
@@ -0,0 +1,222 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::{Field, Row};
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::account::ClientId;
+use crate::csv_parser::{CsvOperation, CsvParseError};
+use crate::decimal::parse_unrounded;
+use crate::engine::{Engine, Operation};
+use crate::journal::Journal;
+use crate::storage::Storage;
+use crate::transaction::TxId;
+
+/// Columns expected in the file, mapped by name (any extra columns are ignored).
+const COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// Rows buffered between the reader thread and the engine.
+const READ_AHEAD_ROWS: usize = 1024;
+
+/// Rows of a row group that could not be mapped to an operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowGroupError {
+    pub row_group: usize,
+    pub failed_rows: usize,
+    pub first_error: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParquetReport {
+    /// Operations successfully executed by the engine.
+    pub executed: u64,
+    pub row_group_errors: Vec<RowGroupError>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParquetInputError {
+    #[error("can not read parquet file: {0}")]
+    Read(String),
+
+    #[error("missing column: {0}")]
+    MissingColumn(&'static str),
+}
+
+impl From<parquet::errors::ParquetError> for ParquetInputError {
+    fn from(value: parquet::errors::ParquetError) -> Self {
+        ParquetInputError::Read(value.to_string())
+    }
+}
+
+/// Reads and executes the operations from a Parquet file with the same columns as the CSV input.
+///
+/// The file is decoded on a blocking thread. Rows that can not be mapped to an operation (wrong column type,
+/// missing value, unknown operation type) are skipped and reported per row group, rejected operations are
+/// skipped like in the CSV input.
+pub async fn read_parquet<TStorage: Storage + Journal>(path: impl AsRef<Path>, engine: &mut Engine<TStorage>) -> Result<ParquetReport, ParquetInputError> {
+    let path: PathBuf = path.as_ref().to_path_buf();
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(READ_AHEAD_ROWS);
+    let reader_task = tokio::task::spawn_blocking(move || -> Result<(), ParquetInputError> {
+        let reader = SerializedFileReader::new(File::open(path).map_err(|e| ParquetInputError::Read(e.to_string()))?)?;
+        let fields = reader.metadata().file_metadata().schema_descr().root_schema().get_fields().to_vec();
+        if let Some(column) = COLUMNS.into_iter().find(|x| !fields.iter().any(|field| field.name() == *x)) {
+            return Err(ParquetInputError::MissingColumn(column));
+        }
+
+        for row_group in 0..reader.num_row_groups() {
+            for row in reader.get_row_group(row_group)?.get_row_iter(None)? {
+                let operation = row.map_err(|e| e.to_string()).and_then(|x| to_operation(&x));
+                if sender.blocking_send((row_group, operation)).is_err() {
+                    return Ok(()); // NOTE: the receiving side is gone, nobody needs the rest of the input
+                }
+            }
+        }
+        Ok(())
+    });
+
+    let mut report = ParquetReport::default();
+    while let Some((row_group, operation)) = receiver.recv().await {
+        match operation {
+            Ok(operation) => {
+                if engine.execute_operation(operation).await.is_ok() {
+                    report.executed += 1;
+                }
+            }
+            Err(err) => match report.row_group_errors.last_mut() {
+                Some(last) if last.row_group == row_group => last.failed_rows += 1,
+                _ => report.row_group_errors.push(RowGroupError { row_group, failed_rows: 1, first_error: err }),
+            },
+        }
+    }
+
+    reader_task.await.map_err(|e| ParquetInputError::Read(e.to_string()))??;
+    Ok(report)
+}
+
+fn to_operation(row: &Row) -> Result<Operation, String> {
+    let (mut op_type, mut client, mut tx, mut amount) = (None, None, None, None);
+    for (name, field) in row.get_column_iter() {
+        match (name.as_str(), field) {
+            (_, Field::Null) => {}
+            ("type", Field::Str(x)) => op_type = Some(x.clone()),
+            ("client", x) => client = Some(to_integer(x).and_then(|x| ClientId::try_from(x).ok()).ok_or_else(|| invalid("client", x))?),
+            ("tx", x) => tx = Some(to_integer(x).and_then(|x| TxId::try_from(x).ok()).ok_or_else(|| invalid("tx", x))?),
+            ("amount", x) => amount = Some(to_amount(x).ok_or_else(|| invalid("amount", x))?),
+            ("type", x) => return Err(invalid("type", x)),
+            _ => {}
+        }
+    }
+    CsvOperation::new(op_type, client, tx, amount).try_into().map_err(|e: CsvParseError| e.to_string())
+}
+
+fn invalid(column: &str, field: &Field) -> String {
+    format!("invalid {} value: {}", column, field)
+}
+
+fn to_integer(field: &Field) -> Option<i128> {
+    match *field {
+        Field::Byte(x) => Some(x.into()),
+        Field::Short(x) => Some(x.into()),
+        Field::Int(x) => Some(x.into()),
+        Field::Long(x) => Some(x.into()),
+        Field::UByte(x) => Some(x.into()),
+        Field::UShort(x) => Some(x.into()),
+        Field::UInt(x) => Some(x.into()),
+        Field::ULong(x) => Some(x.into()),
+        _ => None,
+    }
+}
+
+fn to_amount(field: &Field) -> Option<Decimal> {
+    match field {
+        Field::Str(x) => parse_unrounded(x).ok(),
+        Field::Float(x) => Decimal::try_from(*x).ok(),
+        Field::Double(x) => Decimal::try_from(*x).ok(),
+        Field::Decimal(x) => {
+            let data = x.data();
+            if data.is_empty() || data.len() > 16 {
+                return None;
+            }
+            let mut bytes = [if data[0] & 0x80 != 0 { 0xff } else { 0 }; 16]; // NOTE: big-endian two's complement, sign-extended
+            bytes[16 - data.len()..].copy_from_slice(data);
+            Decimal::try_from_i128_with_scale(i128::from_be_bytes(bytes), x.scale().try_into().ok()?).ok()
+        }
+        x => to_integer(x).and_then(|x| i64::try_from(x).ok()).map(Decimal::from),
+    }
+}
+
+#[cfg(test)]
+mod parquet_reader_tests {
+    use std::sync::Arc;
+
+    use parquet::data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    use crate::decimal::Decimal4;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    const SCHEMA: &str = "message transactions {
+        REQUIRED BYTE_ARRAY type (UTF8);
+        REQUIRED INT32 client;
+        REQUIRED INT64 tx;
+        OPTIONAL BYTE_ARRAY amount (UTF8);
+    }";
+
+    type Rows<'a> = [(&'a str, i32, i64, Option<&'a str>)];
+
+    fn write_file(path: &Path, row_groups: &[&Rows]) {
+        let schema = Arc::new(parse_message_type(SCHEMA).unwrap());
+        let mut writer = SerializedFileWriter::new(File::create(path).unwrap(), schema, Arc::new(WriterProperties::builder().build())).unwrap();
+        for rows in row_groups {
+            let mut row_group = writer.next_row_group().unwrap();
+            let types: Vec<ByteArray> = rows.iter().map(|x| x.0.into()).collect();
+            let clients: Vec<i32> = rows.iter().map(|x| x.1).collect();
+            let txs: Vec<i64> = rows.iter().map(|x| x.2).collect();
+            let amounts: Vec<ByteArray> = rows.iter().filter_map(|x| x.3).map(|x| x.into()).collect();
+            let levels: Vec<i16> = rows.iter().map(|x| x.3.is_some() as i16).collect();
+
+            let mut column = row_group.next_column().unwrap().unwrap();
+            column.typed::<ByteArrayType>().write_batch(&types, None, None).unwrap();
+            column.close().unwrap();
+            let mut column = row_group.next_column().unwrap().unwrap();
+            column.typed::<Int32Type>().write_batch(&clients, None, None).unwrap();
+            column.close().unwrap();
+            let mut column = row_group.next_column().unwrap().unwrap();
+            column.typed::<Int64Type>().write_batch(&txs, None, None).unwrap();
+            column.close().unwrap();
+            let mut column = row_group.next_column().unwrap().unwrap();
+            column.typed::<ByteArrayType>().write_batch(&amounts, Some(&levels), None).unwrap();
+            column.close().unwrap();
+            row_group.close().unwrap();
+        }
+        writer.close().unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_parquet_ok() {
+        let path = std::env::temp_dir().join(format!("transactions_engine_{}.parquet", std::process::id()));
+        write_file(&path, &[
+            &[("deposit", 1, 1, Some("100.5")), ("withdrawal", 1, 2, Some("30")), ("dispute", 1, 1, None)],
+            &[("deposit", -1, 3, Some("5")), ("refund", 1, 4, Some("1")), ("deposit", 2, 5, Some("7"))],
+        ]);
+
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let report = read_parquet(&path, &mut engine).await.unwrap();
+        assert_eq!(report.executed, 4);
+        assert_eq!(report.row_group_errors, vec![RowGroupError {
+            row_group: 1,
+            failed_rows: 2,
+            first_error: "invalid client value: -1".to_string(),
+        }]);
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(-30));
+        assert_eq!(acc.held(), "100.5".parse().unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
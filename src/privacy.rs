@@ -0,0 +1,65 @@
+use serde::Serialize;
+
+use crate::account::Account;
+use crate::journal::JournalEntry;
+use crate::transaction::Transaction;
+
+/// Everything stored about a client, see `Engine::export_account_data`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountDataExport {
+    pub exported_at: u64, // unix millis
+    pub account: Account,
+    pub transactions: Vec<Transaction>,
+    /// The journal entries of the account's operations, with the account as it was after each of them.
+    pub journal: Vec<JournalEntry>,
+}
+
+impl AccountDataExport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("export is always serializable")
+    }
+}
+
+#[cfg(test)]
+mod privacy_tests {
+    use crate::account::AccountMetadata;
+    use crate::decimal::Decimal4;
+    use crate::engine::{Engine, EngineError};
+    use crate::storage::EchoDbStorage;
+
+    #[tokio::test]
+    async fn export_account_data() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(5)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(30)).await, Ok(()));
+        let metadata = AccountMetadata { name: Some("Alice".to_string()), external_ref: Some("crm-42".to_string()) };
+        engine.set_account_metadata(1, metadata.clone()).await.unwrap();
+
+        let export = engine.export_account_data(1).await.unwrap().unwrap();
+        assert_eq!(export.account.metadata(), &metadata);
+        assert_eq!(export.transactions.iter().map(|x| x.id()).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(export.journal.iter().map(|x| x.seq()).collect::<Vec<_>>(), vec![1, 3]);
+        let json: serde_json::Value = serde_json::from_str(&export.to_json()).unwrap();
+        assert_eq!(json["account"]["metadata"]["name"], "Alice");
+        assert_eq!(engine.export_account_data(3).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn erase_account() {
+        let engine = Engine::new(EchoDbStorage::new());
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        engine.set_account_metadata(1, AccountMetadata { name: Some("Alice".to_string()), external_ref: None }).await.unwrap();
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.erase_account(1).await, Err(EngineError::OpenDisputes));
+        assert_eq!(engine.erase_account(2).await, Err(EngineError::AccountNotFound));
+
+        assert_eq!(engine.resolve(1, 1).await, Ok(()));
+        let account = engine.erase_account(1).await.unwrap();
+        assert_eq!((account.metadata(), account.total().unwrap()), (&AccountMetadata::default(), Decimal4::from(100)));
+        assert_eq!(engine.get_account(1).await.unwrap(), Some(account));
+        assert_eq!(engine.get_tx(1).await.unwrap().unwrap().amount(), Decimal4::from(100));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+        assert_eq!(engine.verify_journal_chain(None).await.unwrap().violations, vec![]);
+    }
+}
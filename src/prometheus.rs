@@ -0,0 +1,74 @@
+use std::sync::OnceLock;
+
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use thiserror::Error;
+
+/// Buckets (seconds) of the `*_duration_seconds` histograms, from an in-memory call to a slow database commit.
+const LATENCY_BUCKETS: &[f64] = &[0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+#[derive(Error, Debug)]
+pub enum PrometheusError {
+    #[error("error building the Prometheus recorder: {0}")]
+    Build(#[from] BuildError),
+    #[error("another metrics recorder is already installed")]
+    RecorderAlreadyInstalled,
+}
+
+/// Installs the Prometheus recorder as the global `metrics` recorder, so the engine metrics
+/// (see [`crate::engine_metrics`]) and the storage metrics (see [`crate::metered_storage`]) can be rendered.
+/// Installing it again is a no-op.
+pub fn install() -> Result<&'static PrometheusHandle, PrometheusError> {
+    if let Some(handle) = HANDLE.get() {
+        return Ok(handle);
+    }
+    let recorder = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_duration_seconds".to_string()), LATENCY_BUCKETS)?
+        .build_recorder();
+    let handle = recorder.handle();
+    metrics::set_global_recorder(recorder).map_err(|_| PrometheusError::RecorderAlreadyInstalled)?;
+    Ok(HANDLE.get_or_init(|| handle))
+}
+
+/// The metrics in the Prometheus text format, empty until the recorder is installed.
+pub fn render() -> String {
+    HANDLE.get().map(|x| x.render()).unwrap_or_default()
+}
+
+/// `GET /metrics` serves the metrics of the installed recorder.
+#[cfg(feature = "http")]
+pub fn router() -> axum::Router {
+    use axum::http::header;
+    use axum::routing::get;
+
+    axum::Router::new().route("/metrics", get(|| async { ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], render()) }))
+}
+
+#[cfg(test)]
+mod prometheus_tests {
+    use std::sync::Arc;
+
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+    use crate::engine_metrics::MetricsObserver;
+    use crate::metered_storage::MeteredStorage;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn render_metrics() {
+        install().unwrap();
+        assert!(install().is_ok());
+        let engine = Engine::new(MeteredStorage::new(EchoDbStorage::new(), "echodb")).with_observer(Arc::new(MetricsObserver));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+
+        let rendered = render();
+        assert!(rendered.contains(r#"engine_operations_total{type="deposit",outcome="applied"}"#), "{}", rendered);
+        assert!(rendered.contains(r#"engine_operation_duration_seconds_bucket{type="dispute",le="0.001"}"#), "{}", rendered);
+        assert!(rendered.contains(r#"storage_operation_duration_seconds_bucket{backend="echodb",operation="commit_db_tx",le="1"}"#), "{}", rendered);
+        assert!(rendered.contains("engine_held_funds "), "{}", rendered);
+    }
+}
@@ -0,0 +1,220 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+use crate::csv_parser::CsvOperation;
+use crate::engine::{Engine, EngineError, ExecuteOptions, Operation};
+use crate::journal::{Journal, Provenance};
+use crate::runtime;
+use crate::storage::Storage;
+
+/// The result of a message from a queue (NATS, AMQP) or a line of the TCP server, mapped by each adapter to its acknowledgement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageOutcome {
+    /// The operation was committed (or was already committed before, the engine is idempotent).
+    Applied(Operation),
+    /// The engine rejected the operation, delivering it again gives the same error.
+    Rejected(Operation, EngineError),
+    /// The operation failed on a concurrent operation or the storage, even after the retries in place.
+    Transient(Operation, EngineError),
+    /// The payload is not an operation.
+    Invalid(String),
+}
+
+/// Retries of transient errors before the message is given back to the queue.
+///
+/// Retrying in place keeps the order of the operations, a message given back is usually redelivered after the next ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { attempts: 3, initial_backoff: Duration::from_millis(50) }
+    }
+}
+
+/// Decodes a message payload: a JSON object with the fields of a CSV row, e.g.
+/// `{"type": "deposit", "client": 1, "tx": 1, "amount": "10.5"}`.
+pub fn decode_operation(payload: &[u8]) -> Result<Operation, String> {
+    let operation: CsvOperation = serde_json::from_slice(payload).map_err(|e| format!("invalid json: {}", e))?;
+    operation.try_into().map_err(|e: crate::csv_parser::CsvParseError| e.to_string())
+}
+
+/// Decodes and executes a message payload received in the `provenance` envelope, retrying the transient errors according to `retry`.
+pub async fn execute_message<TStorage: Storage + Journal>(engine: &Engine<TStorage>, payload: &[u8], provenance: Option<&Provenance>, retry: RetryPolicy) -> MessageOutcome {
+    match decode_operation(payload) {
+        Ok(operation) => execute_with_retry(engine, operation, provenance, retry).await,
+        Err(err) => MessageOutcome::Invalid(err),
+    }
+}
+
+/// Executes an operation, retrying the transient errors according to `retry`.
+pub async fn execute_with_retry<TStorage: Storage + Journal>(engine: &Engine<TStorage>, operation: Operation, provenance: Option<&Provenance>, retry: RetryPolicy) -> MessageOutcome {
+    let mut backoff = retry.initial_backoff;
+    let options = ExecuteOptions { provenance, ..ExecuteOptions::default() };
+    for attempt in 0.. {
+        match engine.execute_operation_with(operation.clone(), options).await {
+            Ok(()) => return MessageOutcome::Applied(operation),
+            Err(err) if !err.is_transient() => return MessageOutcome::Rejected(operation, err),
+            Err(err) if attempt >= retry.attempts => return MessageOutcome::Transient(operation, err),
+            Err(_) => {
+                runtime::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+    }
+    unreachable!("the loop returns")
+}
+
+/// An operation given up by the retries in place, waiting for its next attempt. `handle` is what the consumer needs
+/// to acknowledge the message once the operation is done (e.g. the message itself).
+#[derive(Debug)]
+struct QueuedRetry<T> {
+    handle: T,
+    operation: Operation,
+    provenance: Option<Provenance>,
+    attempts: u32,
+    due_at: u64, // unix millis
+    backoff: Duration,
+}
+
+/// The operations that failed on a transient error, retried later with an exponential backoff instead of blocking
+/// the consumer. An operation is given up (its last outcome is returned) after `policy.attempts` queued attempts;
+/// with zero attempts nothing is queued.
+///
+/// Unlike the retries in place, a queued operation is retried after the next messages, e.g. a dispute of a queued
+/// deposit received meanwhile is rejected.
+#[derive(Debug)]
+pub struct RetryQueue<T> {
+    policy: RetryPolicy,
+    queued: Vec<QueuedRetry<T>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<T> RetryQueue<T> {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self { policy, queued: Vec::new(), clock: Arc::new(SystemClock) }
+    }
+
+    /// Sets the clock of the due times, the system clock by default. The consumers use the clock of their engine.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    /// Queues the operation of a transient outcome. Gives the handle and the outcome back if the queue takes no attempts,
+    /// or if the outcome is not a transient one.
+    pub fn push(&mut self, handle: T, outcome: MessageOutcome, provenance: Option<&Provenance>) -> Result<(), (T, MessageOutcome)> {
+        match outcome {
+            MessageOutcome::Transient(operation, _) if self.policy.attempts > 0 => {
+                let backoff = self.policy.initial_backoff;
+                self.queued.push(QueuedRetry { handle, operation, provenance: provenance.cloned(), attempts: 0, due_at: self.clock.now_millis() + backoff.as_millis() as u64, backoff });
+                Ok(())
+            }
+            outcome => Err((handle, outcome)),
+        }
+    }
+
+    /// How long until the next queued operation is due, `None` if the queue is empty.
+    pub fn next_due_in(&self) -> Option<Duration> {
+        let due_at = self.queued.iter().map(|x| x.due_at).min()?;
+        Some(Duration::from_millis(due_at.saturating_sub(self.clock.now_millis())))
+    }
+
+    /// Attempts the due operations once each, in the order they were queued, and returns the handles, the outcomes and
+    /// the provenances of the ones that are done: applied, rejected or out of attempts. The others are queued again with
+    /// twice the backoff.
+    pub async fn retry_due<TStorage: Storage + Journal>(&mut self, engine: &Engine<TStorage>) -> Vec<(T, MessageOutcome, Option<Provenance>)> {
+        let now = self.clock.now_millis();
+        let (due, queued): (Vec<_>, Vec<_>) = std::mem::take(&mut self.queued).into_iter().partition(|x| x.due_at <= now);
+        self.queued = queued;
+        let mut done = Vec::new();
+        for mut retry in due {
+            let options = ExecuteOptions { provenance: retry.provenance.as_ref(), ..ExecuteOptions::default() };
+            retry.attempts += 1;
+            let outcome = match engine.execute_operation_with(retry.operation.clone(), options).await {
+                Ok(()) => MessageOutcome::Applied(retry.operation),
+                Err(err) if !err.is_transient() => MessageOutcome::Rejected(retry.operation, err),
+                Err(err) if retry.attempts >= self.policy.attempts => MessageOutcome::Transient(retry.operation, err),
+                Err(_) => {
+                    retry.backoff *= 2;
+                    retry.due_at = self.clock.now_millis() + retry.backoff.as_millis() as u64;
+                    self.queued.push(retry);
+                    continue;
+                }
+            };
+            done.push((retry.handle, outcome, retry.provenance));
+        }
+        done
+    }
+}
+
+#[cfg(test)]
+mod queue_tests {
+    use rust_decimal::Decimal;
+
+    use crate::clock::ManualClock;
+    use crate::decimal::Decimal4;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn execute_message_outcomes() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let deposit = br#"{"type": "deposit", "client": 1, "tx": 1, "amount": "10"}"#;
+        let applied = MessageOutcome::Applied(Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) });
+        assert_eq!(execute_message(&engine, deposit, None, RetryPolicy::default()).await, applied);
+        assert_eq!(execute_message(&engine, deposit, None, RetryPolicy::default()).await, applied); // NOTE: a redelivery
+        assert_eq!(
+            execute_message(&engine, br#"{"type": "dispute", "client": 1, "tx": 2}"#, None, RetryPolicy::default()).await,
+            MessageOutcome::Rejected(Operation::Dispute { acc_id: 1, tx_id: 2 }, EngineError::TransactionNotFound),
+        );
+        assert_eq!(
+            execute_message(&engine, br#"{"type": "deposit", "client": 1, "tx": 3}"#, None, RetryPolicy::default()).await,
+            MessageOutcome::Invalid("missing field: amount".to_string()),
+        );
+        assert!(matches!(execute_message(&engine, b"deposit,1,3,5", None, RetryPolicy::default()).await, MessageOutcome::Invalid(_)));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(10));
+    }
+
+    #[tokio::test]
+    async fn retry_queue_retries_transient_outcomes() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let deposit = Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) };
+        let withdrawal = Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(50) };
+        let clock = ManualClock::new(0);
+        let mut queue = RetryQueue::new(RetryPolicy { attempts: 2, initial_backoff: Duration::from_secs(1) }).with_clock(Arc::new(clock.clone()));
+        assert_eq!(queue.next_due_in(), None);
+
+        engine.pause();
+        for (handle, operation) in [(1, deposit.clone()), (2, withdrawal.clone())] {
+            let outcome = execute_with_retry(&engine, operation, None, RetryPolicy { attempts: 0, ..RetryPolicy::default() }).await;
+            queue.push(handle, outcome, None).unwrap();
+        }
+        assert_eq!(queue.push(3, MessageOutcome::Applied(deposit.clone()), None), Err((3, MessageOutcome::Applied(deposit.clone()))));
+        assert_eq!(queue.retry_due(&engine).await, vec![]); // NOTE: not due yet
+        clock.advance(queue.next_due_in().unwrap());
+        assert_eq!(queue.retry_due(&engine).await, vec![]);
+        assert_eq!((queue.len(), queue.next_due_in()), (2, Some(Duration::from_secs(2))));
+
+        engine.resume();
+        clock.advance(queue.next_due_in().unwrap());
+        assert_eq!(queue.retry_due(&engine).await, vec![
+            (1, MessageOutcome::Applied(deposit), None),
+            (2, MessageOutcome::Rejected(withdrawal, EngineError::InsufficientFunds), None),
+        ]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::account::{Account, AccountRef, SystemAccount};
+use crate::decimal::{AmountOverflowError, Decimal4};
+use crate::transaction::{Transaction, TransactionState, TransactionType};
+
+/// Balances of an account recomputed from its transaction history.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ExpectedBalance {
+    pub available: Decimal4,
+    pub held: Decimal4,
+    /// The sum of the escrow buckets.
+    pub escrowed: Decimal4,
+    /// Whether a chargeback locked the account. Reported but not compared: the admins can change the status.
+    pub locked: bool,
+}
+
+impl ExpectedBalance {
+    fn apply(&mut self, tx: &Transaction, lock_on_chargeback: bool) {
+        match (tx.tx_type(), tx.state()) {
+            (TransactionType::Withdrawal, _) => self.available -= tx.amount() + tx.fee(),
+            (TransactionType::Deposit, TransactionState::Posted) => self.available += tx.amount(),
+            (TransactionType::Deposit, TransactionState::Disputed) => {
+                self.available += tx.shortfall();
+                self.held += tx.amount() - tx.shortfall();
+            }
+            (TransactionType::Deposit, TransactionState::Chargeback) => {
+                self.available += tx.written_off();
+                self.locked |= lock_on_chargeback;
+            }
+            (TransactionType::Escrow, _) => {
+                self.available -= tx.amount();
+                self.escrowed += tx.amount();
+            }
+            (TransactionType::EscrowRelease, _) => {
+                self.available += tx.amount();
+                self.escrowed -= tx.amount();
+            }
+            (TransactionType::Authorization, TransactionState::Posted) => {
+                self.available -= tx.amount();
+                self.held += tx.amount();
+            }
+            (TransactionType::Authorization, TransactionState::Captured) => self.available -= tx.amount(),
+            // NOTE: the expired authorizations released their funds, and the other states are never reached by the type
+            (TransactionType::Deposit | TransactionType::Authorization, _) => {}
+        }
+    }
+
+    fn matches(&self, acc: &Account) -> bool {
+        self.available == acc.available() && self.held == acc.held() && Ok(self.escrowed) == acc.escrowed()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActualBalance {
+    pub available: Decimal4,
+    pub held: Decimal4,
+    pub escrowed: Decimal4,
+    pub locked: bool,
+}
+
+impl TryFrom<&Account> for ActualBalance {
+    type Error = AmountOverflowError;
+
+    fn try_from(acc: &Account) -> Result<Self, Self::Error> {
+        Ok(Self {
+            available: acc.available(),
+            held: acc.held(),
+            escrowed: acc.escrowed()?,
+            locked: acc.locked(),
+        })
+    }
+}
+
+/// An account whose stored balances differ from the ones recomputed from its transactions, a client account
+/// or a system account. `actual` is `None` when the account has transactions but is missing in storage, or when its
+/// escrow buckets do not add up to a `Decimal4`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountMismatch {
+    pub client: AccountRef,
+    pub expected: ExpectedBalance,
+    pub actual: Option<ActualBalance>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReconciliationReport {
+    pub accounts_checked: usize,
+    pub transactions_checked: usize,
+    pub mismatches: Vec<AccountMismatch>,
+}
+
+impl ReconciliationReport {
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report is always serializable")
+    }
+}
+
+/// Recomputes every account's balances from the transaction history and diffs them against the stored accounts.
+/// The balances of the [`SystemAccount`]s are the sums of the fees and the written-off amounts of all the transactions.
+pub fn reconcile(accounts: &[Account], system_accounts: &BTreeMap<SystemAccount, Account>, transactions: &[Transaction]) -> ReconciliationReport {
+    reconcile_with(accounts, system_accounts, transactions, true)
+}
+
+/// Same as [`reconcile`] for an engine whose chargebacks lock the account only if `lock_on_chargeback` is set.
+pub fn reconcile_with(accounts: &[Account], system_accounts: &BTreeMap<SystemAccount, Account>, transactions: &[Transaction], lock_on_chargeback: bool) -> ReconciliationReport {
+    let mut expected: BTreeMap<AccountRef, ExpectedBalance> = accounts.iter().map(|x| (x.id().into(), ExpectedBalance::default()))
+        .chain(system_accounts.keys().map(|x| ((*x).into(), ExpectedBalance::default())))
+        .collect();
+    for tx in transactions.iter() {
+        expected.entry(tx.account_id().into()).or_default().apply(tx, lock_on_chargeback);
+        if tx.fee().is_positive() {
+            expected.entry(SystemAccount::Fees.into()).or_default().available += tx.fee();
+        }
+        if tx.written_off().is_positive() {
+            expected.entry(SystemAccount::ChargebackLosses.into()).or_default().available -= tx.written_off();
+        }
+    }
+
+    let actual: BTreeMap<AccountRef, &Account> = accounts.iter().map(|x| (x.id().into(), x))
+        .chain(system_accounts.iter().map(|(account, x)| ((*account).into(), x)))
+        .collect();
+    let mismatches = expected.into_iter()
+        .filter_map(|(client, expected)| match actual.get(&client) {
+            Some(acc) if expected.matches(acc) => None,
+            Some(acc) => Some(AccountMismatch { client, expected, actual: ActualBalance::try_from(*acc).ok() }),
+            None => Some(AccountMismatch { client, expected, actual: None }),
+        })
+        .collect();
+
+    ReconciliationReport {
+        accounts_checked: accounts.len() + system_accounts.len(),
+        transactions_checked: transactions.len(),
+        mismatches,
+    }
+}
+
+#[cfg(test)]
+mod reconcile_tests {
+    use super::*;
+
+    #[test]
+    fn reconcile_consistent_accounts() {
+        let mut acc = Account::new(1);
+        acc.deposit(Decimal4::from(100)).unwrap();
+        acc.withdraw(Decimal4::from(30)).unwrap();
+        acc.dispute(Decimal4::from(100)).unwrap();
+        let mut deposit = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(100));
+        deposit.set_state(TransactionState::Disputed).unwrap();
+        let withdrawal = Transaction::new(2, 1, TransactionType::Withdrawal, Decimal4::from(30));
+        let report = reconcile(&[acc], &BTreeMap::new(), &[deposit, withdrawal]);
+        assert!(report.is_consistent());
+        assert_eq!(report.accounts_checked, 1);
+        assert_eq!(report.transactions_checked, 2);
+    }
+
+    #[test]
+    fn reconcile_balance_mismatch() {
+        let mut acc = Account::new(1);
+        acc.deposit(Decimal4::from(100)).unwrap();
+        let deposit = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(90));
+        let report = reconcile(&[acc], &BTreeMap::new(), &[deposit]);
+        assert_eq!(report.mismatches, vec![AccountMismatch {
+            client: AccountRef::Client(1),
+            expected: ExpectedBalance { available: Decimal4::from(90), held: Decimal4::zero(), escrowed: Decimal4::zero(), locked: false },
+            actual: Some(ActualBalance { available: Decimal4::from(100), held: Decimal4::zero(), escrowed: Decimal4::zero(), locked: false }),
+        }]);
+    }
+
+    #[test]
+    fn reconcile_missing_account() {
+        let deposit = Transaction::new(1, 2, TransactionType::Deposit, Decimal4::from(90));
+        let report = reconcile(&[], &BTreeMap::new(), &[deposit]);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].actual, None);
+    }
+
+    #[test]
+    fn reconcile_system_accounts() {
+        let withdrawal = Transaction::new(1, 1, TransactionType::Withdrawal, Decimal4::from(10)).with_fee(Decimal4::from(1));
+        let mut acc = Account::new(1);
+        acc.post(Decimal4::from(-11)).unwrap();
+        let mut fees = Account::default();
+        fees.post(Decimal4::from(1)).unwrap();
+        let (accounts, transactions) = ([acc], [withdrawal]);
+        let report = reconcile(&accounts, &BTreeMap::from([(SystemAccount::Fees, fees)]), &transactions);
+        assert!(report.is_consistent());
+        assert_eq!(report.accounts_checked, 2);
+
+        let report = reconcile(&accounts, &BTreeMap::new(), &transactions);
+        assert_eq!(report.mismatches.iter().map(|x| x.client).collect::<Vec<_>>(), vec![AccountRef::System(SystemAccount::Fees)]);
+    }
+
+    #[test]
+    fn reconcile_report_json() {
+        let report = reconcile(&[Account::new(1)], &BTreeMap::new(), &[]);
+        assert_eq!(report.to_json(), "{\n  \"accounts_checked\": 1,\n  \"transactions_checked\": 0,\n  \"mismatches\": []\n}");
+    }
+}
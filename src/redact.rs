@@ -0,0 +1,155 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rust_decimal::Decimal;
+
+use crate::account::ClientId;
+use crate::decimal::Decimal4;
+use crate::engine::{EngineError, Operation};
+use crate::storage::DbError;
+
+/// Whether the log fields are redacted, off by default. A process-wide switch, like the tracing subscriber itself.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns the redaction of the personal data in the logs and traces on or off, see the `redact_pii` setting of `EngineConfig`.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+const MASK: &str = "***";
+
+/// A log field that is masked when redacting.
+#[derive(Debug, Clone, Copy)]
+pub struct Masked<T>(T);
+
+impl<T: Display> Display for Masked<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match is_enabled() {
+            true => f.write_str(MASK),
+            false => self.0.fmt(f),
+        }
+    }
+}
+
+/// An account id, masked when redacting.
+pub fn client(id: ClientId) -> Masked<ClientId> {
+    Masked(id)
+}
+
+/// A free-form message that may quote the input or the stored values, masked when redacting.
+pub fn text<T: Display>(text: T) -> Masked<T> {
+    Masked(text)
+}
+
+/// An amount, replaced by its order of magnitude when redacting, e.g. `100..1000` for `250.5`.
+#[derive(Debug, Clone, Copy)]
+pub struct Bucketed(Decimal4);
+
+impl Display for Bucketed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !is_enabled() {
+            return self.0.fmt(f);
+        }
+        let value = Decimal::from(self.0);
+        let lower = match value.abs().trunc().mantissa() {
+            0 => 0,
+            x => 10u128.pow(x.ilog10()),
+        };
+        let upper = if lower == 0 { 1 } else { lower * 10 };
+        match value.is_sign_negative() {
+            true => write!(f, "-{}..-{}", upper, lower),
+            false => write!(f, "{}..{}", lower, upper),
+        }
+    }
+}
+
+pub fn amount(amount: Decimal4) -> Bucketed {
+    Bucketed(amount)
+}
+
+/// An error, with the free-form details of the storage errors masked when redacting.
+#[derive(Debug, Clone, Copy)]
+pub struct RedactedError<'a, E>(&'a E);
+
+impl Display for RedactedError<'_, EngineError> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            EngineError::DatabaseError(_) if is_enabled() => write!(f, "database error: {}", MASK),
+            err => err.fmt(f),
+        }
+    }
+}
+
+impl Display for RedactedError<'_, DbError> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            DbError::DatabaseError(_) if is_enabled() => write!(f, "database error: {}", MASK),
+            err => err.fmt(f),
+        }
+    }
+}
+
+pub fn error<E>(err: &E) -> RedactedError<'_, E> {
+    RedactedError(err)
+}
+
+/// An operation shown like its `Debug`, with the account id masked and the amount bucketed when redacting.
+#[derive(Debug, Clone, Copy)]
+pub struct RedactedOperation<'a>(&'a Operation);
+
+impl Display for RedactedOperation<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !is_enabled() {
+            return write!(f, "{:?}", self.0);
+        }
+        match self.0 {
+            Operation::Deposit { tx_id, amount: x, .. } => write!(f, "Deposit {{ acc_id: {}, tx_id: {}, amount: {} }}", MASK, tx_id, amount(Decimal4::from(*x))),
+            Operation::Withdraw { tx_id, amount: x, .. } => write!(f, "Withdraw {{ acc_id: {}, tx_id: {}, amount: {} }}", MASK, tx_id, amount(Decimal4::from(*x))),
+            Operation::Dispute { tx_id, .. } => write!(f, "Dispute {{ acc_id: {}, tx_id: {} }}", MASK, tx_id),
+            Operation::Resolve { tx_id, .. } => write!(f, "Resolve {{ acc_id: {}, tx_id: {} }}", MASK, tx_id),
+            Operation::Chargeback { tx_id, .. } => write!(f, "Chargeback {{ acc_id: {}, tx_id: {} }}", MASK, tx_id),
+            Operation::Escrow { tx_id, bucket, amount: x, .. } => write!(f, "Escrow {{ acc_id: {}, tx_id: {}, bucket: {:?}, amount: {} }}", MASK, tx_id, bucket, amount(Decimal4::from(*x))),
+            Operation::ReleaseEscrow { tx_id, bucket, amount: x, .. } => write!(f, "ReleaseEscrow {{ acc_id: {}, tx_id: {}, bucket: {:?}, amount: {} }}", MASK, tx_id, bucket, amount(Decimal4::from(*x))),
+            Operation::Authorize { tx_id, amount: x, .. } => write!(f, "Authorize {{ acc_id: {}, tx_id: {}, amount: {} }}", MASK, tx_id, amount(Decimal4::from(*x))),
+            Operation::Capture { tx_id, .. } => write!(f, "Capture {{ acc_id: {}, tx_id: {} }}", MASK, tx_id),
+            Operation::Expire { tx_id, .. } => write!(f, "Expire {{ acc_id: {}, tx_id: {} }}", MASK, tx_id),
+        }
+    }
+}
+
+pub fn operation(operation: &Operation) -> RedactedOperation<'_> {
+    RedactedOperation(operation)
+}
+
+#[cfg(test)]
+mod redact_tests {
+    use super::*;
+
+    #[test]
+    fn redacted_fields() {
+        let deposit = Operation::Deposit { acc_id: 7, tx_id: 1, amount: Decimal::from(250) };
+        let storage_error = EngineError::DatabaseError("Invalid amount: 12x".to_string());
+        let render = || {
+            let amounts = [Decimal4::from(250), Decimal4::from_minor_units(5000), Decimal4::from(-42), Decimal4::from(1000)].map(|x| amount(x).to_string());
+            (client(7).to_string(), amounts, error(&storage_error).to_string(), error(&EngineError::InsufficientFunds).to_string(), operation(&deposit).to_string())
+        };
+
+        // NOTE: the switch is process-wide, no other test depends on the rendered fields
+        set_enabled(true);
+        let redacted = render();
+        set_enabled(false);
+        assert_eq!(redacted, (
+            "***".to_string(),
+            ["100..1000", "0..1", "-100..-10", "1000..10000"].map(str::to_string),
+            "database error: ***".to_string(),
+            "insufficient funds".to_string(),
+            "Deposit { acc_id: ***, tx_id: 1, amount: 100..1000 }".to_string(),
+        ));
+        let plain = render();
+        assert_eq!((plain.0, plain.2, plain.4), ("7".to_string(), "database error: Invalid amount: 12x".to_string(), format!("{:?}", deposit)));
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+
+use crate::account::{Account, AccountUpdateError, ClientId, SystemAccount};
+use crate::decimal::Decimal4;
+use crate::engine::{EngineError, Operation};
+use crate::journal::JournalEntry;
+use crate::transaction::{Transaction, TransactionState, TransactionType, TxId};
+
+/// Accounts and transactions rebuilt by re-applying journaled operations from scratch, including the postings
+/// to the [`SystemAccount`]s recorded in the transactions (the fees and the written-off chargebacks).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayState {
+    accounts: BTreeMap<ClientId, Account>,
+    system_accounts: BTreeMap<SystemAccount, Account>,
+    transactions: BTreeMap<TxId, Transaction>,
+    last_seq: u64,
+}
+
+impl ReplayState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn accounts(&self) -> &BTreeMap<ClientId, Account> {
+        &self.accounts
+    }
+
+    pub fn system_accounts(&self) -> &BTreeMap<SystemAccount, Account> {
+        &self.system_accounts
+    }
+
+    pub fn transactions(&self) -> &BTreeMap<TxId, Transaction> {
+        &self.transactions
+    }
+
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Re-applies the journaled operation and returns `true` if the resulting state equals the state recorded in the entry.
+    pub fn apply(&mut self, entry: &JournalEntry) -> Result<bool, EngineError> {
+        if entry.seq() != self.last_seq + 1 {
+            return Err(EngineError::CorruptedJournal(entry.seq()));
+        }
+
+        let (mut acc, tx) = match entry.operation() {
+            &Operation::Deposit { acc_id, tx_id, amount } => {
+                let amount = Decimal4::from(amount);
+                let mut acc = self.accounts.get(&acc_id).cloned().unwrap_or(Account::new(acc_id));
+                acc.copy_status_from(entry.account()); // NOTE: the admins can change the status between the operations
+                acc.deposit(amount)?;
+                (acc, Transaction::new(tx_id, acc_id, TransactionType::Deposit, amount)
+                    .with_created_at(entry.timestamp())
+                    .with_external_id(entry.transaction().external_id().map(str::to_string))
+                    .with_pending_review(entry.transaction().pending_review()))
+            }
+            &Operation::Withdraw { acc_id, tx_id, amount } => {
+                let amount = Decimal4::from(amount);
+                let mut acc = self.accounts.get(&acc_id).cloned().ok_or(EngineError::AccountNotFound)?;
+                let fee = entry.transaction().fee(); // NOTE: it depends on the policy of the engine that journaled it
+                acc.copy_status_from(entry.account());
+                acc.withdraw(amount.checked_add(fee).map_err(AccountUpdateError::from)?)?;
+                self.post(SystemAccount::Fees, fee, entry.timestamp())?;
+                (acc, Transaction::new(tx_id, acc_id, TransactionType::Withdrawal, amount)
+                    .with_created_at(entry.timestamp())
+                    .with_external_id(entry.transaction().external_id().map(str::to_string))
+                    .with_pending_review(entry.transaction().pending_review())
+                    .with_fee(fee))
+            }
+            &Operation::Dispute { acc_id, tx_id } => self.apply_tx_state(entry, acc_id, tx_id, TransactionState::Disputed, false)?,
+            &Operation::Resolve { acc_id, tx_id } => self.apply_tx_state(entry, acc_id, tx_id, TransactionState::Posted, false)?,
+            &Operation::Chargeback { acc_id, tx_id } => self.apply_tx_state(entry, acc_id, tx_id, TransactionState::Chargeback, entry.account().locked())?,
+            Operation::Escrow { acc_id, tx_id, bucket, amount } => {
+                let amount = Decimal4::from(*amount);
+                let mut acc = self.accounts.get(acc_id).cloned().ok_or(EngineError::AccountNotFound)?;
+                acc.copy_status_from(entry.account());
+                acc.escrow(bucket, amount)?;
+                (acc, Transaction::new(*tx_id, *acc_id, TransactionType::Escrow, amount)
+                    .with_created_at(entry.timestamp())
+                    .with_escrow_bucket(Some(bucket.clone())))
+            }
+            Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount } => {
+                let amount = Decimal4::from(*amount);
+                let mut acc = self.accounts.get(acc_id).cloned().ok_or(EngineError::AccountNotFound)?;
+                acc.copy_status_from(entry.account());
+                acc.release_escrow(bucket, amount)?;
+                (acc, Transaction::new(*tx_id, *acc_id, TransactionType::EscrowRelease, amount)
+                    .with_created_at(entry.timestamp())
+                    .with_escrow_bucket(Some(bucket.clone())))
+            }
+            &Operation::Authorize { acc_id, tx_id, amount } => {
+                let amount = Decimal4::from(amount);
+                let mut acc = self.accounts.get(&acc_id).cloned().ok_or(EngineError::AccountNotFound)?;
+                acc.copy_status_from(entry.account());
+                acc.authorize(amount)?;
+                (acc, Transaction::new(tx_id, acc_id, TransactionType::Authorization, amount)
+                    .with_created_at(entry.timestamp())
+                    .with_expires_at(entry.transaction().expires_at())) // NOTE: it depends on the policy of the engine that journaled it
+            }
+            &Operation::Capture { acc_id, tx_id } => self.apply_tx_state(entry, acc_id, tx_id, TransactionState::Captured, false)?,
+            &Operation::Expire { acc_id, tx_id } => self.apply_tx_state(entry, acc_id, tx_id, TransactionState::Expired, false)?,
+        };
+
+        acc.copy_details_from(entry.account()); // NOTE: the metadata and the timestamps are not derived from the operations
+        let matches = &acc == entry.account() && &tx == entry.transaction();
+        self.accounts.insert(acc.id(), acc);
+        self.transactions.insert(tx.id(), tx);
+        self.last_seq = entry.seq();
+        Ok(matches)
+    }
+
+    /// `lock` tells whether a chargeback locked the account: it depends on the policy of the engine that journaled it,
+    /// like the shortfall of a dispute and the written-off amount recorded in the transaction.
+    fn apply_tx_state(&mut self, entry: &JournalEntry, acc_id: ClientId, tx_id: TxId, state: TransactionState, lock: bool) -> Result<(Account, Transaction), EngineError> {
+        let mut tx = self.transactions.get(&tx_id).cloned().ok_or(EngineError::TransactionNotFound)?;
+        if tx.account_id() != acc_id {
+            return Err(EngineError::TransactionIsBoundToAnotherAccount(tx.account_id()));
+        }
+        let mut acc = self.accounts.get(&acc_id).cloned().ok_or(EngineError::AccountNotFound)?;
+        acc.copy_status_from(entry.account());
+        tx.transition(state, entry.operation().clone(), entry.timestamp())?;
+        match state {
+            TransactionState::Disputed => {
+                if entry.transaction().shortfall().is_positive() {
+                    let shortfall = acc.dispute_shortfall(tx.amount());
+                    tx = tx.with_shortfall(shortfall);
+                }
+                acc.dispute_with_shortfall(tx.amount(), tx.shortfall())?;
+            }
+            TransactionState::Posted => {
+                acc.resolve_with_shortfall(tx.amount(), tx.shortfall())?;
+                tx = tx.with_shortfall(Decimal4::zero());
+            }
+            TransactionState::Chargeback => acc.chargeback_with_shortfall(tx.amount(), tx.shortfall(), lock)?,
+            TransactionState::Captured => acc.capture(tx.amount())?,
+            TransactionState::Expired => acc.resolve(tx.amount())?,
+        }
+        if state == TransactionState::Chargeback && entry.transaction().written_off().is_positive() {
+            tx = tx.with_written_off(acc.write_off());
+            self.post(SystemAccount::ChargebackLosses, Decimal4::zero() - tx.written_off(), entry.timestamp())?;
+        }
+        Ok((acc, tx))
+    }
+
+    fn post(&mut self, account: SystemAccount, amount: Decimal4, timestamp: u64) -> Result<(), EngineError> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        let acc = self.system_accounts.entry(account).or_default();
+        acc.post(amount)?;
+        acc.touch(timestamp);
+        Ok(())
+    }
+}
+
+/// A position in the journal history: either a sequence number or a unix millis timestamp, both inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointInTime {
+    Seq(u64),
+    Timestamp(u64),
+}
+
+impl PointInTime {
+    pub fn includes(&self, entry: &JournalEntry) -> bool {
+        match *self {
+            PointInTime::Seq(seq) => entry.seq() <= seq,
+            PointInTime::Timestamp(timestamp) => entry.timestamp() <= timestamp,
+        }
+    }
+}
+
+/// A difference between the state rebuilt from the journal and the state found in storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// Re-applying the operation produced a different state than the one recorded in the journal entry.
+    JournalEntryMismatch { seq: u64 },
+    AccountMismatch { expected: Account, actual: Option<Account> },
+    SystemAccountMismatch { account: SystemAccount, expected: Account, actual: Option<Account> },
+    UnexpectedAccount(Account),
+    TransactionMismatch { expected: Transaction, actual: Option<Transaction> },
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use rust_decimal::Decimal;
+
+    use crate::decimal::Decimal4;
+
+    use super::*;
+
+    fn deposit_entry(seq: u64) -> JournalEntry {
+        let mut acc = Account::new(1);
+        acc.deposit(Decimal4::from(100)).unwrap();
+        let tx = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(100));
+        JournalEntry::new(seq, 0, Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) }, acc, tx)
+    }
+
+    #[test]
+    fn apply_deposit_ok() {
+        let mut state = ReplayState::new();
+        assert_eq!(state.apply(&deposit_entry(1)), Ok(true));
+        assert_eq!(state.accounts()[&1].available(), Decimal4::from(100));
+        assert_eq!(state.transactions()[&1].amount(), Decimal4::from(100));
+        assert_eq!(state.last_seq(), 1);
+    }
+
+    #[test]
+    fn apply_mismatching_entry() {
+        let mut state = ReplayState::new();
+        let entry = JournalEntry::new(1, 0, Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) }, Account::new(1), Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(100)));
+        assert_eq!(state.apply(&entry), Ok(false));
+    }
+
+    #[test]
+    fn apply_gap_in_seq_err() {
+        let mut state = ReplayState::new();
+        assert_eq!(state.apply(&deposit_entry(2)), Err(EngineError::CorruptedJournal(2)));
+    }
+
+    #[test]
+    fn point_in_time_includes() {
+        let entry = deposit_entry(5);
+        assert!(PointInTime::Seq(5).includes(&entry));
+        assert!(!PointInTime::Seq(4).includes(&entry));
+        assert!(PointInTime::Timestamp(0).includes(&entry));
+    }
+
+    #[test]
+    fn apply_dispute_on_unknown_tx_err() {
+        let mut state = ReplayState::new();
+        let entry = JournalEntry::new(1, 0, Operation::Dispute { acc_id: 1, tx_id: 1 }, Account::new(1), Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(100)));
+        assert_eq!(state.apply(&entry), Err(EngineError::TransactionNotFound));
+    }
+}
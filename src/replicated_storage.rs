@@ -0,0 +1,430 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::channel::mpsc;
+use futures::StreamExt;
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::file_storage::{restore, LogRecord};
+use crate::journal::{Journal, JournalEntry};
+use crate::redact;
+use crate::runtime::{self, JoinHandle};
+use crate::storage::{DbError, Savepoint, Savepoints, Storage, TxOptions};
+use crate::transaction::{Transaction, TxId};
+
+/// Gauge of the storage transactions committed on the leader but not applied to a follower yet, labeled with `follower` (the index).
+#[cfg(feature = "metrics")]
+pub const REPLICATION_LAG_METRIC: &str = "storage_replication_lag";
+
+/// Storage decorator that replicates every committed storage transaction of the `leader` to the followers, e.g. a `FileStorage`
+/// kept as a warm standby of an in-memory store. The writes of a storage transaction are recorded while it runs, and applied
+/// to every follower as one storage transaction by a background task after the leader commits, in the commit order.
+///
+/// The reads and the commits only wait for the leader, so the followers lag behind ([`ReplicatedStorage::status`]).
+/// A batch that fails on a follower (e.g. a follower that was written to directly) stops the replication to that follower.
+/// [`ReplicatedStorage::promote_follower`] waits for a follower to catch up and returns it to replace the leader.
+pub struct ReplicatedStorage<L, F> {
+    leader: L,
+    followers: Vec<Follower<F>>,
+    commit_lock: futures::lock::Mutex<()>, // NOTE: keeps the batches in the commit order of the leader
+}
+
+/// Db transaction of the `ReplicatedStorage`, holds the writes made so far until the commit.
+pub struct ReplicatedDbTx<T> {
+    inner: T,
+    records: Vec<LogRecord>,
+    savepoints: Savepoints<usize>, // NOTE: the number of records at each savepoint
+}
+
+/// The replication state of a follower.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FollowerStatus {
+    /// The storage transactions committed on the leader and not applied to the follower yet.
+    pub lag: u64,
+    /// The error that stopped the replication to the follower, it no longer follows the leader.
+    pub error: Option<String>,
+}
+
+struct Follower<F> {
+    storage: Arc<F>,
+    sender: mpsc::UnboundedSender<Arc<Vec<LogRecord>>>,
+    progress: Arc<Progress>,
+    task: Mutex<JoinHandle<()>>, // NOTE: the handle is not `Sync`
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    sent: AtomicU64,
+    applied: AtomicU64,
+    error: Mutex<Option<String>>,
+}
+
+impl Progress {
+    fn lag(&self) -> u64 {
+        self.sent.load(Ordering::SeqCst).saturating_sub(self.applied.load(Ordering::SeqCst))
+    }
+
+    fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+
+    #[cfg(feature = "metrics")]
+    fn record_lag(&self, follower: usize) {
+        metrics::gauge!(REPLICATION_LAG_METRIC, "follower" => follower.to_string()).set(self.lag() as f64);
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    fn record_lag(&self, _follower: usize) {}
+}
+
+async fn apply<F>(follower: &F, batch: &[LogRecord]) -> Result<(), DbError>
+where
+    F: Storage + Journal + Sync,
+    F::DbTx: Send,
+{
+    let mut db_tx = follower.start_db_tx(TxOptions::read_write()).await?;
+    for record in batch.iter().cloned() {
+        restore(follower, &mut db_tx, record).await?;
+    }
+    follower.commit_db_tx(db_tx).await
+}
+
+impl<L, F> ReplicatedStorage<L, F>
+where
+    L: Storage + Sync,
+    L::DbTx: Send,
+    F: Storage + Journal + Send + Sync + 'static,
+    F::DbTx: Send,
+{
+    /// The followers must start with the same state as the leader, e.g. empty or copied with `migrate`.
+    pub fn new(leader: L) -> Self {
+        Self { leader, followers: Vec::new(), commit_lock: futures::lock::Mutex::new(()) }
+    }
+
+    /// Adds a follower and spawns the task replicating to it.
+    pub fn with_follower(mut self, follower: F) -> Self {
+        let index = self.followers.len();
+        let storage = Arc::new(follower);
+        let progress = Arc::new(Progress::default());
+        let (sender, mut receiver) = mpsc::unbounded::<Arc<Vec<LogRecord>>>();
+        let task = runtime::spawn({
+            let (storage, progress) = (storage.clone(), progress.clone());
+            async move {
+                while let Some(batch) = receiver.next().await {
+                    if progress.error().is_some() {
+                        continue;
+                    }
+                    match apply(storage.as_ref(), &batch).await {
+                        Ok(()) => {
+                            progress.applied.fetch_add(1, Ordering::SeqCst);
+                            progress.record_lag(index);
+                        }
+                        Err(err) => {
+                            tracing::error!(follower = index, error = %redact::error(&err), "replication to the follower stopped");
+                            *progress.error.lock().unwrap() = Some(err.to_string());
+                        }
+                    }
+                }
+            }
+        });
+        self.followers.push(Follower { storage, sender, progress, task: Mutex::new(task) });
+        self
+    }
+
+    pub fn leader(&self) -> &L {
+        &self.leader
+    }
+
+    /// # Panics
+    /// Panics if there is no follower with the given index.
+    pub fn follower(&self, index: usize) -> &F {
+        &self.followers[index].storage
+    }
+
+    /// The replication state of every follower, in the order they were added.
+    pub fn status(&self) -> Vec<FollowerStatus> {
+        self.followers.iter().map(|x| FollowerStatus { lag: x.progress.lag(), error: x.progress.error() }).collect()
+    }
+
+    /// Waits until every follower applied the storage transactions committed so far, or stopped with an error.
+    pub async fn catch_up(&self) {
+        while self.followers.iter().any(|x| x.progress.lag() > 0 && x.progress.error().is_none()) {
+            runtime::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    /// Stops the replication, waits for the follower to apply the storage transactions committed so far and returns it,
+    /// to be used as the new leader. Fails if the replication to the follower stopped with an error.
+    ///
+    /// # Panics
+    /// Panics if there is no follower with the given index.
+    pub async fn promote_follower(mut self, index: usize) -> Result<F, DbError> {
+        let follower = self.followers.swap_remove(index);
+        drop(follower.sender);
+        let _ = follower.task.into_inner().unwrap().await;
+        if let Some(err) = follower.progress.error() {
+            return Err(DbError::DatabaseError(format!("follower {} is out of sync: {}", index, err)));
+        }
+        Arc::try_unwrap(follower.storage).map_err(|_| DbError::DatabaseError(format!("follower {} is still in use", index)))
+    }
+}
+
+impl<L, F> Storage for ReplicatedStorage<L, F>
+where
+    L: Storage + Sync,
+    L::DbTx: Send,
+    F: Storage + Journal + Send + Sync + 'static,
+    F::DbTx: Send,
+{
+    type DbTx = ReplicatedDbTx<L::DbTx>;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        self.leader.get_tx(&mut db_tx.inner, tx_id).await
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        self.leader.insert_tx(&mut db_tx.inner, tx).await?;
+        db_tx.records.push(LogRecord::Transaction(tx.clone()));
+        Ok(())
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        self.leader.update_tx(&mut db_tx.inner, old_tx, new_tx).await?;
+        db_tx.records.push(LogRecord::Transaction(new_tx.clone()));
+        Ok(())
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        self.leader.get_all_txs(&mut db_tx.inner).await
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        self.leader.get_txs(&mut db_tx.inner, tx_ids).await
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        self.leader.insert_txs(&mut db_tx.inner, txs).await?;
+        db_tx.records.extend(txs.iter().cloned().map(LogRecord::Transaction));
+        Ok(())
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        let deleted = self.leader.delete_txs(&mut db_tx.inner, tx_ids).await?;
+        if deleted > 0 {
+            db_tx.records.push(LogRecord::DeletedTransactions(tx_ids.to_vec()));
+        }
+        Ok(deleted)
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        self.leader.get_txs_by_account(&mut db_tx.inner, acc_id, cursor, limit).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        self.leader.get_tx_by_external_id(&mut db_tx.inner, external_id).await
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        self.leader.get_expired_authorizations(&mut db_tx.inner, now).await
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        self.leader.get_account(&mut db_tx.inner, acc_id).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        self.leader.get_all_accounts(&mut db_tx.inner).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        self.leader.get_accounts(&mut db_tx.inner, acc_ids).await
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        self.leader.insert_accounts(&mut db_tx.inner, accs).await?;
+        db_tx.records.extend(accs.iter().cloned().map(LogRecord::Account));
+        Ok(())
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        self.leader.list_accounts(&mut db_tx.inner, cursor, limit).await
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        self.leader.insert_account(&mut db_tx.inner, acc).await?;
+        db_tx.records.push(LogRecord::Account(acc.clone()));
+        Ok(())
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        self.leader.update_account(&mut db_tx.inner, old_acc, new_acc).await?;
+        db_tx.records.push(LogRecord::Account(new_acc.clone()));
+        Ok(())
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        self.leader.get_system_account(&mut db_tx.inner, account).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        self.leader.set_system_account(&mut db_tx.inner, account, acc).await?;
+        db_tx.records.push(LogRecord::SystemAccount { account, acc: acc.clone() });
+        Ok(())
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        self.leader.is_operation_processed(&mut db_tx.inner, op_hash).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        self.leader.insert_operation(&mut db_tx.inner, op_hash, timestamp).await?;
+        db_tx.records.push(LogRecord::Operation { op_hash, timestamp });
+        Ok(())
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        self.leader.get_all_operations(&mut db_tx.inner).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        let pruned = self.leader.prune_operations(&mut db_tx.inner, older_than).await?;
+        if pruned > 0 {
+            db_tx.records.push(LogRecord::PrunedOperations { older_than });
+        }
+        Ok(pruned)
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        self.leader.get_operation_rejection(&mut db_tx.inner, op_hash).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        self.leader.insert_operation_rejection(&mut db_tx.inner, op_hash, code, timestamp).await?;
+        db_tx.records.push(LogRecord::OperationRejection { op_hash, code, timestamp });
+        Ok(())
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        self.leader.get_checkpoint(&mut db_tx.inner, source).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        self.leader.set_checkpoint(&mut db_tx.inner, source, rows).await?;
+        db_tx.records.push(LogRecord::Checkpoint { source: source.to_string(), rows });
+        Ok(())
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        let inner = self.leader.start_db_tx(options).await?;
+        Ok(ReplicatedDbTx { inner, records: Vec::new(), savepoints: Savepoints::default() })
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        let _guard = self.commit_lock.lock().await;
+        self.leader.commit_db_tx(db_tx.inner).await?;
+        if db_tx.records.is_empty() {
+            return Ok(());
+        }
+        let batch = Arc::new(db_tx.records);
+        for (index, follower) in self.followers.iter().enumerate() {
+            follower.progress.sent.fetch_add(1, Ordering::SeqCst);
+            follower.progress.record_lag(index);
+            let _ = follower.sender.unbounded_send(batch.clone()); // NOTE: only fails once the follower is promoted
+        }
+        Ok(())
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        let savepoint = self.leader.savepoint(&mut db_tx.inner).await?;
+        db_tx.savepoints.insert(savepoint, db_tx.records.len());
+        Ok(savepoint)
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        self.leader.rollback_to(&mut db_tx.inner, savepoint).await?;
+        let records_len = *db_tx.savepoints.rollback_to(savepoint)?;
+        db_tx.records.truncate(records_len);
+        Ok(())
+    }
+}
+
+impl<L, F> Journal for ReplicatedStorage<L, F>
+where
+    L: Journal + Sync,
+    L::DbTx: Send,
+    F: Storage + Journal + Send + Sync + 'static,
+    F::DbTx: Send,
+{
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        self.leader.get_last_journal_seq(&mut db_tx.inner).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        self.leader.append_journal_entry(&mut db_tx.inner, entry).await?;
+        db_tx.records.push(LogRecord::JournalEntry(Box::new(entry.clone())));
+        Ok(())
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        self.leader.get_journal_entries(&mut db_tx.inner, from_seq, limit).await
+    }
+}
+
+#[cfg(test)]
+mod replicated_storage_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    async fn accounts<S: Storage>(storage: &S) -> Vec<Account> {
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let mut accounts = storage.get_all_accounts(&mut db_tx).await.unwrap();
+        accounts.sort_by_key(Account::id);
+        accounts
+    }
+
+    #[tokio::test]
+    async fn followers_replicate_and_promote() {
+        let storage = ReplicatedStorage::new(EchoDbStorage::new()).with_follower(EchoDbStorage::new()).with_follower(EchoDbStorage::new());
+        let engine = Engine::new(storage);
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.dispute(2, 2).await, Ok(()));
+
+        let storage = engine.storage();
+        storage.catch_up().await;
+        assert_eq!(storage.status(), vec![FollowerStatus { lag: 0, error: None }; 2]);
+        let leader_accounts = accounts(storage.leader()).await;
+        assert_eq!(accounts(storage.follower(1)).await, leader_accounts);
+
+        let storage = engine.into_storage().unwrap();
+        let engine = Engine::new(storage.promote_follower(1).await.unwrap());
+        assert_eq!(engine.get_all_accounts().await.unwrap().len(), 2);
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(())); // the idempotency records are replicated too
+        assert_eq!(engine.resolve(2, 2).await, Ok(()));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn diverged_follower_stops() {
+        let follower = EchoDbStorage::new();
+        let engine = Engine::new(follower);
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(5)).await, Ok(()));
+        let follower = engine.into_storage().unwrap();
+
+        let engine = Engine::new(ReplicatedStorage::new(EchoDbStorage::new()).with_follower(follower));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(100)).await, Ok(()));
+        engine.storage().catch_up().await;
+        let status = engine.storage().status();
+        assert_eq!(status[0].lag, 2);
+        assert!(status[0].error.is_some());
+
+        let storage = engine.into_storage().unwrap();
+        assert!(storage.promote_follower(0).await.is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+
+use crate::account::Account;
+use crate::engine::Operation;
+use crate::journal::Provenance;
+
+/// The verdict of a [`RiskAssessor`] on a deposit or a withdrawal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RiskDecision {
+    /// The operation is applied as usual.
+    #[default]
+    Allow,
+    /// The operation is applied, and its transaction is flagged as pending a manual review (`Transaction::pending_review`).
+    Review,
+    /// The operation is rejected with `EngineError::RiskDenied`.
+    Deny,
+}
+
+/// A hook for an external fraud engine, consulted by the engine before a deposit or a withdrawal is committed.
+///
+/// Only the operations that pass every other check are assessed, and the assessment runs inside the storage transaction
+/// of the operation, so a slow assessor delays the other operations of the storage: call the remote services with a timeout,
+/// and decide in the implementation whether a failed call allows or denies (fails open or closed).
+#[async_trait]
+pub trait RiskAssessor: Send + Sync {
+    /// `account` is the state of the account before the operation, `None` for the first deposit of a new account.
+    async fn assess(&self, operation: &Operation, account: Option<&Account>, provenance: Option<&Provenance>) -> RiskDecision;
+}
+
+#[cfg(test)]
+mod risk_tests {
+    use std::sync::Arc;
+
+    use crate::decimal::Decimal4;
+    use crate::engine::{Engine, EngineError};
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    /// Reviews the amounts over 100 and denies the ones over 1000, or the withdrawals emptying the account.
+    struct ThresholdAssessor;
+
+    #[async_trait]
+    impl RiskAssessor for ThresholdAssessor {
+        async fn assess(&self, operation: &Operation, account: Option<&Account>, _provenance: Option<&Provenance>) -> RiskDecision {
+            let (Operation::Deposit { amount, .. } | Operation::Withdraw { amount, .. }) = operation else {
+                return RiskDecision::Allow;
+            };
+            let amount = Decimal4::from(*amount);
+            let empties = matches!(operation, Operation::Withdraw { .. }) && account.is_some_and(|x| x.available() == amount);
+            match amount {
+                x if x > Decimal4::from(1000) || empties => RiskDecision::Deny,
+                x if x > Decimal4::from(100) => RiskDecision::Review,
+                _ => RiskDecision::Allow,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn assessed_operations() {
+        let engine = Engine::new(EchoDbStorage::new()).with_risk_assessor(Arc::new(ThresholdAssessor));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(500)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 3, Decimal4::from(5000)).await, Err(EngineError::RiskDenied));
+        assert_eq!(engine.withdraw(1, 4, Decimal4::from(550)).await, Err(EngineError::RiskDenied));
+        assert_eq!(engine.withdraw(1, 5, Decimal4::from(200)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 2).await, Ok(()));
+
+        assert_eq!(engine.get_tx(3).await, Ok(None));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().total().unwrap(), Decimal4::from(350));
+        let reviews = engine.get_pending_reviews().await.unwrap();
+        assert_eq!(reviews.iter().map(|x| x.id()).collect::<Vec<_>>(), vec![2, 5]);
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+}
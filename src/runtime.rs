@@ -0,0 +1,107 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::FutureExt;
+
+/// The task behind a [`JoinHandle`] was aborted or panicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinError;
+
+impl Display for JoinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the task was aborted or panicked")
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// A handle of a spawned task, resolving to its output. Dropping the handle detaches the task, [`JoinHandle::abort`] stops it.
+pub struct JoinHandle<T> {
+    output: Pin<Box<dyn Future<Output = Result<T, JoinError>> + Send>>,
+    abort: Option<futures::future::AbortHandle>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Stops the task at its next await point, a blocking task runs to completion.
+    pub fn abort(&self) {
+        if let Some(abort) = self.abort.as_ref() {
+            abort.abort();
+        }
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.output.as_mut().poll(cx)
+    }
+}
+
+impl<T> std::fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JoinHandle").finish_non_exhaustive()
+    }
+}
+
+/// Spawns a task on the runtime the library is built for: Tokio with the `tokio` feature (the default) or async-std
+/// with the `async-std` feature, Tokio when both are enabled. Without a runtime feature the core still compiles,
+/// only the background tasks (e.g. `Engine::spawn_operations_pruner`) are missing.
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub fn spawn<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> JoinHandle<T> {
+    let (abort, registration) = futures::future::AbortHandle::new_pair();
+    let future = futures::future::Abortable::new(future, registration);
+    #[cfg(feature = "tokio")]
+    let output = tokio::spawn(future).map(|x| x.map_err(|_| JoinError).and_then(|x| x.map_err(|_| JoinError))).boxed();
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    let output = async_std::task::spawn(future).map(|x| x.map_err(|_| JoinError)).boxed();
+    JoinHandle { output, abort: Some(abort) }
+}
+
+/// Waits for `duration` without blocking the runtime.
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub async fn sleep(duration: std::time::Duration) {
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(duration).await;
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    async_std::task::sleep(duration).await;
+}
+
+/// Runs blocking code (file reads, decompression, parsing) on a thread where blocking is fine: the blocking pool
+/// of the runtime, or a new thread without a runtime.
+pub fn spawn_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> JoinHandle<T> {
+    #[cfg(feature = "tokio")]
+    let output = tokio::task::spawn_blocking(f).map(|x| x.map_err(|_| JoinError)).boxed();
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    let output = async_std::task::spawn_blocking(f).map(Ok).boxed();
+    #[cfg(not(any(feature = "tokio", feature = "async-std")))]
+    let output = {
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(f());
+        });
+        receiver.map(|x| x.map_err(|_| JoinError)).boxed()
+    };
+    JoinHandle { output, abort: None }
+}
+
+#[cfg(test)]
+mod runtime_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn blocking_tasks() {
+        assert_eq!(spawn_blocking(|| 1 + 1).await, Ok(2));
+    }
+
+    #[cfg(any(feature = "tokio", feature = "async-std"))]
+    #[tokio::test]
+    async fn spawned_tasks() {
+        assert_eq!(spawn(async { 3 }).await, Ok(3));
+        let task = spawn(sleep(std::time::Duration::from_secs(60)));
+        task.abort();
+        assert_eq!(task.await, Err(JoinError));
+    }
+}
@@ -0,0 +1,136 @@
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use tower::retry::Policy;
+use tower::Service;
+
+use crate::engine::{Engine, EngineError, Operation};
+use crate::journal::Journal;
+use crate::queue::RetryPolicy;
+use crate::storage::Storage;
+
+/// The engine as a `tower::Service`, so the standard middleware (timeouts, concurrency and rate limits, retries with
+/// [`RetryPolicy`], ...) can be composed around the execution of the operations, e.g.
+///
+/// ```ignore
+/// let service = ServiceBuilder::new()
+///     .concurrency_limit(64)
+///     .retry(RetryPolicy::default())
+///     .timeout(Duration::from_secs(1))
+///     .service(engine.clone());
+/// ```
+///
+/// The engine is always ready, and every call executes the operation on a clone of the engine.
+impl<TStorage> Service<Operation> for Engine<TStorage>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    type Response = ();
+    type Error = EngineError;
+    type Future = BoxFuture<'static, Result<(), EngineError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, operation: Operation) -> Self::Future {
+        let engine = self.clone();
+        Box::pin(async move { engine.execute_operation(operation).await })
+    }
+}
+
+/// The batch variant: executes the operations one by one in the given order and returns the outcome of each of them.
+/// A rejected operation doesn't stop the batch, so the call itself never fails.
+impl<TStorage> Service<Vec<Operation>> for Engine<TStorage>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    type Response = Vec<Result<(), EngineError>>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, operations: Vec<Operation>) -> Self::Future {
+        let engine = self.clone();
+        Box::pin(async move {
+            let mut outcomes = Vec::with_capacity(operations.len());
+            for operation in operations {
+                outcomes.push(engine.execute_operation(operation).await);
+            }
+            Ok(outcomes)
+        })
+    }
+}
+
+/// Retries the transient errors (see [`EngineError::is_transient`]) with the exponential backoff of the policy,
+/// for the `tower::retry::Retry` middleware. Each request gets its own copy of the policy and its attempts.
+impl Policy<Operation, (), EngineError> for RetryPolicy {
+    type Future = tokio::time::Sleep;
+
+    fn retry(&mut self, _operation: &mut Operation, result: &mut Result<(), EngineError>) -> Option<Self::Future> {
+        match result {
+            Err(err) if err.is_transient() && self.attempts > 0 => {
+                self.attempts -= 1;
+                let backoff = self.initial_backoff;
+                self.initial_backoff *= 2;
+                Some(tokio::time::sleep(backoff))
+            }
+            _ => None,
+        }
+    }
+
+    fn clone_request(&mut self, operation: &Operation) -> Option<Operation> {
+        Some(operation.clone())
+    }
+}
+
+#[cfg(test)]
+mod service_tests {
+    use std::time::Duration;
+
+    use rust_decimal::Decimal;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    use crate::decimal::Decimal4;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn engine_as_service() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let mut service = ServiceBuilder::new()
+            .concurrency_limit(2)
+            .retry(RetryPolicy::default())
+            .service(engine.clone());
+        let deposit = Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) };
+        assert_eq!(service.ready().await.unwrap().call(deposit).await, Ok(()));
+        let withdrawal = Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(50) };
+        assert_eq!(service.ready().await.unwrap().call(withdrawal).await, Err(EngineError::InsufficientFunds));
+
+        let batch = vec![
+            Operation::Deposit { acc_id: 2, tx_id: 3, amount: Decimal::from(5) },
+            Operation::Dispute { acc_id: 2, tx_id: 9 },
+            Operation::Dispute { acc_id: 2, tx_id: 3 },
+        ];
+        let outcomes = engine.clone().oneshot(batch).await.unwrap();
+        assert_eq!(outcomes, vec![Ok(()), Err(EngineError::TransactionNotFound), Ok(())]);
+        assert_eq!(engine.get_account(2).await.unwrap().unwrap().held(), Decimal4::from(5));
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let mut policy = RetryPolicy { attempts: 2, initial_backoff: Duration::from_millis(1) };
+        let mut operation = Operation::Dispute { acc_id: 1, tx_id: 1 };
+        assert!(policy.retry(&mut operation, &mut Err(EngineError::InsufficientFunds)).is_none());
+        assert!(policy.retry(&mut operation, &mut Ok(())).is_none());
+        assert!(policy.retry(&mut operation, &mut Err(EngineError::ConcurrentOperationDetected)).is_some());
+        assert!(policy.retry(&mut operation, &mut Err(EngineError::DatabaseError(String::new()))).is_some());
+        assert_eq!(policy.initial_backoff, Duration::from_millis(4));
+        assert!(policy.retry(&mut operation, &mut Err(EngineError::ConcurrentOperationDetected)).is_none());
+    }
+}
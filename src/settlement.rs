@@ -0,0 +1,159 @@
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::account::ClientId;
+use crate::decimal::Decimal4;
+use crate::transaction::{Transaction, TransactionState, TransactionType};
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// The activity of an account in the settlement period netted into one record. The deposits and withdrawals count
+/// when they were created, the captured authorizations with the withdrawals when they were captured, the chargebacks
+/// when they were applied (even for an older deposit). Disputes, resolves, authorization holds and escrow moves keep
+/// the funds in the account, so they are not settled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SettlementRecord {
+    pub client: ClientId,
+    pub deposits: Decimal4,
+    pub withdrawals: Decimal4,
+    pub fees: Decimal4,
+    pub chargebacks: Decimal4,
+    /// The negative balances written off to the chargeback losses account by the chargebacks.
+    pub written_off: Decimal4,
+    /// The change of the total balance of the account: `deposits - withdrawals - fees - chargebacks + written_off`.
+    pub net: Decimal4,
+    /// The number of transactions netted into the record.
+    pub transactions: usize,
+}
+
+impl SettlementRecord {
+    /// Nets the transactions of an account, `None` if none of them falls into the `[from, to)` period (unix millis).
+    pub fn from_transactions<'a>(client: ClientId, from: u64, to: u64, transactions: impl IntoIterator<Item = &'a Transaction>) -> Option<Self> {
+        let zero = Decimal4::zero();
+        let mut record = Self { client, deposits: zero, withdrawals: zero, fees: zero, chargebacks: zero, written_off: zero, net: zero, transactions: 0 };
+        let in_period = |timestamp: u64| timestamp >= from && timestamp < to;
+        for tx in transactions {
+            if matches!(tx.tx_type(), TransactionType::Escrow | TransactionType::EscrowRelease) {
+                continue;
+            }
+            let created = in_period(tx.created_at());
+            let charged_back = tx.history().iter().any(|x| x.to == TransactionState::Chargeback && in_period(x.timestamp));
+            let captured = tx.history().iter().any(|x| x.to == TransactionState::Captured && in_period(x.timestamp));
+            match tx.tx_type() {
+                TransactionType::Deposit if created => record.deposits += tx.amount(),
+                TransactionType::Withdrawal if created => {
+                    record.withdrawals += tx.amount();
+                    record.fees += tx.fee();
+                }
+                TransactionType::Authorization if captured => record.withdrawals += tx.amount(),
+                _ => {}
+            }
+            if charged_back {
+                record.chargebacks += tx.amount();
+                record.written_off += tx.written_off();
+            }
+            if created || charged_back || captured {
+                record.transactions += 1;
+            }
+        }
+        record.net = record.deposits - record.withdrawals - record.fees - record.chargebacks + record.written_off;
+        (record.transactions > 0).then_some(record)
+    }
+}
+
+/// The settlement of a period: a record per account with activity, ordered by client id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Settlement {
+    pub from: u64,
+    pub to: u64,
+    pub records: Vec<SettlementRecord>,
+    /// The sum of the nets of the records.
+    pub net: Decimal4,
+}
+
+impl Settlement {
+    pub fn new(from: u64, to: u64, records: Vec<SettlementRecord>) -> Self {
+        let net = records.iter().fold(Decimal4::zero(), |net, x| net + x.net);
+        Self { from, to, records, net }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("settlement is always serializable")
+    }
+
+    /// Writes a row per account.
+    pub fn write_csv<W: Write>(&self, writer: W) -> csv::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for record in self.records.iter() {
+            writer.serialize(record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The `[from, to)` unix millis of a UTC day given as `YYYY-MM-DD`, `None` if it is not a valid date.
+pub fn day_range(date: &str) -> Option<(u64, u64)> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if year < 1970 || !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+    // NOTE: the days since the epoch of the civil date, see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146097 + day_of_era - 719468) as u64;
+    Some((days * DAY_MILLIS, (days + 1) * DAY_MILLIS))
+}
+
+#[cfg(test)]
+mod settlement_tests {
+    use crate::engine::Operation;
+
+    use super::*;
+
+    #[test]
+    fn record_nets_period() {
+        let deposit = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(100)).with_created_at(50);
+        let withdrawal = Transaction::new(2, 1, TransactionType::Withdrawal, Decimal4::from(30)).with_created_at(150).with_fee(Decimal4::from(1));
+        let mut charged_back = Transaction::new(3, 1, TransactionType::Deposit, Decimal4::from(20)).with_created_at(120).with_written_off(Decimal4::from(5));
+        charged_back.transition(TransactionState::Disputed, Operation::Dispute { acc_id: 1, tx_id: 3 }, 130).unwrap();
+        charged_back.transition(TransactionState::Chargeback, Operation::Chargeback { acc_id: 1, tx_id: 3 }, 140).unwrap();
+
+        let record = SettlementRecord::from_transactions(1, 100, 200, [&deposit, &withdrawal, &charged_back]).unwrap();
+        assert_eq!((record.deposits, record.withdrawals, record.fees), (Decimal4::from(20), Decimal4::from(30), Decimal4::from(1)));
+        assert_eq!((record.chargebacks, record.written_off, record.transactions), (Decimal4::from(20), Decimal4::from(5), 2));
+        assert_eq!(record.net, Decimal4::from(-26));
+        assert_eq!(SettlementRecord::from_transactions(1, 200, 300, [&deposit, &withdrawal]), None);
+
+        let mut data = Vec::new();
+        Settlement::new(100, 200, vec![record]).write_csv(&mut data).unwrap();
+        assert_eq!(String::from_utf8(data).unwrap(), "\
+client,deposits,withdrawals,fees,chargebacks,written_off,net,transactions
+1,20.0000,30.0000,1.0000,20.0000,5.0000,-26.0000,2
+");
+    }
+
+    #[test]
+    fn utc_day_range() {
+        assert_eq!(day_range("1970-01-01"), Some((0, DAY_MILLIS)));
+        assert_eq!(day_range("2024-02-29"), Some((1_709_164_800_000, 1_709_164_800_000 + DAY_MILLIS)));
+        assert_eq!(day_range("2023-02-29"), None);
+        assert_eq!(day_range("2024-13-01"), None);
+        assert_eq!(day_range("yesterday"), None);
+    }
+}
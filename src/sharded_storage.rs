@@ -0,0 +1,353 @@
+use sha2::{Digest, Sha256};
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, Savepoint, Storage, TxOptions};
+use crate::transaction::{Transaction, TxId};
+
+/// The points of every shard on the hash ring, more points spread the accounts more evenly.
+const VIRTUAL_NODES: u32 = 64;
+
+/// Storage split across several backends: every account, together with its transactions, lives in the shard picked by
+/// consistent hashing of the account id, so adding a shard moves only about `1/N` of the accounts (moving them is up to
+/// the operator, see [`ShardedStorage::shard_of`]). The idempotency records, the checkpoints and the journal live in the first shard.
+///
+/// The transaction lookups by id and the account listings fan out to all the shards. A storage transaction spans all the shards,
+/// which are committed one by one, the first shard last: a crash in between leaves the account and its transaction applied
+/// without the idempotency record and the journal entry, so a retried operation is rejected as a duplicate transaction instead
+/// of being applied twice. Two transactions with the same id inserted concurrently for accounts of different shards are both stored.
+pub struct ShardedStorage<S> {
+    shards: Vec<S>,
+    ring: Vec<(u64, usize)>, // NOTE: (point, shard) sorted by point
+}
+
+/// The storage transactions of every shard, in the shard order.
+pub struct ShardedDbTx<T>(Vec<T>);
+
+fn ring_hash(data: &[u8]) -> u64 {
+    let digest = Sha256::digest(data);
+    u64::from_be_bytes(digest[..8].try_into().expect("digest has 32 bytes"))
+}
+
+impl<S> ShardedStorage<S>
+where
+    S: Storage + Sync,
+    S::DbTx: Send,
+{
+    /// # Panics
+    /// Panics if `shards` is empty.
+    pub fn new(shards: Vec<S>) -> Self {
+        assert!(!shards.is_empty(), "at least one shard is required");
+        let mut ring: Vec<(u64, usize)> = (0..shards.len())
+            .flat_map(|shard| (0..VIRTUAL_NODES).map(move |node| (ring_hash(format!("shard-{}-{}", shard, node).as_bytes()), shard)))
+            .collect();
+        ring.sort();
+        Self { shards, ring }
+    }
+
+    pub fn shards(&self) -> &[S] {
+        &self.shards
+    }
+
+    /// The index of the shard holding the account. Stable across restarts, it only depends on the number of shards.
+    pub fn shard_of(&self, acc_id: ClientId) -> usize {
+        let hash = ring_hash(acc_id.to_string().as_bytes());
+        let i = self.ring.partition_point(|(point, _)| *point < hash);
+        self.ring[i % self.ring.len()].1
+    }
+}
+
+impl<S> Storage for ShardedStorage<S>
+where
+    S: Storage + Sync,
+    S::DbTx: Send,
+{
+    type DbTx = ShardedDbTx<S::DbTx>;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0.iter_mut()) {
+            if let Some(tx) = shard.get_tx(shard_tx, tx_id).await? {
+                return Ok(Some(tx));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        let i = self.shard_of(tx.account_id());
+        self.shards[i].insert_tx(&mut db_tx.0[i], tx).await
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        let i = self.shard_of(old_tx.account_id());
+        self.shards[i].update_tx(&mut db_tx.0[i], old_tx, new_tx).await
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        let mut txs = Vec::new();
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0.iter_mut()) {
+            txs.extend(shard.get_all_txs(shard_tx).await?);
+        }
+        Ok(txs)
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        let mut txs = vec![None; tx_ids.len()];
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0.iter_mut()) {
+            let found = shard.get_txs(shard_tx, tx_ids).await?;
+            for (tx, found) in txs.iter_mut().zip(found).filter(|(tx, _)| tx.is_none()) {
+                *tx = found;
+            }
+        }
+        Ok(txs)
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        for (i, (shard, shard_tx)) in self.shards.iter().zip(db_tx.0.iter_mut()).enumerate() {
+            let shard_txs: Vec<Transaction> = txs.iter().filter(|tx| self.shard_of(tx.account_id()) == i).cloned().collect();
+            if !shard_txs.is_empty() {
+                shard.insert_txs(shard_tx, &shard_txs).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        let mut deleted = 0;
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0.iter_mut()) {
+            deleted += shard.delete_txs(shard_tx, tx_ids).await?;
+        }
+        Ok(deleted)
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        let i = self.shard_of(acc_id);
+        self.shards[i].get_txs_by_account(&mut db_tx.0[i], acc_id, cursor, limit).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0.iter_mut()) {
+            if let Some(tx) = shard.get_tx_by_external_id(shard_tx, external_id).await? {
+                return Ok(Some(tx));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        let mut txs = Vec::new();
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0.iter_mut()) {
+            txs.extend(shard.get_expired_authorizations(shard_tx, now).await?);
+        }
+        txs.sort_by_key(|x| (x.hold_expires_at(), x.id()));
+        Ok(txs)
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        let i = self.shard_of(acc_id);
+        self.shards[i].get_account(&mut db_tx.0[i], acc_id).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        let mut accounts = Vec::new();
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0.iter_mut()) {
+            accounts.extend(shard.get_all_accounts(shard_tx).await?);
+        }
+        Ok(accounts)
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        let mut accounts = vec![None; acc_ids.len()];
+        for (i, (shard, shard_tx)) in self.shards.iter().zip(db_tx.0.iter_mut()).enumerate() {
+            let (positions, shard_ids): (Vec<usize>, Vec<ClientId>) = acc_ids.iter().enumerate().filter(|(_, x)| self.shard_of(**x) == i).map(|(pos, x)| (pos, *x)).unzip();
+            if shard_ids.is_empty() {
+                continue;
+            }
+            for (pos, account) in positions.into_iter().zip(shard.get_accounts(shard_tx, &shard_ids).await?) {
+                accounts[pos] = account;
+            }
+        }
+        Ok(accounts)
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        for (i, (shard, shard_tx)) in self.shards.iter().zip(db_tx.0.iter_mut()).enumerate() {
+            let shard_accs: Vec<Account> = accs.iter().filter(|acc| self.shard_of(acc.id()) == i).cloned().collect();
+            if !shard_accs.is_empty() {
+                shard.insert_accounts(shard_tx, &shard_accs).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the accounts shard by shard, each shard in its own storage order: a page continues in the shard of the cursor
+    /// and moves on to the next shards when that one is exhausted.
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        let (first, mut cursor) = match cursor {
+            Some(acc_id) => (self.shard_of(acc_id), Some(acc_id)),
+            None => (0, None),
+        };
+        let mut accounts = Vec::new();
+        for i in first..self.shards.len() {
+            if accounts.len() >= limit {
+                break;
+            }
+            accounts.extend(self.shards[i].list_accounts(&mut db_tx.0[i], cursor, limit - accounts.len()).await?);
+            cursor = None;
+        }
+        Ok(accounts)
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        let i = self.shard_of(acc.id());
+        self.shards[i].insert_account(&mut db_tx.0[i], acc).await
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        let i = self.shard_of(old_acc.id());
+        self.shards[i].update_account(&mut db_tx.0[i], old_acc, new_acc).await
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        self.shards[0].get_system_account(&mut db_tx.0[0], account).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        self.shards[0].set_system_account(&mut db_tx.0[0], account, acc).await
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        self.shards[0].is_operation_processed(&mut db_tx.0[0], op_hash).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        self.shards[0].insert_operation(&mut db_tx.0[0], op_hash, timestamp).await
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        self.shards[0].get_all_operations(&mut db_tx.0[0]).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        self.shards[0].prune_operations(&mut db_tx.0[0], older_than).await
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        self.shards[0].get_operation_rejection(&mut db_tx.0[0], op_hash).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        self.shards[0].insert_operation_rejection(&mut db_tx.0[0], op_hash, code, timestamp).await
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        self.shards[0].get_checkpoint(&mut db_tx.0[0], source).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        self.shards[0].set_checkpoint(&mut db_tx.0[0], source, rows).await
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        let mut db_txs = Vec::with_capacity(self.shards.len());
+        for shard in self.shards.iter() {
+            db_txs.push(shard.start_db_tx(options).await?);
+        }
+        Ok(ShardedDbTx(db_txs))
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        // NOTE: the first shard, with the idempotency records and the journal, is committed last
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0).rev() {
+            shard.commit_db_tx(shard_tx).await?;
+        }
+        Ok(())
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        let mut savepoint = Savepoint(0);
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0.iter_mut()) {
+            savepoint = shard.savepoint(shard_tx).await?; // NOTE: the same on every shard, they are all set here
+        }
+        Ok(savepoint)
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        for (shard, shard_tx) in self.shards.iter().zip(db_tx.0.iter_mut()) {
+            shard.rollback_to(shard_tx, savepoint).await?;
+        }
+        Ok(())
+    }
+}
+
+impl<S> Journal for ShardedStorage<S>
+where
+    S: Journal + Sync,
+    S::DbTx: Send,
+{
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        self.shards[0].get_last_journal_seq(&mut db_tx.0[0]).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        self.shards[0].append_journal_entry(&mut db_tx.0[0], entry).await
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        self.shards[0].get_journal_entries(&mut db_tx.0[0], from_seq, limit).await
+    }
+}
+
+#[cfg(test)]
+mod sharded_storage_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    fn sharded(n: usize) -> ShardedStorage<EchoDbStorage> {
+        ShardedStorage::new((0..n).map(|_| EchoDbStorage::new()).collect())
+    }
+
+    #[tokio::test]
+    async fn accounts_are_routed_to_shards() {
+        let engine = Engine::new(sharded(3));
+        for acc_id in 1..=30 {
+            assert_eq!(engine.deposit(acc_id, TxId::from(acc_id), Decimal4::from(100)).await, Ok(()));
+        }
+        assert_eq!(engine.dispute(7, 7).await, Ok(()));
+        assert_eq!(engine.deposit(8, 7, Decimal4::from(1)).await, Err(crate::engine::EngineError::TransactionWithTheSameIdAlreadyExists));
+
+        let storage = engine.storage();
+        for (i, shard) in storage.shards().iter().enumerate() {
+            let mut db_tx = shard.start_db_tx(TxOptions::default()).await.unwrap();
+            let accounts = shard.get_all_accounts(&mut db_tx).await.unwrap();
+            assert!(!accounts.is_empty());
+            assert!(accounts.iter().all(|x| storage.shard_of(x.id()) == i));
+            assert!(shard.get_all_txs(&mut db_tx).await.unwrap().iter().all(|x| storage.shard_of(x.account_id()) == i));
+        }
+        assert_eq!(engine.get_all_accounts().await.unwrap().len(), 30);
+
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let (mut listed, mut cursor) = (Vec::new(), None);
+        loop {
+            let page = storage.list_accounts(&mut db_tx, cursor, 7).await.unwrap();
+            let Some(last) = page.last() else { break };
+            cursor = Some(last.id());
+            listed.extend(page.iter().map(Account::id));
+        }
+        drop(db_tx);
+        listed.sort();
+        assert_eq!(listed, (1..=30).collect::<Vec<ClientId>>());
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[test]
+    fn adding_a_shard_moves_few_accounts() {
+        let (four, five) = (sharded(4), sharded(5));
+        let moved = (0..1000).filter(|x| four.shard_of(*x) != five.shard_of(*x)).count();
+        assert!(moved < 400, "{} accounts moved", moved);
+        assert!((0..1000).all(|x| four.shard_of(x) == five.shard_of(x) || five.shard_of(x) == 4));
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::account::Account;
+use crate::engine::{EngineError, Operation};
+use crate::observer::EngineObserver;
+use crate::transaction::Transaction;
+
+/// Completes on the first SIGINT (Ctrl-C) or SIGTERM, only on Ctrl-C outside of unix.
+///
+/// The server and consumer modes pass it as their `shutdown` future: they stop taking new operations, finish the ones
+/// in flight (so every started storage transaction is committed) and return.
+#[cfg(feature = "tokio")]
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(err) => tracing::warn!(error = %err, "error installing the SIGTERM handler"),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// The operations executed by an engine since the observer was registered, printed when a long-running mode shuts down.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OperationCounts {
+    pub applied: u64,
+    pub rejected: u64,
+    /// Applied operations per operation type.
+    pub by_operation: BTreeMap<&'static str, u64>,
+    /// Rejected operations per error.
+    pub by_error: BTreeMap<String, u64>,
+    pub elapsed: Duration,
+}
+
+impl Display for OperationCounts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "executed {} operations in {:.3}s: {} applied, {} rejected", self.applied + self.rejected, self.elapsed.as_secs_f64(), self.applied, self.rejected)?;
+        for (op_type, count) in self.by_operation.iter() {
+            write!(f, "\n  {}: {}", op_type, count)?;
+        }
+        for (error, count) in self.by_error.iter() {
+            write!(f, "\n  error `{}`: {}", error, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// An observer counting the applied and rejected operations, see [`OperationCounts`].
+#[derive(Debug)]
+pub struct OperationCounter {
+    started_at: Instant,
+    counts: Mutex<OperationCounts>,
+}
+
+impl Default for OperationCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OperationCounter {
+    pub fn new() -> Self {
+        Self { started_at: Instant::now(), counts: Mutex::new(OperationCounts::default()) }
+    }
+
+    pub fn counts(&self) -> OperationCounts {
+        let counts = self.counts.lock().unwrap();
+        OperationCounts { elapsed: self.started_at.elapsed(), ..counts.clone() }
+    }
+
+    fn applied(&self, op_type: &'static str) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.applied += 1;
+        *counts.by_operation.entry(op_type).or_default() += 1;
+    }
+}
+
+impl EngineObserver for OperationCounter {
+    fn on_deposit_applied(&self, _account: &Account, _transaction: &Transaction) {
+        self.applied("deposit");
+    }
+
+    fn on_withdrawal_applied(&self, _account: &Account, _transaction: &Transaction) {
+        self.applied("withdrawal");
+    }
+
+    fn on_dispute_opened(&self, _account: &Account, _transaction: &Transaction) {
+        self.applied("dispute");
+    }
+
+    fn on_dispute_resolved(&self, _account: &Account, _transaction: &Transaction) {
+        self.applied("resolve");
+    }
+
+    fn on_chargeback_applied(&self, _account: &Account, _transaction: &Transaction) {
+        self.applied("chargeback");
+    }
+
+    fn on_operation_rejected(&self, _operation: &Operation, error: &EngineError) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.rejected += 1;
+        *counts.by_error.entry(error.to_string()).or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod shutdown_tests {
+    use std::sync::Arc;
+
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn counts_applied_and_rejected_operations() {
+        let counter = Arc::new(OperationCounter::new());
+        let engine = Engine::new(EchoDbStorage::new()).with_observer(counter.clone());
+        engine.deposit(1, 1, Decimal4::from(10)).await.unwrap();
+        engine.deposit(1, 2, Decimal4::from(5)).await.unwrap();
+        engine.withdraw(1, 3, Decimal4::from(50)).await.unwrap_err();
+        engine.dispute(1, 1).await.unwrap();
+
+        let counts = counter.counts();
+        assert_eq!((counts.applied, counts.rejected), (3, 1));
+        assert_eq!(counts.by_operation, BTreeMap::from([("deposit", 2), ("dispute", 1)]));
+        assert_eq!(counts.by_error, BTreeMap::from([("insufficient funds".to_string(), 1)]));
+        assert!(counts.to_string().starts_with("executed 4 operations in "));
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::journal::JournalEntry;
+use crate::transaction::Transaction;
+
+const MAGIC: &[u8; 4] = b"TESN";
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+/// The complete engine state: accounts, system accounts, transactions, idempotency records and the journal.
+///
+/// Binary layout: 4 bytes of magic (`TESN`), big-endian `u16` format version, MessagePack-encoded body.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub accounts: Vec<Account>,
+    pub system_accounts: BTreeMap<SystemAccount, Account>,
+    pub transactions: Vec<Transaction>,
+    pub operations: Vec<u64>,
+    pub journal: Vec<JournalEntry>,
+}
+
+/// The body of the version 1, which kept the system accounts under the two highest client ids.
+#[derive(Deserialize)]
+struct SnapshotV1 {
+    accounts: Vec<Account>,
+    transactions: Vec<Transaction>,
+    operations: Vec<u64>,
+    journal: Vec<JournalEntry>,
+}
+
+impl From<SnapshotV1> for Snapshot {
+    fn from(value: SnapshotV1) -> Self {
+        // NOTE: the clients could not use these ids then
+        let (system, accounts): (Vec<Account>, Vec<Account>) = value.accounts.into_iter().partition(|x| x.id() >= ClientId::MAX - 1);
+        let system_accounts = system.into_iter()
+            .map(|x| (if x.id() == ClientId::MAX { SystemAccount::Fees } else { SystemAccount::ChargebackLosses }, x))
+            .collect();
+        Self { accounts, system_accounts, transactions: value.transactions, operations: value.operations, journal: value.journal }
+    }
+}
+
+impl Snapshot {
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), SnapshotError> {
+        let mut snapshot_writer = SnapshotWriter::begin(writer, &self.accounts, &self.system_accounts, &self.transactions, &self.operations, self.journal.len())?;
+        snapshot_writer.write_journal(&self.journal)?;
+        snapshot_writer.finish()
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+        let mut version = [0u8; 2];
+        reader.read_exact(&mut version)?;
+        match u16::from_be_bytes(version) {
+            1 => decode::<SnapshotV1, _>(reader).map(Snapshot::from),
+            SNAPSHOT_VERSION => decode(reader),
+            version => Err(SnapshotError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// Writes a [`Snapshot`] part by part, so the journal can be written page by page instead of being loaded at once.
+/// The output is the same as the one of [`Snapshot::write`].
+pub struct SnapshotWriter<'a, W: Write> {
+    writer: &'a mut W,
+    journal_left: usize,
+}
+
+impl<'a, W: Write> SnapshotWriter<'a, W> {
+    /// Writes everything but the journal, `journal_len` entries must follow.
+    pub fn begin(writer: &'a mut W, accounts: &[Account], system_accounts: &BTreeMap<SystemAccount, Account>, transactions: &[Transaction], operations: &[u64], journal_len: usize) -> Result<Self, SnapshotError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_be_bytes())?;
+        // NOTE: the body is encoded the way rmp_serde encodes the `Snapshot` struct, as an array of its fields
+        rmp::encode::write_array_len(writer, 5).map_err(|e| SnapshotError::Encode(e.to_string()))?;
+        encode(writer, accounts)?;
+        encode(writer, system_accounts)?;
+        encode(writer, transactions)?;
+        encode(writer, operations)?;
+        let journal_len = u32::try_from(journal_len).map_err(|_| SnapshotError::Encode("too many journal entries".to_string()))?;
+        rmp::encode::write_array_len(writer, journal_len).map_err(|e| SnapshotError::Encode(e.to_string()))?;
+        Ok(Self { writer, journal_left: journal_len as usize })
+    }
+
+    /// Writes the next journal entries, ordered by seq.
+    pub fn write_journal(&mut self, entries: &[JournalEntry]) -> Result<(), SnapshotError> {
+        if entries.len() > self.journal_left {
+            return Err(SnapshotError::Encode("more journal entries than announced".to_string()));
+        }
+        for entry in entries.iter() {
+            encode(self.writer, entry)?;
+        }
+        self.journal_left -= entries.len();
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), SnapshotError> {
+        if self.journal_left > 0 {
+            return Err(SnapshotError::Encode(format!("{} journal entries are missing", self.journal_left)));
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn encode<W: Write, T: Serialize + ?Sized>(writer: &mut W, value: &T) -> Result<(), SnapshotError> {
+    rmp_serde::encode::write(writer, value).map_err(|e| SnapshotError::Encode(e.to_string()))
+}
+
+fn decode<T: for<'de> Deserialize<'de>, R: Read>(reader: &mut R) -> Result<T, SnapshotError> {
+    rmp_serde::decode::from_read(reader).map_err(|e| SnapshotError::Decode(e.to_string()))
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum SnapshotError {
+    #[error("not a snapshot: invalid magic bytes")]
+    InvalidMagic,
+
+    #[error("unsupported snapshot version: {0}")]
+    UnsupportedVersion(u16),
+
+    #[error("storage is not empty")]
+    StorageNotEmpty,
+
+    #[error("io error: {0}")]
+    Io(String),
+
+    #[error("can not encode snapshot: {0}")]
+    Encode(String),
+
+    #[error("can not decode snapshot: {0}")]
+    Decode(String),
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(value: std::io::Error) -> Self {
+        SnapshotError::Io(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use rust_decimal::Decimal;
+
+    use crate::decimal::Decimal4;
+    use crate::engine::Operation;
+    use crate::transaction::{TransactionType, TxId};
+
+    use super::*;
+
+    #[test]
+    fn snapshot_write_read_roundtrip() {
+        let snapshot = Snapshot {
+            accounts: vec![Account::new(1)],
+            system_accounts: BTreeMap::from([(SystemAccount::Fees, Account::default())]),
+            transactions: vec![Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(5))],
+            operations: vec![42],
+            journal: vec![],
+        };
+        let mut data = Vec::new();
+        snapshot.write(&mut data).unwrap();
+        assert_eq!(&data[..6], b"TESN\x00\x02");
+        assert_eq!(Snapshot::read(&mut data.as_slice()), Ok(snapshot));
+    }
+
+    #[test]
+    fn snapshot_writer_pages_the_journal() {
+        let journal: Vec<JournalEntry> = (1..=3u8).map(|seq| {
+            let tx = Transaction::new(TxId::from(seq), 1, TransactionType::Deposit, Decimal4::from(5));
+            JournalEntry::new(u64::from(seq), u64::from(seq) * 10, Operation::Deposit { acc_id: 1, tx_id: TxId::from(seq), amount: Decimal::from(5) }, Account::new(1), tx)
+        }).collect();
+        let snapshot = Snapshot { accounts: vec![Account::new(1)], system_accounts: BTreeMap::new(), transactions: vec![], operations: vec![7], journal };
+        let mut expected = Vec::new();
+        rmp_serde::encode::write(&mut expected, &snapshot).unwrap();
+
+        let mut data = Vec::new();
+        let mut writer = SnapshotWriter::begin(&mut data, &snapshot.accounts, &snapshot.system_accounts, &snapshot.transactions, &snapshot.operations, 3).unwrap();
+        writer.write_journal(&snapshot.journal[..2]).unwrap();
+        writer.write_journal(&snapshot.journal[2..]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(&data[6..], expected.as_slice());
+        assert_eq!(Snapshot::read(&mut data.as_slice()), Ok(snapshot.clone()));
+
+        let mut data = Vec::new();
+        let writer = SnapshotWriter::begin(&mut data, &[], &BTreeMap::new(), &[], &[], 1).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn snapshot_read_version_1() {
+        let accounts = vec![Account::new(1), Account::new(ClientId::MAX - 1), Account::new(ClientId::MAX)];
+        let mut data = b"TESN\x00\x01".to_vec();
+        rmp_serde::encode::write(&mut data, &(accounts, Vec::<Transaction>::new(), vec![42u64], Vec::<JournalEntry>::new())).unwrap();
+        let snapshot = Snapshot::read(&mut data.as_slice()).unwrap();
+        assert_eq!(snapshot.accounts, vec![Account::new(1)]);
+        assert_eq!(snapshot.system_accounts.keys().copied().collect::<Vec<_>>(), vec![SystemAccount::Fees, SystemAccount::ChargebackLosses]);
+        assert_eq!(snapshot.operations, vec![42]);
+    }
+
+    #[test]
+    fn snapshot_read_invalid_magic_err() {
+        assert_eq!(Snapshot::read(&mut b"ABCD\x00\x01".as_slice()), Err(SnapshotError::InvalidMagic));
+    }
+
+    #[test]
+    fn snapshot_read_unsupported_version_err() {
+        assert_eq!(Snapshot::read(&mut b"TESN\x00\x09".as_slice()), Err(SnapshotError::UnsupportedVersion(9)));
+    }
+}
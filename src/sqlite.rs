@@ -0,0 +1,821 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow};
+use sqlx::{QueryBuilder, Row, Sqlite, SqlitePool};
+
+use crate::account::{Account, AccountMetadata, AccountStatus, ClientId, SystemAccount};
+use crate::codec::{Codec, MessagePackCodec};
+use crate::decimal::Decimal4;
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, LockingStrategy, Savepoint, Savepoints, Storage, TxOptions};
+use crate::transaction::{Transaction, TransactionState, TransactionType, TxId};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS accounts (
+    id INTEGER PRIMARY KEY,
+    available TEXT NOT NULL,
+    held TEXT NOT NULL,
+    locked INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    name TEXT,
+    external_ref TEXT,
+    created_at INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL DEFAULT 0,
+    status INTEGER NOT NULL DEFAULT 0,
+    escrow BLOB,
+    seq INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    id INTEGER PRIMARY KEY,
+    account_id INTEGER NOT NULL,
+    tx_type INTEGER NOT NULL,
+    amount TEXT NOT NULL,
+    state INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    external_id TEXT,
+    history BLOB,
+    pending_review INTEGER NOT NULL DEFAULT 0,
+    fee TEXT NOT NULL DEFAULT '0',
+    written_off TEXT NOT NULL DEFAULT '0',
+    escrow_bucket TEXT,
+    shortfall TEXT NOT NULL DEFAULT '0',
+    expires_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS transactions_account_id ON transactions (account_id);
+CREATE TABLE IF NOT EXISTS operations (
+    hash BLOB PRIMARY KEY,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS rejected_operations (
+    hash BLOB PRIMARY KEY,
+    code INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS system_accounts (
+    name TEXT PRIMARY KEY,
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS checkpoints (
+    source TEXT PRIMARY KEY,
+    rows INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS journal (
+    seq INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    data BLOB NOT NULL
+);
+";
+
+/// The columns added to the tables after their first version, added to the older databases on connect.
+const ADDED_COLUMNS: [(&str, &str, &str); 15] = [
+    ("accounts", "name", "TEXT"),
+    ("accounts", "external_ref", "TEXT"),
+    ("accounts", "created_at", "INTEGER NOT NULL DEFAULT 0"),
+    ("accounts", "updated_at", "INTEGER NOT NULL DEFAULT 0"),
+    ("accounts", "status", "INTEGER NOT NULL DEFAULT 0"),
+    ("accounts", "escrow", "BLOB"),
+    ("accounts", "seq", "INTEGER NOT NULL DEFAULT 0"),
+    ("transactions", "external_id", "TEXT"),
+    ("transactions", "history", "BLOB"),
+    ("transactions", "pending_review", "INTEGER NOT NULL DEFAULT 0"),
+    ("transactions", "fee", "TEXT NOT NULL DEFAULT '0'"),
+    ("transactions", "written_off", "TEXT NOT NULL DEFAULT '0'"),
+    ("transactions", "escrow_bucket", "TEXT"),
+    ("transactions", "shortfall", "TEXT NOT NULL DEFAULT '0'"),
+    ("transactions", "expires_at", "INTEGER"),
+];
+
+/// Run once the added columns exist: their indexes and the values derived from the older columns
+/// (the accounts locked before the status was stored). The `locked` column is still written for the older readers.
+const ADDED_COLUMNS_UPGRADE: &str = "
+CREATE UNIQUE INDEX IF NOT EXISTS transactions_external_id ON transactions (external_id);
+CREATE INDEX IF NOT EXISTS transactions_hold_expiry ON transactions (expires_at, id) WHERE tx_type = 2 AND state = 0;
+UPDATE accounts SET status = 1 WHERE locked = 1 AND status = 0;
+";
+
+/// Max number of rows in a single batch statement, keeps the statements below the SQLite bind parameters limit.
+const BATCH_SIZE: usize = 1000;
+
+/// Durable storage backed by a SQLite database.
+/// The pool holds a single connection, so storage transactions are serialized the same way as in `EchoDbStorage`.
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    /// Opens (or creates) the database at the given url, e.g. `sqlite://engine.db` or `sqlite::memory:`, and applies the schema.
+    pub async fn connect(url: &str) -> Result<Self, DbError> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+        sqlx::raw_sql(SCHEMA).execute(&pool).await?;
+        for (table, column, definition) in ADDED_COLUMNS {
+            let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)").bind(table).fetch_all(&pool).await?;
+            if !columns.iter().any(|x| x == column) {
+                sqlx::raw_sql(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition)).execute(&pool).await?;
+            }
+        }
+        sqlx::raw_sql(ADDED_COLUMNS_UPGRADE).execute(&pool).await?;
+        Ok(Self { pool })
+    }
+}
+
+/// A storage transaction of [`SqliteStorage`], the savepoints are the SQL ones named after their position.
+pub struct SqliteDbTx {
+    inner: sqlx::Transaction<'static, Sqlite>,
+    savepoints: Savepoints<()>,
+}
+
+impl Storage for SqliteStorage {
+    type DbTx = SqliteDbTx;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        let row = sqlx::query("SELECT * FROM transactions WHERE id = ?")
+            .bind(sql_id(tx_id)?)
+            .fetch_optional(&mut *db_tx.inner)
+            .await?;
+        row.map(|x| tx_from_row(&x)).transpose()
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        sqlx::query("INSERT INTO transactions (id, account_id, tx_type, amount, state, version, created_at, external_id, history, pending_review, fee, written_off, escrow_bucket, shortfall, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(sql_id(tx.id())?)
+            .bind(sql_id(tx.account_id())?)
+            .bind(tx.tx_type() as u8)
+            .bind(tx.amount().to_string())
+            .bind(tx.state() as u8)
+            .bind(tx.version())
+            .bind(tx.created_at() as i64)
+            .bind(tx.external_id())
+            .bind(MessagePackCodec.encode(tx.history())?)
+            .bind(tx.pending_review())
+            .bind(tx.fee().to_string())
+            .bind(tx.written_off().to_string())
+            .bind(tx.escrow_bucket())
+            .bind(tx.shortfall().to_string())
+            .bind(tx.expires_at().map(|x| x as i64))
+            .execute(&mut *db_tx.inner)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        let result = sqlx::query("UPDATE transactions SET state = ?, version = ?, history = ?, written_off = ?, shortfall = ? WHERE id = ? AND version = ?")
+            .bind(new_tx.state() as u8)
+            .bind(new_tx.version())
+            .bind(MessagePackCodec.encode(new_tx.history())?)
+            .bind(new_tx.written_off().to_string())
+            .bind(new_tx.shortfall().to_string())
+            .bind(sql_id(old_tx.id())?)
+            .bind(old_tx.version())
+            .execute(&mut *db_tx.inner)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::ConcurrentModification);
+        }
+        Ok(())
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        let rows = sqlx::query("SELECT * FROM transactions ORDER BY id")
+            .fetch_all(&mut *db_tx.inner)
+            .await?;
+        rows.iter().map(tx_from_row).collect()
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        let mut found = HashMap::new();
+        for chunk in tx_ids.chunks(BATCH_SIZE) {
+            let mut query = QueryBuilder::new("SELECT * FROM transactions WHERE id IN (");
+            let mut separated = query.separated(", ");
+            for tx_id in chunk {
+                separated.push_bind(sql_id(*tx_id)?);
+            }
+            query.push(")");
+            for row in query.build().fetch_all(&mut *db_tx.inner).await? {
+                let tx = tx_from_row(&row)?;
+                found.insert(tx.id(), tx);
+            }
+        }
+        Ok(tx_ids.iter().map(|x| found.get(x).cloned()).collect())
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        for chunk in txs.chunks(BATCH_SIZE) {
+            let mut query = QueryBuilder::new("INSERT INTO transactions (id, account_id, tx_type, amount, state, version, created_at, external_id, history, pending_review, fee, written_off, escrow_bucket, shortfall, expires_at) ");
+            let rows = chunk.iter()
+                .map(|x| Ok((sql_id(x.id())?, sql_id(x.account_id())?, MessagePackCodec.encode(x.history())?, x)))
+                .collect::<Result<Vec<_>, DbError>>()?;
+            query.push_values(rows, |mut row, (id, account_id, history, tx)| {
+                row.push_bind(id)
+                    .push_bind(account_id)
+                    .push_bind(tx.tx_type() as u8)
+                    .push_bind(tx.amount().to_string())
+                    .push_bind(tx.state() as u8)
+                    .push_bind(tx.version())
+                    .push_bind(tx.created_at() as i64)
+                    .push_bind(tx.external_id())
+                    .push_bind(history)
+                    .push_bind(tx.pending_review())
+                    .push_bind(tx.fee().to_string())
+                    .push_bind(tx.written_off().to_string())
+                    .push_bind(tx.escrow_bucket())
+                    .push_bind(tx.shortfall().to_string())
+                    .push_bind(tx.expires_at().map(|x| x as i64));
+            });
+            query.build().execute(&mut *db_tx.inner).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        let mut deleted = 0;
+        for chunk in tx_ids.chunks(BATCH_SIZE) {
+            let mut query = QueryBuilder::new("DELETE FROM transactions WHERE id IN (");
+            let mut separated = query.separated(", ");
+            for tx_id in chunk {
+                separated.push_bind(sql_id(*tx_id)?);
+            }
+            query.push(")");
+            deleted += query.build().execute(&mut *db_tx.inner).await?.rows_affected() as usize;
+        }
+        Ok(deleted)
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        let rows = sqlx::query("SELECT * FROM transactions WHERE account_id = ? AND id > ? ORDER BY id LIMIT ?")
+            .bind(sql_id(acc_id)?)
+            .bind(cursor.map(sql_id).transpose()?.unwrap_or(-1))
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .fetch_all(&mut *db_tx.inner)
+            .await?;
+        rows.iter().map(tx_from_row).collect()
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        let row = sqlx::query("SELECT * FROM transactions WHERE external_id = ?")
+            .bind(external_id)
+            .fetch_optional(&mut *db_tx.inner)
+            .await?;
+        row.map(|x| tx_from_row(&x)).transpose()
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        // NOTE: the authorizations still posted, spelled out as in the partial index so that the query uses it
+        let rows = sqlx::query("SELECT * FROM transactions WHERE tx_type = 2 AND state = 0 AND expires_at <= ? ORDER BY expires_at, id")
+            .bind(i64::try_from(now).unwrap_or(i64::MAX))
+            .fetch_all(&mut *db_tx.inner)
+            .await?;
+        rows.iter().map(tx_from_row).collect()
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        let row = sqlx::query("SELECT * FROM accounts WHERE id = ?")
+            .bind(sql_id(acc_id)?)
+            .fetch_optional(&mut *db_tx.inner)
+            .await?;
+        row.map(|x| account_from_row(&x)).transpose()
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        let rows = sqlx::query("SELECT * FROM accounts ORDER BY id")
+            .fetch_all(&mut *db_tx.inner)
+            .await?;
+        rows.iter().map(account_from_row).collect()
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        let rows = sqlx::query("SELECT * FROM accounts WHERE id > ? ORDER BY id LIMIT ?")
+            .bind(cursor.map(sql_id).transpose()?.unwrap_or(-1))
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .fetch_all(&mut *db_tx.inner)
+            .await?;
+        rows.iter().map(account_from_row).collect()
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        let mut found = HashMap::new();
+        for chunk in acc_ids.chunks(BATCH_SIZE) {
+            let mut query = QueryBuilder::new("SELECT * FROM accounts WHERE id IN (");
+            let mut separated = query.separated(", ");
+            for acc_id in chunk {
+                separated.push_bind(sql_id(*acc_id)?);
+            }
+            query.push(")");
+            for row in query.build().fetch_all(&mut *db_tx.inner).await? {
+                let acc = account_from_row(&row)?;
+                found.insert(acc.id(), acc);
+            }
+        }
+        Ok(acc_ids.iter().map(|x| found.get(x).cloned()).collect())
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        for chunk in accs.chunks(BATCH_SIZE) {
+            let mut query = QueryBuilder::new("INSERT INTO accounts (id, available, held, locked, version, name, external_ref, created_at, updated_at, status, escrow, seq) ");
+            let rows = chunk.iter().map(|x| Ok((sql_id(x.id())?, x, MessagePackCodec.encode(x.escrow_buckets())?))).collect::<Result<Vec<_>, DbError>>()?;
+            query.push_values(rows, |mut row, (id, acc, escrow)| {
+                row.push_bind(id)
+                    .push_bind(acc.available().to_string())
+                    .push_bind(acc.held().to_string())
+                    .push_bind(acc.locked())
+                    .push_bind(acc.version())
+                    .push_bind(acc.metadata().name.clone())
+                    .push_bind(acc.metadata().external_ref.clone())
+                    .push_bind(acc.created_at() as i64)
+                    .push_bind(acc.updated_at() as i64)
+                    .push_bind(acc.status() as u8)
+                    .push_bind(escrow)
+                    .push_bind(acc.seq() as i64);
+            });
+            query.build().execute(&mut *db_tx.inner).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        sqlx::query("INSERT INTO accounts (id, available, held, locked, version, name, external_ref, created_at, updated_at, status, escrow, seq) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(sql_id(acc.id())?)
+            .bind(acc.available().to_string())
+            .bind(acc.held().to_string())
+            .bind(acc.locked())
+            .bind(acc.version())
+            .bind(acc.metadata().name.clone())
+            .bind(acc.metadata().external_ref.clone())
+            .bind(acc.created_at() as i64)
+            .bind(acc.updated_at() as i64)
+            .bind(acc.status() as u8)
+            .bind(MessagePackCodec.encode(acc.escrow_buckets())?)
+            .bind(acc.seq() as i64)
+            .execute(&mut *db_tx.inner)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        let result = sqlx::query("UPDATE accounts SET available = ?, held = ?, locked = ?, version = ?, name = ?, external_ref = ?, created_at = ?, updated_at = ?, status = ?, escrow = ?, seq = ? WHERE id = ? AND version = ?")
+            .bind(new_acc.available().to_string())
+            .bind(new_acc.held().to_string())
+            .bind(new_acc.locked())
+            .bind(new_acc.version())
+            .bind(new_acc.metadata().name.clone())
+            .bind(new_acc.metadata().external_ref.clone())
+            .bind(new_acc.created_at() as i64)
+            .bind(new_acc.updated_at() as i64)
+            .bind(new_acc.status() as u8)
+            .bind(MessagePackCodec.encode(new_acc.escrow_buckets())?)
+            .bind(new_acc.seq() as i64)
+            .bind(sql_id(old_acc.id())?)
+            .bind(old_acc.version())
+            .execute(&mut *db_tx.inner)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DbError::ConcurrentModification);
+        }
+        Ok(())
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        let row = sqlx::query("SELECT data FROM system_accounts WHERE name = ?")
+            .bind(account.name())
+            .fetch_optional(&mut *db_tx.inner)
+            .await?;
+        row.map(|x| MessagePackCodec.decode(&x.try_get::<Vec<u8>, _>("data")?)).transpose()
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        sqlx::query("INSERT INTO system_accounts (name, data) VALUES (?, ?) ON CONFLICT (name) DO UPDATE SET data = excluded.data")
+            .bind(account.name())
+            .bind(MessagePackCodec.encode(acc)?)
+            .execute(&mut *db_tx.inner)
+            .await?;
+        Ok(())
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        let row = sqlx::query("SELECT 1 FROM operations WHERE hash = ?")
+            .bind(op_hash.to_be_bytes().to_vec())
+            .fetch_optional(&mut *db_tx.inner)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        sqlx::query("INSERT INTO operations (hash, created_at) VALUES (?, ?)")
+            .bind(op_hash.to_be_bytes().to_vec()) // NOTE: SQLite integers are signed, so the hash is stored as bytes
+            .bind(timestamp as i64)
+            .execute(&mut *db_tx.inner)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        let rows = sqlx::query("SELECT hash FROM operations")
+            .fetch_all(&mut *db_tx.inner)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                let bytes: Vec<u8> = row.try_get("hash")?;
+                let bytes = bytes.try_into().map_err(|_| DbError::DatabaseError("Invalid operation hash".to_string()))?;
+                Ok(u64::from_be_bytes(bytes))
+            })
+            .collect()
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        let result = sqlx::query("DELETE FROM operations WHERE created_at < ?")
+            .bind(older_than as i64)
+            .execute(&mut *db_tx.inner)
+            .await?;
+        let rejections = sqlx::query("DELETE FROM rejected_operations WHERE created_at < ?")
+            .bind(older_than as i64)
+            .execute(&mut *db_tx.inner)
+            .await?;
+        Ok((result.rows_affected() + rejections.rows_affected()) as usize)
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        let row = sqlx::query("SELECT code FROM rejected_operations WHERE hash = ?")
+            .bind(op_hash.to_be_bytes().to_vec())
+            .fetch_optional(&mut *db_tx.inner)
+            .await?;
+        Ok(row.map(|x| x.try_get::<i64, _>("code")).transpose()?.map(|x| x as u16))
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        sqlx::query("INSERT INTO rejected_operations (hash, code, created_at) VALUES (?, ?, ?) ON CONFLICT (hash) DO UPDATE SET code = excluded.code, created_at = excluded.created_at")
+            .bind(op_hash.to_be_bytes().to_vec())
+            .bind(code as i64)
+            .bind(timestamp as i64)
+            .execute(&mut *db_tx.inner)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        let row = sqlx::query("SELECT rows FROM checkpoints WHERE source = ?")
+            .bind(source)
+            .fetch_optional(&mut *db_tx.inner)
+            .await?;
+        Ok(row.map(|x| x.try_get::<i64, _>("rows")).transpose()?.map(|x| x as u64))
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        sqlx::query("INSERT INTO checkpoints (source, rows) VALUES (?, ?) ON CONFLICT (source) DO UPDATE SET rows = excluded.rows")
+            .bind(source)
+            .bind(rows as i64)
+            .execute(&mut *db_tx.inner)
+            .await?;
+        Ok(())
+    }
+
+    /// A pessimistic read-write transaction starts with `BEGIN IMMEDIATE`, taking the write lock of the database upfront,
+    /// so another process writing to the file makes it wait instead of failing at its first write. The others start
+    /// deferred and take the locks as they read and write. The isolation is ignored, SQLite transactions are serializable.
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        let inner = match options {
+            TxOptions { read_only: false, locking: LockingStrategy::Pessimistic, .. } => self.pool.begin_with("BEGIN IMMEDIATE").await?,
+            _ => self.pool.begin().await?,
+        };
+        Ok(SqliteDbTx { inner, savepoints: Savepoints::default() })
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        db_tx.inner.commit().await?;
+        Ok(())
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        let savepoint = db_tx.savepoints.next();
+        let sql = format!("SAVEPOINT savepoint_{}", savepoint.0);
+        sqlx::query(&sql).execute(&mut *db_tx.inner).await?;
+        db_tx.savepoints.insert(savepoint, ());
+        Ok(savepoint)
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        db_tx.savepoints.rollback_to(savepoint)?;
+        // NOTE: SQLite cancels the savepoints after the one rolled back to and keeps that one
+        let sql = format!("ROLLBACK TO savepoint_{}", savepoint.0);
+        sqlx::query(&sql).execute(&mut *db_tx.inner).await?;
+        Ok(())
+    }
+}
+
+impl Journal for SqliteStorage {
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        let seq: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(seq), 0) FROM journal")
+            .fetch_one(&mut *db_tx.inner)
+            .await?;
+        Ok(seq as u64)
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        sqlx::query("INSERT INTO journal (seq, timestamp, data) VALUES (?, ?, ?)")
+            .bind(entry.seq() as i64)
+            .bind(entry.timestamp() as i64)
+            .bind(MessagePackCodec.encode(entry)?)
+            .execute(&mut *db_tx.inner)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        let rows = sqlx::query("SELECT data FROM journal WHERE seq >= ? ORDER BY seq LIMIT ?")
+            .bind(from_seq as i64)
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .fetch_all(&mut *db_tx.inner)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                let data: Vec<u8> = row.try_get("data")?;
+                MessagePackCodec.decode(&data)
+            })
+            .collect()
+    }
+}
+
+fn account_from_row(row: &SqliteRow) -> Result<Account, DbError> {
+    let status = match row.try_get::<u8, _>("status")? {
+        0 => AccountStatus::Active,
+        1 => AccountStatus::Locked,
+        2 => AccountStatus::Frozen,
+        3 => AccountStatus::Closed,
+        x => return Err(DbError::DatabaseError(format!("Invalid account status: {}", x))),
+    };
+    Ok(Account::from_parts(
+        id_from_sql(row, "id")?,
+        parse_decimal(row.try_get("available")?)?,
+        parse_decimal(row.try_get("held")?)?,
+        status,
+        row.try_get("version")?,
+    ).with_details(
+        AccountMetadata { name: row.try_get("name")?, external_ref: row.try_get("external_ref")? },
+        row.try_get::<i64, _>("created_at")? as u64,
+        row.try_get::<i64, _>("updated_at")? as u64,
+        row.try_get::<i64, _>("seq")? as u64,
+    ).with_escrow(match row.try_get::<Option<Vec<u8>>, _>("escrow")? {
+        Some(data) => MessagePackCodec.decode(&data)?,
+        None => Default::default(), // NOTE: the accounts stored before the escrow buckets
+    }))
+}
+
+fn tx_from_row(row: &SqliteRow) -> Result<Transaction, DbError> {
+    let tx_type = match row.try_get::<u8, _>("tx_type")? {
+        0 => TransactionType::Deposit,
+        1 => TransactionType::Withdrawal,
+        2 => TransactionType::Authorization,
+        3 => TransactionType::Escrow,
+        4 => TransactionType::EscrowRelease,
+        x => return Err(DbError::DatabaseError(format!("Invalid transaction type: {}", x))),
+    };
+    let state = match row.try_get::<u8, _>("state")? {
+        0 => TransactionState::Posted,
+        1 => TransactionState::Disputed,
+        2 => TransactionState::Chargeback,
+        3 => TransactionState::Captured,
+        4 => TransactionState::Expired,
+        x => return Err(DbError::DatabaseError(format!("Invalid transaction state: {}", x))),
+    };
+    Ok(Transaction::from_parts(
+        id_from_sql(row, "id")?,
+        id_from_sql(row, "account_id")?,
+        tx_type,
+        parse_decimal(row.try_get("amount")?)?,
+        state,
+        row.try_get("version")?,
+        row.try_get::<i64, _>("created_at")? as u64,
+    )
+    .with_external_id(row.try_get("external_id")?)
+    .with_history(match row.try_get::<Option<Vec<u8>>, _>("history")? {
+        Some(data) => MessagePackCodec.decode(&data)?,
+        None => Vec::new(), // NOTE: the transactions stored before the history was recorded
+    })
+    .with_pending_review(row.try_get("pending_review")?)
+    .with_fee(parse_decimal(row.try_get("fee")?)?)
+    .with_written_off(parse_decimal(row.try_get("written_off")?)?)
+    .with_shortfall(parse_decimal(row.try_get("shortfall")?)?)
+    .with_escrow_bucket(row.try_get("escrow_bucket")?)
+    .with_expires_at(row.try_get::<Option<i64>, _>("expires_at")?.map(|x| x as u64)))
+}
+
+/// SQLite integers are signed, so the ids above `i64::MAX` (only possible with the `wide-ids` feature) can not be stored.
+fn sql_id(id: impl Into<u64>) -> Result<i64, DbError> {
+    let id = id.into();
+    i64::try_from(id).map_err(|_| DbError::DatabaseError(format!("Id out of range: {}", id)))
+}
+
+fn id_from_sql<T: TryFrom<i64>>(row: &SqliteRow, column: &str) -> Result<T, DbError> {
+    let value: i64 = row.try_get(column)?;
+    T::try_from(value).map_err(|_| DbError::DatabaseError(format!("Invalid {}: {}", column, value)))
+}
+
+fn parse_decimal(value: &str) -> Result<Decimal4, DbError> {
+    Decimal4::from_str(value).map_err(|_| DbError::DatabaseError(format!("Invalid amount: {}", value)))
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(value: sqlx::Error) -> Self {
+        match value.as_database_error() {
+            Some(err) if err.is_unique_violation() => DbError::EntityAlreadyExists,
+            _ => DbError::DatabaseError(value.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod sqlite_tests {
+    use std::time::Duration;
+
+    use crate::engine::{Engine, EngineError, EnginePolicy};
+
+    use super::*;
+
+    async fn engine() -> Engine<SqliteStorage> {
+        Engine::new(SqliteStorage::connect("sqlite::memory:").await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn sqlite_roundtrip() {
+        let engine = engine().await;
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(-30));
+        assert_eq!(acc.held(), Decimal4::zero());
+        assert!(acc.locked());
+        assert_eq!(engine.get_journal_entries(1, 10).await.unwrap().len(), 4);
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn sqlite_pessimistic_locking() {
+        let engine = engine().await.with_locking(LockingStrategy::Pessimistic);
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 2, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(70));
+
+        let mut db_tx = engine.storage().start_db_tx(TxOptions::read_only()).await.unwrap();
+        assert_eq!(engine.storage().get_tx(&mut db_tx, 2).await.unwrap().map(|x| x.amount()), Some(Decimal4::from(30)));
+    }
+
+    #[tokio::test]
+    async fn sqlite_escrow() {
+        let engine = engine().await;
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.escrow(1, 2, "order-7", Decimal4::from(40)).await, Ok(()));
+        assert_eq!(engine.release_escrow(1, 3, "order-7", Decimal4::from(15)).await, Ok(()));
+
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!((acc.available(), acc.escrowed_in("order-7")), (Decimal4::from(75), Decimal4::from(25)));
+        assert_eq!(engine.get_tx(3).await.unwrap().map(|x| (x.tx_type(), x.escrow_bucket().map(str::to_string))), Some((TransactionType::EscrowRelease, Some("order-7".to_string()))));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn sqlite_authorization() {
+        let engine = engine().await.with_policy(EnginePolicy { authorization_expiry: Some(Duration::from_secs(60)), ..Default::default() });
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.authorize(1, 2, Decimal4::from(40)).await, Ok(()));
+        assert_eq!(engine.authorize(1, 3, Decimal4::from(10)).await, Ok(()));
+        assert_eq!(engine.capture(1, 3).await, Ok(()));
+
+        let tx = engine.get_tx(2).await.unwrap().unwrap();
+        assert_eq!((tx.tx_type(), tx.state()), (TransactionType::Authorization, TransactionState::Posted));
+        assert_eq!(tx.expires_at(), Some(tx.created_at() + 60_000));
+        assert_eq!(engine.expire_holds(tx.created_at() + 59_999).await, Ok(0));
+        assert_eq!(engine.expire_holds(tx.created_at() + 60_000).await, Ok(1));
+        assert_eq!(engine.get_tx(2).await.unwrap().map(|x| x.state()), Some(TransactionState::Expired));
+        assert_eq!(engine.get_tx(3).await.unwrap().map(|x| x.state()), Some(TransactionState::Captured));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(90));
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn sqlite_idempotency() {
+        let engine = engine().await;
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(2, 1, Decimal4::from(50)).await, Err(EngineError::TransactionWithTheSameIdAlreadyExists));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(100));
+    }
+
+    #[tokio::test]
+    async fn sqlite_txs_by_account() {
+        let engine = engine().await;
+        for tx_id in [5, 1, 3] {
+            assert_eq!(engine.deposit(1, tx_id, Decimal4::from(10)).await, Ok(()));
+        }
+        assert_eq!(engine.deposit(2, 2, Decimal4::from(10)).await, Ok(()));
+        let page = engine.get_txs_by_account(1, Some(1), 10).await.unwrap();
+        assert_eq!(page.iter().map(|x| x.id()).collect::<Vec<_>>(), vec![3, 5]);
+    }
+
+    #[tokio::test]
+    async fn sqlite_batch() {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let accs: Vec<Account> = (1..=3).map(Account::new).collect();
+        storage.insert_accounts(&mut db_tx, &accs).await.unwrap();
+        let txs: Vec<Transaction> = (1..=3).map(|x| Transaction::new(x, 1, TransactionType::Deposit, Decimal4::from(10))).collect();
+        storage.insert_txs(&mut db_tx, &txs).await.unwrap();
+
+        assert_eq!(storage.get_accounts(&mut db_tx, &[3, 7, 1]).await, Ok(vec![Some(accs[2].clone()), None, Some(accs[0].clone())]));
+        assert_eq!(storage.get_txs(&mut db_tx, &[2, 9]).await, Ok(vec![Some(txs[1].clone()), None]));
+        assert_eq!(storage.insert_accounts(&mut db_tx, &accs[..1]).await, Err(DbError::EntityAlreadyExists));
+        assert_eq!(storage.delete_txs(&mut db_tx, &[2, 9]).await, Ok(1));
+        assert_eq!(storage.get_txs(&mut db_tx, &[1, 2]).await, Ok(vec![Some(txs[0].clone()), None]));
+    }
+
+    #[tokio::test]
+    async fn sqlite_external_ids() {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let tx = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(10)).with_external_id(Some("a1".to_string())).with_pending_review(true);
+        storage.insert_tx(&mut db_tx, &tx).await.unwrap();
+        storage.insert_tx(&mut db_tx, &Transaction::new(2, 1, TransactionType::Deposit, Decimal4::from(10))).await.unwrap();
+        storage.insert_tx(&mut db_tx, &Transaction::new(3, 1, TransactionType::Deposit, Decimal4::from(10))).await.unwrap();
+
+        assert_eq!(storage.get_tx_by_external_id(&mut db_tx, "a1").await, Ok(Some(tx.clone())));
+        assert_eq!(storage.get_tx_by_external_id(&mut db_tx, "b2").await, Ok(None));
+        let duplicate = Transaction::new(4, 1, TransactionType::Deposit, Decimal4::from(10)).with_external_id(Some("a1".to_string()));
+        assert_eq!(storage.insert_tx(&mut db_tx, &duplicate).await, Err(DbError::EntityAlreadyExists));
+    }
+
+    #[tokio::test]
+    async fn sqlite_checkpoint() {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_checkpoint(&mut db_tx, "a.csv").await, Ok(None));
+        storage.set_checkpoint(&mut db_tx, "a.csv", 10).await.unwrap();
+        storage.set_checkpoint(&mut db_tx, "a.csv", 12).await.unwrap();
+        assert_eq!(storage.get_checkpoint(&mut db_tx, "a.csv").await, Ok(Some(12)));
+        assert_eq!(storage.get_checkpoint(&mut db_tx, "b.csv").await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn sqlite_savepoints() {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        storage.set_checkpoint(&mut db_tx, "a.csv", 10).await.unwrap();
+        let first = storage.savepoint(&mut db_tx).await.unwrap();
+        storage.set_checkpoint(&mut db_tx, "a.csv", 12).await.unwrap();
+        let second = storage.savepoint(&mut db_tx).await.unwrap();
+        storage.set_checkpoint(&mut db_tx, "b.csv", 1).await.unwrap();
+
+        assert_eq!(storage.rollback_to(&mut db_tx, first).await, Ok(()));
+        assert_eq!(storage.get_checkpoint(&mut db_tx, "a.csv").await, Ok(Some(10)));
+        assert_eq!(storage.get_checkpoint(&mut db_tx, "b.csv").await, Ok(None));
+        assert!(storage.rollback_to(&mut db_tx, second).await.is_err());
+        storage.set_checkpoint(&mut db_tx, "a.csv", 14).await.unwrap();
+        storage.commit_db_tx(db_tx).await.unwrap();
+
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        assert_eq!(storage.get_checkpoint(&mut db_tx, "a.csv").await, Ok(Some(14)));
+    }
+
+    #[tokio::test]
+    async fn sqlite_account_metadata() {
+        let path = std::env::temp_dir().join(format!("transactions_engine_sqlite_metadata_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let url = format!("sqlite://{}", path.display());
+        {
+            // NOTE: the accounts table as created before the metadata columns
+            let options = SqliteConnectOptions::from_str(&url).unwrap().create_if_missing(true);
+            let pool = SqlitePoolOptions::new().connect_with(options).await.unwrap();
+            sqlx::raw_sql("CREATE TABLE accounts (id INTEGER PRIMARY KEY, available TEXT NOT NULL, held TEXT NOT NULL, locked INTEGER NOT NULL, version INTEGER NOT NULL);
+                INSERT INTO accounts VALUES (1, '5.0000', '0.0000', 0, 1), (2, '0.0000', '0.0000', 1, 3);").execute(&pool).await.unwrap();
+            pool.close().await;
+        }
+
+        let engine = Engine::new(SqliteStorage::connect(&url).await.unwrap());
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().created_at(), 0);
+        let metadata = AccountMetadata { name: Some("Alice".to_string()), external_ref: Some("crm-42".to_string()) };
+        let acc = engine.set_account_metadata(1, metadata).await.unwrap();
+        assert_eq!(engine.get_account(1).await, Ok(Some(acc)));
+        assert_eq!(engine.get_account(2).await.unwrap().map(|x| x.status()), Some(AccountStatus::Locked));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_version_check() {
+        let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let acc = Account::new(1);
+        storage.insert_account(&mut db_tx, &acc).await.unwrap();
+        assert_eq!(storage.insert_account(&mut db_tx, &acc).await, Err(DbError::EntityAlreadyExists));
+
+        let mut updated = acc.clone();
+        updated.deposit(Decimal4::from(10)).unwrap();
+        storage.update_account(&mut db_tx, &acc, &updated).await.unwrap();
+        assert_eq!(storage.update_account(&mut db_tx, &acc, &updated).await, Err(DbError::ConcurrentModification));
+        assert_eq!(storage.get_account(&mut db_tx, 1).await, Ok(Some(updated)));
+    }
+}
@@ -0,0 +1,223 @@
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::account::{Account, ClientId};
+use crate::decimal::{AmountOverflowError, Decimal4};
+use crate::engine::Operation;
+use crate::journal::JournalEntry;
+use crate::transaction::TxId;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Balance {
+    pub available: Decimal4,
+    pub held: Decimal4,
+    pub total: Decimal4,
+}
+
+impl TryFrom<&Account> for Balance {
+    type Error = AmountOverflowError;
+
+    fn try_from(acc: &Account) -> Result<Self, Self::Error> {
+        Ok(Self {
+            available: acc.available(),
+            held: acc.held(),
+            total: acc.total()?,
+        })
+    }
+}
+
+/// A single applied operation with the running balance right after it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatementLine {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub tx: TxId,
+    #[serde(rename = "type")]
+    pub op_type: &'static str,
+    pub amount: Decimal4,
+    #[serde(flatten)]
+    pub balance: Balance,
+}
+
+/// Account activity in the `[from, to)` period (unix millis), ordered by the journal sequence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Statement {
+    pub client: ClientId,
+    pub from: u64,
+    pub to: u64,
+    pub opening: Balance,
+    pub closing: Balance,
+    pub lines: Vec<StatementLine>,
+}
+
+#[derive(Debug, Serialize)]
+struct CsvStatementRow {
+    seq: Option<u64>,
+    timestamp: Option<u64>,
+    tx: Option<TxId>,
+    #[serde(rename = "type")]
+    op_type: &'static str,
+    amount: Option<Decimal4>,
+    available: Decimal4,
+    held: Decimal4,
+    total: Decimal4,
+}
+
+impl Statement {
+    /// An empty statement, filled with [`Statement::push_entry`].
+    pub fn new(client: ClientId, from: u64, to: u64) -> Self {
+        Self { client, from, to, opening: Balance::default(), closing: Balance::default(), lines: Vec::new() }
+    }
+
+    /// Builds the statement from journal entries, which must be ordered by seq.
+    pub fn from_journal<'a>(client: ClientId, from: u64, to: u64, entries: impl IntoIterator<Item = &'a JournalEntry>) -> Result<Self, AmountOverflowError> {
+        let mut statement = Self::new(client, from, to);
+        entries.into_iter().try_for_each(|x| statement.push_entry(x))?;
+        Ok(statement)
+    }
+
+    /// Applies the next journal entry (by seq), the entries of the other accounts are ignored. Fails when the total
+    /// balance of the account does not fit into a `Decimal4`.
+    pub fn push_entry(&mut self, entry: &JournalEntry) -> Result<(), AmountOverflowError> {
+        if entry.account().id() != self.client {
+            return Ok(());
+        }
+        if entry.timestamp() < self.from {
+            self.opening = entry.account().try_into()?;
+            if self.lines.is_empty() {
+                self.closing = self.opening;
+            }
+        } else if entry.timestamp() < self.to {
+            let line = StatementLine {
+                seq: entry.seq(),
+                timestamp: entry.timestamp(),
+                tx: entry.transaction().id(),
+                op_type: op_type(entry.operation()),
+                amount: entry.transaction().amount(),
+                balance: entry.account().try_into()?,
+            };
+            self.closing = line.balance;
+            self.lines.push(line);
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("statement is always serializable")
+    }
+
+    /// Writes the statement lines as CSV, surrounded by the `opening` and `closing` balance rows.
+    pub fn write_csv<W: Write>(&self, writer: W) -> csv::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.serialize(CsvStatementRow::balance("opening", self.opening))?;
+        for line in self.lines.iter() {
+            writer.serialize(CsvStatementRow {
+                seq: Some(line.seq),
+                timestamp: Some(line.timestamp),
+                tx: Some(line.tx),
+                op_type: line.op_type,
+                amount: Some(line.amount),
+                available: line.balance.available,
+                held: line.balance.held,
+                total: line.balance.total,
+            })?;
+        }
+        writer.serialize(CsvStatementRow::balance("closing", self.closing))?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl CsvStatementRow {
+    fn balance(op_type: &'static str, balance: Balance) -> Self {
+        Self {
+            seq: None,
+            timestamp: None,
+            tx: None,
+            op_type,
+            amount: None,
+            available: balance.available,
+            held: balance.held,
+            total: balance.total,
+        }
+    }
+}
+
+fn op_type(operation: &Operation) -> &'static str {
+    match operation {
+        Operation::Deposit { .. } => "deposit",
+        Operation::Withdraw { .. } => "withdrawal",
+        Operation::Dispute { .. } => "dispute",
+        Operation::Resolve { .. } => "resolve",
+        Operation::Chargeback { .. } => "chargeback",
+        Operation::Escrow { .. } => "escrow",
+        Operation::ReleaseEscrow { .. } => "release",
+        Operation::Authorize { .. } => "authorize",
+        Operation::Capture { .. } => "capture",
+        Operation::Expire { .. } => "expire",
+    }
+}
+
+#[cfg(test)]
+mod statement_tests {
+    use rust_decimal::Decimal;
+
+    use crate::transaction::{Transaction, TransactionType};
+
+    use super::*;
+
+    fn journal() -> Vec<JournalEntry> {
+        let mut acc = Account::new(1);
+        let mut entries = Vec::new();
+        for (seq, timestamp, amount) in [(1, 100, 10), (2, 200, 20), (3, 300, 30)] {
+            acc.deposit(Decimal4::from(amount)).unwrap();
+            let tx = Transaction::new(seq as TxId, 1, TransactionType::Deposit, Decimal4::from(amount));
+            let operation = Operation::Deposit { acc_id: 1, tx_id: seq as TxId, amount: Decimal::from(amount) };
+            entries.push(JournalEntry::new(seq, timestamp, operation, acc.clone(), tx));
+        }
+        let other_tx = Transaction::new(9, 2, TransactionType::Deposit, Decimal4::from(1));
+        entries.push(JournalEntry::new(4, 250, Operation::Deposit { acc_id: 2, tx_id: 9, amount: Decimal::from(1) }, Account::new(2), other_tx));
+        entries
+    }
+
+    #[test]
+    fn statement_for_period() {
+        let statement = Statement::from_journal(1, 150, 300, journal().iter()).unwrap();
+        assert_eq!(statement.opening.total, Decimal4::from(10));
+        assert_eq!(statement.closing.total, Decimal4::from(30));
+        assert_eq!(statement.lines.len(), 1);
+        assert_eq!(statement.lines[0].tx, 2);
+        assert_eq!(statement.lines[0].balance.available, Decimal4::from(30));
+    }
+
+    #[test]
+    fn statement_for_empty_period() {
+        let statement = Statement::from_journal(1, 1000, 2000, journal().iter()).unwrap();
+        assert_eq!(statement.opening.total, Decimal4::from(60));
+        assert_eq!(statement.closing, statement.opening);
+        assert!(statement.lines.is_empty());
+    }
+
+    #[test]
+    fn statement_csv() {
+        let statement = Statement::from_journal(1, 150, 300, journal().iter()).unwrap();
+        let mut data = Vec::new();
+        statement.write_csv(&mut data).unwrap();
+        assert_eq!(String::from_utf8(data).unwrap(), "\
+seq,timestamp,tx,type,amount,available,held,total
+,,,opening,,10.0000,0.0000,10.0000
+2,200,2,deposit,20.0000,30.0000,0.0000,30.0000
+,,,closing,,30.0000,0.0000,30.0000
+");
+    }
+
+    #[test]
+    fn statement_json() {
+        let statement = Statement::from_journal(1, 150, 300, journal().iter()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&statement.to_json()).unwrap();
+        assert_eq!(json["lines"][0]["type"], "deposit");
+        assert_eq!(json["lines"][0]["available"], "30.0000");
+        assert_eq!(json["closing"]["total"], "30.0000");
+    }
+}
@@ -1,30 +1,215 @@
-use echodb::Error;
+#[cfg(not(target_arch = "wasm32"))]
+use std::ops::Range;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+
 use thiserror::Error;
 
-use crate::account::Account;
+use crate::account::{Account, ClientId, SystemAccount};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::codec::{Codec, MessagePackCodec};
+#[cfg(not(target_arch = "wasm32"))]
 use crate::engine::Engine;
-use crate::transaction::Transaction;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::journal::{Journal, JournalEntry};
+use crate::transaction::{Transaction, TxId};
 
 #[trait_variant::make(Send)]
 pub trait Storage {
     type DbTx;
 
-    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: u32) -> Result<Option<Transaction>, DbError>;
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError>;
     async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError>;
     async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError>;
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError>;
+    /// Batch variant of `get_tx`, the result has the same order as `tx_ids`.
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError>;
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError>;
+    /// Removes the given transactions (unknown ids are skipped) and returns the number of removed ones.
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError>;
+    /// Returns up to `limit` transactions of the account ordered by id, starting after the `cursor` transaction id.
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError>;
+    /// Returns the transaction stored with the given external id. The external ids are unique: inserting another
+    /// transaction with the same external id fails with `EntityAlreadyExists`.
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError>;
+    /// Returns the authorizations still holding their funds whose expiry is at or before the `now` timestamp, ordered by
+    /// expiry then by id, read through the expiry index (see `Transaction::hold_expires_at`) instead of a full scan.
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError>;
 
-    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: u16) -> Result<Option<Account>, DbError>;
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError>;
     async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError>;
+    /// Batch variant of `get_account`, the result has the same order as `acc_ids`.
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError>;
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError>;
+    /// Returns up to `limit` accounts in the storage order, starting after the `cursor` account id (the last id of the previous page).
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError>;
     async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError>;
     async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError>;
 
+    // methods for the system accounts
+    /// Returns the balances of a [`SystemAccount`]. They are stored apart from the client accounts, so they never take
+    /// a client id and are not listed with the client accounts.
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError>;
+    /// Inserts or replaces the balances of a system account.
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError>;
+
     // methods for idempotency
     async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError>;
-    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op: u64) -> Result<(), DbError>;
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op: u64, timestamp: u64) -> Result<(), DbError>;
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError>;
+    /// Removes the operation records (and the rejections) inserted before the `older_than` timestamp (unix millis) and returns their count.
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError>;
+    /// Returns the error code the operation was rejected with, see `EnginePolicy::cache_rejections`.
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError>;
+    /// Records the rejection of the operation, replacing the previous one. The rejections are not part of the snapshots
+    /// and the migrations, they only stop the retries of the same operation.
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError>;
+
+    // methods for resuming the input
+    /// Returns the number of rows of the input `source` (e.g. a file path) already applied, if any.
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError>;
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError>;
 
     // methods for consistency
-    async fn start_db_tx(&self) -> Result<Self::DbTx, DbError>;
+    /// Opens a storage transaction, the backends map the options to what they support, see [`TxOptions`].
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError>;
     async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError>;
+    /// Marks the current state of the storage transaction, so the writes made after it can be undone with `rollback_to`
+    /// without abandoning the transaction, e.g. one failed operation of a batch.
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError>;
+    /// Undoes the writes made after the savepoint and releases the later savepoints. The savepoint itself stays,
+    /// it can be rolled back to again. Fails with `DatabaseError` for a released savepoint.
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError>;
+}
+
+/// How a storage transaction is opened, see [`Storage::start_db_tx`]. The default is a read-write transaction with
+/// the default isolation of the backend and the optimistic locking.
+///
+/// The options are hints: a backend applies the ones it supports and ignores the rest, e.g. `MemoryStorage` locks all
+/// its tables whatever the options, and the writes of an `EchoDbStorage` transaction are serialized anyway.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TxOptions {
+    /// The transaction only reads, so it can run on a snapshot without taking the write lock. The writes of a read-only
+    /// transaction may fail.
+    pub read_only: bool,
+    pub isolation: IsolationLevel,
+    pub locking: LockingStrategy,
+}
+
+impl TxOptions {
+    pub fn read_write() -> Self {
+        Self::default()
+    }
+
+    pub fn read_only() -> Self {
+        Self { read_only: true, ..Self::default() }
+    }
+
+    pub fn with_isolation(mut self, isolation: IsolationLevel) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    pub fn with_locking(mut self, locking: LockingStrategy) -> Self {
+        self.locking = locking;
+        self
+    }
+}
+
+/// See [`TxOptions::isolation`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IsolationLevel {
+    /// The default level of the backend, at least read committed.
+    #[default]
+    Default,
+    /// The transaction behaves as if it ran alone, the backends that detect the conflicts fail it instead.
+    Serializable,
+}
+
+/// See [`TxOptions::locking`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum LockingStrategy {
+    /// Nothing is locked upfront, an update of a record changed meanwhile fails with `ConcurrentModification`
+    /// and the engine reports the operation as concurrent, to be retried.
+    #[default]
+    Optimistic,
+    /// The records (or the whole database) are locked for writing when the transaction starts or reads them,
+    /// so the concurrent writers wait instead of failing.
+    Pessimistic,
+}
+
+/// A savepoint of a storage transaction, see [`Storage::savepoint`]: its position among the savepoints of the transaction,
+/// the first one is 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Savepoint(pub usize);
+
+/// The state a storage transaction had at each of its savepoints, for the storages that keep a part of the transaction
+/// themselves (e.g. the log records of `FileStorage`) on top of the savepoints of a wrapped storage.
+#[derive(Debug)]
+pub(crate) struct Savepoints<T>(Vec<T>);
+
+impl<T> Default for Savepoints<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<T> Savepoints<T> {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The next savepoint.
+    pub(crate) fn next(&self) -> Savepoint {
+        Savepoint(self.0.len())
+    }
+
+    /// Records the state at the savepoint, the position of the savepoint comes from the wrapped storage.
+    pub(crate) fn insert(&mut self, savepoint: Savepoint, state: T) {
+        self.0.truncate(savepoint.0);
+        self.0.push(state);
+    }
+
+    /// Releases the savepoints after the given one and returns its state.
+    pub(crate) fn rollback_to(&mut self, savepoint: Savepoint) -> Result<&T, DbError> {
+        if savepoint.0 >= self.0.len() {
+            return Err(DbError::DatabaseError(format!("Unknown savepoint: {}", savepoint.0)));
+        }
+        self.0.truncate(savepoint.0 + 1);
+        Ok(&self.0[savepoint.0])
+    }
+}
+
+/// Reads the transactions of an account page by page through the transaction index, within one storage transaction.
+pub(crate) struct AccountTxPages {
+    acc_id: ClientId,
+    cursor: Option<Option<TxId>>,
+}
+
+impl AccountTxPages {
+    const PAGE_SIZE: usize = 1000;
+
+    pub(crate) fn new(acc_id: ClientId) -> Self {
+        Self { acc_id, cursor: Some(None) }
+    }
+
+    /// Returns the next page of transactions ordered by id, `None` after the last page.
+    pub(crate) async fn next_page<S: Storage>(&mut self, storage: &S, db_tx: &mut S::DbTx) -> Result<Option<Vec<Transaction>>, DbError> {
+        let Some(cursor) = self.cursor else {
+            return Ok(None);
+        };
+        let page = storage.get_txs_by_account(db_tx, self.acc_id, cursor, Self::PAGE_SIZE).await?;
+        self.cursor = page.last().filter(|_| page.len() == Self::PAGE_SIZE).map(|x| Some(x.id()));
+        Ok(Some(page))
+    }
+}
+
+/// Storage that can be split into isolated tenant namespaces sharing the same backend,
+/// so one process can serve several payment partners.
+pub trait TenantStorage: Storage + Sized {
+    /// Returns a view of the storage that only sees the records of the given tenant.
+    fn for_tenant(&self, tenant: &str) -> Self;
 }
 
 #[derive(Error, Debug, PartialEq, Eq)]
@@ -39,73 +224,289 @@ pub enum DbError {
     DatabaseError(String),
 }
 
-pub struct EchoDbStorage {
-    db: echodb::Db<String, Vec<u8>>,
+/// The digits of the largest ids, the ids in the index keys are zero-padded to these widths.
+#[cfg(not(target_arch = "wasm32"))]
+const CLIENT_ID_DIGITS: usize = ClientId::MAX.ilog10() as usize + 1;
+#[cfg(not(target_arch = "wasm32"))]
+const TX_ID_DIGITS: usize = TxId::MAX.ilog10() as usize + 1;
+
+/// A storage transaction of [`EchoDbStorage`]. While a savepoint is set, the writes log the values they replace,
+/// so `rollback_to` can restore them.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct EchoDbTx {
+    inner: echodb::Tx<String, Vec<u8>>,
+    undo: Vec<(String, Option<Vec<u8>>)>,
+    savepoints: Savepoints<usize>, // NOTE: the length of the undo log at each savepoint
+    read_only: bool,
 }
 
-impl Default for EchoDbStorage {
-    fn default() -> Self {
-        Self {
-            db: echodb::new(),
+#[cfg(not(target_arch = "wasm32"))]
+impl EchoDbTx {
+    fn exi(&self, key: String) -> Result<bool, echodb::err::Error> {
+        self.inner.exi(key)
+    }
+
+    fn get(&self, key: String) -> Result<Option<Vec<u8>>, echodb::err::Error> {
+        self.inner.get(key)
+    }
+
+    fn keys(&self, range: Range<String>, limit: usize) -> Result<Vec<String>, echodb::err::Error> {
+        self.inner.keys(range, limit)
+    }
+
+    fn scan(&self, range: Range<String>, limit: usize) -> Result<Vec<(String, Vec<u8>)>, echodb::err::Error> {
+        self.inner.scan(range, limit)
+    }
+
+    fn set(&mut self, key: String, value: Vec<u8>) -> Result<(), echodb::err::Error> {
+        self.log(&key)?;
+        self.inner.set(key, value)
+    }
+
+    fn put(&mut self, key: String, value: Vec<u8>) -> Result<(), echodb::err::Error> {
+        self.log(&key)?;
+        self.inner.put(key, value)
+    }
+
+    fn putc(&mut self, key: String, value: Vec<u8>, check: Option<Vec<u8>>) -> Result<(), echodb::err::Error> {
+        self.log(&key)?;
+        self.inner.putc(key, value, check)
+    }
+
+    fn del(&mut self, key: String) -> Result<(), echodb::err::Error> {
+        self.log(&key)?;
+        self.inner.del(key)
+    }
+
+    fn log(&mut self, key: &str) -> Result<(), echodb::err::Error> {
+        if !self.savepoints.is_empty() {
+            let value = self.inner.get(key.to_string())?;
+            self.undo.push((key.to_string(), value));
         }
+        Ok(())
+    }
+}
+
+/// Not available on `wasm32` targets, see `MemoryStorage`.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct EchoDbStorage<C: Codec = MessagePackCodec> {
+    db: Arc<echodb::Db<String, Vec<u8>>>,
+    codec: C,
+    prefix: String, // NOTE: `t:{tenant}:` for the tenant namespaces, empty otherwise
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: Codec + Default> Default for EchoDbStorage<C> {
+    fn default() -> Self {
+        Self::with_codec(C::default())
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Default for Engine<EchoDbStorage> {
     fn default() -> Self {
         Self::new(EchoDbStorage::default())
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl EchoDbStorage {
     pub fn new() -> Self {
         Self::default()
     }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: Codec> EchoDbStorage<C> {
+    /// Creates the storage that encodes the stored values with the given codec.
+    pub fn with_codec(codec: C) -> Self {
+        Self {
+            db: Arc::new(echodb::new()),
+            codec,
+            prefix: String::new(),
+        }
+    }
+
+    fn get_key_for_tx(&self, tx_id: TxId) -> String {
+        format!("{}tx:{}", self.prefix, tx_id)
+    }
+
+    fn get_key_for_acc_tx(&self, acc_id: ClientId, tx_id: TxId) -> String {
+        format!("{}acc_tx:{:0cw$}:{:0tw$}", self.prefix, acc_id, tx_id, cw = CLIENT_ID_DIGITS, tw = TX_ID_DIGITS) // NOTE: zero-padded to keep the scan order equal to the tx id order
+    }
 
-    fn get_key_for_tx(tx_id: u32) -> String {
-        format!("tx:{}", tx_id)
+    fn get_key_for_external_id(&self, external_id: &str) -> String {
+        format!("{}ext:{}", self.prefix, external_id)
     }
 
-    fn get_key_for_acc(acc_id: u16) -> String {
-        format!("acc:{}", acc_id)
+    fn get_key_for_hold_expiry(&self, expires_at: u64, tx_id: TxId) -> String {
+        format!("{}exp:{:020}:{:0tw$}", self.prefix, expires_at, tx_id, tw = TX_ID_DIGITS) // NOTE: zero-padded to keep the scan order equal to the expiry order
     }
 
-    fn get_key_for_op(op_hash: u64) -> String {
-        format!("op:{}", op_hash)
+    fn get_key_for_acc(&self, acc_id: ClientId) -> String {
+        format!("{}acc:{}", self.prefix, acc_id)
+    }
+
+    fn get_key_for_system_account(&self, account: SystemAccount) -> String {
+        format!("{}sys:{}", self.prefix, account.name())
+    }
+
+    fn get_key_for_op(&self, op_hash: u64) -> String {
+        format!("{}op:{}", self.prefix, op_hash)
+    }
+
+    fn get_key_for_rejection(&self, op_hash: u64) -> String {
+        format!("{}rej:{}", self.prefix, op_hash)
+    }
+
+    fn get_key_for_journal_entry(&self, seq: u64) -> String {
+        format!("{}jrn:{:020}", self.prefix, seq) // NOTE: zero-padded to keep the scan order equal to the seq order
+    }
+
+    fn get_key_for_checkpoint(&self, source: &str) -> String {
+        format!("{}chk:{}", self.prefix, source)
+    }
+
+    fn get_key_for_journal_seq(&self) -> String {
+        format!("{}meta:jrn_seq", self.prefix)
     }
 }
 
-impl Storage for EchoDbStorage {
-    type DbTx = echodb::Tx<String, Vec<u8>>;
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: Codec> Storage for EchoDbStorage<C> {
+    type DbTx = EchoDbTx;
 
-    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: u32) -> Result<Option<Transaction>, DbError> {
-        let key = Self::get_key_for_tx(tx_id);
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        let key = self.get_key_for_tx(tx_id);
         if let Some(data) = db_tx.get(key)? {
-            Ok(Some(rmp_serde::from_slice(&data)?))
+            Ok(Some(self.codec.decode(&data)?))
         } else {
             Ok(None)
         }
     }
 
     async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
-        let key = Self::get_key_for_tx(tx.id());
-        let data = rmp_serde::to_vec(tx)?;
+        if let Some(external_id) = tx.external_id() {
+            let key = self.get_key_for_external_id(external_id);
+            if let Some(data) = db_tx.get(key.clone())? {
+                if self.codec.decode::<TxId>(&data)? != tx.id() {
+                    return Err(DbError::EntityAlreadyExists);
+                }
+            }
+            db_tx.put(key, self.codec.encode(&tx.id())?)?;
+        }
+        let key = self.get_key_for_tx(tx.id());
+        let data = self.codec.encode(tx)?;
         db_tx.put(key, data)?;
+        db_tx.put(self.get_key_for_acc_tx(tx.account_id(), tx.id()), vec![])?;
+        if let Some(expires_at) = tx.hold_expires_at() {
+            db_tx.put(self.get_key_for_hold_expiry(expires_at, tx.id()), vec![])?;
+        }
         Ok(())
     }
 
     async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
-        let key = Self::get_key_for_tx(old_tx.id());
-        let old_data = rmp_serde::to_vec(old_tx)?;
-        let new_data = rmp_serde::to_vec(new_tx)?;
+        let key = self.get_key_for_tx(old_tx.id());
+        let old_data = self.codec.encode(old_tx)?;
+        let new_data = self.codec.encode(new_tx)?;
         db_tx.putc(key, new_data, Some(old_data))?;
+        if old_tx.hold_expires_at() != new_tx.hold_expires_at() {
+            if let Some(expires_at) = old_tx.hold_expires_at() {
+                db_tx.del(self.get_key_for_hold_expiry(expires_at, old_tx.id()))?;
+            }
+            if let Some(expires_at) = new_tx.hold_expires_at() {
+                db_tx.put(self.get_key_for_hold_expiry(expires_at, new_tx.id()), vec![])?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        let mut txs = Vec::new();
+        let from = format!("{}tx:", self.prefix);
+        let to = format!("{}tx;", self.prefix);
+        for (_key, data) in db_tx.scan(from..to, usize::MAX)? {
+            let tx: Transaction = self.codec.decode(&data)?;
+            txs.push(tx);
+        }
+        Ok(txs)
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        let mut txs = Vec::with_capacity(tx_ids.len());
+        for tx_id in tx_ids {
+            txs.push(self.get_tx(db_tx, *tx_id).await?);
+        }
+        Ok(txs)
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        for tx in txs {
+            self.insert_tx(db_tx, tx).await?;
+        }
         Ok(())
     }
 
-    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: u16) -> Result<Option<Account>, DbError> {
-        let key = Self::get_key_for_acc(acc_id);
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        let mut deleted = 0;
+        for tx_id in tx_ids {
+            if let Some(tx) = self.get_tx(db_tx, *tx_id).await? {
+                db_tx.del(self.get_key_for_tx(tx.id()))?;
+                db_tx.del(self.get_key_for_acc_tx(tx.account_id(), tx.id()))?;
+                if let Some(external_id) = tx.external_id() {
+                    db_tx.del(self.get_key_for_external_id(external_id))?;
+                }
+                if let Some(expires_at) = tx.hold_expires_at() {
+                    db_tx.del(self.get_key_for_hold_expiry(expires_at, tx.id()))?;
+                }
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        let from = match cursor {
+            Some(tx_id) => self.get_key_for_acc_tx(acc_id, tx_id) + "\0", // NOTE: the cursor itself is excluded
+            None => self.get_key_for_acc_tx(acc_id, 0),
+        };
+        let to = format!("{}acc_tx:{:0cw$};", self.prefix, acc_id, cw = CLIENT_ID_DIGITS);
+        let mut txs = Vec::new();
+        for key in db_tx.keys(from..to, limit)? {
+            let tx_id = key[key.len() - TX_ID_DIGITS..].parse()
+                .map_err(|_| DbError::DatabaseError(format!("Invalid index key: {}", key)))?;
+            let data = db_tx.get(self.get_key_for_tx(tx_id))?
+                .ok_or_else(|| DbError::DatabaseError(format!("Dangling index key: {}", key)))?;
+            txs.push(self.codec.decode(&data)?);
+        }
+        Ok(txs)
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        match db_tx.get(self.get_key_for_external_id(external_id))? {
+            Some(data) => self.get_tx(db_tx, self.codec.decode(&data)?).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        let from = format!("{}exp:", self.prefix);
+        let to = format!("{}exp:{:020};", self.prefix, now); // NOTE: past every key expiring at `now`
+        let mut txs = Vec::new();
+        for key in db_tx.keys(from..to, usize::MAX)? {
+            let tx_id = key[key.len() - TX_ID_DIGITS..].parse()
+                .map_err(|_| DbError::DatabaseError(format!("Invalid index key: {}", key)))?;
+            let data = db_tx.get(self.get_key_for_tx(tx_id))?
+                .ok_or_else(|| DbError::DatabaseError(format!("Dangling index key: {}", key)))?;
+            txs.push(self.codec.decode(&data)?);
+        }
+        Ok(txs)
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        let key = self.get_key_for_acc(acc_id);
         if let Some(data) = db_tx.get(key)? {
-            Ok(Some(rmp_serde::from_slice(&data)?))
+            Ok(Some(self.codec.decode(&data)?))
         } else {
             Ok(None)
         }
@@ -113,55 +514,228 @@ impl Storage for EchoDbStorage {
 
     async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
         let mut accounts = Vec::new();
-        let from = "acc:".to_string();
-        let to = "acd:".to_string();
+        let from = format!("{}acc:", self.prefix);
+        let to = format!("{}acc;", self.prefix);
         for (_key, data) in db_tx.scan(from..to, usize::MAX)? {
-            let acc: Account = rmp_serde::from_slice(&data)?;
+            let acc: Account = self.codec.decode(&data)?;
             accounts.push(acc);
         }
         Ok(accounts)
     }
 
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        let mut accounts = Vec::new();
+        let from = match cursor {
+            Some(acc_id) => self.get_key_for_acc(acc_id) + "\0", // NOTE: the cursor itself is excluded
+            None => format!("{}acc:", self.prefix),
+        };
+        let to = format!("{}acc;", self.prefix);
+        for (_key, data) in db_tx.scan(from..to, limit)? {
+            let acc: Account = self.codec.decode(&data)?;
+            accounts.push(acc);
+        }
+        Ok(accounts)
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        let mut accounts = Vec::with_capacity(acc_ids.len());
+        for acc_id in acc_ids {
+            accounts.push(self.get_account(db_tx, *acc_id).await?);
+        }
+        Ok(accounts)
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        for acc in accs {
+            self.insert_account(db_tx, acc).await?;
+        }
+        Ok(())
+    }
+
     async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
-        let key = Self::get_key_for_acc(acc.id());
-        let data = rmp_serde::to_vec(acc)?;
+        let key = self.get_key_for_acc(acc.id());
+        let data = self.codec.encode(acc)?;
         db_tx.put(key, data)?;
         Ok(())
     }
 
     async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
-        let key = Self::get_key_for_acc(old_acc.id());
-        let old_data = rmp_serde::to_vec(old_acc)?;
-        let new_data = rmp_serde::to_vec(new_acc)?;
+        let key = self.get_key_for_acc(old_acc.id());
+        let old_data = self.codec.encode(old_acc)?;
+        let new_data = self.codec.encode(new_acc)?;
         db_tx.putc(key, new_data, Some(old_data))?;
         Ok(())
     }
 
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        match db_tx.get(self.get_key_for_system_account(account))? {
+            Some(data) => Ok(Some(self.codec.decode(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        db_tx.set(self.get_key_for_system_account(account), self.codec.encode(acc)?)?;
+        Ok(())
+    }
+
     async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
-        let key = Self::get_key_for_op(op_hash);
+        let key = self.get_key_for_op(op_hash);
         let exists = db_tx.exi(key)?;
         Ok(exists)
     }
 
-    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<(), DbError> {
-        let key = Self::get_key_for_op(op_hash);
-        db_tx.put(key, vec![0])?;
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        let key = self.get_key_for_op(op_hash);
+        db_tx.put(key, self.codec.encode(&timestamp)?)?;
+        Ok(())
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        let mut op_hashes = Vec::new();
+        let from = format!("{}op:", self.prefix);
+        let to = format!("{}op;", self.prefix);
+        for key in db_tx.keys(from..to, usize::MAX)? {
+            let op_hash = key[self.prefix.len() + "op:".len()..].parse()
+                .map_err(|_| DbError::DatabaseError(format!("Invalid operation key: {}", key)))?;
+            op_hashes.push(op_hash);
+        }
+        Ok(op_hashes)
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        let mut pruned = 0;
+        let from = format!("{}op:", self.prefix);
+        let to = format!("{}op;", self.prefix);
+        for (key, data) in db_tx.scan(from..to, usize::MAX)? {
+            let timestamp: u64 = self.codec.decode(&data)?;
+            if timestamp < older_than {
+                db_tx.del(key)?;
+                pruned += 1;
+            }
+        }
+        let from = format!("{}rej:", self.prefix);
+        let to = format!("{}rej;", self.prefix);
+        for (key, data) in db_tx.scan(from..to, usize::MAX)? {
+            let (_code, timestamp): (u16, u64) = self.codec.decode(&data)?;
+            if timestamp < older_than {
+                db_tx.del(key)?;
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        match db_tx.get(self.get_key_for_rejection(op_hash))? {
+            Some(data) => Ok(Some(self.codec.decode::<(u16, u64)>(&data)?.0)),
+            None => Ok(None),
+        }
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        db_tx.set(self.get_key_for_rejection(op_hash), self.codec.encode(&(code, timestamp))?)?;
+        Ok(())
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        let key = self.get_key_for_checkpoint(source);
+        if let Some(data) = db_tx.get(key)? {
+            Ok(Some(self.codec.decode(&data)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        db_tx.set(self.get_key_for_checkpoint(source), self.codec.encode(&rows)?)?;
         Ok(())
     }
 
-    async fn start_db_tx(&self) -> Result<Self::DbTx, DbError> {
-        let db_tx = self.db.begin(true).await?;
-        Ok(db_tx)
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        // NOTE: a read-only transaction reads a snapshot without waiting for the write lock, the writers are serialized by it
+        let inner = self.db.begin(!options.read_only).await?;
+        Ok(EchoDbTx { inner, undo: Vec::new(), savepoints: Savepoints::default(), read_only: options.read_only })
     }
 
     async fn commit_db_tx(&self, mut db_tx: Self::DbTx) -> Result<(), DbError> {
-        db_tx.commit()?;
+        match db_tx.read_only {
+            true => db_tx.inner.cancel()?,
+            false => db_tx.inner.commit()?,
+        }
+        Ok(())
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        let savepoint = db_tx.savepoints.next();
+        db_tx.savepoints.insert(savepoint, db_tx.undo.len());
+        Ok(savepoint)
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        let undo_len = *db_tx.savepoints.rollback_to(savepoint)?;
+        while db_tx.undo.len() > undo_len {
+            match db_tx.undo.pop() {
+                Some((key, Some(value))) => db_tx.inner.set(key, value)?,
+                Some((key, None)) => db_tx.inner.del(key)?,
+                None => break,
+            }
+        }
         Ok(())
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: Codec> Journal for EchoDbStorage<C> {
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        let key = self.get_key_for_journal_seq();
+        if let Some(data) = db_tx.get(key)? {
+            Ok(self.codec.decode(&data)?)
+        } else {
+            Ok(0)
+        }
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        let key = self.get_key_for_journal_entry(entry.seq());
+        let data = self.codec.encode(entry)?;
+        db_tx.put(key, data)?;
+        db_tx.set(self.get_key_for_journal_seq(), self.codec.encode(&entry.seq())?)?;
+        Ok(())
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        let mut entries = Vec::new();
+        let from = self.get_key_for_journal_entry(from_seq);
+        let to = format!("{}jrn;", self.prefix);
+        for (_key, data) in db_tx.scan(from..to, limit)? {
+            let entry: JournalEntry = self.codec.decode(&data)?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+/// Tenant views share the database (and its write lock) with the root storage, their keys are prefixed with `t:{tenant}:`.
+///
+/// # Panics
+/// `for_tenant` panics if the tenant id contains `:`, which would let one tenant see the keys of another one.
+#[cfg(not(target_arch = "wasm32"))]
+impl<C: Codec + Clone> TenantStorage for EchoDbStorage<C> {
+    fn for_tenant(&self, tenant: &str) -> Self {
+        assert!(!tenant.contains(':'), "tenant id must not contain ':'");
+        Self {
+            db: self.db.clone(),
+            codec: self.codec.clone(),
+            prefix: format!("t:{}:", tenant),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 impl From<echodb::err::Error> for DbError {
-    fn from(value: Error) -> Self {
+    fn from(value: echodb::err::Error) -> Self {
+        use echodb::err::Error;
         match value {
             Error::DbError => DbError::DatabaseError("Can not open transaction".to_string()),
             Error::TxClosed => DbError::DatabaseError("Transaction is closed".to_string()),
@@ -172,6 +746,12 @@ impl From<echodb::err::Error> for DbError {
     }
 }
 
+impl From<std::io::Error> for DbError {
+    fn from(value: std::io::Error) -> Self {
+        DbError::DatabaseError(format!("IO error: {}", value))
+    }
+}
+
 impl From<rmp_serde::encode::Error> for DbError {
     fn from(value: rmp_serde::encode::Error) -> Self {
         DbError::DatabaseError(format!("Can not encode data: {}", value))
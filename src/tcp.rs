@@ -0,0 +1,160 @@
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+use crate::csv_parser::{CsvOperation, CsvParseError};
+use crate::engine::{Engine, Operation};
+use crate::journal::Journal;
+use crate::queue::{decode_operation, execute_with_retry, MessageOutcome, RetryPolicy};
+use crate::storage::Storage;
+
+/// The columns of a CSV line, the same as the header of the CSV input.
+const CSV_HEADERS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineServerConfig {
+    /// Longer lines are answered with `INVALID` and the connection is closed, so a client can not exhaust the memory.
+    pub max_line_length: usize,
+    pub retry: RetryPolicy,
+}
+
+impl Default for LineServerConfig {
+    fn default() -> Self {
+        Self { max_line_length: 4096, retry: RetryPolicy::default() }
+    }
+}
+
+/// Decodes a line: a CSV record without a header (`deposit,1,1,10.5`, `dispute,1,1`) or a JSON object
+/// (`{"type": "deposit", "client": 1, "tx": 1, "amount": "10.5"}`, see [`decode_operation`]).
+pub fn decode_line(line: &str) -> Result<Operation, String> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        return decode_operation(line.as_bytes());
+    }
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).trim(csv::Trim::All).from_reader(line.as_bytes());
+    let record = match reader.records().next() {
+        Some(record) => record.map_err(|e| format!("invalid csv: {}", e))?,
+        None => return Err("empty line".to_string()),
+    };
+    let operation: CsvOperation = record.deserialize(Some(&csv::StringRecord::from(CSV_HEADERS.to_vec()))).map_err(|e| format!("invalid csv: {}", e))?;
+    operation.try_into().map_err(|e: CsvParseError| e.to_string())
+}
+
+/// The reply to a line: `OK`, `REJECTED <code> <reason>` (sending the line again gives the same error),
+/// `RETRY <code> <reason>` (a concurrent operation or a storage failure, the line can be sent again) or `INVALID <reason>`.
+pub fn reply(outcome: &MessageOutcome) -> String {
+    match outcome {
+        MessageOutcome::Applied(_) => "OK".to_string(),
+        MessageOutcome::Rejected(_, err) => format!("REJECTED {} {}", err.code(), err),
+        MessageOutcome::Transient(_, err) => format!("RETRY {} {}", err.code(), err),
+        MessageOutcome::Invalid(err) => format!("INVALID {}", err),
+    }
+}
+
+/// Accepts TCP connections on `addr` until `shutdown` completes, each one sending newline-delimited operations
+/// (see [`decode_line`]) and receiving one [`reply`] line per non-empty line, in order. The operations of a connection
+/// are executed one by one; on shutdown every connection is closed after the reply to its current line.
+pub async fn serve<TStorage>(engine: Engine<TStorage>, addr: impl ToSocketAddrs, config: LineServerConfig, shutdown: impl std::future::Future<Output = ()>) -> std::io::Result<()>
+    where TStorage: Storage + Journal + Send + Sync + 'static,
+          TStorage::DbTx: Send
+{
+    let listener = TcpListener::bind(addr).await?;
+    let (stop, stopped) = watch::channel(false);
+    let mut connections = JoinSet::new();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                connections.spawn(handle_connection(engine.clone(), stream, config, stopped.clone()));
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = &mut shutdown => break,
+        }
+    }
+    let _ = stop.send(true);
+    while connections.join_next().await.is_some() {}
+    Ok(())
+}
+
+async fn handle_connection<TStorage, TStream>(engine: Engine<TStorage>, stream: TStream, config: LineServerConfig, mut stopped: watch::Receiver<bool>) -> std::io::Result<()>
+    where TStorage: Storage + Journal,
+          TStream: AsyncRead + AsyncWrite + Unpin
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let mut limited = (&mut reader).take(config.max_line_length as u64 + 1);
+        let read = tokio::select! {
+            read = limited.read_until(b'\n', &mut line) => read?,
+            _ = stopped.changed() => return Ok(()),
+        };
+        if read == 0 {
+            return Ok(()); // NOTE: the client closed the connection
+        }
+        if line.len() > config.max_line_length && line.last() != Some(&b'\n') {
+            writer.write_all(format!("INVALID the line is longer than {} bytes\n", config.max_line_length).as_bytes()).await?;
+            return Ok(());
+        }
+        let outcome = match std::str::from_utf8(&line) {
+            Ok(text) if text.trim().is_empty() => continue,
+            Ok(text) => match decode_line(text) {
+                Ok(operation) => execute_with_retry(&engine, operation, None, config.retry).await,
+                Err(err) => MessageOutcome::Invalid(err),
+            },
+            Err(_) => MessageOutcome::Invalid("the line is not valid UTF-8".to_string()),
+        };
+        writer.write_all(format!("{}\n", reply(&outcome)).as_bytes()).await?;
+    }
+}
+
+#[cfg(test)]
+mod tcp_tests {
+    use rust_decimal::Decimal;
+
+    use crate::decimal::Decimal4;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[test]
+    fn decode_lines() {
+        assert_eq!(decode_line("deposit, 1, 2, 10.5\r\n"), Ok(Operation::Deposit { acc_id: 1, tx_id: 2, amount: "10.5".parse().unwrap() }));
+        assert_eq!(decode_line("dispute,1,2"), Ok(Operation::Dispute { acc_id: 1, tx_id: 2 }));
+        assert_eq!(decode_line("resolve,1,2,"), Ok(Operation::Resolve { acc_id: 1, tx_id: 2 }));
+        assert_eq!(decode_line(r#"{"type": "withdrawal", "client": 1, "tx": 3, "amount": "1"}"#), Ok(Operation::Withdraw { acc_id: 1, tx_id: 3, amount: Decimal::from(1) }));
+        assert_eq!(decode_line("withdrawal,1,3"), Err("missing field: amount".to_string()));
+        assert!(decode_line("deposit,one,3,1").is_err());
+    }
+
+    #[tokio::test]
+    async fn replies_per_line() {
+        let engine = Engine::new(EchoDbStorage::new());
+        let (client, server) = tokio::io::duplex(1024);
+        let (_stop, stopped) = watch::channel(false);
+        let config = LineServerConfig { max_line_length: 64, ..Default::default() };
+        let connection = tokio::spawn(handle_connection(engine.clone(), server, config, stopped));
+
+        let (reader, mut writer) = tokio::io::split(client);
+        let input = format!("deposit,1,1,10\n\n{}\nwithdrawal,1,2,50\nrefund,1,3\ndeposit,1,1,10\n{}\n", r#"{"type": "dispute", "client": 1, "tx": 1}"#, "x".repeat(100));
+        writer.write_all(input.as_bytes()).await.unwrap();
+        let mut replies = BufReader::new(reader).lines();
+        let mut received = Vec::new();
+        while let Some(line) = replies.next_line().await.unwrap() {
+            received.push(line);
+        }
+        assert_eq!(received, vec![
+            "OK",
+            "OK",
+            "REJECTED 104 insufficient funds",
+            "INVALID invalid operation type",
+            "OK",
+            "INVALID the line is longer than 64 bytes",
+        ]);
+        connection.await.unwrap().unwrap();
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().held(), Decimal4::from(10));
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashSet;
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::journal::{Journal, JournalEntry};
+use crate::storage::{DbError, Savepoint, Storage, TxOptions};
+use crate::transaction::{Transaction, TransactionState, TxId};
+
+/// Two-tier storage: the `hot` backend holds the whole state, the `cold` one only the archived transactions.
+///
+/// `archive()` moves the settled transactions (neither disputed nor authorizations still holding their funds) older than
+/// a threshold to the cold tier, transaction lookups fall back to it, so an archived deposit can still be found as
+/// a duplicate or disputed. A change to an archived transaction brings it back to the hot tier, which always takes
+/// precedence. Accounts, idempotency records and the journal stay in the hot tier.
+pub struct TieredStorage<H, C> {
+    hot: H,
+    cold: C,
+}
+
+impl<H, C> TieredStorage<H, C>
+where
+    H: Storage + Sync,
+    H::DbTx: Send,
+    C: Storage + Sync,
+    C::DbTx: Send,
+{
+    pub fn new(hot: H, cold: C) -> Self {
+        Self { hot, cold }
+    }
+
+    pub fn hot(&self) -> &H {
+        &self.hot
+    }
+
+    pub fn cold(&self) -> &C {
+        &self.cold
+    }
+
+    /// Moves the settled transactions created before the `older_than` timestamp (unix millis) to the cold tier
+    /// and returns their count. The cold tier is committed first, so a crash in between leaves the transactions
+    /// in both tiers, and the next run simply archives them again.
+    pub async fn archive(&self, older_than: u64) -> Result<usize, DbError> {
+        let mut db_tx = self.hot.start_db_tx(TxOptions::read_write()).await?;
+        let txs: Vec<Transaction> = self.hot.get_all_txs(&mut db_tx).await?.into_iter()
+            .filter(|tx| tx.state() != TransactionState::Disputed && !tx.is_open_authorization() && tx.created_at() < older_than)
+            .collect();
+        if txs.is_empty() {
+            return Ok(0);
+        }
+
+        let tx_ids: Vec<TxId> = txs.iter().map(Transaction::id).collect();
+        let mut cold_tx = self.cold.start_db_tx(TxOptions::read_write()).await?;
+        let archived = self.cold.get_txs(&mut cold_tx, &tx_ids).await?;
+        for (tx, old_tx) in txs.iter().zip(archived) {
+            match old_tx {
+                Some(old_tx) => self.cold.update_tx(&mut cold_tx, &old_tx, tx).await?,
+                None => self.cold.insert_tx(&mut cold_tx, tx).await?,
+            }
+        }
+        self.cold.commit_db_tx(cold_tx).await?;
+
+        let moved = self.hot.delete_txs(&mut db_tx, &tx_ids).await?;
+        self.hot.commit_db_tx(db_tx).await?;
+        Ok(moved)
+    }
+
+    async fn get_cold_tx(&self, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        let mut cold_tx = self.cold.start_db_tx(TxOptions::read_only()).await?;
+        self.cold.get_tx(&mut cold_tx, tx_id).await
+    }
+}
+
+impl<H, C> Storage for TieredStorage<H, C>
+where
+    H: Storage + Sync,
+    H::DbTx: Send,
+    C: Storage + Sync,
+    C::DbTx: Send,
+{
+    type DbTx = H::DbTx;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        match self.hot.get_tx(db_tx, tx_id).await? {
+            Some(tx) => Ok(Some(tx)),
+            None => self.get_cold_tx(tx_id).await,
+        }
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        self.hot.insert_tx(db_tx, tx).await
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        if self.hot.get_tx(db_tx, old_tx.id()).await?.is_some() {
+            return self.hot.update_tx(db_tx, old_tx, new_tx).await;
+        }
+        if self.get_cold_tx(old_tx.id()).await?.as_ref() != Some(old_tx) {
+            return Err(DbError::ConcurrentModification);
+        }
+        match self.hot.insert_tx(db_tx, new_tx).await {
+            Err(DbError::EntityAlreadyExists) => Err(DbError::ConcurrentModification), // NOTE: brought back by a concurrent update
+            result => result,
+        }
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        let mut txs = self.hot.get_all_txs(db_tx).await?;
+        let hot_ids: HashSet<TxId> = txs.iter().map(Transaction::id).collect();
+        let mut cold_tx = self.cold.start_db_tx(TxOptions::read_only()).await?;
+        txs.extend(self.cold.get_all_txs(&mut cold_tx).await?.into_iter().filter(|tx| !hot_ids.contains(&tx.id())));
+        Ok(txs)
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        let mut txs = self.hot.get_txs(db_tx, tx_ids).await?;
+        let missing: Vec<TxId> = tx_ids.iter().zip(&txs).filter(|(_, tx)| tx.is_none()).map(|(tx_id, _)| *tx_id).collect();
+        if missing.is_empty() {
+            return Ok(txs);
+        }
+        let mut cold_tx = self.cold.start_db_tx(TxOptions::read_only()).await?;
+        let mut archived = self.cold.get_txs(&mut cold_tx, &missing).await?.into_iter();
+        for tx in txs.iter_mut().filter(|tx| tx.is_none()) {
+            *tx = archived.next().flatten();
+        }
+        Ok(txs)
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        self.hot.insert_txs(db_tx, txs).await
+    }
+
+    /// Removes the transactions from both tiers, the cold tier is committed right away.
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        let mut cold_tx = self.cold.start_db_tx(TxOptions::read_write()).await?;
+        let archived = self.cold.delete_txs(&mut cold_tx, tx_ids).await?;
+        self.cold.commit_db_tx(cold_tx).await?;
+        let deleted = self.hot.delete_txs(db_tx, tx_ids).await?;
+        Ok(deleted.max(archived))
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        let mut txs = self.hot.get_txs_by_account(db_tx, acc_id, cursor, limit).await?;
+        let hot_ids: HashSet<TxId> = txs.iter().map(Transaction::id).collect();
+        let mut cold_tx = self.cold.start_db_tx(TxOptions::read_only()).await?;
+        let archived = self.cold.get_txs_by_account(&mut cold_tx, acc_id, cursor, limit).await?;
+        txs.extend(archived.into_iter().filter(|tx| !hot_ids.contains(&tx.id())));
+        txs.sort_by_key(Transaction::id);
+        txs.truncate(limit);
+        Ok(txs)
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        match self.hot.get_tx_by_external_id(db_tx, external_id).await? {
+            Some(tx) => Ok(Some(tx)),
+            None => {
+                let mut cold_tx = self.cold.start_db_tx(TxOptions::read_only()).await?;
+                self.cold.get_tx_by_external_id(&mut cold_tx, external_id).await
+            }
+        }
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        self.hot.get_expired_authorizations(db_tx, now).await // NOTE: the open authorizations are never archived
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        self.hot.get_account(db_tx, acc_id).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        self.hot.get_all_accounts(db_tx).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        self.hot.get_accounts(db_tx, acc_ids).await
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        self.hot.insert_accounts(db_tx, accs).await
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        self.hot.list_accounts(db_tx, cursor, limit).await
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        self.hot.insert_account(db_tx, acc).await
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        self.hot.update_account(db_tx, old_acc, new_acc).await
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        self.hot.get_system_account(db_tx, account).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        self.hot.set_system_account(db_tx, account, acc).await
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        self.hot.is_operation_processed(db_tx, op_hash).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        self.hot.insert_operation(db_tx, op_hash, timestamp).await
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        self.hot.get_all_operations(db_tx).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        self.hot.prune_operations(db_tx, older_than).await
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        self.hot.get_operation_rejection(db_tx, op_hash).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        self.hot.insert_operation_rejection(db_tx, op_hash, code, timestamp).await
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        self.hot.get_checkpoint(db_tx, source).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        self.hot.set_checkpoint(db_tx, source, rows).await
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        self.hot.start_db_tx(options).await
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        self.hot.commit_db_tx(db_tx).await
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        self.hot.savepoint(db_tx).await
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        self.hot.rollback_to(db_tx, savepoint).await
+    }
+}
+
+impl<H, C> Journal for TieredStorage<H, C>
+where
+    H: Journal + Sync,
+    H::DbTx: Send,
+    C: Storage + Sync,
+    C::DbTx: Send,
+{
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        self.hot.get_last_journal_seq(db_tx).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        self.hot.append_journal_entry(db_tx, entry).await
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        self.hot.get_journal_entries(db_tx, from_seq, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tiered_storage_tests {
+    use crate::decimal::Decimal4;
+    use crate::engine::{Engine, EngineError};
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    async fn tx_ids<S: Storage>(storage: &S) -> Vec<TxId> {
+        let mut db_tx = storage.start_db_tx(TxOptions::default()).await.unwrap();
+        let mut tx_ids: Vec<TxId> = storage.get_all_txs(&mut db_tx).await.unwrap().iter().map(Transaction::id).collect();
+        tx_ids.sort();
+        tx_ids
+    }
+
+    #[tokio::test]
+    async fn settled_txs_are_archived() {
+        let engine = Engine::new(TieredStorage::new(EchoDbStorage::new(), EchoDbStorage::new()));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(50)).await, Ok(()));
+        assert_eq!(engine.withdraw(1, 3, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 2).await, Ok(()));
+
+        assert_eq!(engine.storage().archive(u64::MAX).await, Ok(2));
+        assert_eq!(tx_ids(engine.storage().hot()).await, vec![2]);
+        assert_eq!(tx_ids(engine.storage().cold()).await, vec![1, 3]);
+        assert_eq!(tx_ids(engine.storage()).await, vec![1, 2, 3]);
+
+        assert_eq!(engine.deposit(1, 3, Decimal4::from(5)).await, Err(EngineError::TransactionWithTheSameIdAlreadyExists));
+        let txs = engine.get_txs_by_account(1, None, 10).await.unwrap();
+        assert_eq!(txs.iter().map(Transaction::id).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(engine.storage().archive(0).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn archived_tx_can_be_disputed() {
+        let engine = Engine::new(TieredStorage::new(EchoDbStorage::new(), EchoDbStorage::new()));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.storage().archive(u64::MAX).await, Ok(1));
+
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(tx_ids(engine.storage().hot()).await, vec![1]);
+        assert_eq!(engine.storage().archive(u64::MAX).await, Ok(0)); // disputed txs stay hot
+        assert_eq!(engine.resolve(1, 1).await, Ok(()));
+        assert_eq!(engine.storage().archive(u64::MAX).await, Ok(1));
+
+        assert_eq!(tx_ids(engine.storage().hot()).await, Vec::<TxId>::new());
+        let acc = engine.get_account(1).await.unwrap().unwrap();
+        assert_eq!(acc.available(), Decimal4::from(100));
+        assert_eq!(acc.held(), Decimal4::zero());
+        assert_eq!(engine.verify_journal().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn open_authorizations_stay_hot() {
+        let engine = Engine::new(TieredStorage::new(EchoDbStorage::new(), EchoDbStorage::new()));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.authorize(1, 2, Decimal4::from(30)).await, Ok(()));
+        assert_eq!(engine.authorize(1, 3, Decimal4::from(20)).await, Ok(()));
+        assert_eq!(engine.capture(1, 3).await, Ok(()));
+
+        assert_eq!(engine.storage().archive(u64::MAX).await, Ok(2));
+        assert_eq!(tx_ids(engine.storage().hot()).await, vec![2]);
+        assert_eq!(engine.capture(1, 2).await, Ok(()));
+        assert_eq!(engine.storage().archive(u64::MAX).await, Ok(1));
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().total().unwrap(), Decimal4::from(50));
+    }
+}
@@ -0,0 +1,231 @@
+use std::future::Future;
+
+use tracing::Instrument;
+
+use crate::account::{Account, ClientId, SystemAccount};
+use crate::journal::{Journal, JournalEntry};
+use crate::redact;
+use crate::storage::{DbError, Savepoint, Storage, TxOptions};
+use crate::transaction::{Transaction, TxId};
+
+/// Storage decorator that runs every call in a `storage` tracing span (with the `backend` and the `operation`)
+/// and logs the failed calls. Nested in the span of the engine operation, so the calls of an operation are grouped.
+pub struct TracedStorage<S> {
+    inner: S,
+    backend: &'static str,
+}
+
+impl<S> TracedStorage<S> {
+    pub fn new(inner: S, backend: &'static str) -> Self {
+        Self { inner, backend }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    async fn trace<T>(&self, operation: &'static str, call: impl Future<Output = Result<T, DbError>>) -> Result<T, DbError> {
+        let span = tracing::debug_span!("storage", backend = self.backend, operation);
+        let result = call.instrument(span.clone()).await;
+        if let Err(err) = result.as_ref() {
+            span.in_scope(|| tracing::warn!(error = %redact::error(err), "storage call failed"));
+        }
+        result
+    }
+}
+
+impl<S> Storage for TracedStorage<S>
+where
+    S: Storage + Sync,
+    S::DbTx: Send,
+{
+    type DbTx = S::DbTx;
+
+    async fn get_tx(&self, db_tx: &mut Self::DbTx, tx_id: TxId) -> Result<Option<Transaction>, DbError> {
+        self.trace("get_tx", self.inner.get_tx(db_tx, tx_id)).await
+    }
+
+    async fn insert_tx(&self, db_tx: &mut Self::DbTx, tx: &Transaction) -> Result<(), DbError> {
+        self.trace("insert_tx", self.inner.insert_tx(db_tx, tx)).await
+    }
+
+    async fn update_tx(&self, db_tx: &mut Self::DbTx, old_tx: &Transaction, new_tx: &Transaction) -> Result<(), DbError> {
+        self.trace("update_tx", self.inner.update_tx(db_tx, old_tx, new_tx)).await
+    }
+
+    async fn get_all_txs(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Transaction>, DbError> {
+        self.trace("get_all_txs", self.inner.get_all_txs(db_tx)).await
+    }
+
+    async fn get_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<Vec<Option<Transaction>>, DbError> {
+        self.trace("get_txs", self.inner.get_txs(db_tx, tx_ids)).await
+    }
+
+    async fn insert_txs(&self, db_tx: &mut Self::DbTx, txs: &[Transaction]) -> Result<(), DbError> {
+        self.trace("insert_txs", self.inner.insert_txs(db_tx, txs)).await
+    }
+
+    async fn delete_txs(&self, db_tx: &mut Self::DbTx, tx_ids: &[TxId]) -> Result<usize, DbError> {
+        self.trace("delete_txs", self.inner.delete_txs(db_tx, tx_ids)).await
+    }
+
+    async fn get_txs_by_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId, cursor: Option<TxId>, limit: usize) -> Result<Vec<Transaction>, DbError> {
+        self.trace("get_txs_by_account", self.inner.get_txs_by_account(db_tx, acc_id, cursor, limit)).await
+    }
+
+    async fn get_tx_by_external_id(&self, db_tx: &mut Self::DbTx, external_id: &str) -> Result<Option<Transaction>, DbError> {
+        self.trace("get_tx_by_external_id", self.inner.get_tx_by_external_id(db_tx, external_id)).await
+    }
+
+    async fn get_expired_authorizations(&self, db_tx: &mut Self::DbTx, now: u64) -> Result<Vec<Transaction>, DbError> {
+        self.trace("get_expired_authorizations", self.inner.get_expired_authorizations(db_tx, now)).await
+    }
+
+    async fn get_account(&self, db_tx: &mut Self::DbTx, acc_id: ClientId) -> Result<Option<Account>, DbError> {
+        self.trace("get_account", self.inner.get_account(db_tx, acc_id)).await
+    }
+
+    async fn get_all_accounts(&self, db_tx: &mut Self::DbTx) -> Result<Vec<Account>, DbError> {
+        self.trace("get_all_accounts", self.inner.get_all_accounts(db_tx)).await
+    }
+
+    async fn get_accounts(&self, db_tx: &mut Self::DbTx, acc_ids: &[ClientId]) -> Result<Vec<Option<Account>>, DbError> {
+        self.trace("get_accounts", self.inner.get_accounts(db_tx, acc_ids)).await
+    }
+
+    async fn insert_accounts(&self, db_tx: &mut Self::DbTx, accs: &[Account]) -> Result<(), DbError> {
+        self.trace("insert_accounts", self.inner.insert_accounts(db_tx, accs)).await
+    }
+
+    async fn list_accounts(&self, db_tx: &mut Self::DbTx, cursor: Option<ClientId>, limit: usize) -> Result<Vec<Account>, DbError> {
+        self.trace("list_accounts", self.inner.list_accounts(db_tx, cursor, limit)).await
+    }
+
+    async fn insert_account(&self, db_tx: &mut Self::DbTx, acc: &Account) -> Result<(), DbError> {
+        self.trace("insert_account", self.inner.insert_account(db_tx, acc)).await
+    }
+
+    async fn update_account(&self, db_tx: &mut Self::DbTx, old_acc: &Account, new_acc: &Account) -> Result<(), DbError> {
+        self.trace("update_account", self.inner.update_account(db_tx, old_acc, new_acc)).await
+    }
+
+    async fn get_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount) -> Result<Option<Account>, DbError> {
+        self.trace("get_system_account", self.inner.get_system_account(db_tx, account)).await
+    }
+
+    async fn set_system_account(&self, db_tx: &mut Self::DbTx, account: SystemAccount, acc: &Account) -> Result<(), DbError> {
+        self.trace("set_system_account", self.inner.set_system_account(db_tx, account, acc)).await
+    }
+
+    async fn is_operation_processed(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<bool, DbError> {
+        self.trace("is_operation_processed", self.inner.is_operation_processed(db_tx, op_hash)).await
+    }
+
+    async fn insert_operation(&self, db_tx: &mut Self::DbTx, op_hash: u64, timestamp: u64) -> Result<(), DbError> {
+        self.trace("insert_operation", self.inner.insert_operation(db_tx, op_hash, timestamp)).await
+    }
+
+    async fn get_all_operations(&self, db_tx: &mut Self::DbTx) -> Result<Vec<u64>, DbError> {
+        self.trace("get_all_operations", self.inner.get_all_operations(db_tx)).await
+    }
+
+    async fn prune_operations(&self, db_tx: &mut Self::DbTx, older_than: u64) -> Result<usize, DbError> {
+        self.trace("prune_operations", self.inner.prune_operations(db_tx, older_than)).await
+    }
+
+    async fn get_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64) -> Result<Option<u16>, DbError> {
+        self.trace("get_operation_rejection", self.inner.get_operation_rejection(db_tx, op_hash)).await
+    }
+
+    async fn insert_operation_rejection(&self, db_tx: &mut Self::DbTx, op_hash: u64, code: u16, timestamp: u64) -> Result<(), DbError> {
+        self.trace("insert_operation_rejection", self.inner.insert_operation_rejection(db_tx, op_hash, code, timestamp)).await
+    }
+
+    async fn get_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str) -> Result<Option<u64>, DbError> {
+        self.trace("get_checkpoint", self.inner.get_checkpoint(db_tx, source)).await
+    }
+
+    async fn set_checkpoint(&self, db_tx: &mut Self::DbTx, source: &str, rows: u64) -> Result<(), DbError> {
+        self.trace("set_checkpoint", self.inner.set_checkpoint(db_tx, source, rows)).await
+    }
+
+    async fn start_db_tx(&self, options: TxOptions) -> Result<Self::DbTx, DbError> {
+        self.trace("start_db_tx", self.inner.start_db_tx(options)).await
+    }
+
+    async fn commit_db_tx(&self, db_tx: Self::DbTx) -> Result<(), DbError> {
+        self.trace("commit_db_tx", self.inner.commit_db_tx(db_tx)).await
+    }
+
+    async fn savepoint(&self, db_tx: &mut Self::DbTx) -> Result<Savepoint, DbError> {
+        self.trace("savepoint", self.inner.savepoint(db_tx)).await
+    }
+
+    async fn rollback_to(&self, db_tx: &mut Self::DbTx, savepoint: Savepoint) -> Result<(), DbError> {
+        self.trace("rollback_to", self.inner.rollback_to(db_tx, savepoint)).await
+    }
+}
+
+impl<S> Journal for TracedStorage<S>
+where
+    S: Journal + Sync,
+    S::DbTx: Send,
+{
+    async fn get_last_journal_seq(&self, db_tx: &mut Self::DbTx) -> Result<u64, DbError> {
+        self.trace("get_last_journal_seq", self.inner.get_last_journal_seq(db_tx)).await
+    }
+
+    async fn append_journal_entry(&self, db_tx: &mut Self::DbTx, entry: &JournalEntry) -> Result<(), DbError> {
+        self.trace("append_journal_entry", self.inner.append_journal_entry(db_tx, entry)).await
+    }
+
+    async fn get_journal_entries(&self, db_tx: &mut Self::DbTx, from_seq: u64, limit: usize) -> Result<Vec<JournalEntry>, DbError> {
+        self.trace("get_journal_entries", self.inner.get_journal_entries(db_tx, from_seq, limit)).await
+    }
+}
+
+#[cfg(test)]
+mod traced_storage_tests {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::decimal::Decimal4;
+    use crate::engine::Engine;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[test]
+    fn storage_calls_are_traced() {
+        let output = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let writer = {
+            let output = output.clone();
+            move || SharedWriter(output.clone())
+        };
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(false).with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+            runtime.block_on(async {
+                let engine = Engine::new(TracedStorage::new(EchoDbStorage::new(), "echodb"));
+                assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+            });
+        });
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("operation{type=\"deposit\" acc_id=1 tx_id=1"), "{}", output);
+        assert!(output.contains("storage{backend=\"echodb\" operation=\"insert_tx\"}: "), "{}", output);
+    }
+
+    struct SharedWriter(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -1,12 +1,27 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::account::ClientId;
 use crate::decimal::Decimal4;
+use crate::engine::Operation;
+
+/// The id of a transaction: `u32` by default, `u64` with the `wide-ids` feature.
+#[cfg(not(feature = "wide-ids"))]
+pub type TxId = u32;
+/// The id of a transaction: `u32` by default, `u64` with the `wide-ids` feature.
+#[cfg(feature = "wide-ids")]
+pub type TxId = u64;
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub enum TransactionType {
     Deposit = 0,
     Withdrawal = 1,
+    /// Available funds held for a later capture, released if not captured before the authorization expires.
+    Authorization = 2,
+    /// Available funds moved into an escrow bucket of the account.
+    Escrow = 3,
+    /// Funds of an escrow bucket moved back to the available funds.
+    EscrowRelease = 4,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
@@ -14,20 +29,62 @@ pub enum TransactionState {
     Posted = 0,
     Disputed = 1,
     Chargeback = 2,
+    /// The held funds of an authorization were taken from the account.
+    Captured = 3,
+    /// The authorization was not captured in time, its held funds were released.
+    Expired = 4,
+}
+
+/// A change of the state of a transaction, recorded by [`Transaction::transition`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateTransition {
+    pub from: TransactionState,
+    pub to: TransactionState,
+    /// The dispute, resolve or chargeback that changed the state.
+    pub operation: Operation,
+    pub timestamp: u64, // unix millis
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
-    id: u32,
-    account_id: u16,
+    id: TxId,
+    account_id: ClientId,
     tx_type: TransactionType,
     amount: Decimal4,
     state: TransactionState,
     version: u16, // concurrency token
+    #[serde(default)]
+    created_at: u64, // unix millis
+    /// The id of the transaction in the upstream system (e.g. a UUID), unique across the transactions.
+    #[serde(default)]
+    external_id: Option<String>,
+    /// The state transitions in the order they happened.
+    #[serde(default)]
+    history: Vec<StateTransition>,
+    /// Flagged by the `RiskAssessor` for a manual review, the transaction is applied nonetheless.
+    #[serde(default)]
+    pending_review: bool,
+    /// The fee of a withdrawal, taken from the account on top of the amount and moved to `SystemAccount::Fees`.
+    #[serde(default)]
+    fee: Decimal4,
+    /// The negative balance of the account written off to `SystemAccount::ChargebackLosses` by the chargeback of this deposit.
+    #[serde(default)]
+    written_off: Decimal4,
+    /// The part of the amount of this disputed (or charged back) deposit that the dispute could not hold, because the client
+    /// already spent it, see `NegativeAvailablePolicy::Clamp`.
+    #[serde(default)]
+    shortfall: Decimal4,
+    /// The escrow bucket of an escrow or a release.
+    #[serde(default)]
+    escrow_bucket: Option<String>,
+    /// When an authorization expires if not captured (unix millis), `None` for the other transactions and the authorizations
+    /// without expiry, see `Engine::expire_holds`.
+    #[serde(default)]
+    expires_at: Option<u64>,
 }
 
 impl Transaction {
-    pub fn new(id: u32, account_id: u16, tx_type: TransactionType, amount: Decimal4) -> Self {
+    pub fn new(id: TxId, account_id: ClientId, tx_type: TransactionType, amount: Decimal4) -> Self {
         Self {
             id,
             account_id,
@@ -35,14 +92,69 @@ impl Transaction {
             amount,
             state: TransactionState::Posted,
             version: 0,
+            created_at: 0,
+            external_id: None,
+            history: Vec::new(),
+            pending_review: false,
+            fee: Decimal4::zero(),
+            written_off: Decimal4::zero(),
+            shortfall: Decimal4::zero(),
+            escrow_bucket: None,
+            expires_at: None,
         }
     }
 
-    pub fn id(&self) -> u32 {
+    pub fn with_created_at(mut self, created_at: u64) -> Self {
+        self.created_at = created_at;
+        self
+    }
+
+    pub fn with_external_id(mut self, external_id: Option<String>) -> Self {
+        self.external_id = external_id;
+        self
+    }
+
+    pub fn with_pending_review(mut self, pending_review: bool) -> Self {
+        self.pending_review = pending_review;
+        self
+    }
+
+    pub fn with_fee(mut self, fee: Decimal4) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    pub fn with_written_off(mut self, written_off: Decimal4) -> Self {
+        self.written_off = written_off;
+        self
+    }
+
+    pub fn with_shortfall(mut self, shortfall: Decimal4) -> Self {
+        self.shortfall = shortfall;
+        self
+    }
+
+    pub fn with_escrow_bucket(mut self, escrow_bucket: Option<String>) -> Self {
+        self.escrow_bucket = escrow_bucket;
+        self
+    }
+
+    pub fn with_expires_at(mut self, expires_at: Option<u64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    /// Restores a transaction previously persisted by a storage backend.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn from_parts(id: TxId, account_id: ClientId, tx_type: TransactionType, amount: Decimal4, state: TransactionState, version: u16, created_at: u64) -> Self {
+        Self { id, account_id, tx_type, amount, state, version, created_at, external_id: None, history: Vec::new(), pending_review: false, fee: Decimal4::zero(), written_off: Decimal4::zero(), shortfall: Decimal4::zero(), escrow_bucket: None, expires_at: None }
+    }
+
+    pub fn id(&self) -> TxId {
         self.id
     }
 
-    pub fn account_id(&self) -> u16 {
+    pub fn account_id(&self) -> ClientId {
         self.account_id
     }
 
@@ -62,9 +174,80 @@ impl Transaction {
         self.version
     }
 
+    pub fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    pub fn external_id(&self) -> Option<&str> {
+        self.external_id.as_deref()
+    }
+
+    pub fn history(&self) -> &[StateTransition] {
+        &self.history
+    }
+
+    pub fn pending_review(&self) -> bool {
+        self.pending_review
+    }
+
+    pub fn fee(&self) -> Decimal4 {
+        self.fee
+    }
+
+    pub fn written_off(&self) -> Decimal4 {
+        self.written_off
+    }
+
+    pub fn shortfall(&self) -> Decimal4 {
+        self.shortfall
+    }
+
+    pub fn escrow_bucket(&self) -> Option<&str> {
+        self.escrow_bucket.as_deref()
+    }
+
+    pub fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    /// Whether this is an authorization still holding its funds, neither captured nor expired.
+    pub fn is_open_authorization(&self) -> bool {
+        self.tx_type == TransactionType::Authorization && self.state == TransactionState::Posted
+    }
+
+    /// The expiry of an authorization still holding its funds, `None` for the other transactions: the key of the expiry
+    /// index of the storages (see `Storage::get_expired_authorizations`).
+    pub fn hold_expires_at(&self) -> Option<u64> {
+        self.expires_at.filter(|_| self.is_open_authorization())
+    }
+
+    /// Whether this is an authorization still holding its funds past its expiry at the `now` timestamp (unix millis).
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.hold_expires_at().is_some_and(|x| x <= now)
+    }
+
+    /// Restores the state transitions previously persisted by a storage backend.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn with_history(mut self, history: Vec<StateTransition>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Changes the state like [`Transaction::set_state`] and records the change with the triggering operation in the history.
+    pub fn transition(&mut self, new_state: TransactionState, operation: Operation, timestamp: u64) -> Result<(), TxUpdateError> {
+        let from = self.state;
+        self.set_state(new_state)?;
+        self.history.push(StateTransition { from, to: new_state, operation, timestamp });
+        Ok(())
+    }
+
+    /// Deposits can be disputed, resolved and charged back, authorizations can be captured or expired.
     pub fn set_state(&mut self, new_state: TransactionState) -> Result<(), TxUpdateError> {
-        if self.tx_type == TransactionType::Withdrawal {
-            return Err(TxUpdateError::InvalidTxType);
+        let authorization_state = matches!(new_state, TransactionState::Captured | TransactionState::Expired);
+        match self.tx_type {
+            TransactionType::Deposit if !authorization_state => {}
+            TransactionType::Authorization if authorization_state => {}
+            _ => return Err(TxUpdateError::InvalidTxType),
         }
 
         match (self.state, new_state) {
@@ -83,6 +266,11 @@ impl Transaction {
                 self.version += 1;
                 Ok(())
             }
+            (TransactionState::Posted, TransactionState::Captured | TransactionState::Expired) => {
+                self.state = new_state;
+                self.version += 1;
+                Ok(())
+            }
             _ => Err(TxUpdateError::ForbiddenTxStateTransition {
                 from: self.state,
                 to: new_state,
@@ -93,7 +281,7 @@ impl Transaction {
 
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum TxUpdateError {
-    #[error("invalid transaction type: only deposits can be disputed/resolved/chargebacked")]
+    #[error("invalid transaction type: only deposits can be disputed/resolved/chargebacked and authorizations captured/expired")]
     InvalidTxType,
 
     #[error("forbidden state transition: {from:?} -> {to:?}")]
@@ -150,6 +338,32 @@ mod transaction_tests {
         assert_eq!(tx.version(), 3);
     }
 
+    #[test]
+    fn transitions_recorded() {
+        let mut tx = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(100));
+        assert_eq!(tx.transition(TransactionState::Disputed, Operation::Dispute { acc_id: 1, tx_id: 1 }, 10), Ok(()));
+        assert_eq!(tx.transition(TransactionState::Disputed, Operation::Dispute { acc_id: 1, tx_id: 1 }, 20), Err(TxUpdateError::ForbiddenTxStateTransition { from: TransactionState::Disputed, to: TransactionState::Disputed }));
+        assert_eq!(tx.transition(TransactionState::Posted, Operation::Resolve { acc_id: 1, tx_id: 1 }, 30), Ok(()));
+        assert_eq!(tx.history(), [
+            StateTransition { from: TransactionState::Posted, to: TransactionState::Disputed, operation: Operation::Dispute { acc_id: 1, tx_id: 1 }, timestamp: 10 },
+            StateTransition { from: TransactionState::Disputed, to: TransactionState::Posted, operation: Operation::Resolve { acc_id: 1, tx_id: 1 }, timestamp: 30 },
+        ]);
+    }
+
+    #[test]
+    fn authorization_transitions() {
+        let mut tx = Transaction::new(1, 1, TransactionType::Authorization, Decimal4::from(100)).with_expires_at(Some(50));
+        assert!(!tx.is_expired(49));
+        assert!(tx.is_expired(50));
+        assert_eq!(tx.set_state(TransactionState::Disputed), Err(TxUpdateError::InvalidTxType));
+        assert_eq!(tx.set_state(TransactionState::Captured), Ok(()));
+        assert!(!tx.is_expired(50));
+        assert_eq!(tx.set_state(TransactionState::Expired), Err(TxUpdateError::ForbiddenTxStateTransition { from: TransactionState::Captured, to: TransactionState::Expired }));
+
+        let mut deposit = Transaction::new(2, 1, TransactionType::Deposit, Decimal4::from(100));
+        assert_eq!(deposit.set_state(TransactionState::Captured), Err(TxUpdateError::InvalidTxType));
+    }
+
     #[test]
     fn resolve_after_chargeback_err() {
         let mut tx = Transaction::new(1, 1, TransactionType::Deposit, Decimal4::from(100));
@@ -0,0 +1,246 @@
+use crate::account::{Account, AccountUpdateError, ClientId};
+use crate::decimal::Decimal4;
+use crate::engine::{EngineError, EnginePolicy, NegativeAvailablePolicy, Operation};
+use crate::transaction::{Transaction, TransactionState, TransactionType};
+
+/// The account and the transaction as an operation leaves them, see [`Validator::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatedOperation {
+    pub account: Account,
+    /// The created deposit, withdrawal, escrow move or authorization, the disputed, resolved or charged back deposit, or the
+    /// captured or expired authorization.
+    pub tx: Transaction,
+}
+
+/// The business rules of the operations, checked against the state they apply to without a storage: the engine runs
+/// every operation through it, and the dry runs (`Engine::validate_operation`) stop there.
+///
+/// What needs the storage or another service is left to the engine: the idempotency, the uniqueness of the external
+/// ids and the risk assessment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Validator {
+    policy: EnginePolicy,
+}
+
+impl Validator {
+    pub fn new(policy: EnginePolicy) -> Self {
+        Self { policy }
+    }
+
+    /// The checks of the operation alone: the amount (already rounded to the policy precision) is positive and within
+    /// the policy limit.
+    pub fn check_operation(&self, operation: &Operation) -> Result<(), EngineError> {
+        match operation {
+            Operation::Deposit { amount, .. } | Operation::Withdraw { amount, .. } | Operation::Authorize { amount, .. } => {
+                let amount = Decimal4::from(*amount);
+                check_positive(amount)?;
+                match self.policy.max_amount {
+                    Some(max_amount) if amount > max_amount => Err(EngineError::AmountLimitExceeded),
+                    _ => Ok(()),
+                }
+            }
+            Operation::Escrow { amount, .. } | Operation::ReleaseEscrow { amount, .. } => check_positive(Decimal4::from(*amount)),
+            Operation::Dispute { .. } | Operation::Resolve { .. } | Operation::Chargeback { .. } | Operation::Capture { .. } | Operation::Expire { .. } => Ok(()),
+        }
+    }
+
+    /// Validates the operation against its `account` and the transaction `tx` with the id of the operation (`None`
+    /// for the ones not stored), at the `now` timestamp (unix millis), and returns the updated account and transaction.
+    /// The operation itself is expected to pass [`Validator::check_operation`].
+    pub fn validate(&self, operation: &Operation, account: Option<&Account>, tx: Option<&Transaction>, now: u64) -> Result<ValidatedOperation, EngineError> {
+        match operation {
+            Operation::Deposit { acc_id, tx_id, amount } => {
+                let amount = Decimal4::from(*amount);
+                check_new_tx(tx)?;
+                let mut account = account.cloned().unwrap_or_else(|| Account::new(*acc_id));
+                account.deposit(amount)?;
+                account.check_balance_limit(self.policy.max_balance)?;
+                Ok(ValidatedOperation { account, tx: Transaction::new(*tx_id, *acc_id, TransactionType::Deposit, amount).with_created_at(now) })
+            }
+            Operation::Withdraw { acc_id, tx_id, amount } => {
+                let amount = Decimal4::from(*amount);
+                check_new_tx(tx)?;
+                let mut account = account.cloned().ok_or(EngineError::AccountNotFound)?;
+                let fee = self.policy.withdrawal_fee.unwrap_or_default();
+                account.withdraw(amount.checked_add(fee).map_err(AccountUpdateError::from)?)?;
+                let tx = Transaction::new(*tx_id, *acc_id, TransactionType::Withdrawal, amount).with_created_at(now).with_fee(fee);
+                Ok(ValidatedOperation { account, tx })
+            }
+            Operation::Escrow { acc_id, tx_id, bucket, amount } | Operation::ReleaseEscrow { acc_id, tx_id, bucket, amount } => {
+                let amount = Decimal4::from(*amount);
+                check_new_tx(tx)?;
+                let mut account = account.cloned().ok_or(EngineError::AccountNotFound)?;
+                let tx_type = if let Operation::ReleaseEscrow { .. } = operation {
+                    account.release_escrow(bucket, amount)?;
+                    TransactionType::EscrowRelease
+                } else {
+                    account.escrow(bucket, amount)?;
+                    TransactionType::Escrow
+                };
+                let tx = Transaction::new(*tx_id, *acc_id, tx_type, amount).with_created_at(now).with_escrow_bucket(Some(bucket.clone()));
+                Ok(ValidatedOperation { account, tx })
+            }
+            Operation::Authorize { acc_id, tx_id, amount } => {
+                let amount = Decimal4::from(*amount);
+                check_new_tx(tx)?;
+                let mut account = account.cloned().ok_or(EngineError::AccountNotFound)?;
+                account.authorize(amount)?;
+                let expires_at = self.policy.authorization_expiry.map(|x| now.saturating_add(x.as_millis() as u64));
+                let tx = Transaction::new(*tx_id, *acc_id, TransactionType::Authorization, amount).with_created_at(now).with_expires_at(expires_at);
+                Ok(ValidatedOperation { account, tx })
+            }
+            Operation::Capture { acc_id, tx_id } => {
+                let (mut account, old_tx) = existing_tx(*acc_id, account, tx)?;
+                let mut tx = old_tx.clone();
+                tx.transition(TransactionState::Captured, Operation::Capture { acc_id: *acc_id, tx_id: *tx_id }, now)?;
+                if old_tx.is_expired(now) {
+                    return Err(EngineError::AuthorizationExpired);
+                }
+                account.capture(tx.amount())?;
+                Ok(ValidatedOperation { account, tx })
+            }
+            Operation::Expire { acc_id, tx_id } => {
+                let (mut account, old_tx) = existing_tx(*acc_id, account, tx)?;
+                let mut tx = old_tx.clone();
+                tx.transition(TransactionState::Expired, Operation::Expire { acc_id: *acc_id, tx_id: *tx_id }, now)?;
+                if !old_tx.is_expired(now) {
+                    return Err(EngineError::AuthorizationNotExpired);
+                }
+                account.resolve(tx.amount())?; // NOTE: the held funds go back like after a resolved dispute
+                Ok(ValidatedOperation { account, tx })
+            }
+            Operation::Dispute { acc_id, tx_id } => {
+                let (mut account, old_tx) = existing_tx(*acc_id, account, tx)?;
+                let window_expired = self.policy.dispute_window.is_some_and(|x| now.saturating_sub(old_tx.created_at()) > x.as_millis() as u64);
+                if window_expired && old_tx.state() == TransactionState::Posted {
+                    return Err(EngineError::DisputeWindowExpired);
+                }
+                let mut tx = old_tx.clone();
+                tx.transition(TransactionState::Disputed, Operation::Dispute { acc_id: *acc_id, tx_id: *tx_id }, now)?;
+                let shortfall = match self.policy.negative_available {
+                    NegativeAvailablePolicy::Allow => Decimal4::zero(),
+                    NegativeAvailablePolicy::Clamp => account.dispute_shortfall(tx.amount()),
+                    NegativeAvailablePolicy::Reject if account.dispute_shortfall(tx.amount()).is_positive() => return Err(EngineError::DisputeExceedsAvailable),
+                    NegativeAvailablePolicy::Reject => Decimal4::zero(),
+                };
+                let tx = tx.with_shortfall(shortfall);
+                account.dispute_with_shortfall(tx.amount(), shortfall)?;
+                Ok(ValidatedOperation { account, tx })
+            }
+            Operation::Resolve { acc_id, tx_id } => {
+                let (mut account, old_tx) = existing_tx(*acc_id, account, tx)?;
+                let mut tx = old_tx.clone();
+                tx.transition(TransactionState::Posted, Operation::Resolve { acc_id: *acc_id, tx_id: *tx_id }, now)?;
+                account.resolve_with_shortfall(tx.amount(), old_tx.shortfall())?;
+                Ok(ValidatedOperation { account, tx: tx.with_shortfall(Decimal4::zero()) })
+            }
+            Operation::Chargeback { acc_id, tx_id } => {
+                let (mut account, old_tx) = existing_tx(*acc_id, account, tx)?;
+                let mut tx = old_tx.clone();
+                tx.transition(TransactionState::Chargeback, Operation::Chargeback { acc_id: *acc_id, tx_id: *tx_id }, now)?;
+                account.chargeback_with_shortfall(tx.amount(), tx.shortfall(), self.policy.lock_on_chargeback)?;
+                if self.policy.write_off_chargeback_losses {
+                    tx = tx.with_written_off(account.write_off());
+                }
+                Ok(ValidatedOperation { account, tx })
+            }
+        }
+    }
+}
+
+fn check_positive(amount: Decimal4) -> Result<(), EngineError> {
+    if !amount.is_positive() {
+        return Err(EngineError::AmountIsNotPositive);
+    }
+    Ok(())
+}
+
+fn check_new_tx(tx: Option<&Transaction>) -> Result<(), EngineError> {
+    match tx {
+        Some(_) => Err(EngineError::TransactionWithTheSameIdAlreadyExists),
+        None => Ok(()),
+    }
+}
+
+/// The account and the transaction a dispute, a resolve, a chargeback, a capture or an expiry refers to, both must exist.
+fn existing_tx<'a>(acc_id: ClientId, account: Option<&Account>, tx: Option<&'a Transaction>) -> Result<(Account, &'a Transaction), EngineError> {
+    let tx = tx.ok_or(EngineError::TransactionNotFound)?;
+    if tx.account_id() != acc_id {
+        return Err(EngineError::TransactionIsBoundToAnotherAccount(tx.account_id()));
+    }
+    let account = account.cloned().ok_or(EngineError::AccountNotFound)?;
+    Ok((account, tx))
+}
+
+#[cfg(test)]
+mod validator_tests {
+    use std::time::Duration;
+
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    #[test]
+    fn check_operation_alone() {
+        let validator = Validator::new(EnginePolicy { max_amount: Some(Decimal4::from(100)), ..EnginePolicy::default() });
+        assert_eq!(validator.check_operation(&Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(100) }), Ok(()));
+        assert_eq!(validator.check_operation(&Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(101) }), Err(EngineError::AmountLimitExceeded));
+        assert_eq!(validator.check_operation(&Operation::Withdraw { acc_id: 1, tx_id: 1, amount: Decimal::ZERO }), Err(EngineError::AmountIsNotPositive));
+        assert_eq!(validator.check_operation(&Operation::Dispute { acc_id: ClientId::MAX, tx_id: 1 }), Ok(()));
+    }
+
+    #[test]
+    fn validate_against_snapshot() {
+        let validator = Validator::new(EnginePolicy { withdrawal_fee: Some(Decimal4::from(1)), ..EnginePolicy::default() });
+        let deposit = Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) };
+        let validated = validator.validate(&deposit, None, None, 100).unwrap();
+        assert_eq!((validated.account.available(), validated.tx.created_at()), (Decimal4::from(10), 100));
+        assert_eq!(validator.validate(&deposit, None, Some(&validated.tx), 100), Err(EngineError::TransactionWithTheSameIdAlreadyExists));
+
+        let account = validated.account;
+        let withdrawal = |amount| Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(amount) };
+        assert_eq!(validator.validate(&withdrawal(9), Some(&account), None, 200).unwrap().account.available(), Decimal4::zero());
+        assert_eq!(validator.validate(&withdrawal(10), Some(&account), None, 200), Err(EngineError::InsufficientFunds));
+        assert_eq!(validator.validate(&withdrawal(1), None, None, 200), Err(EngineError::AccountNotFound));
+
+        let dispute = Operation::Dispute { acc_id: 2, tx_id: 1 };
+        assert_eq!(validator.validate(&dispute, Some(&account), Some(&validated.tx), 200), Err(EngineError::TransactionIsBoundToAnotherAccount(1)));
+        let dispute = Operation::Dispute { acc_id: 1, tx_id: 1 };
+        let disputed = validator.validate(&dispute, Some(&account), Some(&validated.tx), 200).unwrap();
+        assert_eq!((disputed.account.held(), disputed.tx.state()), (Decimal4::from(10), TransactionState::Disputed));
+        assert_eq!(validator.validate(&dispute, Some(&disputed.account), Some(&disputed.tx), 300).err().map(|x| x.code()), Some(109));
+    }
+
+    #[test]
+    fn validate_authorization() {
+        let validator = Validator::new(EnginePolicy { authorization_expiry: Some(Duration::from_millis(50)), ..EnginePolicy::default() });
+        let deposited = validator.validate(&Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) }, None, None, 100).unwrap();
+        assert_eq!(validator.validate(&Operation::Authorize { acc_id: 1, tx_id: 2, amount: Decimal::from(11) }, Some(&deposited.account), None, 100), Err(EngineError::InsufficientFunds));
+        let authorized = validator.validate(&Operation::Authorize { acc_id: 1, tx_id: 2, amount: Decimal::from(6) }, Some(&deposited.account), None, 100).unwrap();
+        assert_eq!((authorized.account.available(), authorized.account.held(), authorized.tx.expires_at()), (Decimal4::from(4), Decimal4::from(6), Some(150)));
+
+        let capture = Operation::Capture { acc_id: 1, tx_id: 2 };
+        let captured = validator.validate(&capture, Some(&authorized.account), Some(&authorized.tx), 149).unwrap();
+        assert_eq!((captured.account.total().unwrap(), captured.tx.state()), (Decimal4::from(4), TransactionState::Captured));
+        assert_eq!(validator.validate(&capture, Some(&authorized.account), Some(&authorized.tx), 150), Err(EngineError::AuthorizationExpired));
+        assert_eq!(validator.validate(&capture, Some(&deposited.account), Some(&deposited.tx), 100), Err(EngineError::InvalidTxType));
+
+        let expire = Operation::Expire { acc_id: 1, tx_id: 2 };
+        assert_eq!(validator.validate(&expire, Some(&authorized.account), Some(&authorized.tx), 149), Err(EngineError::AuthorizationNotExpired));
+        let expired = validator.validate(&expire, Some(&authorized.account), Some(&authorized.tx), 150).unwrap();
+        assert_eq!((expired.account.available(), expired.account.held(), expired.tx.state()), (Decimal4::from(10), Decimal4::zero(), TransactionState::Expired));
+    }
+
+    #[test]
+    fn validate_with_policy() {
+        let policy = EnginePolicy { dispute_window: Some(Duration::from_millis(50)), negative_available: NegativeAvailablePolicy::Clamp, ..EnginePolicy::default() };
+        let validator = Validator::new(policy);
+        let deposited = validator.validate(&Operation::Deposit { acc_id: 1, tx_id: 1, amount: Decimal::from(10) }, None, None, 100).unwrap();
+        let spent = validator.validate(&Operation::Withdraw { acc_id: 1, tx_id: 2, amount: Decimal::from(6) }, Some(&deposited.account), None, 110).unwrap();
+
+        let dispute = Operation::Dispute { acc_id: 1, tx_id: 1 };
+        let disputed = validator.validate(&dispute, Some(&spent.account), Some(&deposited.tx), 150).unwrap();
+        assert_eq!((disputed.account.available(), disputed.account.held(), disputed.tx.shortfall()), (Decimal4::zero(), Decimal4::from(4), Decimal4::from(6)));
+        assert_eq!(validator.validate(&dispute, Some(&spent.account), Some(&deposited.tx), 151), Err(EngineError::DisputeWindowExpired));
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+
+use crate::csv_parser::{CsvReader, IngestStats};
+use crate::engine::Engine;
+use crate::journal::Journal;
+use crate::storage::Storage;
+
+/// Input file extensions picked up by the watcher (compressed files are detected by the contents).
+const EXTENSIONS: [&str; 3] = [".csv", ".csv.gz", ".csv.zst"];
+
+/// A file taken from the watched directory, with its new location in the done or failed folder.
+#[derive(Debug)]
+pub struct WatchedFile {
+    pub path: PathBuf,
+    pub result: anyhow::Result<IngestStats>,
+}
+
+/// Continuous ingest of a directory: every new CSV file is read into the engine (which keeps its state across the files)
+/// and then moved to the done folder, or to the failed folder when it can not be read or a row fails in the strict mode.
+///
+/// A file is only picked up once it was not modified for `min_age`, so files still being written are left alone.
+/// The files are processed in file name order, a file with the same name already in the done/failed folder is replaced.
+#[derive(Debug, Clone)]
+pub struct DirectoryWatcher {
+    dir: PathBuf,
+    done_dir: PathBuf,
+    failed_dir: PathBuf,
+    poll_interval: Duration,
+    min_age: Duration,
+    reader: CsvReader,
+}
+
+impl DirectoryWatcher {
+    /// Watches `dir`, moving the processed files to its `done` and `failed` subfolders.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        Self {
+            done_dir: dir.join("done"),
+            failed_dir: dir.join("failed"),
+            dir,
+            poll_interval: Duration::from_secs(1),
+            min_age: Duration::from_secs(1),
+            reader: CsvReader::new(),
+        }
+    }
+
+    pub fn with_done_dir(mut self, done_dir: impl Into<PathBuf>) -> Self {
+        self.done_dir = done_dir.into();
+        self
+    }
+
+    pub fn with_failed_dir(mut self, failed_dir: impl Into<PathBuf>) -> Self {
+        self.failed_dir = failed_dir.into();
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn with_min_age(mut self, min_age: Duration) -> Self {
+        self.min_age = min_age;
+        self
+    }
+
+    /// The reader used for every file, e.g. to enable the strict mode.
+    pub fn with_reader(mut self, reader: CsvReader) -> Self {
+        self.reader = reader;
+        self
+    }
+
+    /// Polls the directory every `poll_interval` until `shutdown` completes, `on_file` is called after every processed file.
+    /// The files of the poll in progress are finished before returning. Only a failure of the directory itself
+    /// (e.g. it was removed) stops the loop early.
+    pub async fn run<TStorage: Storage + Journal>(
+        &self,
+        engine: &mut Engine<TStorage>,
+        mut on_file: impl FnMut(&WatchedFile),
+        shutdown: impl std::future::Future<Output = ()>,
+    ) -> anyhow::Result<()> {
+        let mut ticker = tokio::time::interval(self.poll_interval);
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                biased;
+                _ = &mut shutdown => return Ok(()),
+                _ = ticker.tick() => {}
+            }
+            for file in self.poll(engine).await? {
+                on_file(&file);
+            }
+        }
+    }
+
+    /// Processes the files currently ready in the directory and returns them.
+    pub async fn poll<TStorage: Storage + Journal>(&self, engine: &mut Engine<TStorage>) -> anyhow::Result<Vec<WatchedFile>> {
+        let mut processed = Vec::new();
+        for path in self.ready_files()? {
+            let result = self.reader.read(&path, engine).await;
+            let target_dir = if result.is_ok() { &self.done_dir } else { &self.failed_dir };
+            fs::create_dir_all(target_dir).with_context(|| format!("error creating {}", target_dir.display()))?;
+            let target = target_dir.join(path.file_name().expect("listed files have a name"));
+            fs::rename(&path, &target).with_context(|| format!("error moving {} to {}", path.display(), target_dir.display()))?;
+            processed.push(WatchedFile { path: target, result });
+        }
+        Ok(processed)
+    }
+
+    fn ready_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let now = SystemTime::now();
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir).with_context(|| format!("error reading directory {}", self.dir.display()))? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let age = now.duration_since(metadata.modified()?).unwrap_or_default(); // NOTE: a clock skew counts as just modified
+            if metadata.is_file() && is_input(&entry.path()) && age >= self.min_age {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+fn is_input(path: &Path) -> bool {
+    let name = path.file_name().and_then(|x| x.to_str()).unwrap_or_default();
+    !name.starts_with('.') && EXTENSIONS.iter().any(|x| name.ends_with(x))
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use crate::decimal::Decimal4;
+    use crate::storage::EchoDbStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn poll_moves_processed_files() {
+        let dir = std::env::temp_dir().join(format!("transactions_engine_watch_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1.csv"), "type, client, tx, amount\ndeposit, 1, 1, 10\n").unwrap();
+        fs::write(dir.join("2.csv"), "type, client, tx, amount\ndispute, 1, 9,\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not an input").unwrap();
+
+        let watcher = DirectoryWatcher::new(&dir)
+            .with_min_age(Duration::ZERO)
+            .with_reader(CsvReader::new().with_strict(true));
+        let mut engine = Engine::new(EchoDbStorage::new());
+        let files = watcher.poll(&mut engine).await.unwrap();
+        assert_eq!(files.iter().map(|x| (x.path.clone(), x.result.is_ok())).collect::<Vec<_>>(), vec![
+            (dir.join("done").join("1.csv"), true),
+            (dir.join("failed").join("2.csv"), false),
+        ]);
+        assert!(dir.join("notes.txt").exists());
+        assert!(watcher.poll(&mut engine).await.unwrap().is_empty());
+
+        fs::write(dir.join("3.csv"), "type, client, tx, amount\ndeposit, 1, 2, 5\n").unwrap();
+        assert_eq!(watcher.poll(&mut engine).await.unwrap().len(), 1);
+        assert_eq!(engine.get_account(1).await.unwrap().unwrap().available(), Decimal4::from(15));
+
+        // NOTE: a shutdown requested before the first poll leaves the new files for the next run
+        fs::write(dir.join("4.csv"), "type, client, tx, amount
+deposit, 1, 3, 5
+").unwrap();
+        watcher.run(&mut engine, |_| panic!("no file is processed after the shutdown"), std::future::ready(())).await.unwrap();
+        assert!(dir.join("4.csv").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
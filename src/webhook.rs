@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+
+use crate::account::{Account, ClientId};
+use crate::decimal::Decimal4;
+use crate::observer::EngineObserver;
+use crate::transaction::{Transaction, TxId};
+
+/// The header that carries the HMAC-SHA256 signature of the payload, e.g. `sha256=5d41...`.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+const DEFAULT_TEMPLATE: &str = r#"{"event":"{event}","client":{client},"tx":{tx},"amount":{amount}}"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    AccountLocked,
+    Chargeback,
+    LargeDeposit,
+}
+
+impl WebhookEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventKind::AccountLocked => "account_locked",
+            WebhookEventKind::Chargeback => "chargeback",
+            WebhookEventKind::LargeDeposit => "large_deposit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookEvent {
+    pub kind: WebhookEventKind,
+    pub acc_id: ClientId,
+    pub tx_id: Option<TxId>,
+    pub amount: Option<Decimal4>,
+}
+
+/// A single destination for webhook events.
+///
+/// The payload template can reference `{event}`, `{client}`, `{tx}` and `{amount}` placeholders,
+/// missing values are rendered as `null`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookEndpoint {
+    url: String,
+    template: String,
+    secret: Option<String>,
+}
+
+impl WebhookEndpoint {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            template: DEFAULT_TEMPLATE.to_string(),
+            secret: None,
+        }
+    }
+
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    pub fn with_secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn render(&self, event: &WebhookEvent) -> String {
+        let tx = event.tx_id.map(|x| x.to_string()).unwrap_or("null".to_string());
+        let amount = event.amount.map(|x| format!("\"{}\"", x)).unwrap_or("null".to_string());
+        self.template
+            .replace("{event}", event.kind.as_str())
+            .replace("{client}", &event.acc_id.to_string())
+            .replace("{tx}", &tx)
+            .replace("{amount}", &amount)
+    }
+
+    /// Returns the value of the [`SIGNATURE_HEADER`] for the payload, or `None` if the endpoint has no secret.
+    pub fn sign(&self, payload: &str) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+        mac.update(payload.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        Some(format!("sha256={}", hex))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub body: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// Webhook endpoints registered per event type.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    endpoints: HashMap<WebhookEventKind, Vec<WebhookEndpoint>>,
+    large_deposit_threshold: Option<Decimal4>,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    queue_capacity: usize,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: HashMap::new(),
+            large_deposit_threshold: None,
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            queue_capacity: 10_000,
+        }
+    }
+}
+
+impl WebhookConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every failed delivery is retried up to `max_attempts` in total, the backoff doubles after every attempt.
+    pub fn with_retry_policy(mut self, max_attempts: u32, initial_backoff: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// At most `capacity` requests wait for the dispatcher, the ones over it are dropped, see [`WebhookObserver`].
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self
+    }
+
+    pub fn register(mut self, kind: WebhookEventKind, endpoint: WebhookEndpoint) -> Self {
+        self.endpoints.entry(kind).or_default().push(endpoint);
+        self
+    }
+
+    pub fn with_large_deposit_threshold(mut self, threshold: Decimal4) -> Self {
+        self.large_deposit_threshold = Some(threshold);
+        self
+    }
+
+    pub fn endpoints_for(&self, kind: WebhookEventKind) -> &[WebhookEndpoint] {
+        self.endpoints.get(&kind).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn is_large_deposit(&self, amount: Decimal4) -> bool {
+        self.large_deposit_threshold.is_some_and(|threshold| amount >= threshold)
+    }
+
+    /// Builds the signed HTTP requests for every endpoint registered for the event type.
+    pub fn requests_for(&self, event: &WebhookEvent) -> Vec<WebhookRequest> {
+        self.endpoints_for(event.kind).iter().map(|endpoint| {
+            let body = endpoint.render(event);
+            let mut headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+            if let Some(signature) = endpoint.sign(&body) {
+                headers.push((SIGNATURE_HEADER.to_string(), signature));
+            }
+            WebhookRequest { url: endpoint.url().to_string(), body, headers }
+        }).collect()
+    }
+}
+
+#[trait_variant::make(Send)]
+pub trait WebhookTransport {
+    async fn send(&self, request: &WebhookRequest) -> Result<(), String>;
+}
+
+/// Sends webhook requests as HTTP POST, any non-2xx status is treated as a failure.
+#[derive(Debug, Clone, Default)]
+pub struct HttpTransport {
+    client: reqwest::Client,
+}
+
+impl HttpTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl WebhookTransport for HttpTransport {
+    async fn send(&self, request: &WebhookRequest) -> Result<(), String> {
+        let mut builder = self.client.post(&request.url).body(request.body.clone());
+        for (name, value) in request.headers.iter() {
+            builder = builder.header(name, value);
+        }
+        let response = builder.send().await.map_err(|e| e.to_string())?;
+        response.error_for_status().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// Creates a connected observer/dispatcher pair.
+/// Register the observer in the engine and run the dispatcher in a background task: `tokio::spawn(dispatcher.run())`.
+pub fn webhooks<T: WebhookTransport>(config: WebhookConfig, transport: T) -> (WebhookObserver, WebhookDispatcher<T>) {
+    let (sender, receiver) = mpsc::channel(config.queue_capacity);
+    let observer = WebhookObserver { config: config.clone(), sender, dropped: AtomicU64::new(0) };
+    let dispatcher = WebhookDispatcher { config, transport, receiver };
+    (observer, dispatcher)
+}
+
+/// Turns engine events into signed webhook requests and queues them for the [`WebhookDispatcher`].
+/// Never blocks the engine: the queue is bounded by [`WebhookConfig::with_queue_capacity`], and when the dispatcher
+/// falls behind the new requests are dropped with a warning and counted in [`WebhookObserver::dropped`].
+#[derive(Debug)]
+pub struct WebhookObserver {
+    config: WebhookConfig,
+    sender: mpsc::Sender<WebhookRequest>,
+    dropped: AtomicU64,
+}
+
+impl WebhookObserver {
+    /// The number of requests dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn enqueue(&self, event: WebhookEvent) {
+        for request in self.config.requests_for(&event) {
+            match self.sender.try_send(request) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(request)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!(url = %request.url, event = event.kind.as_str(), "the webhook queue is full, dropping the request");
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {} // NOTE: the dispatcher is gone, nothing to deliver to
+            }
+        }
+    }
+}
+
+impl EngineObserver for WebhookObserver {
+    fn on_deposit_applied(&self, account: &Account, transaction: &Transaction) {
+        if self.config.is_large_deposit(transaction.amount()) {
+            self.enqueue(WebhookEvent { kind: WebhookEventKind::LargeDeposit, acc_id: account.id(), tx_id: Some(transaction.id()), amount: Some(transaction.amount()) });
+        }
+    }
+
+    fn on_chargeback_applied(&self, account: &Account, transaction: &Transaction) {
+        self.enqueue(WebhookEvent { kind: WebhookEventKind::Chargeback, acc_id: account.id(), tx_id: Some(transaction.id()), amount: Some(transaction.amount()) });
+    }
+
+    fn on_account_locked(&self, account: &Account) {
+        self.enqueue(WebhookEvent { kind: WebhookEventKind::AccountLocked, acc_id: account.id(), tx_id: None, amount: None });
+    }
+}
+
+/// Delivers queued webhook requests with retries and exponential backoff.
+pub struct WebhookDispatcher<T: WebhookTransport> {
+    config: WebhookConfig,
+    transport: T,
+    receiver: mpsc::Receiver<WebhookRequest>,
+}
+
+impl<T: WebhookTransport> WebhookDispatcher<T> {
+    /// Delivers requests until the observer is dropped and the queue is drained.
+    /// Returns the number of requests that could not be delivered after all attempts.
+    pub async fn run(mut self) -> u64 {
+        let mut failed = 0;
+        while let Some(request) = self.receiver.recv().await {
+            if self.deliver(&request).await.is_err() {
+                failed += 1;
+            }
+        }
+        failed
+    }
+
+    async fn deliver(&self, request: &WebhookRequest) -> Result<(), String> {
+        let mut backoff = self.config.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match self.transport.send(request).await {
+                Ok(()) => return Ok(()),
+                Err(err) if attempt >= self.config.max_attempts => return Err(err),
+                Err(_) => {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod webhook_tests {
+    use super::*;
+
+    fn lock_event() -> WebhookEvent {
+        WebhookEvent { kind: WebhookEventKind::AccountLocked, acc_id: 1, tx_id: Some(2), amount: None }
+    }
+
+    #[test]
+    fn render_default_template() {
+        let endpoint = WebhookEndpoint::new("http://localhost/hook");
+        assert_eq!(endpoint.render(&lock_event()), r#"{"event":"account_locked","client":1,"tx":2,"amount":null}"#);
+    }
+
+    #[test]
+    fn render_custom_template() {
+        let endpoint = WebhookEndpoint::new("http://localhost/hook").with_template("{event}/{client}/{amount}");
+        let event = WebhookEvent { kind: WebhookEventKind::LargeDeposit, acc_id: 3, tx_id: None, amount: Some(Decimal4::from(5)) };
+        assert_eq!(endpoint.render(&event), "large_deposit/3/\"5.0000\"");
+    }
+
+    #[test]
+    fn sign_with_secret() {
+        let endpoint = WebhookEndpoint::new("http://localhost/hook").with_secret("key");
+        let signature = endpoint.sign("The quick brown fox jumps over the lazy dog");
+        assert_eq!(signature, Some("sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8".to_string()));
+    }
+
+    #[test]
+    fn sign_without_secret() {
+        let endpoint = WebhookEndpoint::new("http://localhost/hook");
+        assert_eq!(endpoint.sign("payload"), None);
+    }
+
+    #[test]
+    fn requests_only_for_registered_event_type() {
+        let config = WebhookConfig::new()
+            .register(WebhookEventKind::AccountLocked, WebhookEndpoint::new("http://a").with_secret("a"))
+            .register(WebhookEventKind::AccountLocked, WebhookEndpoint::new("http://b"))
+            .register(WebhookEventKind::Chargeback, WebhookEndpoint::new("http://c"));
+        let requests = config.requests_for(&lock_event());
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url, "http://a");
+        assert!(requests[0].headers.iter().any(|(name, _)| name == SIGNATURE_HEADER));
+        assert_eq!(requests[1].url, "http://b");
+        assert!(!requests[1].headers.iter().any(|(name, _)| name == SIGNATURE_HEADER));
+    }
+
+    #[derive(Default)]
+    struct FlakyTransport {
+        failures_left: std::sync::Mutex<u32>,
+        delivered: std::sync::Mutex<Vec<WebhookRequest>>,
+    }
+
+    impl WebhookTransport for &FlakyTransport {
+        async fn send(&self, request: &WebhookRequest) -> Result<(), String> {
+            let mut failures_left = self.failures_left.lock().unwrap();
+            if *failures_left > 0 {
+                *failures_left -= 1;
+                return Err("connection refused".to_string());
+            }
+            self.delivered.lock().unwrap().push(request.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatcher_delivers_engine_events_with_retries() {
+        let config = WebhookConfig::new()
+            .with_retry_policy(3, Duration::from_millis(1))
+            .with_large_deposit_threshold(Decimal4::from(1000))
+            .register(WebhookEventKind::AccountLocked, WebhookEndpoint::new("http://lock"))
+            .register(WebhookEventKind::Chargeback, WebhookEndpoint::new("http://chargeback"))
+            .register(WebhookEventKind::LargeDeposit, WebhookEndpoint::new("http://large"));
+        let transport = FlakyTransport { failures_left: std::sync::Mutex::new(2), ..Default::default() };
+        let (observer, dispatcher) = webhooks(config, &transport);
+
+        let engine = crate::engine::Engine::default().with_observer(std::sync::Arc::new(observer));
+        assert_eq!(engine.deposit(1, 1, Decimal4::from(100)).await, Ok(()));
+        assert_eq!(engine.deposit(1, 2, Decimal4::from(1000)).await, Ok(()));
+        assert_eq!(engine.dispute(1, 1).await, Ok(()));
+        assert_eq!(engine.chargeback(1, 1).await, Ok(()));
+        drop(engine);
+
+        assert_eq!(dispatcher.run().await, 0);
+        let urls: Vec<_> = transport.delivered.lock().unwrap().iter().map(|x| x.url.clone()).collect();
+        assert_eq!(urls, vec!["http://large", "http://chargeback", "http://lock"]);
+    }
+
+    #[tokio::test]
+    async fn dispatcher_gives_up_after_max_attempts() {
+        let config = WebhookConfig::new()
+            .with_retry_policy(2, Duration::from_millis(1))
+            .register(WebhookEventKind::AccountLocked, WebhookEndpoint::new("http://lock"));
+        let transport = FlakyTransport { failures_left: std::sync::Mutex::new(2), ..Default::default() };
+        let (observer, dispatcher) = webhooks(config, &transport);
+        observer.on_account_locked(&Account::new(1));
+        observer.on_account_locked(&Account::new(2));
+        drop(observer);
+        assert_eq!(dispatcher.run().await, 1);
+        assert_eq!(transport.delivered.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn observer_drops_requests_when_queue_is_full() {
+        let config = WebhookConfig::new()
+            .with_queue_capacity(1)
+            .register(WebhookEventKind::AccountLocked, WebhookEndpoint::new("http://lock"));
+        let transport = FlakyTransport::default();
+        let (observer, dispatcher) = webhooks(config, &transport);
+        observer.on_account_locked(&Account::new(1));
+        observer.on_account_locked(&Account::new(2));
+        assert_eq!(observer.dropped(), 1);
+        drop(observer);
+        assert_eq!(dispatcher.run().await, 0);
+        assert_eq!(transport.delivered.lock().unwrap()[0].body, r#"{"event":"account_locked","client":1,"tx":null,"amount":null}"#);
+    }
+
+    #[test]
+    fn large_deposit_threshold() {
+        let config = WebhookConfig::new().with_large_deposit_threshold(Decimal4::from(1000));
+        assert!(config.is_large_deposit(Decimal4::from(1000)));
+        assert!(!config.is_large_deposit(Decimal4::from(999)));
+        assert!(!WebhookConfig::new().is_large_deposit(Decimal4::from(1000)));
+    }
+}
@@ -32,3 +32,22 @@ Feature: Dispute
     Then the last operation should succeed
     And the user's available balance should be $0
     And the user's held balance should be $100
+
+  Scenario: Dispute a deposit within the dispute window
+    Given the dispute window is 30 days
+    And A user has an empty account
+    When the user deposits $100
+    And 30 days pass
+    And the user disputes the last transaction
+    Then the last operation should succeed
+    And the user's held balance should be $100
+
+  Scenario: Can not dispute a deposit after the dispute window
+    Given the dispute window is 30 days
+    And A user has an empty account
+    When the user deposits $100
+    And 31 days pass
+    And the user disputes the last transaction
+    Then the last operation should fail
+    And the user's available balance should be $100
+    And the user's held balance should be $0
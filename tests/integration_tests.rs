@@ -1,27 +1,35 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use cucumber::{given, then, when, World};
 use cucumber::gherkin::Step;
-use transactions_engine::account::Account;
+use transactions_engine::account::{Account, ClientId};
+use transactions_engine::clock::ManualClock;
 use transactions_engine::csv_parser::CsvOperation;
 use transactions_engine::decimal::Decimal4;
-use transactions_engine::engine::{Engine, EngineError};
+use transactions_engine::engine::{Engine, EngineError, EnginePolicy};
 use transactions_engine::storage::EchoDbStorage;
+use transactions_engine::transaction::TxId;
 
 #[derive(cucumber::World, Debug)]
 #[world(init = Self::new)]
 struct TransactionsEngineWorld {
     engine: Engine<EchoDbStorage>,
-    tx_counter: u32,
+    clock: ManualClock,
+    tx_counter: TxId,
     given_acc: Account,
     last_result: Result<(), EngineError>,
-    last_deposit_tx: Option<u32>,
-    last_disputed_tx: Option<u32>,
+    last_deposit_tx: Option<TxId>,
+    last_disputed_tx: Option<TxId>,
     csv_operations: Vec<CsvOperation>,
 }
 
 impl TransactionsEngineWorld {
     fn new() -> Self {
+        let clock = ManualClock::new(1_700_000_000_000);
         Self {
-            engine: Engine::default(),
+            engine: Engine::default().with_clock(Arc::new(clock.clone())),
+            clock,
             tx_counter: 0,
             given_acc: Account::default(),
             last_result: Ok(()),
@@ -55,6 +63,13 @@ async fn given_locked_acc_with_amount(world: &mut TransactionsEngineWorld, amoun
     Ok(())
 }
 
+#[given(expr = "the dispute window is {int} days")]
+async fn given_dispute_window(world: &mut TransactionsEngineWorld, days: u64) -> anyhow::Result<()> {
+    let policy = EnginePolicy { dispute_window: Some(Duration::from_secs(days * 24 * 60 * 60)), ..*world.engine.policy() };
+    world.engine = world.engine.clone().with_policy(policy);
+    Ok(())
+}
+
 #[given("the CSV file with the following content:")]
 async fn given_csv_file(world: &mut TransactionsEngineWorld, step: &Step) -> anyhow::Result<()> {
     let csv_content = step.docstring().unwrap();
@@ -109,6 +124,12 @@ async fn last_disputed_tx_is_charged_back(world: &mut TransactionsEngineWorld) -
     Ok(())
 }
 
+#[when(expr = "{int} days pass")]
+async fn days_pass(world: &mut TransactionsEngineWorld, days: u64) -> anyhow::Result<()> {
+    world.clock.advance(Duration::from_secs(days * 24 * 60 * 60));
+    Ok(())
+}
+
 #[when("the CSV operations are performed")]
 async fn csv_operations_are_performed(world: &mut TransactionsEngineWorld) -> anyhow::Result<()> {
     for csv_op in world.csv_operations.iter() {
@@ -136,7 +157,7 @@ async fn user_held_balance_is(world: &mut TransactionsEngineWorld, amount: f32)
 #[then(expr = "the user's total balance should be ${float}")]
 async fn user_total_balance_is(world: &mut TransactionsEngineWorld, amount: f32) -> anyhow::Result<()> {
     let acc = world.engine.get_account(1).await?.ok_or(anyhow::anyhow!("Account not found"))?;
-    assert_eq!(acc.total(), amount.try_into()?);
+    assert_eq!(acc.total()?, amount.try_into()?);
     Ok(())
 }
 
@@ -145,7 +166,7 @@ async fn user_balance_is(world: &mut TransactionsEngineWorld, amount: f32) -> an
     let acc = world.engine.get_account(1).await?.ok_or(anyhow::anyhow!("Account not found"))?;
     let amount: Decimal4 = amount.try_into()?;
     assert_eq!(acc.available(), amount);
-    assert_eq!(acc.total(), amount);
+    assert_eq!(acc.total()?, amount);
     Ok(())
 }
 
@@ -192,7 +213,7 @@ async fn accounts_should_be(world: &mut TransactionsEngineWorld, step: &Step) ->
 
     let table = step.table.as_ref().unwrap();
     for row in table.rows.iter().skip(1) { // NOTE: skip header
-        let id: u16 = row[0].parse()?;
+        let id: ClientId = row[0].parse()?;
         let available: Decimal4 = row[1].parse()?;
         let held: Decimal4 = row[2].parse()?;
         let total: Decimal4 = row[3].parse()?;
@@ -200,7 +221,7 @@ async fn accounts_should_be(world: &mut TransactionsEngineWorld, step: &Step) ->
         let acc = world.engine.get_account(id).await?.ok_or(anyhow::anyhow!("Account not found"))?;
         assert_eq!(acc.available(), available);
         assert_eq!(acc.held(), held);
-        assert_eq!(acc.total(), total);
+        assert_eq!(acc.total()?, total);
         assert_eq!(acc.locked(), locked);
     }
 